@@ -69,6 +69,17 @@ pub const L1_ENTRY_SIZE: usize = 512 * L2_ENTRY_SIZE;
 
 pub const L0_ENTRY_SIZE: usize = 512 * L1_ENTRY_SIZE;
 
+// With 5-level paging (LA57) there is an additional PML5 above the PML4, which extends virtual
+// addresses to 57 bits.
+pub const X86_LA57_NUM_LAYERS: usize = 5;
+
+pub const PML5_ENTRY_SIZE: usize = 512 * L0_ENTRY_SIZE;
+
+pub spec const X86_LA57_MAX_ENTRY_SIZE: nat = 512 * 512 * 512 * 512 * 4096;
+
+/// Upper bound of the virtual address space with 5-level paging
+pub spec const MAX_LA57_BASE: nat = X86_LA57_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES as nat);
+
 pub open spec fn index_from_offset(offset: nat, entry_size: nat) -> (res: nat)
     recommends
         entry_size > 0,
@@ -96,6 +107,23 @@ pub open spec fn candidate_mapping_in_bounds(base: nat, pte: PageTableEntry) ->
     base + pte.frame.size < x86_arch_spec.upper_vaddr(0, 0)
 }
 
+/// The size of the virtual memory covered when mapping `ptes` back to back
+pub open spec fn range_size(ptes: Seq<PageTableEntry>) -> nat
+    decreases ptes.len(),
+{
+    if ptes.len() == 0 {
+        0
+    } else {
+        range_size(ptes.drop_last()) + ptes.last().frame.size
+    }
+}
+
+/// The virtual address at which the `i`-th entry of `ptes` is mapped when mapping `ptes` back to
+/// back starting at `base`
+pub open spec fn range_entry_base(base: nat, ptes: Seq<PageTableEntry>, i: int) -> nat {
+    base + range_size(ptes.take(i))
+}
+
 pub open spec fn candidate_mapping_overlaps_existing_vmem(
     mappings: Map<nat, PageTableEntry>,
     base: nat,
@@ -222,10 +250,22 @@ impl MemRegionExec {
     }
 }
 
+/// Memory type of a frame mapping. The encoding in the page table entries assumes the PAT MSR has
+/// its power-on default value, where the PAT entries 4-7 repeat entries 0-3. That way the memory
+/// type is determined by the PCD and PWT bits alone and we always leave the PAT bit unset.
+pub enum MemoryType {
+    WriteBack,
+    WriteThrough,
+    UncacheableMinus,
+    Uncacheable,
+}
+
 pub struct Flags {
     pub is_writable: bool,
     pub is_supervisor: bool,
     pub disable_execute: bool,
+    pub memory_type: MemoryType,
+    pub is_global: bool,
 }
 
 pub struct PageTableEntry {
@@ -286,12 +326,12 @@ impl Arch {
     }
 
     pub open spec(checked) fn inv(&self) -> bool {
-        &&& self.layers.len() <= X86_NUM_LAYERS
+        &&& self.layers.len() <= X86_LA57_NUM_LAYERS
         &&& forall|i: nat|
             #![trigger self.entry_size(i)]
             #![trigger self.num_entries(i)]
             i < self.layers.len() ==> {
-                &&& 0 < self.entry_size(i) <= X86_MAX_ENTRY_SIZE
+                &&& 0 < self.entry_size(i) <= X86_LA57_MAX_ENTRY_SIZE
                 &&& 0 < self.num_entries(i) <= X86_NUM_ENTRIES
                 &&& self.entry_size_is_next_layer_size(i)
             }
@@ -423,6 +463,52 @@ pub spec const x86_arch_spec: Arch = Arch {
     ],
 };
 
+/// The architecture with 5-level paging. Layer 0 is the PML5, the other layers are the same as in
+/// `x86_arch_spec`.
+pub spec const x86_la57_arch_spec: Arch = Arch {
+    layers: seq![
+        ArchLayer { entry_size: PML5_ENTRY_SIZE as nat, num_entries: 512 },
+        ArchLayer { entry_size: L0_ENTRY_SIZE as nat, num_entries: 512 },
+        ArchLayer { entry_size: L1_ENTRY_SIZE as nat, num_entries: 512 },
+        ArchLayer { entry_size: L2_ENTRY_SIZE as nat, num_entries: 512 },
+        ArchLayer { entry_size: L3_ENTRY_SIZE as nat, num_entries: 512 },
+    ],
+};
+
+/// The x86 architecture with `num_layers` paging layers, i.e. 4 or 5.
+pub open spec fn x86_arch_spec_with_layers(num_layers: nat) -> Arch {
+    if num_layers == X86_LA57_NUM_LAYERS { x86_la57_arch_spec } else { x86_arch_spec }
+}
+
+pub exec fn x86_arch_exec_with_layers(num_layers: usize) -> (res: ArchExec)
+    requires
+        num_layers == X86_NUM_LAYERS || num_layers == X86_LA57_NUM_LAYERS,
+    ensures
+        res@ === x86_arch_spec_with_layers(num_layers as nat),
+        res.layers@.len() == num_layers,
+{
+    let mut v = Vec::new();
+    if num_layers == X86_LA57_NUM_LAYERS {
+        v.push(ArchLayerExec { entry_size: PML5_ENTRY_SIZE, num_entries: 512 });
+    }
+    v.push(ArchLayerExec { entry_size: L0_ENTRY_SIZE, num_entries: 512 });
+    v.push(ArchLayerExec { entry_size: L1_ENTRY_SIZE, num_entries: 512 });
+    v.push(ArchLayerExec { entry_size: L2_ENTRY_SIZE, num_entries: 512 });
+    v.push(ArchLayerExec { entry_size: L3_ENTRY_SIZE, num_entries: 512 });
+    let res = ArchExec { layers: v };
+    proof {
+        assert(res@.layers =~= x86_arch_spec_with_layers(num_layers as nat).layers);
+    }
+    res
+}
+
+pub proof fn x86_la57_arch_spec_upper_bound()
+    ensures
+        x86_la57_arch_spec.upper_vaddr(0, 0) == 512 * 512 * 512 * 1024 * 1024 * 1024,
+{
+    assert(x86_la57_arch_spec.upper_vaddr(0, 0) == 512 * 512 * 512 * 1024 * 1024 * 1024) by (compute_only);
+}
+
 pub proof fn x86_arch_spec_upper_bound()
     ensures
         x86_arch_spec.upper_vaddr(0, 0) == 512 * 512 * 1024 * 1024 * 1024,
@@ -1,6 +1,7 @@
 use vstd::prelude::*;
 
-use crate::definitions_t::{MAX_PHYADDR, axiom_max_phyaddr_width_facts, aligned, new_seq, Flags, ArchExec, ArchLayerExec};
+use crate::definitions_t::{MAX_PHYADDR, axiom_max_phyaddr_width_facts, aligned, new_seq, Flags, MemoryType, ArchExec, ArchLayerExec,
+MemRegionExec, PageTableEntryExec, PageTableEntry, range_size, range_entry_base};
 
 verus! {
 pub proof fn lemma_maxphyaddr_facts()
@@ -33,11 +34,41 @@ pub exec fn aligned_exec(addr: usize, size: usize) -> (res: bool)
     addr % size == 0
 }
 
+pub exec fn clone_flags(flags: &Flags) -> (res: Flags)
+    ensures
+        res == flags
+{
+    Flags {
+        is_writable: flags.is_writable,
+        is_supervisor: flags.is_supervisor,
+        disable_execute: flags.disable_execute,
+        memory_type: match flags.memory_type {
+            MemoryType::WriteBack        => MemoryType::WriteBack,
+            MemoryType::WriteThrough     => MemoryType::WriteThrough,
+            MemoryType::UncacheableMinus => MemoryType::UncacheableMinus,
+            MemoryType::Uncacheable      => MemoryType::Uncacheable,
+        },
+        is_global: flags.is_global,
+    }
+}
+
+pub exec fn clone_pte(pte: &PageTableEntryExec) -> (res: PageTableEntryExec)
+    ensures
+        res == pte
+{
+    PageTableEntryExec {
+        frame: MemRegionExec { base: pte.frame.base, size: pte.frame.size },
+        flags: clone_flags(&pte.flags),
+    }
+}
+
 /// We always set permissive flags on directories. Restrictions happen on the frame mapping.
 pub spec const permissive_flags: Flags = Flags {
     is_writable:     true,
     is_supervisor:   false,
     disable_execute: false,
+    memory_type:     MemoryType::WriteBack,
+    is_global:       false,
 };
 
 // Sometimes z3 needs these concrete bounds to prove the no-overflow VC
@@ -45,9 +76,15 @@ pub proof fn overflow_bounds()
     ensures
         X86_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1) < 0x10000000000000000,
         MAX_BASE + X86_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1) < 0x10000000000000000,
+        X86_LA57_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1) < 0x10000000000000000,
+        MAX_LA57_BASE + X86_LA57_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1) < 0x10000000000000000,
+        MAX_BASE <= MAX_LA57_BASE,
 {
     assert(X86_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1) < 0x10000000000000000) by (nonlinear_arith);
     assert(MAX_BASE + X86_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1) < 0x10000000000000000) by (nonlinear_arith);
+    assert(X86_LA57_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1) < 0x10000000000000000) by (nonlinear_arith);
+    assert(MAX_LA57_BASE + X86_LA57_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1) < 0x10000000000000000) by (nonlinear_arith);
+    assert(MAX_BASE <= MAX_LA57_BASE) by (nonlinear_arith);
 }
 
 // Architecture
@@ -73,7 +110,8 @@ pub proof fn overflow_bounds()
 // [     #  512 , 512 , 512 , 512 ]
 // [     #  9   , 9   , 9   , 9   , 12  ]
 
-use crate::definitions_t::{Arch, ArchLayer, MAX_BASE, X86_MAX_ENTRY_SIZE, X86_NUM_ENTRIES, x86_arch_spec, X86_NUM_LAYERS};
+use crate::definitions_t::{Arch, ArchLayer, MAX_BASE, X86_MAX_ENTRY_SIZE, X86_NUM_ENTRIES, x86_arch_spec, X86_NUM_LAYERS,
+    MAX_LA57_BASE, X86_LA57_MAX_ENTRY_SIZE, X86_LA57_NUM_LAYERS, x86_la57_arch_spec, x86_arch_spec_with_layers};
 
 impl Clone for ArchLayerExec {
     fn clone(&self) -> Self {
@@ -139,15 +177,15 @@ impl ArchExec {
         requires
             self@.inv(),
             layer < self@.layers.len(),
-            base <= MAX_BASE,
+            base <= MAX_LA57_BASE,
             idx <= X86_NUM_ENTRIES,
         ensures
             res == self@.entry_base(layer as nat, base as nat, idx as nat)
     {
         proof {
             // FIXME: Weird error message when using the spec const here
-            // lib::mult_leq_mono_both(idx as nat, self@.entry_size(layer as nat), X86_NUM_ENTRIES as nat, X86_MAX_ENTRY_SIZE);
-            crate::extra::mult_leq_mono_both(idx as nat, self@.entry_size(layer as nat), X86_NUM_ENTRIES as nat, 512 * 1024 * 1024 * 1024);
+            // lib::mult_leq_mono_both(idx as nat, self@.entry_size(layer as nat), X86_NUM_ENTRIES as nat, X86_LA57_MAX_ENTRY_SIZE);
+            crate::extra::mult_leq_mono_both(idx as nat, self@.entry_size(layer as nat), X86_NUM_ENTRIES as nat, 512 * 512 * 1024 * 1024 * 1024);
         }
         base + idx * self.entry_size(layer)
     }
@@ -156,7 +194,7 @@ impl ArchExec {
         requires
             self@.inv(),
             layer < self@.layers.len(),
-            base <= MAX_BASE,
+            base <= MAX_LA57_BASE,
             idx <= X86_NUM_ENTRIES,
         ensures
             res == self@.next_entry_base(layer as nat, base as nat, idx as nat)
@@ -164,16 +202,16 @@ impl ArchExec {
         proof {
             overflow_bounds();
             let es = self@.entry_size(layer as nat);
-            assert(0 <= (idx + 1) * es <= X86_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1)) by (nonlinear_arith)
-                requires es <= X86_MAX_ENTRY_SIZE, idx <= X86_NUM_ENTRIES
+            assert(0 <= (idx + 1) * es <= X86_LA57_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1)) by (nonlinear_arith)
+                requires es <= X86_LA57_MAX_ENTRY_SIZE, idx <= X86_NUM_ENTRIES
                 { /* New instability with z3 4.10.1 */ };
         }
         let offset = (idx + 1) * self.entry_size(layer);
         proof {
-            assert(base + offset <= MAX_BASE + X86_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1)) by (nonlinear_arith)
+            assert(base + offset <= MAX_LA57_BASE + X86_LA57_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1)) by (nonlinear_arith)
                 requires
-                    0 <= offset <= X86_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1),
-                    0 <= base <= MAX_BASE,
+                    0 <= offset <= X86_LA57_MAX_ENTRY_SIZE * (X86_NUM_ENTRIES + 1),
+                    0 <= base <= MAX_LA57_BASE,
                 {};
         }
         base + offset
@@ -261,7 +299,7 @@ pub proof fn x86_arch_inv()
     assert(x86_arch_spec.contains_entry_size(4096));
     assert(x86_arch_spec.layers.len() <= X86_NUM_LAYERS);
     assert forall|i:nat| i < x86_arch_spec.layers.len() implies {
-            &&& 0 < #[trigger] x86_arch_spec.entry_size(i)  <= X86_MAX_ENTRY_SIZE
+            &&& 0 < #[trigger] x86_arch_spec.entry_size(i)  <= X86_LA57_MAX_ENTRY_SIZE
             &&& 0 < #[trigger] x86_arch_spec.num_entries(i) <= X86_NUM_ENTRIES
             &&& x86_arch_spec.entry_size_is_next_layer_size(i)
         } by {
@@ -272,6 +310,113 @@ pub proof fn x86_arch_inv()
     assert(x86_arch_spec.inv());
 }
 
+#[verifier(nonlinear)]
+pub proof fn x86_la57_arch_inv()
+    ensures
+        x86_la57_arch_spec.inv()
+{
+    assert(x86_la57_arch_spec.entry_size(4) == 4096);
+    assert(x86_la57_arch_spec.layers.len() <= X86_LA57_NUM_LAYERS);
+    assert forall|i:nat| i < x86_la57_arch_spec.layers.len() implies {
+            &&& 0 < #[trigger] x86_la57_arch_spec.entry_size(i)  <= X86_LA57_MAX_ENTRY_SIZE
+            &&& 0 < #[trigger] x86_la57_arch_spec.num_entries(i) <= X86_NUM_ENTRIES
+            &&& x86_la57_arch_spec.entry_size_is_next_layer_size(i)
+        } by {
+        assert(0 < #[trigger] x86_la57_arch_spec.entry_size(i)  <= X86_LA57_MAX_ENTRY_SIZE);
+        assert(0 < #[trigger] x86_la57_arch_spec.num_entries(i) <= X86_NUM_ENTRIES);
+        assert(x86_la57_arch_spec.entry_size_is_next_layer_size(i));
+    }
+    assert(x86_la57_arch_spec.inv());
+}
+
+/// With 5-level paging, layer `layer + 1` has the sizes of layer `layer` of 4-level paging.
+pub proof fn lemma_x86_arch_spec_with_layers(num_layers: nat)
+    requires
+        num_layers == X86_NUM_LAYERS || num_layers == X86_LA57_NUM_LAYERS,
+    ensures
+        x86_arch_spec_with_layers(num_layers).inv(),
+        x86_arch_spec_with_layers(num_layers).layers.len() == num_layers,
+        forall|i: nat| i < num_layers ==>
+            #[trigger] x86_arch_spec_with_layers(num_layers).num_entries(i) == X86_NUM_ENTRIES,
+        forall|i: nat| i < X86_NUM_LAYERS ==>
+            #[trigger] x86_arch_spec_with_layers(num_layers).entry_size((i + num_layers - X86_NUM_LAYERS) as nat)
+                == x86_arch_spec.entry_size(i),
+        x86_arch_spec_with_layers(num_layers).upper_vaddr(0, 0) <= MAX_LA57_BASE,
+{
+    x86_arch_inv();
+    x86_la57_arch_inv();
+    assert(x86_arch_spec.upper_vaddr(0, 0) == MAX_BASE) by (compute_only);
+    assert(x86_la57_arch_spec.upper_vaddr(0, 0) == MAX_LA57_BASE) by (compute_only);
+    overflow_bounds();
+}
 
+pub proof fn lemma_range_size_cons(pte: PageTableEntry, ptes: Seq<PageTableEntry>)
+    ensures
+        range_size(seq![pte] + ptes) == pte.frame.size + range_size(ptes),
+    decreases ptes.len()
+{
+    if ptes.len() == 0 {
+        assert(seq![pte] + ptes =~= seq![pte]);
+        assert(seq![pte].drop_last() =~= seq![]);
+    } else {
+        assert((seq![pte] + ptes).drop_last() =~= seq![pte] + ptes.drop_last());
+        lemma_range_size_cons(pte, ptes.drop_last());
+    }
 }
 
+pub proof fn lemma_range_size_take(ptes: Seq<PageTableEntry>, i: int)
+    requires
+        0 <= i < ptes.len(),
+    ensures
+        range_size(ptes.take(i)) + ptes[i].frame.size <= range_size(ptes),
+    decreases ptes.len()
+{
+    if i == ptes.len() - 1 {
+        assert(ptes.drop_last() =~= ptes.take(i));
+    } else {
+        assert(ptes.drop_last().take(i) =~= ptes.take(i));
+        lemma_range_size_take(ptes.drop_last(), i);
+    }
+}
+
+pub proof fn lemma_range_size_append(a: Seq<PageTableEntry>, b: Seq<PageTableEntry>)
+    ensures
+        range_size(a + b) == range_size(a) + range_size(b),
+    decreases b.len()
+{
+    if b.len() == 0 {
+        assert(a + b =~= a);
+    } else {
+        assert((a + b).drop_last() =~= a + b.drop_last());
+        assert((a + b).last() === b.last());
+        lemma_range_size_append(a, b.drop_last());
+    }
+}
+
+/// The `i`-th entry of a range lies within the range.
+pub proof fn lemma_range_entry_base_bounds(base: nat, ptes: Seq<PageTableEntry>, i: int)
+    requires
+        0 <= i < ptes.len(),
+    ensures
+        base <= range_entry_base(base, ptes, i),
+        range_entry_base(base, ptes, i) + ptes[i].frame.size <= base + range_size(ptes),
+{
+    lemma_range_size_take(ptes, i);
+}
+
+/// Mapping the entries `i..j` of a range starting at the base of the `i`-th entry maps each of
+/// them at the same address as mapping the whole range.
+pub proof fn lemma_range_entry_base_subrange(base: nat, ptes: Seq<PageTableEntry>, i: int, j: int, k: int)
+    requires
+        0 <= i <= j <= ptes.len(),
+        0 <= k <= j - i,
+    ensures
+        range_entry_base(range_entry_base(base, ptes, i), ptes.subrange(i, j), k) == range_entry_base(base, ptes, i + k),
+        k == j - i ==> range_entry_base(base, ptes, i) + range_size(ptes.subrange(i, j)) == range_entry_base(base, ptes, j),
+{
+    assert(ptes.take(i) + ptes.subrange(i, j).take(k) =~= ptes.take(i + k));
+    assert(ptes.subrange(i, j).take(j - i) =~= ptes.subrange(i, j));
+    lemma_range_size_append(ptes.take(i), ptes.subrange(i, j).take(k));
+}
+
+}
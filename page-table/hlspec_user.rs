@@ -2,7 +2,8 @@ use vstd::prelude::*;
 
 use crate::definitions_t::{
     aligned, axiom_max_phyaddr_width_facts, candidate_mapping_in_bounds, x86_arch_spec_upper_bound,
-    Flags, LoadResult, MemRegion, PageTableEntry, RWOp, StoreResult, MAX_PHYADDR_SPEC, WORD_SIZE,
+    Flags, LoadResult, MemRegion, MemoryType, PageTableEntry, RWOp, StoreResult, MAX_PHYADDR_SPEC,
+    WORD_SIZE,
 };
 use crate::spec_t::hlspec::*;
 
@@ -44,7 +45,13 @@ proof fn program_1() {
 
     let pte1 = PageTableEntry {
         frame: MemRegion { base: 4096, size: 4096 },
-        flags: Flags { is_writable: true, is_supervisor: false, disable_execute: true },
+        flags: Flags {
+            is_writable: true,
+            is_supervisor: false,
+            disable_execute: true,
+            memory_type: MemoryType::WriteBack,
+            is_global: false,
+        },
     };
 
     assert(candidate_mapping_in_bounds(4096 * 3, pte1));
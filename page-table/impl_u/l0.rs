@@ -1,7 +1,7 @@
 use vstd::prelude::*;
 use vstd::map::*;
 use crate::extra;
-use crate::definitions_t::{ MemRegion, overlap, between, Arch, aligned, PageTableEntry };
+use crate::definitions_t::{ MemRegion, overlap, between, Arch, aligned, PageTableEntry, Flags, range_size };
 
 verus! {
 
@@ -192,6 +192,77 @@ impl PageTableContents {
         }
     }
 
+    pub open spec(checked) fn accepted_protect(self, base: nat) -> bool {
+        self.accepted_unmap(base)
+    }
+
+    /// Replaces the flags of the frame mapped at `base`, keeping the frame itself.
+    pub open spec(checked) fn protect(self, base: nat, flags: Flags) -> Result<PageTableContents,PageTableContents>
+        recommends self.accepted_protect(base)
+    {
+        if self.map.dom().contains(base) {
+            Ok(PageTableContents {
+                map: self.map.insert(base, PageTableEntry { frame: self.map.index(base).frame, flags }),
+                ..self
+            })
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Maps `ptes` back to back starting at `base`. Mapping stops at the first entry that can't be
+    /// mapped, in which case the entries before it remain mapped.
+    pub open spec fn map_range(self, base: nat, ptes: Seq<PageTableEntry>) -> Result<PageTableContents,PageTableContents>
+        decreases ptes.len()
+    {
+        if ptes.len() == 0 {
+            Ok(self)
+        } else {
+            match self.map_range(base, ptes.drop_last()) {
+                Ok(pt)  => pt.map_frame(base + range_size(ptes.drop_last()), ptes.last()),
+                Err(pt) => Err(pt),
+            }
+        }
+    }
+
+    /// Once an entry fails to map, `map_range` returns the same error for every longer prefix.
+    pub proof fn lemma_map_range_err_propagates(self, base: nat, ptes: Seq<PageTableEntry>, i: nat)
+        requires
+            i <= ptes.len(),
+            self.map_range(base, ptes.take(i as int)).is_Err(),
+        ensures
+            self.map_range(base, ptes) === self.map_range(base, ptes.take(i as int)),
+        decreases ptes.len()
+    {
+        if i < ptes.len() {
+            assert(ptes.drop_last().take(i as int) =~= ptes.take(i as int));
+            self.lemma_map_range_err_propagates(base, ptes.drop_last(), i);
+        } else {
+            assert(ptes.take(i as int) =~= ptes);
+        }
+    }
+
+    /// The mapping at `b` overlaps the range `[base, base + len)` without being contained in it.
+    pub open spec fn straddles_range(self, b: nat, base: nat, len: nat) -> bool {
+        let size = self.map.index(b).frame.size;
+        &&& b < base + len
+        &&& base < b + size
+        &&& (b < base || base + len < b + size)
+    }
+
+    /// Removes all frames whose base lies in `[base, base + len)`. This is atomic: If any frame
+    /// straddles the boundaries of the range, nothing is removed.
+    pub open spec fn unmap_range(self, base: nat, len: nat) -> Result<PageTableContents,PageTableContents> {
+        if exists|b: nat| self.map.dom().contains(b) && #[trigger] self.straddles_range(b, base, len) {
+            Err(self)
+        } else {
+            Ok(PageTableContents {
+                map: self.map.remove_keys(Set::new(|b: nat| between(b, base, base + len))),
+                ..self
+            })
+        }
+    }
+
     proof fn lemma_unmap_preserves_inv(self, base: nat)
         requires
             self.inv(),
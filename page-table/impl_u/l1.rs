@@ -1,10 +1,10 @@
 use vstd::prelude::*;
 use crate::definitions_t::{new_seq};
-use crate::definitions_u::{lemma_new_seq};
+use crate::definitions_u::{ lemma_new_seq, lemma_range_size_append, lemma_range_size_take, lemma_range_entry_base_bounds };
 use crate::extra::{ self, result_map };
 use crate::impl_u::indexing;
 
-use crate::definitions_t::{ MemRegion, overlap, Arch, between, aligned, PageTableEntry, Flags };
+use crate::definitions_t::{ MemRegion, overlap, Arch, between, aligned, PageTableEntry, Flags, range_size, range_entry_base };
 use crate::definitions_u::{ permissive_flags };
 use crate::impl_u::l0::{ self, ambient_lemmas1 };
 
@@ -926,6 +926,37 @@ impl Directory {
         }
     }
 
+    /// The number of directories `map_frame` adds to the page table, i.e. the number of pages an
+    /// implementation has to allocate for the mapping.
+    pub open spec fn map_frame_num_new_dirs(self, base: nat, pte: PageTableEntry) -> nat
+        decreases self.arch.layers.len() - self.layer
+    {
+        decreases_by(Self::check_map_frame);
+
+        if self.inv() && self.accepted_mapping(base, pte) {
+            let entry = self.index_for_vaddr(base);
+            match self.entries.index(entry as int) {
+                NodeEntry::Page(p) => 0,
+                NodeEntry::Directory(d) => {
+                    if self.entry_size() == pte.frame.size {
+                        0
+                    } else {
+                        d.map_frame_num_new_dirs(base, pte)
+                    }
+                },
+                NodeEntry::Empty() => {
+                    if self.entry_size() == pte.frame.size {
+                        0
+                    } else {
+                        1 + self.new_empty_dir(entry).map_frame_num_new_dirs(base, pte)
+                    }
+                },
+            }
+        } else {
+            arbitrary()
+        }
+    }
+
     pub proof fn lemma_accepted_mapping_implies_directory_accepted_mapping(self, base: nat, pte: PageTableEntry, d: Directory)
         requires
             self.inv(),
@@ -1196,6 +1227,34 @@ impl Directory {
         }
     }
 
+    pub proof fn lemma_map_frame_num_new_dirs_bound(self, base: nat, pte: PageTableEntry)
+        requires
+            self.inv(),
+            self.accepted_mapping(base, pte),
+        ensures
+            self.layer + self.map_frame_num_new_dirs(base, pte) < self.arch.layers.len(),
+        decreases self.arch.layers.len() - self.layer
+    {
+        ambient_lemmas1();
+        let idx = self.index_for_vaddr(base);
+        self.lemma_map_frame_structure_assertions(base, pte, idx);
+        indexing::lemma_index_from_base_and_addr(self.base_vaddr, base, self.entry_size(), self.num_entries());
+        match self.entries.index(idx as int) {
+            NodeEntry::Page(p) => {},
+            NodeEntry::Directory(d) => {
+                if self.entry_size() != pte.frame.size {
+                    assert(d.layer == self.layer + 1 && d.arch == self.arch);
+                    d.lemma_map_frame_num_new_dirs_bound(base, pte);
+                }
+            },
+            NodeEntry::Empty() => {
+                if self.entry_size() != pte.frame.size {
+                    self.new_empty_dir(idx).lemma_map_frame_num_new_dirs_bound(base, pte);
+                }
+            },
+        }
+    }
+
     pub proof fn lemma_map_frame_structure_assertions(self, base: nat, pte: PageTableEntry, idx: nat)
         requires
             self.inv(),
@@ -1411,6 +1470,508 @@ impl Directory {
         }
     }
 
+    /// Maps `ptes` back to back starting at `base`. Mapping stops at the first entry that can't be
+    /// mapped, in which case the entries before it remain mapped.
+    pub open spec fn map_range(self, base: nat, ptes: Seq<PageTableEntry>) -> Result<Directory,Directory>
+        decreases ptes.len()
+    {
+        if ptes.len() == 0 {
+            Ok(self)
+        } else {
+            match self.map_range(base, ptes.drop_last()) {
+                Ok(d)  => d.map_frame(base + range_size(ptes.drop_last()), ptes.last()),
+                Err(d) => Err(d),
+            }
+        }
+    }
+
+    /// Once an entry fails to map, `map_range` returns the same error for every longer prefix.
+    pub proof fn lemma_map_range_err_propagates(self, base: nat, ptes: Seq<PageTableEntry>, i: nat)
+        requires
+            i <= ptes.len(),
+            self.map_range(base, ptes.take(i as int)).is_Err(),
+        ensures
+            self.map_range(base, ptes) === self.map_range(base, ptes.take(i as int)),
+        decreases ptes.len()
+    {
+        if i < ptes.len() {
+            assert(ptes.drop_last().take(i as int) =~= ptes.take(i as int));
+            self.lemma_map_range_err_propagates(base, ptes.drop_last(), i);
+        } else {
+            assert(ptes.take(i as int) =~= ptes);
+        }
+    }
+
+    /// Mapping `a + b` maps `a` and then continues with `b` right after it.
+    pub proof fn lemma_map_range_append(self, base: nat, a: Seq<PageTableEntry>, b: Seq<PageTableEntry>)
+        ensures
+            self.map_range(base, a + b) === match self.map_range(base, a) {
+                Ok(d)  => d.map_range(base + range_size(a), b),
+                Err(d) => Err(d),
+            },
+        decreases b.len()
+    {
+        if b.len() == 0 {
+            assert(a + b =~= a);
+        } else {
+            assert((a + b).drop_last() =~= a + b.drop_last());
+            assert((a + b).last() === b.last());
+            lemma_range_size_append(a, b.drop_last());
+            self.lemma_map_range_append(base, a, b.drop_last());
+        }
+    }
+
+    pub proof fn lemma_map_range_preserves_inv(self, base: nat, ptes: Seq<PageTableEntry>)
+        requires
+            self.inv(),
+            forall|i: int| 0 <= i < ptes.len() ==> #[trigger] self.accepted_mapping(range_entry_base(base, ptes, i), ptes[i]),
+        ensures
+            ({
+                let res = match self.map_range(base, ptes) { Ok(d) => d, Err(d) => d };
+                &&& res.inv()
+                &&& res.layer === self.layer
+                &&& res.arch === self.arch
+                &&& res.base_vaddr === self.base_vaddr
+                &&& 0 < ptes.len() && self.map_range(base, ptes).is_Ok() ==> !res.empty()
+            }),
+        decreases ptes.len()
+    {
+        if ptes.len() > 0 {
+            let prefix = ptes.drop_last();
+            let i = prefix.len() as int;
+            assert forall|j: int| 0 <= j < prefix.len()
+                implies #[trigger] self.accepted_mapping(range_entry_base(base, prefix, j), prefix[j]) by
+            {
+                assert(prefix.take(j) =~= ptes.take(j));
+                assert(self.accepted_mapping(range_entry_base(base, ptes, j), ptes[j]));
+            };
+            self.lemma_map_range_preserves_inv(base, prefix);
+            match self.map_range(base, prefix) {
+                Ok(d) => {
+                    assert(ptes.take(i) =~= prefix);
+                    assert(self.accepted_mapping(range_entry_base(base, ptes, i), ptes[i]));
+                    assert(d.accepted_mapping(base + range_size(prefix), ptes.last()));
+                    d.lemma_map_frame_refines_map_frame(base + range_size(prefix), ptes.last());
+                    if d.map_frame(base + range_size(prefix), ptes.last()).is_Ok() {
+                        d.lemma_map_frame_preserves_inv(base + range_size(prefix), ptes.last());
+                    }
+                },
+                Err(d) => { },
+            }
+        }
+    }
+
+    pub proof fn lemma_map_range_refines_map_range(self, base: nat, ptes: Seq<PageTableEntry>)
+        requires
+            self.inv(),
+            forall|i: int| 0 <= i < ptes.len() ==> #[trigger] self.accepted_mapping(range_entry_base(base, ptes, i), ptes[i]),
+        ensures
+            result_map(self.map_range(base, ptes), |d: Directory| d.interp()) === self.interp().map_range(base, ptes),
+        decreases ptes.len()
+    {
+        if ptes.len() > 0 {
+            let prefix = ptes.drop_last();
+            let i = prefix.len() as int;
+            assert forall|j: int| 0 <= j < prefix.len()
+                implies #[trigger] self.accepted_mapping(range_entry_base(base, prefix, j), prefix[j]) by
+            {
+                assert(prefix.take(j) =~= ptes.take(j));
+                assert(self.accepted_mapping(range_entry_base(base, ptes, j), ptes[j]));
+            };
+            self.lemma_map_range_refines_map_range(base, prefix);
+            self.lemma_map_range_preserves_inv(base, prefix);
+            match self.map_range(base, prefix) {
+                Ok(d) => {
+                    assert(ptes.take(i) =~= prefix);
+                    assert(self.accepted_mapping(range_entry_base(base, ptes, i), ptes[i]));
+                    assert(d.accepted_mapping(base + range_size(prefix), ptes.last()));
+                    d.lemma_map_frame_refines_map_frame(base + range_size(prefix), ptes.last());
+                },
+                Err(d) => { },
+            }
+        }
+    }
+
+    /// An address within the bounds of the entry `idx` is mapped through that entry.
+    pub proof fn lemma_index_for_vaddr_in_entry(self, vaddr: nat, idx: nat)
+        requires
+            self.inv(),
+            idx < self.num_entries(),
+            self.entry_base(idx) <= vaddr,
+            vaddr < self.next_entry_base(idx),
+        ensures
+            self.index_for_vaddr(vaddr) == idx,
+    {
+        ambient_lemmas1();
+        let es = self.entry_size();
+        indexing::lemma_entry_base_from_index(self.base_vaddr, idx, es);
+        indexing::lemma_entry_base_from_index_support(self.base_vaddr, idx, es);
+        if idx + 1 < self.num_entries() {
+            indexing::lemma_entry_base_from_index(self.base_vaddr, idx + 1, es);
+        }
+        indexing::lemma_index_from_base_and_addr(self.base_vaddr, vaddr, es, self.num_entries());
+        let i = self.index_for_vaddr(vaddr);
+        indexing::lemma_entry_base_from_index(self.base_vaddr, i, es);
+        assert(!(i < idx));
+        assert(!(idx < i));
+    }
+
+    /// If the `i`-th entry of a range is mapped through a directory at entry `idx` of `self`,
+    /// every later entry of the range that begins within entry `idx` is mapped through that
+    /// directory as well.
+    pub proof fn lemma_range_entry_in_entry(self, base: nat, ptes: Seq<PageTableEntry>, i: int, j: int, idx: nat)
+        requires
+            self.inv(),
+            idx < self.num_entries(),
+            0 <= i <= j < ptes.len(),
+            self.entry_base(idx) <= range_entry_base(base, ptes, i),
+            range_entry_base(base, ptes, j) < self.next_entry_base(idx),
+            ptes[i].frame.size != self.entry_size(),
+            self.accepted_mapping(range_entry_base(base, ptes, i), ptes[i]),
+            self.accepted_mapping(range_entry_base(base, ptes, j), ptes[j]),
+        ensures
+            ptes[j].frame.size != self.entry_size(),
+            self.index_for_vaddr(range_entry_base(base, ptes, j)) == idx,
+    {
+        ambient_lemmas1();
+        let es = self.entry_size();
+        let eb = self.entry_base(idx);
+        let b = range_entry_base(base, ptes, j);
+        indexing::lemma_entry_base_from_index(self.base_vaddr, idx, es);
+        indexing::lemma_entry_base_from_index_support(self.base_vaddr, idx, es);
+        if j > i {
+            assert(ptes.take(j).take(i) =~= ptes.take(i));
+            assert(ptes.take(j)[i] === ptes[i]);
+            lemma_range_size_take(ptes.take(j), i);
+            assert(ptes[i].frame.size > 0);
+            assert(eb < b);
+            if ptes[j].frame.size == es {
+                assert(aligned(eb, es));
+                extra::leq_add_aligned_less(eb, es, b);
+                assert(false);
+            }
+        }
+        self.lemma_index_for_vaddr_in_entry(b, idx);
+    }
+
+    /// Mapping a nonempty range that lies within the entry `idx` only changes that entry: The
+    /// range is mapped into the directory at `idx`, or into a new empty directory if the entry is
+    /// empty.
+    pub proof fn lemma_map_range_in_entry(self, base: nat, ptes: Seq<PageTableEntry>, idx: nat)
+        requires
+            self.inv(),
+            idx < self.num_entries(),
+            0 < ptes.len(),
+            self.entry_base(idx) <= base,
+            base + range_size(ptes) <= self.next_entry_base(idx),
+            forall|i: int| 0 <= i < ptes.len() ==> #[trigger] self.accepted_mapping(range_entry_base(base, ptes, i), ptes[i]),
+            forall|i: int| 0 <= i < ptes.len() ==> (#[trigger] ptes[i]).frame.size != self.entry_size(),
+            !self.entries[idx as int].is_Page(),
+        ensures
+            ({
+                let d = match self.entries[idx as int] {
+                    NodeEntry::Directory(d) => d,
+                    _                       => self.new_empty_dir(idx),
+                };
+                &&& d.inv()
+                &&& d.layer == self.layer + 1
+                &&& d.arch == self.arch
+                &&& d.base_vaddr == self.entry_base(idx)
+                &&& forall|i: int| 0 <= i < ptes.len() ==> #[trigger] d.accepted_mapping(range_entry_base(base, ptes, i), ptes[i])
+                &&& self.map_range(base, ptes) === match d.map_range(base, ptes) {
+                    Ok(d2)  => Ok(self.update(idx, NodeEntry::Directory(d2))),
+                    Err(d2) => Err(self.update(idx, NodeEntry::Directory(d2))),
+                }
+            }),
+        decreases ptes.len()
+    {
+        ambient_lemmas1();
+        ambient_lemmas2();
+        let d = match self.entries[idx as int] {
+            NodeEntry::Directory(d) => d,
+            _                       => self.new_empty_dir(idx),
+        };
+        indexing::lemma_entry_base_from_index(self.base_vaddr, idx, self.entry_size());
+        indexing::lemma_entry_base_from_index_support(self.base_vaddr, idx, self.entry_size());
+        assert(self.directories_obey_invariant());
+        if self.entries[idx as int].is_Empty() {
+            self.lemma_new_empty_dir(idx);
+        }
+        assert(d.upper_vaddr() == self.next_entry_base(idx));
+        assert forall|i: int| 0 <= i < ptes.len()
+            implies #[trigger] d.accepted_mapping(range_entry_base(base, ptes, i), ptes[i]) by
+        {
+            let b = range_entry_base(base, ptes, i);
+            assert(self.accepted_mapping(b, ptes[i]));
+            lemma_range_entry_base_bounds(base, ptes, i);
+            self.lemma_index_for_vaddr_in_entry(b, idx);
+            self.lemma_accepted_mapping_implies_directory_accepted_mapping(b, ptes[i], d);
+        };
+
+        let prefix = ptes.drop_last();
+        let n = prefix.len() as int;
+        let b = base + range_size(prefix);
+        assert(ptes.take(n) =~= prefix);
+        assert(ptes.last() === ptes[n]);
+        assert(self.accepted_mapping(b, ptes.last()));
+        assert(d.accepted_mapping(b, ptes.last()));
+        lemma_range_entry_base_bounds(base, ptes, n);
+        self.lemma_index_for_vaddr_in_entry(b, idx);
+        if n == 0 {
+            assert(prefix =~= seq![]);
+            self.lemma_map_frame_structure_assertions(b, ptes.last(), idx);
+        } else {
+            assert forall|j: int| 0 <= j < prefix.len()
+                implies #[trigger] self.accepted_mapping(range_entry_base(base, prefix, j), prefix[j]) by
+            {
+                assert(prefix.take(j) =~= ptes.take(j));
+                assert(self.accepted_mapping(range_entry_base(base, ptes, j), ptes[j]));
+            };
+            assert forall|j: int| 0 <= j < prefix.len()
+                implies (#[trigger] prefix[j]).frame.size != self.entry_size() by
+            {
+                assert(prefix[j] === ptes[j]);
+            };
+            self.lemma_map_range_in_entry(base, prefix, idx);
+            assert forall|j: int| 0 <= j < prefix.len()
+                implies #[trigger] d.accepted_mapping(range_entry_base(base, prefix, j), prefix[j]) by
+            {
+                assert(prefix.take(j) =~= ptes.take(j));
+                assert(d.accepted_mapping(range_entry_base(base, ptes, j), ptes[j]));
+            };
+            d.lemma_map_range_preserves_inv(base, prefix);
+            match d.map_range(base, prefix) {
+                Ok(d2) => {
+                    // The last entry is mapped through the same entry of `self`, which now holds
+                    // `d2`.
+                    let s2 = self.update(idx, NodeEntry::Directory(d2));
+                    assert(s2.inv()) by {
+                        assert(s2.directories_are_in_next_layer());
+                        assert(s2.directories_match_arch());
+                        assert(s2.directories_obey_invariant());
+                        assert(s2.directories_are_nonempty());
+                    };
+                    assert(s2.accepted_mapping(b, ptes.last()));
+                    assert(s2.index_for_vaddr(b) == idx);
+                    assert(d2.accepted_mapping(b, ptes.last()));
+                    match d2.map_frame(b, ptes.last()) {
+                        Ok(d3)  => assert(s2.update(idx, NodeEntry::Directory(d3)).entries
+                                          =~= self.update(idx, NodeEntry::Directory(d3)).entries),
+                        Err(d3) => assert(s2.update(idx, NodeEntry::Directory(d3)).entries
+                                          =~= self.update(idx, NodeEntry::Directory(d3)).entries),
+                    }
+                },
+                Err(d2) => { },
+            }
+        }
+    }
+
+    /// Removes all frames whose base lies in `[base, base + len)`, together with the directories
+    /// that become empty. Entries that don't overlap the range are left alone. This only
+    /// describes an unmap if no frame straddles the boundaries of the range, see `unmap_range`.
+    pub open spec fn remove_range(self, base: nat, len: nat) -> Directory
+        decreases self.arch.layers.len() - self.layer, self.num_entries() + 1, 0nat
+    {
+        Directory {
+            entries: self.remove_range_aux(base, len, 0),
+            ..self
+        }
+    }
+
+    pub open spec fn remove_range_aux(self, base: nat, len: nat, i: nat) -> Seq<NodeEntry>
+        decreases self.arch.layers.len() - self.layer, self.num_entries() - i, 1nat
+    {
+        if self.inv() {
+            if i >= self.entries.len() {
+                seq![]
+            } else {
+                seq![self.remove_range_entry(base, len, i)] + self.remove_range_aux(base, len, i + 1)
+            }
+        } else {
+            arbitrary()
+        }
+    }
+
+    pub open spec fn remove_range_entry(self, base: nat, len: nat, i: nat) -> NodeEntry
+        decreases self.arch.layers.len() - self.layer, self.num_entries() - i, 0nat
+    {
+        if self.inv() && i < self.entries.len() {
+            if self.entry_base(i) < base + len && base < self.next_entry_base(i) {
+                match self.entries[i as int] {
+                    NodeEntry::Directory(d) => {
+                        let new_d = d.remove_range(base, len);
+                        if new_d.empty() {
+                            NodeEntry::Empty()
+                        } else {
+                            NodeEntry::Directory(new_d)
+                        }
+                    },
+                    _ => NodeEntry::Empty(),
+                }
+            } else {
+                self.entries[i as int]
+            }
+        } else {
+            arbitrary()
+        }
+    }
+
+    /// Removes all frames whose base lies in `[base, base + len)`. Like
+    /// `l0::PageTableContents::unmap_range` this is atomic: If any frame straddles the boundaries
+    /// of the range, nothing is removed.
+    pub open spec fn unmap_range(self, base: nat, len: nat) -> Result<Directory,Directory> {
+        if exists|b: nat| self.interp().map.dom().contains(b) && #[trigger] self.interp().straddles_range(b, base, len) {
+            Err(self)
+        } else {
+            Ok(self.remove_range(base, len))
+        }
+    }
+
+    pub proof fn lemma_remove_range_aux_facts(self, base: nat, len: nat, i: nat)
+        requires
+            self.inv(),
+            i <= self.num_entries(),
+        ensures
+            self.remove_range_aux(base, len, i).len() == self.num_entries() - i,
+            forall|j: nat| j < self.num_entries() - i
+                ==> #[trigger] self.remove_range_aux(base, len, i)[j as int] === self.remove_range_entry(base, len, i + j),
+        decreases self.num_entries() - i
+    {
+        if i < self.num_entries() {
+            self.lemma_remove_range_aux_facts(base, len, i + 1);
+            assert forall|j: nat| j < self.num_entries() - i
+                implies #[trigger] self.remove_range_aux(base, len, i)[j as int] === self.remove_range_entry(base, len, i + j) by
+            {
+                if j > 0 {
+                    assert(self.remove_range_aux(base, len, i)[j as int] === self.remove_range_aux(base, len, i + 1)[j - 1]);
+                }
+            };
+        }
+    }
+
+    proof fn lemma_remove_range_interp_aux(self, base: nat, len: nat, i: nat)
+        requires
+            self.inv(),
+            self.remove_range(base, len).inv(),
+            forall|j: nat| i <= j < self.num_entries() ==>
+                (#[trigger] self.remove_range(base, len).interp_of_entry(j)).map
+                    === self.interp_of_entry(j).map.remove_keys(Set::new(|b: nat| between(b, base, base + len))),
+        ensures
+            self.remove_range(base, len).interp_aux(i) === l0::PageTableContents {
+                map: self.interp_aux(i).map.remove_keys(Set::new(|b: nat| between(b, base, base + len))),
+                ..self.interp_aux(i)
+            },
+        decreases self.num_entries() - i
+    {
+        let r = self.remove_range(base, len);
+        let keys = Set::new(|b: nat| between(b, base, base + len));
+        if i >= self.entries.len() {
+            assert(self.interp_aux(i).map.remove_keys(keys) =~= map![]);
+        } else {
+            self.lemma_remove_range_interp_aux(base, len, i + 1);
+            let rem = self.interp_aux(i + 1);
+            let entry_i = self.interp_of_entry(i);
+            assert(r.interp_of_entry(i).lower == entry_i.lower);
+            assert(rem.map.union_prefer_right(entry_i.map).remove_keys(keys)
+                   =~= rem.map.remove_keys(keys).union_prefer_right(entry_i.map.remove_keys(keys)));
+        }
+    }
+
+    pub proof fn lemma_remove_range_refines(self, base: nat, len: nat)
+        requires
+            self.inv(),
+            !exists|b: nat| self.interp().map.dom().contains(b) && #[trigger] self.interp().straddles_range(b, base, len),
+        ensures
+            self.remove_range(base, len).inv(),
+            self.remove_range(base, len).layer == self.layer,
+            self.remove_range(base, len).arch == self.arch,
+            self.remove_range(base, len).base_vaddr == self.base_vaddr,
+            self.remove_range(base, len).interp() === self.interp().unmap_range(base, len).get_Ok_0(),
+        decreases self.arch.layers.len() - self.layer
+    {
+        ambient_lemmas1();
+        ambient_lemmas2();
+        let r = self.remove_range(base, len);
+        let keys = Set::new(|b: nat| between(b, base, base + len));
+        self.lemma_remove_range_aux_facts(base, len, 0);
+        self.lemma_interp_of_entry();
+        self.lemma_inv_implies_interp_inv();
+        assert(forall|i: nat| i < self.num_entries() ==> #[trigger] r.entries[i as int] === self.remove_range_entry(base, len, i));
+
+        // Directories that overlap the range are replaced by the directory with the range removed
+        assert forall|i: nat| i < self.num_entries() && #[trigger] self.entries[i as int].is_Directory() implies {
+            let d = self.entries[i as int].get_Directory_0();
+            let new_d = d.remove_range(base, len);
+            &&& new_d.inv()
+            &&& new_d.layer == d.layer
+            &&& new_d.arch == d.arch
+            &&& new_d.base_vaddr == d.base_vaddr
+            &&& new_d.interp() === d.interp().unmap_range(base, len).get_Ok_0()
+        } by {
+            let d = self.entries[i as int].get_Directory_0();
+            assert(d.inv());
+            d.lemma_inv_implies_interp_inv();
+            assert_by_contradiction!(!exists|b: nat| d.interp().map.dom().contains(b) && #[trigger] d.interp().straddles_range(b, base, len), {
+                let b = choose|b: nat| d.interp().map.dom().contains(b) && #[trigger] d.interp().straddles_range(b, base, len);
+                self.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(i);
+                assert(self.interp_of_entry(i).map.contains_pair(b, d.interp().map[b]));
+                assert(self.interp().map.contains_pair(b, d.interp().map[b]));
+                assert(self.interp().straddles_range(b, base, len));
+            });
+            d.lemma_remove_range_refines(base, len);
+        };
+
+        assert(r.inv()) by {
+            assert(r.well_formed());
+            assert(r.pages_match_entry_size());
+            assert(r.directories_are_in_next_layer());
+            assert(r.directories_match_arch());
+            assert(r.directories_obey_invariant());
+            assert(r.directories_are_nonempty());
+            assert(r.frames_aligned());
+        };
+
+        assert forall|i: nat| i < self.num_entries()
+            implies (#[trigger] r.interp_of_entry(i)).map === self.interp_of_entry(i).map.remove_keys(keys) by
+        {
+            indexing::lemma_entry_base_from_index(self.base_vaddr, i, self.entry_size());
+            indexing::lemma_entry_base_from_index_support(self.base_vaddr, i, self.entry_size());
+            if self.entry_base(i) < base + len && base < self.next_entry_base(i) {
+                match self.entries[i as int] {
+                    NodeEntry::Page(p) => {
+                        // The page overlaps the range and doesn't straddle it, so it's contained
+                        // in the range.
+                        let eb = self.entry_base(i);
+                        self.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(i);
+                        assert(self.interp_of_entry(i).map.contains_pair(eb, p));
+                        assert(self.interp().map.contains_pair(eb, p));
+                        assert(!self.interp().straddles_range(eb, base, len));
+                        assert(keys.contains(eb));
+                        assert(self.interp_of_entry(i).map.remove_keys(keys) =~= map![]);
+                    },
+                    NodeEntry::Directory(d) => {
+                        let new_d = d.remove_range(base, len);
+                        if new_d.empty() {
+                            new_d.lemma_empty_implies_interp_empty();
+                            assert(self.interp_of_entry(i).map.remove_keys(keys) =~= new_d.interp().map);
+                        } else {
+                            assert(self.interp_of_entry(i).map.remove_keys(keys) =~= new_d.interp().map);
+                        }
+                    },
+                    NodeEntry::Empty() => {
+                        assert(self.interp_of_entry(i).map.remove_keys(keys) =~= map![]);
+                    },
+                }
+            } else {
+                // Nothing in this entry lies in the range
+                assert(self.interp_of_entry(i).map.remove_keys(keys) =~= self.interp_of_entry(i).map);
+            }
+        };
+
+        self.lemma_remove_range_interp_aux(base, len, 0);
+        assert(self.interp().map.remove_keys(keys) =~= self.interp().map.remove_keys(Set::new(|b: nat| between(b, base, base + len))));
+    }
+
     pub open spec(checked) fn accepted_unmap(self, base: nat) -> bool
         recommends self.well_formed()
     {
@@ -1465,6 +2026,29 @@ impl Directory {
     }
 
 
+    /// The number of directories `unmap` removes from the page table because they became empty,
+    /// i.e. the number of pages an implementation can free.
+    pub open spec fn unmap_num_removed_dirs(self, base: nat) -> nat
+        decreases self.arch.layers.len() - self.layer via Self::check_unmap
+    {
+        if self.inv() && self.accepted_unmap(base) {
+            let entry = self.index_for_vaddr(base);
+            match self.entries.index(entry as int) {
+                NodeEntry::Page(p) => 0,
+                NodeEntry::Directory(d) => {
+                    match d.unmap(base) {
+                        Ok(new_d) =>
+                            d.unmap_num_removed_dirs(base) + if new_d.empty() { 1nat } else { 0nat },
+                        Err(new_d) => 0,
+                    }
+                },
+                NodeEntry::Empty() => 0,
+            }
+        } else {
+            arbitrary()
+        }
+    }
+
     pub proof fn lemma_unmap_preserves_inv(self, base: nat)
         requires
             self.inv(),
@@ -1751,6 +2335,259 @@ impl Directory {
     {
         self.lemma_remove_from_interp_of_entry_implies_remove_from_interp_aux(j, 0, vaddr, n);
     }
+
+    pub open spec(checked) fn accepted_protect(self, base: nat) -> bool
+        recommends self.well_formed()
+    {
+        self.interp().accepted_protect(base)
+    }
+
+    pub open spec fn protect(self, base: nat, flags: Flags) -> Result<Directory,Directory>
+        recommends
+            self.inv(),
+            self.accepted_protect(base),
+        decreases self.arch.layers.len() - self.layer via Self::check_protect
+    {
+        if self.inv() && self.accepted_protect(base) {
+            let entry = self.index_for_vaddr(base);
+            match self.entries.index(entry as int) {
+                NodeEntry::Page(p) => {
+                    if aligned(base, self.entry_size()) {
+                        Ok(self.update(entry, NodeEntry::Page(PageTableEntry { frame: p.frame, flags })))
+                    } else {
+                        Err(self)
+                    }
+                },
+                NodeEntry::Directory(d) => {
+                    match d.protect(base, flags) {
+                        Ok(new_d)  => Ok(self.update(entry, NodeEntry::Directory(new_d))),
+                        Err(new_d) => Err(self.update(entry, NodeEntry::Directory(new_d))),
+                    }
+                },
+                NodeEntry::Empty() => Err(self),
+            }
+        } else {
+            arbitrary()
+        }
+    }
+
+    #[verifier(decreases_by)]
+    proof fn check_protect(self, base: nat, flags: Flags) {
+        if self.inv() && self.accepted_protect(base) {
+            ambient_lemmas2();
+            indexing::lemma_index_from_base_and_addr(self.base_vaddr, base, self.entry_size(), self.num_entries());
+        } else {
+        }
+    }
+
+    pub proof fn lemma_protect_preserves_inv(self, base: nat, flags: Flags)
+        requires
+            self.inv(),
+            self.accepted_protect(base),
+            self.protect(base, flags).is_Ok(),
+        ensures
+            self.protect(base, flags).get_Ok_0().inv(),
+            // Protect only changes flags, so no entry becomes empty or nonempty
+            forall|i: nat| i < self.num_entries() ==>
+                (#[trigger] self.protect(base, flags).get_Ok_0().entries.index(i as int)).is_Empty() == self.entries.index(i as int).is_Empty(),
+        decreases self.arch.layers.len() - self.layer
+    {
+        ambient_lemmas1();
+        ambient_lemmas2();
+
+        let res = self.protect(base, flags).get_Ok_0();
+
+        let entry = self.index_for_vaddr(base);
+        indexing::lemma_entry_base_from_index(self.base_vaddr, entry, self.entry_size());
+        indexing::lemma_index_from_base_and_addr(self.base_vaddr, base, self.entry_size(), self.num_entries());
+
+        assert(entry < self.num_entries());
+        match self.entries.index(entry as int) {
+            NodeEntry::Page(p) => {
+                assert(res.pages_match_entry_size());
+                assert(res.frames_aligned());
+                assert(res.directories_obey_invariant());
+            },
+            NodeEntry::Directory(d) => {
+                d.lemma_inv_implies_interp_inv();
+                assert(d.accepted_protect(base));
+                let new_d = d.protect(base, flags).get_Ok_0();
+                d.lemma_protect_preserves_inv(base, flags);
+                assert(!new_d.empty()) by {
+                    assert(!d.empty());
+                    let i = choose|i: nat| i < d.num_entries() && !d.entries.index(i as int).is_Empty();
+                    assert(!new_d.entries.index(i as int).is_Empty());
+                };
+                assert(res.directories_obey_invariant());
+                assert(res.directories_are_nonempty());
+            },
+            NodeEntry::Empty() => { },
+        }
+    }
+
+    pub proof fn lemma_protect_structure_assertions(self, base: nat, flags: Flags, idx: nat)
+        requires
+            self.inv(),
+            self.accepted_protect(base),
+            idx == self.index_for_vaddr(base),
+        ensures
+            match self.entries.index(idx as int) {
+                NodeEntry::Page(p)      => {
+                    if aligned(base, self.entry_size()) {
+                        base == self.base_vaddr + idx * self.entry_size()
+                    } else {
+                        true
+                    }
+                },
+                NodeEntry::Directory(d) => {
+                    &&& d.inv()
+                    &&& d.accepted_protect(base)
+                },
+                NodeEntry::Empty()      => true,
+            }
+        decreases self.arch.layers.len() - self.layer
+    {
+        self.lemma_unmap_structure_assertions(base, idx);
+    }
+
+    pub proof fn lemma_protect_refines_protect(self, base: nat, flags: Flags)
+        requires
+             self.inv(),
+             self.accepted_protect(base),
+        ensures
+            self.protect(base, flags).is_Err() ==> self.protect(base, flags).get_Err_0() === self,
+            equal(result_map(self.protect(base, flags), |d: Directory| d.interp()), self.interp().protect(base, flags)),
+        decreases self.arch.layers.len() - self.layer
+    {
+        ambient_lemmas1();
+        ambient_lemmas2();
+        self.lemma_inv_implies_interp_inv();
+
+        if let Ok(nself) = self.protect(base, flags) {
+            self.lemma_protect_preserves_inv(base, flags);
+            assert(nself.inv());
+        }
+
+        let entry = self.index_for_vaddr(base);
+        indexing::lemma_entry_base_from_index(self.base_vaddr, entry, self.entry_size());
+        indexing::lemma_entry_base_from_index_support(self.base_vaddr, entry, self.entry_size());
+        indexing::lemma_index_from_base_and_addr(self.base_vaddr, base, self.entry_size(), self.num_entries());
+        self.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(entry);
+
+        match self.entries.index(entry as int) {
+            NodeEntry::Page(p) => {
+                if aligned(base, self.entry_size()) {
+                    let new_pte = PageTableEntry { frame: p.frame, flags };
+                    assert(self.interp_of_entry(entry).map.dom().contains(base));
+                    assert(self.interp_of_entry(entry).map.insert(base, new_pte) =~= map![self.entry_base(entry) => new_pte]);
+                    assert(self.update(entry, NodeEntry::Page(new_pte)).inv());
+                    self.lemma_update_interp_of_entry_implies_update_interp(entry, base, new_pte, NodeEntry::Page(new_pte));
+                    assert(self.interp().map.index(base) === p);
+                } else {
+                    assert(!self.interp().map.dom().contains(base));
+                }
+            },
+            NodeEntry::Directory(d) => {
+                assert(d.inv());
+                d.lemma_inv_implies_interp_inv();
+                assert(d.accepted_protect(base));
+                d.lemma_protect_refines_protect(base, flags);
+                match d.protect(base, flags) {
+                    Ok(new_d) => {
+                        d.lemma_protect_preserves_inv(base, flags);
+                        assert(d.interp().map.dom().contains(base));
+                        let new_pte = PageTableEntry { frame: d.interp().map.index(base).frame, flags };
+                        assert(equal(new_d.interp().map, d.interp().map.insert(base, new_pte)));
+                        assert(equal(self.interp_of_entry(entry).map, d.interp().map));
+                        assert(self.update(entry, NodeEntry::Directory(new_d)).inv());
+                        self.lemma_update_interp_of_entry_implies_update_interp(entry, base, new_pte, NodeEntry::Directory(new_d));
+                    }
+                    Err(e) => {
+                        assert(self.entries.index(entry as int) === NodeEntry::Directory(e));
+                        let res = self.update(entry, NodeEntry::Directory(e)).entries;
+                        assert(res =~= self.entries);
+                    }
+                }
+            },
+            NodeEntry::Empty() => { },
+        }
+    }
+
+    proof fn lemma_update_interp_of_entry_implies_update_interp_aux(self, j: nat, i: nat, vaddr: nat, pte: PageTableEntry, n: NodeEntry)
+        requires
+            self.inv(),
+            i <= j,
+            j < self.num_entries(),
+            self.interp_of_entry(j).map.dom().contains(vaddr),
+            self.update(j, n).inv(),
+            equal(
+                self.interp_of_entry(j).map.insert(vaddr, pte),
+                match n {
+                    NodeEntry::Page(p)      => map![self.entry_base(j) => p],
+                    NodeEntry::Directory(d) => d.interp_aux(0).map,
+                    NodeEntry::Empty()      => map![],
+                }),
+        ensures
+            equal(self.interp_aux(i).map.insert(vaddr, pte), self.update(j, n).interp_aux(i).map),
+        decreases self.arch.layers.len() - self.layer, self.num_entries() - i
+    {
+        assert(j < self.entries.len());
+        ambient_lemmas1();
+        self.lemma_inv_implies_interp_aux_inv(i);
+        self.lemma_inv_implies_interp_aux_inv(i + 1);
+        self.lemma_inv_implies_interp_of_entry_inv(i);
+        self.lemma_inv_implies_interp_of_entry_inv(j);
+
+        self.lemma_interp_of_entry();
+        self.lemma_interp_of_entry_contains_mapping_implies_interp_aux_contains_mapping(i, j);
+
+        let nself = self.update(j, n);
+
+        if i >= self.entries.len() {
+        } else {
+            if i == j {
+                assert(equal(self.interp_of_entry(i).map.insert(vaddr, pte), nself.interp_of_entry(i).map));
+                self.lemma_entries_equal_implies_interp_aux_equal(nself, i+1);
+                assert(equal(self.interp_aux(i + 1).map, nself.interp_aux(i + 1).map));
+
+                assert(equal(self.interp_aux(i + 1).map.union_prefer_right(self.interp_of_entry(i).map).insert(vaddr, pte),
+                             nself.interp_aux(i + 1).map.union_prefer_right(nself.interp_of_entry(i).map)));
+            } else {
+                assert(i < j);
+                assert(self.directories_obey_invariant());
+
+                self.lemma_update_interp_of_entry_implies_update_interp_aux(j, i + 1, vaddr, pte, n);
+                self.lemma_interp_of_entry_contains_mapping_implies_interp_aux_contains_mapping(i + 1, j);
+                assert(!self.interp_of_entry(i).map.dom().contains(vaddr));
+
+                assert(equal(self.interp_aux(i + 1).map.insert(vaddr, pte), nself.interp_aux(i + 1).map));
+                assert(equal(self.interp_aux(i).map, self.interp_aux(i + 1).map.union_prefer_right(self.interp_of_entry(i).map)));
+
+                assert(nself.inv());
+                assert(equal(nself.interp_aux(i).map, nself.interp_aux(i + 1).map.union_prefer_right(nself.interp_of_entry(i).map)));
+                assert(self.interp_aux(i).map.insert(vaddr, pte) =~= nself.interp_aux(i).map);
+            }
+        }
+    }
+
+    proof fn lemma_update_interp_of_entry_implies_update_interp(self, j: nat, vaddr: nat, pte: PageTableEntry, n: NodeEntry)
+        requires
+            self.inv(),
+            j < self.num_entries(),
+            self.interp_of_entry(j).map.dom().contains(vaddr),
+            self.update(j, n).inv(),
+            equal(
+                self.interp_of_entry(j).map.insert(vaddr, pte),
+                match n {
+                    NodeEntry::Page(p)      => map![self.entry_base(j) => p],
+                    NodeEntry::Directory(d) => d.interp_aux(0).map,
+                    NodeEntry::Empty()      => map![],
+                })
+        ensures
+            equal(self.interp().map.insert(vaddr, pte), self.update(j, n).interp().map),
+    {
+        self.lemma_update_interp_of_entry_implies_update_interp_aux(j, 0, vaddr, pte, n);
+    }
 }
 
 }
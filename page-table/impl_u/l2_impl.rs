@@ -1,19 +1,23 @@
 use vstd::prelude::*;
 use vstd::assert_by_contradiction;
 
-use crate::definitions_t::{ MemRegion, MemRegionExec, PageTableEntry, PageTableEntryExec, Flags,
+use crate::definitions_t::{ MemRegion, MemRegionExec, PageTableEntry, PageTableEntryExec, Flags, MemoryType,
 between, aligned, new_seq, x86_arch_exec, x86_arch_spec, axiom_max_phyaddr_width_facts, MAX_BASE,
-WORD_SIZE, PAGE_SIZE, MAX_PHYADDR, MAX_PHYADDR_WIDTH, L1_ENTRY_SIZE, L2_ENTRY_SIZE, L3_ENTRY_SIZE,
-X86_NUM_LAYERS, X86_NUM_ENTRIES, bit, bitmask_inc };
-use crate::definitions_u::{ lemma_new_seq, aligned_exec, permissive_flags};
+WORD_SIZE, PAGE_SIZE, MAX_PHYADDR, MAX_PHYADDR_WIDTH, L0_ENTRY_SIZE, L1_ENTRY_SIZE, L2_ENTRY_SIZE, L3_ENTRY_SIZE,
+X86_NUM_LAYERS, X86_NUM_ENTRIES, bit, bitmask_inc, range_size, range_entry_base, Arch,
+x86_arch_spec_upper_bound, MAX_LA57_BASE, PML5_ENTRY_SIZE, x86_la57_arch_spec,
+x86_arch_spec_with_layers, x86_arch_exec_with_layers, ArchExec };
+use crate::definitions_u::{ lemma_new_seq, aligned_exec, clone_flags, clone_pte, permissive_flags,
+lemma_x86_arch_spec_with_layers, overflow_bounds, lemma_range_size_cons, lemma_range_size_take,
+lemma_range_size_append, lemma_range_entry_base_bounds, lemma_range_entry_base_subrange };
 use crate::impl_u::l1;
 use crate::impl_u::l0::{ambient_arith};
 use crate::impl_u::indexing;
 use crate::spec_t::mem;
 use crate::spec_t::hardware::{PageDirectoryEntry,GhostPageDirectoryEntry, MASK_FLAG_P,
-MASK_FLAG_RW, MASK_FLAG_US, MASK_FLAG_PWT, MASK_FLAG_PCD, MASK_FLAG_XD, MASK_ADDR,
-MASK_PG_FLAG_PAT, MASK_L1_PG_FLAG_PS, MASK_DIR_ADDR, MASK_L1_PG_ADDR, MASK_L2_PG_ADDR,
-MASK_L3_PG_ADDR};
+MASK_FLAG_RW, MASK_FLAG_US, MASK_FLAG_PWT, MASK_FLAG_PCD, MASK_FLAG_A, MASK_FLAG_XD, MASK_ADDR,
+MASK_PG_FLAG_D, MASK_PG_FLAG_G, MASK_PG_FLAG_PAT, MASK_L1_PG_FLAG_PS, MASK_DIR_ADDR, MASK_L1_PG_ADDR, MASK_L2_PG_ADDR,
+MASK_L3_PG_ADDR, memory_type, entry_format_layer};
 use crate::extra::{ self, result_map_ok };
 
 
@@ -152,6 +156,7 @@ impl PageDirectoryEntry {
         is_supervisor: bool,
         is_writethrough: bool,
         disable_cache: bool,
+        is_global: bool,
         disable_execute: bool,
         )
         requires
@@ -167,6 +172,7 @@ impl PageDirectoryEntry {
                 | if is_supervisor         { 0 }                  else { MASK_FLAG_US }
                 | if is_writethrough       { MASK_FLAG_PWT }      else { 0 }
                 | if disable_cache         { MASK_FLAG_PCD }      else { 0 }
+                | if is_global             { MASK_PG_FLAG_G }     else { 0 }
                 | if disable_execute       { MASK_FLAG_XD }       else { 0 };
                (PageDirectoryEntry { entry: e, layer: Ghost(layer as nat) }).all_mb0_bits_are_zero()
             }),
@@ -177,8 +183,9 @@ impl PageDirectoryEntry {
         let or4 = if is_supervisor         { 0 }                         else { MASK_FLAG_US as u64 };
         let or5 = if is_writethrough       { MASK_FLAG_PWT as u64 }      else { 0 };
         let or6 = if disable_cache         { MASK_FLAG_PCD as u64 }      else { 0 };
-        let or7 = if disable_execute       { MASK_FLAG_XD as u64 }       else { 0 };
-        let e = address | or1 | or2 | or3 | or4 | or5 | or6 | or7;
+        let or7 = if is_global             { MASK_PG_FLAG_G as u64 }     else { 0 };
+        let or8 = if disable_execute       { MASK_FLAG_XD as u64 }       else { 0 };
+        let e = address | or1 | or2 | or3 | or4 | or5 | or6 | or7 | or8;
         let mw: u64 = MAX_PHYADDR_WIDTH;
         assert(forall|a:u64| #![auto] a == a | 0) by (bit_vector);
 
@@ -204,7 +211,7 @@ impl PageDirectoryEntry {
             requires
                 address & bitmask_inc!(12u64, mw - 1) == address,
                 32 <= mw <= 52;
-        PageDirectoryEntry::lemma_new_entry_addr_mask_is_address(layer, address, is_page, is_writable, is_supervisor, is_writethrough, disable_cache, disable_execute);
+        PageDirectoryEntry::lemma_new_entry_addr_mask_is_address(layer, address, is_page, is_writable, is_supervisor, is_writethrough, disable_cache, is_global, disable_execute);
         if layer == 0 {
             assert(!is_page);
             assert(e & bit!(7u64) == 0);
@@ -238,6 +245,54 @@ impl PageDirectoryEntry {
         assert(pde.all_mb0_bits_are_zero());
     }
 
+    pub proof fn lemma_new_entry_ad_bits_are_zero(
+        layer: usize,
+        address: u64,
+        is_page: bool,
+        is_writable: bool,
+        is_supervisor: bool,
+        is_writethrough: bool,
+        disable_cache: bool,
+        is_global: bool,
+        disable_execute: bool,
+        )
+        requires
+            address & MASK_ADDR == address,
+        ensures
+            ({ let e = address
+                | MASK_FLAG_P
+                | if is_page && layer != 3 { MASK_L1_PG_FLAG_PS } else { 0 }
+                | if is_writable           { MASK_FLAG_RW }       else { 0 }
+                | if is_supervisor         { 0 }                  else { MASK_FLAG_US }
+                | if is_writethrough       { MASK_FLAG_PWT }      else { 0 }
+                | if disable_cache         { MASK_FLAG_PCD }      else { 0 }
+                | if is_global             { MASK_PG_FLAG_G }     else { 0 }
+                | if disable_execute       { MASK_FLAG_XD }       else { 0 };
+               e & mem::MASK_AD_FLAGS == 0
+            }),
+    {
+        let or1 = MASK_FLAG_P;
+        let or2 = if is_page && layer != 3 { MASK_L1_PG_FLAG_PS as u64 } else { 0 };
+        let or3 = if is_writable           { MASK_FLAG_RW as u64 }       else { 0 };
+        let or4 = if is_supervisor         { 0 }                         else { MASK_FLAG_US as u64 };
+        let or5 = if is_writethrough       { MASK_FLAG_PWT as u64 }      else { 0 };
+        let or6 = if disable_cache         { MASK_FLAG_PCD as u64 }      else { 0 };
+        let or7 = if is_global             { MASK_PG_FLAG_G as u64 }     else { 0 };
+        let or8 = if disable_execute       { MASK_FLAG_XD as u64 }       else { 0 };
+        let e = address | or1 | or2 | or3 | or4 | or5 | or6 | or7 | or8;
+        let mw: u64 = MAX_PHYADDR_WIDTH;
+        assert(forall|a:u64| #![auto] a == a | 0) by (bit_vector);
+        assert(mem::MASK_AD_FLAGS == bit!(5u64) | bit!(6u64));
+
+        axiom_max_phyaddr_width_facts();
+        assert(address & (bit!(5u64) | bit!(6u64)) == 0) by (bit_vector)
+            requires
+                address & bitmask_inc!(12u64, mw - 1) == address,
+                32 <= mw <= 52;
+        assert(forall|a:u64,i:u64| #![auto] i < 64 && i != 5 && i != 6 && (a & (bit!(5u64) | bit!(6u64)) == 0) ==> (a | bit!(i)) & (bit!(5u64) | bit!(6u64)) == 0) by (bit_vector);
+        assert(e & (bit!(5u64) | bit!(6u64)) == 0);
+    }
+
 
     pub proof fn lemma_new_entry_addr_mask_is_address(
         layer: usize,
@@ -247,6 +302,7 @@ impl PageDirectoryEntry {
         is_supervisor: bool,
         is_writethrough: bool,
         disable_cache: bool,
+        is_global: bool,
         disable_execute: bool,
         )
         requires
@@ -262,6 +318,7 @@ impl PageDirectoryEntry {
                 | if is_supervisor         { 0 }                   else { MASK_FLAG_US }
                 | if is_writethrough       { MASK_FLAG_PWT }       else { 0 }
                 | if disable_cache         { MASK_FLAG_PCD }       else { 0 }
+                | if is_global             { MASK_PG_FLAG_G }      else { 0 }
                 | if disable_execute       { MASK_FLAG_XD }        else { 0 };
                &&& e & MASK_ADDR == address
                &&& e & MASK_FLAG_P == MASK_FLAG_P
@@ -270,6 +327,7 @@ impl PageDirectoryEntry {
                &&& (e & MASK_FLAG_US == MASK_FLAG_US) == !is_supervisor
                &&& (e & MASK_FLAG_PWT == MASK_FLAG_PWT) == is_writethrough
                &&& (e & MASK_FLAG_PCD == MASK_FLAG_PCD) == disable_cache
+               &&& (e & MASK_PG_FLAG_G == MASK_PG_FLAG_G) == is_global
                &&& (e & MASK_FLAG_XD == MASK_FLAG_XD) == disable_execute
                &&& (is_page && layer == 1 ==> e & MASK_PG_FLAG_PAT == 0)
                &&& (is_page && layer == 2 ==> e & MASK_PG_FLAG_PAT == 0)
@@ -282,8 +340,9 @@ impl PageDirectoryEntry {
         let or4 = if is_supervisor         { 0 }                          else { MASK_FLAG_US as u64 };
         let or5 = if is_writethrough       { MASK_FLAG_PWT as u64 }       else { 0 };
         let or6 = if disable_cache         { MASK_FLAG_PCD as u64 }       else { 0 };
-        let or7 = if disable_execute       { MASK_FLAG_XD as u64 }        else { 0 };
-        let e = address | or1 | or2 | or3 | or4 | or5 | or6 | or7;
+        let or7 = if is_global             { MASK_PG_FLAG_G as u64 }      else { 0 };
+        let or8 = if disable_execute       { MASK_FLAG_XD as u64 }        else { 0 };
+        let e = address | or1 | or2 | or3 | or4 | or5 | or6 | or7 | or8;
         let mw: u64 = MAX_PHYADDR_WIDTH;
         axiom_max_phyaddr_width_facts();
         assert(forall|a:u64,x:u64| x < 64 && (a & bit!(x) == 0) ==> a & bit!(x) != bit!(x)) by (bit_vector);
@@ -332,12 +391,21 @@ impl PageDirectoryEntry {
             r@.get_Page_flag_RW() == pte.flags.is_writable,
             (r.entry & MASK_FLAG_US == MASK_FLAG_US) == !pte.flags.is_supervisor,
             r@.get_Page_flag_US() == !pte.flags.is_supervisor,
-            r.entry & MASK_FLAG_PWT != MASK_FLAG_PWT,
-            r.entry & MASK_FLAG_PCD != MASK_FLAG_PCD,
+            memory_type(r@.get_Page_flag_PAT(), r@.get_Page_flag_PCD(), r@.get_Page_flag_PWT()) == pte.flags.memory_type,
+            (r.entry & MASK_PG_FLAG_G == MASK_PG_FLAG_G) == pte.flags.is_global,
+            r@.get_Page_flag_G() == pte.flags.is_global,
             (r.entry & MASK_FLAG_XD == MASK_FLAG_XD) == pte.flags.disable_execute,
             r@.get_Page_flag_XD() == pte.flags.disable_execute,
+            r.entry & mem::MASK_AD_FLAGS == 0,
     {
-        Self::new_entry(layer, pte.frame.base as u64, true, pte.flags.is_writable, pte.flags.is_supervisor, false, false, pte.flags.disable_execute)
+        // With the default PAT MSR the memory type is selected by PCD and PWT alone.
+        let (is_writethrough, disable_cache) = match pte.flags.memory_type {
+            MemoryType::WriteBack        => (false, false),
+            MemoryType::WriteThrough     => (true,  false),
+            MemoryType::UncacheableMinus => (false, true),
+            MemoryType::Uncacheable      => (true,  true),
+        };
+        Self::new_entry(layer, pte.frame.base as u64, true, pte.flags.is_writable, pte.flags.is_supervisor, is_writethrough, disable_cache, pte.flags.is_global, pte.flags.disable_execute)
     }
 
     pub fn new_dir_entry(layer: usize, address: u64) -> (r: Self)
@@ -353,6 +421,7 @@ impl PageDirectoryEntry {
             r@.get_Directory_flag_RW(),
             r@.get_Directory_flag_US(),
             !r@.get_Directory_flag_XD(),
+            r.entry & mem::MASK_AD_FLAGS == 0,
     {
         Self::new_entry(
             layer,
@@ -362,6 +431,7 @@ impl PageDirectoryEntry {
             false, // is_supervisor
             false, // is_writethrough
             false, // disable_cache
+            false, // is_global
             false) // disable_execute
     }
 
@@ -373,6 +443,7 @@ impl PageDirectoryEntry {
         is_supervisor: bool,
         is_writethrough: bool,
         disable_cache: bool,
+        is_global: bool,
         disable_execute: bool,
         ) -> (r: PageDirectoryEntry)
         requires
@@ -392,7 +463,9 @@ impl PageDirectoryEntry {
             (r.entry & MASK_FLAG_US == MASK_FLAG_US) == !is_supervisor,
             (r.entry & MASK_FLAG_PWT == MASK_FLAG_PWT) == is_writethrough,
             (r.entry & MASK_FLAG_PCD == MASK_FLAG_PCD) == disable_cache,
+            (r.entry & MASK_PG_FLAG_G == MASK_PG_FLAG_G) == is_global,
             (r.entry & MASK_FLAG_XD == MASK_FLAG_XD) == disable_execute,
+            r.entry & mem::MASK_AD_FLAGS == 0,
     {
         let e =
         PageDirectoryEntry {
@@ -404,14 +477,16 @@ impl PageDirectoryEntry {
                 | if is_supervisor         { 0 }                   else { MASK_FLAG_US }
                 | if is_writethrough       { MASK_FLAG_PWT }       else { 0 }
                 | if disable_cache         { MASK_FLAG_PCD }       else { 0 }
+                | if is_global             { MASK_PG_FLAG_G }      else { 0 }
                 | if disable_execute       { MASK_FLAG_XD }        else { 0 }
             },
             layer: Ghost(layer as nat),
         };
 
         proof {
-            PageDirectoryEntry::lemma_new_entry_addr_mask_is_address(layer, address, is_page, is_writable, is_supervisor, is_writethrough, disable_cache, disable_execute);
-            PageDirectoryEntry::lemma_new_entry_mb0_bits_are_zero(layer, address, is_page, is_writable, is_supervisor, is_writethrough, disable_cache, disable_execute);
+            PageDirectoryEntry::lemma_new_entry_addr_mask_is_address(layer, address, is_page, is_writable, is_supervisor, is_writethrough, disable_cache, is_global, disable_execute);
+            PageDirectoryEntry::lemma_new_entry_mb0_bits_are_zero(layer, address, is_page, is_writable, is_supervisor, is_writethrough, disable_cache, is_global, disable_execute);
+            PageDirectoryEntry::lemma_new_entry_ad_bits_are_zero(layer, address, is_page, is_writable, is_supervisor, is_writethrough, disable_cache, is_global, disable_execute);
             if is_page { e.lemma_addr_mask_when_hp_pat_is_zero(); }
         }
         e
@@ -425,11 +500,23 @@ impl PageDirectoryEntry {
             res.is_writable     <==> self.entry & MASK_FLAG_RW == MASK_FLAG_RW,
             res.is_supervisor   <==> self.entry & MASK_FLAG_US != MASK_FLAG_US,
             res.disable_execute <==> self.entry & MASK_FLAG_XD == MASK_FLAG_XD,
+            res.memory_type     ==   memory_type(self@.get_Page_flag_PAT(), self@.get_Page_flag_PCD(), self@.get_Page_flag_PWT()),
+            res.is_global       <==> self.entry & MASK_PG_FLAG_G == MASK_PG_FLAG_G,
     {
+        let is_writethrough = self.entry & MASK_FLAG_PWT == MASK_FLAG_PWT;
+        let disable_cache   = self.entry & MASK_FLAG_PCD == MASK_FLAG_PCD;
         Flags {
             is_writable:     self.entry & MASK_FLAG_RW == MASK_FLAG_RW,
             is_supervisor:   self.entry & MASK_FLAG_US != MASK_FLAG_US,
             disable_execute: self.entry & MASK_FLAG_XD == MASK_FLAG_XD,
+            memory_type:
+                match (disable_cache, is_writethrough) {
+                    (false, false) => MemoryType::WriteBack,
+                    (false, true)  => MemoryType::WriteThrough,
+                    (true, false)  => MemoryType::UncacheableMinus,
+                    (true, true)   => MemoryType::Uncacheable,
+                },
+            is_global:       self.entry & MASK_PG_FLAG_G == MASK_PG_FLAG_G,
         }
     }
 
@@ -514,6 +601,95 @@ pub mod PT {
 
 use super::*;
 
+/// The layout of the page table in `mem`, which has 4 or 5 layers.
+pub open spec fn arch(mem: &mem::PageTableMemory) -> Arch {
+    x86_arch_spec_with_layers(mem.num_layers_spec())
+}
+
+/// The entry format of the directories on `layer`. With 5-level paging the PML5 uses the PML4
+/// format and every other layer uses the format of the layer above it in 4-level paging.
+pub open spec fn format_layer(mem: &mem::PageTableMemory, layer: nat) -> nat {
+    entry_format_layer(mem.num_layers_spec(), layer)
+}
+
+fn arch_exec(mem: &mem::PageTableMemory) -> (res: ArchExec)
+    requires
+        mem.inv(),
+    ensures
+        res@ === arch(mem),
+        res.layers@.len() == mem.num_layers_spec(),
+{
+    x86_arch_exec_with_layers(mem.num_layers())
+}
+
+fn format_layer_exec(mem: &mem::PageTableMemory, layer: usize) -> (res: usize)
+    requires
+        mem.inv(),
+        layer < mem.num_layers_spec(),
+    ensures
+        res == format_layer(mem, layer as nat),
+        res <= 3,
+{
+    let num_layers = mem.num_layers();
+    if layer + X86_NUM_LAYERS >= num_layers { layer + X86_NUM_LAYERS - num_layers } else { 0 }
+}
+
+pub proof fn lemma_arch_facts(mem: &mem::PageTableMemory)
+    requires
+        mem.inv(),
+    ensures
+        arch(mem).inv(),
+        arch(mem).layers.len() == mem.num_layers_spec(),
+        arch(mem).upper_vaddr(0, 0) <= MAX_LA57_BASE,
+        MAX_BASE <= arch(mem).upper_vaddr(0, 0),
+        forall|layer: nat| layer < mem.num_layers_spec() ==> #[trigger] arch(mem).num_entries(layer) == X86_NUM_ENTRIES,
+        forall|layer: nat| layer < mem.num_layers_spec() ==> #[trigger] format_layer(mem, layer) <= 3,
+        forall|layer: nat| layer + 1 < mem.num_layers_spec() ==> #[trigger] format_layer(mem, layer) < 3,
+        format_layer(mem, (mem.num_layers_spec() - 1) as nat) == 3,
+        // The layers that use the PML4 format have entries larger than any page
+        forall|layer: nat| layer < mem.num_layers_spec() && format_layer(mem, layer) == 0
+            ==> #[trigger] arch(mem).entry_size(layer) >= L0_ENTRY_SIZE,
+        // and the others have the entry sizes of the corresponding layer in 4-level paging.
+        forall|layer: nat| layer < mem.num_layers_spec() && format_layer(mem, layer) > 0
+            ==> #[trigger] arch(mem).entry_size(layer) == x86_arch_spec.entry_size(format_layer(mem, layer)),
+        forall|size: nat| x86_arch_spec.contains_entry_size_at_index_atleast(size, 1)
+            ==> #[trigger] arch(mem).contains_entry_size_at_index_atleast(size, 1),
+{
+    let n = mem.num_layers_spec();
+    lemma_x86_arch_spec_with_layers(n);
+    overflow_bounds();
+    x86_arch_spec_upper_bound();
+    assert(x86_arch_spec.upper_vaddr(0, 0) == MAX_BASE) by (compute_only);
+    assert forall|layer: nat| layer < n && format_layer(mem, layer) > 0
+        implies #[trigger] arch(mem).entry_size(layer) == x86_arch_spec.entry_size(format_layer(mem, layer))
+    by {
+        let i = format_layer(mem, layer);
+        assert((i + n - X86_NUM_LAYERS) as nat == layer);
+        assert(x86_arch_spec_with_layers(n).entry_size((i + n - X86_NUM_LAYERS) as nat) == x86_arch_spec.entry_size(i));
+    };
+    assert forall|layer: nat| layer < n && format_layer(mem, layer) == 0
+        implies #[trigger] arch(mem).entry_size(layer) >= L0_ENTRY_SIZE
+    by {
+        if n == X86_NUM_LAYERS {
+            assert(layer == 0);
+            assert(x86_arch_spec.entry_size(0) == L0_ENTRY_SIZE);
+        } else if layer == 0 {
+            assert(x86_la57_arch_spec.entry_size(0) == PML5_ENTRY_SIZE);
+        } else {
+            assert(layer == 1);
+            assert(x86_la57_arch_spec.entry_size(1) == L0_ENTRY_SIZE);
+        }
+    };
+    assert forall|size: nat| x86_arch_spec.contains_entry_size_at_index_atleast(size, 1)
+        implies #[trigger] arch(mem).contains_entry_size_at_index_atleast(size, 1)
+    by {
+        let i = choose|i: nat| 1 <= i && i < x86_arch_spec.layers.len() && #[trigger] x86_arch_spec.entry_size(i) == size;
+        let j = (i + n - X86_NUM_LAYERS) as nat;
+        assert(format_layer(mem, j) == i);
+        assert(arch(mem).entry_size(j) == size);
+    };
+}
+
 pub open spec(checked) fn inv(mem: &mem::PageTableMemory, pt: PTDir) -> bool {
     &&& pt.region == mem.cr3_spec()@
     &&& inv_at(mem, pt, 0, mem.cr3_spec().base)
@@ -523,7 +699,7 @@ pub open spec(checked) fn inv(mem: &mem::PageTableMemory, pt: PTDir) -> bool {
 pub open spec fn entry_at_spec(mem: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize, i: nat) -> PageDirectoryEntry {
     PageDirectoryEntry {
         entry: mem.spec_read(i, pt.region),
-        layer: Ghost(layer),
+        layer: Ghost(format_layer(mem, layer)),
     }
 }
 
@@ -531,7 +707,7 @@ pub open spec fn entry_at_spec(mem: &mem::PageTableMemory, pt: PTDir, layer: nat
 pub open spec fn view_at(mem: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize, i: nat) -> GhostPageDirectoryEntry {
     PageDirectoryEntry {
         entry: mem.spec_read(i, pt.region),
-        layer: Ghost(layer),
+        layer: Ghost(format_layer(mem, layer)),
     }@
 }
 
@@ -541,7 +717,7 @@ fn entry_at(mem: &mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usize, p
         i < 512,
         inv_at(mem, pt, layer as nat, ptr),
     ensures
-        res.layer@ == layer as nat,
+        res.layer@ == format_layer(mem, layer as nat),
         res@ === view_at(mem, pt, layer as nat, ptr, i as nat),
         res == entry_at_spec(mem, pt, layer as nat, ptr, i as nat),
         res.hp_pat_is_zero(),
@@ -555,7 +731,7 @@ fn entry_at(mem: &mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usize, p
     proof { let _ = entry_at_spec(mem, pt, layer as nat, ptr, i as nat); }
     PageDirectoryEntry {
         entry: mem.read(ptr, i, Ghost(pt.region)),
-        layer: Ghost(layer as nat),
+        layer: Ghost(format_layer(mem, layer as nat)),
     }
 }
 
@@ -568,8 +744,8 @@ pub open spec fn ghost_pt_matches_structure(mem: &mem::PageTableMemory, pt: PTDi
 }
 
 pub open spec fn directories_obey_invariant_at(mem: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize) -> bool
-    decreases X86_NUM_LAYERS - layer, 0nat
-        when layer_in_range(layer)
+    decreases mem.num_layers_spec() - layer, 0nat
+        when layer_in_range(mem, layer)
 {
     forall|i: nat| i < X86_NUM_ENTRIES ==> {
         let entry = #[trigger] view_at(mem, pt, layer, ptr, i);
@@ -583,12 +759,12 @@ pub open spec fn empty_at(mem: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr
     forall|i: nat| i < X86_NUM_ENTRIES ==> view_at(mem, pt, layer, ptr, i).is_Empty()
 }
 
-pub open spec(checked) fn layer_in_range(layer: nat) -> bool {
-    layer < X86_NUM_LAYERS
+pub open spec(checked) fn layer_in_range(mem: &mem::PageTableMemory, layer: nat) -> bool {
+    layer < mem.num_layers_spec()
 }
 
 pub open spec(checked) fn inv_at(mem: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize) -> bool
-    decreases X86_NUM_LAYERS - layer
+    decreases mem.num_layers_spec() - layer
 {
     &&& ptr % PAGE_SIZE == 0
     &&& mem.inv()
@@ -596,7 +772,7 @@ pub open spec(checked) fn inv_at(mem: &mem::PageTableMemory, pt: PTDir, layer: n
     &&& pt.region.base == ptr
     &&& pt.region.size == PAGE_SIZE
     &&& mem.region_view(pt.region).len() == pt.entries.len()
-    &&& layer_in_range(layer)
+    &&& layer_in_range(mem, layer)
     &&& pt.entries.len() == X86_NUM_ENTRIES
     &&& directories_obey_invariant_at(mem, pt, layer, ptr)
     &&& directories_have_flags(mem, pt, layer, ptr)
@@ -655,14 +831,14 @@ pub open spec fn ghost_pt_used_regions_rtrancl(mem: &mem::PageTableMemory, pt: P
 }
 
 pub open spec fn interp_at(mem: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize, base_vaddr: nat) -> l1::Directory
-    decreases X86_NUM_LAYERS - layer, X86_NUM_ENTRIES, 2nat
+    decreases mem.num_layers_spec() - layer, X86_NUM_ENTRIES, 2nat
 {
     decreases_when(inv_at(mem, pt, layer, ptr));
     l1::Directory {
         entries: interp_at_aux(mem, pt, layer, ptr, base_vaddr, seq![]),
         layer: layer,
         base_vaddr,
-        arch: x86_arch_spec,
+        arch: arch(mem),
         // We don't have to check the flags because we know (from the invariant) that all
         // directories have these flags set.
         flags: permissive_flags,
@@ -670,22 +846,24 @@ pub open spec fn interp_at(mem: &mem::PageTableMemory, pt: PTDir, layer: nat, pt
 }
 
 pub open spec fn interp_at_entry(mem: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize, base_vaddr: nat, idx: nat) -> l1::NodeEntry
-    decreases X86_NUM_LAYERS - layer, X86_NUM_ENTRIES - idx, 0nat
+    decreases mem.num_layers_spec() - layer, X86_NUM_ENTRIES - idx, 0nat
 {
     decreases_when(inv_at(mem, pt, layer, ptr));
     match view_at(mem, pt, layer, ptr, idx) {
         GhostPageDirectoryEntry::Directory { addr: dir_addr, .. } => {
-            let entry_base = x86_arch_spec.entry_base(layer, base_vaddr, idx);
+            let entry_base = arch(mem).entry_base(layer, base_vaddr, idx);
             l1::NodeEntry::Directory(interp_at(mem, pt.entries[idx as int].get_Some_0(), layer + 1, dir_addr, entry_base))
         },
-        GhostPageDirectoryEntry::Page { addr, flag_RW, flag_US, flag_XD, .. } =>
+        GhostPageDirectoryEntry::Page { addr, flag_RW, flag_US, flag_PWT, flag_PCD, flag_G, flag_PAT, flag_XD, .. } =>
             l1::NodeEntry::Page(
                 PageTableEntry {
-                    frame: MemRegion { base: addr as nat, size: x86_arch_spec.entry_size(layer) },
+                    frame: MemRegion { base: addr as nat, size: arch(mem).entry_size(layer) },
                     flags: Flags {
                         is_writable:     flag_RW,
                         is_supervisor:   !flag_US,
                         disable_execute: flag_XD,
+                        memory_type:     memory_type(flag_PAT, flag_PCD, flag_PWT),
+                        is_global:       flag_G,
                     },
                 }),
         GhostPageDirectoryEntry::Empty =>
@@ -694,7 +872,7 @@ pub open spec fn interp_at_entry(mem: &mem::PageTableMemory, pt: PTDir, layer: n
 }
 
 pub open spec fn interp_at_aux(mem: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize, base_vaddr: nat, init: Seq<l1::NodeEntry>) -> Seq<l1::NodeEntry>
-    decreases X86_NUM_LAYERS - layer, X86_NUM_ENTRIES - init.len(), 1nat
+    decreases mem.num_layers_spec() - layer, X86_NUM_ENTRIES - init.len(), 1nat
         when inv_at(mem, pt, layer, ptr)
 {
     if init.len() >= X86_NUM_ENTRIES {
@@ -709,6 +887,26 @@ pub open spec fn interp(mem: &mem::PageTableMemory, pt: PTDir) -> l1::Directory
     interp_at(mem, pt, 0, mem.cr3_spec().base, 0)
 }
 
+/// The entry of the page mapping that contains `vaddr`, if there is one.
+pub open spec fn page_entry_at(mem: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize, base_vaddr: nat, vaddr: nat) -> Option<GhostPageDirectoryEntry>
+    decreases mem.num_layers_spec() - layer
+{
+    decreases_when(inv_at(mem, pt, layer, ptr));
+    let idx = arch(mem).index_for_vaddr(layer, base_vaddr, vaddr);
+    match view_at(mem, pt, layer, ptr, idx) {
+        GhostPageDirectoryEntry::Directory { addr: dir_addr, .. } => {
+            let entry_base = arch(mem).entry_base(layer, base_vaddr, idx);
+            page_entry_at(mem, pt.entries[idx as int].get_Some_0(), layer + 1, dir_addr, entry_base, vaddr)
+        },
+        GhostPageDirectoryEntry::Page { .. } => Some(view_at(mem, pt, layer, ptr, idx)),
+        GhostPageDirectoryEntry::Empty       => None,
+    }
+}
+
+pub open spec fn page_entry(mem: &mem::PageTableMemory, pt: PTDir, vaddr: nat) -> Option<GhostPageDirectoryEntry> {
+    page_entry_at(mem, pt, 0, mem.cr3_spec().base, 0, vaddr)
+}
+
 proof fn lemma_inv_at_different_memory(mem1: &mem::PageTableMemory, mem2: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize)
     requires
         inv_at(mem1, pt, layer, ptr),
@@ -716,10 +914,11 @@ proof fn lemma_inv_at_different_memory(mem1: &mem::PageTableMemory, mem2: &mem::
             ==> #[trigger] mem1.region_view(r) === mem2.region_view(r),
         // Some parts of mem2's invariant that we should already know
         mem2.inv(),
+        mem2.num_layers_spec() == mem1.num_layers_spec(),
         mem2.regions().contains(pt.region),
         pt.used_regions.subset_of(mem2.regions()),
     ensures inv_at(mem2, pt, layer, ptr),
-    decreases X86_NUM_LAYERS - layer
+    decreases mem1.num_layers_spec() - layer
 {
     assert forall|i: nat| i < X86_NUM_ENTRIES implies
         view_at(mem2, pt, layer, ptr, i) == view_at(mem1, pt, layer, ptr, i) by { };
@@ -740,11 +939,37 @@ proof fn lemma_inv_at_different_memory(mem1: &mem::PageTableMemory, mem2: &mem::
     };
 }
 
+proof fn lemma_interp_at_same_region_views(mem1: &mem::PageTableMemory, mem2: &mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize, base: nat)
+    requires
+        inv_at(mem1, pt, layer, ptr),
+        forall|r: MemRegion| #[trigger] mem1.region_view(r) === mem2.region_view(r),
+        mem2.regions() === mem1.regions(),
+        mem2.phys_mem_ref_as_usize_spec() == mem1.phys_mem_ref_as_usize_spec(),
+        mem2.num_layers_spec() == mem1.num_layers_spec(),
+    ensures
+        inv_at(mem2, pt, layer, ptr),
+        interp_at(mem2, pt, layer, ptr, base) === interp_at(mem1, pt, layer, ptr, base),
+{
+    lemma_inv_at_different_memory(mem1, mem2, pt, layer, ptr);
+    lemma_interp_at_aux_facts(mem1, pt, layer, ptr, base, seq![]);
+    lemma_interp_at_aux_facts(mem2, pt, layer, ptr, base, seq![]);
+    assert forall|i: nat|
+        i < X86_NUM_ENTRIES
+        implies
+        #[trigger] interp_at(mem2, pt, layer, ptr, base).entries[i as int] ==
+        interp_at(mem1, pt, layer, ptr, base).entries[i as int] by
+    {
+        lemma_interp_at_entry_different_memory(mem1, pt, mem2, pt, layer, ptr, base, i);
+    }
+    assert(interp_at(mem2, pt, layer, ptr, base).entries =~= interp_at(mem1, pt, layer, ptr, base).entries);
+}
+
 proof fn lemma_interp_at_entry_different_memory(mem1: &mem::PageTableMemory, pt1: PTDir, mem2: &mem::PageTableMemory, pt2: PTDir, layer: nat, ptr: usize, base: nat, idx: nat)
     requires
         idx < X86_NUM_ENTRIES,
         pt2.region == pt1.region,
         pt2.entries[idx as int] == pt1.entries[idx as int],
+        mem2.num_layers_spec() == mem1.num_layers_spec(),
         inv_at(mem1, pt1, layer, ptr),
         inv_at(mem2, pt2, layer, ptr),
         view_at(mem1, pt1, layer, ptr, idx) == view_at(mem2, pt2, layer, ptr, idx),
@@ -752,11 +977,11 @@ proof fn lemma_interp_at_entry_different_memory(mem1: &mem::PageTableMemory, pt1
             ==> #[trigger] mem1.region_view(r) == mem2.region_view(r)),
     ensures
         interp_at_entry(mem1, pt1, layer, ptr, base, idx) == interp_at_entry(mem2, pt2, layer, ptr, base, idx),
-    decreases X86_NUM_LAYERS - layer
+    decreases mem1.num_layers_spec() - layer
 {
     match view_at(mem1, pt1, layer, ptr, idx) {
         GhostPageDirectoryEntry::Directory { addr: dir_addr, .. } => {
-            let e_base = x86_arch_spec.entry_base(layer, base, idx);
+            let e_base = arch(mem1).entry_base(layer, base, idx);
             let dir_pt = pt1.entries[idx as int].get_Some_0();
             assert(directories_obey_invariant_at(mem1, pt1, layer, ptr));
             assert(directories_obey_invariant_at(mem2, pt2, layer, ptr));
@@ -784,9 +1009,9 @@ pub proof fn lemma_interp_at_facts(mem: &mem::PageTableMemory, pt: PTDir, layer:
         interp_at(mem, pt, layer, ptr, base_vaddr).inv(),
     ensures
         interp_at(mem, pt, layer, ptr, base_vaddr).base_vaddr     == base_vaddr,
-        interp_at(mem, pt, layer, ptr, base_vaddr).upper_vaddr()  == x86_arch_spec.upper_vaddr(layer, base_vaddr),
+        interp_at(mem, pt, layer, ptr, base_vaddr).upper_vaddr()  == arch(mem).upper_vaddr(layer, base_vaddr),
         interp_at(mem, pt, layer, ptr, base_vaddr).interp().lower == base_vaddr,
-        interp_at(mem, pt, layer, ptr, base_vaddr).interp().upper == x86_arch_spec.upper_vaddr(layer, base_vaddr),
+        interp_at(mem, pt, layer, ptr, base_vaddr).interp().upper == arch(mem).upper_vaddr(layer, base_vaddr),
         ({ let res = interp_at(mem, pt, layer, ptr, base_vaddr);
            forall|j: nat| j < res.entries.len() ==> res.entries[j as int] === #[trigger] interp_at_entry(mem, pt, layer, ptr, base_vaddr, j)
         }),
@@ -812,7 +1037,7 @@ pub proof fn lemma_interp_at_facts_entries(mem: &mem::PageTableMemory, pt: PTDir
             match view_at(mem, pt, layer, ptr, i) {
                 GhostPageDirectoryEntry::Directory { addr: dir_addr, .. }  => {
                     &&& res.entries[i as int].is_Directory()
-                    &&& res.entries[i as int].get_Directory_0() == interp_at(mem, pt.entries[i as int].get_Some_0(), (layer + 1) as nat, dir_addr, x86_arch_spec.entry_base(layer, base_vaddr, i))
+                    &&& res.entries[i as int].get_Directory_0() == interp_at(mem, pt.entries[i as int].get_Some_0(), (layer + 1) as nat, dir_addr, arch(mem).entry_base(layer, base_vaddr, i))
                 },
                 GhostPageDirectoryEntry::Page { addr, .. } => res.entries[i as int].is_Page() && res.entries[i as int].get_Page_0().frame.base == addr,
                 GhostPageDirectoryEntry::Empty             => res.entries[i as int].is_Empty(),
@@ -831,14 +1056,14 @@ proof fn lemma_interp_at_aux_facts(mem: &mem::PageTableMemory, pt: PTDir, layer:
                 match view_at(mem, pt, layer, ptr, j) {
                     GhostPageDirectoryEntry::Directory { addr: dir_addr, .. }  => {
                         &&& res[j as int].is_Directory()
-                        &&& res[j as int].get_Directory_0() == interp_at(mem, pt.entries[j as int].get_Some_0(), (layer + 1) as nat, dir_addr, x86_arch_spec.entry_base(layer, base_vaddr, j))
+                        &&& res[j as int].get_Directory_0() == interp_at(mem, pt.entries[j as int].get_Some_0(), (layer + 1) as nat, dir_addr, arch(mem).entry_base(layer, base_vaddr, j))
                     },
                     GhostPageDirectoryEntry::Page { addr, .. } => res[j as int].is_Page() && res[j as int].get_Page_0().frame.base == addr,
                     GhostPageDirectoryEntry::Empty             => res[j as int].is_Empty(),
                 })
             &&& (forall|j: nat| init.len() <= j && j < res.len() ==> res[j as int] == #[trigger] interp_at_entry(mem, pt, layer, ptr, base_vaddr, j))
         }),
-    decreases X86_NUM_LAYERS - layer, X86_NUM_ENTRIES - init.len(), 0nat
+    decreases mem.num_layers_spec() - layer, X86_NUM_ENTRIES - init.len(), 0nat
 {
     if init.len() >= X86_NUM_ENTRIES as nat {
     } else {
@@ -853,17 +1078,20 @@ fn resolve_aux(mem: &mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usize
         inv_at(mem, pt, layer as nat, ptr),
         interp_at(mem, pt, layer as nat, ptr, base as nat).inv(),
         interp_at(mem, pt, layer as nat, ptr, base as nat).interp().accepted_resolve(vaddr as nat),
-        base <= vaddr < MAX_BASE,
+        base <= vaddr < MAX_LA57_BASE,
     ensures
         // Refinement of l1
         result_map_ok(res, |v: (usize, PageTableEntryExec)| (v.0 as nat, v.1@)) === interp_at(mem, pt, layer as nat, ptr, base as nat).resolve(vaddr as nat),
         // Refinement of l0
         result_map_ok(res, |v: (usize, PageTableEntryExec)| (v.0 as nat, v.1@)) === interp_at(mem, pt, layer as nat, ptr, base as nat).interp().resolve(vaddr as nat),
-    // decreases X86_NUM_LAYERS - layer
+    // decreases mem.num_layers_spec() - layer
 {
-    proof { lemma_interp_at_facts(mem, pt, layer as nat, ptr, base as nat); }
-    let idx: usize = x86_arch_exec().index_for_vaddr(layer, base, vaddr);
-    proof { indexing::lemma_index_from_base_and_addr(base as nat, vaddr as nat, x86_arch_spec.entry_size(layer as nat), X86_NUM_ENTRIES as nat); }
+    proof {
+        lemma_interp_at_facts(mem, pt, layer as nat, ptr, base as nat);
+        lemma_arch_facts(mem);
+    }
+    let idx: usize = arch_exec(mem).index_for_vaddr(layer, base, vaddr);
+    proof { indexing::lemma_index_from_base_and_addr(base as nat, vaddr as nat, arch(mem).entry_size(layer as nat), X86_NUM_ENTRIES as nat); }
     let entry      = entry_at(mem, Ghost(pt), layer, ptr, idx);
     let interp: Ghost<l1::Directory> = Ghost(interp_at(mem, pt, layer as nat, ptr, base as nat));
     proof {
@@ -871,12 +1099,12 @@ fn resolve_aux(mem: &mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usize
         interp@.lemma_resolve_refines(vaddr as nat);
     }
     if entry.is_mapping() {
-        let entry_base: usize = x86_arch_exec().entry_base(layer, base, idx);
+        let entry_base: usize = arch_exec(mem).entry_base(layer, base, idx);
         proof {
-            indexing::lemma_entry_base_from_index(base as nat, idx as nat, x86_arch_spec.entry_size(layer as nat));
+            indexing::lemma_entry_base_from_index(base as nat, idx as nat, arch(mem).entry_size(layer as nat));
             assert(entry_base <= vaddr);
         }
-        if entry.is_dir(layer) {
+        if entry.is_dir(format_layer_exec(mem, layer)) {
             assert(entry@.is_Directory());
             let dir_addr = entry.address() as usize;
             assert(pt.entries[idx as int].is_Some());
@@ -897,7 +1125,7 @@ fn resolve_aux(mem: &mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usize
             assert(entry@.is_Page());
             assert(interp@.entries[idx as int].is_Page());
             let pte = PageTableEntryExec {
-                frame: MemRegionExec { base: entry.address() as usize, size: x86_arch_exec().entry_size(layer) },
+                frame: MemRegionExec { base: entry.address() as usize, size: arch_exec(mem).entry_size(layer) },
                 flags: entry.flags()
             };
             let res = Ok((entry_base, pte));
@@ -926,7 +1154,7 @@ pub fn resolve(mem: &mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, vaddr: usize
         inv(mem, pt),
         interp(mem, pt).inv(),
         interp(mem, pt).interp().accepted_resolve(vaddr as nat),
-        vaddr < MAX_BASE,
+        vaddr < MAX_LA57_BASE,
     ensures
         // Refinement of l1
         result_map_ok(res, |v: (usize, PageTableEntryExec)| (v.0 as nat, v.1@)) === interp(mem, pt).resolve(vaddr as nat),
@@ -939,7 +1167,7 @@ pub fn resolve(mem: &mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, vaddr: usize
 }
 
 pub open spec fn accepted_mapping(vaddr: nat, pte: PageTableEntry) -> bool {
-    // Can't map pages in PML4, i.e. layer 0
+    // Can't map pages in PML4 or PML5, i.e. only 1G, 2M and 4K pages
     &&& x86_arch_spec.contains_entry_size_at_index_atleast(pte.frame.size, 1)
     &&& pte.frame.base <= MAX_PHYADDR
 }
@@ -950,10 +1178,10 @@ fn map_frame_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer:
         inv_at(&*old(mem), pt, layer as nat, ptr),
         interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).inv(),
         old(mem).inv(),
-        old(mem).alloc_available_pages() >= 3 - layer,
+        old(mem).alloc_available_pages() >= old(mem).num_layers_spec() - 1 - layer,
         accepted_mapping(vaddr as nat, pte@),
         interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).accepted_mapping(vaddr as nat, pte@),
-        base <= vaddr < MAX_BASE,
+        base <= vaddr < MAX_LA57_BASE,
     ensures
         match res {
             Ok(resv) => {
@@ -980,23 +1208,30 @@ fn map_frame_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer:
         match res {
             Ok(resv) => {
                 let (pt_res, new_regions) = resv@;
-                Ok(interp_at(mem, pt_res, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).map_frame(vaddr as nat, pte@)
+                &&& Ok(interp_at(mem, pt_res, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).map_frame(vaddr as nat, pte@)
+                // We allocate one page for each directory the mapping adds
+                &&& mem.alloc_available_pages() + interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).map_frame_num_new_dirs(vaddr as nat, pte@)
+                    == old(mem).alloc_available_pages()
             },
             Err(e) =>
                 Err(interp_at(mem, pt, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).map_frame(vaddr as nat, pte@),
         },
         mem.cr3_spec() == old(mem).cr3_spec(),
-    // decreases X86_NUM_LAYERS - layer
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+    // decreases mem.num_layers_spec() - layer
 {
-    proof { lemma_interp_at_facts(mem, pt, layer as nat, ptr, base as nat); }
-    let idx: usize = x86_arch_exec().index_for_vaddr(layer, base, vaddr);
+    proof {
+        lemma_interp_at_facts(mem, pt, layer as nat, ptr, base as nat);
+        lemma_arch_facts(mem);
+    }
+    let idx: usize = arch_exec(mem).index_for_vaddr(layer, base, vaddr);
     proof {
         assert({
-            &&& between(vaddr as nat, x86_arch_spec.entry_base(layer as nat, base as nat, idx as nat), x86_arch_spec.next_entry_base(layer as nat, base as nat, idx as nat))
-            &&& aligned(vaddr as nat, x86_arch_spec.entry_size(layer as nat)) ==> vaddr == x86_arch_spec.entry_base(layer as nat, base as nat, idx as nat)
+            &&& between(vaddr as nat, arch(mem).entry_base(layer as nat, base as nat, idx as nat), arch(mem).next_entry_base(layer as nat, base as nat, idx as nat))
+            &&& aligned(vaddr as nat, arch(mem).entry_size(layer as nat)) ==> vaddr == arch(mem).entry_base(layer as nat, base as nat, idx as nat)
             &&& idx < X86_NUM_ENTRIES }) by
         {
-            let es = x86_arch_spec.entry_size(layer as nat);
+            let es = arch(mem).entry_size(layer as nat);
             assert(aligned(base as nat, es)) by {
                 extra::mod_mult_zero_implies_mod_zero(base as nat, es, X86_NUM_ENTRIES as nat);
             };
@@ -1010,14 +1245,14 @@ fn map_frame_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer:
         interp@.lemma_map_frame_structure_assertions(vaddr as nat, pte@, idx as nat);
         interp@.lemma_map_frame_refines_map_frame(vaddr as nat, pte@);
     }
-    let entry_base: usize = x86_arch_exec().entry_base(layer, base, idx);
+    let entry_base: usize = arch_exec(mem).entry_base(layer, base, idx);
     proof {
-        indexing::lemma_entry_base_from_index(base as nat, idx as nat, x86_arch_spec.entry_size(layer as nat));
+        indexing::lemma_entry_base_from_index(base as nat, idx as nat, arch(mem).entry_size(layer as nat));
         assert(entry_base <= vaddr);
     }
     if entry.is_mapping() {
-        if entry.is_dir(layer) {
-            if x86_arch_exec().entry_size(layer) == pte.frame.size {
+        if entry.is_dir(format_layer_exec(mem, layer)) {
+            if arch_exec(mem).entry_size(layer) == pte.frame.size {
                 assert(Err(interp_at(mem, pt, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).map_frame(vaddr as nat, pte@));
                 Err(())
             } else {
@@ -1214,6 +1449,8 @@ fn map_frame_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer:
                         assert(mem.regions() === old(mem).regions().union(new_regions@));
                         assert(pt_res@.used_regions === pt.used_regions.union(new_regions@));
                         assert(pt_res@.region === pt.region);
+                        assert(interp@.entries[idx as int] === l1::NodeEntry::Directory(interp_at(&*old(mem), dir_pt@, (layer + 1) as nat, dir_addr, entry_base as nat)));
+                        assert(mem.alloc_available_pages() + interp@.map_frame_num_new_dirs(vaddr as nat, pte@) == old(mem).alloc_available_pages());
 
                         Ok(Ghost((pt_res@,new_regions@)))
                     },
@@ -1228,35 +1465,30 @@ fn map_frame_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer:
             Err(())
         }
     } else {
-        if x86_arch_exec().entry_size(layer) == pte.frame.size {
+        if arch_exec(mem).entry_size(layer) == pte.frame.size {
+            let entry_layer = format_layer_exec(mem, layer);
             proof {
-                assert_by_contradiction!(layer > 0, {
-                    let iprime = choose|i: nat| 0 < i && i < X86_NUM_LAYERS && #[trigger] x86_arch_spec.entry_size(i) == pte.frame.size;
-                    assert(x86_arch_spec.entry_size(0) == pte.frame.size);
-                    assert(x86_arch_spec.contains_entry_size_at_index_atleast(pte.frame.size as nat, 1));
-                    assert forall|i: nat|
-                        0 < i < X86_NUM_LAYERS
-                        implies
-                        x86_arch_spec.entry_size(0) >= #[trigger] x86_arch_spec.entry_size(i)
-                    by {
-                        x86_arch_spec.lemma_entry_sizes_increase(0, i);
-                    };
+                lemma_arch_facts(mem);
+                assert(x86_arch_spec.entry_size(1) == L1_ENTRY_SIZE);
+                assert(x86_arch_spec.entry_size(2) == L2_ENTRY_SIZE);
+                assert(x86_arch_spec.entry_size(3) == L3_ENTRY_SIZE);
+                // Pages are at most 1G, so they're never mapped by a layer with the PML4 format
+                assert_by_contradiction!(entry_layer > 0, {
+                    let iprime = choose|i: nat| 1 <= i && i < X86_NUM_LAYERS && #[trigger] x86_arch_spec.entry_size(i) == pte.frame.size;
+                    assert(pte.frame.size <= L1_ENTRY_SIZE);
+                    assert(arch(mem).entry_size(layer as nat) >= L0_ENTRY_SIZE);
                     assert(false);
                 });
                 let frame_base = pte.frame.base as u64;
-                assert(addr_is_zero_padded(layer as nat, frame_base, true)) by {
-                    assert(x86_arch_spec.contains_entry_size_at_index_atleast(pte.frame.size as nat, 1));
-                    assert(x86_arch_spec.entry_size(layer as nat) == pte.frame.size);
+                assert(addr_is_zero_padded(entry_layer as nat, frame_base, true)) by {
+                    assert(x86_arch_spec.entry_size(entry_layer as nat) == pte.frame.size);
                     assert(aligned(pte.frame.base as nat, pte.frame.size as nat));
                     lemma_aligned_addr_mask_facts(frame_base);
-                    if layer == 1 {
-                        assert(x86_arch_spec.entry_size(1) == L1_ENTRY_SIZE);
+                    if entry_layer == 1 {
                         assert(frame_base & MASK_L1_PG_ADDR == frame_base & MASK_ADDR);
-                    } else if layer == 2 {
-                        assert(x86_arch_spec.entry_size(2) == L2_ENTRY_SIZE);
+                    } else if entry_layer == 2 {
                         assert(frame_base & MASK_L2_PG_ADDR == frame_base & MASK_ADDR);
-                    } else if layer == 3 {
-                        assert(x86_arch_spec.entry_size(3) == L3_ENTRY_SIZE);
+                    } else if entry_layer == 3 {
                         assert(frame_base & MASK_L3_PG_ADDR == frame_base & MASK_ADDR);
                     } else {
                         assert(false);
@@ -1266,7 +1498,7 @@ fn map_frame_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer:
                     lemma_aligned_addr_mask_facts(frame_base);
                 };
             }
-            let new_page_entry = PageDirectoryEntry::new_page_entry(layer, pte);
+            let new_page_entry = PageDirectoryEntry::new_page_entry(entry_layer, pte);
             let pwmem: Ghost<mem::PageTableMemory> = Ghost(*mem);
 
             mem.write(ptr, idx, Ghost(pt.region), new_page_entry.entry);
@@ -1331,6 +1563,7 @@ fn map_frame_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer:
             assert(forall|r: MemRegion| !pt.used_regions.contains(r) ==> #[trigger] mem.region_view(r) === old(mem).region_view(r));
             assert(mem.regions().union(set![]) =~= mem.regions());
             assert(pt.used_regions.union(set![]) =~= pt.used_regions);
+            assert(interp@.map_frame_num_new_dirs(vaddr as nat, pte@) == 0);
 
             Ok(Ghost((pt, set![])))
         } else {
@@ -1546,12 +1779,15 @@ fn map_frame_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer:
                             }
                         };
                         assert(forall|r: MemRegion| new_regions@.contains(r) ==> !(#[trigger] pt.used_regions.contains(r)));
+                        assert(interp@.map_frame_num_new_dirs(vaddr as nat, pte@)
+                               == 1 + interp_at(mem_with_empty@, new_dir_pt@, (layer + 1) as nat, new_dir_region.base, entry_base as nat).map_frame_num_new_dirs(vaddr as nat, pte@));
+                        assert(mem.alloc_available_pages() + interp@.map_frame_num_new_dirs(vaddr as nat, pte@) == old(mem).alloc_available_pages());
                     }
                     Ok(Ghost((pt_final@, new_regions@)))
                 },
                 Err(e) => {
                     proof {
-                        indexing::lemma_index_from_base_and_addr(entry_base as nat, vaddr as nat, x86_arch_spec.entry_size((layer + 1) as nat), X86_NUM_ENTRIES as nat);
+                        indexing::lemma_index_from_base_and_addr(entry_base as nat, vaddr as nat, arch(mem).entry_size((layer + 1) as nat), X86_NUM_ENTRIES as nat);
                         assert(false); // We always successfully insert into an empty directory
                     }
                     Err(e)
@@ -1572,7 +1808,7 @@ pub proof fn lemma_zeroed_page_implies_empty_at(mem: &mem::PageTableMemory, pt:
         pt.region.base == ptr,
         ptr == pt.region.base,
         pt.used_regions === set![pt.region],
-        layer_in_range(layer),
+        layer_in_range(mem, layer),
         pt.entries.len() == X86_NUM_ENTRIES,
         forall|i: nat| i < X86_NUM_ENTRIES ==> mem.region_view(pt.region)[i as int] == 0u64,
         forall|i: nat| i < X86_NUM_ENTRIES ==> pt.entries[i as int].is_None(),
@@ -1602,15 +1838,15 @@ proof fn lemma_empty_at_interp_at_aux_equal_l1_empty_dir(mem: &mem::PageTableMem
                 pt.entries[idx as int].get_Some_0(),
                 layer + 1,
                 view_at(mem, pt, layer, ptr, idx).get_Directory_addr(),
-                x86_arch_spec.entry_base(layer, base, idx),
+                arch(mem).entry_base(layer, base, idx),
                 init);
         &&& res.len() === X86_NUM_ENTRIES as nat
         &&& forall|i: nat| i < res.len() ==> res[i as int] === l1::NodeEntry::Empty()
         })
-    decreases X86_NUM_LAYERS - layer, X86_NUM_ENTRIES - init.len(), 0nat
+    decreases mem.num_layers_spec() - layer, X86_NUM_ENTRIES - init.len(), 0nat
 {
     let e_ptr = view_at(mem, pt, layer, ptr, idx).get_Directory_addr();
-    let e_base = x86_arch_spec.entry_base(layer, base, idx);
+    let e_base = arch(mem).entry_base(layer, base, idx);
     let e_pt = pt.entries[idx as int].get_Some_0();
 
     if init.len() >= X86_NUM_ENTRIES as nat {
@@ -1634,7 +1870,7 @@ proof fn lemma_empty_at_interp_at_equal_l1_empty_dir(mem: &mem::PageTableMemory,
                 pt.entries[idx as int].get_Some_0(),
                 layer + 1,
                 view_at(mem, pt, layer, ptr, idx).get_Directory_addr(),
-                x86_arch_spec.entry_base(layer, base, idx));
+                arch(mem).entry_base(layer, base, idx));
         &&& res.entries.len() === X86_NUM_ENTRIES as nat
         &&& forall|i: nat| i < res.entries.len() ==> res.entries[i as int] === l1::NodeEntry::Empty()
         })
@@ -1650,7 +1886,7 @@ proof fn lemma_not_empty_at_implies_interp_at_aux_not_empty(mem: &mem::PageTable
         nonempty_idx < init.len() ==> !init[nonempty_idx as int].is_Empty()
     ensures
         !interp_at_aux(mem, pt, layer, ptr, base, init)[nonempty_idx as int].is_Empty()
-    decreases X86_NUM_LAYERS - layer, X86_NUM_ENTRIES - init.len(), 0nat
+    decreases mem.num_layers_spec() - layer, X86_NUM_ENTRIES - init.len(), 0nat
 {
     if init.len() >= X86_NUM_ENTRIES as nat {
     } else {
@@ -1685,18 +1921,30 @@ pub fn map_frame(mem: &mut mem::PageTableMemory, pt: &mut Ghost<PTDir>, vaddr: u
         inv(&*old(mem), old(pt)@),
         interp(&*old(mem), old(pt)@).inv(),
         old(mem).inv(),
-        old(mem).alloc_available_pages() >= 3,
+        old(mem).alloc_available_pages() >= old(mem).num_layers_spec() - 1,
         accepted_mapping(vaddr as nat, pte@),
         interp(&*old(mem), old(pt)@).accepted_mapping(vaddr as nat, pte@),
-        vaddr < MAX_BASE,
+        vaddr < MAX_LA57_BASE,
     ensures
         inv(mem, pt@),
         interp(mem, pt@).inv(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        // Refinement of l1
+        match res {
+            Ok(_) => Ok(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).map_frame(vaddr as nat, pte@),
+            Err(_) => Err(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).map_frame(vaddr as nat, pte@),
+        },
         // Refinement of l0
         match res {
             Ok(_) => Ok(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().map_frame(vaddr as nat, pte@),
             Err(_) => Err(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().map_frame(vaddr as nat, pte@),
         },
+        // Allocated pages
+        match res {
+            Ok(_) => mem.alloc_available_pages() + interp(&*old(mem), old(pt)@).map_frame_num_new_dirs(vaddr as nat, pte@)
+                == old(mem).alloc_available_pages(),
+            Err(_) => mem.alloc_available_pages() == old(mem).alloc_available_pages(),
+        },
 {
     proof { interp(mem, pt@).lemma_map_frame_refines_map_frame(vaddr as nat, pte@); }
     match map_frame_aux(mem, *pt, 0, mem.cr3().base, 0, vaddr, pte) {
@@ -1714,8 +1962,9 @@ fn is_directory_empty(mem: &mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer
     ensures res === empty_at(mem, pt, layer as nat, ptr)
 {
     assert(directories_obey_invariant_at(mem, pt, layer as nat, ptr));
+    proof { lemma_arch_facts(mem); }
     let mut idx = 0;
-    let num_entries = x86_arch_exec().num_entries(layer);
+    let num_entries = arch_exec(mem).num_entries(layer);
     while idx < num_entries
         invariant
             num_entries == X86_NUM_ENTRIES,
@@ -1740,7 +1989,7 @@ fn insert_empty_directory(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir
         inv_at(&*old(mem), pt, layer as nat, ptr),
         interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).inv(),
         old(mem).alloc_available_pages() > 0,
-        layer < 3,
+        layer + 1 < old(mem).num_layers_spec(),
         idx < 512,
         view_at(&*old(mem), pt, layer as nat, ptr, idx as nat).is_Empty(),
     ensures
@@ -1749,11 +1998,12 @@ fn insert_empty_directory(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir
         mem.regions() == old(mem).regions().insert(res.1@),
         mem.alloc_available_pages() == old(mem).alloc_available_pages() - 1,
         mem.cr3_spec() == old(mem).cr3_spec(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
         forall|i: nat| i < 512 && i != idx ==> view_at(mem, res.0@, layer as nat, ptr, i) == view_at(&*old(mem), res.0@, layer as nat, ptr, i),
         forall|r: MemRegion| r != res.0@.region && r != res.0@.entries[idx as int].get_Some_0().region ==> mem.region_view(r) == old(mem).region_view(r),
         ({ let pt_res = res.0@; let new_dir_region = res.1; let new_dir_entry = res.2;
            let new_dir_pt = pt_res.entries[idx as int].get_Some_0();
-           let entry_base = x86_arch_spec.entry_base(layer as nat, base as nat, idx as nat);
+           let entry_base = arch(mem).entry_base(layer as nat, base as nat, idx as nat);
            let new_dir_interp = interp_at(mem, pt_res.entries[idx as int].get_Some_0(), (layer + 1) as nat, new_dir_region.base, entry_base);
            let interp = interp_at(&*old(mem), pt, layer as nat, ptr, base as nat);
            &&& entry_at_spec(mem, pt_res, layer as nat, ptr, idx as nat) == new_dir_entry
@@ -1780,7 +2030,7 @@ fn insert_empty_directory(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir
     assert(new_dir_ptr_u64 & MASK_DIR_ADDR == new_dir_ptr_u64) by {
         lemma_page_aligned_implies_mask_dir_addr_is_identity();
     };
-    let new_dir_entry = PageDirectoryEntry::new_dir_entry(layer, new_dir_ptr_u64);
+    let new_dir_entry = PageDirectoryEntry::new_dir_entry(format_layer_exec(mem, layer), new_dir_ptr_u64);
     mem.write(ptr, idx, Ghost(pt.region), new_dir_entry.entry);
 
     let pt_res: Ghost<PTDir> = Ghost(
@@ -1836,7 +2086,7 @@ fn insert_empty_directory(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir
         lemma_empty_at_interp_at_equal_l1_empty_dir(mem, pt_res@, layer as nat, ptr, base as nat, idx as nat);
         interp@.lemma_new_empty_dir(idx as nat);
         lemma_interp_at_aux_facts(mem, pt_res@, layer as nat, ptr, base as nat, seq![]);
-        let entry_base = x86_arch_spec.entry_base(layer as nat, base as nat, idx as nat);
+        let entry_base = arch(mem).entry_base(layer as nat, base as nat, idx as nat);
         let new_dir_interp = interp_at(mem, new_dir_pt@, (layer + 1) as nat, new_dir_ptr, entry_base);
         assert(new_dir_interp.entries =~= interp@.new_empty_dir(idx as nat).entries);
         assert(new_dir_interp == interp@.new_empty_dir(idx as nat));
@@ -1852,7 +2102,7 @@ fn unmap_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usi
         interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).inv(),
         old(mem).inv(),
         interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).accepted_unmap(vaddr as nat),
-        base <= vaddr < MAX_BASE,
+        base <= vaddr < MAX_LA57_BASE,
     ensures
         match res {
             Ok(resv) => {
@@ -1881,32 +2131,39 @@ fn unmap_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usi
         match res {
             Ok(resv) => {
                 let (pt_res, removed_regions) = resv@;
-                Ok(interp_at(mem, pt_res, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).unmap(vaddr as nat)
+                &&& Ok(interp_at(mem, pt_res, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).unmap(vaddr as nat)
+                // We free one page for each directory the unmap removes
+                &&& mem.alloc_available_pages()
+                    == old(mem).alloc_available_pages() + interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).unmap_num_removed_dirs(vaddr as nat)
             },
             Err(e) =>
                 Err(interp_at(mem, pt, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).unmap(vaddr as nat),
         },
         mem.cr3_spec() == old(mem).cr3_spec(),
-    // decreases X86_NUM_LAYERS - layer
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+    // decreases mem.num_layers_spec() - layer
 {
-    proof { lemma_interp_at_facts(mem, pt, layer as nat, ptr, base as nat); }
-    let idx: usize = x86_arch_exec().index_for_vaddr(layer, base, vaddr);
-    proof { indexing::lemma_index_from_base_and_addr(base as nat, vaddr as nat, x86_arch_spec.entry_size(layer as nat), X86_NUM_ENTRIES as nat); }
+    proof {
+        lemma_interp_at_facts(mem, pt, layer as nat, ptr, base as nat);
+        lemma_arch_facts(mem);
+    }
+    let idx: usize = arch_exec(mem).index_for_vaddr(layer, base, vaddr);
+    proof { indexing::lemma_index_from_base_and_addr(base as nat, vaddr as nat, arch(mem).entry_size(layer as nat), X86_NUM_ENTRIES as nat); }
     let entry = entry_at(mem, Ghost(pt), layer, ptr, idx);
     let interp: Ghost<l1::Directory> = Ghost(interp_at(mem, pt, layer as nat, ptr, base as nat));
     proof {
         interp@.lemma_unmap_structure_assertions(vaddr as nat, idx as nat);
         interp@.lemma_unmap_refines_unmap(vaddr as nat);
     }
-    let entry_base: usize = x86_arch_exec().entry_base(layer, base, idx);
+    let entry_base: usize = arch_exec(mem).entry_base(layer, base, idx);
     proof {
-        indexing::lemma_entry_base_from_index(base as nat, idx as nat, x86_arch_spec.entry_size(layer as nat));
+        indexing::lemma_entry_base_from_index(base as nat, idx as nat, arch(mem).entry_size(layer as nat));
         assert(entry_base <= vaddr);
     }
     assert(interp_at_entry(mem, pt, layer as nat, ptr, base as nat, idx as nat)
            == interp_at(mem, pt, layer as nat, ptr, base as nat).entries[idx as int]);
     if entry.is_mapping() {
-        if entry.is_dir(layer) {
+        if entry.is_dir(format_layer_exec(mem, layer)) {
             let dir_addr = entry.address() as usize;
             assert(pt.entries[idx as int].is_Some());
             let dir_pt: Ghost<PTDir> = Ghost(pt.entries.index(idx as int).get_Some_0());
@@ -2009,6 +2266,12 @@ fn unmap_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usi
                                     interp_at(mem, pt_res@, layer as nat, ptr, base as nat).entries =~=
                                     interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).unmap(vaddr as nat).get_Ok_0().entries);
                             };
+
+                            lemma_empty_at_implies_interp_at_empty(mem_with_empty@, dir_pt_res@, (layer + 1) as nat, dir_addr, entry_base as nat);
+                            assert(interp@.entries[idx as int] === l1::NodeEntry::Directory(interp_at(&*old(mem), dir_pt@, (layer + 1) as nat, dir_addr, entry_base as nat)));
+                            assert(interp@.unmap_num_removed_dirs(vaddr as nat)
+                                   == interp_at(&*old(mem), dir_pt@, (layer + 1) as nat, dir_addr, entry_base as nat).unmap_num_removed_dirs(vaddr as nat) + 1);
+                            assert(mem.alloc_available_pages() == old(mem).alloc_available_pages() + interp@.unmap_num_removed_dirs(vaddr as nat));
                         }
                         Ok(res)
                     } else {
@@ -2084,6 +2347,12 @@ fn unmap_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usi
                                     interp_at(mem, pt_res@, layer as nat, ptr, base as nat).entries =~=
                                     interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).unmap(vaddr as nat).get_Ok_0().entries);
                             };
+
+                            lemma_not_empty_at_implies_interp_at_not_empty(mem, dir_pt_res@, (layer + 1) as nat, dir_addr, entry_base as nat);
+                            assert(interp@.entries[idx as int] === l1::NodeEntry::Directory(interp_at(&*old(mem), dir_pt@, (layer + 1) as nat, dir_addr, entry_base as nat)));
+                            assert(interp@.unmap_num_removed_dirs(vaddr as nat)
+                                   == interp_at(&*old(mem), dir_pt@, (layer + 1) as nat, dir_addr, entry_base as nat).unmap_num_removed_dirs(vaddr as nat));
+                            assert(mem.alloc_available_pages() == old(mem).alloc_available_pages() + interp@.unmap_num_removed_dirs(vaddr as nat));
                         }
                         Ok(res)
                     }
@@ -2096,7 +2365,7 @@ fn unmap_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usi
                 },
             }
         } else {
-            if aligned_exec(vaddr, x86_arch_exec().entry_size(layer)) {
+            if aligned_exec(vaddr, arch_exec(mem).entry_size(layer)) {
                 mem.write(ptr, idx, Ghost(pt.region), 0u64);
 
                 let removed_regions: Ghost<Set<MemRegion>> = Ghost(Set::empty());
@@ -2156,6 +2425,8 @@ fn unmap_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usi
                             interp_at(mem, pt, layer as nat, ptr, base as nat).entries =~=
                             interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).unmap(vaddr as nat).get_Ok_0().entries);
                     };
+
+                    assert(interp@.unmap_num_removed_dirs(vaddr as nat) == 0);
                 }
                 Ok(res)
 
@@ -2178,15 +2449,27 @@ pub fn unmap(mem: &mut mem::PageTableMemory, pt: &mut Ghost<PTDir>, vaddr: usize
         interp(&*old(mem), old(pt)@).inv(),
         old(mem).inv(),
         interp(&*old(mem), old(pt)@).accepted_unmap(vaddr as nat),
-        vaddr < MAX_BASE,
+        vaddr < MAX_LA57_BASE,
     ensures
         inv(mem, pt@),
         interp(mem, pt@).inv(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        // Refinement of l1
+        match res {
+            Ok(_)  => Ok(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).unmap(vaddr as nat),
+            Err(_) => Err(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).unmap(vaddr as nat),
+        },
         // Refinement of l0
         match res {
             Ok(_)  => Ok(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().unmap(vaddr as nat),
             Err(_) => Err(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().unmap(vaddr as nat),
         },
+        // Freed pages
+        match res {
+            Ok(_)  => mem.alloc_available_pages()
+                == old(mem).alloc_available_pages() + interp(&*old(mem), old(pt)@).unmap_num_removed_dirs(vaddr as nat),
+            Err(_) => mem.alloc_available_pages() == old(mem).alloc_available_pages(),
+        },
 {
     proof { interp(mem, pt@).lemma_unmap_refines_unmap(vaddr as nat); }
     match unmap_aux(mem, *pt, 0, mem.cr3().base, 0, vaddr) {
@@ -2199,6 +2482,1583 @@ pub fn unmap(mem: &mut mem::PageTableMemory, pt: &mut Ghost<PTDir>, vaddr: usize
     }
 }
 
+fn protect_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usize, ptr: usize, base: usize, vaddr: usize, flags: Flags)
+    -> (res: Result<(),()>)
+    requires
+        inv_at(&*old(mem), pt, layer as nat, ptr),
+        interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).inv(),
+        old(mem).inv(),
+        interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).accepted_protect(vaddr as nat),
+        base <= vaddr < MAX_LA57_BASE,
+    ensures
+        // Protect only rewrites existing page entries, so the ghost structure is unchanged
+        inv_at(mem, pt, layer as nat, ptr),
+        mem.regions() === old(mem).regions(),
+        forall|r: MemRegion| !pt.used_regions.contains(r) ==> #[trigger] mem.region_view(r) === old(mem).region_view(r),
+        // The accessed and dirty flags of the rewritten entry are kept
+        forall|r: MemRegion| #[trigger] mem.ad_view(r) === old(mem).ad_view(r),
+        mem.alloc_available_pages() == old(mem).alloc_available_pages(),
+        res.is_Err() ==> mem === old(mem),
+        // Refinement of l1
+        match res {
+            Ok(_)  => Ok(interp_at(mem, pt, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags),
+            Err(_) => Err(interp_at(mem, pt, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags),
+        },
+        mem.cr3_spec() == old(mem).cr3_spec(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        mem.phys_mem_ref_as_usize_spec() == old(mem).phys_mem_ref_as_usize_spec(),
+    // decreases mem.num_layers_spec() - layer
+{
+    proof {
+        lemma_interp_at_facts(mem, pt, layer as nat, ptr, base as nat);
+        lemma_arch_facts(mem);
+    }
+    let idx: usize = arch_exec(mem).index_for_vaddr(layer, base, vaddr);
+    proof { indexing::lemma_index_from_base_and_addr(base as nat, vaddr as nat, arch(mem).entry_size(layer as nat), X86_NUM_ENTRIES as nat); }
+    let entry = entry_at(mem, Ghost(pt), layer, ptr, idx);
+    let interp: Ghost<l1::Directory> = Ghost(interp_at(mem, pt, layer as nat, ptr, base as nat));
+    proof {
+        interp@.lemma_protect_structure_assertions(vaddr as nat, flags, idx as nat);
+        interp@.lemma_protect_refines_protect(vaddr as nat, flags);
+    }
+    let entry_base: usize = arch_exec(mem).entry_base(layer, base, idx);
+    proof {
+        indexing::lemma_entry_base_from_index(base as nat, idx as nat, arch(mem).entry_size(layer as nat));
+        assert(entry_base <= vaddr);
+    }
+    assert(interp_at_entry(mem, pt, layer as nat, ptr, base as nat, idx as nat)
+           == interp_at(mem, pt, layer as nat, ptr, base as nat).entries[idx as int]);
+    if entry.is_mapping() {
+        if entry.is_dir(format_layer_exec(mem, layer)) {
+            let dir_addr = entry.address() as usize;
+            assert(pt.entries[idx as int].is_Some());
+            let dir_pt: Ghost<PTDir> = Ghost(pt.entries.index(idx as int).get_Some_0());
+            assert(directories_obey_invariant_at(mem, pt, layer as nat, ptr));
+            assert(forall|r: MemRegion| #![auto] pt.entries[idx as int].get_Some_0().used_regions.contains(r) ==> pt.used_regions.contains(r));
+            match protect_aux(mem, dir_pt, layer + 1, dir_addr, entry_base, vaddr, flags) {
+                Ok(()) => {
+                    proof {
+                        assert(!dir_pt@.used_regions.contains(pt.region));
+                        assert(mem.region_view(pt.region) === old(mem).region_view(pt.region));
+                        assert(forall|i: nat| i < X86_NUM_ENTRIES ==> entry_at_spec(mem, pt, layer as nat, ptr, i) == entry_at_spec(&*old(mem), pt, layer as nat, ptr, i));
+                        assert(forall|i: nat| i < X86_NUM_ENTRIES ==> view_at(mem, pt, layer as nat, ptr, i) == view_at(&*old(mem), pt, layer as nat, ptr, i));
+
+                        assert(directories_obey_invariant_at(mem, pt, layer as nat, ptr)) by {
+                            assert forall|i: nat| i < X86_NUM_ENTRIES implies {
+                                let entry = #[trigger] view_at(mem, pt, layer as nat, ptr, i);
+                                entry.is_Directory() ==> {
+                                    &&& inv_at(mem, pt.entries[i as int].get_Some_0(), layer as nat + 1, entry.get_Directory_addr())
+                                }
+                            } by {
+                                let entry = view_at(mem, pt, layer as nat, ptr, i);
+                                if i != idx && entry.is_Directory() {
+                                    assert(directories_obey_invariant_at(&*old(mem), pt, layer as nat, ptr));
+                                    lemma_inv_at_different_memory(&*old(mem), mem, pt.entries[i as int].get_Some_0(), (layer + 1) as nat, entry.get_Directory_addr());
+                                }
+                            };
+                        };
+                        assert(inv_at(mem, pt, layer as nat, ptr));
+
+                        // Refinement
+                        assert(Ok(interp_at(mem, pt, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags)) by {
+                            lemma_interp_at_aux_facts(mem, pt, layer as nat, ptr, base as nat, seq![]);
+                            assert forall|i: nat|
+                                i < X86_NUM_ENTRIES
+                                implies
+                                #[trigger] interp_at(mem, pt, layer as nat, ptr, base as nat).entries[i as int] ==
+                                interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags).get_Ok_0().entries[i as int] by
+                            {
+                                if i == idx {
+                                    assert(interp_at(mem, pt, layer as nat, ptr, base as nat).entries[idx as int]
+                                           == l1::NodeEntry::Directory(interp_at(mem, dir_pt@, (layer + 1) as nat, dir_addr, entry_base as nat)));
+                                    assert(interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).entries[idx as int] == interp_at_entry(&*old(mem), pt, layer as nat, ptr, base as nat, idx as nat));
+                                } else {
+                                    lemma_interp_at_entry_different_memory(&*old(mem), pt, mem, pt, layer as nat, ptr, base as nat, i);
+                                }
+                            }
+                            assert(
+                                interp_at(mem, pt, layer as nat, ptr, base as nat).entries =~=
+                                interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags).get_Ok_0().entries);
+                        };
+                    }
+                    Ok(())
+                },
+                Err(e) => {
+                    assert(mem === old(mem));
+                    assert(Err(interp_at(mem, pt, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags));
+                    Err(e)
+                },
+            }
+        } else {
+            if aligned_exec(vaddr, arch_exec(mem).entry_size(layer)) {
+                let frame_base = entry.address();
+                let entry_layer = format_layer_exec(mem, layer);
+                proof {
+                    assert(0 < entry_layer);
+                    assert(forall|a: u64, m: u64| (a & m) & m == a & m) by (bit_vector);
+                    assert(addr_is_zero_padded(entry_layer as nat, frame_base, true));
+                    assert(frame_base & MASK_ADDR == frame_base);
+                }
+                let pte = PageTableEntryExec {
+                    frame: MemRegionExec { base: frame_base as usize, size: arch_exec(mem).entry_size(layer) },
+                    flags,
+                };
+                let new_page_entry = PageDirectoryEntry::new_page_entry(entry_layer, pte);
+                let pwmem: Ghost<mem::PageTableMemory> = Ghost(*mem);
+
+                mem.write_keep_ad(ptr, idx, Ghost(pt.region), new_page_entry.entry);
+
+                assert(inv_at(mem, pt, layer as nat, ptr)) by {
+                    assert(mem.region_view(pt.region) === pwmem@.region_view(pt.region).update(idx as int, new_page_entry.entry));
+
+                    assert forall|i: nat| i < X86_NUM_ENTRIES implies
+                        #[trigger] view_at(mem, pt, layer as nat, ptr, i) == if i == idx { new_page_entry@ } else { view_at(&*old(mem), pt, layer as nat, ptr, i) } by { };
+                    assert forall|i: nat| i < X86_NUM_ENTRIES && i != idx implies
+                        entry_at_spec(mem, pt, layer as nat, ptr, i) == entry_at_spec(&*old(mem), pt, layer as nat, ptr, i) by { };
+
+                    assert(directories_obey_invariant_at(mem, pt, layer as nat, ptr)) by {
+                        assert forall|i: nat| i < X86_NUM_ENTRIES implies {
+                            let entry = #[trigger] view_at(mem, pt, layer as nat, ptr, i);
+                            entry.is_Directory() ==> {
+                                &&& inv_at(mem, pt.entries[i as int].get_Some_0(), (layer + 1) as nat, entry.get_Directory_addr())
+                            }
+                        } by {
+                            let entry = view_at(mem, pt, layer as nat, ptr, i);
+                            if i != idx {
+                                assert(directories_obey_invariant_at(&*old(mem), pt, layer as nat, ptr));
+                                if entry.is_Directory() {
+                                    lemma_inv_at_different_memory(&*old(mem), mem, pt.entries[i as int].get_Some_0(), (layer + 1) as nat, entry.get_Directory_addr());
+                                }
+                            }
+                        };
+                    };
+                }
+
+                assert(Ok(interp_at(mem, pt, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags)) by {
+                    lemma_interp_at_aux_facts(mem, pt, layer as nat, ptr, base as nat, seq![]);
+                    assert(interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags).is_Ok());
+
+                    assert forall|i: nat|
+                        i < X86_NUM_ENTRIES && i != idx
+                        implies
+                            interp_at(mem, pt, layer as nat, ptr, base as nat).entries[i as int]
+                            === #[trigger] interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags).get_Ok_0().entries[i as int] by
+                    {
+                        assert(interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).entries[i as int] === interp_at_entry(&*old(mem), pt, layer as nat, ptr, base as nat, i));
+                        lemma_interp_at_entry_different_memory(&*old(mem), pt, mem, pt, layer as nat, ptr, base as nat, i);
+                    };
+
+                    let new_interp = interp_at(mem, pt, layer as nat, ptr, base as nat);
+                    assert(new_interp.entries[idx as int] === interp_at_entry(mem, pt, layer as nat, ptr, base as nat, idx as nat));
+                    assert(view_at(mem, pt, layer as nat, ptr, idx as nat) === new_page_entry@);
+                    assert(interp@.entries[idx as int].get_Page_0().frame === pte@.frame);
+                    assert(interp_at_entry(mem, pt, layer as nat, ptr, base as nat, idx as nat) === l1::NodeEntry::Page(pte@));
+
+                    assert(new_interp.entries[idx as int] == interp@.protect(vaddr as nat, flags).get_Ok_0().entries[idx as int]);
+                    assert(new_interp.entries =~= interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags).get_Ok_0().entries);
+                };
+
+                // posts
+                assert(forall|r: MemRegion| !pt.used_regions.contains(r) ==> #[trigger] mem.region_view(r) === old(mem).region_view(r));
+                Ok(())
+            } else {
+                assert(mem === old(mem));
+                assert(Err(interp_at(mem, pt, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags));
+                Err(())
+            }
+        }
+    } else {
+        assert(mem === old(mem));
+        assert(Err(interp_at(mem, pt, layer as nat, ptr, base as nat)) === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).protect(vaddr as nat, flags));
+        Err(())
+    }
+}
+
+/// Changes the flags of the page mapped at `vaddr` by rewriting its entry in place. Unlike
+/// unmapping and mapping it again, the mapping never disappears in between, and the accessed and
+/// dirty flags of the entry are kept.
+pub fn protect(mem: &mut mem::PageTableMemory, pt: &mut Ghost<PTDir>, vaddr: usize, flags: Flags) -> (res: Result<(),()>)
+    requires
+        inv(&*old(mem), old(pt)@),
+        interp(&*old(mem), old(pt)@).inv(),
+        old(mem).inv(),
+        interp(&*old(mem), old(pt)@).accepted_protect(vaddr as nat),
+        vaddr < MAX_LA57_BASE,
+    ensures
+        inv(mem, pt@),
+        interp(mem, pt@).inv(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        pt@ === old(pt)@,
+        forall|r: MemRegion| #[trigger] mem.ad_view(r) === old(mem).ad_view(r),
+        // Refinement of l1
+        match res {
+            Ok(_)  => Ok(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).protect(vaddr as nat, flags),
+            Err(_) => Err(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).protect(vaddr as nat, flags),
+        },
+        // Refinement of l0
+        match res {
+            Ok(_)  => Ok(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().protect(vaddr as nat, flags),
+            Err(_) => Err(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().protect(vaddr as nat, flags),
+        },
+{
+    proof { interp(mem, pt@).lemma_protect_refines_protect(vaddr as nat, flags); }
+    match protect_aux(mem, *pt, 0, mem.cr3().base, 0, vaddr, flags) {
+        Ok(()) => {
+            proof { interp(&*old(mem), pt@).lemma_protect_preserves_inv(vaddr as nat, flags); }
+            Ok(())
+        },
+        Err(e) => Err(()),
+    }
+}
+
+fn fetch_clear_ad_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usize, ptr: usize, base: usize, vaddr: usize, bits: u64) -> (res: Result<u64,()>)
+    requires
+        inv_at(&*old(mem), pt, layer as nat, ptr),
+        interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).inv(),
+        interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).interp().accepted_resolve(vaddr as nat),
+        base <= vaddr < MAX_LA57_BASE,
+        bits & !mem::MASK_AD_FLAGS == 0,
+    ensures
+        forall|r: MemRegion| #[trigger] mem.region_view(r) === old(mem).region_view(r),
+        mem.regions() === old(mem).regions(),
+        mem.alloc_available_pages() == old(mem).alloc_available_pages(),
+        mem.cr3_spec() == old(mem).cr3_spec(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        mem.phys_mem_ref_as_usize_spec() == old(mem).phys_mem_ref_as_usize_spec(),
+        res.is_Err() ==> mem === old(mem),
+        res.is_Ok() == page_entry_at(&*old(mem), pt, layer as nat, ptr, base as nat, vaddr as nat).is_Some(),
+    // decreases mem.num_layers_spec() - layer
+{
+    proof {
+        lemma_interp_at_facts(mem, pt, layer as nat, ptr, base as nat);
+        lemma_arch_facts(mem);
+    }
+    let idx: usize = arch_exec(mem).index_for_vaddr(layer, base, vaddr);
+    proof { indexing::lemma_index_from_base_and_addr(base as nat, vaddr as nat, arch(mem).entry_size(layer as nat), X86_NUM_ENTRIES as nat); }
+    let entry = entry_at(mem, Ghost(pt), layer, ptr, idx);
+    let interp: Ghost<l1::Directory> = Ghost(interp_at(mem, pt, layer as nat, ptr, base as nat));
+    proof { interp@.lemma_resolve_structure_assertions(vaddr as nat, idx as nat); }
+    if entry.is_mapping() {
+        if entry.is_dir(format_layer_exec(mem, layer)) {
+            let entry_base: usize = arch_exec(mem).entry_base(layer, base, idx);
+            proof {
+                indexing::lemma_entry_base_from_index(base as nat, idx as nat, arch(mem).entry_size(layer as nat));
+                assert(entry_base <= vaddr);
+            }
+            let dir_addr = entry.address() as usize;
+            assert(pt.entries[idx as int].is_Some());
+            let dir_pt: Ghost<PTDir> = Ghost(pt.entries.index(idx as int).get_Some_0());
+            assert(directories_obey_invariant_at(mem, pt, layer as nat, ptr));
+            proof {
+                assert(interp@.entries[idx as int].is_Directory());
+                assert(l1::NodeEntry::Directory(interp_at(mem, dir_pt@, (layer + 1) as nat, dir_addr, entry_base as nat)) === interp@.entries[idx as int]);
+            }
+            fetch_clear_ad_aux(mem, dir_pt, layer + 1, dir_addr, entry_base, vaddr, bits)
+        } else {
+            Ok(mem.fetch_clear_ad(ptr, idx, Ghost(pt.region), bits))
+        }
+    } else {
+        Err(())
+    }
+}
+
+/// Reads the entry of the page mapping that contains `vaddr` and atomically clears the flags
+/// `bits` in it. Accessed and dirty flags the MMU sets concurrently are never lost, and the
+/// mappings don't change.
+fn fetch_clear_ad(mem: &mut mem::PageTableMemory, pt: &mut Ghost<PTDir>, vaddr: usize, bits: u64) -> (res: Result<u64,()>)
+    requires
+        inv(&*old(mem), old(pt)@),
+        interp(&*old(mem), old(pt)@).inv(),
+        interp(&*old(mem), old(pt)@).interp().accepted_resolve(vaddr as nat),
+        vaddr < MAX_LA57_BASE,
+        bits & !mem::MASK_AD_FLAGS == 0,
+    ensures
+        inv(mem, pt@),
+        interp(mem, pt@).inv(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        pt@ === old(pt)@,
+        interp(mem, pt@) === interp(&*old(mem), old(pt)@),
+        res.is_Ok() == page_entry(&*old(mem), old(pt)@, vaddr as nat).is_Some(),
+{
+    let res = fetch_clear_ad_aux(mem, *pt, 0, mem.cr3().base, 0, vaddr, bits);
+    proof { lemma_interp_at_same_region_views(&*old(mem), mem, pt@, 0, mem.cr3_spec().base, 0); }
+    res
+}
+
+/// Returns the accessed flag of the page mapping that contains `vaddr`, or an error if there is
+/// no such mapping.
+pub fn query_accessed(mem: &mut mem::PageTableMemory, pt: &mut Ghost<PTDir>, vaddr: usize) -> (res: Result<bool,()>)
+    requires
+        inv(&*old(mem), old(pt)@),
+        interp(&*old(mem), old(pt)@).inv(),
+        interp(&*old(mem), old(pt)@).interp().accepted_resolve(vaddr as nat),
+        vaddr < MAX_LA57_BASE,
+    ensures
+        inv(mem, pt@),
+        interp(mem, pt@).inv(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        pt@ === old(pt)@,
+        interp(mem, pt@) === interp(&*old(mem), old(pt)@),
+        res.is_Ok() == page_entry(&*old(mem), old(pt)@, vaddr as nat).is_Some(),
+{
+    assert(0u64 & !mem::MASK_AD_FLAGS == 0) by (bit_vector);
+    match fetch_clear_ad(mem, pt, vaddr, 0) {
+        Ok(entry) => Ok(entry & MASK_FLAG_A == MASK_FLAG_A),
+        Err(e)    => Err(e),
+    }
+}
+
+/// Returns the dirty flag of the page mapping that contains `vaddr` and clears it, or an error if
+/// there is no such mapping. A write by another core either shows up in the result or sets the
+/// flag again. The caller still has to shoot down the TLB entries for `vaddr` afterwards, since a
+/// core that cached the translation with the dirty flag set doesn't set it again on later writes
+/// (see the ClearDirty operation in `spec_t::os`).
+pub fn query_and_clear_dirty(mem: &mut mem::PageTableMemory, pt: &mut Ghost<PTDir>, vaddr: usize) -> (res: Result<bool,()>)
+    requires
+        inv(&*old(mem), old(pt)@),
+        interp(&*old(mem), old(pt)@).inv(),
+        interp(&*old(mem), old(pt)@).interp().accepted_resolve(vaddr as nat),
+        vaddr < MAX_LA57_BASE,
+    ensures
+        inv(mem, pt@),
+        interp(mem, pt@).inv(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        pt@ === old(pt)@,
+        // Clearing the dirty flag doesn't change the mappings
+        interp(mem, pt@) === interp(&*old(mem), old(pt)@),
+        res.is_Ok() == page_entry(&*old(mem), old(pt)@, vaddr as nat).is_Some(),
+{
+    assert(MASK_PG_FLAG_D & !mem::MASK_AD_FLAGS == 0) by (bit_vector);
+    match fetch_clear_ad(mem, pt, vaddr, MASK_PG_FLAG_D) {
+        Ok(entry) => Ok(entry & MASK_PG_FLAG_D == MASK_PG_FLAG_D),
+        Err(e)    => Err(e),
+    }
+}
+
+pub open spec fn ptes_view(ptes: Seq<PageTableEntryExec>) -> Seq<PageTableEntry> {
+    ptes.map_values(|pte: PageTableEntryExec| pte@)
+}
+
+pub open spec fn accepted_range(d: l1::Directory, vaddr: nat, ptes: Seq<PageTableEntry>) -> bool {
+    &&& vaddr + range_size(ptes) <= MAX_BASE
+    &&& forall|i: int| 0 <= i < ptes.len() ==> {
+        &&& #[trigger] accepted_mapping(range_entry_base(vaddr, ptes, i), ptes[i])
+        &&& d.accepted_mapping(range_entry_base(vaddr, ptes, i), ptes[i])
+    }
+}
+
+/// The entries that `map_range` maps back to back. They are read one at a time while walking the
+/// page table, so they don't have to be collected in memory first.
+pub trait RangeEntries {
+    spec fn spec_entries(&self) -> Seq<PageTableEntry>;
+
+    spec fn wf(&self) -> bool;
+
+    /// Whether there is an entry `i`, where `offset` is the size of the entries before it.
+    fn has_entry(&self, i: usize, offset: usize) -> (res: bool)
+        requires
+            self.wf(),
+            i <= self.spec_entries().len(),
+            offset == range_size(self.spec_entries().take(i as int)),
+        ensures
+            res == (i < self.spec_entries().len()),
+            res ==> i < usize::MAX;
+
+    /// Returns the entry `i`, where `offset` is the size of the entries before it.
+    fn entry(&self, i: usize, offset: usize) -> (res: PageTableEntryExec)
+        requires
+            self.wf(),
+            i < self.spec_entries().len(),
+            offset == range_size(self.spec_entries().take(i as int)),
+        ensures
+            res@ === self.spec_entries()[i as int];
+}
+
+impl RangeEntries for [PageTableEntryExec] {
+    open spec fn spec_entries(&self) -> Seq<PageTableEntry> {
+        ptes_view(self@)
+    }
+
+    open spec fn wf(&self) -> bool {
+        true
+    }
+
+    fn has_entry(&self, i: usize, offset: usize) -> (res: bool) {
+        i < self.len()
+    }
+
+    fn entry(&self, i: usize, offset: usize) -> (res: PageTableEntryExec) {
+        clone_pte(&self[i])
+    }
+}
+
+/// Replacing the directory at entry `idx` by `dir_pt2` preserves the invariant, as long as the
+/// new directory doesn't use the regions of the other entries and their memory is unchanged. The
+/// other entries keep their interpretation.
+proof fn lemma_update_directory_at(mem1: &mem::PageTableMemory, pt1: PTDir, mem2: &mem::PageTableMemory, dir_pt2: PTDir, used_regions: Set<MemRegion>, layer: nat, ptr: usize, base: nat, idx: nat)
+    requires
+        inv_at(mem1, pt1, layer, ptr),
+        mem2.inv(),
+        mem2.num_layers_spec() == mem1.num_layers_spec(),
+        idx < X86_NUM_ENTRIES,
+        view_at(mem1, pt1, layer, ptr, idx).is_Directory(),
+        mem2.regions().contains(pt1.region),
+        mem2.region_view(pt1.region) === mem1.region_view(pt1.region),
+        inv_at(mem2, dir_pt2, layer + 1, view_at(mem1, pt1, layer, ptr, idx).get_Directory_addr()),
+        !dir_pt2.used_regions.contains(pt1.region),
+        used_regions.contains(pt1.region),
+        used_regions.subset_of(mem2.regions()),
+        dir_pt2.used_regions.subset_of(used_regions),
+        forall|j: nat, r: MemRegion|
+            j < X86_NUM_ENTRIES && j != idx && pt1.entries[j as int].is_Some()
+            && #[trigger] pt1.entries[j as int].get_Some_0().used_regions.contains(r)
+            ==> {
+                &&& !dir_pt2.used_regions.contains(r)
+                &&& used_regions.contains(r)
+                &&& mem2.regions().contains(r)
+                &&& mem2.region_view(r) === mem1.region_view(r)
+            },
+    ensures
+        ({
+            let pt2 = PTDir { region: pt1.region, entries: pt1.entries.update(idx as int, Some(dir_pt2)), used_regions };
+            let dir_addr = view_at(mem1, pt1, layer, ptr, idx).get_Directory_addr();
+            let interp2 = interp_at(mem2, pt2, layer, ptr, base);
+            &&& inv_at(mem2, pt2, layer, ptr)
+            &&& interp2.entries.len() == X86_NUM_ENTRIES
+            &&& forall|i: nat| i < X86_NUM_ENTRIES && i != idx
+                ==> #[trigger] interp2.entries[i as int] === interp_at(mem1, pt1, layer, ptr, base).entries[i as int]
+            &&& interp2.entries[idx as int]
+                === l1::NodeEntry::Directory(interp_at(mem2, dir_pt2, layer + 1, dir_addr, arch(mem1).entry_base(layer, base, idx)))
+        }),
+{
+    let pt2 = PTDir { region: pt1.region, entries: pt1.entries.update(idx as int, Some(dir_pt2)), used_regions };
+    assert forall|i: nat| i < X86_NUM_ENTRIES implies
+        view_at(mem2, pt2, layer, ptr, i) == view_at(mem1, pt1, layer, ptr, i) by { };
+    assert forall|i: nat| i < X86_NUM_ENTRIES implies
+        entry_at_spec(mem2, pt2, layer, ptr, i) == entry_at_spec(mem1, pt1, layer, ptr, i) by { };
+    assert(directories_obey_invariant_at(mem1, pt1, layer, ptr));
+    assert(ghost_pt_used_regions_pairwise_disjoint(mem1, pt1, layer, ptr));
+    assert(ghost_pt_region_notin_used_regions(mem1, pt1, layer, ptr));
+
+    assert(directories_obey_invariant_at(mem2, pt2, layer, ptr)) by {
+        assert forall|i: nat| i < X86_NUM_ENTRIES implies {
+            let entry = #[trigger] view_at(mem2, pt2, layer, ptr, i);
+            entry.is_Directory() ==> inv_at(mem2, pt2.entries[i as int].get_Some_0(), layer + 1, entry.get_Directory_addr())
+        } by {
+            let entry = view_at(mem2, pt2, layer, ptr, i);
+            if i != idx && entry.is_Directory() {
+                let pt_entry = pt1.entries[i as int].get_Some_0();
+                assert(inv_at(mem1, pt_entry, layer + 1, entry.get_Directory_addr()));
+                assert(pt_entry.used_regions.contains(pt_entry.region));
+                lemma_inv_at_different_memory(mem1, mem2, pt_entry, layer + 1, entry.get_Directory_addr());
+            }
+        };
+    };
+    assert(ghost_pt_used_regions_pairwise_disjoint(mem2, pt2, layer, ptr)) by {
+        assert forall|i: nat, j: nat, r: MemRegion|
+            i != j &&
+            i < pt2.entries.len() && pt2.entries[i as int].is_Some() &&
+            #[trigger] pt2.entries[i as int].get_Some_0().used_regions.contains(r) &&
+            j < pt2.entries.len() && pt2.entries[j as int].is_Some()
+            implies !(#[trigger] pt2.entries[j as int].get_Some_0().used_regions.contains(r)) by
+        {
+            if i == idx {
+                assert(pt1.entries[j as int].get_Some_0().used_regions.contains(r) ==> !dir_pt2.used_regions.contains(r));
+            } else if j == idx {
+                assert(pt1.entries[i as int].get_Some_0().used_regions.contains(r));
+            }
+        };
+    };
+    assert(ghost_pt_matches_structure(mem2, pt2, layer, ptr));
+    assert(ghost_pt_used_regions_rtrancl(mem2, pt2, layer, ptr));
+    assert(ghost_pt_region_notin_used_regions(mem2, pt2, layer, ptr));
+    assert(inv_at(mem2, pt2, layer, ptr));
+
+    lemma_interp_at_aux_facts(mem1, pt1, layer, ptr, base, seq![]);
+    lemma_interp_at_aux_facts(mem2, pt2, layer, ptr, base, seq![]);
+    assert forall|i: nat| i < X86_NUM_ENTRIES && i != idx
+        implies #[trigger] interp_at(mem2, pt2, layer, ptr, base).entries[i as int] === interp_at(mem1, pt1, layer, ptr, base).entries[i as int] by
+    {
+        lemma_interp_at_entry_different_memory(mem1, pt1, mem2, pt2, layer, ptr, base, i);
+    };
+}
+
+/// Clearing the entry `idx` preserves the invariant, as long as the memory of the other entries
+/// is unchanged. The other entries keep their interpretation.
+proof fn lemma_clear_entry_at(mem1: &mem::PageTableMemory, pt1: PTDir, mem2: &mem::PageTableMemory, used_regions: Set<MemRegion>, layer: nat, ptr: usize, base: nat, idx: nat)
+    requires
+        inv_at(mem1, pt1, layer, ptr),
+        mem2.inv(),
+        mem2.num_layers_spec() == mem1.num_layers_spec(),
+        idx < X86_NUM_ENTRIES,
+        mem2.regions().contains(pt1.region),
+        mem2.region_view(pt1.region) === mem1.region_view(pt1.region).update(idx as int, 0u64),
+        used_regions.contains(pt1.region),
+        used_regions.subset_of(mem2.regions()),
+        forall|j: nat, r: MemRegion|
+            j < X86_NUM_ENTRIES && j != idx && pt1.entries[j as int].is_Some()
+            && #[trigger] pt1.entries[j as int].get_Some_0().used_regions.contains(r)
+            ==> {
+                &&& used_regions.contains(r)
+                &&& mem2.regions().contains(r)
+                &&& mem2.region_view(r) === mem1.region_view(r)
+            },
+    ensures
+        ({
+            let pt2 = PTDir { region: pt1.region, entries: pt1.entries.update(idx as int, None), used_regions };
+            let interp2 = interp_at(mem2, pt2, layer, ptr, base);
+            &&& inv_at(mem2, pt2, layer, ptr)
+            &&& interp2.entries.len() == X86_NUM_ENTRIES
+            &&& forall|i: nat| i < X86_NUM_ENTRIES && i != idx
+                ==> #[trigger] interp2.entries[i as int] === interp_at(mem1, pt1, layer, ptr, base).entries[i as int]
+            &&& interp2.entries[idx as int] === l1::NodeEntry::Empty()
+        }),
+{
+    let pt2 = PTDir { region: pt1.region, entries: pt1.entries.update(idx as int, None), used_regions };
+    entry_at_spec(mem2, pt2, layer, ptr, idx).lemma_zero_entry_facts();
+    assert forall|i: nat| i < X86_NUM_ENTRIES && i != idx implies
+        view_at(mem2, pt2, layer, ptr, i) == view_at(mem1, pt1, layer, ptr, i) by { };
+    assert forall|i: nat| i < X86_NUM_ENTRIES && i != idx implies
+        entry_at_spec(mem2, pt2, layer, ptr, i) == entry_at_spec(mem1, pt1, layer, ptr, i) by { };
+    assert(directories_obey_invariant_at(mem1, pt1, layer, ptr));
+    assert(ghost_pt_used_regions_pairwise_disjoint(mem1, pt1, layer, ptr));
+    assert(ghost_pt_region_notin_used_regions(mem1, pt1, layer, ptr));
+
+    assert(directories_obey_invariant_at(mem2, pt2, layer, ptr)) by {
+        assert forall|i: nat| i < X86_NUM_ENTRIES implies {
+            let entry = #[trigger] view_at(mem2, pt2, layer, ptr, i);
+            entry.is_Directory() ==> inv_at(mem2, pt2.entries[i as int].get_Some_0(), layer + 1, entry.get_Directory_addr())
+        } by {
+            let entry = view_at(mem2, pt2, layer, ptr, i);
+            if i != idx && entry.is_Directory() {
+                let pt_entry = pt1.entries[i as int].get_Some_0();
+                assert(inv_at(mem1, pt_entry, layer + 1, entry.get_Directory_addr()));
+                assert(pt_entry.used_regions.contains(pt_entry.region));
+                lemma_inv_at_different_memory(mem1, mem2, pt_entry, layer + 1, entry.get_Directory_addr());
+            }
+        };
+    };
+    assert(ghost_pt_matches_structure(mem2, pt2, layer, ptr));
+    assert(ghost_pt_used_regions_pairwise_disjoint(mem2, pt2, layer, ptr));
+    assert(ghost_pt_used_regions_rtrancl(mem2, pt2, layer, ptr));
+    assert(ghost_pt_region_notin_used_regions(mem2, pt2, layer, ptr));
+    assert(inv_at(mem2, pt2, layer, ptr));
+
+    lemma_interp_at_aux_facts(mem1, pt1, layer, ptr, base, seq![]);
+    lemma_interp_at_aux_facts(mem2, pt2, layer, ptr, base, seq![]);
+    assert forall|i: nat| i < X86_NUM_ENTRIES && i != idx
+        implies #[trigger] interp_at(mem2, pt2, layer, ptr, base).entries[i as int] === interp_at(mem1, pt1, layer, ptr, base).entries[i as int] by
+    {
+        lemma_interp_at_entry_different_memory(mem1, pt1, mem2, pt2, layer, ptr, base, i);
+    };
+}
+
+/// Every entry of `ptes` from `start` on that begins below the upper bound of `d` can be mapped
+/// into `d`.
+pub open spec fn range_accepted_at(d: l1::Directory, vaddr: nat, ptes: Seq<PageTableEntry>, start: int) -> bool {
+    forall|j: int| start <= j < ptes.len() && #[trigger] range_entry_base(vaddr, ptes, j) < d.upper_vaddr() ==> {
+        &&& accepted_mapping(range_entry_base(vaddr, ptes, j), ptes[j])
+        &&& d.accepted_mapping(range_entry_base(vaddr, ptes, j), ptes[j])
+    }
+}
+
+/// Maps the entries of `ptes` from `start` on into the directory at `ptr`, where `va` is the
+/// address of entry `start`. Consecutive entries that fall into the same entry of this directory
+/// are mapped by a single recursive call, so every directory is walked only once. Stops before
+/// the first entry that begins above this directory and returns its index and address.
+fn map_range_aux<E: RangeEntries + ?Sized>(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usize, ptr: usize, base: usize, vaddr: usize, ptes: &E, start: usize, va: usize)
+    -> (res: (Ghost<(PTDir,Set<MemRegion>)>, usize /* end */, usize /* end_va */, Result<(),()>))
+    requires
+        inv_at(&*old(mem), pt, layer as nat, ptr),
+        interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).inv(),
+        old(mem).inv(),
+        ptes.wf(),
+        start < ptes.spec_entries().len(),
+        va == range_entry_base(vaddr as nat, ptes.spec_entries(), start as int),
+        base <= va,
+        va < interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).upper_vaddr(),
+        vaddr + range_size(ptes.spec_entries()) <= MAX_LA57_BASE,
+        range_accepted_at(interp_at(&*old(mem), pt, layer as nat, ptr, base as nat), vaddr as nat, ptes.spec_entries(), start as int),
+        old(mem).alloc_available_pages() >= (old(mem).num_layers_spec() - 1 - layer) * (ptes.spec_entries().len() - start),
+    ensures
+        ({
+            let (pt_res, new_regions) = res.0@;
+            let end = res.1;
+            let ptes_v = ptes.spec_entries();
+            let old_interp = interp_at(&*old(mem), pt, layer as nat, ptr, base as nat);
+            let new_interp = interp_at(mem, pt_res, layer as nat, ptr, base as nat);
+            &&& start < end <= ptes.spec_entries().len()
+            &&& res.2 == range_entry_base(vaddr as nat, ptes_v, end as int)
+            &&& range_entry_base(vaddr as nat, ptes_v, end - 1) < old_interp.upper_vaddr()
+            // We return the regions that we added
+            &&& mem.regions() === old(mem).regions().union(new_regions)
+            &&& pt_res.used_regions === pt.used_regions.union(new_regions)
+            // and only those we added
+            &&& new_regions.disjoint(old(mem).regions())
+            &&& (forall|r: MemRegion| new_regions.contains(r) ==> !(#[trigger] pt.used_regions.contains(r)))
+            // Invariant preserved
+            &&& inv_at(mem, pt_res, layer as nat, ptr)
+            &&& new_interp.inv()
+            // We only touch already allocated regions if they're in pt.used_regions
+            &&& (forall|r: MemRegion| !(#[trigger] pt.used_regions.contains(r)) && !(new_regions.contains(r))
+                ==> mem.region_view(r) === old(mem).region_view(r))
+            &&& pt_res.region === pt.region
+            // Refinement of l1
+            &&& match res.3 {
+                Ok(_)  => Ok(new_interp),
+                Err(_) => Err(new_interp),
+            } === old_interp.map_range(va as nat, ptes_v.subrange(start as int, end as int))
+            // If successful, we stopped at the end of the range or of this directory
+            &&& res.3.is_Ok() ==> end == ptes.spec_entries().len() || res.2 >= old_interp.upper_vaddr()
+            // Each entry allocates at most one page for each layer below this one
+            &&& old(mem).alloc_available_pages() - mem.alloc_available_pages()
+                <= (old(mem).num_layers_spec() - 1 - layer) * (end - start)
+        }),
+        mem.cr3_spec() == old(mem).cr3_spec(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+    // decreases mem.num_layers_spec() - layer
+{
+    let ghost ptes_v = ptes.spec_entries();
+    let ghost old_interp = interp_at(&*old(mem), pt, layer as nat, ptr, base as nat);
+    let ghost pages: int = old(mem).num_layers_spec() - 1 - layer;
+    proof {
+        lemma_interp_at_facts(mem, pt, layer as nat, ptr, base as nat);
+        lemma_arch_facts(mem);
+        assert(ptes_v.subrange(start as int, start as int) =~= seq![]);
+        assert(old(mem).regions().union(Set::empty()) =~= old(mem).regions());
+        assert(pt.used_regions.union(Set::empty()) =~= pt.used_regions);
+    }
+    let upper: usize = arch_exec(mem).entry_base(layer, base, X86_NUM_ENTRIES);
+    let mut pt_cur: Ghost<PTDir> = Ghost(pt);
+    let mut new_regions: Ghost<Set<MemRegion>> = Ghost(Set::empty());
+    let mut i: usize = start;
+    let mut cur_va: usize = va;
+    while ptes.has_entry(i, cur_va - vaddr) && cur_va < upper
+        invariant
+            ptes_v === ptes.spec_entries(),
+            ptes.wf(),
+            old_interp === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat),
+            old_interp.inv(),
+            inv_at(&*old(mem), pt, layer as nat, ptr),
+            pages == old(mem).num_layers_spec() - 1 - layer,
+            upper == old_interp.upper_vaddr(),
+            start < ptes_v.len(),
+            base <= va,
+            va == range_entry_base(vaddr as nat, ptes_v, start as int),
+            vaddr + range_size(ptes_v) <= MAX_LA57_BASE,
+            range_accepted_at(old_interp, vaddr as nat, ptes_v, start as int),
+            old(mem).alloc_available_pages() >= pages * (ptes_v.len() - start),
+            start <= i <= ptes_v.len(),
+            i == start ==> cur_va == va && cur_va < upper,
+            i > start ==> range_entry_base(vaddr as nat, ptes_v, i - 1) < upper,
+            cur_va == range_entry_base(vaddr as nat, ptes_v, i as int),
+            va <= cur_va,
+            mem.inv(),
+            mem.cr3_spec() == old(mem).cr3_spec(),
+            mem.num_layers_spec() == old(mem).num_layers_spec(),
+            mem.regions() === old(mem).regions().union(new_regions@),
+            pt_cur@.used_regions === pt.used_regions.union(new_regions@),
+            new_regions@.disjoint(old(mem).regions()),
+            forall|r: MemRegion| new_regions@.contains(r) ==> !(#[trigger] pt.used_regions.contains(r)),
+            inv_at(mem, pt_cur@, layer as nat, ptr),
+            interp_at(mem, pt_cur@, layer as nat, ptr, base as nat).inv(),
+            forall|r: MemRegion| !(#[trigger] pt.used_regions.contains(r)) && !(new_regions@.contains(r))
+                ==> mem.region_view(r) === old(mem).region_view(r),
+            pt_cur@.region === pt.region,
+            Ok(interp_at(mem, pt_cur@, layer as nat, ptr, base as nat))
+                === old_interp.map_range(va as nat, ptes_v.subrange(start as int, i as int)),
+            old(mem).alloc_available_pages() - mem.alloc_available_pages() <= pages * (i - start),
+    {
+        let pte = ptes.entry(i, cur_va - vaddr);
+        let prev_mem: Ghost<&mem::PageTableMemory> = Ghost(mem);
+        let ghost prev_pt = pt_cur@;
+        let ghost prev_interp = interp_at(mem, pt_cur@, layer as nat, ptr, base as nat);
+        let ghost b = cur_va as nat;
+        let ghost pte_v = ptes_v[i as int];
+        let ghost prefix = ptes_v.subrange(start as int, i as int);
+        proof {
+            lemma_interp_at_facts(mem, pt_cur@, layer as nat, ptr, base as nat);
+            lemma_arch_facts(mem);
+            assert(ptes_v.take(i + 1).drop_last() =~= ptes_v.take(i as int));
+            lemma_range_entry_base_bounds(vaddr as nat, ptes_v, i as int);
+            lemma_range_entry_base_subrange(vaddr as nat, ptes_v, start as int, i as int, i - start);
+            assert(va + range_size(prefix) == b);
+            // The accepted mappings only depend on the layer, base and architecture of the
+            // directory, which are the same for all interpretations.
+            assert(old_interp.accepted_mapping(b, pte_v));
+            assert(prev_interp.accepted_mapping(b, pte_v));
+            assert(accepted_mapping(b, pte_v));
+            assert(old(mem).alloc_available_pages() >= pages * (ptes_v.len() - start));
+            assert(mem.alloc_available_pages() >= pages * (ptes_v.len() - i)) by (nonlinear_arith)
+                requires
+                    old(mem).alloc_available_pages() >= pages * (ptes_v.len() - start),
+                    old(mem).alloc_available_pages() - mem.alloc_available_pages() <= pages * (i - start),
+                    start <= i;
+            assert(pages * (ptes_v.len() - i) >= pages) by (nonlinear_arith)
+                requires i < ptes_v.len(), pages >= 0;
+        }
+        let idx: usize = arch_exec(mem).index_for_vaddr(layer, base, cur_va);
+        proof {
+            let es = arch(mem).entry_size(layer as nat);
+            assert(aligned(base as nat, es)) by {
+                extra::mod_mult_zero_implies_mod_zero(base as nat, es, X86_NUM_ENTRIES as nat);
+            };
+            indexing::lemma_index_from_base_and_addr(base as nat, cur_va as nat, es, X86_NUM_ENTRIES as nat);
+            indexing::lemma_entry_base_from_index(base as nat, idx as nat, es);
+            lemma_interp_at_facts_entries(mem, pt_cur@, layer as nat, ptr, base as nat, idx as nat);
+            prev_interp.lemma_map_frame_structure_assertions(b, pte_v, idx as nat);
+        }
+        let entry = entry_at(mem, Ghost(pt_cur@), layer, ptr, idx);
+        let entry_base: usize = arch_exec(mem).entry_base(layer, base, idx);
+        let size = pte.frame.size;
+        if size == arch_exec(mem).entry_size(layer) || (entry.is_mapping() && !entry.is_dir(format_layer_exec(mem, layer))) {
+            // The entry is mapped at this layer (or can't be mapped at all)
+            match map_frame_aux(mem, Ghost(pt_cur@), layer, ptr, base, cur_va, pte) {
+                Ok(rec_res) => {
+                    proof {
+                        let (pt_res, frame_regions) = rec_res@;
+                        prev_interp.lemma_map_frame_preserves_inv(b, pte_v);
+                        prev_interp.lemma_map_frame_num_new_dirs_bound(b, pte_v);
+                        assert(ptes_v.subrange(start as int, i + 1).drop_last() =~= prefix);
+                        assert(ptes_v.subrange(start as int, i + 1).last() === pte_v);
+                        assert(mem.regions() =~= old(mem).regions().union(new_regions@.union(frame_regions)));
+                        assert(pt_res.used_regions =~= pt.used_regions.union(new_regions@.union(frame_regions)));
+                        assert(old(mem).alloc_available_pages() - mem.alloc_available_pages() <= pages * (i + 1 - start)) by (nonlinear_arith)
+                            requires
+                                old(mem).alloc_available_pages() - prev_mem@.alloc_available_pages() <= pages * (i - start),
+                                prev_mem@.alloc_available_pages() - mem.alloc_available_pages() <= pages;
+                        assert(ptes_v.take(i + 1).drop_last() =~= ptes_v.take(i as int));
+                    }
+                    pt_cur = Ghost(rec_res@.0);
+                    new_regions = Ghost(new_regions@.union(rec_res@.1));
+                    cur_va = cur_va + size;
+                    i = i + 1;
+                },
+                Err(_) => {
+                    proof {
+                        assert(ptes_v.subrange(start as int, i + 1).drop_last() =~= prefix);
+                        assert(ptes_v.subrange(start as int, i + 1).last() === pte_v);
+                        assert(ptes_v.take(i + 1).drop_last() =~= ptes_v.take(i as int));
+                        assert(pages * (i - start) <= pages * (i + 1 - start)) by (nonlinear_arith)
+                            requires pages >= 0, start <= i;
+                    }
+                    return (Ghost((pt_cur@, new_regions@)), i + 1, cur_va + size, Err(()));
+                },
+            }
+        } else {
+            // The entry goes into the directory at `idx`, together with the following entries
+            // that fall into the same entry of this directory.
+            let mut pt_with_dir: Ghost<PTDir> = Ghost(pt_cur@);
+            let mut dir_regions: Ghost<Set<MemRegion>> = Ghost(Set::empty());
+            let dir_addr: usize = if entry.is_mapping() {
+                entry.address() as usize
+            } else {
+                let (pt_with_empty, new_dir_region, _new_dir_entry) = insert_empty_directory(mem, Ghost(pt_cur@), layer, ptr, base, idx);
+                pt_with_dir = pt_with_empty;
+                dir_regions = Ghost(set![new_dir_region@]);
+                new_dir_region.base
+            };
+            let mem_with_dir: Ghost<&mem::PageTableMemory> = Ghost(mem);
+            let dir_pt: Ghost<PTDir> = Ghost(pt_with_dir@.entries[idx as int].get_Some_0());
+            let ghost d = match prev_interp.entries[idx as int] {
+                l1::NodeEntry::Directory(d) => d,
+                _                           => prev_interp.new_empty_dir(idx as nat),
+            };
+            proof {
+                let es = arch(mem).entry_size(layer as nat);
+                assert(directories_obey_invariant_at(mem_with_dir@, pt_with_dir@, layer as nat, ptr));
+                assert(view_at(mem_with_dir@, pt_with_dir@, layer as nat, ptr, idx as nat).is_Directory());
+                assert(view_at(mem_with_dir@, pt_with_dir@, layer as nat, ptr, idx as nat).get_Directory_addr() == dir_addr);
+                lemma_interp_at_aux_facts(mem_with_dir@, pt_with_dir@, layer as nat, ptr, base as nat, seq![]);
+                assert(interp_at(mem_with_dir@, dir_pt@, (layer + 1) as nat, dir_addr, entry_base as nat) === d);
+                assert(d.inv());
+                assert(d.upper_vaddr() == prev_interp.next_entry_base(idx as nat));
+                // The entries that begin within this entry of the directory are mapped through `d`
+                assert forall|j: int| i <= j < ptes_v.len() && #[trigger] range_entry_base(vaddr as nat, ptes_v, j) < d.upper_vaddr()
+                    implies {
+                        &&& accepted_mapping(range_entry_base(vaddr as nat, ptes_v, j), ptes_v[j])
+                        &&& d.accepted_mapping(range_entry_base(vaddr as nat, ptes_v, j), ptes_v[j])
+                    } by
+                {
+                    let bj = range_entry_base(vaddr as nat, ptes_v, j);
+                    assert(bj < old_interp.upper_vaddr());
+                    assert(prev_interp.accepted_mapping(bj, ptes_v[j]));
+                    prev_interp.lemma_range_entry_in_entry(vaddr as nat, ptes_v, i as int, j, idx as nat);
+                    prev_interp.lemma_accepted_mapping_implies_directory_accepted_mapping(bj, ptes_v[j], d);
+                };
+                assert(range_accepted_at(d, vaddr as nat, ptes_v, i as int));
+                assert(mem.alloc_available_pages() >= (mem.num_layers_spec() - 1 - (layer + 1)) * (ptes_v.len() - i)) by (nonlinear_arith)
+                    requires
+                        mem.alloc_available_pages() + dir_regions@.len() >= pages * (ptes_v.len() - i),
+                        dir_regions@.len() <= 1,
+                        pages >= 1,
+                        mem.num_layers_spec() - 1 - layer == pages,
+                        i < ptes_v.len();
+            }
+            let (dir_res, end, end_va, dir_ok) = map_range_aux(mem, dir_pt, layer + 1, dir_addr, entry_base, vaddr, ptes, i, cur_va);
+            let pt_next: Ghost<PTDir> = Ghost(
+                PTDir {
+                    region:       pt_with_dir@.region,
+                    entries:      pt_with_dir@.entries.update(idx as int, Some(dir_res@.0)),
+                    used_regions: pt_with_dir@.used_regions.union(dir_res@.1),
+                });
+            proof {
+                let (dir_pt_res, child_regions) = dir_res@;
+                let suffix = ptes_v.subrange(i as int, end as int);
+                let d_new = interp_at(mem, dir_pt_res, (layer + 1) as nat, dir_addr, entry_base as nat);
+                assert(ghost_pt_used_regions_rtrancl(mem_with_dir@, pt_with_dir@, layer as nat, ptr));
+                assert(ghost_pt_used_regions_pairwise_disjoint(mem_with_dir@, pt_with_dir@, layer as nat, ptr));
+                assert(ghost_pt_region_notin_used_regions(mem_with_dir@, pt_with_dir@, layer as nat, ptr));
+                lemma_update_directory_at(mem_with_dir@, pt_with_dir@, mem, dir_pt_res, pt_next@.used_regions, layer as nat, ptr, base as nat, idx as nat);
+
+                // All entries of `suffix` lie within the entry `idx`
+                lemma_range_entry_base_subrange(vaddr as nat, ptes_v, i as int, end as int, end - i);
+                assert(ptes_v.take(end as int).drop_last() =~= ptes_v.take(end - 1));
+                assert(d.accepted_mapping(range_entry_base(vaddr as nat, ptes_v, end - 1), ptes_v[end - 1]));
+                assert(b + range_size(suffix) <= prev_interp.next_entry_base(idx as nat));
+                assert forall|k: int| 0 <= k < suffix.len()
+                    implies #[trigger] prev_interp.accepted_mapping(range_entry_base(b, suffix, k), suffix[k]) by
+                {
+                    lemma_range_entry_base_subrange(vaddr as nat, ptes_v, i as int, end as int, k);
+                    lemma_range_entry_base_bounds(b, suffix, k);
+                    assert(old_interp.accepted_mapping(range_entry_base(vaddr as nat, ptes_v, i + k), ptes_v[i + k]));
+                };
+                assert forall|k: int| 0 <= k < suffix.len()
+                    implies (#[trigger] suffix[k]).frame.size != prev_interp.entry_size() by
+                {
+                    lemma_range_entry_base_subrange(vaddr as nat, ptes_v, i as int, end as int, k);
+                    lemma_range_entry_base_bounds(b, suffix, k);
+                    assert(prev_interp.accepted_mapping(range_entry_base(vaddr as nat, ptes_v, i + k), ptes_v[i + k]));
+                    prev_interp.lemma_range_entry_in_entry(vaddr as nat, ptes_v, i as int, i + k, idx as nat);
+                };
+                prev_interp.lemma_map_range_in_entry(b, suffix, idx as nat);
+                prev_interp.lemma_map_range_preserves_inv(b, suffix);
+
+                // The new interpretation is the previous one with the directory at `idx` replaced
+                assert forall|j: nat| j < X86_NUM_ENTRIES && j != idx
+                    implies #[trigger] interp_at(mem_with_dir@, pt_with_dir@, layer as nat, ptr, base as nat).entries[j as int]
+                        === prev_interp.entries[j as int] by
+                {
+                    if pt_with_dir@.entries[j as int].is_Some() {
+                        assert(pt_with_dir@.entries[j as int] === prev_pt.entries[j as int]);
+                    }
+                    lemma_interp_at_entry_different_memory(prev_mem@, prev_pt, mem_with_dir@, pt_with_dir@, layer as nat, ptr, base as nat, j);
+                };
+                let new_interp = interp_at(mem, pt_next@, layer as nat, ptr, base as nat);
+                assert(new_interp.entries =~= prev_interp.update(idx as nat, l1::NodeEntry::Directory(d_new)).entries);
+                assert(new_interp === prev_interp.update(idx as nat, l1::NodeEntry::Directory(d_new)));
+
+                // and that's mapping the prefix and then the suffix
+                assert(prefix + suffix =~= ptes_v.subrange(start as int, end as int));
+                old_interp.lemma_map_range_append(va as nat, prefix, suffix);
+
+                // Regions
+                assert(mem.regions() =~= old(mem).regions().union(new_regions@.union(dir_regions@).union(child_regions)));
+                assert(pt_next@.used_regions =~= pt.used_regions.union(new_regions@.union(dir_regions@).union(child_regions)));
+                assert(old(mem).alloc_available_pages() - mem.alloc_available_pages() <= pages * (end - start)) by (nonlinear_arith)
+                    requires
+                        old(mem).alloc_available_pages() - prev_mem@.alloc_available_pages() <= pages * (i - start),
+                        prev_mem@.alloc_available_pages() - mem_with_dir@.alloc_available_pages() <= 1,
+                        mem_with_dir@.alloc_available_pages() - mem.alloc_available_pages() <= (pages - 1) * (end - i),
+                        pages >= 1,
+                        i < end;
+            }
+            pt_cur = pt_next;
+            new_regions = Ghost(new_regions@.union(dir_regions@).union(dir_res@.1));
+            match dir_ok {
+                Ok(_) => {},
+                Err(_) => {
+                    return (Ghost((pt_cur@, new_regions@)), end, end_va, Err(()));
+                },
+            }
+            i = end;
+            cur_va = end_va;
+        }
+    }
+    (Ghost((pt_cur@, new_regions@)), i, cur_va, Ok(()))
+}
+
+/// Maps the frames in `ptes` back to back starting at `vaddr`. The entries are mapped one after
+/// another, if one of them fails to map, the ones before it remain mapped. Each directory is
+/// walked once, no matter how many of the entries fall into it. Each mapping needs at most one new
+/// directory for each layer below the root, so the caller has to provide that many available
+/// pages per entry.
+pub fn map_range<E: RangeEntries + ?Sized>(mem: &mut mem::PageTableMemory, pt: &mut Ghost<PTDir>, vaddr: usize, ptes: &E) -> (res: Result<(),()>)
+    requires
+        inv(&*old(mem), old(pt)@),
+        interp(&*old(mem), old(pt)@).inv(),
+        old(mem).inv(),
+        ptes.wf(),
+        old(mem).alloc_available_pages() >= (old(mem).num_layers_spec() - 1) * ptes.spec_entries().len(),
+        accepted_range(interp(&*old(mem), old(pt)@), vaddr as nat, ptes.spec_entries()),
+    ensures
+        inv(mem, pt@),
+        interp(mem, pt@).inv(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        // Refinement of l1
+        match res {
+            Ok(_)  => Ok(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).map_range(vaddr as nat, ptes.spec_entries()),
+            Err(_) => Err(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).map_range(vaddr as nat, ptes.spec_entries()),
+        },
+        // Refinement of l0
+        match res {
+            Ok(_)  => Ok(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().map_range(vaddr as nat, ptes.spec_entries()),
+            Err(_) => Err(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().map_range(vaddr as nat, ptes.spec_entries()),
+        },
+{
+    let ghost old_interp = interp(&*old(mem), old(pt)@);
+    let ghost ptes_v = ptes.spec_entries();
+    proof {
+        assert forall|i: int| 0 <= i < ptes_v.len()
+            implies #[trigger] old_interp.accepted_mapping(range_entry_base(vaddr as nat, ptes_v, i), ptes_v[i]) by
+        {
+            assert(accepted_mapping(range_entry_base(vaddr as nat, ptes_v, i), ptes_v[i]));
+        };
+        old_interp.lemma_map_range_refines_map_range(vaddr as nat, ptes_v);
+        old_interp.lemma_map_range_preserves_inv(vaddr as nat, ptes_v);
+        assert(ptes_v.take(0) =~= seq![]);
+    }
+    if !ptes.has_entry(0, 0) {
+        proof { assert(ptes_v =~= seq![]); }
+        return Ok(());
+    }
+    proof {
+        lemma_interp_at_facts(mem, pt@, 0, mem.cr3_spec().base, 0);
+        lemma_arch_facts(mem);
+        assert(accepted_mapping(range_entry_base(vaddr as nat, ptes_v, 0), ptes_v[0]));
+        assert(range_accepted_at(old_interp, vaddr as nat, ptes_v, 0));
+        assert((mem.num_layers_spec() - 1 - 0) * (ptes_v.len() - 0) == (mem.num_layers_spec() - 1) * ptes_v.len());
+    }
+    let (res, end, _end_va, mapped) = map_range_aux(mem, *pt, 0, mem.cr3().base, 0, vaddr, ptes, 0, vaddr);
+    proof {
+        assert(ptes_v.subrange(0, end as int) =~= ptes_v.take(end as int));
+        // The root covers the whole range, so we only stop early if an entry fails to map
+        if mapped.is_Ok() && end < ptes_v.len() {
+            lemma_range_entry_base_bounds(vaddr as nat, ptes_v, end as int);
+            assert(false);
+        }
+    }
+    *pt = Ghost(res@.0);
+    match mapped {
+        Ok(_) => {
+            proof { assert(ptes_v.take(ptes_v.len() as int) =~= ptes_v); }
+            Ok(())
+        },
+        Err(_) => {
+            proof { old_interp.lemma_map_range_err_propagates(vaddr as nat, ptes_v, end as nat); }
+            Err(())
+        },
+    }
+}
+
+/// Removes all mappings whose base lies in `[vaddr, end)` from the directory at `ptr`, removing
+/// the directories that become empty. Each entry of the directory that overlaps the range is
+/// visited once.
+fn unmap_range_aux(mem: &mut mem::PageTableMemory, Ghost(pt): Ghost<PTDir>, layer: usize, ptr: usize, base: usize, vaddr: usize, end: usize)
+    -> (res: Ghost<(PTDir,Set<MemRegion>)>)
+    requires
+        inv_at(&*old(mem), pt, layer as nat, ptr),
+        interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).inv(),
+        old(mem).inv(),
+        vaddr < end <= MAX_LA57_BASE,
+        base < end,
+        vaddr < interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).upper_vaddr(),
+    ensures
+        ({
+            let (pt_res, removed_regions) = res@;
+            // We return the regions that we removed
+            &&& old(mem).regions() == mem.regions().union(removed_regions)
+            &&& pt.used_regions == pt_res.used_regions.union(removed_regions)
+            // and only those we removed
+            &&& (forall|r: MemRegion| removed_regions.contains(r) ==> !(#[trigger] mem.regions().contains(r)))
+            &&& (forall|r: MemRegion| removed_regions.contains(r) ==> !(#[trigger] pt_res.used_regions.contains(r)))
+            // Invariant preserved
+            &&& inv_at(mem, pt_res, layer as nat, ptr)
+            // We only touch regions in pt.used_regions
+            &&& (forall|r: MemRegion|
+                 !(#[trigger] pt_res.used_regions.contains(r))
+                 && !(#[trigger] removed_regions.contains(r))
+                ==> mem.region_view(r) === old(mem).region_view(r))
+            &&& pt_res.region === pt.region
+            // Refinement of l1
+            &&& interp_at(mem, pt_res, layer as nat, ptr, base as nat)
+                === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat).remove_range(vaddr as nat, (end - vaddr) as nat)
+        }),
+        mem.cr3_spec() == old(mem).cr3_spec(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+    // decreases mem.num_layers_spec() - layer
+{
+    let ghost old_interp = interp_at(&*old(mem), pt, layer as nat, ptr, base as nat);
+    let ghost len: nat = (end - vaddr) as nat;
+    let ghost removed_interp = old_interp.remove_range(vaddr as nat, len);
+    proof {
+        lemma_interp_at_facts(mem, pt, layer as nat, ptr, base as nat);
+        lemma_arch_facts(mem);
+        old_interp.lemma_remove_range_aux_facts(vaddr as nat, len, 0);
+        assert(pt.used_regions.union(Set::empty()) =~= pt.used_regions);
+        assert(old(mem).regions().union(Set::empty()) =~= old(mem).regions());
+    }
+    let start_va: usize = if vaddr < base { base } else { vaddr };
+    let mut idx: usize = arch_exec(mem).index_for_vaddr(layer, base, start_va);
+    proof {
+        let es = arch(mem).entry_size(layer as nat);
+        assert(aligned(base as nat, es)) by {
+            extra::mod_mult_zero_implies_mod_zero(base as nat, es, X86_NUM_ENTRIES as nat);
+        };
+        indexing::lemma_index_from_base_and_addr(base as nat, start_va as nat, es, X86_NUM_ENTRIES as nat);
+        // The entries before `idx` end at or below `start_va`, so they don't overlap the range.
+        assert forall|j: nat| j < idx implies old_interp.next_entry_base(j) <= vaddr by {
+            indexing::lemma_entry_base_from_index(base as nat, j, es);
+            indexing::lemma_entry_base_from_index(base as nat, idx as nat, es);
+        };
+    }
+    let mut pt_cur: Ghost<PTDir> = Ghost(pt);
+    let mut removed_regions: Ghost<Set<MemRegion>> = Ghost(Set::empty());
+    while idx < X86_NUM_ENTRIES && arch_exec(mem).entry_base(layer, base, idx) < end
+        invariant
+            old_interp === interp_at(&*old(mem), pt, layer as nat, ptr, base as nat),
+            old_interp.inv(),
+            inv_at(&*old(mem), pt, layer as nat, ptr),
+            len == end - vaddr,
+            removed_interp === old_interp.remove_range(vaddr as nat, len),
+            removed_interp.entries.len() == X86_NUM_ENTRIES,
+            forall|j: nat| j < X86_NUM_ENTRIES ==> #[trigger] removed_interp.entries[j as int] === old_interp.remove_range_entry(vaddr as nat, len, j),
+            idx <= X86_NUM_ENTRIES,
+            mem.inv(),
+            mem.cr3_spec() == old(mem).cr3_spec(),
+            mem.num_layers_spec() == old(mem).num_layers_spec(),
+            old(mem).regions() == mem.regions().union(removed_regions@),
+            pt.used_regions == pt_cur@.used_regions.union(removed_regions@),
+            forall|r: MemRegion| removed_regions@.contains(r) ==> !(#[trigger] mem.regions().contains(r)),
+            forall|r: MemRegion| removed_regions@.contains(r) ==> !(#[trigger] pt_cur@.used_regions.contains(r)),
+            inv_at(mem, pt_cur@, layer as nat, ptr),
+            forall|r: MemRegion|
+                !(#[trigger] pt_cur@.used_regions.contains(r))
+                && !(#[trigger] removed_regions@.contains(r))
+                ==> mem.region_view(r) === old(mem).region_view(r),
+            pt_cur@.region === pt.region,
+            interp_at(mem, pt_cur@, layer as nat, ptr, base as nat).entries.len() == X86_NUM_ENTRIES,
+            // The entries before `idx` are done
+            forall|j: nat| j < idx
+                ==> #[trigger] interp_at(mem, pt_cur@, layer as nat, ptr, base as nat).entries[j as int] === removed_interp.entries[j as int],
+            // and the ones after it are untouched
+            forall|j: nat| idx <= j < X86_NUM_ENTRIES ==> {
+                &&& #[trigger] pt_cur@.entries[j as int] === pt.entries[j as int]
+                &&& view_at(mem, pt_cur@, layer as nat, ptr, j) === view_at(&*old(mem), pt, layer as nat, ptr, j)
+                &&& interp_at(mem, pt_cur@, layer as nat, ptr, base as nat).entries[j as int] === old_interp.entries[j as int]
+                &&& pt.entries[j as int].is_Some() ==> forall|r: MemRegion| pt.entries[j as int].get_Some_0().used_regions.contains(r)
+                    ==> pt_cur@.used_regions.contains(r) && #[trigger] mem.region_view(r) === old(mem).region_view(r)
+            },
+    {
+        let prev_mem: Ghost<&mem::PageTableMemory> = Ghost(mem);
+        let ghost prev_pt = pt_cur@;
+        let entry_base: usize = arch_exec(mem).entry_base(layer, base, idx);
+        proof {
+            lemma_interp_at_facts(&*old(mem), pt, layer as nat, ptr, base as nat);
+            lemma_interp_at_facts_entries(&*old(mem), pt, layer as nat, ptr, base as nat, idx as nat);
+            indexing::lemma_entry_base_from_index(base as nat, idx as nat, arch(mem).entry_size(layer as nat));
+            // Every entry from `idx` on that begins below `end` overlaps the range
+            assert(vaddr < old_interp.next_entry_base(idx as nat));
+            assert(ghost_pt_used_regions_rtrancl(mem, pt_cur@, layer as nat, ptr));
+            assert(ghost_pt_used_regions_pairwise_disjoint(mem, pt_cur@, layer as nat, ptr));
+            assert(ghost_pt_region_notin_used_regions(mem, pt_cur@, layer as nat, ptr));
+        }
+        let entry = entry_at(mem, Ghost(pt_cur@), layer, ptr, idx);
+        if entry.is_mapping() {
+            if entry.is_dir(format_layer_exec(mem, layer)) {
+                let dir_addr = entry.address() as usize;
+                let dir_pt: Ghost<PTDir> = Ghost(pt.entries.index(idx as int).get_Some_0());
+                proof {
+                    assert(directories_obey_invariant_at(&*old(mem), pt, layer as nat, ptr));
+                    assert(directories_obey_invariant_at(mem, pt_cur@, layer as nat, ptr));
+                    lemma_interp_at_aux_facts(&*old(mem), pt, layer as nat, ptr, base as nat, seq![]);
+                    lemma_inv_at_different_memory(&*old(mem), mem, dir_pt@, (layer + 1) as nat, dir_addr);
+                    lemma_interp_at_aux_facts(mem, pt_cur@, layer as nat, ptr, base as nat, seq![]);
+                    assert(old_interp.directories_obey_invariant());
+                }
+                let ghost old_dir_interp = interp_at(mem, dir_pt@, (layer + 1) as nat, dir_addr, entry_base as nat);
+                let dir_res = unmap_range_aux(mem, dir_pt, layer + 1, dir_addr, entry_base, vaddr, end);
+                let dir_pt_res: Ghost<PTDir> = Ghost(dir_res@.0);
+                let child_removed: Ghost<Set<MemRegion>> = Ghost(dir_res@.1);
+                let mem_with_dir: Ghost<&mem::PageTableMemory> = Ghost(mem);
+                let pt_with_dir: Ghost<PTDir> = Ghost(
+                    PTDir {
+                        region:       pt_cur@.region,
+                        entries:      pt_cur@.entries.update(idx as int, Some(dir_pt_res@)),
+                        used_regions: pt_cur@.used_regions.difference(child_removed@),
+                    });
+                proof {
+                    lemma_update_directory_at(prev_mem@, prev_pt, mem, dir_pt_res@, pt_with_dir@.used_regions, layer as nat, ptr, base as nat, idx as nat);
+                    assert(old_dir_interp === interp_at(&*old(mem), dir_pt@, (layer + 1) as nat, dir_addr, entry_base as nat));
+                    assert(old_interp.entries[idx as int] === l1::NodeEntry::Directory(old_dir_interp));
+                }
+                if is_directory_empty(mem, dir_pt_res, layer + 1, dir_addr) {
+                    mem.write(ptr, idx, Ghost(pt.region), 0u64);
+                    mem.dealloc_page(MemRegionExec { base: dir_addr, size: PAGE_SIZE, });
+                    removed_regions = Ghost(removed_regions@.union(child_removed@).insert(dir_pt_res@.region));
+                    let pt_res: Ghost<PTDir> = Ghost(
+                        PTDir {
+                            region:       pt_cur@.region,
+                            entries:      pt_cur@.entries.update(idx as int, None),
+                            used_regions: pt.used_regions.difference(removed_regions@),
+                        });
+                    proof {
+                        lemma_empty_at_implies_interp_at_empty(mem_with_dir@, dir_pt_res@, (layer + 1) as nat, dir_addr, entry_base as nat);
+                        lemma_clear_entry_at(mem_with_dir@, pt_with_dir@, mem, pt_res@.used_regions, layer as nat, ptr, base as nat, idx as nat);
+                        assert(pt_with_dir@.entries.update(idx as int, None) =~= pt_res@.entries);
+                        assert(old(mem).regions() =~= mem.regions().union(removed_regions@));
+                        assert(pt.used_regions =~= pt_res@.used_regions.union(removed_regions@));
+                    }
+                    pt_cur = pt_res;
+                } else {
+                    removed_regions = Ghost(removed_regions@.union(child_removed@));
+                    proof {
+                        lemma_not_empty_at_implies_interp_at_not_empty(mem, dir_pt_res@, (layer + 1) as nat, dir_addr, entry_base as nat);
+                        assert(old(mem).regions() =~= mem.regions().union(removed_regions@));
+                        assert(pt.used_regions =~= pt_with_dir@.used_regions.union(removed_regions@));
+                    }
+                    pt_cur = pt_with_dir;
+                }
+            } else {
+                mem.write(ptr, idx, Ghost(pt.region), 0u64);
+                let pt_res: Ghost<PTDir> = Ghost(
+                    PTDir {
+                        region:       pt_cur@.region,
+                        entries:      pt_cur@.entries.update(idx as int, None),
+                        used_regions: pt_cur@.used_regions,
+                    });
+                proof {
+                    lemma_clear_entry_at(prev_mem@, prev_pt, mem, pt_res@.used_regions, layer as nat, ptr, base as nat, idx as nat);
+                    assert(pt_cur@.entries[idx as int].is_None());
+                    assert(pt_cur@.entries.update(idx as int, None) =~= pt_cur@.entries);
+                }
+                pt_cur = pt_res;
+            }
+        } else {
+            proof {
+                assert(old_interp.entries[idx as int].is_Empty());
+            }
+        }
+        idx = idx + 1;
+    }
+    proof {
+        lemma_interp_at_facts(mem, pt_cur@, layer as nat, ptr, base as nat);
+        // The remaining entries begin at or above `end`, so they don't overlap the range.
+        assert forall|j: nat| idx <= j < X86_NUM_ENTRIES
+            implies old_interp.remove_range_entry(vaddr as nat, len, j) === old_interp.entries[j as int] by
+        {
+            let es = arch(mem).entry_size(layer as nat);
+            indexing::lemma_entry_base_from_index(base as nat, idx as nat, es);
+            indexing::lemma_entry_base_from_index(base as nat, j, es);
+        };
+        assert(interp_at(mem, pt_cur@, layer as nat, ptr, base as nat).entries =~= removed_interp.entries);
+    }
+    Ghost((pt_cur@, removed_regions@))
+}
+
+/// Removes all mappings whose base lies in `[vaddr, vaddr + len)`. If a mapping straddles the
+/// boundaries of the range, nothing is removed.
+pub fn unmap_range(mem: &mut mem::PageTableMemory, pt: &mut Ghost<PTDir>, vaddr: usize, len: usize) -> (res: Result<(),()>)
+    requires
+        inv(&*old(mem), old(pt)@),
+        interp(&*old(mem), old(pt)@).inv(),
+        old(mem).inv(),
+        aligned(vaddr as nat, PAGE_SIZE as nat),
+        aligned(len as nat, PAGE_SIZE as nat),
+        0 < len,
+        vaddr + len <= MAX_BASE,
+    ensures
+        inv(mem, pt@),
+        interp(mem, pt@).inv(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        // Refinement of l1
+        match res {
+            Ok(_)  => Ok(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).unmap_range(vaddr as nat, len as nat),
+            Err(_) => Err(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).unmap_range(vaddr as nat, len as nat),
+        },
+        // Refinement of l0
+        match res {
+            Ok(_)  => Ok(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().unmap_range(vaddr as nat, len as nat),
+            Err(_) => Err(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().unmap_range(vaddr as nat, len as nat),
+        },
+{
+    let ghost old_interp = interp(&*old(mem), old(pt)@).interp();
+    proof {
+        interp(mem, pt@).lemma_inv_implies_interp_inv();
+        ambient_arith();
+        overflow_bounds();
+    }
+    let end = vaddr + len;
+
+    // Unmapping is atomic, so we first check that no mapping straddles one of the boundaries.
+    // A mapping that straddles the lower boundary contains `vaddr`, one that straddles the upper
+    // boundary contains `end - 1`.
+    match resolve(mem, *pt, vaddr) {
+        Ok((base, pte)) => {
+            if base != vaddr {
+                proof {
+                    assert(old_interp.map.dom().contains(base as nat));
+                    assert(old_interp.straddles_range(base as nat, vaddr as nat, len as nat));
+                }
+                return Err(());
+            }
+        },
+        Err(_) => {},
+    }
+    match resolve(mem, *pt, end - 1) {
+        Ok((base, pte)) => {
+            if base < vaddr || pte.frame.size > end - base {
+                proof {
+                    assert(old_interp.map.dom().contains(base as nat));
+                    assert(old_interp.straddles_range(base as nat, vaddr as nat, len as nat));
+                }
+                return Err(());
+            }
+        },
+        Err(_) => {},
+    }
+    proof {
+        assert_by_contradiction!(!exists|b: nat| old_interp.map.dom().contains(b) && #[trigger] old_interp.straddles_range(b, vaddr as nat, len as nat), {
+            let b = choose|b: nat| old_interp.map.dom().contains(b) && #[trigger] old_interp.straddles_range(b, vaddr as nat, len as nat);
+            if b < vaddr {
+                assert(old_interp.resolve(vaddr as nat) === Ok((b, old_interp.map[b])));
+            } else {
+                assert(old_interp.resolve((end - 1) as nat) === Ok((b, old_interp.map[b])));
+            }
+        });
+    }
+
+    // No mapping straddles the range, so we can remove all mappings in it in a single walk.
+    proof {
+        lemma_interp_at_facts(mem, pt@, 0, mem.cr3_spec().base, 0);
+        lemma_arch_facts(mem);
+        interp(mem, pt@).lemma_remove_range_refines(vaddr as nat, len as nat);
+    }
+    let res = unmap_range_aux(mem, *pt, 0, mem.cr3().base, 0, vaddr, end);
+    *pt = Ghost(res@.0);
+    Ok(())
+}
+
+/// The first layer, starting at `layer`, whose entry size can be used to map a frame at `vaddr`
+/// to `paddr` without exceeding `len`. The last layer is used if no other layer fits.
+pub open spec fn region_layer(arch: Arch, layer: nat, vaddr: nat, paddr: nat, len: nat) -> nat
+    decreases arch.layers.len() - layer
+{
+    if layer + 1 >= arch.layers.len() {
+        layer
+    } else {
+        let size = arch.entry_size(layer);
+        if aligned(vaddr, size) && aligned(paddr, size) && size <= len {
+            layer
+        } else {
+            region_layer(arch, layer + 1, vaddr, paddr, len)
+        }
+    }
+}
+
+/// The entries that map `len` bytes at `vaddr` to the physical memory at `paddr`, using the
+/// largest possible page size at every point. Pages can't be mapped in layer 0, so we start
+/// looking at layer 1.
+pub open spec fn region_ptes(vaddr: nat, paddr: nat, len: nat, flags: Flags) -> Seq<PageTableEntry>
+    decreases len
+{
+    let size = x86_arch_spec.entry_size(region_layer(x86_arch_spec, 1, vaddr, paddr, len));
+    if len == 0 || size == 0 || len < size {
+        seq![]
+    } else {
+        seq![PageTableEntry { frame: MemRegion { base: paddr, size }, flags }]
+            + region_ptes(vaddr + size, paddr + size, (len - size) as nat, flags)
+    }
+}
+
+proof fn lemma_region_layer(vaddr: nat, paddr: nat, len: nat, layer: nat)
+    requires
+        1 <= layer < X86_NUM_LAYERS,
+        aligned(vaddr, PAGE_SIZE as nat),
+        aligned(paddr, PAGE_SIZE as nat),
+        PAGE_SIZE <= len,
+    ensures
+        ({
+            let l = region_layer(x86_arch_spec, layer, vaddr, paddr, len);
+            let size = x86_arch_spec.entry_size(l);
+            &&& layer <= l < X86_NUM_LAYERS
+            &&& size == L1_ENTRY_SIZE || size == L2_ENTRY_SIZE || size == L3_ENTRY_SIZE
+            &&& aligned(vaddr, size)
+            &&& aligned(paddr, size)
+            &&& size <= len
+            &&& aligned(size, PAGE_SIZE as nat)
+        }),
+    decreases X86_NUM_LAYERS - layer
+{
+    if layer + 1 < X86_NUM_LAYERS {
+        let size = x86_arch_spec.entry_size(layer);
+        if !(aligned(vaddr, size) && aligned(paddr, size) && size <= len) {
+            lemma_region_layer(vaddr, paddr, len, layer + 1);
+        }
+    }
+    assert(aligned(L1_ENTRY_SIZE as nat, PAGE_SIZE as nat)) by (compute_only);
+    assert(aligned(L2_ENTRY_SIZE as nat, PAGE_SIZE as nat)) by (compute_only);
+    assert(aligned(L3_ENTRY_SIZE as nat, PAGE_SIZE as nat)) by (compute_only);
+}
+
+/// The entries of `region_ptes` cover exactly the given region and each of them can be mapped.
+proof fn lemma_region_ptes(vaddr: nat, paddr: nat, len: nat, flags: Flags)
+    requires
+        aligned(vaddr, PAGE_SIZE as nat),
+        aligned(paddr, PAGE_SIZE as nat),
+        aligned(len, PAGE_SIZE as nat),
+    ensures
+        range_size(region_ptes(vaddr, paddr, len, flags)) == len,
+        forall|i: int| 0 <= i < region_ptes(vaddr, paddr, len, flags).len() ==> {
+            let ptes = region_ptes(vaddr, paddr, len, flags);
+            &&& (#[trigger] ptes[i]).frame.base == paddr + range_size(ptes.take(i))
+            &&& ptes[i].flags == flags
+            &&& aligned(range_entry_base(vaddr, ptes, i), ptes[i].frame.size)
+            &&& aligned(ptes[i].frame.base, ptes[i].frame.size)
+            &&& x86_arch_spec.contains_entry_size_at_index_atleast(ptes[i].frame.size, 1)
+        },
+    decreases len
+{
+    let ptes = region_ptes(vaddr, paddr, len, flags);
+    if len == 0 {
+        assert(ptes =~= seq![]);
+    } else {
+        assert(PAGE_SIZE <= len) by (nonlinear_arith)
+            requires aligned(len, PAGE_SIZE as nat), len > 0, PAGE_SIZE == 4096;
+        lemma_region_layer(vaddr, paddr, len, 1);
+        let l = region_layer(x86_arch_spec, 1, vaddr, paddr, len);
+        let size = x86_arch_spec.entry_size(l);
+        let pte = PageTableEntry { frame: MemRegion { base: paddr, size }, flags };
+        let rest = region_ptes(vaddr + size, paddr + size, (len - size) as nat, flags);
+        extra::mod_add_zero(vaddr, size, PAGE_SIZE as nat);
+        extra::mod_add_zero(paddr, size, PAGE_SIZE as nat);
+        extra::subtract_mod_eq_zero(size, len, PAGE_SIZE as nat);
+        lemma_region_ptes(vaddr + size, paddr + size, (len - size) as nat, flags);
+        assert(ptes === seq![pte] + rest);
+        lemma_range_size_cons(pte, rest);
+        assert forall|i: int| 0 <= i < ptes.len() implies {
+            &&& (#[trigger] ptes[i]).frame.base == paddr + range_size(ptes.take(i))
+            &&& ptes[i].flags == flags
+            &&& aligned(range_entry_base(vaddr, ptes, i), ptes[i].frame.size)
+            &&& aligned(ptes[i].frame.base, ptes[i].frame.size)
+            &&& x86_arch_spec.contains_entry_size_at_index_atleast(ptes[i].frame.size, 1)
+        } by {
+            if i == 0 {
+                assert(ptes.take(0) =~= seq![]);
+                assert(x86_arch_spec.entry_size(l) == size);
+            } else {
+                assert(ptes.take(i) =~= seq![pte] + rest.take(i - 1));
+                lemma_range_size_cons(pte, rest.take(i - 1));
+                assert(ptes[i] === rest[i - 1]);
+                assert(range_entry_base(vaddr, ptes, i) == range_entry_base(vaddr + size, rest, i - 1));
+            }
+        }
+    }
+}
+
+/// Skipping the first `i` entries of `region_ptes` leaves the entries of the rest of the region.
+proof fn lemma_region_ptes_skip(vaddr: nat, paddr: nat, len: nat, flags: Flags, i: int)
+    requires
+        aligned(vaddr, PAGE_SIZE as nat),
+        aligned(paddr, PAGE_SIZE as nat),
+        aligned(len, PAGE_SIZE as nat),
+        0 <= i <= region_ptes(vaddr, paddr, len, flags).len(),
+    ensures
+        ({
+            let ptes = region_ptes(vaddr, paddr, len, flags);
+            let offset = range_size(ptes.take(i));
+            &&& i <= offset <= len
+            &&& aligned(offset, PAGE_SIZE as nat)
+            &&& ptes.skip(i) === region_ptes(vaddr + offset, paddr + offset, (len - offset) as nat, flags)
+            &&& (i < ptes.len()) == (offset < len)
+        }),
+    decreases i
+{
+    let ptes = region_ptes(vaddr, paddr, len, flags);
+    if len > 0 {
+        assert(PAGE_SIZE <= len) by (nonlinear_arith)
+            requires aligned(len, PAGE_SIZE as nat), len > 0, PAGE_SIZE == 4096;
+        lemma_region_layer(vaddr, paddr, len, 1);
+    }
+    if i == 0 {
+        assert(ptes.take(0) =~= seq![]);
+        assert(ptes.skip(0) =~= ptes);
+        assert(aligned(0, PAGE_SIZE as nat));
+    } else {
+        let size = x86_arch_spec.entry_size(region_layer(x86_arch_spec, 1, vaddr, paddr, len));
+        let pte = PageTableEntry { frame: MemRegion { base: paddr, size }, flags };
+        let rest = region_ptes(vaddr + size, paddr + size, (len - size) as nat, flags);
+        extra::mod_add_zero(vaddr, size, PAGE_SIZE as nat);
+        extra::mod_add_zero(paddr, size, PAGE_SIZE as nat);
+        extra::subtract_mod_eq_zero(size, len, PAGE_SIZE as nat);
+        assert(ptes === seq![pte] + rest);
+        lemma_region_ptes_skip(vaddr + size, paddr + size, (len - size) as nat, flags, i - 1);
+        let offset = range_size(rest.take(i - 1));
+        assert(ptes.take(i) =~= seq![pte] + rest.take(i - 1));
+        lemma_range_size_cons(pte, rest.take(i - 1));
+        assert(ptes.skip(i) =~= rest.skip(i - 1));
+        extra::mod_add_zero(size, offset, PAGE_SIZE as nat);
+        assert(vaddr + size + offset == vaddr + (size + offset));
+        assert(paddr + size + offset == paddr + (size + offset));
+    }
+}
+
+/// The entries of `region_ptes`, each computed from the part of the region that remains after the
+/// entries before it.
+struct RegionEntries {
+    vaddr: usize,
+    paddr: usize,
+    len: usize,
+    flags: Flags,
+}
+
+impl RangeEntries for RegionEntries {
+    open spec fn spec_entries(&self) -> Seq<PageTableEntry> {
+        region_ptes(self.vaddr as nat, self.paddr as nat, self.len as nat, self.flags)
+    }
+
+    open spec fn wf(&self) -> bool {
+        &&& aligned(self.vaddr as nat, PAGE_SIZE as nat)
+        &&& aligned(self.paddr as nat, PAGE_SIZE as nat)
+        &&& aligned(self.len as nat, PAGE_SIZE as nat)
+        &&& self.vaddr + self.len <= MAX_BASE
+        &&& self.paddr + self.len <= MAX_PHYADDR
+    }
+
+    fn has_entry(&self, i: usize, offset: usize) -> (res: bool) {
+        proof { lemma_region_ptes_skip(self.vaddr as nat, self.paddr as nat, self.len as nat, self.flags, i as int); }
+        offset < self.len
+    }
+
+    fn entry(&self, i: usize, offset: usize) -> (res: PageTableEntryExec) {
+        let ghost rest_len = (self.len - offset) as nat;
+        proof {
+            lemma_region_ptes_skip(self.vaddr as nat, self.paddr as nat, self.len as nat, self.flags, i as int);
+            extra::mod_add_zero(self.vaddr as nat, offset as nat, PAGE_SIZE as nat);
+            extra::mod_add_zero(self.paddr as nat, offset as nat, PAGE_SIZE as nat);
+            extra::subtract_mod_eq_zero(offset as nat, self.len as nat, PAGE_SIZE as nat);
+            assert(PAGE_SIZE <= rest_len) by (nonlinear_arith)
+                requires aligned(rest_len, PAGE_SIZE as nat), rest_len > 0, PAGE_SIZE == 4096;
+            lemma_region_layer((self.vaddr + offset) as nat, (self.paddr + offset) as nat, rest_len, 1);
+        }
+        let arch = x86_arch_exec();
+        let mut layer: usize = 1;
+        while layer + 1 < arch.layers.len()
+            && !(aligned_exec(self.vaddr + offset, arch.entry_size(layer))
+                && aligned_exec(self.paddr + offset, arch.entry_size(layer))
+                && arch.entry_size(layer) <= self.len - offset)
+            invariant
+                arch@ === x86_arch_spec,
+                arch.layers@.len() == X86_NUM_LAYERS,
+                1 <= layer < X86_NUM_LAYERS,
+                offset < self.len,
+                self.vaddr + self.len <= MAX_BASE,
+                self.paddr + self.len <= MAX_PHYADDR,
+                region_layer(x86_arch_spec, layer as nat, (self.vaddr + offset) as nat, (self.paddr + offset) as nat, rest_len)
+                    == region_layer(x86_arch_spec, 1, (self.vaddr + offset) as nat, (self.paddr + offset) as nat, rest_len),
+        {
+            layer = layer + 1;
+        }
+        let size = arch.entry_size(layer);
+        proof {
+            let ptes = self.spec_entries();
+            assert(ptes[i as int] === ptes.skip(i as int)[0]);
+            assert(region_ptes((self.vaddr + offset) as nat, (self.paddr + offset) as nat, rest_len, self.flags)[0]
+                === PageTableEntry { frame: MemRegion { base: (self.paddr + offset) as nat, size: size as nat }, flags: self.flags });
+        }
+        PageTableEntryExec {
+            frame: MemRegionExec { base: self.paddr + offset, size },
+            flags: clone_flags(&self.flags),
+        }
+    }
+}
+
+/// Maps the `len` bytes of physically contiguous memory at `paddr` to `vaddr`. At every point
+/// this uses the largest page size that the virtual and physical address are aligned to and that
+/// fits into the remaining region. The entries are computed while walking the page table, which
+/// happens only once for the whole region.
+pub fn map_region(mem: &mut mem::PageTableMemory, pt: &mut Ghost<PTDir>, vaddr: usize, paddr: usize, len: usize, flags: Flags) -> (res: Result<(),()>)
+    requires
+        inv(&*old(mem), old(pt)@),
+        interp(&*old(mem), old(pt)@).inv(),
+        old(mem).inv(),
+        aligned(vaddr as nat, PAGE_SIZE as nat),
+        aligned(paddr as nat, PAGE_SIZE as nat),
+        aligned(len as nat, PAGE_SIZE as nat),
+        vaddr + len <= MAX_BASE,
+        paddr + len <= MAX_PHYADDR,
+        old(mem).alloc_available_pages() >= (old(mem).num_layers_spec() - 1) * region_ptes(vaddr as nat, paddr as nat, len as nat, flags).len(),
+    ensures
+        inv(mem, pt@),
+        interp(mem, pt@).inv(),
+        mem.num_layers_spec() == old(mem).num_layers_spec(),
+        // The entries cover exactly the region: each one maps to the physical memory at the same
+        // offset into the region as its virtual address and together they have size `len`.
+        range_size(region_ptes(vaddr as nat, paddr as nat, len as nat, flags)) == len,
+        forall|i: int| 0 <= i < region_ptes(vaddr as nat, paddr as nat, len as nat, flags).len() ==> {
+            let ptes = region_ptes(vaddr as nat, paddr as nat, len as nat, flags);
+            &&& (#[trigger] ptes[i]).frame.base == paddr + range_size(ptes.take(i))
+            &&& ptes[i].flags == flags
+        },
+        // Refinement of l1
+        match res {
+            Ok(_)  => Ok(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).map_range(vaddr as nat, region_ptes(vaddr as nat, paddr as nat, len as nat, flags)),
+            Err(_) => Err(interp(mem, pt@)) === interp(&*old(mem), old(pt)@).map_range(vaddr as nat, region_ptes(vaddr as nat, paddr as nat, len as nat, flags)),
+        },
+        // Refinement of l0
+        match res {
+            Ok(_)  => Ok(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().map_range(vaddr as nat, region_ptes(vaddr as nat, paddr as nat, len as nat, flags)),
+            Err(_) => Err(interp(mem, pt@).interp()) === interp(&*old(mem), old(pt)@).interp().map_range(vaddr as nat, region_ptes(vaddr as nat, paddr as nat, len as nat, flags)),
+        },
+{
+    let ghost all_ptes = region_ptes(vaddr as nat, paddr as nat, len as nat, flags);
+    let entries = RegionEntries { vaddr, paddr, len, flags };
+    proof {
+        lemma_region_ptes(vaddr as nat, paddr as nat, len as nat, flags);
+        x86_arch_spec_upper_bound();
+        lemma_arch_facts(&*old(mem));
+        assert forall|i: int| 0 <= i < all_ptes.len() implies {
+            &&& #[trigger] accepted_mapping(range_entry_base(vaddr as nat, all_ptes, i), all_ptes[i])
+            &&& interp(&*old(mem), old(pt)@).accepted_mapping(range_entry_base(vaddr as nat, all_ptes, i), all_ptes[i])
+        } by {
+            lemma_range_size_take(all_ptes, i);
+            assert(range_entry_base(vaddr as nat, all_ptes, i) + all_ptes[i].frame.size <= MAX_BASE);
+            assert(all_ptes[i].frame.base < paddr + len);
+        }
+    }
+    map_range(mem, pt, vaddr, &entries)
+}
+
+pub proof fn lemma_no_entries_implies_interp_at_aux_no_entries(mem: mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize, base_vaddr: nat, init: Seq<l1::NodeEntry>)
+    requires
+        mem.regions() == set![mem.cr3_spec()@],
+        (forall|i: nat| i < 512 ==> mem.region_view(mem.cr3_spec()@)[i as int] == 0),
+        layer == 0,
+        inv_at(&mem, pt, layer, ptr),
+        forall|i: nat| i < init.len() ==> init[i as int] == l1::NodeEntry::Empty(),
+        init.len() <= 512,
+    ensures
+        ({ let res = interp_at_aux(&mem, pt, layer, ptr, base_vaddr, init);
+            &&& res.len() == 512
+            &&& forall|i: nat| i < res.len() ==> res[i as int] == l1::NodeEntry::Empty()
+        })
+    decreases 512 - init.len()
+{
+    lemma_new_seq::<Option<PTDir>>(512, Option::None);
+    let res = interp_at_aux(&mem, pt, layer, ptr, base_vaddr, init);
+    if init.len() >= 512 {
+    } else {
+        let entry = interp_at_entry(&mem, pt, layer, ptr, base_vaddr, init.len());
+        assert(ghost_pt_matches_structure(&mem, pt, layer, ptr));
+        assert forall|i: nat| i < 512 implies view_at(&mem, pt, layer, ptr, i).is_Empty() by {
+            let entry = mem.spec_read(i, pt.region);
+            assert((entry & (1u64 << 0)) != (1u64 << 0)) by (bit_vector) requires entry == 0u64;
+        };
+        assert(entry == l1::NodeEntry::Empty());
+        lemma_no_entries_implies_interp_at_aux_no_entries(mem, pt, layer, ptr, base_vaddr, init.push(entry));
+    }
+}
+
 }
 
 } // verus!
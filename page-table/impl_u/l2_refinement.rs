@@ -1,18 +1,144 @@
 use vstd::prelude::*;
-use vstd::assert_by_contradiction;
 
-use crate::definitions_t::{ Flags, x86_arch_spec, axiom_x86_arch_exec_spec, MAX_BASE, L0_ENTRY_SIZE, L1_ENTRY_SIZE, L2_ENTRY_SIZE, L3_ENTRY_SIZE, aligned, new_seq, bitmask_inc };
-use crate::definitions_t::{ PageTableEntry, PageTableEntryExec, MemRegion};
-use crate::spec_t::impl_spec;
+use crate::definitions_t::{ x86_arch_spec, x86_la57_arch_spec, x86_arch_spec_with_layers, L0_ENTRY_SIZE,
+L1_ENTRY_SIZE, L2_ENTRY_SIZE, L3_ENTRY_SIZE, PML5_ENTRY_SIZE, MAX_LA57_BASE, X86_NUM_LAYERS, X86_LA57_NUM_LAYERS,
+aligned, bitmask_inc };
+use crate::definitions_t::{ PageTableEntry, MemRegion, Flags, MemoryType, PAGE_SIZE };
+use crate::definitions_u::{ permissive_flags, lemma_x86_arch_spec_with_layers };
 use crate::spec_t::mem;
-use crate::spec_t::hardware::{ interp_pt_mem, l0_bits, l1_bits, l2_bits, l3_bits, valid_pt_walk, read_entry, GhostPageDirectoryEntry, nat_to_u64 };
+use crate::spec_t::hardware::{ interp_pt_mem, l0_bits, l1_bits, l2_bits, l3_bits, valid_pt_walk, walk_index,
+GhostPageDirectoryEntry, nat_to_u64 };
+use crate::spec_t::entry_format::{ ArchEntryFormat, DecodedEntry, X86EntryFormat, walk, valid_walk,
+read_decoded_entry };
 
-use crate::definitions_u::{ lemma_new_seq, x86_arch_inv };
 use crate::impl_u::l1;
+use crate::impl_u::indexing;
 use crate::impl_u::l2_impl::{ PT, PTDir };
 
 verus! {
 
+// The refinement of the page table walk holds for any entry format: If every entry of the paging
+// structure at `dir_addr` decodes to the corresponding entry of the directory `d`, the walk finds
+// exactly the mappings of `d`. `PT` provides this for `X86EntryFormat`.
+
+/// The paging structure at `dir_addr` decodes to the directory `d` in the entry format `F`
+pub open spec fn describes<F: ArchEntryFormat>(mem: mem::PageTableMemory, d: l1::Directory, dir_addr: nat) -> bool
+    decreases d.arch.layers.len() - d.layer, 1nat
+        when d.layer < d.arch.layers.len()
+{
+    &&& d.arch == F::arch(mem.num_layers_spec())
+    &&& forall|i: nat| i < d.entries.len() ==> #[trigger] entry_describes::<F>(mem, d, dir_addr, i)
+}
+
+pub open spec fn entry_describes<F: ArchEntryFormat>(mem: mem::PageTableMemory, d: l1::Directory, dir_addr: nat, i: nat) -> bool
+    decreases d.arch.layers.len() - d.layer, 0nat
+        when d.layer < d.arch.layers.len()
+{
+    match read_decoded_entry::<F>(mem, dir_addr, d.layer, i) {
+        DecodedEntry::Directory { addr, flags } => {
+            &&& d.entries[i as int].is_Directory()
+            &&& d.entries[i as int].get_Directory_0().layer == d.layer + 1
+            &&& d.entries[i as int].get_Directory_0().arch == d.arch
+            &&& d.layer + 1 < d.arch.layers.len()
+            // Directories don't restrict the mappings below them
+            &&& flags.is_writable && !flags.is_supervisor && !flags.disable_execute
+            &&& describes::<F>(mem, d.entries[i as int].get_Directory_0(), addr)
+        },
+        DecodedEntry::Page { addr, flags } => {
+            d.entries[i as int] == l1::NodeEntry::Page(PageTableEntry {
+                frame: MemRegion { base: addr, size: d.entry_size() },
+                flags,
+            })
+        },
+        DecodedEntry::Empty => d.entries[i as int].is_Empty(),
+    }
+}
+
+/// The index `F` selects in a paging structure is the index of the entry containing the address
+pub open spec fn index_matches_arch<F: ArchEntryFormat>(num_layers: nat) -> bool {
+    let arch = F::arch(num_layers);
+    forall|layer: nat, base: nat, addr: nat|
+        #![trigger arch.index_for_vaddr(layer, base, addr)]
+        layer < arch.layers.len()
+        && aligned(base, arch.entry_size(layer) * arch.num_entries(layer))
+        && base <= addr < arch.upper_vaddr(layer, base)
+        && addr < arch.upper_vaddr(0, 0)
+        ==> F::index(num_layers, layer, nat_to_u64(addr)) == arch.index_for_vaddr(layer, base, addr)
+}
+
+/// The walk through the paging structure at `dir_addr` described by `d`, starting with the
+/// permissive flags of the directories above it, finds exactly the mappings of `d`.
+pub proof fn lemma_walk_describes<F: ArchEntryFormat>(mem: mem::PageTableMemory, d: l1::Directory, dir_addr: nat, addr: nat)
+    requires
+        d.inv(),
+        describes::<F>(mem, d, dir_addr),
+        index_matches_arch::<F>(mem.num_layers_spec()),
+        d.base_vaddr <= addr < d.upper_vaddr(),
+        addr < d.arch.upper_vaddr(0, 0),
+        addr <= u64::MAX,
+    ensures
+        match walk::<F>(mem, d.layer, dir_addr, nat_to_u64(addr), permissive_flags) {
+            Some(pte) => d.interp().map.contains_pair(addr, pte),
+            None      => !d.interp().map.contains_key(addr),
+        },
+    decreases d.arch.layers.len() - d.layer
+{
+    let n = mem.num_layers_spec();
+    let es = d.entry_size();
+    let ne = d.num_entries();
+    let idx = d.index_for_vaddr(addr);
+    assert(nat_to_u64(addr) as nat == addr);
+    indexing::lemma_index_from_base_and_addr(d.base_vaddr, addr, es, ne);
+    indexing::lemma_entry_base_from_index(d.base_vaddr, idx, es);
+    assert(aligned(d.base_vaddr, es)) by {
+        crate::extra::mod_mult_zero_implies_mod_zero(d.base_vaddr, es, ne);
+    };
+    assert(F::index(n, d.layer, nat_to_u64(addr)) == idx);
+    assert(entry_describes::<F>(mem, d, dir_addr, idx));
+    d.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(idx);
+    match read_decoded_entry::<F>(mem, dir_addr, d.layer, idx) {
+        DecodedEntry::Directory { addr: next_dir_addr, flags } => {
+            let sub = d.entries[idx as int].get_Directory_0();
+            assert(d.directories_obey_invariant());
+            assert(sub.inv());
+            assert(sub.base_vaddr == d.entry_base(idx));
+            // The directory covers exactly the entry's range
+            assert(sub.upper_vaddr() == d.entry_base(idx + 1)) by {
+                assert(d.arch.entry_size_is_next_layer_size(d.layer));
+                let sub_es = sub.entry_size();
+                let sub_ne = sub.num_entries();
+                assert(sub.base_vaddr + sub_ne * sub_es == d.entry_base(idx) + es) by (nonlinear_arith)
+                    requires es == sub_es * sub_ne;
+            };
+            assert(d.interp_of_entry(idx).map == sub.interp().map);
+            // The walk keeps the permissive flags it started with
+            assert(Flags {
+                is_writable: permissive_flags.is_writable && flags.is_writable,
+                is_supervisor: permissive_flags.is_supervisor || flags.is_supervisor,
+                disable_execute: permissive_flags.disable_execute || flags.disable_execute,
+                memory_type: permissive_flags.memory_type,
+                is_global: permissive_flags.is_global,
+            } == permissive_flags);
+            lemma_walk_describes::<F>(mem, sub, next_dir_addr, addr);
+        },
+        DecodedEntry::Page { addr: page_addr, flags } => {
+            assert(d.interp_of_entry(idx).map == map![d.entry_base(idx) => d.entries[idx as int].get_Page_0()]);
+            // The entry contains an aligned address iff it's the entry's base
+            assert(aligned(addr, es) <==> addr == d.entry_base(idx));
+            assert(Flags {
+                is_writable: permissive_flags.is_writable && flags.is_writable,
+                is_supervisor: permissive_flags.is_supervisor || flags.is_supervisor,
+                disable_execute: permissive_flags.disable_execute || flags.disable_execute,
+                memory_type: flags.memory_type,
+                is_global: flags.is_global,
+            } == flags);
+        },
+        DecodedEntry::Empty => {
+            assert(d.interp_of_entry(idx).map === map![]);
+        },
+    }
+}
+
 pub proof fn lemma_page_table_walk_interp()
     ensures
         forall|mem: mem::PageTableMemory, pt: PTDir| #![auto] PT::inv(&mem, pt) && PT::interp(&mem, pt).inv() ==> PT::interp(&mem, pt).interp().map === interp_pt_mem(mem)
@@ -20,590 +146,224 @@ pub proof fn lemma_page_table_walk_interp()
     assert forall|mem: mem::PageTableMemory, pt: PTDir| #![auto]
         PT::inv(&mem, pt) && PT::interp(&mem, pt).inv() implies PT::interp(&mem, pt).interp().map === interp_pt_mem(mem)
     by {
-        let m1 = interp_pt_mem(mem);
-        let m2 = PT::interp(&mem, pt).interp().map;
-        PT::interp(&mem, pt).lemma_inv_implies_interp_inv();
-        assert(PT::interp(&mem, pt).interp().inv());
-        lemma_page_table_walk_interp_aux_1(mem, pt);
-        lemma_page_table_walk_interp_aux_2(mem, pt);
-        assert(m1 =~= m2) by {
-            assert forall|addr: nat| m1.dom().contains(addr) <==> m2.dom().contains(addr) by {
-                assert(m1.dom().contains(addr) ==> m2.contains_pair(addr, m1[addr]));
-                assert(m2.dom().contains(addr) ==> m1.contains_pair(addr, m2[addr]));
-            };
-            assert forall|addr: nat| #[trigger] m1.contains_key(addr) && m2.contains_key(addr) implies m1[addr] == m2[addr] by {
-                assert(m1.contains_pair(addr, m1[addr]));
-                assert(m2.contains_pair(addr, m1[addr]));
-            };
-        };
+        lemma_page_table_walk_interp_aux(mem, pt);
     };
 }
 
-pub proof fn lemma_page_table_walk_interp_aux_1(mem: mem::PageTableMemory, pt: PTDir)
-    requires PT::inv(&mem, pt) && PT::interp(&mem, pt).inv()
+proof fn lemma_page_table_walk_interp_aux(mem: mem::PageTableMemory, pt: PTDir)
+    requires
+        PT::inv(&mem, pt),
+        PT::interp(&mem, pt).inv(),
     ensures
-        forall|k, v| interp_pt_mem(mem).contains_pair(k, v)
-            ==> #[trigger] PT::interp(&mem, pt).interp().map.contains_pair(k, v)
+        PT::interp(&mem, pt).interp().map === interp_pt_mem(mem)
 {
+    let n = mem.num_layers_spec();
+    let d = PT::interp(&mem, pt);
+    let root = mem.cr3_spec()@.base;
     let m1 = interp_pt_mem(mem);
-    let m2 = PT::interp(&mem, pt).interp().map;
-    PT::interp(&mem, pt).lemma_inv_implies_interp_inv();
-    assert(PT::interp(&mem, pt).interp().inv());
-    assert forall|addr: nat, pte: PageTableEntry|
-        m1.contains_pair(addr, pte) implies #[trigger] m2.contains_pair(addr, pte)
-    by {
-        assert(addr == addr as u64);
-        let addr: u64 = addr as u64;
-        assert(addr < MAX_BASE);
-        let pte = choose|pte: PageTableEntry| valid_pt_walk(mem, addr, pte);
-        assert(valid_pt_walk(mem, addr as u64, pte));
-        PT::lemma_interp_at_facts(&mem, pt, 0, mem.cr3_spec().base, 0);
-
-        let l0_idx_u64:  u64 = l0_bits!(addr);
-        let l0_idx:      nat = l0_idx_u64 as nat;
-        let l1_idx_u64:  u64 = l1_bits!(addr);
-        let l1_idx:      nat = l1_idx_u64 as nat;
-        let l2_idx_u64:  u64 = l2_bits!(addr);
-        let l2_idx:      nat = l2_idx_u64 as nat;
-        let l3_idx_u64:  u64 = l3_bits!(addr);
-        let l3_idx:      nat = l3_idx_u64 as nat;
-        assert(forall|a:u64| (a & bitmask_inc!(0u64,8u64) == a) ==> a < 512) by (bit_vector);
-        assert(l0_idx < 512 && l1_idx < 512 && l2_idx < 512 && l3_idx < 512) by {
-            assert(((addr & bitmask_inc!(12u64,20u64)) >> 12u64) & bitmask_inc!(0u64,8u64) == ((addr & bitmask_inc!(12u64,20u64)) >> 12u64)) by (bit_vector);
-            assert(((addr & bitmask_inc!(21u64,29u64)) >> 21u64) & bitmask_inc!(0u64,8u64) == ((addr & bitmask_inc!(21u64,29u64)) >> 21u64)) by (bit_vector);
-            assert(((addr & bitmask_inc!(30u64,38u64)) >> 30u64) & bitmask_inc!(0u64,8u64) == ((addr & bitmask_inc!(30u64,38u64)) >> 30u64)) by (bit_vector);
-            assert(((addr & bitmask_inc!(39u64,47u64)) >> 39u64) & bitmask_inc!(0u64,8u64) == ((addr & bitmask_inc!(39u64,47u64)) >> 39u64)) by (bit_vector);
+    let m2 = d.interp().map;
+    PT::lemma_interp_at_facts(&mem, pt, 0, mem.cr3_spec().base, 0);
+    PT::lemma_arch_facts(&mem);
+    lemma_interp_at_describes(mem, pt, 0, mem.cr3_spec().base, 0);
+    lemma_x86_index_matches_arch(n);
+    d.lemma_inv_implies_interp_inv();
+    assert(d.upper_vaddr() == X86EntryFormat::arch(n).upper_vaddr(0, 0));
+    // The walk starts with the permissive flags
+    assert(permissive_flags == Flags {
+        is_writable: true,
+        is_supervisor: false,
+        disable_execute: false,
+        memory_type: MemoryType::WriteBack,
+        is_global: false,
+    });
+    assert forall|addr: nat| addr < d.upper_vaddr() implies {
+        match #[trigger] walk::<X86EntryFormat>(mem, 0, root, nat_to_u64(addr), permissive_flags) {
+            Some(pte) => m2.contains_pair(addr, pte),
+            None      => !m2.contains_key(addr),
+        }
+    } by {
+        lemma_walk_describes::<X86EntryFormat>(mem, d, root, addr);
+    };
+    assert forall|addr: nat| #[trigger] m2.contains_key(addr) implies addr < d.upper_vaddr() by {
+        assert(d.interp().candidate_mapping_in_bounds(addr, m2[addr]));
+    };
+    assert(m1 =~= m2) by {
+        assert forall|addr: nat| m1.dom().contains(addr) <==> #[trigger] m2.dom().contains(addr) by {
+            if m2.dom().contains(addr) {
+                assert(valid_walk::<X86EntryFormat>(mem, nat_to_u64(addr), m2[addr]));
+            }
         };
-        assert(bitmask_inc!(39u64,47u64) == 0xFF80_0000_0000) by (compute);
-        assert(bitmask_inc!(30u64,38u64) == 0x007F_C000_0000) by (compute);
-        assert(bitmask_inc!(21u64,29u64) == 0x0000_3FE0_0000) by (compute);
-        assert(bitmask_inc!(12u64,20u64) == 0x0000_001F_F000) by (compute);
-        let interp_l0_dir   = PT::interp(&mem, pt);
-        let interp_l0_entry = PT::interp_at_entry(&mem, pt, 0, mem.cr3_spec().base, 0, l0_idx);
-        interp_l0_dir.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(l0_idx);
-        match read_entry(mem, mem.cr3_spec()@.base, 0, l0_idx) {
-            GhostPageDirectoryEntry::Directory {
-                addr: l0_dir_addr, flag_RW: l0_RW, flag_US: l0_US, flag_XD: l0_XD, ..
-            } => {
-                assert(interp_l0_entry.is_Directory());
-                let l1_base_vaddr = x86_arch_spec.entry_base(0, 0, l0_idx);
-                let l0_dir_ghost_pt = pt.entries[l0_idx as int].get_Some_0();
-                assert(PT::directories_obey_invariant_at(&mem, pt, 0, mem.cr3_spec().base));
-                assert(PT::inv_at(&mem, l0_dir_ghost_pt, 1, l0_dir_addr));
-                assert(interp_l0_dir.directories_obey_invariant());
-                assert(interp_l0_dir.entries[l0_idx as int].get_Directory_0().inv());
-                PT::lemma_interp_at_facts(&mem, l0_dir_ghost_pt, 1, l0_dir_addr, l1_base_vaddr);
-                let interp_l1_dir   = PT::interp_at(&mem, l0_dir_ghost_pt, 1, l0_dir_addr, l1_base_vaddr);
-                let interp_l1_entry = PT::interp_at_entry(&mem, l0_dir_ghost_pt, 1, l0_dir_addr, l1_base_vaddr, l1_idx);
-                interp_l1_dir.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(l1_idx);
-                match read_entry(mem, l0_dir_addr as nat, 1, l1_idx) {
-                    GhostPageDirectoryEntry::Page {
-                        addr: page_addr, flag_RW: l1_RW, flag_US: l1_US, flag_XD: l1_XD, ..
-                    } => {
-                        assert(aligned(addr as nat, L1_ENTRY_SIZE as nat));
-                        assert(pte == PageTableEntry {
-                            frame: MemRegion { base: page_addr as nat, size: L1_ENTRY_SIZE as nat },
-                            flags: Flags {
-                                is_writable:      l0_RW &&  l1_RW,
-                                is_supervisor:   !l0_US || !l1_US,
-                                disable_execute:  l0_XD ||  l1_XD
-                            }
-                        });
-
-                        assert(addr == ((l0_idx_u64 << 39u64) | (l1_idx_u64 << 30u64))) by (bit_vector)
-                            requires
-                                l0_idx_u64 == (addr & 0xFF80_0000_0000) >> 39,
-                                l1_idx_u64 == (addr & 0x007F_C000_0000) >> 30,
-                                addr < mul(512u64, mul(512, mul(512, mul(512, 4096)))),
-                                addr % mul(512, mul(512, 4096)) == 0;
-
-                        assert(add(mul(l0_idx_u64, mul(512u64, mul(512, mul(512, 4096)))), mul(l1_idx_u64, mul(512u64, mul(512, 4096)))) == l0_idx_u64 << 39u64 | l1_idx_u64 << 30u64) by (bit_vector)
-                            requires l0_idx_u64 < 512 && l1_idx_u64 < 512;
-                        // Previous assert proves: l0_idx * L0_ENTRY_SIZE + l1_idx * L1_ENTRY_SIZE == (l0_idx as u64) << 39u64 | (l1_idx as u64) << 30u64
-
-                        assert(interp_l1_dir.interp_of_entry(l1_idx).map.contains_pair(addr as nat, pte)); // unstable
-                        assert(interp_l1_dir.interp().map.contains_pair(addr as nat, pte));
-                        assert(interp_l0_dir.interp().map.contains_pair(addr as nat, pte));
-                        assert(m2.contains_pair(addr as nat, pte));
-                    },
-                    GhostPageDirectoryEntry::Directory {
-                        addr: l1_dir_addr, flag_RW: l1_RW, flag_US: l1_US, flag_XD: l1_XD, ..
-                    } => {
-                        assert(interp_l1_entry.is_Directory());
-                        let l2_base_vaddr = x86_arch_spec.entry_base(1, l1_base_vaddr, l1_idx);
-                        let l1_dir_ghost_pt = l0_dir_ghost_pt.entries[l1_idx as int].get_Some_0();
-                        assert(PT::directories_obey_invariant_at(&mem, l0_dir_ghost_pt, 1, l0_dir_addr));
-                        assert(PT::inv_at(&mem, l1_dir_ghost_pt, 2, l1_dir_addr));
-                        PT::lemma_interp_at_facts(&mem, l1_dir_ghost_pt, 2, l1_dir_addr, l2_base_vaddr);
-                        let interp_l2_dir   = PT::interp_at(&mem, l1_dir_ghost_pt, 2, l1_dir_addr, l2_base_vaddr);
-                        let interp_l2_entry = PT::interp_at_entry(&mem, l1_dir_ghost_pt, 2, l1_dir_addr, l2_base_vaddr, l2_idx);
-                        interp_l2_dir.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(l2_idx);
-                        match read_entry(mem, l1_dir_addr as nat, 2, l2_idx) {
-                            GhostPageDirectoryEntry::Page {
-                                addr: page_addr, flag_RW: l2_RW, flag_US: l2_US, flag_XD: l2_XD, ..
-                            } => {
-                                assert(aligned(addr as nat, L2_ENTRY_SIZE as nat));
-                                assert(pte == PageTableEntry {
-                                    frame: MemRegion { base: page_addr as nat, size: L2_ENTRY_SIZE as nat },
-                                    flags: Flags {
-                                        is_writable:      l0_RW &&  l1_RW &&  l2_RW,
-                                        is_supervisor:   !l0_US || !l1_US || !l2_US,
-                                        disable_execute:  l0_XD ||  l1_XD ||  l2_XD
-                                    }
-                                });
-
-                                assert(addr == ((l0_idx_u64 << 39u64) | (l1_idx_u64 << 30u64) | (l2_idx_u64 << 21u64))) by (bit_vector)
-                                    requires
-                                        l0_idx_u64 == (addr & 0xFF80_0000_0000) >> 39,
-                                        l1_idx_u64 == (addr & 0x007F_C000_0000) >> 30,
-                                        l2_idx_u64 == (addr & 0x0000_3FE0_0000) >> 21,
-                                        addr < mul(512u64, mul(512, mul(512, mul(512, 4096)))),
-                                        addr % mul(512, 4096) == 0;
-
-                                assert(add(add(
-                                        mul(l0_idx_u64, mul(512u64, mul(512, mul(512, 4096)))),
-                                        mul(l1_idx_u64, mul(512u64, mul(512, 4096)))),
-                                        mul(l2_idx_u64, mul(512, 4096)))
-                                       == l0_idx_u64 << 39u64 | l1_idx_u64 << 30u64 | l2_idx_u64 << 21u64) by (bit_vector)
-                                    requires l0_idx_u64 < 512 && l1_idx_u64 < 512 && l2_idx_u64 < 512;
-                                // Previous assert proves:
-                                // l0_idx * L0_ENTRY_SIZE + l1_idx * L1_ENTRY_SIZE + l2_idx * L2_ENTRY_SIZE
-                                // == (l0_idx as u64) << 39u64 | (l1_idx as u64) << 30u64 | (l2_idx as u64) << 21u64
-
-                                assert(interp_l2_dir.interp_of_entry(l2_idx).map.contains_pair(addr as nat, pte));
-                                assert(interp_l2_dir.interp().map.contains_pair(addr as nat, pte));
-                                assert(interp_l1_dir.interp().map.contains_pair(addr as nat, pte));
-                                assert(interp_l0_dir.interp().map.contains_pair(addr as nat, pte));
-                                assert(m2.contains_pair(addr as nat, pte));
-                            },
-                            GhostPageDirectoryEntry::Directory {
-                                addr: l2_dir_addr, flag_RW: l2_RW, flag_US: l2_US, flag_XD: l2_XD, ..
-                            } => {
-                                assert(interp_l2_entry.is_Directory());
-                                let l3_base_vaddr = x86_arch_spec.entry_base(2, l2_base_vaddr, l2_idx);
-                                let l2_dir_ghost_pt = l1_dir_ghost_pt.entries[l2_idx as int].get_Some_0();
-                                assert(PT::directories_obey_invariant_at(&mem, l1_dir_ghost_pt, 2, l1_dir_addr));
-                                assert(PT::inv_at(&mem, l2_dir_ghost_pt, 3, l2_dir_addr));
-                                PT::lemma_interp_at_facts(&mem, l2_dir_ghost_pt, 3, l2_dir_addr, l3_base_vaddr);
-                                let interp_l3_dir   = PT::interp_at(&mem, l2_dir_ghost_pt, 3, l2_dir_addr, l3_base_vaddr);
-                                let interp_l3_entry = PT::interp_at_entry(&mem, l2_dir_ghost_pt, 3, l2_dir_addr, l3_base_vaddr, l3_idx);
-                                interp_l3_dir.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(l3_idx);
-                                match read_entry(mem, l2_dir_addr as nat, 3, l3_idx) {
-                                    GhostPageDirectoryEntry::Page {
-                                        addr: page_addr, flag_RW: l3_RW, flag_US: l3_US, flag_XD: l3_XD, ..
-                                    } => {
-                                        assert(aligned(addr as nat, L3_ENTRY_SIZE as nat));
-                                        assert(pte == PageTableEntry {
-                                            frame: MemRegion { base: page_addr as nat, size: L3_ENTRY_SIZE as nat },
-                                            flags: Flags {
-                                                is_writable:      l0_RW &&  l1_RW &&  l2_RW &&  l3_RW,
-                                                is_supervisor:   !l0_US || !l1_US || !l2_US || !l3_US,
-                                                disable_execute:  l0_XD ||  l1_XD ||  l2_XD ||  l3_XD
-                                            }
-                                        });
-
-                                        assert(addr == ((l0_idx_u64 << 39u64) | (l1_idx_u64 << 30u64) | (l2_idx_u64 << 21u64) | (l3_idx_u64 << 12u64))) by (bit_vector)
-                                            requires
-                                                l0_idx_u64 == (addr & 0xFF80_0000_0000) >> 39,
-                                                l1_idx_u64 == (addr & 0x007F_C000_0000) >> 30,
-                                                l2_idx_u64 == (addr & 0x0000_3FE0_0000) >> 21,
-                                                l3_idx_u64 == (addr & 0x0000_001F_F000) >> 12,
-                                                addr < mul(512u64, mul(512, mul(512, mul(512, 4096)))),
-                                                addr % 4096 == 0;
-
-                                        assert(add(add(add(
-                                                mul(l0_idx_u64, mul(512u64, mul(512, mul(512, 4096)))),
-                                                mul(l1_idx_u64, mul(512u64, mul(512, 4096)))),
-                                                mul(l2_idx_u64, mul(512, 4096))),
-                                                mul(l3_idx_u64, 4096))
-                                               == l0_idx_u64 << 39u64 | l1_idx_u64 << 30u64 | l2_idx_u64 << 21u64 | l3_idx_u64 << 12u64) by (bit_vector)
-                                            requires l0_idx_u64 < 512 && l1_idx_u64 < 512 && l2_idx_u64 < 512 && l3_idx_u64 < 512;
-                                        // Previous assert proves:
-                                        // l0_idx * L0_ENTRY_SIZE + l1_idx * L1_ENTRY_SIZE + l2_idx * L2_ENTRY_SIZE + l3_idx * L3_ENTRY_SIZE
-                                        // == (l0_idx as u64) << 39u64 | (l1_idx as u64) << 30u64 | (l2_idx as u64) << 21u64 | (l3_idx as u64) << 12u64
-
-                                        assert(interp_l3_dir.interp_of_entry(l3_idx).map.contains_pair(addr as nat, pte));
-                                        assert(interp_l3_dir.interp().map.contains_pair(addr as nat, pte));
-                                        assert(interp_l2_dir.interp().map.contains_pair(addr as nat, pte));
-                                        assert(interp_l1_dir.interp().map.contains_pair(addr as nat, pte));
-                                        interp_l0_dir.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(l0_idx);
-                                        assert(interp_l0_dir.interp().map.contains_pair(addr as nat, pte));
-                                        assert(m1.contains_pair(addr as nat, pte));
-                                    },
-                                    GhostPageDirectoryEntry::Directory { .. } => assert(false),
-                                    GhostPageDirectoryEntry::Empty => assert(false),
-                                }
-                            },
-                            GhostPageDirectoryEntry::Empty => assert(false),
-                        }
-                    },
-                    GhostPageDirectoryEntry::Empty => assert(false),
-                }
-            },
-            _ => assert(false),
+        assert forall|addr: nat| #[trigger] m1.contains_key(addr) && m2.contains_key(addr) implies m1[addr] == m2[addr] by {
+            assert(valid_walk::<X86EntryFormat>(mem, nat_to_u64(addr), m1[addr]));
+            assert(walk::<X86EntryFormat>(mem, 0, root, nat_to_u64(addr), permissive_flags) == Some(m1[addr]));
         };
     };
 }
 
-pub proof fn lemma_page_table_walk_interp_aux_2(mem: mem::PageTableMemory, pt: PTDir)
-    requires PT::inv(&mem, pt) && PT::interp(&mem, pt).inv()
+/// The x86 entries of the page table decode to the entries of its interpretation.
+proof fn lemma_interp_at_describes(mem: mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize, base: nat)
+    requires
+        PT::inv_at(&mem, pt, layer, ptr),
+        PT::interp_at(&mem, pt, layer, ptr, base).inv(),
     ensures
-        forall|k| !interp_pt_mem(mem).contains_key(k)
-            ==> !(#[trigger] PT::interp(&mem, pt).interp().map.contains_key(k))
+        describes::<X86EntryFormat>(mem, PT::interp_at(&mem, pt, layer, ptr, base), ptr as nat),
+    decreases mem.num_layers_spec() - layer
 {
-    let m1 = interp_pt_mem(mem);
-    let m2 = PT::interp(&mem, pt).interp().map;
-    PT::interp(&mem, pt).lemma_inv_implies_interp_inv();
-    assert(PT::interp(&mem, pt).interp().inv());
-    assert forall|addr: nat| !m1.contains_key(addr) ==> !m2.contains_key(addr) by {
-        PT::lemma_interp_at_facts(&mem, pt, 0, mem.cr3_spec().base, 0);
-        PT::interp(&mem, pt).lemma_inv_implies_interp_inv();
-        if addr < MAX_BASE && (exists|pte: PageTableEntry| valid_pt_walk(mem, nat_to_u64(addr), pte)) {
-        } else {
-            if addr >= MAX_BASE {
-            } else {
-                assert(addr < MAX_BASE);
-                // Not all of these assertions may be necessary but the assertion after the `let
-                // addr` was unstable and seems okay now, so I'm not touching these.
-                assert(addr == addr as u64);
-                assert(nat_to_u64(addr) == addr);
-                assert(!exists|pte: PageTableEntry| valid_pt_walk(mem, nat_to_u64(addr), pte));
-                let addr: u64 = addr as u64;
-                assert(!exists|pte: PageTableEntry| valid_pt_walk(mem, addr, pte));
-                let l0_idx_u64:  u64 = l0_bits!(addr);
-                let l0_idx:      nat = l0_idx_u64 as nat;
-                let l1_idx_u64:  u64 = l1_bits!(addr);
-                let l1_idx:      nat = l1_idx_u64 as nat;
-                let l2_idx_u64:  u64 = l2_bits!(addr);
-                let l2_idx:      nat = l2_idx_u64 as nat;
-                let l3_idx_u64:  u64 = l3_bits!(addr);
-                let l3_idx:      nat = l3_idx_u64 as nat;
-                assert(forall|a:u64| (a & bitmask_inc!(0u64,8u64) == a) ==> a < 512) by (bit_vector);
-                assert(l0_idx < 512 && l1_idx < 512 && l2_idx < 512 && l3_idx < 512) by {
-                    assert(((addr & bitmask_inc!(12u64,20u64)) >> 12u64) & bitmask_inc!(0u64,8u64) == ((addr & bitmask_inc!(12u64,20u64)) >> 12u64)) by (bit_vector);
-                    assert(((addr & bitmask_inc!(21u64,29u64)) >> 21u64) & bitmask_inc!(0u64,8u64) == ((addr & bitmask_inc!(21u64,29u64)) >> 21u64)) by (bit_vector);
-                    assert(((addr & bitmask_inc!(30u64,38u64)) >> 30u64) & bitmask_inc!(0u64,8u64) == ((addr & bitmask_inc!(30u64,38u64)) >> 30u64)) by (bit_vector);
-                    assert(((addr & bitmask_inc!(39u64,47u64)) >> 39u64) & bitmask_inc!(0u64,8u64) == ((addr & bitmask_inc!(39u64,47u64)) >> 39u64)) by (bit_vector);
-                };
-                assert(bitmask_inc!(39u64,47u64) == 0xFF80_0000_0000) by (compute);
-                assert(bitmask_inc!(30u64,38u64) == 0x007F_C000_0000) by (compute);
-                assert(bitmask_inc!(21u64,29u64) == 0x0000_3FE0_0000) by (compute);
-                assert(bitmask_inc!(12u64,20u64) == 0x0000_001F_F000) by (compute);
-                let interp_l0_dir   = PT::interp(&mem, pt);
-                let interp_l0_entry = PT::interp_at_entry(&mem, pt, 0, mem.cr3_spec().base, 0, l0_idx);
-                interp_l0_dir.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(l0_idx);
-                match read_entry(mem, mem.cr3_spec()@.base, 0, l0_idx) {
-                    GhostPageDirectoryEntry::Directory {
-                        addr: l0_dir_addr, flag_RW: l0_RW, flag_US: l0_US, flag_XD: l0_XD, ..
-                    } => {
-                        assert(interp_l0_entry.is_Directory());
-                        let l1_base_vaddr = x86_arch_spec.entry_base(0, 0, l0_idx);
-                        let l0_dir_ghost_pt = pt.entries[l0_idx as int].get_Some_0();
-                        assert(PT::directories_obey_invariant_at(&mem, pt, 0, mem.cr3_spec().base));
-                        assert(PT::inv_at(&mem, l0_dir_ghost_pt, 1, l0_dir_addr));
-                        assert(interp_l0_dir.directories_obey_invariant());
-                        assert(interp_l0_dir.entries[l0_idx as int].get_Directory_0().inv());
-                        PT::lemma_interp_at_facts(&mem, l0_dir_ghost_pt, 1, l0_dir_addr, l1_base_vaddr);
-                        let interp_l1_dir   = PT::interp_at(&mem, l0_dir_ghost_pt, 1, l0_dir_addr, l1_base_vaddr);
-                        let interp_l1_entry = PT::interp_at_entry(&mem, l0_dir_ghost_pt, 1, l0_dir_addr, l1_base_vaddr, l1_idx);
-                        interp_l1_dir.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(l1_idx);
-
-                        let low_bits: u64 = addr % (L1_ENTRY_SIZE as u64);
-                        // This assert proves: ... == l0_idx_u64 * L0_ENTRY_SIZE + l1_idx_u64 * L1_ENTRY_SIZE + low_bits
-                        assert((l0_idx_u64 << 39u64) | (l1_idx_u64 << 30u64) | low_bits
-                               == add(add(mul(l0_idx_u64, mul(512, mul(512, mul(512, 4096)))),
-                                          mul(l1_idx_u64, mul(512, mul(512, 4096)))),
-                                          low_bits)) by (bit_vector)
-                            requires
-                                l1_idx_u64 == (addr & 0x007F_C000_0000) >> 30,
-                                low_bits == addr % mul(512, mul(512, 4096));
-                        assert(addr == ((l0_idx_u64 << 39u64) | (l1_idx_u64 << 30u64) | low_bits)) by (bit_vector)
-                            requires
-                                l0_idx_u64 == (addr & 0xFF80_0000_0000) >> 39,
-                                l1_idx_u64 == (addr & 0x007F_C000_0000) >> 30,
-                                addr < mul(512u64, mul(512, mul(512, mul(512, 4096)))),
-                                low_bits == addr % mul(512, mul(512, 4096));
-                        match read_entry(mem, l0_dir_addr as nat, 1, l1_idx) {
-                            GhostPageDirectoryEntry::Page {
-                                addr: page_addr, flag_RW: l1_RW, flag_US: l1_US, flag_XD: l1_XD, ..
-                            } => {
-                                assert_by_contradiction!(!aligned(addr as nat, L1_ENTRY_SIZE as nat), {
-                                    let pte = PageTableEntry {
-                                        frame: MemRegion { base: page_addr as nat, size: L1_ENTRY_SIZE as nat },
-                                        flags: Flags {
-                                            is_writable:      l0_RW &&  l1_RW,
-                                            is_supervisor:   !l0_US || !l1_US,
-                                            disable_execute:  l0_XD ||  l1_XD
-                                        }
-                                    };
-                                    assert(valid_pt_walk(mem, addr as u64, pte));
-                                });
-                                assert(!interp_l1_dir.interp_of_entry(l1_idx).map.contains_key(addr as nat));
-                                assert(!interp_l1_dir.interp().map.contains_key(addr as nat));
-                                assert(!interp_l0_dir.interp().map.contains_key(addr as nat));
-                                assert(!m2.contains_key(addr as nat));
-                            }
-                            GhostPageDirectoryEntry::Directory {
-                                addr: l1_dir_addr, flag_RW: l1_RW, flag_US: l1_US, flag_XD: l1_XD, ..
-                            } => {
-                                assert(interp_l1_entry.is_Directory());
-                                let l2_base_vaddr = x86_arch_spec.entry_base(1, l1_base_vaddr, l1_idx);
-                                let l1_dir_ghost_pt = l0_dir_ghost_pt.entries[l1_idx as int].get_Some_0();
-                                assert(PT::directories_obey_invariant_at(&mem, l0_dir_ghost_pt, 1, l0_dir_addr));
-                                assert(PT::inv_at(&mem, l1_dir_ghost_pt, 2, l1_dir_addr));
-                                PT::lemma_interp_at_facts(&mem, l1_dir_ghost_pt, 2, l1_dir_addr, l2_base_vaddr);
-                                let interp_l2_dir   = PT::interp_at(&mem, l1_dir_ghost_pt, 2, l1_dir_addr, l2_base_vaddr);
-                                let interp_l2_entry = PT::interp_at_entry(&mem, l1_dir_ghost_pt, 2, l1_dir_addr, l2_base_vaddr, l2_idx);
-                                interp_l2_dir.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(l2_idx);
-
-                                let low_bits: u64 = addr % (L2_ENTRY_SIZE as u64);
-                                // This assert proves: ... == l0_idx_u64 * L0_ENTRY_SIZE + l1_idx_u64 * L1_ENTRY_SIZE + l2_idx_u64 * L2_ENTRY_SIZE + low_bits
-                                assert((l0_idx_u64 << 39u64) | (l1_idx_u64 << 30u64) | (l2_idx_u64 << 21u64) | low_bits
-                                       == add(add(add(
-                                              mul(l0_idx_u64, mul(512, mul(512, mul(512, 4096)))),
-                                              mul(l1_idx_u64, mul(512, mul(512, 4096)))),
-                                              mul(l2_idx_u64, mul(512, 4096))),
-                                              low_bits)) by (bit_vector)
-                                    requires
-                                        l1_idx_u64 == (addr & 0x007F_C000_0000) >> 30,
-                                        l2_idx_u64 == (addr & 0x0000_3FE0_0000) >> 21,
-                                        low_bits == addr % mul(512, 4096);
-                                assert(addr == ((l0_idx_u64 << 39u64) | (l1_idx_u64 << 30u64) | (l2_idx_u64 << 21u64) | low_bits)) by (bit_vector)
-                                    requires
-                                        l0_idx_u64 == (addr & 0xFF80_0000_0000) >> 39,
-                                        l1_idx_u64 == (addr & 0x007F_C000_0000) >> 30,
-                                        l2_idx_u64 == (addr & 0x0000_3FE0_0000) >> 21,
-                                        addr < mul(512u64, mul(512, mul(512, mul(512, 4096)))),
-                                        low_bits == addr % mul(512, 4096);
-                                match read_entry(mem, l1_dir_addr as nat, 2, l2_idx) {
-                                    GhostPageDirectoryEntry::Page {
-                                        addr: page_addr, flag_RW: l2_RW, flag_US: l2_US, flag_XD: l2_XD, ..
-                                    } => {
-                                        assert_by_contradiction!(!aligned(addr as nat, L2_ENTRY_SIZE as nat), {
-                                            let pte = PageTableEntry {
-                                                frame: MemRegion { base: page_addr as nat, size: L2_ENTRY_SIZE as nat },
-                                                flags: Flags {
-                                                    is_writable:      l0_RW &&  l1_RW &&  l2_RW,
-                                                    is_supervisor:   !l0_US || !l1_US || !l2_US,
-                                                    disable_execute:  l0_XD ||  l1_XD ||  l2_XD
-                                                }
-                                            };
-                                            assert(valid_pt_walk(mem, addr as u64, pte));
-                                        });
-                                        assert(!interp_l2_dir.interp_of_entry(l2_idx).map.contains_key(addr as nat));
-                                        assert(!interp_l2_dir.interp().map.contains_key(addr as nat));
-                                        assert(!interp_l1_dir.interp_of_entry(l1_idx).map.contains_key(addr as nat));
-                                        assert(!interp_l1_dir.interp().map.contains_key(addr as nat));
-                                        assert(!interp_l0_dir.interp().map.contains_key(addr as nat));
-                                        assert(!m2.contains_key(addr as nat));
-                                    },
-                                    GhostPageDirectoryEntry::Directory {
-                                        addr: l2_dir_addr, flag_RW: l2_RW, flag_US: l2_US, flag_XD: l2_XD, ..
-                                    } => {
-                                        assert(interp_l2_entry.is_Directory());
-                                        let l3_base_vaddr = x86_arch_spec.entry_base(2, l2_base_vaddr, l2_idx);
-                                        let l2_dir_ghost_pt = l1_dir_ghost_pt.entries[l2_idx as int].get_Some_0();
-                                        assert(PT::directories_obey_invariant_at(&mem, l1_dir_ghost_pt, 2, l1_dir_addr));
-                                        assert(PT::inv_at(&mem, l2_dir_ghost_pt, 3, l2_dir_addr));
-                                        PT::lemma_interp_at_facts(&mem, l2_dir_ghost_pt, 3, l2_dir_addr, l3_base_vaddr);
-                                        let interp_l3_dir   = PT::interp_at(&mem, l2_dir_ghost_pt, 3, l2_dir_addr, l3_base_vaddr);
-                                        let interp_l3_entry = PT::interp_at_entry(&mem, l2_dir_ghost_pt, 3, l2_dir_addr, l3_base_vaddr, l3_idx);
-                                        interp_l3_dir.lemma_interp_of_entry_contains_mapping_implies_interp_contains_mapping(l3_idx);
-
-                                        let low_bits: u64 = addr % (L3_ENTRY_SIZE as u64);
-                                        // This assert proves: ... == l0_idx_u64 * L0_ENTRY_SIZE + l1_idx_u64 * L1_ENTRY_SIZE + l2_idx_u64 * L2_ENTRY_SIZE + l3_idx_u64 * L3_ENTRY_SIZE + low_bits
-                                        assert((l0_idx_u64 << 39u64) | (l1_idx_u64 << 30u64) | (l2_idx_u64 << 21u64) | (l3_idx_u64 << 12u64) | low_bits
-                                               == add(add(add(add(
-                                                      mul(l0_idx_u64, mul(512, mul(512, mul(512, 4096)))),
-                                                      mul(l1_idx_u64, mul(512, mul(512, 4096)))),
-                                                      mul(l2_idx_u64, mul(512, 4096))),
-                                                      mul(l3_idx_u64, 4096)),
-                                                      low_bits)) by (bit_vector)
-                                            requires
-                                                l1_idx_u64 == (addr & 0x007F_C000_0000) >> 30,
-                                                l2_idx_u64 == (addr & 0x0000_3FE0_0000) >> 21,
-                                                l3_idx_u64 == (addr & 0x0000_001F_F000) >> 12,
-                                                low_bits == addr % 4096;
-                                        assert(addr == ((l0_idx_u64 << 39u64) | (l1_idx_u64 << 30u64) | (l2_idx_u64 << 21u64) | (l3_idx_u64 << 12u64) | low_bits)) by (bit_vector)
-                                            requires
-                                                l0_idx_u64 == (addr & 0xFF80_0000_0000) >> 39,
-                                                l1_idx_u64 == (addr & 0x007F_C000_0000) >> 30,
-                                                l2_idx_u64 == (addr & 0x0000_3FE0_0000) >> 21,
-                                                l3_idx_u64 == (addr & 0x0000_001F_F000) >> 12,
-                                                addr < mul(512u64, mul(512, mul(512, mul(512, 4096)))),
-                                                low_bits == addr % 4096;
-                                        match read_entry(mem, l2_dir_addr as nat, 3, l3_idx) {
-                                            GhostPageDirectoryEntry::Page {
-                                                addr: page_addr, flag_RW: l3_RW, flag_US: l3_US, flag_XD: l3_XD, ..
-                                            } => {
-                                                assert_by_contradiction!(!aligned(addr as nat, L3_ENTRY_SIZE as nat), {
-                                                    let pte = PageTableEntry {
-                                                        frame: MemRegion { base: page_addr as nat, size: L3_ENTRY_SIZE as nat },
-                                                        flags: Flags {
-                                                            is_writable:      l0_RW &&  l1_RW &&  l2_RW &&  l3_RW,
-                                                            is_supervisor:   !l0_US || !l1_US || !l2_US || !l3_US,
-                                                            disable_execute:  l0_XD ||  l1_XD ||  l2_XD ||  l3_XD
-                                                        }
-                                                    };
-                                                    assert(valid_pt_walk(mem, addr as u64, pte));
-                                                });
-                                                assert(!interp_l3_dir.interp_of_entry(l3_idx).map.contains_key(addr as nat));
-                                                assert(!interp_l3_dir.interp().map.contains_key(addr as nat));
-                                                assert(!interp_l2_dir.interp_of_entry(l2_idx).map.contains_key(addr as nat));
-                                                assert(!interp_l2_dir.interp().map.contains_key(addr as nat));
-                                                assert(!interp_l1_dir.interp_of_entry(l1_idx).map.contains_key(addr as nat));
-                                                assert(!interp_l1_dir.interp().map.contains_key(addr as nat));
-                                                assert(!interp_l0_dir.interp().map.contains_key(addr as nat));
-                                                assert(!m2.contains_key(addr as nat));
-                                            },
-                                            GhostPageDirectoryEntry::Directory { .. } => assert(false),
-                                            GhostPageDirectoryEntry::Empty => {
-                                                assert(!interp_l3_dir.interp_of_entry(l3_idx).map.contains_key(addr as nat));
-                                                assert(!interp_l3_dir.interp().map.contains_key(addr as nat));
-                                                assert(!interp_l2_dir.interp_of_entry(l2_idx).map.contains_key(addr as nat));
-                                                assert(!interp_l2_dir.interp().map.contains_key(addr as nat));
-                                                assert(!interp_l1_dir.interp_of_entry(l1_idx).map.contains_key(addr as nat));
-                                                assert(!interp_l1_dir.interp().map.contains_key(addr as nat));
-                                                assert(!interp_l0_dir.interp().map.contains_key(addr as nat));
-                                                assert(!m2.contains_key(addr as nat));
-                                            }
-                                        }
-                                    },
-                                    GhostPageDirectoryEntry::Empty => {
-                                        assert(!interp_l2_dir.interp_of_entry(l2_idx).map.contains_key(addr as nat));
-                                        assert(!interp_l2_dir.interp().map.contains_key(addr as nat));
-                                        assert(!interp_l1_dir.interp_of_entry(l1_idx).map.contains_key(addr as nat));
-                                        assert(!interp_l1_dir.interp().map.contains_key(addr as nat));
-                                        assert(!interp_l0_dir.interp().map.contains_key(addr as nat));
-                                        assert(!m2.contains_key(addr as nat));
-                                    },
-                                }
-                            },
-                            GhostPageDirectoryEntry::Empty => {
-                                assert(!interp_l1_dir.interp_of_entry(l1_idx).map.contains_key(addr as nat));
-                                assert(!interp_l1_dir.interp().map.contains_key(addr as nat));
-                                assert(!interp_l0_dir.interp().map.contains_key(addr as nat));
-                                assert(!m2.contains_key(addr as nat));
-                            },
-                        }
-                    },
-                    GhostPageDirectoryEntry::Page { .. } => assert(false),
-                    GhostPageDirectoryEntry::Empty => {
-                        let low_bits: u64 = addr % (L0_ENTRY_SIZE as u64);
-                        // This assert proves: ... == l0_idx_u64 * L0_ENTRY_SIZE + low_bits
-                        assert((l0_idx_u64 << 39u64) | low_bits
-                               == add(mul(l0_idx_u64, mul(512, mul(512, mul(512, 4096)))),
-                                      low_bits)) by (bit_vector)
-                            requires
-                                low_bits == addr % mul(512, mul(512, mul(512, 4096)));
-                        assert(addr == ((l0_idx_u64 << 39u64) | low_bits)) by (bit_vector)
-                            requires
-                                l0_idx_u64 == (addr & 0xFF80_0000_0000) >> 39,
-                                addr < mul(512u64, mul(512, mul(512, mul(512, 4096)))),
-                                low_bits == addr % mul(512, mul(512, mul(512, 4096)));
-                        assert(!interp_l0_dir.interp().map.contains_key(addr as nat));
-                        assert(!m2.contains_key(addr as nat));
-                    },
-                };
-            }
+    let n = mem.num_layers_spec();
+    let d = PT::interp_at(&mem, pt, layer, ptr, base);
+    PT::lemma_interp_at_facts(&mem, pt, layer, ptr, base);
+    PT::lemma_arch_facts(&mem);
+    assert(d.layer == layer);
+    assert(d.arch == X86EntryFormat::arch(n));
+    assert(pt.region == MemRegion { base: ptr as nat, size: PAGE_SIZE as nat });
+    assert forall|i: nat| i < d.entries.len() implies #[trigger] entry_describes::<X86EntryFormat>(mem, d, ptr as nat, i) by {
+        PT::lemma_interp_at_facts_entries(&mem, pt, layer, ptr, base, i);
+        assert(d.entries[i as int] == PT::interp_at_entry(&mem, pt, layer, ptr, base, i));
+        assert(X86EntryFormat::table_size() == PAGE_SIZE);
+        match PT::view_at(&mem, pt, layer, ptr, i) {
+            GhostPageDirectoryEntry::Directory { addr: dir_addr, .. } => {
+                let dir_pt = pt.entries[i as int].get_Some_0();
+                let dir = d.entries[i as int].get_Directory_0();
+                assert(PT::directories_obey_invariant_at(&mem, pt, layer, ptr));
+                assert(PT::directories_have_flags(&mem, pt, layer, ptr));
+                assert(PT::inv_at(&mem, dir_pt, layer + 1, dir_addr));
+                assert(d.directories_obey_invariant());
+                assert(d.directories_are_in_next_layer());
+                assert(d.directories_match_arch());
+                assert(dir.inv());
+                lemma_interp_at_describes(mem, dir_pt, layer + 1, dir_addr, d.entry_base(i));
+            },
+            GhostPageDirectoryEntry::Page { .. } => {},
+            GhostPageDirectoryEntry::Empty => {},
         }
     };
 }
 
-proof fn lemma_no_entries_implies_interp_at_aux_no_entries(mem: mem::PageTableMemory, pt: PTDir, layer: nat, ptr: usize, base_vaddr: nat, init: Seq<l1::NodeEntry>)
+proof fn lemma_x86_index_matches_arch(num_layers: nat)
     requires
-        mem.regions() == set![mem.cr3_spec()@],
-        (forall|i: nat| i < 512 ==> mem.region_view(mem.cr3_spec()@)[i as int] == 0),
-        layer == 0,
-        PT::inv_at(&mem, pt, layer, ptr),
-        forall|i: nat| i < init.len() ==> init[i as int] == l1::NodeEntry::Empty(),
-        init.len() <= 512,
+        num_layers == X86_NUM_LAYERS || num_layers == X86_LA57_NUM_LAYERS,
     ensures
-        ({ let res = PT::interp_at_aux(&mem, pt, layer, ptr, base_vaddr, init);
-            &&& res.len() == 512
-            &&& forall|i: nat| i < res.len() ==> res[i as int] == l1::NodeEntry::Empty()
-        })
-    decreases 512 - init.len()
+        index_matches_arch::<X86EntryFormat>(num_layers),
 {
-    lemma_new_seq::<Option<PTDir>>(512, Option::None);
-    let res = PT::interp_at_aux(&mem, pt, layer, ptr, base_vaddr, init);
-    if init.len() >= 512 {
-    } else {
-        let entry = PT::interp_at_entry(&mem, pt, layer, ptr, base_vaddr, init.len());
-        assert(PT::ghost_pt_matches_structure(&mem, pt, layer, ptr));
-        assert forall|i: nat| i < 512 implies PT::view_at(&mem, pt, layer, ptr, i).is_Empty() by {
-            let entry = mem.spec_read(i, pt.region);
-            assert((entry & (1u64 << 0)) != (1u64 << 0)) by (bit_vector) requires entry == 0u64;
-        };
-        assert(entry == l1::NodeEntry::Empty());
-        lemma_no_entries_implies_interp_at_aux_no_entries(mem, pt, layer, ptr, base_vaddr, init.push(entry));
-    }
+    let arch = x86_arch_spec_with_layers(num_layers);
+    lemma_x86_arch_spec_with_layers(num_layers);
+    assert forall|layer: nat, base: nat, addr: nat|
+        layer < arch.layers.len()
+        && aligned(base, arch.entry_size(layer) * arch.num_entries(layer))
+        && base <= addr < arch.upper_vaddr(layer, base)
+        && addr < arch.upper_vaddr(0, 0)
+        implies X86EntryFormat::index(num_layers, layer, nat_to_u64(addr)) == #[trigger] arch.index_for_vaddr(layer, base, addr)
+    by {
+        let es = arch.entry_size(layer);
+        assert(arch.num_entries(layer) == 512);
+        assert(arch.upper_vaddr(layer, base) == base + es * 512) by (nonlinear_arith)
+            requires arch.upper_vaddr(layer, base) == base + 512 * es;
+        lemma_walk_index(num_layers, layer, base, addr);
+    };
 }
 
-impl impl_spec::InterfaceSpec for impl_spec::PageTableImpl {
-    closed spec fn ispec_inv(&self, mem: &mem::PageTableMemory) -> bool {
-        exists|pt: PTDir| #[trigger] PT::inv(mem, pt) && PT::interp(mem, pt).inv()
-    }
-
-    proof fn ispec_init_implies_inv(&self, mem: &mem::PageTableMemory) {
-        let pt = PTDir {
-            region: mem.cr3_spec()@,
-            entries: new_seq(512, Option::None),
-            used_regions: set![mem.cr3_spec()@],
-        };
-        lemma_new_seq::<Option<PTDir>>(512, Option::None);
-        assert(PT::inv(mem, pt)) by {
-            x86_arch_inv();
-            axiom_x86_arch_exec_spec();
-            PT::lemma_zeroed_page_implies_empty_at(mem, pt, 0, mem.cr3_spec().base);
-        };
-        lemma_no_entries_implies_interp_at_aux_no_entries(*mem, pt, 0, mem.cr3_spec().base, 0, seq![]);
-        assert(aligned(PT::interp(mem, pt).base_vaddr, PT::interp(mem, pt).entry_size() * PT::interp(mem, pt).num_entries())) by {
-            assert(PT::interp(mem, pt).base_vaddr == 0);
-            assert(forall|x: nat| x != 0 ==> #[trigger] aligned(0, x));
-            assert(forall|x: nat| x != 0 ==> aligned(PT::interp(mem, pt).base_vaddr, x));
-            let x = PT::interp(mem, pt).entry_size() * PT::interp(mem, pt).num_entries();
-            assert(x != 0);
-            assert(aligned(PT::interp(mem, pt).base_vaddr, x));
-        };
-        assert(PT::interp(mem, pt).inv());
-    }
-
-    fn ispec_map_frame(&self, mem: &mut mem::PageTableMemory, vaddr: usize, pte: PageTableEntryExec) -> (res: Result<(),()>) {
-        let mut pt: Ghost<PTDir> = Ghost(choose|pt: PTDir| #[trigger] PT::inv(mem, pt) && PT::interp(mem, pt).inv());
-        proof {
-            PT::lemma_interp_at_facts(mem, pt@, 0, mem.cr3_spec().base, 0);
-            PT::interp(mem, pt@).lemma_inv_implies_interp_inv();
-            assert(x86_arch_spec.upper_vaddr(0, 0) == crate::definitions_t::PT_BOUND_HIGH) by (compute_only);
-            lemma_page_table_walk_interp();
-        }
-        PT::map_frame(mem, &mut pt, vaddr, pte)
+/// The MMU's index into a directory is the index of the directory entry that contains `addr`.
+proof fn lemma_walk_index(num_layers: nat, layer: nat, base: nat, addr: nat)
+    requires
+        num_layers == X86_NUM_LAYERS || num_layers == X86_LA57_NUM_LAYERS,
+        layer < num_layers,
+        aligned(base, x86_arch_spec_with_layers(num_layers).entry_size(layer) * 512),
+        base <= addr < base + x86_arch_spec_with_layers(num_layers).entry_size(layer) * 512,
+        addr < MAX_LA57_BASE,
+    ensures
+        walk_index(num_layers, layer, nat_to_u64(addr)) == x86_arch_spec_with_layers(num_layers).index_for_vaddr(layer, base, addr),
+{
+    let n = num_layers;
+    let es = x86_arch_spec_with_layers(n).entry_size(layer);
+    let a = nat_to_u64(addr);
+    assert(a as nat == addr);
+    let shift = (12 + 9 * (n - 1 - layer)) as u64;
+    if n == X86_NUM_LAYERS {
+        assert(x86_arch_spec.entry_size(0) == L0_ENTRY_SIZE);
+        assert(x86_arch_spec.entry_size(1) == L1_ENTRY_SIZE);
+        assert(x86_arch_spec.entry_size(2) == L2_ENTRY_SIZE);
+        assert(x86_arch_spec.entry_size(3) == L3_ENTRY_SIZE);
+    } else {
+        assert(x86_la57_arch_spec.entry_size(0) == PML5_ENTRY_SIZE);
+        assert(x86_la57_arch_spec.entry_size(1) == L0_ENTRY_SIZE);
+        assert(x86_la57_arch_spec.entry_size(2) == L1_ENTRY_SIZE);
+        assert(x86_la57_arch_spec.entry_size(3) == L2_ENTRY_SIZE);
+        assert(x86_la57_arch_spec.entry_size(4) == L3_ENTRY_SIZE);
     }
-
-    fn ispec_unmap(&self, mem: &mut mem::PageTableMemory, vaddr: usize) -> (res: Result<(),()>) {
-        let mut pt: Ghost<PTDir> = Ghost(choose|pt: PTDir| #[trigger] PT::inv(mem, pt) && PT::interp(mem, pt).inv());
-        proof {
-            PT::lemma_interp_at_facts(mem, pt@, 0, mem.cr3_spec().base, 0);
-            PT::interp(mem, pt@).lemma_inv_implies_interp_inv();
-            assert(x86_arch_spec.upper_vaddr(0, 0) == crate::definitions_t::PT_BOUND_HIGH) by (compute_only);
-            lemma_page_table_walk_interp();
+    assert(walk_index(n, layer, a) == (addr / es) % 512) by {
+        if shift == 12 {
+            assert(es == 0x1000);
+            assert((a >> 12u64) & 0x1FFu64 == (a / 0x1000u64) % 512u64) by (bit_vector);
+        } else if shift == 21 {
+            assert(es == 0x20_0000);
+            assert((a >> 21u64) & 0x1FFu64 == (a / 0x20_0000u64) % 512u64) by (bit_vector);
+        } else if shift == 30 {
+            assert(es == 0x4000_0000);
+            assert((a >> 30u64) & 0x1FFu64 == (a / 0x4000_0000u64) % 512u64) by (bit_vector);
+        } else if shift == 39 {
+            assert(es == 0x80_0000_0000);
+            assert((a >> 39u64) & 0x1FFu64 == (a / 0x80_0000_0000u64) % 512u64) by (bit_vector);
+        } else {
+            assert(shift == 48);
+            assert(es == 0x1_0000_0000_0000);
+            assert((a >> 48u64) & 0x1FFu64 == (a / 0x1_0000_0000_0000u64) % 512u64) by (bit_vector);
         }
-        PT::unmap(mem, &mut pt, vaddr)
-    }
+    };
+    lemma_index_from_aligned_base(base, addr, es);
+}
 
-    fn ispec_resolve(&self, mem: &mem::PageTableMemory, vaddr: usize) -> (res: Result<(usize, PageTableEntryExec),()>) {
-        let pt: Ghost<PTDir> = Ghost(choose|pt: PTDir| #[trigger] PT::inv(mem, pt) && PT::interp(mem, pt).inv());
-        proof {
-            PT::lemma_interp_at_facts(mem, pt@, 0, mem.cr3_spec().base, 0);
-            PT::interp(mem, pt@).lemma_inv_implies_interp_inv();
-            assert(x86_arch_spec.upper_vaddr(0, 0) == crate::definitions_t::PT_BOUND_HIGH) by (compute_only);
-            lemma_page_table_walk_interp();
-        }
-        match PT::resolve(mem, pt, vaddr) {
-            Ok((v,pte)) => Ok((v,pte)),
-            Err(e)      => Err(e),
-        }
-    }
+proof fn lemma_index_from_aligned_base(base: nat, addr: nat, es: nat)
+    requires
+        0 < es,
+        aligned(base, es * 512),
+        base <= addr < base + es * 512,
+    ensures
+        (addr - base) as nat / es == (addr / es) % 512,
+{
+    let q = base / (es * 512);
+    let r = (addr - base) as nat;
+    vstd::arithmetic::div_mod::lemma_fundamental_div_mod(base as int, (es * 512) as int);
+    vstd::arithmetic::div_mod::lemma_fundamental_div_mod(r as int, es as int);
+    vstd::arithmetic::div_mod::lemma_mod_pos_bound(r as int, es as int);
+    assert(addr == (q * 512 + r / es) * es + r % es) by (nonlinear_arith)
+        requires
+            base == (es * 512) * q,
+            addr == base + r,
+            r == es * (r / es) + r % es;
+    assert(r / es < 512) by (nonlinear_arith)
+        requires
+            r == es * (r / es) + r % es,
+            0 <= r % es,
+            r < es * 512,
+            0 < es;
+    vstd::arithmetic::div_mod::lemma_fundamental_div_mod_converse(addr as int, es as int, (q * 512 + r / es) as int, (r % es) as int);
+    vstd::arithmetic::div_mod::lemma_fundamental_div_mod_converse((addr / es) as int, 512, q as int, (r / es) as int);
 }
 
+/// With four layers, the walk of the x86 entry format is the 4-level walk `valid_pt_walk`.
+pub proof fn lemma_valid_pt_walk_with_four_layers(pt_mem: mem::PageTableMemory, addr: u64, pte: PageTableEntry)
+    requires
+        pt_mem.num_layers_spec() == X86_NUM_LAYERS,
+    ensures
+        valid_walk::<X86EntryFormat>(pt_mem, addr, pte) == valid_pt_walk(pt_mem, addr, pte),
+{
+    lemma_x86_arch_spec_with_layers(X86_NUM_LAYERS as nat);
+    assert(bitmask_inc!(39u64,47u64) == 0xFF80_0000_0000) by (compute);
+    assert(bitmask_inc!(30u64,38u64) == 0x007F_C000_0000) by (compute);
+    assert(bitmask_inc!(21u64,29u64) == 0x0000_3FE0_0000) by (compute);
+    assert(bitmask_inc!(12u64,20u64) == 0x0000_001F_F000) by (compute);
+    assert((addr >> 39u64) & 0x1FFu64 == (addr & 0xFF80_0000_0000) >> 39u64) by (bit_vector);
+    assert((addr >> 30u64) & 0x1FFu64 == (addr & 0x007F_C000_0000) >> 30u64) by (bit_vector);
+    assert((addr >> 21u64) & 0x1FFu64 == (addr & 0x0000_3FE0_0000) >> 21u64) by (bit_vector);
+    assert((addr >> 12u64) & 0x1FFu64 == (addr & 0x0000_001F_F000) >> 12u64) by (bit_vector);
+    assert(walk_index(4, 0, addr) == l0_bits!(addr) as nat);
+    assert(walk_index(4, 1, addr) == l1_bits!(addr) as nat);
+    assert(walk_index(4, 2, addr) == l2_bits!(addr) as nat);
+    assert(walk_index(4, 3, addr) == l3_bits!(addr) as nat);
+    assert(x86_arch_spec.entry_size(1) == L1_ENTRY_SIZE);
+    assert(x86_arch_spec.entry_size(2) == L2_ENTRY_SIZE);
+    assert(x86_arch_spec.entry_size(3) == L3_ENTRY_SIZE);
+    reveal_with_fuel(walk, 5);
 }
+
+} // verus!
@@ -1,11 +1,14 @@
-//#[cfg(feature = "impl")]
-//pub mod l0;
-//#[cfg(feature = "impl")]
-//pub mod l1;
-//#[cfg(feature = "impl")]
-//pub mod l2_impl;
-//#[cfg(feature = "impl")]
-//pub mod l2_refinement;
+#[cfg(feature = "impl")]
+pub mod l0;
+#[cfg(feature = "impl")]
+pub mod l1;
+#[cfg(feature = "impl")]
+pub mod l2_impl;
+#[cfg(feature = "impl")]
+pub mod l2_refinement;
 pub mod spec_pt;
-//pub mod indexing;
+#[cfg(feature = "impl")]
+pub mod indexing;
 pub mod os_refinement;
+#[cfg(all(feature = "impl", feature = "nr"))]
+pub mod vspace;
@@ -410,6 +410,14 @@ proof fn next_step_refines_hl_next_step(
         os::OSStep::UnmapEnd { core } => {
             step_Unmap_End_refines(c, s1, s2, core);
         },
+        //ClearDirty steps
+        os::OSStep::ClearDirtyOp { .. }
+        | os::OSStep::ClearDirtyInitiateShootdown { .. }
+        | os::OSStep::ClearDirtyEnd { .. } => {
+            assert(s1.interp_pt_mem() == s2.interp_pt_mem());
+            assert(s1.interp(c).thread_state =~= s2.interp(c).thread_state);
+            lemma_effective_mappings_unaffected_if_thread_state_constant(c, s1, s2);
+        },
         _ => {},
     }
 }
@@ -1,6 +1,6 @@
 use vstd::prelude::*;
 
-use crate::definitions_t::{candidate_mapping_overlaps_existing_vmem, PageTableEntry};
+use crate::definitions_t::{candidate_mapping_overlaps_existing_vmem, Flags, PageTableEntry};
 use crate::spec_t::hardware;
 use crate::spec_t::mem;
 
@@ -28,6 +28,8 @@ pub struct PageTableVariables {
 // and unmapping an entry is a sequence that matches: `Read* (Write (Read | ViewStutter)*)?`
 // (Reading to traverse the tree, then if the entry exists, unmapping it with a view-affecting
 // write and unmapping zero or more, now-empty directories)
+// Protecting an entry is a sequence that matches: `Read* Write?`
+// (Reading to traverse the tree, then if the entry exists, overwriting its flags)
 pub enum ThreadState {
     Idle,
     Mapping,
@@ -49,6 +51,7 @@ pub enum PageTableStep {
     MapEnd { vaddr: nat, pte: PageTableEntry, result: Result<(), ()> },
     UnmapStart { vaddr: nat, result: Result<(), ()> },
     UnmapEnd,
+    Protect { vaddr: nat, flags: Flags, result: Result<(), ()> },
     ViewStutter,
     Stutter,
 }
@@ -106,6 +109,28 @@ pub open spec fn step_Unmap_End(
     &&& s2 == s1
 }
 
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Protect
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+pub open spec fn step_Protect(
+    s1: PageTableVariables,
+    s2: PageTableVariables,
+    vaddr: nat,
+    flags: Flags,
+    result: Result<(), ()>,
+) -> bool {
+    if s1.interp().dom().contains(vaddr) {
+        &&& result is Ok
+        &&& s2.interp() == s1.interp().insert(
+            vaddr,
+            PageTableEntry { frame: s1.interp()[vaddr].frame, flags },
+        )
+    } else {
+        &&& result is Err
+        &&& s2.interp() == s1.interp()
+    }
+}
+
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 // Stutter
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -139,12 +164,13 @@ pub open spec fn next_step(
     step: PageTableStep,
 ) -> bool {
     match step {
-        PageTableStep::MapStart { vaddr, pte }       => step_Map_Start(s1, s2, vaddr, pte),
-        PageTableStep::MapEnd { vaddr, pte, result } => step_Map_End(s1, s2, vaddr, pte, result),
-        PageTableStep::UnmapStart { vaddr, result }  => step_Unmap_Start(s1, s2, vaddr, result),
-        PageTableStep::UnmapEnd                      => step_Unmap_End(s1, s2),
-        PageTableStep::ViewStutter                   => step_View_Stutter(s1, s2),
-        PageTableStep::Stutter                       => step_Stutter(s1, s2),
+        PageTableStep::MapStart { vaddr, pte }          => step_Map_Start(s1, s2, vaddr, pte),
+        PageTableStep::MapEnd { vaddr, pte, result }    => step_Map_End(s1, s2, vaddr, pte, result),
+        PageTableStep::UnmapStart { vaddr, result }     => step_Unmap_Start(s1, s2, vaddr, result),
+        PageTableStep::UnmapEnd                         => step_Unmap_End(s1, s2),
+        PageTableStep::Protect { vaddr, flags, result } => step_Protect(s1, s2, vaddr, flags, result),
+        PageTableStep::ViewStutter                      => step_View_Stutter(s1, s2),
+        PageTableStep::Stutter                          => step_Stutter(s1, s2),
     }
 }
 
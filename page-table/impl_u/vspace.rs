@@ -0,0 +1,390 @@
+use vstd::prelude::*;
+
+use verified_node_replication::Dispatch;
+
+use crate::definitions_t::{ PageTableEntry, PageTableEntryExec, MemRegionExec, aligned, new_seq,
+    x86_arch_spec, x86_arch_spec_upper_bound, axiom_x86_arch_exec_spec, MAX_BASE, MAX_PHYADDR,
+    L0_ENTRY_SIZE, L1_ENTRY_SIZE, L2_ENTRY_SIZE, L3_ENTRY_SIZE, PAGE_SIZE, X86_NUM_LAYERS };
+use crate::definitions_u::{ lemma_new_seq, aligned_exec, clone_pte, permissive_flags, x86_arch_inv };
+use crate::impl_u::l1;
+use crate::impl_u::l2_impl::{ PT, PTDir };
+use crate::spec_t::mem;
+
+verus! {
+
+/// Executable version of `MAX_BASE`, the upper bound of the virtual address space.
+pub const MAX_BASE_EXEC: usize = 512 * L0_ENTRY_SIZE;
+
+/// Number of pages of page table memory of each replica of the address space, including the page
+/// of the layer 0 directory.
+pub const VSPACE_NUM_PAGES: usize = 4096;
+
+proof fn lemma_x86_arch_facts()
+    ensures
+        MAX_BASE_EXEC == MAX_BASE,
+        x86_arch_spec.upper_vaddr(0, 0) == MAX_BASE,
+        x86_arch_spec.layers.len() == 4,
+        x86_arch_spec.entry_size(1) == L1_ENTRY_SIZE,
+        x86_arch_spec.entry_size(2) == L2_ENTRY_SIZE,
+        x86_arch_spec.entry_size(3) == L3_ENTRY_SIZE,
+        forall|size: nat| #[trigger] x86_arch_spec.contains_entry_size_at_index_atleast(size, 1)
+            <==> (size == L1_ENTRY_SIZE || size == L2_ENTRY_SIZE || size == L3_ENTRY_SIZE),
+        x86_arch_spec.contains_entry_size(L3_ENTRY_SIZE as nat),
+{
+    x86_arch_spec_upper_bound();
+    assert(MAX_BASE_EXEC == MAX_BASE) by (compute_only);
+    assert(x86_arch_spec.upper_vaddr(0, 0) == MAX_BASE) by (compute_only);
+    assert(x86_arch_spec.entry_size(1) == L1_ENTRY_SIZE);
+    assert(x86_arch_spec.entry_size(2) == L2_ENTRY_SIZE);
+    assert(x86_arch_spec.entry_size(3) == L3_ENTRY_SIZE);
+    assert(x86_arch_spec.contains_entry_size(L3_ENTRY_SIZE as nat));
+}
+
+/// The read-only operations on the address space.
+pub enum VSpaceReadOp {
+    /// Resolves the virtual address to the mapping it is in
+    Resolve(usize),
+}
+
+/// The update operations on the address space.
+pub enum VSpaceWriteOp {
+    /// Maps the frame of the entry at the virtual address
+    Map(usize, PageTableEntryExec),
+    /// Removes the mapping at the virtual address
+    Unmap(usize),
+}
+
+/// Reasons for a failed map operation.
+pub enum MapError {
+    /// The mapping is rejected or overlaps an existing mapping
+    Invalid,
+    /// There may not be enough pages left for the directories the mapping needs
+    OutOfMemory,
+}
+
+/// The response of an operation on the address space.
+pub enum VSpaceResponse {
+    Resolve(Result<(usize, PageTableEntryExec), ()>),
+    Map(Result<(), MapError>),
+    Unmap(Result<(), ()>),
+}
+
+/// The abstract state of the address space: the `l1::Directory` interpretation of the page table
+/// and the number of pages that are left for new directories.
+pub ghost struct VSpaceView {
+    pub dir: l1::Directory,
+    pub free_pages: nat,
+}
+
+/// An address space backed by the verified page table that can be replicated with
+/// node-replication.
+///
+/// The view of the address space contains the `l1::Directory` interpretation of the page table,
+/// i.e. the implementation of each operation is verified against the `l1` specification. Since the
+/// page table memory of a replica is bounded, the view also tracks the number of free pages, which
+/// determines when a map operation fails for lack of memory.
+pub struct VerifiedVSpace {
+    pub mem: mem::PageTableMemory,
+    pub pt: Ghost<PTDir>,
+}
+
+pub open spec fn pte_exec(pte: PageTableEntry) -> PageTableEntryExec {
+    PageTableEntryExec {
+        frame: MemRegionExec { base: pte.frame.base as usize, size: pte.frame.size as usize },
+        flags: pte.flags,
+    }
+}
+
+impl VerifiedVSpace {
+    /// Mappings we hand to `PT::map_frame`, all others are rejected without modifying the page
+    /// table.
+    pub open spec fn accepted_map(vaddr: nat, pte: PageTableEntry) -> bool {
+        &&& vaddr < MAX_BASE
+        &&& vaddr + pte.frame.size <= MAX_BASE
+        &&& aligned(vaddr, pte.frame.size)
+        &&& aligned(pte.frame.base, pte.frame.size)
+        &&& PT::accepted_mapping(vaddr, pte)
+    }
+
+    /// Addresses we hand to `PT::unmap`, all others are rejected without modifying the page
+    /// table.
+    pub open spec fn accepted_unmap(vaddr: nat) -> bool {
+        &&& vaddr < MAX_BASE
+        &&& aligned(vaddr, PAGE_SIZE as nat)
+    }
+
+    pub open spec fn resolve_spec(ds: l1::Directory, vaddr: usize) -> Result<(usize, PageTableEntryExec), ()> {
+        if vaddr < MAX_BASE {
+            match ds.resolve(vaddr as nat) {
+                Ok((base, pte)) => Ok((base as usize, pte_exec(pte))),
+                Err(e) => Err(e),
+            }
+        } else {
+            Err(())
+        }
+    }
+
+    /// A mapping adds at most three directories, we only attempt it if there are enough free
+    /// pages for all of them.
+    pub open spec fn map_spec(ds: VSpaceView, vaddr: usize, pte: PageTableEntryExec) -> (VSpaceView, Result<(), MapError>) {
+        if !Self::accepted_map(vaddr as nat, pte@) {
+            (ds, Err(MapError::Invalid))
+        } else if ds.free_pages < 3 {
+            (ds, Err(MapError::OutOfMemory))
+        } else {
+            match ds.dir.map_frame(vaddr as nat, pte@) {
+                Ok(d)  => {
+                    let free_pages = (ds.free_pages - ds.dir.map_frame_num_new_dirs(vaddr as nat, pte@)) as nat;
+                    (VSpaceView { dir: d, free_pages }, Ok(()))
+                },
+                Err(d) => (VSpaceView { dir: d, free_pages: ds.free_pages }, Err(MapError::Invalid)),
+            }
+        }
+    }
+
+    pub open spec fn unmap_spec(ds: VSpaceView, vaddr: usize) -> (VSpaceView, Result<(), ()>) {
+        if Self::accepted_unmap(vaddr as nat) {
+            match ds.dir.unmap(vaddr as nat) {
+                Ok(d)  => {
+                    let free_pages = ds.free_pages + ds.dir.unmap_num_removed_dirs(vaddr as nat);
+                    (VSpaceView { dir: d, free_pages }, Ok(()))
+                },
+                Err(d) => (VSpaceView { dir: d, free_pages: ds.free_pages }, Err(())),
+            }
+        } else {
+            (ds, Err(()))
+        }
+    }
+
+    fn check_map(vaddr: usize, pte: &PageTableEntryExec) -> (res: bool)
+        ensures res == Self::accepted_map(vaddr as nat, pte@)
+    {
+        proof { lemma_x86_arch_facts(); }
+        let size = pte.frame.size;
+        if size != L1_ENTRY_SIZE && size != L2_ENTRY_SIZE && size != L3_ENTRY_SIZE {
+            return false;
+        }
+        vaddr < MAX_BASE_EXEC
+            && size <= MAX_BASE_EXEC - vaddr
+            && aligned_exec(vaddr, size)
+            && aligned_exec(pte.frame.base, size)
+            && pte.frame.base as u64 <= MAX_PHYADDR
+    }
+
+    fn check_unmap(vaddr: usize) -> (res: bool)
+        ensures res == Self::accepted_unmap(vaddr as nat)
+    {
+        proof { lemma_x86_arch_facts(); }
+        vaddr < MAX_BASE_EXEC && aligned_exec(vaddr, PAGE_SIZE)
+    }
+
+    proof fn lemma_interp_facts(&self)
+        requires self.inv()
+        ensures
+            self@.dir.base_vaddr == 0,
+            self@.dir.upper_vaddr() == MAX_BASE,
+            self@.dir.layer == 0,
+            self@.dir.arch == x86_arch_spec,
+            self@.dir.interp().inv(),
+            self@.dir.interp().lower == 0,
+            self@.dir.interp().upper == MAX_BASE,
+            self@.dir.interp().arch == x86_arch_spec,
+    {
+        lemma_x86_arch_facts();
+        assert(PT::arch(&self.mem) == x86_arch_spec);
+        PT::lemma_interp_at_facts(&self.mem, self.pt@, 0, self.mem.cr3_spec().base, 0);
+        self@.dir.lemma_inv_implies_interp_inv();
+    }
+
+    /// Resolves the virtual address to the base address and entry of the mapping it is in
+    pub fn resolve(&self, vaddr: usize) -> (res: Result<(usize, PageTableEntryExec), ()>)
+        requires self.inv()
+        ensures res == Self::resolve_spec(self@.dir, vaddr)
+    {
+        proof { lemma_x86_arch_facts(); }
+        if vaddr >= MAX_BASE_EXEC {
+            return Err(());
+        }
+        proof { self.lemma_interp_facts(); }
+        match PT::resolve(&self.mem, self.pt, vaddr) {
+            Ok((base, pte)) => {
+                assert(pte_exec(pte@) == pte);
+                Ok((base, pte))
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Maps the frame of `pte` at the virtual address `vaddr`
+    pub fn map(&mut self, vaddr: usize, pte: PageTableEntryExec) -> (res: Result<(), MapError>)
+        requires old(self).inv()
+        ensures
+            self.inv(),
+            (self@, res) == Self::map_spec(old(self)@, vaddr, pte),
+    {
+        if !Self::check_map(vaddr, &pte) {
+            return Err(MapError::Invalid);
+        }
+        if self.mem.available_pages() < 3 {
+            return Err(MapError::OutOfMemory);
+        }
+        proof {
+            self.lemma_interp_facts();
+            lemma_x86_arch_facts();
+            assert(x86_arch_spec.contains_entry_size_at_index_atleast(pte@.frame.size, 1));
+            assert(x86_arch_spec.contains_entry_size_at_index_atleast(pte@.frame.size, 0));
+            assert(self@.dir.accepted_mapping(vaddr as nat, pte@));
+        }
+        let mut pt = self.pt;
+        let res = PT::map_frame(&mut self.mem, &mut pt, vaddr, pte);
+        self.pt = pt;
+        match res {
+            Ok(()) => Ok(()),
+            Err(()) => Err(MapError::Invalid),
+        }
+    }
+
+    /// Removes the mapping at the virtual address `vaddr`
+    pub fn unmap(&mut self, vaddr: usize) -> (res: Result<(), ()>)
+        requires old(self).inv()
+        ensures
+            self.inv(),
+            (self@, res) == Self::unmap_spec(old(self)@, vaddr),
+    {
+        if !Self::check_unmap(vaddr) {
+            return Err(());
+        }
+        proof {
+            self.lemma_interp_facts();
+            lemma_x86_arch_facts();
+            assert(x86_arch_spec.contains_entry_size(L3_ENTRY_SIZE as nat) && aligned(vaddr as nat, L3_ENTRY_SIZE as nat));
+            assert(self@.dir.accepted_unmap(vaddr as nat));
+        }
+        let mut pt = self.pt;
+        let res = PT::unmap(&mut self.mem, &mut pt, vaddr);
+        self.pt = pt;
+        res
+    }
+}
+
+impl Dispatch for VerifiedVSpace {
+    type ReadOperation = VSpaceReadOp;
+
+    type WriteOperation = VSpaceWriteOp;
+
+    type Response = VSpaceResponse;
+
+    type View = VSpaceView;
+
+    open spec fn view(&self) -> Self::View {
+        VSpaceView {
+            dir: PT::interp(&self.mem, self.pt@),
+            free_pages: self.mem.alloc_available_pages(),
+        }
+    }
+
+    open spec fn inv(&self) -> bool {
+        &&& self.mem.num_layers_spec() == X86_NUM_LAYERS
+        &&& PT::inv(&self.mem, self.pt@)
+        &&& PT::interp(&self.mem, self.pt@).inv()
+    }
+
+    open spec fn init_spec() -> Self::View {
+        VSpaceView {
+            dir: l1::Directory {
+                entries: new_seq(512, l1::NodeEntry::Empty()),
+                layer: 0,
+                base_vaddr: 0,
+                arch: x86_arch_spec,
+                flags: permissive_flags,
+            },
+            free_pages: (VSPACE_NUM_PAGES - 1) as nat,
+        }
+    }
+
+    open spec fn dispatch_spec(ds: Self::View, op: Self::ReadOperation) -> Self::Response {
+        match op {
+            VSpaceReadOp::Resolve(vaddr) => VSpaceResponse::Resolve(Self::resolve_spec(ds.dir, vaddr)),
+        }
+    }
+
+    open spec fn dispatch_mut_spec(ds: Self::View, op: Self::WriteOperation) -> (
+        Self::View,
+        Self::Response,
+    ) {
+        match op {
+            VSpaceWriteOp::Map(vaddr, pte) => {
+                let (ds, res) = Self::map_spec(ds, vaddr, pte);
+                (ds, VSpaceResponse::Map(res))
+            },
+            VSpaceWriteOp::Unmap(vaddr) => {
+                let (ds, res) = Self::unmap_spec(ds, vaddr);
+                (ds, VSpaceResponse::Unmap(res))
+            },
+        }
+    }
+
+    fn init() -> Self {
+        let mem = mem::PageTableMemory::new(VSPACE_NUM_PAGES, X86_NUM_LAYERS);
+        let pt: Ghost<PTDir> = Ghost(PTDir {
+            region: mem.cr3_spec()@,
+            entries: new_seq(512, Option::None),
+            used_regions: set![mem.cr3_spec()@],
+        });
+        proof {
+            lemma_new_seq::<Option<PTDir>>(512, Option::None);
+            lemma_new_seq::<u64>(512, 0u64);
+            lemma_new_seq::<l1::NodeEntry>(512, l1::NodeEntry::Empty());
+            assert(PT::arch(&mem) == x86_arch_spec);
+            assert(PT::inv(&mem, pt@)) by {
+                x86_arch_inv();
+                axiom_x86_arch_exec_spec();
+                PT::lemma_zeroed_page_implies_empty_at(&mem, pt@, 0, mem.cr3_spec().base);
+            };
+            PT::lemma_no_entries_implies_interp_at_aux_no_entries(mem, pt@, 0, mem.cr3_spec().base, 0, seq![]);
+            let interp = PT::interp(&mem, pt@);
+            assert(aligned(interp.base_vaddr, interp.entry_size() * interp.num_entries())) by {
+                assert(interp.base_vaddr == 0);
+                assert(forall|x: nat| x != 0 ==> #[trigger] aligned(0, x));
+                assert(interp.entry_size() * interp.num_entries() != 0);
+            };
+            assert(interp.inv());
+            assert(interp.entries =~= new_seq(512, l1::NodeEntry::Empty()));
+        }
+        VerifiedVSpace { mem, pt }
+    }
+
+    fn clone_write_op(op: &Self::WriteOperation) -> Self::WriteOperation {
+        match op {
+            VSpaceWriteOp::Map(vaddr, pte) => VSpaceWriteOp::Map(*vaddr, clone_pte(pte)),
+            VSpaceWriteOp::Unmap(vaddr) => VSpaceWriteOp::Unmap(*vaddr),
+        }
+    }
+
+    fn clone_response(op: &Self::Response) -> Self::Response {
+        match op {
+            VSpaceResponse::Resolve(Ok((base, pte))) => VSpaceResponse::Resolve(Ok((*base, clone_pte(pte)))),
+            VSpaceResponse::Resolve(Err(())) => VSpaceResponse::Resolve(Err(())),
+            VSpaceResponse::Map(Ok(())) => VSpaceResponse::Map(Ok(())),
+            VSpaceResponse::Map(Err(MapError::Invalid)) => VSpaceResponse::Map(Err(MapError::Invalid)),
+            VSpaceResponse::Map(Err(MapError::OutOfMemory)) => VSpaceResponse::Map(Err(MapError::OutOfMemory)),
+            VSpaceResponse::Unmap(Ok(())) => VSpaceResponse::Unmap(Ok(())),
+            VSpaceResponse::Unmap(Err(())) => VSpaceResponse::Unmap(Err(())),
+        }
+    }
+
+    fn dispatch(&self, op: Self::ReadOperation) -> Self::Response {
+        match op {
+            VSpaceReadOp::Resolve(vaddr) => VSpaceResponse::Resolve(self.resolve(vaddr)),
+        }
+    }
+
+    fn dispatch_mut(&mut self, op: Self::WriteOperation) -> Self::Response {
+        match op {
+            VSpaceWriteOp::Map(vaddr, pte) => VSpaceResponse::Map(self.map(vaddr, pte)),
+            VSpaceWriteOp::Unmap(vaddr) => VSpaceResponse::Unmap(self.unmap(vaddr)),
+        }
+    }
+}
+
+} // verus!
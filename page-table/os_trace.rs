@@ -2,7 +2,7 @@ use vstd::prelude::*;
 
 use crate::definitions_t::{
     candidate_mapping_in_bounds, x86_arch_spec_upper_bound, Flags, HWRWOp, HWStoreResult,
-    MemRegion, PageTableEntry,
+    MemRegion, MemoryType, PageTableEntry,
 };
 use crate::spec_t::hardware as hw;
 use crate::spec_t::mem;
@@ -42,13 +42,19 @@ proof fn program_1() {
             |core: hw::Core| core.NUMA_id < c.hw.NUMA_no && core.core_id < c.hw.core_no,
             |c| CoreState::Idle,
         ),
-        TLB_Shootdown: ShootdownVector { vaddr: 0, open_requests: set![] },
+        TLB_Shootdown: ShootdownVector { vaddr: 0, open_requests: set![], keeps_mapping: false },
         sound: true,
     };
 
     let pte1 = PageTableEntry {
         frame: MemRegion { base: 4096, size: 4096 },
-        flags: Flags { is_writable: true, is_supervisor: false, disable_execute: true },
+        flags: Flags {
+            is_writable: true,
+            is_supervisor: false,
+            disable_execute: true,
+            memory_type: MemoryType::WriteBack,
+            is_global: false,
+        },
     };
 
     assert(candidate_mapping_in_bounds(4096 * 3, pte1));
@@ -214,6 +220,7 @@ proof fn program_1() {
                 core2,
                 core3,
             ],
+            keeps_mapping: false,
         },
         ..s9b
     };
@@ -231,6 +238,7 @@ proof fn program_1() {
                 core2,
                 core3,
             ],
+            keeps_mapping: false,
         },
         ..s10
     };
@@ -245,6 +253,7 @@ proof fn program_1() {
                 core2,
                 core3,
             ],
+            keeps_mapping: false,
         },
         ..s11
     };
@@ -258,6 +267,7 @@ proof fn program_1() {
             open_requests: set![
                 core2,
             ],
+            keeps_mapping: false,
         },
         ..s12
     };
@@ -291,7 +301,7 @@ proof fn program_1() {
     ));
 
     let s15 = OSVariables {
-        TLB_Shootdown: ShootdownVector { vaddr: 4096 * 3, open_requests: set![] },
+        TLB_Shootdown: ShootdownVector { vaddr: 4096 * 3, open_requests: set![], keeps_mapping: false },
         ..s14
     };
 
@@ -0,0 +1,197 @@
+#![verus::trusted]
+// trusted:
+// this abstracts over the architecture-specific encoding of page table entries and defines the
+// page table walk in terms of that abstraction
+
+use vstd::prelude::*;
+
+use crate::definitions_t::{
+    aligned, x86_arch_spec_with_layers, Arch, Flags, MemRegion, MemoryType, PageTableEntry,
+    PAGE_SIZE,
+};
+use crate::spec_t::hardware::{self, GhostPageDirectoryEntry, PageDirectoryEntry};
+use crate::spec_t::mem;
+
+verus! {
+
+/// An entry decoded independently of the architecture. `Directory` entries carry the restrictions
+/// they impose on the mappings below them; only the access rights in these flags are relevant.
+pub ghost enum DecodedEntry {
+    Directory { addr: nat, flags: Flags },
+    Page { addr: nat, flags: Flags },
+    Empty,
+}
+
+/// The encoding of page table entries of an architecture. `arch` describes the shape of the page
+/// table, `table_size` is the size in bytes of a paging structure, `index` selects the entry for a
+/// virtual address in a paging structure and `decode` interprets a raw entry found on a given
+/// layer. `num_layers` is the number of layers the page table memory was created with (see
+/// `mem::PageTableMemory::num_layers_spec`); formats with a fixed number of layers ignore it.
+pub trait ArchEntryFormat {
+    spec fn arch(num_layers: nat) -> Arch;
+
+    spec fn table_size() -> nat;
+
+    spec fn index(num_layers: nat, layer: nat, addr: u64) -> nat;
+
+    spec fn decode(num_layers: nat, entry: u64, layer: nat) -> DecodedEntry;
+}
+
+pub open spec fn read_decoded_entry<F: ArchEntryFormat>(
+    pt_mem: mem::PageTableMemory,
+    dir_addr: nat,
+    layer: nat,
+    idx: nat,
+) -> DecodedEntry {
+    let region = MemRegion { base: dir_addr, size: F::table_size() };
+    F::decode(pt_mem.num_layers_spec(), pt_mem.spec_read(idx, region), layer)
+}
+
+/// The frame mapping the MMU arrives at when walking the page table from the paging structure at
+/// `dir_addr` on `layer`. `flags` are the restrictions accumulated on the directories visited so
+/// far. This is the only definition of the page table walk; the hardware state machine uses it
+/// with `X86EntryFormat`.
+pub open spec fn walk<F: ArchEntryFormat>(
+    pt_mem: mem::PageTableMemory,
+    layer: nat,
+    dir_addr: nat,
+    addr: u64,
+    flags: Flags,
+) -> Option<PageTableEntry>
+    decreases F::arch(pt_mem.num_layers_spec()).layers.len() - layer,
+{
+    let num_layers = pt_mem.num_layers_spec();
+    if layer < F::arch(num_layers).layers.len() {
+        match read_decoded_entry::<F>(pt_mem, dir_addr, layer, F::index(num_layers, layer, addr)) {
+            DecodedEntry::Directory { addr: next_dir_addr, flags: dir_flags } => {
+                walk::<F>(
+                    pt_mem,
+                    layer + 1,
+                    next_dir_addr,
+                    addr,
+                    Flags {
+                        is_writable: flags.is_writable && dir_flags.is_writable,
+                        is_supervisor: flags.is_supervisor || dir_flags.is_supervisor,
+                        disable_execute: flags.disable_execute || dir_flags.disable_execute,
+                        memory_type: flags.memory_type,
+                        is_global: flags.is_global,
+                    },
+                )
+            },
+            DecodedEntry::Page { addr: page_addr, flags: page_flags } => {
+                let size = F::arch(num_layers).entry_size(layer);
+                if aligned(addr as nat, size) {
+                    Some(
+                        PageTableEntry {
+                            frame: MemRegion { base: page_addr, size },
+                            flags: Flags {
+                                is_writable: flags.is_writable && page_flags.is_writable,
+                                is_supervisor: flags.is_supervisor || page_flags.is_supervisor,
+                                disable_execute: flags.disable_execute
+                                    || page_flags.disable_execute,
+                                memory_type: page_flags.memory_type,
+                                is_global: page_flags.is_global,
+                            },
+                        },
+                    )
+                } else {
+                    None
+                }
+            },
+            DecodedEntry::Empty => None,
+        }
+    } else {
+        None
+    }
+}
+
+pub open spec fn valid_walk<F: ArchEntryFormat>(
+    pt_mem: mem::PageTableMemory,
+    addr: u64,
+    pte: PageTableEntry,
+) -> bool {
+    let initial_flags = Flags {
+        is_writable: true,
+        is_supervisor: false,
+        disable_execute: false,
+        memory_type: MemoryType::WriteBack,
+        is_global: false,
+    };
+    walk::<F>(pt_mem, 0, pt_mem.cr3_spec()@.base, addr, initial_flags) == Some(pte)
+}
+
+/// Page table walker interpretation of the page table memory for the entry format `F`
+pub open spec fn interp_walk<F: ArchEntryFormat>(pt_mem: mem::PageTableMemory) -> Map<
+    nat,
+    PageTableEntry,
+> {
+    Map::new(
+        |addr: nat|
+            addr < F::arch(pt_mem.num_layers_spec()).upper_vaddr(0, 0)
+            // Casting addr to u64 is okay since the upper bound is at most 2^57
+             && exists|pte: PageTableEntry|
+                valid_walk::<F>(pt_mem, hardware::nat_to_u64(addr), pte),
+        |addr: nat|
+            choose|pte: PageTableEntry| valid_walk::<F>(pt_mem, hardware::nat_to_u64(addr), pte),
+    )
+}
+
+/// The x86 entry format as defined in `spec_t::hardware`, with 4-level or 5-level (LA57) paging
+pub struct X86EntryFormat {}
+
+impl ArchEntryFormat for X86EntryFormat {
+    open spec fn arch(num_layers: nat) -> Arch {
+        x86_arch_spec_with_layers(num_layers)
+    }
+
+    open spec fn table_size() -> nat {
+        PAGE_SIZE as nat
+    }
+
+    open spec fn index(num_layers: nat, layer: nat, addr: u64) -> nat {
+        hardware::walk_index(num_layers, layer, addr)
+    }
+
+    open spec fn decode(num_layers: nat, entry: u64, layer: nat) -> DecodedEntry {
+        let format_layer = hardware::entry_format_layer(num_layers, layer);
+        match (PageDirectoryEntry { entry, layer: Ghost(format_layer) })@ {
+            GhostPageDirectoryEntry::Directory { addr, flag_RW, flag_US, flag_XD, .. } => {
+                DecodedEntry::Directory {
+                    addr: addr as nat,
+                    flags: Flags {
+                        is_writable: flag_RW,
+                        is_supervisor: !flag_US,
+                        disable_execute: flag_XD,
+                        memory_type: MemoryType::WriteBack,
+                        is_global: false,
+                    },
+                }
+            },
+            GhostPageDirectoryEntry::Page {
+                addr,
+                flag_RW,
+                flag_US,
+                flag_PWT,
+                flag_PCD,
+                flag_G,
+                flag_PAT,
+                flag_XD,
+                ..
+            } => {
+                DecodedEntry::Page {
+                    addr: addr as nat,
+                    flags: Flags {
+                        is_writable: flag_RW,
+                        is_supervisor: !flag_US,
+                        disable_execute: flag_XD,
+                        memory_type: hardware::memory_type(flag_PAT, flag_PCD, flag_PWT),
+                        is_global: flag_G,
+                    },
+                }
+            },
+            GhostPageDirectoryEntry::Empty => DecodedEntry::Empty,
+        }
+    }
+}
+
+} // verus!
@@ -5,9 +5,10 @@
 
 use crate::definitions_t::{
     aligned, axiom_max_phyaddr_width_facts, between, bit, bitmask_inc, Flags, HWRWOp, MemRegion,
-    PageTableEntry, L1_ENTRY_SIZE, L2_ENTRY_SIZE, L3_ENTRY_SIZE, MAX_BASE, MAX_PHYADDR_WIDTH,
-    PAGE_SIZE,
+    MemoryType, PageTableEntry, L1_ENTRY_SIZE, L2_ENTRY_SIZE, L3_ENTRY_SIZE,
+    MAX_PHYADDR_WIDTH, PAGE_SIZE, X86_NUM_LAYERS,
 };
+use crate::spec_t::entry_format::{interp_walk, read_decoded_entry, DecodedEntry, X86EntryFormat};
 use crate::spec_t::mem::{self, word_index_spec};
 use vstd::prelude::*;
 
@@ -419,6 +420,18 @@ pub open spec fn read_entry(
     PageDirectoryEntry { entry: pt_mem.spec_read(idx, region), layer: Ghost(layer) }@
 }
 
+/// The memory type of a frame mapping, as determined by its PAT, PCD and PWT bits. We assume the
+/// PAT MSR has its power-on default value, i.e. the PAT entries 4-7 are the same as entries 0-3,
+/// which makes the PAT bit irrelevant.
+pub open spec fn memory_type(flag_PAT: bool, flag_PCD: bool, flag_PWT: bool) -> MemoryType {
+    match (flag_PCD, flag_PWT) {
+        (false, false) => MemoryType::WriteBack,
+        (false, true)  => MemoryType::WriteThrough,
+        (true, false)  => MemoryType::UncacheableMinus,
+        (true, true)   => MemoryType::Uncacheable,
+    }
+}
+
 /// TODO: list 4-level paging no HLAT etc. as assumptions (+ the register to enable XD semantics,
 /// it's must-be-zero otherwise)
 ///
@@ -429,12 +442,17 @@ pub open spec fn read_entry(
 /// true iff the RW flag is set in all directories along the translation path and in the frame
 /// mapping. Similarly, `pte.flags.is_supervisor` is true iff the US flag is unset in all those
 /// structures and `pte.flags.disable_execute` is true iff the XD flag is set in at least one of
-/// those structures.
+/// those structures. The memory type and the global bit are taken from the frame mapping only; the
+/// PWT and PCD bits on directories only affect how the directories themselves are cached.
 ///
 /// In practice, we always set these flags to their more permissive state in directories and only
 /// make more restrictive settings in the frame mappings. (Ensured in the invariant, see conjunct
 /// `directories_have_flags` in refinement layers 1 and 2.) But in the hardware model we still
 /// define the full, correct semantics to ensure the implementation sets the flags correctly.
+///
+/// The hardware state machine uses `entry_format::walk` with `X86EntryFormat`, which also covers
+/// 5-level paging. With four layers it is the same as this walk (see
+/// `l2_refinement::lemma_valid_pt_walk_with_four_layers`).
 pub open spec fn valid_pt_walk(
     pt_mem: mem::PageTableMemory,
     addr: u64,
@@ -457,6 +475,10 @@ pub open spec fn valid_pt_walk(
                     addr: page_addr,
                     flag_RW: l1_RW,
                     flag_US: l1_US,
+                    flag_PWT: l1_PWT,
+                    flag_PCD: l1_PCD,
+                    flag_G: l1_G,
+                    flag_PAT: l1_PAT,
                     flag_XD: l1_XD,
                     ..
                 } => {
@@ -466,6 +488,8 @@ pub open spec fn valid_pt_walk(
                             is_writable: l0_RW && l1_RW,
                             is_supervisor: !l0_US || !l1_US,
                             disable_execute: l0_XD || l1_XD,
+                            memory_type: memory_type(l1_PAT, l1_PCD, l1_PWT),
+                            is_global: l1_G,
                         },
                     }
                 },
@@ -481,6 +505,10 @@ pub open spec fn valid_pt_walk(
                             addr: page_addr,
                             flag_RW: l2_RW,
                             flag_US: l2_US,
+                            flag_PWT: l2_PWT,
+                            flag_PCD: l2_PCD,
+                            flag_G: l2_G,
+                            flag_PAT: l2_PAT,
                             flag_XD: l2_XD,
                             ..
                         } => {
@@ -493,6 +521,8 @@ pub open spec fn valid_pt_walk(
                                     is_writable: l0_RW && l1_RW && l2_RW,
                                     is_supervisor: !l0_US || !l1_US || !l2_US,
                                     disable_execute: l0_XD || l1_XD || l2_XD,
+                                    memory_type: memory_type(l2_PAT, l2_PCD, l2_PWT),
+                                    is_global: l2_G,
                                 },
                             }
                         },
@@ -508,6 +538,10 @@ pub open spec fn valid_pt_walk(
                                     addr: page_addr,
                                     flag_RW: l3_RW,
                                     flag_US: l3_US,
+                                    flag_PWT: l3_PWT,
+                                    flag_PCD: l3_PCD,
+                                    flag_G: l3_G,
+                                    flag_PAT: l3_PAT,
                                     flag_XD: l3_XD,
                                     ..
                                 } => {
@@ -521,6 +555,8 @@ pub open spec fn valid_pt_walk(
                                             is_writable: l0_RW && l1_RW && l2_RW && l3_RW,
                                             is_supervisor: !l0_US || !l1_US || !l2_US || !l3_US,
                                             disable_execute: l0_XD || l1_XD || l2_XD || l3_XD,
+                                            memory_type: memory_type(l3_PAT, l3_PCD, l3_PWT),
+                                            is_global: l3_G,
                                         },
                                     }
                                 },
@@ -546,16 +582,36 @@ pub open spec fn nat_to_u64(n: nat) -> u64
     n as u64
 }
 
-/// Page table walker interpretation of the page table memory
+/// Page table walker interpretation of the page table memory, walking as many layers as the
+/// paging mode the memory was set up for. The walk is `entry_format::walk` with the x86 entry
+/// format; `valid_pt_walk` above spells out the 4-level case.
 pub open spec fn interp_pt_mem(pt_mem: mem::PageTableMemory) -> Map<nat, PageTableEntry> {
-    Map::new(
-        |addr: nat|
-            addr
-                < MAX_BASE
-            // Casting addr to u64 is okay since addr < MAX_BASE < u64::MAX
-             && exists|pte: PageTableEntry| valid_pt_walk(pt_mem, nat_to_u64(addr), pte),
-        |addr: nat| choose|pte: PageTableEntry| valid_pt_walk(pt_mem, nat_to_u64(addr), pte),
-    )
+    interp_walk::<X86EntryFormat>(pt_mem)
+}
+
+// The x86 page table walk generalized over the number of paging layers, to cover 5-level paging
+// (LA57). These define `X86EntryFormat`.
+
+/// The index into the paging structure on `layer` of a walk through `num_layers` layers. These are
+/// bits 48:56 of `addr` for the PML5, bits 39:47 for the PML4 and so on, down to bits 12:20 for the
+/// page table.
+pub open spec fn walk_index(num_layers: nat, layer: nat, addr: u64) -> nat
+    recommends
+        layer < num_layers,
+{
+    let shift = (12 + 9 * (num_layers - 1 - layer)) as u64;
+    ((addr >> shift) & 0x1FFu64) as nat
+}
+
+/// The format of an entry only depends on the distance of its paging structure to the bottom of
+/// the walk. PML5 entries have the same format as PML4 entries, which are layer 0 in
+/// `PageDirectoryEntry`.
+pub open spec fn entry_format_layer(num_layers: nat, layer: nat) -> nat {
+    if layer + X86_NUM_LAYERS >= num_layers {
+        (layer + X86_NUM_LAYERS - num_layers) as nat
+    } else {
+        0
+    }
 }
 
 pub open spec fn init(c: HWConstants, s: HWVariables) -> bool {
@@ -570,6 +626,83 @@ pub open spec fn NUMA_init(c: HWConstants, n: NUMAVariables) -> bool {
     &&& forall|id: nat| #[trigger] valid_core_id(c, id) ==> n.cores[id].tlb.dom() === Set::empty()
 }
 
+/// An entry the MMU uses when walking the page table: its index `idx` in the paging structure
+/// `region`, and whether it maps a page rather than a directory
+pub ghost struct WalkPathEntry {
+    pub region: MemRegion,
+    pub idx: nat,
+    pub is_page: bool,
+}
+
+/// The present entries the MMU uses to translate `addr`, starting with the paging structure at
+/// `dir_addr` on `layer`
+pub open spec fn walk_path(
+    pt_mem: mem::PageTableMemory,
+    layer: nat,
+    dir_addr: nat,
+    addr: u64,
+) -> Seq<WalkPathEntry>
+    decreases pt_mem.num_layers_spec() - layer,
+{
+    let num_layers = pt_mem.num_layers_spec();
+    if layer < num_layers {
+        let region = MemRegion { base: dir_addr, size: PAGE_SIZE as nat };
+        let idx = walk_index(num_layers, layer, addr);
+        match read_decoded_entry::<X86EntryFormat>(pt_mem, dir_addr, layer, idx) {
+            DecodedEntry::Directory { addr: next_dir_addr, .. } => {
+                seq![WalkPathEntry { region, idx, is_page: false }]
+                    + walk_path(pt_mem, layer + 1, next_dir_addr, addr)
+            },
+            DecodedEntry::Page { .. } => {
+                seq![WalkPathEntry { region, idx, is_page: true }]
+            },
+            DecodedEntry::Empty => seq![],
+        }
+    } else {
+        seq![]
+    }
+}
+
+/// The MMU may set the accessed flag and, if `set_dirty`, also the dirty flag. It never clears
+/// them.
+pub open spec fn ad_bits_updated(ad1: u64, ad2: u64, set_dirty: bool) -> bool {
+    ||| ad2 == ad1
+    ||| ad2 == ad1 | MASK_FLAG_A
+    ||| set_dirty && ad2 == ad1 | MASK_FLAG_A | MASK_PG_FLAG_D
+}
+
+/// `pt_mem2` is `pt_mem1` after the MMU translated `addr`: It set the accessed flags of the entries
+/// it used and, on a write, the dirty flag of the entry mapping the page. The accessed and dirty
+/// flags are kept apart from the rest of the entries (see `mem::PageTableMemory`), so the latter
+/// don't change.
+pub open spec fn pt_mem_ad_bits_updated(
+    pt_mem1: mem::PageTableMemory,
+    pt_mem2: mem::PageTableMemory,
+    addr: u64,
+    is_write: bool,
+) -> bool {
+    let path = walk_path(pt_mem1, 0, pt_mem1.cr3_spec()@.base, addr);
+    &&& pt_mem2.regions() == pt_mem1.regions()
+    &&& pt_mem2.cr3_spec() == pt_mem1.cr3_spec()
+    &&& pt_mem2.num_layers_spec() == pt_mem1.num_layers_spec()
+    &&& pt_mem2.phys_mem_ref_as_usize_spec() == pt_mem1.phys_mem_ref_as_usize_spec()
+    &&& pt_mem2.alloc_available_pages() == pt_mem1.alloc_available_pages()
+    &&& forall|r: MemRegion| #[trigger] pt_mem2.region_view(r) == pt_mem1.region_view(r)
+    &&& forall|r: MemRegion| #[trigger] pt_mem2.ad_view(r).len() == pt_mem1.ad_view(r).len()
+    &&& forall|r: MemRegion, i: nat|
+        i < 512 ==> {
+            let ad1 = pt_mem1.spec_read_ad(i, r);
+            let ad2 = #[trigger] pt_mem2.spec_read_ad(i, r);
+            if path.contains(WalkPathEntry { region: r, idx: i, is_page: true }) {
+                ad_bits_updated(ad1, ad2, is_write)
+            } else if path.contains(WalkPathEntry { region: r, idx: i, is_page: false }) {
+                ad_bits_updated(ad1, ad2, false)
+            } else {
+                ad2 == ad1
+            }
+        }
+}
+
 // We only allow aligned accesses. Can think of unaligned accesses as two aligned accesses. When we
 // get to concurrency we may have to change that.
 pub open spec fn step_ReadWrite(
@@ -603,9 +736,16 @@ pub open spec fn step_ReadWrite(
                         && pte.flags.is_writable {
                         &&& result is Ok
                         &&& s2.mem === s1.mem.update(pmem_idx as int, new_value)
+                        &&& pt_mem_ad_bits_updated(
+                            s1.global_pt,
+                            s2.global_pt,
+                            nat_to_u64(vaddr),
+                            true,
+                        )
                     } else {
                         &&& result is Pagefault
                         &&& s2.mem === s1.mem
+                        &&& s2.global_pt == s1.global_pt
                     }
                 },
                 HWRWOp::Load { is_exec, result } => {
@@ -614,8 +754,15 @@ pub open spec fn step_ReadWrite(
                         ==> !pte.flags.disable_execute) {
                         &&& result is Value
                         &&& result->0 == s1.mem[pmem_idx as int]
+                        &&& pt_mem_ad_bits_updated(
+                            s1.global_pt,
+                            s2.global_pt,
+                            nat_to_u64(vaddr),
+                            false,
+                        )
                     } else {
                         &&& result is Pagefault
+                        &&& s2.global_pt == s1.global_pt
                     }
                 },
             }
@@ -630,6 +777,7 @@ pub open spec fn step_ReadWrite(
             // .. and the result is always a Undefined and an unchanged memory.
 
             &&& s2.mem === s1.mem
+            &&& s2.global_pt == s1.global_pt
             &&& match op {
                 HWRWOp::Store { new_value, result } => result is Pagefault,
                 HWRWOp::Load { is_exec, result } => result is Pagefault,
@@ -696,6 +844,8 @@ pub open spec fn step_TLBFill(
     &&& s2.NUMAs[core.NUMA_id].cores[core.core_id].tlb
         === s1.NUMAs[core.NUMA_id].cores[core.core_id].tlb.insert(vaddr, pte)
     &&& other_NUMAs_and_cores_unchanged(c, s1, s2, core)
+    // the page table walk sets the accessed flags of the entries it uses
+    &&& pt_mem_ad_bits_updated(s1.global_pt, s2.global_pt, nat_to_u64(vaddr), false)
 }
 
 pub open spec fn step_TLBEvict(
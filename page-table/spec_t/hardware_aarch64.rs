@@ -0,0 +1,237 @@
+#![verus::trusted]
+// trusted:
+// this defines the ARMv8-A stage 1 translation table descriptors as interpreted by the hardware,
+// for the 4K and 64K translation granules with 48-bit virtual addresses
+
+use vstd::prelude::*;
+
+use crate::definitions_t::{bit, bitmask_inc, Arch, ArchLayer, Flags, MemoryType};
+use crate::spec_t::entry_format::{ArchEntryFormat, DecodedEntry};
+
+verus! {
+
+// Assumptions about the system configuration:
+// - TCR_EL1.T0SZ = 16, i.e. 48-bit virtual addresses, and the root of the translation table is
+//   the region referenced by `cr3_spec` of the `PageTableMemory`.
+// - MAIR_EL1 holds Normal write-back, Normal write-through, Normal non-cacheable and
+//   Device-nGnRnE in attribute indices 0 to 3 and repeats them in indices 4 to 7, so the memory
+//   type is determined by the lower two bits of AttrIndx.
+// - The access flag is managed by hardware (FEAT_HAFDBS), so AF does not affect translation.
+// - Execute-never bits on table descriptors apply to the whole subtree; we don't distinguish
+//   between the privileged and unprivileged variants on tables.
+pub const MASK_DESC_VALID: u64 = bit!(0u64);
+
+/// Table descriptor on layers above the last, page descriptor on the last layer; block
+/// descriptor if unset
+pub const MASK_DESC_TABLE: u64 = bit!(1u64);
+
+pub spec const MASK_DESC_ATTR_INDX: u64 = bitmask_inc!(2u64, 4u64);
+
+/// AP[1]; if set, EL0 may access the memory mapped by this descriptor
+pub const MASK_DESC_AP_EL0: u64 = bit!(6u64);
+
+/// AP[2]; if set, the memory mapped by this descriptor is read-only
+pub const MASK_DESC_AP_RO: u64 = bit!(7u64);
+
+pub const MASK_DESC_AF: u64 = bit!(10u64);
+
+/// Not global; if set, the translation is specific to the current ASID
+pub const MASK_DESC_NG: u64 = bit!(11u64);
+
+pub const MASK_DESC_PXN: u64 = bit!(53u64);
+
+pub const MASK_DESC_UXN: u64 = bit!(54u64);
+
+pub const MASK_TABLE_PXN: u64 = bit!(59u64);
+
+pub const MASK_TABLE_UXN: u64 = bit!(60u64);
+
+/// APTable[0]; if set, EL0 may not access memory mapped below this table
+pub const MASK_TABLE_AP_NO_EL0: u64 = bit!(61u64);
+
+/// APTable[1]; if set, memory mapped below this table is read-only
+pub const MASK_TABLE_AP_RO: u64 = bit!(62u64);
+
+pub ghost enum Granule {
+    Size4K,
+    Size64K,
+}
+
+/// Four layers of 512 entries; layer 0 corresponds to lookup level 0.
+pub spec const aarch64_4k_arch_spec: Arch = Arch {
+    layers: seq![
+        ArchLayer { entry_size: 512 * 1024 * 1024 * 1024, num_entries: 512 },
+        ArchLayer { entry_size: 1024 * 1024 * 1024, num_entries: 512 },
+        ArchLayer { entry_size: 2 * 1024 * 1024, num_entries: 512 },
+        ArchLayer { entry_size: 4096, num_entries: 512 },
+    ],
+};
+
+/// Three layers; layer 0 corresponds to lookup level 1, which only resolves 6 address bits with
+/// a 48-bit virtual address space.
+pub spec const aarch64_64k_arch_spec: Arch = Arch {
+    layers: seq![
+        ArchLayer { entry_size: 4 * 1024 * 1024 * 1024 * 1024, num_entries: 64 },
+        ArchLayer { entry_size: 512 * 1024 * 1024, num_entries: 8192 },
+        ArchLayer { entry_size: 64 * 1024, num_entries: 8192 },
+    ],
+};
+
+pub open spec fn granule_arch(granule: Granule) -> Arch {
+    match granule {
+        Granule::Size4K => aarch64_4k_arch_spec,
+        Granule::Size64K => aarch64_64k_arch_spec,
+    }
+}
+
+pub open spec fn granule_size(granule: Granule) -> nat {
+    match granule {
+        Granule::Size4K => 4096,
+        Granule::Size64K => 64 * 1024,
+    }
+}
+
+/// The lowest bit of the virtual address translated on `layer`
+pub open spec fn index_shift(granule: Granule, layer: nat) -> u64 {
+    match granule {
+        Granule::Size4K => (12 + 9 * (3 - layer)) as u64,
+        Granule::Size64K => (16 + 13 * (2 - layer)) as u64,
+    }
+}
+
+pub open spec fn table_index(granule: Granule, layer: nat, addr: u64) -> nat {
+    let num_entries = granule_arch(granule).num_entries(layer) as u64;
+    ((addr >> index_shift(granule, layer)) % num_entries) as nat
+}
+
+/// Whether block descriptors are permitted on `layer`. Without FEAT_LPA the 64K granule has no
+/// blocks on lookup level 1.
+pub open spec fn block_allowed(granule: Granule, layer: nat) -> bool {
+    match granule {
+        Granule::Size4K => layer == 1 || layer == 2,
+        Granule::Size64K => layer == 1,
+    }
+}
+
+/// Output address of a table or page descriptor
+pub open spec fn next_level_addr(granule: Granule, entry: u64) -> nat {
+    match granule {
+        Granule::Size4K => (entry & bitmask_inc!(12u64, 47u64)) as nat,
+        Granule::Size64K => (entry & bitmask_inc!(16u64, 47u64)) as nat,
+    }
+}
+
+/// Output address of a block descriptor on `layer`
+pub open spec fn block_addr(granule: Granule, layer: nat, entry: u64) -> nat {
+    (entry & bitmask_inc!(index_shift(granule, layer), 47u64)) as nat
+}
+
+pub open spec fn attr_index_memory_type(entry: u64) -> MemoryType {
+    let attr_indx = (entry & MASK_DESC_ATTR_INDX) >> 2u64;
+    if attr_indx % 4 == 0 {
+        MemoryType::WriteBack
+    } else if attr_indx % 4 == 1 {
+        MemoryType::WriteThrough
+    } else if attr_indx % 4 == 2 {
+        MemoryType::UncacheableMinus
+    } else {
+        MemoryType::Uncacheable
+    }
+}
+
+pub open spec fn block_or_page_flags(entry: u64) -> Flags {
+    let is_supervisor = entry & MASK_DESC_AP_EL0 != MASK_DESC_AP_EL0;
+    Flags {
+        is_writable: entry & MASK_DESC_AP_RO != MASK_DESC_AP_RO,
+        is_supervisor,
+        disable_execute: if is_supervisor {
+            entry & MASK_DESC_PXN == MASK_DESC_PXN
+        } else {
+            entry & MASK_DESC_UXN == MASK_DESC_UXN
+        },
+        memory_type: attr_index_memory_type(entry),
+        is_global: entry & MASK_DESC_NG != MASK_DESC_NG,
+    }
+}
+
+pub open spec fn table_flags(entry: u64) -> Flags {
+    Flags {
+        is_writable: entry & MASK_TABLE_AP_RO != MASK_TABLE_AP_RO,
+        is_supervisor: entry & MASK_TABLE_AP_NO_EL0 == MASK_TABLE_AP_NO_EL0,
+        disable_execute: {
+            ||| entry & MASK_TABLE_PXN == MASK_TABLE_PXN
+            ||| entry & MASK_TABLE_UXN == MASK_TABLE_UXN
+        },
+        memory_type: MemoryType::WriteBack,
+        is_global: false,
+    }
+}
+
+pub open spec fn decode_descriptor(granule: Granule, entry: u64, layer: nat) -> DecodedEntry {
+    let last_layer = layer + 1 == granule_arch(granule).layers.len();
+    if entry & MASK_DESC_VALID != MASK_DESC_VALID {
+        DecodedEntry::Empty
+    } else if entry & MASK_DESC_TABLE == MASK_DESC_TABLE {
+        if last_layer {
+            DecodedEntry::Page {
+                addr: next_level_addr(granule, entry),
+                flags: block_or_page_flags(entry),
+            }
+        } else {
+            DecodedEntry::Directory {
+                addr: next_level_addr(granule, entry),
+                flags: table_flags(entry),
+            }
+        }
+    } else if block_allowed(granule, layer) {
+        DecodedEntry::Page {
+            addr: block_addr(granule, layer, entry),
+            flags: block_or_page_flags(entry),
+        }
+    } else {
+        // Reserved encoding, causes a translation fault
+        DecodedEntry::Empty
+    }
+}
+
+pub struct AArch64Granule4K {}
+
+impl ArchEntryFormat for AArch64Granule4K {
+    open spec fn arch(num_layers: nat) -> Arch {
+        aarch64_4k_arch_spec
+    }
+
+    open spec fn table_size() -> nat {
+        granule_size(Granule::Size4K)
+    }
+
+    open spec fn index(num_layers: nat, layer: nat, addr: u64) -> nat {
+        table_index(Granule::Size4K, layer, addr)
+    }
+
+    open spec fn decode(num_layers: nat, entry: u64, layer: nat) -> DecodedEntry {
+        decode_descriptor(Granule::Size4K, entry, layer)
+    }
+}
+
+pub struct AArch64Granule64K {}
+
+impl ArchEntryFormat for AArch64Granule64K {
+    open spec fn arch(num_layers: nat) -> Arch {
+        aarch64_64k_arch_spec
+    }
+
+    open spec fn table_size() -> nat {
+        granule_size(Granule::Size64K)
+    }
+
+    open spec fn index(num_layers: nat, layer: nat, addr: u64) -> nat {
+        table_index(Granule::Size64K, layer, addr)
+    }
+
+    open spec fn decode(num_layers: nat, entry: u64, layer: nat) -> DecodedEntry {
+        decode_descriptor(Granule::Size64K, entry, layer)
+    }
+}
+
+} // verus!
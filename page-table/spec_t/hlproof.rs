@@ -1,7 +1,7 @@
 #![verus::trusted]
 use crate::definitions_t::{
-    above_zero, between, candidate_mapping_overlaps_existing_pmem, overlap, MemRegion,
-    PageTableEntry, WORD_SIZE,
+    above_zero, between, candidate_mapping_overlaps_existing_pmem, overlap, range_entry_base,
+    Flags, MemRegion, PageTableEntry, WORD_SIZE,
 };
 use crate::spec_t::mem;
 use vstd::prelude::*;
@@ -12,8 +12,10 @@ use crate::spec_t::hlspec::{
     candidate_mapping_overlaps_inflight_pmem, if_map_then_unique, inflight_maps_unique,
     inflight_mem_size_over_zero, inv, mappings_frame_sizes_over_zero, mem_domain_from_entry,
     mem_domain_from_entry_contains, mem_domain_from_mappings, mem_domain_from_mappings_contains,
-    pmem_no_overlap, step_Map_end, step_Map_start, step_Unmap_start, AbstractArguments,
-    AbstractConstants, AbstractVariables,
+    map_range_prefix_len, pmem_no_overlap, range_mappings, step_MapRange_end, step_MapRange_start,
+    step_Map_enabled, step_Map_end, step_Map_start, step_Protect_start, step_UnmapRange_start,
+    step_Unmap_start,
+    AbstractArguments, AbstractConstants, AbstractVariables,
 };
 
 verus! {
@@ -282,7 +284,7 @@ pub proof fn insert_non_map_preserves_unique(
         if (args.dom().contains(id)) {
             if (id == base) {
             } else {
-                if let AbstractArguments::Map { vaddr, pte } = thread_state.index(id) {
+                if is_map(thread_state.index(id)) {
                     assert(args.remove(id) == thread_state.remove(id).insert(base, arg));
                 } else {
                 }
@@ -339,6 +341,9 @@ pub proof fn insert_map_preserves_unique(
                     assert(!overlap(pte.frame, y.frame));
                     assert(args.index(id) != arg);
                     assert(args.remove(id) == thread_state.remove(id).insert(thread_id, arg));
+                } else if thread_state.index(id) is MapRange {
+                    assert(args.index(id) != arg);
+                    assert(args.remove(id) == thread_state.remove(id).insert(thread_id, arg));
                 } else {
                 }
             }
@@ -347,14 +352,57 @@ pub proof fn insert_map_preserves_unique(
     }
 }
 
-pub open spec fn is_map(arg: AbstractArguments) -> bool {
-    if let AbstractArguments::Map { vaddr, pte } = arg {
-        true
-    } else {
-        false
+pub proof fn insert_map_range_preserves_unique(
+    thread_state: Map<nat, AbstractArguments>,
+    thread_id: nat,
+    vaddr: nat,
+    ptes: Seq<PageTableEntry>,
+)
+    requires
+        inflight_maps_unique(thread_state),
+        0 < ptes.len(),
+        above_zero(ptes[0].frame.size),
+        !candidate_mapping_overlaps_inflight_pmem(thread_state.values(), ptes[0]),
+    ensures
+        inflight_maps_unique(
+            thread_state.insert(thread_id, AbstractArguments::MapRange { vaddr, ptes }),
+        ),
+{
+    let arg = AbstractArguments::MapRange { vaddr, ptes };
+    let args = thread_state.insert(thread_id, arg);
+    assert forall|id: nat| #[trigger] args.dom().contains(id) implies if_map_then_unique(
+        args,
+        id,
+    ) by {
+        if (id == thread_id) {
+            // Any other inflight range with the same entries would overlap with `ptes[0]`
+            assert forall|other_id: nat| #[trigger]
+                thread_state.dom().contains(other_id) implies arg != thread_state.index(
+                other_id,
+            ) by {
+                if thread_state.index(other_id) === arg {
+                    assert(thread_state.values().contains(arg));
+                    assert(overlap(ptes[0].frame, ptes[0].frame));
+                }
+            }
+            assert(args.remove(id) == thread_state.remove(id));
+        } else {
+            if is_map(thread_state.index(id)) {
+                if thread_state.index(id) === arg {
+                    assert(thread_state.values().contains(arg));
+                    assert(overlap(ptes[0].frame, ptes[0].frame));
+                }
+                assert(args.remove(id) == thread_state.remove(id).insert(thread_id, arg));
+            } else {
+            }
+        }
     }
 }
 
+pub open spec fn is_map(arg: AbstractArguments) -> bool {
+    arg is Map || arg is MapRange
+}
+
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 //                                                                                                               //
 //                                        Step preserves inv proofs                                              //
@@ -459,4 +507,207 @@ pub proof fn map_end_preserves_inv(
     }
 }
 
+pub proof fn lemma_map_range_prefix_len_bound(
+    mappings: Map<nat, PageTableEntry>,
+    vaddr: nat,
+    ptes: Seq<PageTableEntry>,
+)
+    ensures
+        map_range_prefix_len(mappings, vaddr, ptes) <= ptes.len(),
+    decreases ptes.len(),
+{
+    if ptes.len() > 0 {
+        lemma_map_range_prefix_len_bound(mappings, vaddr, ptes.drop_last());
+    }
+}
+
+pub proof fn lemma_range_mappings_union_no_overlap_pmem(
+    mappings: Map<nat, PageTableEntry>,
+    vaddr: nat,
+    ptes: Seq<PageTableEntry>,
+    k: nat,
+)
+    requires
+        mappings.dom().finite(),
+        pmem_no_overlap(mappings),
+        mappings_frame_sizes_over_zero(mappings),
+        k <= ptes.len(),
+        forall|i: int| 0 <= i < ptes.len() ==> above_zero(#[trigger] ptes[i].frame.size),
+        forall|i: int|
+            0 <= i < ptes.len() ==> !candidate_mapping_overlaps_existing_pmem(
+                mappings,
+                #[trigger] ptes[i],
+            ),
+        forall|i: int, j: int|
+            0 <= i < ptes.len() && 0 <= j < ptes.len() && i != j ==> !overlap(
+                #[trigger] ptes[i].frame,
+                #[trigger] ptes[j].frame,
+            ),
+    ensures
+        ({
+            let mappings_k = mappings.union_prefer_right(range_mappings(vaddr, ptes.take(k as int)));
+            &&& mappings_k.dom().finite()
+            &&& pmem_no_overlap(mappings_k)
+            &&& mappings_frame_sizes_over_zero(mappings_k)
+            &&& forall|i: int|
+                k <= i < ptes.len() ==> !candidate_mapping_overlaps_existing_pmem(
+                    mappings_k,
+                    #[trigger] ptes[i],
+                )
+        }),
+    decreases k,
+{
+    let mappings_k = mappings.union_prefer_right(range_mappings(vaddr, ptes.take(k as int)));
+    if k == 0 {
+        assert(range_mappings(vaddr, ptes.take(0)) =~= Map::empty());
+        assert(mappings_k =~= mappings);
+    } else {
+        lemma_range_mappings_union_no_overlap_pmem(mappings, vaddr, ptes, (k - 1) as nat);
+        let prev = mappings.union_prefer_right(range_mappings(vaddr, ptes.take(k - 1)));
+        let base = range_entry_base(vaddr, ptes, k - 1);
+        let pte = ptes[k - 1];
+        assert(ptes.take(k as int).drop_last() =~= ptes.take(k - 1));
+        assert(ptes.take(k as int).last() === pte);
+        assert(mappings_k =~= prev.insert(base, pte));
+        lemma_overlap(prev, base, pte);
+        assert forall|i: int|
+            k <= i < ptes.len() implies !candidate_mapping_overlaps_existing_pmem(
+            mappings_k,
+            #[trigger] ptes[i],
+        ) by {
+            assert(!overlap(ptes[i].frame, pte.frame));
+            assert(!candidate_mapping_overlaps_existing_pmem(prev, ptes[i]));
+        }
+    }
+}
+
+pub proof fn map_range_start_preserves_inv(
+    c: AbstractConstants,
+    s1: AbstractVariables,
+    s2: AbstractVariables,
+    thread_id: nat,
+    vaddr: nat,
+    ptes: Seq<PageTableEntry>,
+)
+    requires
+        step_MapRange_start(c, s1, s2, thread_id, vaddr, ptes),
+        s1.sound ==> inv(c, s1),
+        s1.sound,
+        s1.thread_state.dom().contains(thread_id),
+    ensures
+        s2.sound ==> inv(c, s2),
+{
+    if (s2.sound) {
+        lemma_mem_domain_from_mapping_finite(c.phys_mem_size, s2.mappings);
+        assert(forall|id: nat|
+            #![auto]
+            s2.mappings.dom().contains(id) ==> s1.mappings.index(id) == s2.mappings.index(id));
+        assert(s2.thread_state.values().subset_of(
+            s1.thread_state.values().insert(AbstractArguments::MapRange { vaddr, ptes }),
+        ));
+        assert forall|i: int| 0 <= i < ptes.len() implies above_zero(#[trigger] ptes[i].frame.size) by {
+            assert(step_Map_enabled(
+                s1.thread_state.values(),
+                s1.mappings,
+                range_entry_base(vaddr, ptes, i),
+                ptes[i],
+            ));
+        }
+        insert_map_range_preserves_unique(s1.thread_state, thread_id, vaddr, ptes);
+    } else {
+    }
+}
+
+pub proof fn map_range_end_preserves_inv(
+    c: AbstractConstants,
+    s1: AbstractVariables,
+    s2: AbstractVariables,
+    thread_id: nat,
+    result: Result<(), ()>,
+)
+    requires
+        step_MapRange_end(c, s1, s2, thread_id, result),
+        s1.sound ==> inv(c, s1),
+        s1.sound,
+        s1.thread_state.dom().contains(thread_id),
+    ensures
+        s2.sound ==> inv(c, s2),
+{
+    if let AbstractArguments::MapRange { vaddr, ptes } = s1.thread_state.index(thread_id) {
+        let n = map_range_prefix_len(s1.mappings, vaddr, ptes);
+        let arg = AbstractArguments::MapRange { vaddr, ptes };
+        assert(s1.thread_state.values().contains(arg));
+        lemma_map_range_prefix_len_bound(s1.mappings, vaddr, ptes);
+        lemma_range_mappings_union_no_overlap_pmem(s1.mappings, vaddr, ptes, n);
+        lemma_mem_domain_from_mapping_finite(c.phys_mem_size, s2.mappings);
+        assert(s2.thread_state.values().subset_of(
+            s1.thread_state.values().insert(AbstractArguments::Empty),
+        ));
+        insert_non_map_preserves_unique(s1.thread_state, thread_id, AbstractArguments::Empty);
+        assert(s2.thread_state == s1.thread_state.remove(thread_id).insert(
+            thread_id,
+            AbstractArguments::Empty,
+        ));
+    } else {
+    }
+}
+
+pub proof fn unmap_range_start_preserves_inv(
+    c: AbstractConstants,
+    s1: AbstractVariables,
+    s2: AbstractVariables,
+    thread_id: nat,
+    vaddr: nat,
+    len: nat,
+)
+    requires
+        step_UnmapRange_start(c, s1, s2, thread_id, vaddr, len),
+        s1.sound ==> inv(c, s1),
+        s1.sound,
+        s1.thread_state.dom().contains(thread_id),
+    ensures
+        s2.sound ==> inv(c, s2),
+{
+    if (s2.sound) {
+        assert(s2.mappings.dom().subset_of(s1.mappings.dom()));
+        lemma_subset_is_finite(s1.mappings.dom(), s2.mappings.dom());
+        lemma_mem_domain_from_mapping_finite(c.phys_mem_size, s2.mappings);
+        assert(forall|id: nat|
+            #![auto]
+            s2.mappings.dom().contains(id) ==> s1.mappings.index(id) == s2.mappings.index(id));
+        let arg = s2.thread_state.index(thread_id);
+        assert(s2.thread_state.values().subset_of(s1.thread_state.values().insert(arg)));
+        insert_non_map_preserves_unique(s1.thread_state, thread_id, arg);
+    } else {
+    }
+}
+
+pub proof fn protect_start_preserves_inv(
+    c: AbstractConstants,
+    s1: AbstractVariables,
+    s2: AbstractVariables,
+    thread_id: nat,
+    vaddr: nat,
+    flags: Flags,
+)
+    requires
+        step_Protect_start(c, s1, s2, thread_id, vaddr, flags),
+        s1.sound ==> inv(c, s1),
+        s1.sound,
+        s1.thread_state.dom().contains(thread_id),
+    ensures
+        s2.sound ==> inv(c, s2),
+{
+    if (s2.sound) {
+        assert(s2.mappings.dom() =~= s1.mappings.dom());
+        assert(forall|id: nat|
+            #![auto]
+            s2.mappings.dom().contains(id) ==> s1.mappings.index(id).frame == s2.mappings.index(id).frame);
+        let arg = s2.thread_state.index(thread_id);
+        assert(s2.thread_state.values().subset_of(s1.thread_state.values().insert(arg)));
+        insert_non_map_preserves_unique(s1.thread_state, thread_id, arg);
+    } else {
+    }
+}
+
 } // verus!
@@ -5,15 +5,16 @@
 use crate::definitions_t::{
     above_zero, aligned, between, candidate_mapping_in_bounds,
     candidate_mapping_overlaps_existing_pmem, candidate_mapping_overlaps_existing_vmem, overlap,
-    x86_arch_spec, MemRegion, PageTableEntry, RWOp, L1_ENTRY_SIZE, L2_ENTRY_SIZE, L3_ENTRY_SIZE,
-    MAX_PHYADDR, WORD_SIZE,
+    range_entry_base, range_size, x86_arch_spec, Flags, MemRegion, PageTableEntry, RWOp, L1_ENTRY_SIZE,
+    L2_ENTRY_SIZE, L3_ENTRY_SIZE, MAX_PHYADDR, WORD_SIZE,
 };
 use crate::spec_t::mem;
 use vstd::prelude::*;
 
 use crate::spec_t::hlproof::{
     insert_non_map_preserves_unique, lemma_mem_domain_from_mapping_finite, map_end_preserves_inv,
-    map_start_preserves_inv, unmap_start_preserves_inv,
+    map_range_end_preserves_inv, map_range_start_preserves_inv, map_start_preserves_inv,
+    protect_start_preserves_inv, unmap_range_start_preserves_inv, unmap_start_preserves_inv,
 };
 
 verus! {
@@ -42,6 +43,12 @@ pub enum AbstractStep {
     MapEnd { thread_id: nat, result: Result<(), ()> },
     UnmapStart { thread_id: nat, vaddr: nat },
     UnmapEnd { thread_id: nat, result: Result<(), ()> },
+    MapRangeStart { thread_id: nat, vaddr: nat, ptes: Seq<PageTableEntry> },
+    MapRangeEnd { thread_id: nat, result: Result<(), ()> },
+    UnmapRangeStart { thread_id: nat, vaddr: nat, len: nat },
+    UnmapRangeEnd { thread_id: nat, result: Result<(), ()> },
+    ProtectStart { thread_id: nat, vaddr: nat, flags: Flags },
+    ProtectEnd { thread_id: nat, result: Result<(), ()> },
     Stutter,
 }
 
@@ -50,6 +57,9 @@ pub enum AbstractStep {
 pub enum AbstractArguments {
     Map { vaddr: nat, pte: PageTableEntry },
     Unmap { vaddr: nat, pte: Option<PageTableEntry> },
+    MapRange { vaddr: nat, ptes: Seq<PageTableEntry> },
+    UnmapRange { vaddr: nat, len: nat, ptes: Option<Map<nat, PageTableEntry>> },
+    Protect { vaddr: nat, pte: Option<PageTableEntry>, flags: Flags },
     Empty,
 }
 
@@ -161,6 +171,34 @@ pub open spec fn candidate_mapping_overlaps_inflight_vmem(
                         MemRegion { base: base, size: candidate_size },
                     )
                 },
+                AbstractArguments::MapRange { vaddr, ptes } => {
+                    overlap(
+                        MemRegion { base: vaddr, size: range_size(ptes) },
+                        MemRegion { base: base, size: candidate_size },
+                    )
+                },
+                AbstractArguments::UnmapRange { vaddr, len, ptes } => {
+                    let size = if ptes.is_some() {
+                        len
+                    } else {
+                        0
+                    };
+                    overlap(
+                        MemRegion { base: vaddr, size: size },
+                        MemRegion { base: base, size: candidate_size },
+                    )
+                },
+                AbstractArguments::Protect { vaddr, pte, .. } => {
+                    let size = if pte.is_some() {
+                        pte.unwrap().frame.size
+                    } else {
+                        0
+                    };
+                    overlap(
+                        MemRegion { base: vaddr, size: size },
+                        MemRegion { base: base, size: candidate_size },
+                    )
+                },
                 _ => { false },
             }
         }
@@ -180,11 +218,34 @@ pub open spec fn candidate_mapping_overlaps_inflight_pmem(
                     &&& pte.is_some()
                     &&& overlap(candidate.frame, pte.unwrap().frame)
                 },
+                AbstractArguments::MapRange { vaddr, ptes } => {
+                    exists|i: int| 0 <= i < ptes.len() && overlap(candidate.frame, #[trigger] ptes[i].frame)
+                },
+                AbstractArguments::UnmapRange { vaddr, len, ptes } => {
+                    &&& ptes.is_some()
+                    &&& candidate_mapping_overlaps_existing_pmem(ptes.unwrap(), candidate)
+                },
                 _ => { false },
             }
         }
 }
 
+/// Some inflight protect changes the flags of the mapping at `base`, which was `pte` before
+pub open spec fn inflight_protect_of(
+    inflightargs: Set<AbstractArguments>,
+    base: nat,
+    pte: PageTableEntry,
+) -> bool {
+    exists|b: AbstractArguments|
+        #![auto]
+        {
+            &&& inflightargs.contains(b)
+            &&& b matches AbstractArguments::Protect { vaddr, pte: Some(old_pte), .. }
+            &&& vaddr == base
+            &&& old_pte === pte
+        }
+}
+
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 // MMU atomic ReadWrite
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -209,8 +270,10 @@ pub open spec fn step_ReadWrite(
         Some((base, pte)) => {
             let paddr = (pte.frame.base + (vaddr - base)) as nat;
             let pmem_idx = mem::word_index_spec(paddr);
-            // If pte is Some, it's an existing mapping that contains vaddr..
-            &&& s1.mappings.contains_pair(base, pte)
+            // If pte is Some, it's an existing mapping that contains vaddr. While a protect on
+            // it is inflight, TLBs may still hold the old flags, so those are allowed too..
+            &&& s1.mappings.contains_pair(base, pte) || (s1.mappings.dom().contains(base)
+                && inflight_protect_of(s1.thread_state.values(), base, pte))
             &&& between(
                 vaddr,
                 base,
@@ -429,6 +492,312 @@ pub open spec fn step_Unmap_end(
     }
 }
 
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Map range
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The mappings that result from mapping `ptes` back to back starting at `vaddr`
+pub open spec fn range_mappings(vaddr: nat, ptes: Seq<PageTableEntry>) -> Map<nat, PageTableEntry>
+    decreases ptes.len(),
+{
+    if ptes.len() == 0 {
+        Map::empty()
+    } else {
+        range_mappings(vaddr, ptes.drop_last()).insert(
+            vaddr + range_size(ptes.drop_last()),
+            ptes.last(),
+        )
+    }
+}
+
+/// The number of leading entries of `ptes` that get mapped. Mapping the range stops at the first
+/// entry that overlaps an existing mapping.
+pub open spec fn map_range_prefix_len(
+    mappings: Map<nat, PageTableEntry>,
+    vaddr: nat,
+    ptes: Seq<PageTableEntry>,
+) -> nat
+    decreases ptes.len(),
+{
+    if ptes.len() == 0 {
+        0
+    } else {
+        let n = map_range_prefix_len(mappings, vaddr, ptes.drop_last());
+        if n == ptes.len() - 1 && !candidate_mapping_overlaps_existing_vmem(
+            mappings,
+            vaddr + range_size(ptes.drop_last()),
+            ptes.last(),
+        ) {
+            ptes.len()
+        } else {
+            n
+        }
+    }
+}
+
+pub open spec fn step_MapRange_sound(
+    mappings: Map<nat, PageTableEntry>,
+    inflights: Set<AbstractArguments>,
+    vaddr: nat,
+    ptes: Seq<PageTableEntry>,
+) -> bool {
+    &&& forall|i: int|
+        0 <= i < ptes.len() ==> step_Map_sound(
+            mappings,
+            inflights,
+            #[trigger] range_entry_base(vaddr, ptes, i),
+            ptes[i],
+        )
+    // The frames of the range must not overlap each other either
+    &&& forall|i: int, j: int|
+        0 <= i < ptes.len() && 0 <= j < ptes.len() && i != j ==> !overlap(
+            #[trigger] ptes[i].frame,
+            #[trigger] ptes[j].frame,
+        )
+}
+
+pub open spec fn step_MapRange_enabled(
+    inflight: Set<AbstractArguments>,
+    map: Map<nat, PageTableEntry>,
+    vaddr: nat,
+    ptes: Seq<PageTableEntry>,
+) -> bool {
+    &&& 0 < ptes.len()
+    &&& forall|i: int|
+        0 <= i < ptes.len() ==> step_Map_enabled(
+            inflight,
+            map,
+            #[trigger] range_entry_base(vaddr, ptes, i),
+            ptes[i],
+        )
+}
+
+pub open spec fn step_MapRange_start(
+    c: AbstractConstants,
+    s1: AbstractVariables,
+    s2: AbstractVariables,
+    thread_id: nat,
+    vaddr: nat,
+    ptes: Seq<PageTableEntry>,
+) -> bool {
+    &&& step_MapRange_enabled(s1.thread_state.values(), s1.mappings, vaddr, ptes)
+    &&& valid_thread(c, thread_id)
+    &&& s1.thread_state[thread_id] === AbstractArguments::Empty
+    &&& if step_MapRange_sound(s1.mappings, s1.thread_state.values(), vaddr, ptes) {
+        state_unchanged_besides_thread_state(
+            s1,
+            s2,
+            thread_id,
+            AbstractArguments::MapRange { vaddr, ptes },
+        )
+    } else {
+        unsound_state(s1, s2)
+    }
+}
+
+//mapping a range is not atomic: the entries before the first one that fails remain mapped
+pub open spec fn step_MapRange_end(
+    c: AbstractConstants,
+    s1: AbstractVariables,
+    s2: AbstractVariables,
+    thread_id: nat,
+    result: Result<(), ()>,
+) -> bool {
+    &&& s2.sound == s1.sound
+    &&& valid_thread(c, thread_id)
+    &&& s2.thread_state === s1.thread_state.insert(thread_id, AbstractArguments::Empty)
+    &&& match s1.thread_state[thread_id] {
+        AbstractArguments::MapRange { vaddr, ptes } => {
+            let n = map_range_prefix_len(s1.mappings, vaddr, ptes);
+            &&& if n == ptes.len() {
+                result is Ok
+            } else {
+                result is Err
+            }
+            &&& s2.mappings === s1.mappings.union_prefer_right(
+                range_mappings(vaddr, ptes.take(n as int)),
+            )
+            &&& (forall|idx: nat|
+                #![auto]
+                s1.mem.dom().contains(idx) ==> s2.mem[idx] === s1.mem[idx])
+            &&& s2.mem.dom() === mem_domain_from_mappings(c.phys_mem_size, s2.mappings)
+        },
+        _ => { false },
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Unmap range
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The mappings whose base lies in `[vaddr, vaddr + len)`
+pub open spec fn mappings_in_range(
+    mappings: Map<nat, PageTableEntry>,
+    vaddr: nat,
+    len: nat,
+) -> Map<nat, PageTableEntry> {
+    mappings.restrict(Set::new(|b: nat| between(b, vaddr, vaddr + len)))
+}
+
+/// Some mapping overlaps `[vaddr, vaddr + len)` without being contained in it
+pub open spec fn mapping_straddles_range(
+    mappings: Map<nat, PageTableEntry>,
+    vaddr: nat,
+    len: nat,
+) -> bool {
+    exists|b: nat|
+        #![auto]
+        {
+            &&& mappings.dom().contains(b)
+            &&& overlap(
+                MemRegion { base: b, size: mappings[b].frame.size },
+                MemRegion { base: vaddr, size: len },
+            )
+            &&& !(vaddr <= b && b + mappings[b].frame.size <= vaddr + len)
+        }
+}
+
+pub open spec fn step_UnmapRange_enabled(vaddr: nat, len: nat) -> bool {
+    &&& 0 < len
+    &&& vaddr + len <= x86_arch_spec.upper_vaddr(0, 0)
+    &&& aligned(vaddr, L3_ENTRY_SIZE as nat)
+    &&& aligned(len, L3_ENTRY_SIZE as nat)
+}
+
+//unmapping a range is atomic: if a mapping straddles the range nothing is unmapped
+pub open spec fn step_UnmapRange_start(
+    c: AbstractConstants,
+    s1: AbstractVariables,
+    s2: AbstractVariables,
+    thread_id: nat,
+    vaddr: nat,
+    len: nat,
+) -> bool {
+    let ptes = if mapping_straddles_range(s1.mappings, vaddr, len) {
+        Option::None
+    } else {
+        Some(mappings_in_range(s1.mappings, vaddr, len))
+    };
+    &&& step_UnmapRange_enabled(vaddr, len)
+    &&& valid_thread(c, thread_id)
+    &&& s1.thread_state[thread_id] === AbstractArguments::Empty
+    &&& if step_Unmap_sound(s1.thread_state.values(), vaddr, len) {
+        &&& s2.thread_state === s1.thread_state.insert(
+            thread_id,
+            AbstractArguments::UnmapRange { vaddr, len, ptes },
+        )
+        &&& if (ptes is None) {
+            &&& s2.mappings === s1.mappings
+            &&& s2.mem === s1.mem
+        } else {
+            &&& s2.mappings === s1.mappings.remove_keys(ptes.unwrap().dom())
+            &&& s2.mem.dom() === mem_domain_from_mappings(c.phys_mem_size, s2.mappings)
+            &&& (forall|idx: nat|
+                #![auto]
+                s2.mem.dom().contains(idx) ==> s2.mem[idx] === s1.mem[idx])
+        }
+        &&& s2.sound == s1.sound
+    } else {
+        unsound_state(s1, s2)
+    }
+}
+
+pub open spec fn step_UnmapRange_end(
+    c: AbstractConstants,
+    s1: AbstractVariables,
+    s2: AbstractVariables,
+    thread_id: nat,
+    result: Result<(), ()>,
+) -> bool {
+    &&& valid_thread(c, thread_id)
+    &&& s2.thread_state === s1.thread_state.insert(thread_id, AbstractArguments::Empty)
+    &&& s2.sound == s1.sound
+    &&& s2.mappings === s1.mappings
+    &&& s2.mem === s1.mem
+    &&& match s1.thread_state[thread_id] {
+        AbstractArguments::UnmapRange { vaddr, len, ptes } => {
+            &&& if ptes is Some {
+                result is Ok
+            } else {
+                result is Err
+            }
+        },
+        _ => { false },
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Protect
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+pub open spec fn step_Protect_enabled(vaddr: nat) -> bool {
+    step_Unmap_enabled(vaddr)
+}
+
+//the new flags take effect immediately, the old ones stay observable until the end step
+pub open spec fn step_Protect_start(
+    c: AbstractConstants,
+    s1: AbstractVariables,
+    s2: AbstractVariables,
+    thread_id: nat,
+    vaddr: nat,
+    flags: Flags,
+) -> bool {
+    let pte = if (s1.mappings.dom().contains(vaddr)) {
+        Some(s1.mappings.index(vaddr))
+    } else {
+        Option::None
+    };
+    let pte_size = if (pte is Some) {
+        pte.unwrap().frame.size
+    } else {
+        0
+    };
+    &&& step_Protect_enabled(vaddr)
+    &&& valid_thread(c, thread_id)
+    &&& s1.thread_state[thread_id] === AbstractArguments::Empty
+    &&& if step_Unmap_sound(s1.thread_state.values(), vaddr, pte_size) {
+        &&& s2.thread_state === s1.thread_state.insert(
+            thread_id,
+            AbstractArguments::Protect { vaddr, pte, flags },
+        )
+        &&& if (pte is None) {
+            &&& s2.mappings === s1.mappings
+        } else {
+            &&& s2.mappings === s1.mappings.insert(
+                vaddr,
+                PageTableEntry { frame: pte.unwrap().frame, flags },
+            )
+        }
+        // The frames don't change, hence neither does the memory
+        &&& s2.mem === s1.mem
+        &&& s2.sound == s1.sound
+    } else {
+        unsound_state(s1, s2)
+    }
+}
+
+pub open spec fn step_Protect_end(
+    c: AbstractConstants,
+    s1: AbstractVariables,
+    s2: AbstractVariables,
+    thread_id: nat,
+    result: Result<(), ()>,
+) -> bool {
+    &&& valid_thread(c, thread_id)
+    &&& s2.thread_state === s1.thread_state.insert(thread_id, AbstractArguments::Empty)
+    &&& s2.sound == s1.sound
+    &&& s2.mappings === s1.mappings
+    &&& s2.mem === s1.mem
+    &&& match s1.thread_state[thread_id] {
+        AbstractArguments::Protect { vaddr, pte, flags } => {
+            &&& if pte is Some {
+                result is Ok
+            } else {
+                result is Err
+            }
+        },
+        _ => { false },
+    }
+}
+
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 // Stutter
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -487,6 +856,51 @@ pub open spec fn next_step(
                 thread_id,
                 result,
             ),
+            AbstractStep::MapRangeStart { thread_id, vaddr, ptes } => step_MapRange_start(
+                c,
+                s1,
+                s2,
+                thread_id,
+                vaddr,
+                ptes,
+            ),
+            AbstractStep::MapRangeEnd { thread_id, result } => step_MapRange_end(
+                c,
+                s1,
+                s2,
+                thread_id,
+                result,
+            ),
+            AbstractStep::UnmapRangeStart { thread_id, vaddr, len } => step_UnmapRange_start(
+                c,
+                s1,
+                s2,
+                thread_id,
+                vaddr,
+                len,
+            ),
+            AbstractStep::UnmapRangeEnd { thread_id, result } => step_UnmapRange_end(
+                c,
+                s1,
+                s2,
+                thread_id,
+                result,
+            ),
+            AbstractStep::ProtectStart { thread_id, vaddr, flags } => step_Protect_start(
+                c,
+                s1,
+                s2,
+                thread_id,
+                vaddr,
+                flags,
+            ),
+            AbstractStep::ProtectEnd { thread_id, result } => step_Protect_end(
+                c,
+                s1,
+                s2,
+                thread_id,
+                result,
+            ),
             AbstractStep::Stutter => step_Stutter(c, s1, s2),
         }
     } else {
@@ -517,6 +931,13 @@ pub open spec fn inflight_map_no_overlap_pmem(
                 AbstractArguments::Map { vaddr, pte } => {
                     !candidate_mapping_overlaps_existing_pmem(mappings, pte)
                 },
+                AbstractArguments::MapRange { vaddr, ptes } => {
+                    forall|i: int|
+                        0 <= i < ptes.len() ==> !candidate_mapping_overlaps_existing_pmem(
+                            mappings,
+                            #[trigger] ptes[i],
+                        )
+                },
                 _ => { true },
             }
         }
@@ -532,6 +953,18 @@ pub open spec fn inflight_map_no_overlap_inflight_pmem(
                 AbstractArguments::Map { vaddr, pte } => {
                     !candidate_mapping_overlaps_inflight_pmem(inflightargs.remove(b), pte)
                 },
+                AbstractArguments::MapRange { vaddr, ptes } => {
+                    &&& forall|i: int|
+                        0 <= i < ptes.len() ==> !candidate_mapping_overlaps_inflight_pmem(
+                            inflightargs.remove(b),
+                            #[trigger] ptes[i],
+                        )
+                    &&& forall|i: int, j: int|
+                        0 <= i < ptes.len() && 0 <= j < ptes.len() && i != j ==> !overlap(
+                            #[trigger] ptes[i].frame,
+                            #[trigger] ptes[j].frame,
+                        )
+                },
                 _ => { true },
             }
         }
@@ -549,6 +982,9 @@ pub open spec fn inflight_mem_size_over_zero(inflightargs: Set<AbstractArguments
         {
             inflightargs.contains(b) ==> match b {
                 AbstractArguments::Map { vaddr, pte } => { above_zero(pte.frame.size) },
+                AbstractArguments::MapRange { vaddr, ptes } => {
+                    forall|i: int| 0 <= i < ptes.len() ==> above_zero(#[trigger] ptes[i].frame.size)
+                },
                 _ => { true },
             }
         }
@@ -558,7 +994,7 @@ pub open spec fn if_map_then_unique(thread_state: Map<nat, AbstractArguments>, i
     recommends
         thread_state.dom().contains(id),
 {
-    if let AbstractArguments::Map { vaddr, pte } = thread_state.index(id) {
+    if thread_state.index(id) is Map || thread_state.index(id) is MapRange {
         !thread_state.remove(id).values().contains(thread_state.index(id))
     } else {
         true
@@ -624,6 +1060,40 @@ pub proof fn next_step_preserves_inv(
             AbstractStep::MapEnd { thread_id, result } => {
                 map_end_preserves_inv(c, s1, s2, thread_id, result);
             },
+            AbstractStep::UnmapRangeStart { thread_id, vaddr, len } => {
+                unmap_range_start_preserves_inv(c, s1, s2, thread_id, vaddr, len);
+            },
+            AbstractStep::UnmapRangeEnd { thread_id, result } => {
+                assert(s2.thread_state.values().subset_of(
+                    s1.thread_state.values().insert(AbstractArguments::Empty),
+                ));
+                lemma_mem_domain_from_mapping_finite(c.phys_mem_size, s2.mappings);
+                insert_non_map_preserves_unique(
+                    s1.thread_state,
+                    thread_id,
+                    AbstractArguments::Empty,
+                );
+            },
+            AbstractStep::MapRangeStart { thread_id, vaddr, ptes } => {
+                map_range_start_preserves_inv(c, s1, s2, thread_id, vaddr, ptes);
+            },
+            AbstractStep::MapRangeEnd { thread_id, result } => {
+                map_range_end_preserves_inv(c, s1, s2, thread_id, result);
+            },
+            AbstractStep::ProtectStart { thread_id, vaddr, flags } => {
+                protect_start_preserves_inv(c, s1, s2, thread_id, vaddr, flags);
+            },
+            AbstractStep::ProtectEnd { thread_id, result } => {
+                assert(s2.thread_state.values().subset_of(
+                    s1.thread_state.values().insert(AbstractArguments::Empty),
+                ));
+                lemma_mem_domain_from_mapping_finite(c.phys_mem_size, s2.mappings);
+                insert_non_map_preserves_unique(
+                    s1.thread_state,
+                    thread_id,
+                    AbstractArguments::Empty,
+                );
+            },
             _ => {},
         }
     } else {
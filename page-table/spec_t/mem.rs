@@ -5,12 +5,14 @@
 // trusted-ness of this file, but not in a quantifiable fashion; for this reason we deem
 // it appropriate to exclude it from P:C accounting
 
+use core::sync::atomic::{AtomicU64, Ordering};
 use vstd::prelude::*;
 
 use crate::definitions_t::{
     aligned, new_seq, overlap, MemRegion, MemRegionExec, MAX_PHYADDR, PAGE_SIZE,
-    WORD_SIZE,
+    WORD_SIZE, X86_NUM_LAYERS, X86_LA57_NUM_LAYERS,
 };
+use crate::spec_t::hardware::{MASK_FLAG_A, MASK_PG_FLAG_D};
 
 verus! {
 
@@ -36,13 +38,28 @@ pub open spec fn word_index_spec(addr: nat) -> nat
     addr / (WORD_SIZE as nat)
 }
 
-// FIXME: We need to allow the dirty and accessed bits to change in the memory.
-// Or maybe we just specify reads to return those bits as arbitrary?
+/// The accessed and dirty flags of an entry. The MMU sets them concurrently to the implementation
+/// (see `hardware::pt_mem_ad_bits_updated`), so they're kept apart from the rest of the entry:
+/// `region_view` holds the entries with these bits cleared and `ad_view` holds the bits. `read`
+/// masks them out and `write` only writes entries with both flags clear. The flags are only
+/// observed, and cleared, with the atomic `fetch_clear_ad`, so an update by the MMU can't be lost.
+pub const MASK_AD_FLAGS: u64 = MASK_FLAG_A | MASK_PG_FLAG_D;
+
+/// Upper bound (exclusive) on the number of pages of a `PageTableMemory` created with `new`. All
+/// physical addresses handed out by `alloc_page` are then below 4 GiB and thus below
+/// `MAX_PHYADDR`.
+pub const MAX_NUM_PAGES: usize = 0x10_0000;
+
 #[verifier(external_body)]
 pub struct PageTableMemory {
     /// `phys_mem_ref` is the starting address of the physical memory linear mapping
     phys_mem_ref: *mut u64,
     cr3: u64,
+    /// Physical addresses of the pages `alloc_page` hands out next
+    free_pages: std::vec::Vec<u64>,
+    /// Number of pages of the memory `phys_mem_ref` points to
+    num_pages: usize,
+    num_layers: usize,
 }
 
 impl PageTableMemory {
@@ -52,6 +69,10 @@ impl PageTableMemory {
 
     pub spec fn region_view(self, r: MemRegion) -> Seq<u64>;
 
+    /// The accessed and dirty flags of the entries in `r`, as far as the implementation knows.
+    /// The MMU may have set more of them since.
+    pub spec fn ad_view(self, r: MemRegion) -> Seq<u64>;
+
     pub open spec fn inv(self) -> bool {
         &&& self.phys_mem_ref_as_usize_spec() <= 0x7FE0_0000_0000_0000
         &&& forall|s1: MemRegion, s2: MemRegion|
@@ -61,6 +82,7 @@ impl PageTableMemory {
             )
         &&& aligned(self.cr3_spec().base as nat, PAGE_SIZE as nat)
         &&& self.cr3_spec().size == PAGE_SIZE
+        &&& self.num_layers_spec() == X86_NUM_LAYERS || self.num_layers_spec() == X86_LA57_NUM_LAYERS
     }
 
     pub open spec fn init(self) -> bool {
@@ -78,8 +100,61 @@ impl PageTableMemory {
 
     pub open spec fn cr3_spec(&self) -> MemRegionExec;
 
-    // We assume that alloc_page never fails. In practice we can just keep a buffer of 3+ pages
-    // that are allocated before we use map_frame.
+    /// The number of paging layers the MMU walks: 4, or 5 with 5-level paging (LA57). It's chosen
+    /// when the memory is created and never changes.
+    #[verifier(external_body)]
+    pub fn num_layers(&self) -> (res: usize)
+        ensures
+            res == self.num_layers_spec(),
+    {
+        self.num_layers
+    }
+
+    pub open spec fn num_layers_spec(&self) -> nat;
+
+    /// Creates a new page table memory backed by `num_pages` zeroed pages allocated on the heap.
+    /// Physical addresses are offsets into that allocation. The only region is the layer 0
+    /// directory at `cr3`, the remaining pages are available to `alloc_page`. The MMU walks
+    /// `num_layers` layers.
+    #[verifier(external_body)]
+    pub fn new(num_pages: usize, num_layers: usize) -> (res: Self)
+        requires
+            0 < num_pages < MAX_NUM_PAGES,
+            num_layers == X86_NUM_LAYERS || num_layers == X86_LA57_NUM_LAYERS,
+        ensures
+            res.inv(),
+            res.num_layers_spec() == num_layers,
+            res.regions() === set![res.cr3_spec()@],
+            res.region_view(res.cr3_spec()@) === new_seq::<u64>(512nat, 0u64),
+            res.ad_view(res.cr3_spec()@) === new_seq::<u64>(512nat, 0u64),
+            res.alloc_available_pages() == num_pages - 1,
+    {
+        let layout = arena_layout(num_pages);
+        let phys_mem_ref = unsafe { std::alloc::alloc_zeroed(layout) } as *mut u64;
+        if phys_mem_ref.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        assert!(phys_mem_ref as usize <= 0x7FE0_0000_0000_0000);
+        // The first page holds the layer 0 directory, the others are handed out from the lowest
+        // address up.
+        let mut free_pages = std::vec::Vec::with_capacity(num_pages - 1);
+        let mut page = num_pages - 1;
+        while page > 0 {
+            free_pages.push((page * PAGE_SIZE) as u64);
+            page = page - 1;
+        }
+        PageTableMemory { phys_mem_ref, cr3: 0, free_pages, num_pages, num_layers }
+    }
+
+    /// The number of pages `alloc_page` can still hand out
+    #[verifier(external_body)]
+    pub fn available_pages(&self) -> (res: usize)
+        ensures
+            res == self.alloc_available_pages(),
+    {
+        self.free_pages.len()
+    }
+
     /// Allocates one page and returns its physical address
     #[verifier(external_body)]
     pub fn alloc_page(&mut self) -> (r: MemRegionExec)
@@ -94,13 +169,22 @@ impl PageTableMemory {
             !old(self).regions().contains(r@),
             self.regions() === old(self).regions().insert(r@),
             self.region_view(r@) === new_seq::<u64>(512nat, 0u64),
+            self.ad_view(r@) === new_seq::<u64>(512nat, 0u64),
             forall|r2: MemRegion|
                 r2 !== r@ ==> #[trigger] self.region_view(r2) === old(self).region_view(r2),
+            forall|r2: MemRegion|
+                r2 !== r@ ==> #[trigger] self.ad_view(r2) === old(self).ad_view(r2),
             self.cr3_spec() == old(self).cr3_spec(),
+            self.num_layers_spec() == old(self).num_layers_spec(),
             self.phys_mem_ref_as_usize_spec() == old(self).phys_mem_ref_as_usize_spec(),
             self.inv(),
     {
-        unimplemented!()
+        let base = self.free_pages.pop().unwrap();
+        let word_offset: isize = word_index(base as usize) as isize;
+        unsafe {
+            self.phys_mem_ref.offset(word_offset).write_bytes(0, PAGE_SIZE / WORD_SIZE);
+        }
+        MemRegionExec { base: base as usize, size: PAGE_SIZE }
     }
 
     /// Deallocates a page
@@ -110,14 +194,18 @@ impl PageTableMemory {
             old(self).inv(),
             old(self).regions().contains(r@),
         ensures
+            self.alloc_available_pages() == old(self).alloc_available_pages() + 1,
             self.regions() === old(self).regions().remove(r@),
             forall|r2: MemRegion|
                 r2 !== r@ ==> #[trigger] self.region_view(r2) === old(self).region_view(r2),
+            forall|r2: MemRegion|
+                r2 !== r@ ==> #[trigger] self.ad_view(r2) === old(self).ad_view(r2),
             self.cr3_spec() == old(self).cr3_spec(),
+            self.num_layers_spec() == old(self).num_layers_spec(),
             self.phys_mem_ref_as_usize_spec() == old(self).phys_mem_ref_as_usize_spec(),
             self.inv(),
     {
-        unimplemented!()
+        self.free_pages.push(r.base as u64);
     }
 
     #[verifier(external_body)]
@@ -129,18 +217,50 @@ impl PageTableMemory {
             old(self).inv(),
             old(self).regions().contains(region@),
             idx < 512,
+            value & MASK_AD_FLAGS == 0,
         ensures
             self.region_view(region@) === old(self).region_view(region@).update(idx as int, value),
             forall|r: MemRegion| r !== region@ ==> self.region_view(r) === old(self).region_view(r),
+            self.ad_view(region@) === old(self).ad_view(region@).update(idx as int, 0),
+            forall|r: MemRegion| r !== region@ ==> self.ad_view(r) === old(self).ad_view(r),
             self.regions() === old(self).regions(),
             self.alloc_available_pages() == old(self).alloc_available_pages(),
             self.cr3_spec() == old(self).cr3_spec(),
+            self.num_layers_spec() == old(self).num_layers_spec(),
             self.phys_mem_ref_as_usize_spec() == old(self).phys_mem_ref_as_usize_spec(),
     {
         let word_offset: isize = (word_index(pbase) + idx) as isize;
-        unsafe {
-            self.phys_mem_ref.offset(word_offset).write(value);
-        }
+        unsafe { AtomicU64::from_ptr(self.phys_mem_ref.offset(word_offset)) }
+            .store(value, Ordering::SeqCst);
+    }
+
+    #[verifier(external_body)]
+    /// Write value to physical address `pbase + idx * WORD_SIZE` but keep the accessed and dirty
+    /// flags of the entry that's there. The update is atomic, so flags the MMU sets concurrently
+    /// aren't lost.
+    pub fn write_keep_ad(&mut self, pbase: usize, idx: usize, region: Ghost<MemRegion>, value: u64)
+        requires
+            pbase == region@.base,
+            aligned(pbase as nat, WORD_SIZE as nat),
+            old(self).inv(),
+            old(self).regions().contains(region@),
+            idx < 512,
+            value & MASK_AD_FLAGS == 0,
+        ensures
+            self.region_view(region@) === old(self).region_view(region@).update(idx as int, value),
+            forall|r: MemRegion| r !== region@ ==> self.region_view(r) === old(self).region_view(r),
+            forall|r: MemRegion| self.ad_view(r) === old(self).ad_view(r),
+            self.regions() === old(self).regions(),
+            self.alloc_available_pages() == old(self).alloc_available_pages(),
+            self.cr3_spec() == old(self).cr3_spec(),
+            self.num_layers_spec() == old(self).num_layers_spec(),
+            self.phys_mem_ref_as_usize_spec() == old(self).phys_mem_ref_as_usize_spec(),
+    {
+        let word_offset: isize = (word_index(pbase) + idx) as isize;
+        let _ = unsafe { AtomicU64::from_ptr(self.phys_mem_ref.offset(word_offset)) }
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |e| {
+                Some((e & MASK_AD_FLAGS) | value)
+            });
     }
 
     #[verifier(external_body)]
@@ -155,13 +275,60 @@ impl PageTableMemory {
             res == self.spec_read(idx as nat, region@),
     {
         let word_offset: isize = (word_index(pbase) + idx) as isize;
-        unsafe { self.phys_mem_ref.offset(word_offset).read() }
+        let value = unsafe { AtomicU64::from_ptr(self.phys_mem_ref.offset(word_offset)) }
+            .load(Ordering::SeqCst);
+        value & !MASK_AD_FLAGS
     }
 
     pub open spec fn spec_read(self, idx: nat, region: MemRegion) -> (res: u64) {
         self.region_view(region)[idx as int]
     }
 
+    pub open spec fn spec_read_ad(self, idx: nat, region: MemRegion) -> (res: u64) {
+        self.ad_view(region)[idx as int]
+    }
+
+    #[verifier(external_body)]
+    /// Atomically clears the flags `bits` of the entry at physical address `pbase + idx * WORD_SIZE`
+    /// and returns the entry as it was before, including the accessed and dirty flags the MMU set.
+    pub fn fetch_clear_ad(
+        &mut self,
+        pbase: usize,
+        idx: usize,
+        region: Ghost<MemRegion>,
+        bits: u64,
+    ) -> (res: u64)
+        requires
+            pbase == region@.base,
+            aligned(pbase as nat, WORD_SIZE as nat),
+            old(self).inv(),
+            old(self).regions().contains(region@),
+            idx < 512,
+            bits & !MASK_AD_FLAGS == 0,
+        ensures
+            res & !MASK_AD_FLAGS == old(self).spec_read(idx as nat, region@),
+            // The flags the implementation knows of are still set
+            res & old(self).spec_read_ad(idx as nat, region@) == old(self).spec_read_ad(
+                idx as nat,
+                region@,
+            ),
+            self.ad_view(region@) === old(self).ad_view(region@).update(
+                idx as int,
+                res & MASK_AD_FLAGS & !bits,
+            ),
+            forall|r: MemRegion| r !== region@ ==> self.ad_view(r) === old(self).ad_view(r),
+            forall|r: MemRegion| self.region_view(r) === old(self).region_view(r),
+            self.regions() === old(self).regions(),
+            self.alloc_available_pages() == old(self).alloc_available_pages(),
+            self.cr3_spec() == old(self).cr3_spec(),
+            self.num_layers_spec() == old(self).num_layers_spec(),
+            self.phys_mem_ref_as_usize_spec() == old(self).phys_mem_ref_as_usize_spec(),
+    {
+        let word_offset: isize = (word_index(pbase) + idx) as isize;
+        unsafe { AtomicU64::from_ptr(self.phys_mem_ref.offset(word_offset)) }
+            .fetch_and(!bits, Ordering::SeqCst)
+    }
+
     /// This function manually does the address computation which `read` and `write` rely on not
     /// overflowing. Since this function is not `external_body`, Verus checks that there's no
     /// overflow. The preconditions are those of `read`, which are a subset of the `write`
@@ -226,3 +393,20 @@ impl PageTableMemory {
 }
 
 } // verus!
+
+fn arena_layout(num_pages: usize) -> std::alloc::Layout {
+    std::alloc::Layout::from_size_align(num_pages * PAGE_SIZE, PAGE_SIZE).unwrap()
+}
+
+impl Drop for PageTableMemory {
+    fn drop(&mut self) {
+        unsafe { std::alloc::dealloc(self.phys_mem_ref as *mut u8, arena_layout(self.num_pages)) }
+    }
+}
+
+// SAFETY: The memory `phys_mem_ref` points to is owned by the `PageTableMemory`: it is allocated
+// in `new` and freed when the `PageTableMemory` is dropped. It is only written through `&mut self`
+// and read through `&self`, so the usual borrowing rules make it safe to move the memory to and
+// share it between threads.
+unsafe impl Send for PageTableMemory {}
+unsafe impl Sync for PageTableMemory {}
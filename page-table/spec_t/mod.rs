@@ -1,4 +1,6 @@
 pub mod hardware;
+pub mod hardware_aarch64;
+pub mod entry_format;
 pub mod hlspec;
 pub mod os;
 //#[cfg(feature = "impl")]
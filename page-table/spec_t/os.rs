@@ -13,7 +13,7 @@ use crate::spec_t::{hardware, hlspec, mem};
 use crate::definitions_t::{
     above_zero, aligned, between, candidate_mapping_in_bounds,
     candidate_mapping_overlaps_existing_pmem, candidate_mapping_overlaps_existing_vmem, overlap,
-    x86_arch_spec, HWLoadResult, HWRWOp, HWStoreResult, LoadResult, MemRegion, PageTableEntry,
+    x86_arch_spec, Flags, HWLoadResult, HWRWOp, HWStoreResult, LoadResult, MemRegion, PageTableEntry,
     RWOp, StoreResult, L1_ENTRY_SIZE, L2_ENTRY_SIZE, L3_ENTRY_SIZE, MAX_PHYADDR, WORD_SIZE,
 };
 use crate::spec_t::hardware::Core;
@@ -42,6 +42,10 @@ pub struct OSVariables {
 pub struct ShootdownVector {
     pub vaddr: nat,
     pub open_requests: Set<Core>,
+    /// The shootdown follows a change of the flags of the mapping at `vaddr`, which still exists.
+    /// TLB entries that already hold the updated mapping may stay. After an unmap or after
+    /// clearing the dirty flag all TLB entries for `vaddr` have to be evicted.
+    pub keeps_mapping: bool,
 }
 
 #[allow(inconsistent_fields)]
@@ -57,6 +61,16 @@ pub enum CoreState {
         vaddr: nat,
         result: Result<PageTableEntry, ()>,
     },
+    ProtectWaiting { ULT_id: nat, vaddr: nat, flags: Flags },
+    ProtectOpDone { ULT_id: nat, vaddr: nat, flags: Flags, result: Result<PageTableEntry, ()> },
+    ProtectShootdownWaiting {
+        ULT_id: nat,
+        vaddr: nat,
+        flags: Flags,
+        result: Result<PageTableEntry, ()>,
+    },
+    ClearDirtyOpDone { ULT_id: nat, vaddr: nat, result: Result<bool, ()> },
+    ClearDirtyShootdownWaiting { ULT_id: nat, vaddr: nat, result: Result<bool, ()> },
 }
 
 impl CoreState {
@@ -64,13 +78,18 @@ impl CoreState {
         match self {
             CoreState::Idle
             | CoreState::MapWaiting { .. }
-            | CoreState::UnmapWaiting { .. } => false,
+            | CoreState::UnmapWaiting { .. }
+            | CoreState::ProtectWaiting { .. } => false,
             _ => true,
         }
     }
 
+    /// The core runs no operation that changes the mappings. Clearing the dirty flag leaves the
+    /// mappings unchanged and is invisible to the high-level spec.
     pub open spec fn is_idle(self) -> bool {
-        self is Idle
+        ||| self is Idle
+        ||| self is ClearDirtyOpDone
+        ||| self is ClearDirtyShootdownWaiting
     }
 
     pub open spec fn vmem_pte_size(self, pt: Map<nat, PageTableEntry>) -> nat
@@ -81,7 +100,8 @@ impl CoreState {
             CoreState::MapWaiting { pte, .. } | CoreState::MapExecuting { pte, .. } => {
                 pte.frame.size
             },
-            CoreState::UnmapWaiting { vaddr, .. } => {
+            CoreState::UnmapWaiting { vaddr, .. }
+            | CoreState::ProtectWaiting { vaddr, .. } => {
                 if pt.dom().contains(vaddr) {
                     pt.index(vaddr).frame.size
                 } else {
@@ -90,14 +110,18 @@ impl CoreState {
             },
             CoreState::UnmapOpExecuting { result, .. }
             | CoreState::UnmapOpDone { result, .. }
-            | CoreState::UnmapShootdownWaiting { result, .. } => {
+            | CoreState::UnmapShootdownWaiting { result, .. }
+            | CoreState::ProtectOpDone { result, .. }
+            | CoreState::ProtectShootdownWaiting { result, .. } => {
                 if result is Ok {
                     result.get_Ok_0().frame.size
                 } else {
                     0
                 }
             },
-            CoreState::Idle => arbitrary(),
+            CoreState::Idle
+            | CoreState::ClearDirtyOpDone { .. }
+            | CoreState::ClearDirtyShootdownWaiting { .. } => arbitrary(),
         }
     }
 
@@ -111,7 +135,12 @@ impl CoreState {
             | CoreState::UnmapWaiting { vaddr, .. }
             | CoreState::UnmapOpExecuting { vaddr, .. }
             | CoreState::UnmapOpDone { vaddr, .. }
-            | CoreState::UnmapShootdownWaiting { vaddr, .. } => { vaddr },
+            | CoreState::UnmapShootdownWaiting { vaddr, .. }
+            | CoreState::ProtectWaiting { vaddr, .. }
+            | CoreState::ProtectOpDone { vaddr, .. }
+            | CoreState::ProtectShootdownWaiting { vaddr, .. }
+            | CoreState::ClearDirtyOpDone { vaddr, .. }
+            | CoreState::ClearDirtyShootdownWaiting { vaddr, .. } => { vaddr },
             CoreState::Idle => arbitrary(),
         }
     }
@@ -152,7 +181,12 @@ impl OSVariables {
                 | CoreState::UnmapWaiting { ULT_id, .. }
                 | CoreState::UnmapOpExecuting { ULT_id, .. }
                 | CoreState::UnmapOpDone { ULT_id, .. }
-                | CoreState::UnmapShootdownWaiting { ULT_id, .. } => {
+                | CoreState::UnmapShootdownWaiting { ULT_id, .. }
+                | CoreState::ProtectWaiting { ULT_id, .. }
+                | CoreState::ProtectOpDone { ULT_id, .. }
+                | CoreState::ProtectShootdownWaiting { ULT_id, .. }
+                | CoreState::ClearDirtyOpDone { ULT_id, .. }
+                | CoreState::ClearDirtyShootdownWaiting { ULT_id, .. } => {
                     &&& c.valid_ULT(ULT_id)
                     &&& c.ULT2core[ULT_id] === core
                 },
@@ -237,15 +271,29 @@ impl OSVariables {
                 hardware::valid_core(c.hw, dispatcher) ==> match self.core_states[dispatcher] {
                     CoreState::UnmapShootdownWaiting { vaddr, .. } => {
                         forall|handler: Core|
-                            !(#[trigger] self.TLB_Shootdown.open_requests.contains(handler))
+                            hardware::valid_core(c.hw, handler)
+                                && !(#[trigger] self.TLB_Shootdown.open_requests.contains(handler))
                                 ==> !self.hw.NUMAs[handler.NUMA_id].cores[handler.core_id].tlb.dom().contains(
                             vaddr)
                     },
+                    CoreState::ProtectShootdownWaiting { vaddr, .. }
+                    | CoreState::ClearDirtyShootdownWaiting { vaddr, .. } => {
+                        forall|handler: Core|
+                            hardware::valid_core(c.hw, handler)
+                                && !(#[trigger] self.TLB_Shootdown.open_requests.contains(handler))
+                                ==> self.tlb_entry_is_current(handler, vaddr)
+                    },
                     _ => true,
                 }
             }
     }
 
+    /// The TLB of `core` holds no entry for `vaddr`, or the same one as the page table
+    pub open spec fn tlb_entry_is_current(self, core: Core, vaddr: nat) -> bool {
+        let tlb = self.hw.NUMAs[core.NUMA_id].cores[core.core_id].tlb;
+        tlb.dom().contains(vaddr) ==> self.interp_pt_mem().contains_pair(vaddr, tlb[vaddr])
+    }
+
     //returns set with the vaddr that is currently unmapped.
     pub open spec fn Unmap_vaddr(self) -> Set<nat> {
         Set::new(
@@ -275,8 +323,10 @@ impl OSVariables {
 
     pub open spec fn shootdown_exists(self, c: OSConstants) -> bool {
         !(self.TLB_Shootdown.open_requests === Set::<Core>::empty()) ==> exists|core|
-            hardware::valid_core(c.hw, core)
-                && self.core_states[core] matches (CoreState::UnmapShootdownWaiting { vaddr, .. })
+            hardware::valid_core(c.hw, core) && (
+                self.core_states[core] is UnmapShootdownWaiting
+                || self.core_states[core] is ProtectShootdownWaiting
+                || self.core_states[core] is ClearDirtyShootdownWaiting)
     }
 
     pub open spec fn tlb_inv(self, c: OSConstants) -> bool {
@@ -363,13 +413,34 @@ impl OSVariables {
         )
     }
 
+    /// Returns the new flags if a protect of `v_address` is waiting to be executed.
+    pub open spec fn waiting_protect_flags(self, v_address: nat) -> Option<Flags> {
+        if exists|core: Core|
+            self.core_states.dom().contains(core)
+                && #[trigger] self.core_states[core] matches CoreState::ProtectWaiting { vaddr, .. }
+                && vaddr == v_address {
+            let core = choose|core: Core|
+                self.core_states.dom().contains(core)
+                    && #[trigger] self.core_states[core] matches CoreState::ProtectWaiting { vaddr, .. }
+                    && vaddr == v_address;
+            Some(self.core_states[core]->ProtectWaiting_flags)
+        } else {
+            None
+        }
+    }
+
     pub open spec fn effective_mappings(self) -> Map<nat, PageTableEntry> {
         let effective_mappings = self.interp_pt_mem();
         let unmap_dom = self.inflight_unmap_vaddr();
         Map::new(
             |vmem_idx: nat|
                 effective_mappings.dom().contains(vmem_idx) && !unmap_dom.contains(vmem_idx),
-            |vmem_idx: nat| effective_mappings[vmem_idx],
+            |vmem_idx: nat|
+                // A waiting protect has already taken effect at the high level
+                match self.waiting_protect_flags(vmem_idx) {
+                    Some(flags) => PageTableEntry { frame: effective_mappings[vmem_idx].frame, flags },
+                    None => effective_mappings[vmem_idx],
+                },
         )
     }
 
@@ -439,7 +510,34 @@ impl OSVariables {
                                 hlspec::AbstractArguments::Empty
                             }
                         },
-                        CoreState::Idle => hlspec::AbstractArguments::Empty,
+                        CoreState::ProtectWaiting { ULT_id, vaddr, flags } => {
+                            let pte = if self.interp_pt_mem().dom().contains(vaddr) {
+                                Some(self.interp_pt_mem().index(vaddr))
+                            } else {
+                                None
+                            };
+                            if ULT_id == ult_id {
+                                hlspec::AbstractArguments::Protect { vaddr, pte, flags }
+                            } else {
+                                hlspec::AbstractArguments::Empty
+                            }
+                        },
+                        CoreState::ProtectOpDone { ULT_id, vaddr, flags, result }
+                        | CoreState::ProtectShootdownWaiting { ULT_id, vaddr, flags, result } => {
+                            if ULT_id == ult_id {
+                                hlspec::AbstractArguments::Protect { vaddr, flags, pte:
+                                    match result {
+                                        Ok(pte) => Some(pte),
+                                        Err(_) => None,
+                                    }
+                                }
+                            } else {
+                                hlspec::AbstractArguments::Empty
+                            }
+                        },
+                        CoreState::Idle
+                        | CoreState::ClearDirtyOpDone { .. }
+                        | CoreState::ClearDirtyShootdownWaiting { .. } => hlspec::AbstractArguments::Empty,
                     }
                 },
         )
@@ -481,7 +579,13 @@ pub open spec fn candidate_mapping_overlaps_inflight_pmem(
                     &&& result is Ok
                     &&& overlap(candidate.frame, result.get_Ok_0().frame)
                 },
-                CoreState::Idle => false,
+                // Protect keeps the frame mapped, so the existing mappings already cover it
+                CoreState::ProtectWaiting { .. }
+                | CoreState::ProtectOpDone { .. }
+                | CoreState::ProtectShootdownWaiting { .. } => false,
+                CoreState::Idle
+                | CoreState::ClearDirtyOpDone { .. }
+                | CoreState::ClearDirtyShootdownWaiting { .. } => false,
             }
         }
 }
@@ -504,7 +608,8 @@ pub open spec fn candidate_mapping_overlaps_inflight_vmem(
                         MemRegion { base: base, size: candidate_size },
                     )
                 },
-                CoreState::UnmapWaiting { vaddr, .. } => {
+                CoreState::UnmapWaiting { vaddr, .. }
+                | CoreState::ProtectWaiting { vaddr, .. } => {
                     let size = if pt.dom().contains(vaddr) {
                         pt.index(vaddr).frame.size
                     } else {
@@ -517,7 +622,9 @@ pub open spec fn candidate_mapping_overlaps_inflight_vmem(
                 },
                 CoreState::UnmapOpExecuting { vaddr, result, .. }
                 | CoreState::UnmapOpDone { vaddr, result, .. }
-                | CoreState::UnmapShootdownWaiting { vaddr, result, .. } => {
+                | CoreState::UnmapShootdownWaiting { vaddr, result, .. }
+                | CoreState::ProtectOpDone { vaddr, result, .. }
+                | CoreState::ProtectShootdownWaiting { vaddr, result, .. } => {
                     let size = if result is Ok {
                         result.get_Ok_0().frame.size
                     } else {
@@ -550,10 +657,12 @@ pub open spec fn step_HW(
     &&& !(system_step is PTMemOp)
     //hw/spec_pt-statemachine steps
     &&& hardware::next_step(c.hw, s1.hw, s2.hw, system_step)
-    &&& spec_pt::step_Stutter(
-        s1.pt_variables(core),
-        s2.pt_variables(core),
-    )
+    // The MMU may set accessed and dirty flags, which don't change the interpretation
+    &&& if system_step is ReadWrite || system_step is TLBFill {
+        spec_pt::step_View_Stutter(s1.pt_variables(core), s2.pt_variables(core))
+    } else {
+        spec_pt::step_Stutter(s1.pt_variables(core), s2.pt_variables(core))
+    }
     //new state
     &&& s2.core_states == s1.core_states
     &&& s2.TLB_Shootdown == s1.TLB_Shootdown
@@ -829,6 +938,7 @@ pub open spec fn step_Unmap_Initiate_Shootdown(
     &&& s2.TLB_Shootdown == ShootdownVector {
         vaddr: vaddr,
         open_requests: Set::new(|core: Core| hardware::valid_core(c.hw, core)),
+        keeps_mapping: false,
     }
     &&& s2.sound == s1.sound
 }
@@ -844,10 +954,12 @@ pub open spec fn step_Ack_Shootdown_IPI(
     //enabling conditions
     //TODO discuss: only valid cores are in the open_requests
     &&& s1.TLB_Shootdown.open_requests.contains(core)
-    &&& !s1.hw.NUMAs[core.NUMA_id].cores[core.core_id].tlb.dom().contains(s1.TLB_Shootdown.vaddr)
-    &&& !s1.interp_pt_mem().contains_key(
-        s1.TLB_Shootdown.vaddr,
-    )
+    &&& if s1.TLB_Shootdown.keeps_mapping {
+        // a protect shootdown only requires the old entry to be gone
+        s1.tlb_entry_is_current(core, s1.TLB_Shootdown.vaddr)
+    } else {
+        !s1.hw.NUMAs[core.NUMA_id].cores[core.core_id].tlb.dom().contains(s1.TLB_Shootdown.vaddr)
+    }
     //hw/spec_pt-statemachine steps
     &&& hardware::step_PTMemOp(c.hw, s1.hw, s2.hw)
     &&& spec_pt::step_Stutter(
@@ -859,6 +971,7 @@ pub open spec fn step_Ack_Shootdown_IPI(
     &&& s2.TLB_Shootdown == ShootdownVector {
         vaddr: s1.TLB_Shootdown.vaddr,
         open_requests: s1.TLB_Shootdown.open_requests.remove(core),
+        keeps_mapping: s1.TLB_Shootdown.keeps_mapping,
     }
     &&& s2.sound == s1.sound
 }
@@ -890,6 +1003,246 @@ pub open spec fn step_Unmap_End(
     &&& s1.sound == s2.sound
 }
 
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// Protect
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+pub open spec fn step_Protect_enabled(vaddr: nat) -> bool {
+    step_Unmap_enabled(vaddr)
+}
+
+pub open spec fn step_Protect_Start(
+    c: OSConstants,
+    s1: OSVariables,
+    s2: OSVariables,
+    ULT_id: nat,
+    vaddr: nat,
+    flags: Flags,
+) -> bool {
+    let pt = hardware::interp_pt_mem(s1.hw.global_pt);
+    let core = c.ULT2core.index(ULT_id);
+    let pte_size = if pt.contains_key(vaddr) {
+        pt.index(vaddr).frame.size
+    } else {
+        0
+    };
+    //enabling conditions
+    &&& c.valid_ULT(ULT_id)
+    &&& s1.core_states[core] is Idle
+    &&& step_Protect_enabled(vaddr)
+    //hw/spec_pt-statemachine steps
+    &&& hardware::step_PTMemOp(c.hw, s1.hw, s2.hw)
+    &&& spec_pt::step_Stutter(
+        s1.pt_variables(core),
+        s2.pt_variables(core),
+    )
+    //new state
+    &&& s2.core_states == s1.core_states.insert(
+        core,
+        CoreState::ProtectWaiting { ULT_id, vaddr, flags },
+    )
+    &&& s2.TLB_Shootdown == s1.TLB_Shootdown
+    &&& s2.sound == s1.sound && (step_Unmap_sound(
+        hardware::interp_pt_mem(s1.hw.global_pt),
+        s1.core_states.values(),
+        vaddr,
+        pte_size,
+    ))
+}
+
+pub open spec fn step_Protect_Op(
+    c: OSConstants,
+    s1: OSVariables,
+    s2: OSVariables,
+    core: Core,
+    result: Result<(), ()>,
+) -> bool {
+    //enabling conditions
+    &&& hardware::valid_core(c.hw, core)
+    &&& s1.core_states[core] matches CoreState::ProtectWaiting { ULT_id, vaddr, flags }
+    &&& s1.kernel_lock(c) is None
+    //hw/spec_pt-statemachine steps
+    &&& hardware::step_PTMemOp(c.hw, s1.hw, s2.hw)
+    &&& spec_pt::step_Protect(
+        s1.pt_variables(core),
+        s2.pt_variables(core),
+        vaddr,
+        flags,
+        result,
+    )
+    //new state
+    &&& if result is Ok {
+        s2.core_states == s1.core_states.insert(
+            core,
+            CoreState::ProtectOpDone {
+                ULT_id,
+                vaddr,
+                flags,
+                result: Ok(s1.interp_pt_mem()[vaddr]),
+            },
+        )
+    } else {
+        s2.core_states == s1.core_states.insert(
+            core,
+            CoreState::ProtectOpDone { ULT_id, vaddr, flags, result: Err(()) },
+        )
+    }
+    &&& s2.TLB_Shootdown == s1.TLB_Shootdown
+    &&& s2.sound == s1.sound
+}
+
+pub open spec fn step_Protect_Initiate_Shootdown(
+    c: OSConstants,
+    s1: OSVariables,
+    s2: OSVariables,
+    core: Core,
+) -> bool {
+    //enabling conditions
+    &&& hardware::valid_core(c.hw, core)
+    &&& s1.core_states[core] matches CoreState::ProtectOpDone { ULT_id: ult_id, vaddr, flags, result }
+    &&& result is Ok
+    //hw/spec_pt-statemachine steps
+    &&& hardware::step_PTMemOp(c.hw, s1.hw, s2.hw)
+    &&& spec_pt::step_Stutter(
+        s1.pt_variables(core),
+        s2.pt_variables(core),
+    )
+    //new state
+    &&& s2.core_states == s1.core_states.insert(
+        core,
+        CoreState::ProtectShootdownWaiting { ULT_id: ult_id, vaddr, flags, result },
+    )
+    &&& s2.TLB_Shootdown == ShootdownVector {
+        vaddr: vaddr,
+        open_requests: Set::new(|core: Core| hardware::valid_core(c.hw, core)),
+        keeps_mapping: true,
+    }
+    &&& s2.sound == s1.sound
+}
+
+pub open spec fn step_Protect_End(
+    c: OSConstants,
+    s1: OSVariables,
+    s2: OSVariables,
+    core: Core,
+) -> bool {
+    //enabling conditions
+    &&& hardware::valid_core(c.hw, core)
+    &&& match s1.core_states[core] {
+        CoreState::ProtectShootdownWaiting { .. } => {
+            s1.TLB_Shootdown.open_requests.is_empty()
+        },
+        CoreState::ProtectOpDone { result, .. } => { result is Err },
+        _ => false,
+    }
+    //hw/spec_pt-statemachine steps
+    &&& hardware::step_PTMemOp(c.hw, s1.hw, s2.hw)
+    &&& spec_pt::step_Stutter(
+        s1.pt_variables(core),
+        s2.pt_variables(core),
+    )
+    //new state
+    &&& s2.core_states == s1.core_states.insert(core, CoreState::Idle)
+    &&& s2.TLB_Shootdown == s1.TLB_Shootdown
+    &&& s1.sound == s2.sound
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+// ClearDirty
+///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+pub open spec fn step_ClearDirty_enabled(vaddr: nat) -> bool {
+    step_Unmap_enabled(vaddr)
+}
+
+/// Clears the dirty flag of the mapping at `vaddr`. The result is the flag before clearing it,
+/// which the MMU may set at any time, so it's not determined by the page table.
+pub open spec fn step_ClearDirty_Op(
+    c: OSConstants,
+    s1: OSVariables,
+    s2: OSVariables,
+    ULT_id: nat,
+    vaddr: nat,
+    result: Result<bool, ()>,
+) -> bool {
+    let core = c.ULT2core.index(ULT_id);
+    //enabling conditions
+    &&& c.valid_ULT(ULT_id)
+    &&& s1.core_states[core] is Idle
+    &&& s1.kernel_lock(c) is None
+    &&& step_ClearDirty_enabled(vaddr)
+    &&& result is Ok <==> s1.interp_pt_mem().dom().contains(vaddr)
+    //hw/spec_pt-statemachine steps
+    &&& hardware::step_PTMemOp(c.hw, s1.hw, s2.hw)
+    &&& spec_pt::step_View_Stutter(
+        s1.pt_variables(core),
+        s2.pt_variables(core),
+    )
+    //new state
+    &&& s2.core_states == s1.core_states.insert(
+        core,
+        CoreState::ClearDirtyOpDone { ULT_id, vaddr, result },
+    )
+    &&& s2.TLB_Shootdown == s1.TLB_Shootdown
+    &&& s2.sound == s1.sound
+}
+
+pub open spec fn step_ClearDirty_Initiate_Shootdown(
+    c: OSConstants,
+    s1: OSVariables,
+    s2: OSVariables,
+    core: Core,
+) -> bool {
+    //enabling conditions
+    &&& hardware::valid_core(c.hw, core)
+    &&& s1.core_states[core] matches CoreState::ClearDirtyOpDone { ULT_id: ult_id, vaddr, result }
+    &&& result is Ok
+    //hw/spec_pt-statemachine steps
+    &&& hardware::step_PTMemOp(c.hw, s1.hw, s2.hw)
+    &&& spec_pt::step_Stutter(
+        s1.pt_variables(core),
+        s2.pt_variables(core),
+    )
+    //new state
+    &&& s2.core_states == s1.core_states.insert(
+        core,
+        CoreState::ClearDirtyShootdownWaiting { ULT_id: ult_id, vaddr, result },
+    )
+    // A TLB entry cached while the dirty flag was set lets its core write without setting the
+    // flag again, so it has to be evicted even though the mapping stays the same.
+    &&& s2.TLB_Shootdown == ShootdownVector {
+        vaddr: vaddr,
+        open_requests: Set::new(|core: Core| hardware::valid_core(c.hw, core)),
+        keeps_mapping: false,
+    }
+    &&& s2.sound == s1.sound
+}
+
+pub open spec fn step_ClearDirty_End(
+    c: OSConstants,
+    s1: OSVariables,
+    s2: OSVariables,
+    core: Core,
+) -> bool {
+    //enabling conditions
+    &&& hardware::valid_core(c.hw, core)
+    &&& match s1.core_states[core] {
+        CoreState::ClearDirtyShootdownWaiting { .. } => {
+            s1.TLB_Shootdown.open_requests.is_empty()
+        },
+        CoreState::ClearDirtyOpDone { result, .. } => { result is Err },
+        _ => false,
+    }
+    //hw/spec_pt-statemachine steps
+    &&& hardware::step_PTMemOp(c.hw, s1.hw, s2.hw)
+    &&& spec_pt::step_Stutter(
+        s1.pt_variables(core),
+        s2.pt_variables(core),
+    )
+    //new state
+    &&& s2.core_states == s1.core_states.insert(core, CoreState::Idle)
+    &&& s2.TLB_Shootdown == s1.TLB_Shootdown
+    &&& s1.sound == s2.sound
+}
+
 pub open spec fn step_View_Stutter(
     c: OSConstants,
     s1: OSVariables,
@@ -928,6 +1281,15 @@ pub enum OSStep {
     UnmapInitiateShootdown { core: Core },
     AckShootdownIPI { core: Core },
     UnmapEnd { core: Core },
+    //protect
+    ProtectStart { ULT_id: nat, vaddr: nat, flags: Flags },
+    ProtectOp { core: Core, result: Result<(), ()> },
+    ProtectInitiateShootdown { core: Core },
+    ProtectEnd { core: Core },
+    //clear dirty
+    ClearDirtyOp { ULT_id: nat, vaddr: nat, result: Result<bool, ()> },
+    ClearDirtyInitiateShootdown { core: Core },
+    ClearDirtyEnd { core: Core },
     ViewStutter { core: Core },
 }
 
@@ -1012,6 +1374,25 @@ impl OSStep {
                     _ => arbitrary(),
                 }
             },
+            //Protect steps
+            OSStep::ProtectStart { ULT_id, vaddr, flags } => {
+                hlspec::AbstractStep::ProtectStart { thread_id: ULT_id, vaddr, flags }
+            },
+            OSStep::ProtectOp { .. } => hlspec::AbstractStep::Stutter,
+            OSStep::ProtectInitiateShootdown { .. } => hlspec::AbstractStep::Stutter,
+            OSStep::ProtectEnd { core } => {
+                match s.core_states[core] {
+                    CoreState::ProtectShootdownWaiting { result, ULT_id, .. }
+                    | CoreState::ProtectOpDone { result, ULT_id, .. } => {
+                        hlspec::AbstractStep::ProtectEnd { thread_id: ULT_id, result: result_map_ok(result, |r| ()) }
+                    },
+                    _ => arbitrary(),
+                }
+            },
+            //ClearDirty steps
+            OSStep::ClearDirtyOp { .. } => hlspec::AbstractStep::Stutter,
+            OSStep::ClearDirtyInitiateShootdown { .. } => hlspec::AbstractStep::Stutter,
+            OSStep::ClearDirtyEnd { .. } => hlspec::AbstractStep::Stutter,
             OSStep::ViewStutter { .. } => hlspec::AbstractStep::Stutter,
         }
     }
@@ -1031,6 +1412,15 @@ pub open spec fn next_step(c: OSConstants, s1: OSVariables, s2: OSVariables, ste
         OSStep::UnmapInitiateShootdown { core } => step_Unmap_Initiate_Shootdown(c, s1, s2, core),
         OSStep::AckShootdownIPI { core }        => step_Ack_Shootdown_IPI(c, s1, s2, core),
         OSStep::UnmapEnd { core }               => step_Unmap_End(c, s1, s2, core),
+        //Protect steps
+        OSStep::ProtectStart { ULT_id, vaddr, flags } => step_Protect_Start(c, s1, s2, ULT_id, vaddr, flags),
+        OSStep::ProtectOp { core, result }      => step_Protect_Op(c, s1, s2, core, result),
+        OSStep::ProtectInitiateShootdown { core } => step_Protect_Initiate_Shootdown(c, s1, s2, core),
+        OSStep::ProtectEnd { core }             => step_Protect_End(c, s1, s2, core),
+        //ClearDirty steps
+        OSStep::ClearDirtyOp { ULT_id, vaddr, result } => step_ClearDirty_Op(c, s1, s2, ULT_id, vaddr, result),
+        OSStep::ClearDirtyInitiateShootdown { core } => step_ClearDirty_Initiate_Shootdown(c, s1, s2, core),
+        OSStep::ClearDirtyEnd { core }          => step_ClearDirty_End(c, s1, s2, core),
         OSStep::ViewStutter { core }            => step_View_Stutter(c, s1, s2, core),
     }
 }
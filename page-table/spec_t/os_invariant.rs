@@ -195,12 +195,156 @@ pub proof fn next_step_preserves_tlb_inv(
             assert(s2.TLB_dom_subset_of_pt_and_inflight_unmap_vaddr(c));
 
         },
+        //Protect steps
+        os::OSStep::ProtectStart { ULT_id, vaddr, flags } => {
+            let core = c.ULT2core[ULT_id];
+            lemma_Unmap_vaddr_unchanged(s1, s2, core, s2.core_states[core]);
+            assert(s2.interp_pt_mem() == s1.interp_pt_mem());
+            assert(s2.shootdown_cores_valid(c));
+            assert(s2.successful_IPI(c));
+            assert(s2.TLB_dom_subset_of_pt_and_inflight_unmap_vaddr(c));
+
+        },
+        os::OSStep::ProtectOp { core, result } => {
+            lemma_Unmap_vaddr_unchanged(s1, s2, core, s2.core_states[core]);
+            // Only the flags of the entry change
+            assert(s2.interp_pt_mem().dom() =~= s1.interp_pt_mem().dom());
+            assert(s2.shootdown_cores_valid(c));
+            // No core held the lock, so no core is waiting for a shootdown
+            assert forall|dispatcher: hardware::Core|
+                hardware::valid_core(c.hw, dispatcher) && dispatcher != core
+                implies !(#[trigger] s2.core_states[dispatcher]).holds_lock() by {
+                assert(!s1.core_states[dispatcher].holds_lock());
+            }
+            assert(s2.successful_IPI(c));
+            assert(s2.TLB_dom_subset_of_pt_and_inflight_unmap_vaddr(c));
+
+        },
+        os::OSStep::ProtectInitiateShootdown { core } => {
+            lemma_Unmap_vaddr_unchanged(s1, s2, core, s2.core_states[core]);
+            assert(s2.interp_pt_mem() == s1.interp_pt_mem());
+            assert(s2.shootdown_cores_valid(c));
+            assert forall|dispatcher: hardware::Core|
+                hardware::valid_core(c.hw, dispatcher) && dispatcher != core
+                implies !(#[trigger] s2.core_states[dispatcher]).holds_lock() by {
+                assert(s1.core_states[core].holds_lock());
+                assert(!s1.core_states[dispatcher].holds_lock());
+            }
+            // All valid cores have an open request
+            assert forall|handler: hardware::Core| hardware::valid_core(c.hw, handler)
+                implies #[trigger] s2.TLB_Shootdown.open_requests.contains(handler) by { }
+            assert(s2.successful_IPI(c));
+            assert(s2.TLB_dom_subset_of_pt_and_inflight_unmap_vaddr(c));
+        },
+        os::OSStep::ProtectEnd { core } => {
+            lemma_Unmap_vaddr_unchanged(s1, s2, core, os::CoreState::Idle);
+            assert(s2.interp_pt_mem() == s1.interp_pt_mem());
+            assert(s2.shootdown_cores_valid(c));
+            assert forall|dispatcher: hardware::Core|
+                hardware::valid_core(c.hw, dispatcher) && dispatcher != core
+                implies !(#[trigger] s2.core_states[dispatcher]).holds_lock() by {
+                assert(s1.core_states[core].holds_lock());
+                assert(!s1.core_states[dispatcher].holds_lock());
+            }
+            assert(s2.successful_IPI(c));
+            assert(s2.TLB_dom_subset_of_pt_and_inflight_unmap_vaddr(c));
+
+        },
+        //ClearDirty steps
+        os::OSStep::ClearDirtyOp { ULT_id, vaddr, result } => {
+            let core = c.ULT2core[ULT_id];
+            lemma_Unmap_vaddr_unchanged(s1, s2, core, s2.core_states[core]);
+            assert(s2.interp_pt_mem() == s1.interp_pt_mem());
+            assert(s2.shootdown_cores_valid(c));
+            // No core held the lock, so no core is waiting for a shootdown
+            assert forall|dispatcher: hardware::Core|
+                hardware::valid_core(c.hw, dispatcher) && dispatcher != core
+                implies !(#[trigger] s2.core_states[dispatcher]).holds_lock() by {
+                assert(!s1.core_states[dispatcher].holds_lock());
+            }
+            assert(s2.successful_IPI(c));
+            assert(s2.TLB_dom_subset_of_pt_and_inflight_unmap_vaddr(c));
+        },
+        os::OSStep::ClearDirtyInitiateShootdown { core } => {
+            lemma_Unmap_vaddr_unchanged(s1, s2, core, s2.core_states[core]);
+            assert(s2.interp_pt_mem() == s1.interp_pt_mem());
+            assert(s2.shootdown_cores_valid(c));
+            assert forall|dispatcher: hardware::Core|
+                hardware::valid_core(c.hw, dispatcher) && dispatcher != core
+                implies !(#[trigger] s2.core_states[dispatcher]).holds_lock() by {
+                assert(s1.core_states[core].holds_lock());
+                assert(!s1.core_states[dispatcher].holds_lock());
+            }
+            // All valid cores have an open request
+            assert forall|handler: hardware::Core| hardware::valid_core(c.hw, handler)
+                implies #[trigger] s2.TLB_Shootdown.open_requests.contains(handler) by { }
+            assert(s2.successful_IPI(c));
+            assert(s2.TLB_dom_subset_of_pt_and_inflight_unmap_vaddr(c));
+        },
+        os::OSStep::ClearDirtyEnd { core } => {
+            lemma_Unmap_vaddr_unchanged(s1, s2, core, os::CoreState::Idle);
+            assert(s2.interp_pt_mem() == s1.interp_pt_mem());
+            assert(s2.shootdown_cores_valid(c));
+            assert forall|dispatcher: hardware::Core|
+                hardware::valid_core(c.hw, dispatcher) && dispatcher != core
+                implies !(#[trigger] s2.core_states[dispatcher]).holds_lock() by {
+                assert(s1.core_states[core].holds_lock());
+                assert(!s1.core_states[dispatcher].holds_lock());
+            }
+            assert(s2.successful_IPI(c));
+            assert(s2.TLB_dom_subset_of_pt_and_inflight_unmap_vaddr(c));
+        },
         os::OSStep::ViewStutter { .. } => {
             assume(false);
         },
     }
 }
 
+/// Changing the state of a core doesn't change `Unmap_vaddr`, unless the core finishes or starts the
+/// second half of an unmap
+pub proof fn lemma_Unmap_vaddr_unchanged(
+    s1: os::OSVariables,
+    s2: os::OSVariables,
+    core: hardware::Core,
+    corestate: os::CoreState,
+)
+    requires
+        s1.core_states.dom().contains(core),
+        s2.core_states == s1.core_states.insert(core, corestate),
+        !(s1.core_states[core] is UnmapOpDone),
+        !(s1.core_states[core] is UnmapShootdownWaiting),
+        !(corestate is UnmapOpDone),
+        !(corestate is UnmapShootdownWaiting),
+    ensures
+        s2.Unmap_vaddr() == s1.Unmap_vaddr(),
+{
+    assert forall|v: nat| s1.Unmap_vaddr().contains(v) implies s2.Unmap_vaddr().contains(v) by {
+        let cr = choose|cr: hardware::Core|
+            s1.core_states.dom().contains(cr) && match s1.core_states[cr] {
+                os::CoreState::UnmapOpDone { vaddr, result, .. }
+                | os::CoreState::UnmapShootdownWaiting { vaddr, result, .. } => {
+                    (result is Ok) && (vaddr === v)
+                },
+                _ => false,
+            };
+        assert(cr != core);
+        assert(s2.core_states.dom().contains(cr) && s2.core_states[cr] == s1.core_states[cr]);
+    }
+    assert forall|v: nat| s2.Unmap_vaddr().contains(v) implies s1.Unmap_vaddr().contains(v) by {
+        let cr = choose|cr: hardware::Core|
+            s2.core_states.dom().contains(cr) && match s2.core_states[cr] {
+                os::CoreState::UnmapOpDone { vaddr, result, .. }
+                | os::CoreState::UnmapShootdownWaiting { vaddr, result, .. } => {
+                    (result is Ok) && (vaddr === v)
+                },
+                _ => false,
+            };
+        assert(cr != core);
+        assert(s1.core_states.dom().contains(cr) && s1.core_states[cr] == s2.core_states[cr]);
+    }
+    assert(s2.Unmap_vaddr() =~= s1.Unmap_vaddr());
+}
+
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 // Proof of overlapping virtual memory Invariants
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -402,6 +546,75 @@ pub proof fn next_step_preserves_overlap_vmem_inv(
                 assert(s2.overlapping_vmem_inv(c));
                 assert(s2.existing_map_no_overlap_existing_vmem(c));
             },
+            //Protect steps
+            os::OSStep::ProtectStart { ULT_id, vaddr, flags } => {
+                let core = c.ULT2core[ULT_id];
+                let corestate = os::CoreState::ProtectWaiting { ULT_id, vaddr, flags };
+                Lemma_insert_no_overlap_preserves_no_overlap(
+                    c,
+                    s1.core_states,
+                    s1.interp_pt_mem(),
+                    core,
+                    corestate,
+                );
+                Lemma_unique_and_overlap_values_implies_overlap_vmem(c, s2);
+                assert(s2.existing_map_no_overlap_existing_vmem(c));
+            },
+            os::OSStep::ProtectOp { core, result } => {
+                let corestate = s2.core_states[core];
+                assert(s2.core_states == s1.core_states.insert(core, corestate));
+                assert(corestate.vmem_pte_size(s1.interp_pt_mem())
+                    == s1.core_states[core].vmem_pte_size(s1.interp_pt_mem()));
+                assert forall|cr| #![auto]
+                    s1.core_states.dom().contains(cr) && s1.core_states[cr].holds_lock()
+                    implies cr == core by {
+                    assert(hardware::valid_core(c.hw, cr));
+                }
+                Lemma_overlapping_inv_implies_unique_and_overlap_values(c, s1);
+                Lemma_insert_preserves_no_overlap(
+                    c,
+                    s1.core_states,
+                    s1.interp_pt_mem(),
+                    core,
+                    corestate,
+                );
+                // Only the flags of the entry change, so the sizes of all mappings stay the same
+                assert(s2.interp_pt_mem().dom() =~= s1.interp_pt_mem().dom());
+                assert forall|base| #[trigger] s2.interp_pt_mem().dom().contains(base) implies
+                    s2.interp_pt_mem()[base].frame == s1.interp_pt_mem()[base].frame by { }
+                Lemma_same_frames_preserves_no_overlap(
+                    c,
+                    s2.core_states,
+                    s1.interp_pt_mem(),
+                    s2.interp_pt_mem(),
+                );
+                Lemma_unique_and_overlap_values_implies_overlap_vmem(c, s2);
+                Lemma_same_frames_preserves_existing_no_overlap(c, s1, s2);
+            },
+            os::OSStep::ProtectInitiateShootdown { core } => {
+                let vaddr = s1.core_states[core]->ProtectOpDone_vaddr;
+                let ULT_id = s1.core_states[core]->ProtectOpDone_ULT_id;
+                let flags = s1.core_states[core]->ProtectOpDone_flags;
+                let result = s1.core_states[core]->ProtectOpDone_result;
+                let corestate = os::CoreState::ProtectShootdownWaiting { ULT_id, vaddr, flags, result };
+                Lemma_insert_preserves_no_overlap(
+                    c,
+                    s1.core_states,
+                    s1.interp_pt_mem(),
+                    core,
+                    corestate,
+                );
+                Lemma_unique_and_overlap_values_implies_overlap_vmem(c, s2);
+                assert(s2.existing_map_no_overlap_existing_vmem(c));
+            },
+            //ClearDirty steps
+            os::OSStep::ClearDirtyOp { ULT_id, .. } => {
+                // The core stays idle and only the accessed and dirty flags change
+                assert(s2.interp_pt_mem() == s1.interp_pt_mem());
+                assert(s2.core_states[c.ULT2core[ULT_id]].is_idle());
+                assert(s2.overlapping_vmem_inv(c));
+                assert(s2.existing_map_no_overlap_existing_vmem(c));
+            },
             _ => {
                 assert(s2.overlapping_vmem_inv(c));
                 assert(s2.existing_map_no_overlap_existing_vmem(c));
@@ -723,6 +936,83 @@ pub proof fn Lemma_submap_preserves_no_overlap(
     }
 }
 
+/// Protect only changes the flags of a mapping, which the overlap invariants don't depend on
+pub proof fn Lemma_same_frames_preserves_no_overlap(
+    c: os::OSConstants,
+    core_states: Map<hardware::Core, os::CoreState>,
+    pt: Map<nat, PageTableEntry>,
+    pt2: Map<nat, PageTableEntry>,
+)
+    requires
+        no_overlap_vmem_values(c, core_states, pt),
+        pt2.dom() == pt.dom(),
+        forall|base| #[trigger] pt2.dom().contains(base) ==> pt2[base].frame == pt[base].frame,
+    ensures
+        no_overlap_vmem_values(c, core_states, pt2),
+{
+    assert forall|state: os::CoreState| !state.is_idle() implies
+        #[trigger] state.vmem_pte_size(pt2) == state.vmem_pte_size(pt) by {
+        if pt.dom().contains(state.vaddr()) {
+            assert(pt2.dom().contains(state.vaddr()));
+        }
+    }
+    assert forall|state1: os::CoreState, state2: os::CoreState|
+        core_states.values().contains(state1) && core_states.values().contains(state2)
+            && !state1.is_idle() && !state2.is_idle() && overlap(
+            MemRegion { base: state1.vaddr(), size: state1.vmem_pte_size(pt2) },
+            MemRegion { base: state2.vaddr(), size: state2.vmem_pte_size(pt2) },
+        ) implies state1 == state2 by {
+        assert(state1.vmem_pte_size(pt2) == state1.vmem_pte_size(pt));
+        assert(state2.vmem_pte_size(pt2) == state2.vmem_pte_size(pt));
+    }
+}
+
+pub proof fn Lemma_same_frames_preserves_existing_no_overlap(
+    c: os::OSConstants,
+    s1: os::OSVariables,
+    s2: os::OSVariables,
+)
+    requires
+        s1.existing_map_no_overlap_existing_vmem(c),
+        s2.interp_pt_mem().dom() == s1.interp_pt_mem().dom(),
+        forall|base| #[trigger] s2.interp_pt_mem().dom().contains(base)
+            ==> s2.interp_pt_mem()[base].frame == s1.interp_pt_mem()[base].frame,
+    ensures
+        s2.existing_map_no_overlap_existing_vmem(c),
+{
+    assert forall|base| #[trigger]
+        s2.interp_pt_mem().dom().contains(base) implies !candidate_mapping_overlaps_existing_vmem(
+        s2.interp_pt_mem().remove(base),
+        base,
+        s2.interp_pt_mem()[base],
+    ) by {
+        if candidate_mapping_overlaps_existing_vmem(
+            s2.interp_pt_mem().remove(base),
+            base,
+            s2.interp_pt_mem()[base],
+        ) {
+            let b = choose|b: nat|
+                #![auto]
+                {
+                    &&& s2.interp_pt_mem().remove(base).dom().contains(b)
+                    &&& overlap(
+                        MemRegion { base: base, size: s2.interp_pt_mem()[base].frame.size },
+                        MemRegion { base: b, size: s2.interp_pt_mem().remove(base)[b].frame.size },
+                    )
+                };
+            assert(s2.interp_pt_mem().dom().contains(b));
+            assert(s1.interp_pt_mem().remove(base).dom().contains(b));
+            assert(s1.interp_pt_mem().remove(base)[b].frame == s2.interp_pt_mem().remove(base)[b].frame);
+            assert(candidate_mapping_overlaps_existing_vmem(
+                s1.interp_pt_mem().remove(base),
+                base,
+                s1.interp_pt_mem()[base],
+            ));
+            assert(false);
+        }
+    }
+}
+
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 // soundness lemmata
 ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -773,7 +1063,8 @@ pub proof fn lemma_candidate_mapping_inflight_vmem_overlap_os_implies_hl(
                                 MemRegion { base: base, size: candidate_size },
                             )
                         },
-                        os::CoreState::UnmapWaiting { vaddr, .. } => {
+                        os::CoreState::UnmapWaiting { vaddr, .. }
+                        | os::CoreState::ProtectWaiting { vaddr, .. } => {
                             let size = if s.interp_pt_mem().dom().contains(vaddr) {
                                 s.interp_pt_mem().index(vaddr).frame.size
                             } else {
@@ -786,7 +1077,9 @@ pub proof fn lemma_candidate_mapping_inflight_vmem_overlap_os_implies_hl(
                         },
                         os::CoreState::UnmapOpExecuting { vaddr, result, .. }
                         | os::CoreState::UnmapOpDone { vaddr, result, .. }
-                        | os::CoreState::UnmapShootdownWaiting { vaddr, result, .. } => {
+                        | os::CoreState::UnmapShootdownWaiting { vaddr, result, .. }
+                        | os::CoreState::ProtectOpDone { vaddr, result, .. }
+                        | os::CoreState::ProtectShootdownWaiting { vaddr, result, .. } => {
                             let size = if result is Ok {
                                 result.get_Ok_0().frame.size
                             } else {
@@ -889,6 +1182,75 @@ pub proof fn lemma_candidate_mapping_inflight_vmem_overlap_os_implies_hl(
                         });
                     }
                 },
+                os::CoreState::ProtectWaiting { ULT_id, vaddr, flags } => {
+                    assert(c.valid_ULT(ULT_id));
+                    let thread_state = s.interp_thread_state(c)[ULT_id];
+                    assert(s.interp(c).thread_state.dom().contains(ULT_id));
+                    assert(s.interp(c).thread_state.values().contains(thread_state));
+                    if (s.interp_pt_mem().dom().contains(vaddr)) {
+                        assert({
+                            &&& thread_state matches hlspec::AbstractArguments::Protect {
+                                vaddr: v_address,
+                                pte: Some(p_te),
+                                ..
+                            }
+                            &&& v_address === vaddr
+                            &&& s.interp_pt_mem()[vaddr] === p_te
+                            &&& overlap(
+                                MemRegion { base: v_address, size: p_te.frame.size },
+                                MemRegion { base: base, size: candidate_size },
+                            )
+                        });
+                    } else {
+                        assert({
+                            &&& thread_state matches hlspec::AbstractArguments::Protect {
+                                vaddr: v_address,
+                                pte: None,
+                                ..
+                            }
+                            &&& v_address === vaddr
+                            &&& overlap(
+                                MemRegion { base: v_address, size: 0 },
+                                MemRegion { base: base, size: candidate_size },
+                            )
+                        });
+                    }
+                },
+                os::CoreState::ProtectOpDone { ULT_id, vaddr, result, .. }
+                | os::CoreState::ProtectShootdownWaiting { ULT_id, vaddr, result, .. } => {
+                    assert(c.valid_ULT(ULT_id));
+                    let thread_state = s.interp_thread_state(c)[ULT_id];
+                    assert(s.interp(c).thread_state.dom().contains(ULT_id));
+                    assert(s.interp(c).thread_state.values().contains(thread_state));
+                    if result is Ok {
+                        assert({
+                            &&& thread_state matches hlspec::AbstractArguments::Protect {
+                                vaddr: v_address,
+                                pte: Some(pte),
+                                ..
+                            }
+                            &&& v_address === vaddr
+                            &&& result.get_Ok_0() === pte
+                            &&& overlap(
+                                MemRegion { base: v_address, size: pte.frame.size },
+                                MemRegion { base: base, size: candidate_size },
+                            )
+                        });
+                    } else {
+                        assert({
+                            &&& thread_state matches hlspec::AbstractArguments::Protect {
+                                vaddr: v_address,
+                                pte: None,
+                                ..
+                            }
+                            &&& v_address === vaddr
+                            &&& overlap(
+                                MemRegion { base: v_address, size: 0 },
+                                MemRegion { base: base, size: candidate_size },
+                            )
+                        });
+                    }
+                },
                 _ => {},
             };
         } else {
@@ -941,7 +1303,8 @@ pub proof fn lemma_candidate_mapping_inflight_vmem_overlap_hl_implies_os(
                                 MemRegion { base: base, size: candidate_size },
                             )
                         },
-                        hlspec::AbstractArguments::Unmap { vaddr, pte } => {
+                        hlspec::AbstractArguments::Unmap { vaddr, pte }
+                        | hlspec::AbstractArguments::Protect { vaddr, pte, .. } => {
                             let size = if pte.is_some() {
                                 pte.unwrap().frame.size
                             } else {
@@ -1043,6 +1406,54 @@ pub proof fn lemma_candidate_mapping_inflight_vmem_overlap_hl_implies_os(
 
                     }
                 },
+                os::CoreState::ProtectWaiting { ULT_id: ult_id, vaddr, .. } => {
+                    assert(ult_id == ULT_id);
+                    if s.interp_pt_mem().dom().contains(vaddr) {
+                        let pte = s.interp_pt_mem()[vaddr];
+                        assert({
+                            &&& thread_state matches hlspec::AbstractArguments::Protect {
+                                vaddr: v_addr,
+                                pte: Some(entry),
+                                ..
+                            }
+                            &&& vaddr === v_addr
+                            &&& entry === pte
+                        });
+                        assert(overlap(
+                            MemRegion { base: vaddr, size: pte.frame.size },
+                            MemRegion { base: base, size: candidate_size },
+                        ));
+                    } else {
+                        assert(overlap(
+                            MemRegion { base: vaddr, size: 0 },
+                            MemRegion { base: base, size: candidate_size },
+                        ));
+                    }
+                },
+                os::CoreState::ProtectOpDone { ULT_id: ult_id, vaddr, result, .. }
+                | os::CoreState::ProtectShootdownWaiting { ULT_id: ult_id, vaddr, result, .. } => {
+                    assert(ult_id == ULT_id);
+                    if result is Ok {
+                        assert({
+                            &&& thread_state matches hlspec::AbstractArguments::Protect {
+                                vaddr: v_addr,
+                                pte: Some(pte),
+                                ..
+                            }
+                            &&& vaddr === v_addr
+                            &&& result.get_Ok_0() === pte
+                        });
+                        assert(overlap(
+                            MemRegion { base: vaddr, size: result.get_Ok_0().frame.size },
+                            MemRegion { base: base, size: candidate_size },
+                        ));
+                    } else {
+                        assert(overlap(
+                            MemRegion { base: vaddr, size: 0 },
+                            MemRegion { base: base, size: candidate_size },
+                        ));
+                    }
+                },
                 _ => {},
             };
         } else {
@@ -1100,7 +1511,7 @@ pub proof fn lemma_candidate_mapping_inflight_pmem_overlap_os_implies_hl(
                             &&& result is Ok
                             &&& overlap(candidate.frame, result.get_Ok_0().frame)
                         },
-                        os::CoreState::Idle => false,
+                        _ => false,
                     }
                 };
             let core = choose|core| #[trigger]
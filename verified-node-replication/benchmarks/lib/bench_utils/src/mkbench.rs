@@ -51,7 +51,42 @@ impl ThreadToken {
 type ReplicaId = usize;
 
 #[cfg(feature = "verified")]
-use verified_node_replication::{Dispatch, /* AffinityFn, NodeReplicated, NR,*/ ReplicaId, ThreadToken};
+use verified_node_replication::{ConcurrentDispatch, Dispatch, /* AffinityFn, NodeReplicated, NR,*/ ReplicaId, ThreadToken, MultiLogThreadToken};
+
+/// The thread token handed out by a verified data-structure on registration.
+#[cfg(feature = "verified")]
+pub trait BenchThreadToken {
+    fn replica_id(&self) -> ReplicaId;
+    fn thread_id(&self) -> u32;
+}
+
+#[cfg(feature = "verified")]
+impl<D: Dispatch> BenchThreadToken for ThreadToken<D> {
+    fn replica_id(&self) -> ReplicaId {
+        ThreadToken::replica_id(self)
+    }
+    fn thread_id(&self) -> u32 {
+        ThreadToken::thread_id(self)
+    }
+}
+
+#[cfg(feature = "verified")]
+impl<D: ConcurrentDispatch> BenchThreadToken for MultiLogThreadToken<D> {
+    fn replica_id(&self) -> ReplicaId {
+        MultiLogThreadToken::replica_id(self)
+    }
+    fn thread_id(&self) -> u32 {
+        MultiLogThreadToken::thread_id(self)
+    }
+}
+
+/// Converts a log size in bytes to the number of log entries of the verified library.
+#[cfg(feature = "verified")]
+pub fn log_entries<D: Dispatch>(log_bytes: usize) -> usize {
+    use verified_node_replication::constants::{MAX_LOG_SIZE, MIN_LOG_SIZE};
+    let entry_size = std::mem::size_of::<D::WriteOperation>() + std::mem::size_of::<u64>();
+    (log_bytes / entry_size).clamp(MIN_LOG_SIZE, MAX_LOG_SIZE)
+}
 
 
 use rand::seq::SliceRandom;
@@ -126,14 +161,14 @@ type BenchFn<R> = fn(
 /// The function that executes the benchmark operation.
 type BenchFn<R> = fn(
     tid: crate::ThreadId,
-    idx: ThreadToken<<R as DsInterface>::D>,
+    idx: <R as DsInterface>::TT,
     replica: &Arc<R>,
     operations: &Operation<
         <<R as DsInterface>::D as Dispatch>::ReadOperation,
         <<R as DsInterface>::D as Dispatch>::WriteOperation,
     >,
     usize,
-) -> ThreadToken<<R as DsInterface>::D>;
+) -> <R as DsInterface>::TT;
 
 /// The interface a data-structure must implement to be benchmarked by
 /// `ScaleBench`.
@@ -277,6 +312,9 @@ impl<'a, T: Dispatch + Sync + Default> DsInterface for NodeReplicated<'_, T> {
 pub trait DsInterface {
     type D: Dispatch + Default + Sync;
 
+    /// The thread token type of the data-structure.
+    type TT: BenchThreadToken + Send + 'static;
+
     /// Allocate a new data-structure.
     ///
     /// - `replicas`: How many replicas the data-structure should maintain.
@@ -286,21 +324,21 @@ pub trait DsInterface {
     /// Register a thread with a data-structure.
     ///
     /// - `rid` indicates which replica the thread should use.
-    fn register(&mut self, rid: ReplicaId) -> Option<ThreadToken<Self::D >>;
+    fn register(&mut self, rid: ReplicaId) -> Option<Self::TT>;
 
     /// Apply a mutable operation to the data-structure.
     fn execute_mut(
         &self,
         op: <Self::D as Dispatch>::WriteOperation,
-        idx: ThreadToken<Self::D >,
-    ) -> Result<(<Self::D as Dispatch>::Response, ThreadToken<Self::D >), ThreadToken<Self::D >>;
+        idx: Self::TT,
+    ) -> Result<(<Self::D as Dispatch>::Response, Self::TT), Self::TT>;
 
     /// Apply a immutable operation to the data-structure.
     fn execute(
         &self,
         op: <Self::D as Dispatch>::ReadOperation,
-        idx: ThreadToken<Self::D >,
-    ) -> Result<(<Self::D as Dispatch>::Response, ThreadToken<Self::D >), ThreadToken<Self::D >>;
+        idx: Self::TT,
+    ) -> Result<(<Self::D as Dispatch>::Response, Self::TT), Self::TT>;
 }
 
 
@@ -584,17 +622,24 @@ where
         let start_sync = Arc::new(Barrier::new(thread_num));
         let replicas = NonZeroUsize::new(self.replicas()).unwrap();
 
-        let mut ds = R::new(replicas, NonZeroUsize::new(1).unwrap(), self.log_size);
+        let logs = NonZeroUsize::new(match self.ls {
+            LogStrategy::One => 1,
+            LogStrategy::PerThread => thread_num,
+            LogStrategy::Custom(n) => n,
+        })
+        .expect("need at least one log");
+
+        let mut ds = R::new(replicas, logs, self.log_size);
 
         #[cfg(feature = "verified")]
         let mut thread_tokens = {
             let mut thread_tokens =  HashMap::with_capacity(replicas.into());
             for (rid, cores) in self.rm.clone().into_iter() {
                 if thread_tokens.contains_key(&rid) {
-                    let tks : &mut Vec<ThreadToken<R::D>> = thread_tokens.get_mut(&rid).unwrap();
+                    let tks : &mut Vec<R::TT> = thread_tokens.get_mut(&rid).unwrap();
                     tks.extend(cores.iter().map(|_| ds.register(rid).unwrap()));
                 } else {
-                    // let tks :  Vec<ThreadToken<R::D>> = Vec::new();
+                    // let tks :  Vec<R::TT> = Vec::new();
                     thread_tokens.insert(rid, cores.iter().map(|_| ds.register(rid).unwrap()).collect());
                 }
             }
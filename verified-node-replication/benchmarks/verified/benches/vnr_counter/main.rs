@@ -18,7 +18,7 @@ use bench_utils::benchmark::*;
 use bench_utils::mkbench::{self, DsInterface};
 use bench_utils::topology::ThreadMapping;
 use bench_utils::Operation;
-use verified_node_replication::{Dispatch, AffinityFn, NodeReplicated, ReplicaId, ThreadToken, NodeReplicatedT};
+use verified_node_replication::{Dispatch, AffinityFn, NodeReplicated, NrConfig, ReplicaId, ThreadToken, NodeReplicatedT};
 
 use builtin::Tracked;
 
@@ -124,6 +124,7 @@ struct VNRWrapper {
 /// `ScaleBench`.
 impl DsInterface for VNRWrapper {
     type D = NrCounter; //: Dispatch + Default + Sync;
+    type TT = ThreadToken<NrCounter>;
 
     /// Allocate a new data-structure.
     ///
@@ -131,7 +132,10 @@ impl DsInterface for VNRWrapper {
     /// - `logs`: How many logs the data-structure should be partitioned over.
     fn new(replicas: NonZeroUsize, logs: NonZeroUsize, log_size: usize) -> Self {
         VNRWrapper {
-            val: NodeReplicatedT::<NrCounter>::new(replicas.into(), AffinityFn::new(mkbench::chg_affinity)),
+            val: NodeReplicated::with_config(
+                NrConfig::new(replicas.into()).log_size(mkbench::log_entries::<NrCounter>(log_size)),
+                AffinityFn::new(mkbench::chg_affinity),
+            ),
         }
     }
 
@@ -150,7 +154,7 @@ impl DsInterface for VNRWrapper {
     ) -> Result<(<Self::D as Dispatch>::Response, ThreadToken<Self::D>), ThreadToken<Self::D>> {
         match NodeReplicatedT::execute_mut(&self.val, op, idx, Tracked::assume_new()) {
             Ok((res, tkn, _)) => Ok((res, tkn)),
-            Err((tkn, _)) => Err(tkn),
+            Err((tkn, _, _)) => Err(tkn),
         }
     }
 
@@ -162,7 +166,7 @@ impl DsInterface for VNRWrapper {
     ) -> Result<(<Self::D as Dispatch>::Response, ThreadToken<Self::D>), ThreadToken<Self::D>> {
         match NodeReplicatedT::execute(&self.val, op, idx, Tracked::assume_new()) {
             Ok((res, tkn, _)) => Ok((res, tkn)),
-            Err((tkn, _)) => Err(tkn),
+            Err((tkn, _, _)) => Err(tkn),
         }
     }
 }
@@ -0,0 +1,17 @@
+// Queue Benchmark for verified NR
+// Adapted from https://github.com/vmware/node-replication/blob/master/node-replication/benches/stack/main.rs
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Benchmarks the verified replicated queue.
+use verified_node_replication::NrQueue;
+use verified_node_replication::nrqueue::{NrQueueReadOp, NrQueueWriteOp};
+
+#[path = "../../src/seq_bench.rs"]
+mod seq_bench;
+use seq_bench::*;
+
+fn main() {
+    run::<NrQueue<u64>>("vnr-queue", |write_ratio| {
+        generate_operations(NOP, write_ratio, NrQueueReadOp::Peek, NrQueueWriteOp::Push, NrQueueWriteOp::Pop)
+    });
+}
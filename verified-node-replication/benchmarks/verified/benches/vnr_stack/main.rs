@@ -0,0 +1,17 @@
+// Stack Benchmark for verified NR
+// Adapted from https://github.com/vmware/node-replication/blob/master/node-replication/benches/stack/main.rs
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Benchmarks the verified replicated stack.
+use verified_node_replication::NrStack;
+use verified_node_replication::nrstack::{NrStackReadOp, NrStackWriteOp};
+
+#[path = "../../src/seq_bench.rs"]
+mod seq_bench;
+use seq_bench::*;
+
+fn main() {
+    run::<NrStack<u64>>("vnr-stack", |write_ratio| {
+        generate_operations(NOP, write_ratio, NrStackReadOp::Peek, NrStackWriteOp::Push, NrStackWriteOp::Pop)
+    });
+}
@@ -0,0 +1,194 @@
+// Queue and Stack Benchmark Harness for verified NR
+// Adapted from https://github.com/vmware/node-replication/blob/master/node-replication/benches/stack/main.rs
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Defines the harness shared by the `vnr_queue` and the `vnr_stack` benchmarks, which only
+//! differ in the replicated data structure and its operations.
+#![allow(dead_code)]
+use std::fmt::Debug;
+use std::marker::Sync;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use logging::warn;
+use rand::seq::SliceRandom;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use bench_utils::benchmark::*;
+use bench_utils::mkbench::{self, DsInterface};
+use bench_utils::topology::ThreadMapping;
+use bench_utils::Operation;
+use verified_node_replication::{Dispatch, AffinityFn, NodeReplicated, NrConfig, ReplicaId, ThreadToken, NodeReplicatedT};
+
+use builtin::Tracked;
+
+// Number of operation for test-harness.
+#[cfg(feature = "smokebench")]
+pub const NOP: usize = 2_500_000;
+#[cfg(not(feature = "smokebench"))]
+pub const NOP: usize = 25_000_000;
+
+pub struct VNRWrapper<D: Dispatch + Sync> {
+    val: NodeReplicated<D>,
+}
+
+/// The interface a data-structure must implement to be benchmarked by
+/// `ScaleBench`.
+impl<D> DsInterface for VNRWrapper<D>
+where
+    D: Dispatch + Default + Sync + Send + 'static,
+{
+    type D = D;
+    type TT = ThreadToken<D>;
+
+    /// Allocate a new data-structure.
+    ///
+    /// - `replicas`: How many replicas the data-structure should maintain.
+    /// - `logs`: How many logs the data-structure should be partitioned over.
+    fn new(replicas: NonZeroUsize, logs: NonZeroUsize, log_size: usize) -> Self {
+        VNRWrapper {
+            val: NodeReplicated::with_config(
+                NrConfig::new(replicas.into()).log_size(mkbench::log_entries::<D>(log_size)),
+                AffinityFn::new(mkbench::chg_affinity),
+            ),
+        }
+    }
+
+    /// Register a thread with a data-structure.
+    ///
+    /// - `rid` indicates which replica the thread should use.
+    fn register(&mut self, rid: ReplicaId) -> Option<ThreadToken<Self::D>> {
+        NodeReplicatedT::<D>::register(&mut self.val, rid)
+    }
+
+    /// Apply a mutable operation to the data-structure.
+    fn execute_mut(
+        &self,
+        op: <Self::D as Dispatch>::WriteOperation,
+        idx: ThreadToken<Self::D>,
+    ) -> Result<(<Self::D as Dispatch>::Response, ThreadToken<Self::D>), ThreadToken<Self::D>> {
+        match NodeReplicatedT::execute_mut(&self.val, op, idx, Tracked::assume_new()) {
+            Ok((res, tkn, _)) => Ok((res, tkn)),
+            Err((tkn, _, _)) => Err(tkn),
+        }
+    }
+
+    /// Apply a immutable operation to the data-structure.
+    fn execute(
+        &self,
+        op: <Self::D as Dispatch>::ReadOperation,
+        idx: ThreadToken<Self::D>,
+    ) -> Result<(<Self::D as Dispatch>::Response, ThreadToken<Self::D>), ThreadToken<Self::D>> {
+        match NodeReplicatedT::execute(&self.val, op, idx, Tracked::assume_new()) {
+            Ok((res, tkn, _)) => Ok((res, tkn)),
+            Err((tkn, _, _)) => Err(tkn),
+        }
+    }
+}
+
+/// Generate a random sequence of operations
+///
+/// # Arguments
+///  - `nop`: Number of operations to generate
+///  - `write_ratio`: percentage of update operations, half of them Push, half of them Pop
+///  - `peek`, `push`, `pop`: the operations of the data structure
+pub fn generate_operations<OpRd: Copy, OpWr: Copy>(
+    nop: usize,
+    write_ratio: usize,
+    peek: OpRd,
+    push: impl Fn(u64) -> OpWr,
+    pop: OpWr,
+) -> Vec<Operation<OpRd, OpWr>> {
+    let mut ops = Vec::with_capacity(nop);
+
+    let mut rng = ChaCha8Rng::seed_from_u64(42);
+
+    for idx in 0..nop {
+        if idx % 100 < write_ratio {
+            if idx % 2 == 0 {
+                ops.push(Operation::WriteOperation(push(rng.gen())));
+            } else {
+                ops.push(Operation::WriteOperation(pop));
+            }
+        } else {
+            ops.push(Operation::ReadOperation(peek));
+        }
+    }
+
+    ops.shuffle(&mut rng);
+    ops
+}
+
+/// Compare scale-out behaviour of the data structure.
+fn scale_out<R>(
+    c: &mut TestHarness,
+    name: &str,
+    write_ratio: usize,
+    ops: Vec<Operation<<R::D as Dispatch>::ReadOperation, <R::D as Dispatch>::WriteOperation>>,
+) where
+    R: DsInterface + Send + Sync + 'static,
+    R::D: Send,
+    <R::D as Dispatch>::WriteOperation: Send + Sync + Copy + PartialEq,
+    <R::D as Dispatch>::ReadOperation: Send + Sync + Copy,
+    <R::D as Dispatch>::Response: Sync + Send + Debug,
+{
+    let bench_name = format!("{}-scaleout-wr{}", name, write_ratio);
+
+    mkbench::ScaleBenchBuilder::<R>::new(ops)
+        .thread_defaults()
+        .update_batch(32)
+        .log_size(2 * 1024 * 1024)
+        .replica_strategy(mkbench::ReplicaStrategy::Socket)
+        .thread_mapping(ThreadMapping::Interleave)
+        .log_strategy(mkbench::LogStrategy::One)
+        .configure(
+            c,
+            &bench_name,
+            |_cid, tkn, replica, op, _batch_size| match op {
+                Operation::ReadOperation(op) => match replica.execute(*op, tkn) {
+                    Ok(r) => r.1,
+                    Err(r) => r,
+                },
+                Operation::WriteOperation(op) => match replica.execute_mut(*op, tkn) {
+                    Ok(r) => r.1,
+                    Err(r) => r,
+                },
+            },
+        );
+}
+
+/// Runs the scale-out benchmark of the data structure for the configured write ratios.
+///
+/// - `name`: the name of the benchmark
+/// - `ops`: generates the operations for a given write ratio
+pub fn run<D>(
+    name: &str,
+    ops: impl Fn(usize) -> Vec<Operation<D::ReadOperation, D::WriteOperation>>,
+) where
+    D: Dispatch + Default + Sync + Send + 'static,
+    D::WriteOperation: Send + Sync + Copy + PartialEq,
+    D::ReadOperation: Send + Sync + Copy,
+    D::Response: Sync + Send + Debug,
+{
+    let _r = env_logger::try_init();
+    if cfg!(feature = "smokebench") {
+        warn!("Running with feature 'smokebench' may not get the desired results");
+    }
+
+    bench_utils::disable_dvfs();
+
+    let mut harness = TestHarness::new(Duration::from_secs(10));
+
+    let write_ratios = if cfg!(feature = "exhaustive") {
+        vec![0, 10, 20, 40, 60, 80, 100]
+    } else if cfg!(feature = "smokebench") {
+        vec![10]
+    } else {
+        vec![0, 10, 50, 100]
+    };
+
+    for write_ratio in write_ratios.into_iter() {
+        scale_out::<VNRWrapper<D>>(&mut harness, name, write_ratio, ops(write_ratio));
+    }
+}
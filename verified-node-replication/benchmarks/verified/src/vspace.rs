@@ -0,0 +1,1022 @@
+// Virtual Address Space for the Verified NR Benchmarks
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Defines a virtual address space that can be replicated. It is shared by the `vspace`
+//! binary and the `vnr_vspace` benchmark.
+#![allow(dead_code)]
+
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+
+use rand::{Rng};
+use rand_chacha::ChaCha8Rng;
+use rand::SeedableRng;
+
+use bench_utils::mkbench::{self, DsInterface};
+use bench_utils::Operation;
+use verified_node_replication::{ConcurrentDispatch, Dispatch, Fallible, TryDispatch, AffinityFn, LogMapper, MultiLogNodeReplicated, MultiLogThreadToken, NrConfig, ReplicaId, MultiLogNodeReplicatedT};
+
+use builtin::{nat, Tracked};
+use vstd::map::Map;
+
+use std::fmt;
+use std::mem::transmute;
+use std::pin::Pin;
+
+use logging::{debug, trace};
+use x86::bits64::paging::*;
+
+const VSPACE_RANGE: u64 = 512*1024*1024*1024;
+
+
+fn kernel_vaddr_to_paddr(v: VAddr) -> PAddr {
+    let vaddr_val: usize = v.into();
+    PAddr::from(vaddr_val as u64 - 0x0)
+}
+
+fn paddr_to_kernel_vaddr(p: PAddr) -> VAddr {
+    let paddr_val: u64 = p.into();
+    VAddr::from((paddr_val + 0x0) as usize)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VSpaceError {
+    pub at: u64,
+}
+
+/// Type of resource we're trying to allocate
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ResourceType {
+    /// ELF Binary data
+    Binary,
+    /// Physical memory
+    Memory,
+    /// Page-table meta-data
+    PageTable,
+}
+
+/// Mapping rights to give to address translation.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[allow(unused)]
+pub enum MapAction {
+    /// Don't map
+    None,
+    /// Map region read-only.
+    ReadUser,
+    /// Map region read-only for kernel.
+    ReadKernel,
+    /// Map region read-write.
+    ReadWriteUser,
+    /// Map region read-write for kernel.
+    ReadWriteKernel,
+    /// Map region read-executable.
+    ReadExecuteUser,
+    /// Map region read-executable for kernel.
+    ReadExecuteKernel,
+    /// Map region read-write-executable.
+    ReadWriteExecuteUser,
+    /// Map region read-write-executable for kernel.
+    ReadWriteExecuteKernel,
+}
+
+impl MapAction {
+    /// Transform MapAction into rights for 1 GiB page.
+    fn to_pdpt_rights(&self) -> PDPTFlags {
+        use MapAction::*;
+        match self {
+            None => PDPTFlags::empty(),
+            ReadUser => PDPTFlags::XD,
+            ReadKernel => PDPTFlags::US | PDPTFlags::XD,
+            ReadWriteUser => PDPTFlags::RW | PDPTFlags::XD,
+            ReadWriteKernel => PDPTFlags::RW | PDPTFlags::US | PDPTFlags::XD,
+            ReadExecuteUser => PDPTFlags::empty(),
+            ReadExecuteKernel => PDPTFlags::US,
+            ReadWriteExecuteUser => PDPTFlags::RW,
+            ReadWriteExecuteKernel => PDPTFlags::RW | PDPTFlags::US,
+        }
+    }
+
+    /// Transform MapAction into rights for 2 MiB page.
+    fn to_pd_rights(&self) -> PDFlags {
+        use MapAction::*;
+        match self {
+            None => PDFlags::empty(),
+            ReadUser => PDFlags::XD,
+            ReadKernel => PDFlags::US | PDFlags::XD,
+            ReadWriteUser => PDFlags::RW | PDFlags::XD,
+            ReadWriteKernel => PDFlags::RW | PDFlags::US | PDFlags::XD,
+            ReadExecuteUser => PDFlags::empty(),
+            ReadExecuteKernel => PDFlags::US,
+            ReadWriteExecuteUser => PDFlags::RW,
+            ReadWriteExecuteKernel => PDFlags::RW | PDFlags::US,
+        }
+    }
+
+    /// Transform MapAction into rights for 4KiB page.
+    fn to_pt_rights(&self) -> PTFlags {
+        use MapAction::*;
+        match self {
+            None => PTFlags::empty(),
+            ReadUser => PTFlags::XD,
+            ReadKernel => PTFlags::US | PTFlags::XD,
+            ReadWriteUser => PTFlags::RW | PTFlags::XD,
+            ReadWriteKernel => PTFlags::RW | PTFlags::US | PTFlags::XD,
+            ReadExecuteUser => PTFlags::empty(),
+            ReadExecuteKernel => PTFlags::US,
+            ReadWriteExecuteUser => PTFlags::RW,
+            ReadWriteExecuteKernel => PTFlags::RW | PTFlags::US,
+        }
+    }
+}
+
+impl MapAction {
+    /// Transform the rights of a present mapping back into a MapAction.
+    fn from_rights(writable: bool, kernel: bool, no_execute: bool) -> MapAction {
+        use MapAction::*;
+        match (writable, kernel, no_execute) {
+            (false, false, true) => ReadUser,
+            (false, true, true) => ReadKernel,
+            (true, false, true) => ReadWriteUser,
+            (true, true, true) => ReadWriteKernel,
+            (false, false, false) => ReadExecuteUser,
+            (false, true, false) => ReadExecuteKernel,
+            (true, false, false) => ReadWriteExecuteUser,
+            (true, true, false) => ReadWriteExecuteKernel,
+        }
+    }
+
+    /// Transform the rights of a 1 GiB page into a MapAction.
+    fn from_pdpt_rights(flags: PDPTFlags) -> MapAction {
+        MapAction::from_rights(
+            flags.contains(PDPTFlags::RW),
+            flags.contains(PDPTFlags::US),
+            flags.contains(PDPTFlags::XD),
+        )
+    }
+
+    /// Transform the rights of a 2 MiB page into a MapAction.
+    fn from_pd_rights(flags: PDFlags) -> MapAction {
+        MapAction::from_rights(
+            flags.contains(PDFlags::RW),
+            flags.contains(PDFlags::US),
+            flags.contains(PDFlags::XD),
+        )
+    }
+
+    /// Transform the rights of a 4 KiB page into a MapAction.
+    fn from_pt_rights(flags: PTFlags) -> MapAction {
+        MapAction::from_rights(
+            flags.contains(PTFlags::RW),
+            flags.contains(PTFlags::US),
+            flags.contains(PTFlags::XD),
+        )
+    }
+}
+
+impl fmt::Display for MapAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use MapAction::*;
+        match self {
+            None => write!(f, " ---"),
+            ReadUser => write!(f, "uR--"),
+            ReadKernel => write!(f, "kR--"),
+            ReadWriteUser => write!(f, "uRW-"),
+            ReadWriteKernel => write!(f, "kRW-"),
+            ReadExecuteUser => write!(f, "uR-X"),
+            ReadExecuteKernel => write!(f, "kR-X"),
+            ReadWriteExecuteUser => write!(f, "uRWX"),
+            ReadWriteExecuteKernel => write!(f, "kRWX"),
+        }
+    }
+}
+
+
+pub struct VSpace {
+    pub pml4: Pin<Box<PML4>>,
+    pub mem_counter: usize,
+    mapping: mmap::MemoryMap,
+    mem_ptr: *mut u8,
+}
+
+unsafe impl Sync for VSpace {}
+unsafe impl Send for VSpace {}
+
+/// A leaf entry of the page table, accessed through a raw pointer as the directories above it
+/// are shared by all partitions of the address space.
+#[derive(Clone, Copy)]
+enum LeafPtr {
+    /// A 1 GiB mapping
+    Huge(*mut PDPTEntry),
+    /// A 2 MiB mapping
+    Large(*mut PDEntry),
+    /// A 4 KiB entry, which may not be present
+    Base(*mut PTEntry),
+}
+
+/// The mutable operations on the address space.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Modify {
+   /// Maps a 4 KiB frame at the virtual address
+   Map(u64, u64),
+   /// Removes the mapping of the virtual address
+   Unmap(u64),
+   /// Changes the rights of the mapping of the virtual address
+   Protect(u64, MapAction),
+}
+
+/// The immutable operations on the address space.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Access {
+   /// Translates the virtual address to its physical address
+   Resolve(u64),
+   /// Obtains the physical address and the rights of the virtual address
+   Identify(u64),
+}
+
+/// The result of a successful operation on the address space.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VSpaceResult {
+   /// The physical address of `Resolve`
+   Resolved(u64),
+   /// The physical address and the rights of `Identify`
+   Identified(u64, MapAction),
+   /// A `Map`, `Unmap`, or `Protect` succeeded
+   Done,
+}
+
+/// The TryDispatch traits executes `ReadOperation` (our Access enum)
+/// and `WriteOperation` (our Modify enum) against the replicated
+/// data-structure. It is replicated as a [`Fallible`], an operation that
+/// fails returns the `VSpaceError`.
+impl TryDispatch for VSpace {
+   type ReadOperation = Access;
+   type WriteOperation = Modify;
+   type Response = VSpaceResult;
+   type Error = VSpaceError;
+   type View = VSpace;
+
+   fn init() -> Self {
+        Default::default()
+    }
+
+
+   /// The `try_dispatch` function applies the immutable operations.
+   fn try_dispatch(&self, op: Self::ReadOperation) -> Result<Self::Response, Self::Error> {
+       match op {
+           Access::Resolve(key) => Ok(VSpaceResult::Resolved(self.resolve_wrapped(key))),
+           Access::Identify(key) => self
+               .identify(VAddr::from(key))
+               .map(|(paddr, rights)| VSpaceResult::Identified(paddr.as_u64(), rights))
+               .ok_or(VSpaceError { at: key }),
+       }
+   }
+
+   /// The `try_dispatch_mut` function applies the mutable operations.
+   fn try_dispatch_mut(
+       &mut self,
+       op: Self::WriteOperation,
+   ) -> Result<Self::Response, Self::Error> {
+       match op {
+           Modify::Map(key, value) => self.map_generic_wrapped(key, value, 0x1000).map(|_| VSpaceResult::Done),
+           Modify::Unmap(key) => self.unmap(VAddr::from(key)).map(|_| VSpaceResult::Done),
+           Modify::Protect(key, rights) => self.protect(VAddr::from(key), rights).map(|_| VSpaceResult::Done),
+       }
+   }
+
+    // partial eq also add an exec operation
+    fn clone_write_op(op: &Self::WriteOperation) -> Self::WriteOperation {
+        op.clone()
+    }
+
+    fn clone_response(op: &Self::Response) -> Self::Response {
+        op.clone()
+    }
+
+    fn clone_error(err: &Self::Error) -> Self::Error {
+        err.clone()
+    }
+
+}
+
+/// The partitions of the address space are its 1 GiB regions, assigned to the logs by the
+/// [`LogMapper`] below. The page tables are allocated up front, hence an operation only changes
+/// the leaf entries of its region, which are disjoint from the ones of the other partitions.
+/// The logs share one copy of the address space and apply their operations concurrently.
+impl ConcurrentDispatch for VSpace {
+    type ReadOperation = Access;
+    type WriteOperation = Modify;
+    type Response = <Fallible<VSpace> as Dispatch>::Response;
+    type View = VSpace;
+    type Partition = ();
+
+    fn init(_num_partitions: usize) -> (Self, Tracked<Map<nat, ()>>) {
+        (Default::default(), Tracked::assume_new())
+    }
+
+    fn clone_write_op(op: &Modify) -> Modify {
+        op.clone()
+    }
+
+    fn clone_response(op: &Result<VSpaceResult, VSpaceError>) -> Result<VSpaceResult, VSpaceError> {
+        <Fallible<VSpace> as Dispatch>::clone_response(op)
+    }
+
+    fn dispatch(&self, op: Access, _part: Tracked<&()>) -> Result<VSpaceResult, VSpaceError> {
+        match op {
+            Access::Resolve(key) => Ok(VSpaceResult::Resolved(
+                self.identify(VAddr::from(key)).map(|(paddr, _)| paddr.as_u64()).unwrap_or(0x0),
+            )),
+            Access::Identify(key) => self
+                .identify(VAddr::from(key))
+                .map(|(paddr, rights)| VSpaceResult::Identified(paddr.as_u64(), rights))
+                .ok_or(VSpaceError { at: key }),
+        }
+    }
+
+    fn dispatch_mut(&self, op: Modify, _part: Tracked<&mut ()>) -> Result<VSpaceResult, VSpaceError> {
+        match op {
+            Modify::Map(key, value) => self
+                .map_shared(VAddr::from(key), PAddr::from(value), MapAction::ReadWriteExecuteUser)
+                .map(|_| VSpaceResult::Done),
+            Modify::Unmap(key) => self.unmap_shared(VAddr::from(key)).map(|_| VSpaceResult::Done),
+            Modify::Protect(key, rights) => self
+                .protect_shared(VAddr::from(key), rights)
+                .map(|_| VSpaceResult::Done),
+        }
+    }
+}
+
+/// Partitions the address space over the logs at a 1 GiB granularity. Operations on
+/// different 1 GiB regions touch disjoint parts of the page table and therefore commute.
+impl LogMapper for VSpace {
+    fn write_op_log_idx(op: &Modify, nlogs: usize) -> usize {
+        match op {
+            Modify::Map(vaddr, _) => ((*vaddr as usize) / ONE_GIB) % nlogs,
+            Modify::Unmap(vaddr) => ((*vaddr as usize) / ONE_GIB) % nlogs,
+            Modify::Protect(vaddr, _) => ((*vaddr as usize) / ONE_GIB) % nlogs,
+        }
+    }
+
+    fn read_op_log_idx(op: &Access, nlogs: usize) -> usize {
+        match op {
+            Access::Resolve(vaddr) => ((*vaddr as usize) / ONE_GIB) % nlogs,
+            Access::Identify(vaddr) => ((*vaddr as usize) / ONE_GIB) % nlogs,
+        }
+    }
+}
+
+
+
+pub struct VNRWrapper {
+    val: MultiLogNodeReplicated<VSpace>,
+}
+
+/// The interface a data-structure must implement to be benchmarked by
+/// `ScaleBench`.
+impl DsInterface for VNRWrapper {
+    type D = Fallible<VSpace>;
+    type TT = MultiLogThreadToken<VSpace>;
+
+    /// Allocate a new data-structure.
+    ///
+    /// - `replicas`: How many replicas the data-structure should maintain.
+    /// - `logs`: How many logs the data-structure should be partitioned over.
+    fn new(replicas: NonZeroUsize, logs: NonZeroUsize, log_size: usize) -> Self {
+        VNRWrapper {
+            val: MultiLogNodeReplicated::with_config(
+                logs.into(),
+                NrConfig::new(replicas.into()).log_size(mkbench::log_entries::<Self::D>(log_size)),
+                AffinityFn::new(mkbench::chg_affinity),
+            ),
+        }
+    }
+
+    /// Register a thread with a data-structure.
+    ///
+    /// - `rid` indicates which replica the thread should use.
+    fn register(&mut self, rid: ReplicaId) -> Option<Self::TT> {
+        MultiLogNodeReplicatedT::<VSpace>::register(&mut self.val, rid)
+    }
+
+    /// Apply a mutable operation to the data-structure.
+    fn execute_mut(
+        &self,
+        op: <Self::D as Dispatch>::WriteOperation,
+        idx: Self::TT,
+    ) -> Result<(<Self::D as Dispatch>::Response, Self::TT), Self::TT> {
+        match MultiLogNodeReplicatedT::execute_mut(&self.val, op, idx, Tracked::assume_new()) {
+            Ok((res, tkn, _)) => Ok((res, tkn)),
+            Err((tkn, _, _)) => Err(tkn),
+        }
+    }
+
+    /// Apply a immutable operation to the data-structure.
+    fn execute(
+        &self,
+        op: <Self::D as Dispatch>::ReadOperation,
+        idx: Self::TT,
+    ) -> Result<(<Self::D as Dispatch>::Response, Self::TT), Self::TT> {
+        match MultiLogNodeReplicatedT::execute(&self.val, op, idx, Tracked::assume_new()) {
+            Ok((res, tkn, _)) => Ok((res, tkn)),
+            Err((tkn, _, _)) => Err(tkn),
+        }
+    }
+}
+
+
+pub const TWO_MIB: usize = 2 * 1024 * 1024;
+pub const ONE_GIB: usize = 1024 * 1024 * 1024;
+
+// sudo sh -c "echo 16 > /sys/devices/system/node/node0/hugepages/hugepages-1048576kB/nr_hugepages"
+// sudo sh -c "echo 16 > /sys/devices/system/node/node1/hugepages/hugepages-1048576kB/nr_hugepages"
+// sudo sh -c "echo 16 > /sys/devices/system/node/node2/hugepages/hugepages-1048576kB/nr_hugepages"
+// sudo sh -c "echo 16 > /sys/devices/system/node/node3/hugepages/hugepages-1048576kB/nr_hugepages"
+
+/// Maps `size` bytes of memory backed by pages of size `ps`, or returns a `VSpaceError` if the
+/// memory can't be mapped, e.g., because there are not enough reserved huge-pages.
+pub fn alloc(size: usize, ps: usize) -> Result<mmap::MemoryMap, VSpaceError> {
+    use libc::{MAP_ANON, MAP_HUGETLB, MAP_POPULATE, MAP_SHARED};
+
+    const MAP_HUGE_SHIFT: usize = 26;
+    const MAP_HUGE_2MB: i32 = 21 << MAP_HUGE_SHIFT;
+    const MAP_HUGE_1GB: i32 = 30 << MAP_HUGE_SHIFT;
+
+    pub const FOUR_KIB: usize = 4 * 1024;
+    const PAGESIZE: u64 = FOUR_KIB as u64;
+
+
+    assert!(size % FOUR_KIB == 0|| size % TWO_MIB ==0 || size % ONE_GIB ==0);
+
+    let mut non_standard_flags = MAP_SHARED | MAP_ANON | MAP_POPULATE;
+    match ps {
+        TWO_MIB => non_standard_flags |= MAP_HUGETLB | MAP_HUGE_2MB,
+        ONE_GIB => non_standard_flags |= MAP_HUGETLB | MAP_HUGE_1GB,
+        _ => (),
+    }
+
+    let flags = [
+        mmap::MapOption::MapNonStandardFlags(non_standard_flags),
+        mmap::MapOption::MapReadable,
+        mmap::MapOption::MapWritable,
+    ];
+    let res = mmap::MemoryMap::new(size, &flags).map_err(|_| VSpaceError { at: 0 })?;
+    if res.data().is_null() {
+        return Err(VSpaceError { at: 0 });
+    }
+
+    Ok(res)
+}
+
+impl Default for VSpace {
+    fn default() -> VSpace {
+        VSpace::new().expect("can't get memory, do we have reserved huge-pages?")
+    }
+}
+
+impl VSpace {
+    /// Creates an address space that identity maps the first `VSPACE_RANGE` bytes.
+    pub fn new() -> Result<VSpace, VSpaceError> {
+        let mapping = alloc(3*ONE_GIB, ONE_GIB)?;
+        let mem_ptr = mapping.data();
+
+        // make sure the memory for ptable is some contiguous block
+        // this allows Linux / THP to kick in and increase tput by ~60Mops
+        // make sure to do:
+        // sudo sh -c "echo always > /sys/kernel/mm/transparent_hugepage/enabled"
+
+        let mut vs = VSpace {
+            pml4: Box::pin(
+                [PML4Entry::new(PAddr::from(0x0u64), PML4Flags::empty()); PAGE_SIZE_ENTRIES],
+            ),
+            mapping,
+            mem_counter: 4096,
+            mem_ptr,
+        };
+        for i in 0..VSPACE_RANGE / 4096 {
+            vs.map_generic(
+                VAddr::from(i * 4096),
+                (PAddr::from(i * 4096), 4096),
+                MapAction::ReadWriteExecuteUser,
+            )?;
+        }
+
+        logging::error!("vs.mem_counter {}", vs.mem_counter);
+
+        Ok(vs)
+    }
+
+    pub fn map_generic_wrapped(
+        self: &mut VSpace,
+        vbase: u64,
+        pregion: u64,
+        pregion_len: usize,
+    ) -> Result<(), VSpaceError> {
+        let rights = MapAction::ReadWriteExecuteUser;
+        self.map_generic(
+            VAddr::from(vbase),
+            (PAddr::from(pregion), pregion_len),
+            rights,
+        )
+    }
+
+    pub fn map_generic(
+        &mut self,
+        vbase: VAddr,
+        pregion: (PAddr, usize),
+        rights: MapAction,
+    ) -> Result<(), VSpaceError> {
+        let (pbase, psize) = pregion;
+        assert_eq!(pbase % BASE_PAGE_SIZE, 0);
+        assert_eq!(psize % BASE_PAGE_SIZE, 0);
+        assert_eq!(vbase % BASE_PAGE_SIZE, 0);
+        assert_ne!(rights, MapAction::None);
+
+        debug!(
+            "map_generic {:#x} -- {:#x} -> {:#x} -- {:#x} {}",
+            vbase,
+            vbase + psize,
+            pbase,
+            pbase + psize,
+            rights
+        );
+
+        let pml4_idx = pml4_index(vbase);
+        if !self.pml4[pml4_idx].is_present() {
+            trace!("New PDPDT for {:?} @ PML4[{}]", vbase, pml4_idx);
+            self.pml4[pml4_idx] = self.new_pdpt();
+        }
+        assert!(
+            self.pml4[pml4_idx].is_present(),
+            "The PML4 slot we need was not allocated?"
+        );
+
+        let pdpt = self.get_pdpt(self.pml4[pml4_idx]);
+        let mut pdpt_idx = pdpt_index(vbase);
+        // TODO: if we support None mappings, this is if not good enough:
+        if !pdpt[pdpt_idx].is_present() {
+            // The virtual address corresponding to our position within the page-table
+            let vaddr_pos: usize = PML4_SLOT_SIZE * pml4_idx + HUGE_PAGE_SIZE * pdpt_idx;
+
+            // In case we can map something at a 1 GiB granularity and
+            // we still have at least 1 GiB to map, create huge-page mappings
+            if vbase.as_usize() == vaddr_pos
+                && (pbase % HUGE_PAGE_SIZE == 0)
+                && psize >= HUGE_PAGE_SIZE
+            {
+                // To track how much space we've covered
+                let mut mapped = 0;
+
+                // Add entries to PDPT as long as we're within this allocated PDPT table
+                // and have 1 GiB chunks to map:
+                while mapped < psize && ((psize - mapped) >= HUGE_PAGE_SIZE) && pdpt_idx < 512 {
+                    assert!(!pdpt[pdpt_idx].is_present());
+                    pdpt[pdpt_idx] = PDPTEntry::new(
+                        pbase + mapped,
+                        PDPTFlags::P | PDPTFlags::PS | rights.to_pdpt_rights(),
+                    );
+                    trace!(
+                        "Mapped 1GiB range {:#x} -- {:#x} -> {:#x} -- {:#x}",
+                        vbase + mapped,
+                        (vbase + mapped) + HUGE_PAGE_SIZE,
+                        pbase + mapped,
+                        (vbase + mapped) + HUGE_PAGE_SIZE
+                    );
+
+                    pdpt_idx += 1;
+                    mapped += HUGE_PAGE_SIZE;
+                }
+
+                if mapped < psize {
+                    trace!(
+                        "map_generic recurse from 1 GiB map to finish {:#x} -- {:#x} -> {:#x} -- {:#x}",
+                        vbase + mapped,
+                        vbase + (psize - mapped),
+                        (pbase + mapped),
+                        pbase + (psize - mapped),
+                    );
+                    return self.map_generic(
+                        vbase + mapped,
+                        ((pbase + mapped), psize - mapped),
+                        rights,
+                    );
+                } else {
+                    // Everything fit in 1 GiB ranges,
+                    // We're done with mappings
+                    return Ok(());
+                }
+            } else {
+                trace!(
+                    "Mapping 0x{:x} -- 0x{:x} is smaller than 1 GiB, going deeper.",
+                    vbase,
+                    vbase + psize
+                );
+                pdpt[pdpt_idx] = self.new_pd();
+            }
+        }
+        assert!(
+            pdpt[pdpt_idx].is_present(),
+            "The PDPT entry we're relying on is not allocated?"
+        );
+        if pdpt[pdpt_idx].is_page() {
+            // "An existing mapping already covers the 1 GiB range we're trying to map in?
+            return Err(VSpaceError { at: vbase.as_u64() });
+        }
+
+        let pd = self.get_pd(pdpt[pdpt_idx]);
+        let mut pd_idx = pd_index(vbase);
+        if !pd[pd_idx].is_present() {
+            let vaddr_pos: usize =
+                PML4_SLOT_SIZE * pml4_idx + HUGE_PAGE_SIZE * pdpt_idx + LARGE_PAGE_SIZE * pd_idx;
+
+            // In case we can map something at a 2 MiB granularity and
+            // we still have at least 2 MiB to map create large-page mappings
+            if vbase.as_usize() == vaddr_pos
+                && (pbase % LARGE_PAGE_SIZE == 0)
+                && psize >= LARGE_PAGE_SIZE
+            {
+                let mut mapped = 0;
+                // Add entries as long as we are within this allocated PDPT table
+                // and have at least 2 MiB things to map
+                while mapped < psize && ((psize - mapped) >= LARGE_PAGE_SIZE) && pd_idx < 512 {
+                    if pd[pd_idx].is_present() {
+                        trace!("Already mapped pd at {:#x}", pbase + mapped);
+                        return Err(VSpaceError { at: vbase.as_u64() });
+                    }
+
+                    pd[pd_idx] = PDEntry::new(
+                        pbase + mapped,
+                        PDFlags::P | PDFlags::PS | rights.to_pd_rights(),
+                    );
+                    trace!(
+                        "Mapped 2 MiB region {:#x} -- {:#x} -> {:#x} -- {:#x}",
+                        vbase + mapped,
+                        (vbase + mapped) + LARGE_PAGE_SIZE,
+                        pbase + mapped,
+                        (pbase + mapped) + LARGE_PAGE_SIZE
+                    );
+
+                    pd_idx += 1;
+                    mapped += LARGE_PAGE_SIZE;
+                }
+
+                if mapped < psize {
+                    trace!(
+                        "map_generic recurse from 2 MiB map to finish {:#x} -- {:#x} -> {:#x} -- {:#x}",
+                        vbase + mapped,
+                        vbase + (psize - mapped),
+                        (pbase + mapped),
+                        pbase + (psize - mapped),
+                    );
+                    return self.map_generic(
+                        vbase + mapped,
+                        ((pbase + mapped), psize - mapped),
+                        rights,
+                    );
+                } else {
+                    // Everything fit in 2 MiB ranges,
+                    // We're done with mappings
+                    return Ok(());
+                }
+            } else {
+                trace!(
+                    "Mapping 0x{:x} -- 0x{:x} is smaller than 2 MiB, going deeper.",
+                    vbase,
+                    vbase + psize
+                );
+                pd[pd_idx] = self.new_pt();
+            }
+        }
+        assert!(
+            pd[pd_idx].is_present(),
+            "The PD entry we're relying on is not allocated?"
+        );
+        if pd[pd_idx].is_page() {
+            // An existing mapping already covers the 2 MiB range we're trying to map in?
+            return Err(VSpaceError { at: vbase.as_u64() });
+        }
+
+        let pt = self.get_pt(pd[pd_idx]);
+        let mut pt_idx = pt_index(vbase);
+        let mut mapped: usize = 0;
+        while mapped < psize && pt_idx < 512 {
+            // existing 4 KiB mappings are overwritten
+            pt[pt_idx] = PTEntry::new(pbase + mapped, PTFlags::P | rights.to_pt_rights());
+
+            mapped += BASE_PAGE_SIZE;
+            pt_idx += 1;
+        }
+
+        // Need go to different PD/PDPT/PML4 slot
+        if mapped < psize {
+            trace!(
+                "map_generic recurse from 4 KiB map to finish {:#x} -- {:#x} -> {:#x} -- {:#x}",
+                vbase + mapped,
+                vbase + (psize - mapped),
+                (pbase + mapped),
+                pbase + (psize - mapped),
+            );
+            return self.map_generic(vbase + mapped, ((pbase + mapped), psize - mapped), rights);
+        } else {
+            // else we're done here, return
+            Ok(())
+        }
+    }
+
+    /// A simple wrapper function for allocating just one page.
+    fn allocate_one_page(&mut self) -> PAddr {
+        logging::info!("allocate a page...");
+        self.mem_counter += 4096;
+        self.allocate_pages(1, ResourceType::PageTable)
+    }
+
+    fn allocate_pages(&mut self, how_many: usize, _typ: ResourceType) -> PAddr {
+        logging::info!("allocate_pages {}...", how_many);
+
+        let new_region: *mut u8 = unsafe {
+            assert!(self.mem_counter < 3*ONE_GIB); // if this triggers you need to adjust the alloc size of `mem_ptr`
+            self.mem_ptr.offset(self.mem_counter as isize)
+        };
+        self.mem_counter += how_many * 4096;
+
+        assert!(!new_region.is_null());
+        for i in 0..how_many * BASE_PAGE_SIZE {
+            unsafe {
+                *new_region.offset(i as isize) = 0u8;
+            }
+        }
+
+        kernel_vaddr_to_paddr(VAddr::from(new_region as usize))
+    }
+
+    fn new_pt(&mut self) -> PDEntry {
+        let paddr: PAddr = self.allocate_one_page();
+        return PDEntry::new(paddr, PDFlags::P | PDFlags::RW | PDFlags::US);
+    }
+
+    fn new_pd(&mut self) -> PDPTEntry {
+        let paddr: PAddr = self.allocate_one_page();
+        return PDPTEntry::new(paddr, PDPTFlags::P | PDPTFlags::RW | PDPTFlags::US);
+    }
+
+    fn new_pdpt(&mut self) -> PML4Entry {
+        let paddr: PAddr = self.allocate_one_page();
+        return PML4Entry::new(paddr, PML4Flags::P | PML4Flags::RW | PML4Flags::US);
+    }
+
+    /// Resolve a PDEntry to a page table.
+    fn get_pt<'b>(&mut self, entry: PDEntry) -> &'b mut PT {
+        unsafe { transmute::<VAddr, &mut PT>(paddr_to_kernel_vaddr(entry.address())) }
+    }
+
+    /// Resolve a PDPTEntry to a page directory.
+    fn get_pd<'b>(&mut self, entry: PDPTEntry) -> &'b mut PD {
+        unsafe { transmute::<VAddr, &mut PD>(paddr_to_kernel_vaddr(entry.address())) }
+    }
+
+    /// Resolve a PML4Entry to a PDPT.
+    fn get_pdpt<'b>(&mut self, entry: PML4Entry) -> &'b mut PDPT {
+        unsafe { transmute::<VAddr, &mut PDPT>(paddr_to_kernel_vaddr(entry.address())) }
+    }
+
+    pub fn resolve_wrapped(&self, addr: u64) -> u64 {
+        let a = self.resolve_addr(VAddr::from(addr)).map(|pa| pa.as_u64()).unwrap_or(0x0);
+        a
+    }
+
+    pub fn resolve_addr(&self, addr: VAddr) -> Option<PAddr> {
+        self.identify(addr).map(|(paddr, _)| paddr)
+    }
+
+    /// Removes the mapping that contains `addr`, see [`VSpace::unmap_shared`].
+    pub fn unmap(&mut self, addr: VAddr) -> Result<(), VSpaceError> {
+        self.unmap_shared(addr)
+    }
+
+    /// Changes the rights of the mapping that contains `addr` to `rights`, see
+    /// [`VSpace::protect_shared`].
+    pub fn protect(&mut self, addr: VAddr, rights: MapAction) -> Result<(), VSpaceError> {
+        self.protect_shared(addr, rights)
+    }
+
+    /// Walks the page table to the entry that maps `addr` without creating references to the
+    /// tables on the way, they may be accessed by other partitions concurrently.
+    ///
+    /// Returns the 4 KiB entry even if it is not present, and None if a directory is missing.
+    fn entry_ptr(&self, addr: VAddr) -> Option<LeafPtr> {
+        let pml4_entry = self.pml4[pml4_index(addr)];
+        if !pml4_entry.is_present() {
+            return None;
+        }
+
+        let pdpt = paddr_to_kernel_vaddr(pml4_entry.address()).as_usize() as *mut PDPTEntry;
+        let pdpt_entry = unsafe { pdpt.add(pdpt_index(addr)) };
+        let pdpt_val = unsafe { pdpt_entry.read() };
+        if !pdpt_val.is_present() {
+            return None;
+        }
+        if pdpt_val.is_page() {
+            return Some(LeafPtr::Huge(pdpt_entry));
+        }
+
+        let pd = paddr_to_kernel_vaddr(pdpt_val.address()).as_usize() as *mut PDEntry;
+        let pd_entry = unsafe { pd.add(pd_index(addr)) };
+        let pd_val = unsafe { pd_entry.read() };
+        if !pd_val.is_present() {
+            return None;
+        }
+        if pd_val.is_page() {
+            return Some(LeafPtr::Large(pd_entry));
+        }
+
+        let pt = paddr_to_kernel_vaddr(pd_val.address()).as_usize() as *mut PTEntry;
+        Some(LeafPtr::Base(unsafe { pt.add(pt_index(addr)) }))
+    }
+
+    /// Walks the page table to the leaf entry that maps `addr`, if any, see [`VSpace::entry_ptr`].
+    fn leaf_ptr(&self, addr: VAddr) -> Option<LeafPtr> {
+        match self.entry_ptr(addr)? {
+            LeafPtr::Base(e) if !unsafe { e.read() }.is_present() => None,
+            leaf => Some(leaf),
+        }
+    }
+
+    /// Returns the physical address `addr` translates to and the rights of its mapping.
+    ///
+    /// Only reads the entries on the path to the mapping, the tables are not borrowed.
+    pub fn identify(&self, addr: VAddr) -> Option<(PAddr, MapAction)> {
+        match self.leaf_ptr(addr)? {
+            LeafPtr::Huge(e) => {
+                let e = unsafe { e.read() };
+                Some((e.address() + addr.huge_page_offset(), MapAction::from_pdpt_rights(e.flags())))
+            }
+            LeafPtr::Large(e) => {
+                let e = unsafe { e.read() };
+                Some((e.address() + addr.large_page_offset(), MapAction::from_pd_rights(e.flags())))
+            }
+            LeafPtr::Base(e) => {
+                let e = unsafe { e.read() };
+                Some((e.address() + addr.base_page_offset(), MapAction::from_pt_rights(e.flags())))
+            }
+        }
+    }
+
+    /// Maps the 4 KiB frame `paddr` at `vbase` by writing its leaf entry.
+    ///
+    /// The directories are shared by all partitions and are not allocated here, mapping an
+    /// address whose directories are missing fails.
+    pub fn map_shared(&self, vbase: VAddr, paddr: PAddr, rights: MapAction) -> Result<(), VSpaceError> {
+        assert_eq!(paddr % BASE_PAGE_SIZE, 0);
+        assert_eq!(vbase % BASE_PAGE_SIZE, 0);
+        assert_ne!(rights, MapAction::None);
+        trace!("map_shared {:#x} -> {:#x} {}", vbase, paddr, rights);
+        match self.entry_ptr(vbase) {
+            Some(LeafPtr::Base(e)) => unsafe {
+                e.write(PTEntry::new(paddr, PTFlags::P | rights.to_pt_rights()))
+            },
+            // an existing mapping already covers the address, or a directory is missing
+            _ => return Err(VSpaceError { at: vbase.as_u64() }),
+        }
+        Ok(())
+    }
+
+    /// Removes the mapping that contains `addr` by writing its leaf entry.
+    ///
+    /// The directories on the path to the mapping are not freed, a subsequent map can reuse them.
+    pub fn unmap_shared(&self, addr: VAddr) -> Result<(), VSpaceError> {
+        trace!("unmap_shared {:#x}", addr);
+        match self.leaf_ptr(addr) {
+            Some(LeafPtr::Huge(e)) => unsafe {
+                e.write(PDPTEntry::new(PAddr::from(0x0u64), PDPTFlags::empty()))
+            },
+            Some(LeafPtr::Large(e)) => unsafe {
+                e.write(PDEntry::new(PAddr::from(0x0u64), PDFlags::empty()))
+            },
+            Some(LeafPtr::Base(e)) => unsafe {
+                e.write(PTEntry::new(PAddr::from(0x0u64), PTFlags::empty()))
+            },
+            None => return Err(VSpaceError { at: addr.as_u64() }),
+        }
+        Ok(())
+    }
+
+    /// Changes the rights of the mapping that contains `addr` to `rights` by writing its leaf
+    /// entry.
+    pub fn protect_shared(&self, addr: VAddr, rights: MapAction) -> Result<(), VSpaceError> {
+        trace!("protect_shared {:#x} {}", addr, rights);
+        if rights == MapAction::None {
+            // use unmap to remove a mapping
+            return Err(VSpaceError { at: addr.as_u64() });
+        }
+        match self.leaf_ptr(addr) {
+            Some(LeafPtr::Huge(e)) => unsafe {
+                let old = e.read();
+                e.write(PDPTEntry::new(old.address(), PDPTFlags::P | PDPTFlags::PS | rights.to_pdpt_rights()))
+            },
+            Some(LeafPtr::Large(e)) => unsafe {
+                let old = e.read();
+                e.write(PDEntry::new(old.address(), PDFlags::P | PDFlags::PS | rights.to_pd_rights()))
+            },
+            Some(LeafPtr::Base(e)) => unsafe {
+                let old = e.read();
+                e.write(PTEntry::new(old.address(), PTFlags::P | rights.to_pt_rights()))
+            },
+            None => return Err(VSpaceError { at: addr.as_u64() }),
+        }
+        Ok(())
+    }
+
+    pub fn map_new(
+        &mut self,
+        base: VAddr,
+        size: usize,
+        rights: MapAction,
+        paddr: PAddr,
+    ) -> Result<(PAddr, usize), VSpaceError> {
+        assert_eq!(base % BASE_PAGE_SIZE, 0, "base is not page-aligned");
+        assert_eq!(size % BASE_PAGE_SIZE, 0, "size is not page-aligned");
+        self.map_generic(base, (paddr, size), rights)?;
+        Ok((paddr, size))
+    }
+}
+
+
+/// Percentage of the update operations that are unmaps
+pub const UNMAP_PCT: usize = 30;
+
+/// Percentage of the update operations that change the rights of a mapping
+pub const PROTECT_PCT: usize = 10;
+
+/// Percentage of the read operations that identify instead of resolve an address
+pub const IDENTIFY_PCT: usize = 50;
+
+/// The rights the protect operations choose from
+const PROTECT_RIGHTS: [MapAction; 4] = [
+    MapAction::ReadUser,
+    MapAction::ReadWriteUser,
+    MapAction::ReadExecuteUser,
+    MapAction::ReadWriteExecuteUser,
+];
+
+/// Picks a random address within `mask` that hasn't been unmapped
+fn mapped_addr(rng: &mut ChaCha8Rng, mask: u64, is_unmapped: &HashSet<u64>) -> u64 {
+    loop {
+        let addr = rng.gen::<u64>() & mask;
+        if !is_unmapped.contains(&addr) {
+            return addr;
+        }
+    }
+}
+
+/// Generate a random sequence of operations
+///
+/// The address space starts out fully mapped. Unmaps and protects target addresses that are
+/// still mapped at their position in the sequence, maps target previously unmapped addresses
+/// if there are any. The sequence is therefore not shuffled.
+///
+/// # Arguments
+///  - `nop`: Number of operations to generate
+///  - `write_ratio`: percentage of update operations, of which `UNMAP_PCT` are unmaps,
+///    `PROTECT_PCT` are protects and the remaining ones are maps
+pub fn generate_operations(
+    nop: usize,
+    write_ratio: usize,
+) -> Vec<Operation<Access, Modify>> {
+    let mut ops = Vec::with_capacity(nop);
+    let mut rng = ChaCha8Rng::seed_from_u64(42);
+
+    const MASK: u64 = 0x7fffffffff & !0xfffu64;
+    // the addresses that have been unmapped and not mapped again
+    let mut unmapped: Vec<u64> = Vec::new();
+    let mut is_unmapped: HashSet<u64> = HashSet::new();
+    for _ in 0..nop {
+        if rng.gen_range(0..100) < write_ratio {
+            let kind = rng.gen_range(0..100);
+            let op = if kind < UNMAP_PCT {
+                let addr = mapped_addr(&mut rng, MASK, &is_unmapped);
+                unmapped.push(addr);
+                is_unmapped.insert(addr);
+                Modify::Unmap(addr)
+            } else if kind < UNMAP_PCT + PROTECT_PCT {
+                let rights = PROTECT_RIGHTS[rng.gen_range(0..PROTECT_RIGHTS.len())];
+                Modify::Protect(mapped_addr(&mut rng, MASK, &is_unmapped), rights)
+            } else if !unmapped.is_empty() {
+                let addr = unmapped.swap_remove(rng.gen_range(0..unmapped.len()));
+                is_unmapped.remove(&addr);
+                Modify::Map(addr, rng.gen::<u64>() & MASK)
+            } else {
+                Modify::Map(rng.gen::<u64>() & MASK, rng.gen::<u64>() & MASK)
+            };
+            ops.push(Operation::WriteOperation(op))
+        } else if rng.gen_range(0..100) < IDENTIFY_PCT {
+            ops.push(Operation::ReadOperation(Access::Identify(
+                rng.gen::<u64>() & MASK,
+            )))
+        } else {
+            ops.push(Operation::ReadOperation(Access::Resolve(
+                rng.gen::<u64>() & MASK,
+            )))
+        }
+    }
+
+    ops
+}
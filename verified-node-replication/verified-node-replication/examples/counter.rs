@@ -184,7 +184,7 @@ pub fn main() {
                         Result::Ok((ret, t, _)) => {
                             tkn = t;
                         },
-                        Result::Err((t, _)) => {
+                        Result::Err((t, _, _)) => {
                             tkn = t;
                         }
                     }
@@ -195,7 +195,7 @@ pub fn main() {
                         Result::Ok((ret, t, _)) => {
                             tkn = t;
                         },
-                        Result::Err((t, _)) => {
+                        Result::Err((t, _, _)) => {
                             tkn = t;
                         }
                     }
@@ -207,7 +207,7 @@ pub fn main() {
                 Result::Ok((ret, t, _)) => {
                     tkn = t;
                 },
-                Result::Err((t, _)) => {
+                Result::Err((t, _, _)) => {
                     tkn = t;
                 }
             }
@@ -220,7 +220,7 @@ pub fn main() {
                 Result::Ok((ret, t, _)) => {
                     tkn = t;
                 },
-                Result::Err((t, _)) => {
+                Result::Err((t, _, _)) => {
                     tkn = t;
                 }
             }
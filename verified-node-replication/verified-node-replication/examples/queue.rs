@@ -0,0 +1,151 @@
+// Replicated Queue and Stack Example with Verified NR
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// trustedness: ignore this file
+
+// stdlib dependencies
+use  std::sync::Arc;
+
+// the verus dependencies
+use builtin::Tracked;
+
+// the traits and types we need from the verified-node-replicaton crate
+use verified_node_replication::{AffinityFn, NodeReplicated, NodeReplicatedT, NrQueue, NrStack, ThreadToken};
+use verified_node_replication::nrqueue::{NrQueueReadOp, NrQueueResponse, NrQueueWriteOp};
+use verified_node_replication::nrstack::{NrStackReadOp, NrStackResponse, NrStackWriteOp};
+
+/// the number of replicas we want to create
+const NUM_REPLICAS: usize = 2;
+
+/// number of operations each trhead executes
+const NUM_OPS_PER_THREAD: usize = 100_000;
+
+/// number of threads per replica
+const NUM_THREADS_PER_REPLICA: usize = 4;
+
+/// total number of threads being created
+const NUM_THREADS: usize = NUM_THREADS_PER_REPLICA*NUM_REPLICAS;
+
+
+struct NrQueueStack(
+    Arc<NodeReplicated<NrQueue<u64>>>,
+    ThreadToken<NrQueue<u64>>,
+    Arc<NodeReplicated<NrStack<u64>>>,
+    ThreadToken<NrStack<u64>>,
+);
+
+
+pub fn main() {
+
+    println!("Creating Replicated Data Structures...");
+
+    let mut nr_queue = NodeReplicated::new(NUM_REPLICAS, AffinityFn::new(|f| {}));
+    let mut nr_stack = NodeReplicated::new(NUM_REPLICAS, AffinityFn::new(|f| {}));
+
+    println!("Obtaining Thread tokens for {NUM_THREADS} threads...");
+
+    let mut queue_tokens = Vec::with_capacity(NUM_THREADS + NUM_REPLICAS);
+    let mut stack_tokens = Vec::with_capacity(NUM_THREADS + NUM_REPLICAS);
+    for idx in 0..NUM_THREADS+NUM_REPLICAS {
+        match (nr_queue.register(idx % NUM_REPLICAS), nr_stack.register(idx % NUM_REPLICAS)) {
+            (Option::Some(qtkn), Option::Some(stkn)) => {
+                queue_tokens.push(qtkn);
+                stack_tokens.push(stkn);
+            }
+            _ => panic!("could not register with replica!"),
+        }
+    }
+
+    let nr_queue = Arc::new(nr_queue);
+    let nr_stack = Arc::new(nr_stack);
+
+    // every thread alternates between pushing and popping an element, popping may find the
+    // data structure empty, so every thread returns the number of elements it popped
+    let thread_loop = |ds: NrQueueStack| -> (usize, usize) {
+        let NrQueueStack(queue, mut qtkn, stack, mut stkn) = ds;
+        let tid = (qtkn.replica_id(), qtkn.thread_id());
+        println!("Thread #{tid:?} start. executing {NUM_OPS_PER_THREAD} operations");
+        let mut qpopped = 0;
+        let mut spopped = 0;
+        for i in 0..NUM_OPS_PER_THREAD {
+            let (qop, sop) = if i % 2 == 0 {
+                (NrQueueWriteOp::Push(i as u64), NrStackWriteOp::Push(i as u64))
+            } else {
+                (NrQueueWriteOp::Pop, NrStackWriteOp::Pop)
+            };
+            qtkn = match queue.execute_mut(qop, qtkn, Tracked::assume_new()) {
+                Result::Ok((NrQueueResponse::Value(Some(_)), t, _)) => {
+                    qpopped += 1;
+                    t
+                }
+                Result::Ok((_, t, _)) => t,
+                Result::Err((t, _, _)) => t,
+            };
+            stkn = match stack.execute_mut(sop, stkn, Tracked::assume_new()) {
+                Result::Ok((NrStackResponse::Value(Some(_)), t, _)) => {
+                    spopped += 1;
+                    t
+                }
+                Result::Ok((_, t, _)) => t,
+                Result::Err((t, _, _)) => t,
+            };
+        }
+        println!("Thread #{tid:?} done.");
+        (qpopped, spopped)
+    };
+
+    println!("Creating {NUM_THREADS} threads...");
+
+    let mut threads = Vec::with_capacity(NUM_THREADS);
+    for idx in 0..NUM_THREADS {
+        let queue = nr_queue.clone();
+        let stack = nr_stack.clone();
+        let qtkn = queue_tokens.pop().unwrap();
+        let stkn = stack_tokens.pop().unwrap();
+        threads.push(std::thread::spawn(move || {
+            thread_loop(NrQueueStack(queue, qtkn, stack, stkn))
+        }));
+    }
+
+    println!("Waiting for threads to finish...");
+
+    // Wait for all the threads to finish
+    let mut qpopped = 0;
+    let mut spopped = 0;
+    for idx in 0..NUM_THREADS {
+        let thread = threads.pop().unwrap();
+        let (q, s) = thread.join().unwrap();
+        qpopped += q;
+        spopped += s;
+    }
+
+    // the elements that have been pushed, but not popped
+    let pushed = NUM_THREADS * ((NUM_OPS_PER_THREAD + 1) / 2);
+    let queue_expected = pushed - qpopped;
+    let stack_expected = pushed - spopped;
+
+    println!("Obtain final result...");
+
+    for idx in 0..NUM_REPLICAS {
+        let qtkn = queue_tokens.pop().unwrap();
+        match nr_queue.execute(NrQueueReadOp::Len, qtkn, Tracked::assume_new()) {
+            Result::Ok((NrQueueResponse::Len(len), _, _)) => {
+                println!("Replica {idx} - Queue Length: {len} expected {queue_expected}");
+            },
+            _ => {
+                println!("Replica {idx} - Queue Length: Err");
+            }
+        }
+        let stkn = stack_tokens.pop().unwrap();
+        match nr_stack.execute(NrStackReadOp::Len, stkn, Tracked::assume_new()) {
+            Result::Ok((NrStackResponse::Len(len), _, _)) => {
+                println!("Replica {idx} - Stack Length: {len} expected {stack_expected}");
+            },
+            _ => {
+                println!("Replica {idx} - Stack Length: Err");
+            }
+        }
+    }
+
+    println!("Done!");
+}
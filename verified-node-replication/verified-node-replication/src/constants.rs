@@ -20,13 +20,21 @@ pub open const MAX_REPLICAS: usize = 16;
 pub open const DEFAULT_LOG_BYTES: usize = 2 * 1024 * 1024;
 
 // making the assumption here that the write operation is about 12-16 bytes..
+/// the default number of entries in the log
 pub open const LOG_SIZE: usize = 512 * 1024;
 
+/// the maximum number of entries in the log, bounded such that the log indices don't overflow
+pub open const MAX_LOG_SIZE: usize = 0x400_0000;
+
 // 4 * 1024 * 1024;
 /// maximum number of threads per replica
 pub open const MAX_THREADS_PER_REPLICA: usize = 64;
 
-pub open const MAX_PENDING_OPS: usize = 1;
+/// the default number of threads per replica
+pub open const DEFAULT_THREADS_PER_REPLICA: usize = 64;
+
+/// the maximum number of operations a thread can have pending at a time
+pub open const MAX_PENDING_OPS: usize = 32;
 
 /// the maximum number of requests
 pub open const MAX_REQUESTS: usize = MAX_THREADS_PER_REPLICA * MAX_PENDING_OPS;
@@ -34,15 +42,31 @@ pub open const MAX_REQUESTS: usize = MAX_THREADS_PER_REPLICA * MAX_PENDING_OPS;
 /// interval when we do a try_combine when checking for responses
 pub open const RESPONSE_CHECK_INTERVAL: usize = 0x2000_0000;
 
+/// the maximum number of operations that are appended to the log at once, this is the full
+/// batch of every thread of a replica with the default number of threads.
+///
+/// A combiner collects at most these many operations per round, the operations it leaves
+/// pending are collected by the next round. This keeps the appends, and with them the log
+/// sizes, independent of `MAX_THREADS_PER_REPLICA`.
+pub open const MAX_APPEND: usize = 2048;
+
 /// Constant required for garbage collection. When the tail and the head are these many
 /// entries apart on the circular buffer, garbage collection will be performed by one of
 /// the replicas registered with the log.
 ///
 /// For the GC algorithm to work, we need to ensure that we can support the largest
-/// possible append after deciding to perform GC. This largest possible append is when
-/// every thread within a replica has a full batch of writes to be appended to the shared
-/// log.
-pub open const GC_FROM_HEAD: usize = MAX_PENDING_OPS * MAX_THREADS_PER_REPLICA;
+/// possible append after deciding to perform GC. This largest possible append is bounded
+/// by `MAX_APPEND`.
+pub open const GC_FROM_HEAD: usize = MAX_APPEND;
+
+/// the minimum number of entries in the log, it must at least fit two of the largest
+/// possible appends to leave room for garbage collection.
+pub open const MIN_LOG_SIZE: usize = 2 * GC_FROM_HEAD;
+
+/// whether the number of log entries is within the supported bounds
+pub open spec fn valid_log_size(log_size: nat) -> bool {
+    MIN_LOG_SIZE <= log_size <= MAX_LOG_SIZE
+}
 
 /// Threshold after how many iterations we abort and report the replica we're waiting for
 /// as stuck for busy spinning loops.
@@ -53,4 +77,8 @@ pub open const WARN_THRESHOLD: usize = 0x10000000;
 /// the maximum number of identifiers that can be used
 pub open const MAX_IDX: u64 = 0xffff_ffff_f000_0000;
 
+/// the local version of a retired replica, larger than any valid log index such that the
+/// replica no longer holds back the head of the log.
+pub open const RETIRED_VERSION: u64 = 0xffff_ffff_ffff_ffff;
+
 } // verus!
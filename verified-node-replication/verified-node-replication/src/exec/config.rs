@@ -0,0 +1,109 @@
+// Verified Node Replication Library
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+#[allow(unused_imports)]
+use builtin::*;
+use builtin_macros::*;
+
+use vstd::prelude::*;
+
+use crate::constants::{
+    valid_log_size, DEFAULT_THREADS_PER_REPLICA, LOG_SIZE, MAX_REPLICAS, MAX_THREADS_PER_REPLICA,
+};
+
+verus! {
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Configuration
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The configuration of a replicated data structure.
+///
+/// The constants in [`crate::constants`] only define upper bounds, the configuration selects the
+/// actual size of the log and the number of replicas and threads within those bounds.
+///
+/// The log has a fixed number of replica slots, which is the capacity `max_replicas`. Replicas
+/// beyond the initial `num_replicas` are retired when the data structure is created and brought
+/// into service with [`crate::NodeReplicated::add_replica`].
+///
+///  - Dafny: N/A
+///  - Rust:  N/A (Log::new_with_bytes(bytes, ...) and NodeReplicated::new(num_replicas, ...))
+pub struct NrConfig {
+    /// the number of replicas
+    pub num_replicas: usize,
+    /// the maximum number of replicas
+    pub max_replicas: usize,
+    /// the number of entries in the log
+    pub log_size: usize,
+    /// the number of threads that can register with a replica
+    pub threads_per_replica: usize,
+}
+
+impl NrConfig {
+    /// whether the configuration is within the supported bounds
+    pub open spec fn wf(&self) -> bool {
+        &&& 0 < self.num_replicas <= self.max_replicas
+        &&& self.max_replicas <= MAX_REPLICAS
+        &&& valid_log_size(self.log_size as nat)
+        &&& 0 < self.threads_per_replica <= MAX_THREADS_PER_REPLICA
+    }
+
+    /// creates a new configuration with the given number of replicas, the default log size and
+    /// the default number of threads per replica. The data structure can't grow beyond the
+    /// given number of replicas.
+    pub fn new(num_replicas: usize) -> (res: Self)
+        requires
+            0 < num_replicas <= MAX_REPLICAS,
+        ensures
+            res.wf(),
+            res.num_replicas == num_replicas,
+            res.max_replicas == num_replicas,
+            res.log_size == LOG_SIZE,
+            res.threads_per_replica == DEFAULT_THREADS_PER_REPLICA,
+    {
+        NrConfig {
+            num_replicas,
+            max_replicas: num_replicas,
+            log_size: LOG_SIZE,
+            threads_per_replica: DEFAULT_THREADS_PER_REPLICA,
+        }
+    }
+
+    /// sets the maximum number of replicas, i.e., the number of replicas the data structure can
+    /// grow to with [`crate::NodeReplicated::add_replica`]
+    pub fn max_replicas(self, max_replicas: usize) -> (res: Self)
+        requires
+            self.wf(),
+            self.num_replicas <= max_replicas <= MAX_REPLICAS,
+        ensures
+            res.wf(),
+            res == (NrConfig { max_replicas, ..self }),
+    {
+        NrConfig { max_replicas, ..self }
+    }
+
+    /// sets the number of entries in the log
+    pub fn log_size(self, log_size: usize) -> (res: Self)
+        requires
+            self.wf(),
+            valid_log_size(log_size as nat),
+        ensures
+            res.wf(),
+            res == (NrConfig { log_size, ..self }),
+    {
+        NrConfig { log_size, ..self }
+    }
+
+    /// sets the number of threads that can register with a replica
+    pub fn threads_per_replica(self, threads_per_replica: usize) -> (res: Self)
+        requires
+            self.wf(),
+            0 < threads_per_replica <= MAX_THREADS_PER_REPLICA,
+        ensures
+            res.wf(),
+            res == (NrConfig { threads_per_replica, ..self }),
+    {
+        NrConfig { threads_per_replica, ..self }
+    }
+}
+
+} // verus!
@@ -9,21 +9,22 @@ use vstd::{
     atomic_ghost::AtomicU64,
     atomic_with_ghost,
     cell::{CellId, PCell, PointsTo},
+    map::Map,
     prelude::*,
 };
 
-use crate::Dispatch;
+use crate::{Dispatch, NrError};
 
 // constants
-use crate::constants::MAX_THREADS_PER_REPLICA;
+use crate::constants::{MAX_PENDING_OPS, MAX_THREADS_PER_REPLICA};
 
 // spec import
-use crate::spec::flat_combiner::FlatCombiner;
+use crate::spec::flat_combiner::{slot_id, FlatCombiner};
+use crate::spec::types::ReqId;
 use crate::spec::unbounded_log::UnboundedLog;
 
 // exec imports
 use crate::exec::replica::{ReplicaId, ReplicaToken};
-use crate::exec::CachePadded;
 use crate::exec::Replica;
 
 verus! {
@@ -45,30 +46,156 @@ pub struct ThreadToken<DT: Dispatch> {
     /// identifies the thread within the replica
     pub  /* REVIEW: (crate) */
      tid: ThreadId,
-    /// the flat combiner client of the thread
-    pub fc_client: Tracked<FlatCombiner::clients>,
-    /// the permission to access the thread's operation batch
-    pub batch_perm: Tracked<PointsTo<PendingOperation<DT>>>,
+    /// the flat combiner clients of the thread, one for each entry of the thread's batch
+    pub fc_clients: Tracked<Map<nat, FlatCombiner::clients>>,
+    /// the permissions to access the entries of the thread's operation batch
+    pub batch_perms: Tracked<Map<nat, PointsTo<PendingOperation<DT>>>>,
+    /// whether the entry of the batch holds an operation that has been submitted, but whose
+    /// response has not been polled yet
+    pub  /* REVIEW: (crate) */
+     pending: Vec<bool>,
+    /// the entry of the batch that takes the next operation of the thread, the entries are used
+    /// round-robin such that the combiner collects the operations in the order of submission
+    pub  /* REVIEW: (crate) */
+     next: usize,
+    /// a version of the log that includes the last update of the thread, reads with session
+    /// consistency wait until the replica has reached it
+    pub  /* REVIEW: (crate) */
+     session_version: u64,
 }
 
 impl<DT: Dispatch> ThreadToken<DT> {
     pub open spec fn wf2(&self, num_replicas: nat) -> bool {
         &&& self.rid.wf(num_replicas)
-        &&& self.fc_client@@.value.is_Idle()
-        &&& (self.tid as nat)
-            < MAX_THREADS_PER_REPLICA
-        // &&& self.fc_client@@.instance == fc_inst
+        &&& (self.tid as nat) < MAX_THREADS_PER_REPLICA
+        &&& self.pending.len() == MAX_PENDING_OPS
+        &&& self.next < MAX_PENDING_OPS
+        &&& (forall|i: nat|
+            #![trigger self.fc_clients@[i]]
+            #![trigger self.batch_perms@[i]]
+            i < MAX_PENDING_OPS && !self.pending[i as int] ==> {
+                &&& self.fc_clients@.contains_key(i)
+                &&& self.fc_clients@[i]@.value.is_Idle()
+                &&& self.fc_clients@[i]@.key == slot_id(self.tid as nat, i, MAX_PENDING_OPS as nat)
+                &&& self.batch_perms@.contains_key(i)
+                &&& self.batch_perms@[i]@.value.is_None()
+            })
+    }
+
+    /// the thread token is well-formed, but some entries of its batch may be pending
+    pub open spec fn wf_pending(&self, replica: &Replica<DT>) -> bool {
+        self.wf_pending_in(
+            replica.spec_id(),
+            replica.contexts@,
+            replica.flat_combiner_instance@,
+        )
+    }
 
-        &&& self.batch_perm@@.value.is_None()
-        &&& self.fc_client@@.key == self.tid as nat
+    /// the thread token is well-formed for the replica with the given id, thread contexts and
+    /// flat combiner instance, but some entries of its batch may be pending
+    pub open spec fn wf_pending_in(
+        &self,
+        replica_id: nat,
+        contexts: Seq<Context<DT>>,
+        fc_instance: FlatCombiner::Instance,
+    ) -> bool {
+        &&& self.wf2(replica_id + 1)  // +1 here because ids got < replicas
+
+        &&& self.rid@ == replica_id
+        &&& self.thread_id_spec() < contexts.len()
+        &&& (forall|i: nat|
+            #![trigger self.fc_clients@[i]]
+            #![trigger self.batch_perms@[i]]
+            i < MAX_PENDING_OPS && !self.pending[i as int] ==> {
+                &&& self.fc_clients@[i]@.instance == fc_instance
+                &&& self.batch_perms@[i]@.pcell
+                    == contexts[self.thread_id_spec() as int].batch[i as int].id()
+            })
     }
 
     pub open spec fn wf(&self, replica: &Replica<DT>) -> bool {
-        &&& self.wf2(replica.spec_id() + 1)  // +1 here because ids got < replicas
+        &&& self.wf_pending(replica)
+        &&& self.no_pending()
+    }
 
-        &&& self.rid@ == replica.spec_id()
-        &&& self.fc_client@@.instance == replica.flat_combiner_instance
-        &&& self.batch_perm@@.pcell == replica.contexts[self.thread_id_spec() as int].batch.0.id()
+    pub open spec fn session_version_spec(&self) -> nat {
+        self.session_version as nat
+    }
+
+    /// none of the entries of the thread's batch is pending
+    pub open spec fn no_pending(&self) -> bool {
+        forall|i: int| 0 <= i < self.pending.len() ==> !(#[trigger] self.pending[i])
+    }
+
+    /// Creates a new thread token from the flat combiner clients and the batch permissions of
+    /// the thread, with none of the entries pending.
+    pub fn new(
+        rid: ReplicaToken,
+        tid: ThreadId,
+        fc_clients: Tracked<Map<nat, FlatCombiner::clients>>,
+        batch_perms: Tracked<Map<nat, PointsTo<PendingOperation<DT>>>>,
+    ) -> (res: Self)
+        ensures
+            res.rid == rid,
+            res.tid == tid,
+            res.fc_clients == fc_clients,
+            res.batch_perms == batch_perms,
+            res.pending.len() == MAX_PENDING_OPS,
+            res.no_pending(),
+            res.next == 0,
+            res.session_version == 0,
+    {
+        let mut pending = Vec::with_capacity(MAX_PENDING_OPS);
+        while pending.len() < MAX_PENDING_OPS
+            invariant
+                pending.len() <= MAX_PENDING_OPS,
+                forall|i: int| 0 <= i < pending.len() ==> !(#[trigger] pending[i]),
+        {
+            pending.push(false);
+        }
+        ThreadToken { rid, tid, fc_clients, batch_perms, pending, next: 0, session_version: 0 }
+    }
+
+    /// Returns the index of the entry of the batch that takes the next operation, if it is not
+    /// pending.
+    ///
+    /// The entries are used round-robin. An entry is not reused before its response has been
+    /// polled, even if later entries are free, such that the combiner finds the operations of
+    /// the thread in the order of submission.
+    pub fn next_entry(&self) -> (res: Option<usize>)
+        requires
+            self.pending.len() == MAX_PENDING_OPS,
+            self.next < MAX_PENDING_OPS,
+        ensures
+            res.is_Some() ==> res.get_Some_0() == self.next && !self.pending[self.next as int],
+            res.is_None() ==> self.pending[self.next as int],
+    {
+        if self.pending[self.next] {
+            None
+        } else {
+            Some(self.next)
+        }
+    }
+
+    /// Advances to the entry of the batch that takes the operation after the next one.
+    pub fn advance(&mut self)
+        requires
+            old(self).next < MAX_PENDING_OPS,
+        ensures
+            self.next < MAX_PENDING_OPS,
+            self.next == (old(self).next + 1) % (MAX_PENDING_OPS as int),
+            self.rid == old(self).rid,
+            self.tid == old(self).tid,
+            self.fc_clients == old(self).fc_clients,
+            self.batch_perms == old(self).batch_perms,
+            self.pending == old(self).pending,
+            self.session_version == old(self).session_version,
+    {
+        if self.next + 1 < MAX_PENDING_OPS {
+            self.next = self.next + 1;
+        } else {
+            self.next = 0;
+        }
     }
 
     pub fn thread_id(&self) -> (result: ThreadId)
@@ -98,6 +225,67 @@ impl<DT: Dispatch> ThreadToken<DT> {
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Pending Handle
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A handle to an update operation that has been submitted to an entry of the thread's context,
+/// but whose response has not been obtained yet. The entry remains pending in the thread token
+/// until the response has been polled.
+///
+///  - Dafny: N/A
+///  - Rust:  N/A
+pub struct PendingHandle<DT: Dispatch> {
+    /// the replica id this thread uses
+    pub  /* REVIEW: (crate) */
+     rid: ReplicaToken,
+    /// identifies the thread within the replica
+    pub  /* REVIEW: (crate) */
+     tid: ThreadId,
+    /// the entry of the thread's batch that holds the operation
+    pub  /* REVIEW: (crate) */
+     idx: usize,
+    /// the ghost state of the request, the flat combiner client is waiting for the response
+    pub context_ghost: Tracked<FCClientRequestResponseGhost<DT>>,
+    /// the request id of the submitted operation
+    pub req_id: Ghost<ReqId>,
+}
+
+impl<DT: Dispatch> PendingHandle<DT> {
+    pub open spec fn wf(&self, replica: &Replica<DT>) -> bool {
+        &&& self.rid.wf(replica.spec_id() + 1)
+        &&& self.rid@ == replica.spec_id()
+        &&& (self.tid as nat) < MAX_THREADS_PER_REPLICA
+        &&& self.thread_id_spec() < replica.contexts.len()
+        &&& self.idx < MAX_PENDING_OPS
+        &&& self.context_ghost@.dequeue_resp_pre(
+            replica.contexts[self.thread_id_spec() as int].batch[self.idx as int].id(),
+            slot_id(self.thread_id_spec(), self.idx as nat, MAX_PENDING_OPS as nat),
+            replica.flat_combiner_instance@,
+        )
+        &&& self.context_ghost@.fc_clients@.value.get_Waiting_0() == self.req_id@
+    }
+
+    pub open spec fn thread_id_spec(&self) -> nat {
+        self.tid as nat
+    }
+
+    pub const fn replica_id(&self) -> (result: ReplicaId)
+        ensures
+            result as nat == self.replica_id_spec(),
+    {
+        self.rid.id()
+    }
+
+    pub open spec fn replica_id_spec(&self) -> nat {
+        self.rid.id_spec()
+    }
+
+    /// the request id of the submitted operation
+    pub open spec fn req_id_spec(&self) -> ReqId {
+        self.req_id@
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // Pending Operation
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -111,7 +299,7 @@ pub struct PendingOperation<DT: Dispatch> {
     /// the operation that is being executed
     pub  /*REVIEW: (crate)*/
      op: DT::WriteOperation,
-    /// the response of the operation
+    /// the response of the operation, none if the operation couldn't be appended to the log
     pub  /*REVIEW: (crate)*/
      resp: Option<DT::Response>,
 }
@@ -142,7 +330,10 @@ struct_with_invariants!{
 ///  - Dafny: linear datatype Context = Context(
 ///  - Rust:  pub(crate) struct Context<T, R, M>
 ///
-/// Note, in contrast to the Rust version, we just have a single outstanding operation
+/// Note, in contrast to the Rust version, every entry of the batch has its own atomic that
+/// indicates whether the entry holds an operation or a response. The entries are not padded to
+/// the cache line, only the contexts of different threads are. The replica creates a context for
+/// each of the `NrConfig::threads_per_replica` threads.
 #[repr(align(128))]
 pub struct Context<DT: Dispatch> {
     /// Array that will hold all pending operations to be appended to the shared
@@ -152,13 +343,13 @@ pub struct Context<DT: Dispatch> {
     ///
     ///  - Dafny: linear cell: CachePadded<Cell<OpResponse>>
     ///  - Rust:  pub(crate) batch: [CachePadded<PendingOperation<T, R, M>>; MAX_PENDING_OPS],
-    pub/*REVIEW: (crate)*/ batch: CachePadded<PCell<PendingOperation<DT>>>,
+    pub/*REVIEW: (crate)*/ batch: Vec<PCell<PendingOperation<DT>>>,
 
-    /// The number of operations in this context, (just 0 or 1)
+    /// The number of operations in each entry of the batch, (just 0 or 1)
     ///
     ///  - Dafny: linear atomic: CachePadded<Atomic<uint64, ContextGhost>>,
     ///  - Rust:  N/A
-    pub/*REVIEW: (crate)*/ atomic: CachePadded<AtomicU64<_, ContextGhost<DT>, _>>,
+    pub/*REVIEW: (crate)*/ atomic: Vec<AtomicU64<_, ContextGhost<DT>, _>>,
 
     /// ghost: identifier of the thread
     pub thread_id_g: Ghost<nat>,
@@ -169,52 +360,119 @@ pub struct Context<DT: Dispatch> {
 
 pub open spec fn wf(&self, thread_idx: nat) -> bool {
     predicate {
-        self.thread_id_g@ == thread_idx
+        &&& self.thread_id_g@ == thread_idx
+        &&& self.batch.len() == MAX_PENDING_OPS
+        &&& self.atomic.len() == MAX_PENDING_OPS
     }
-    invariant on atomic with (flat_combiner_instance, unbounded_log_instance, batch, thread_id_g) specifically (self.atomic.0) is (v: u64, g: ContextGhost<DT>) {
-        &&& g.inv(v, thread_id_g@, batch.0, flat_combiner_instance@, unbounded_log_instance@)
+    invariant on atomic with (flat_combiner_instance, unbounded_log_instance, batch, thread_id_g)
+        forall |i: int|
+        where (0 <= i < self.atomic@.len())
+        specifically (self.atomic@[i])
+        is (v: u64, g: ContextGhost<DT>)
+    {
+        &&& g.inv(v, slot_id(thread_id_g@, i as nat, MAX_PENDING_OPS as nat), batch@[i], flat_combiner_instance@, unbounded_log_instance@)
     }
 }}  // struct_with_invariants!
 
 
 impl<DT: Dispatch> Context<DT> {
+    /// Creates the context of a thread, taking the flat combiner slots of the thread's batch.
+    /// Returns the permissions to access the entries of the batch, keyed by their index.
     pub fn new(
         thread_id: usize,
-        slot: Tracked<FlatCombiner::slots>,
+        slots: Tracked<Map<nat, FlatCombiner::slots>>,
         flat_combiner_instance: Tracked<FlatCombiner::Instance>,
         unbounded_log_instance: Tracked<UnboundedLog::Instance<DT>>,
-    ) -> (res: (Context<DT>, Tracked<PointsTo<PendingOperation<DT>>>))
+    ) -> (res: (Context<DT>, Tracked<Map<nat, PointsTo<PendingOperation<DT>>>>))
         requires
-            slot@@.value.is_Empty(),
-            slot@@.instance == flat_combiner_instance,
-            slot@@.key == thread_id as nat,
+            forall|i: nat| #![trigger slot_id(thread_id as nat, i, MAX_PENDING_OPS as nat)]
+                i < MAX_PENDING_OPS ==> {
+                    let s = slot_id(thread_id as nat, i, MAX_PENDING_OPS as nat);
+                    &&& slots@.contains_key(s)
+                    &&& slots@[s]@.value.is_Empty()
+                    &&& slots@[s]@.instance == flat_combiner_instance
+                    &&& slots@[s]@.key == s
+                },
         ensures
             res.0.wf(thread_id as nat),
-            res.0.batch.0.id() == res.1@@.pcell,
             res.0.flat_combiner_instance == flat_combiner_instance,
             res.0.unbounded_log_instance == unbounded_log_instance,
-            res.1@@.value.is_None(),
+            forall|i: nat| #![trigger res.1@[i]]
+                i < MAX_PENDING_OPS ==> {
+                    &&& res.1@.contains_key(i)
+                    &&& res.1@[i]@.pcell == res.0.batch[i as int].id()
+                    &&& res.1@[i]@.value.is_None()
+                },
     {
+        let tracked mut slots = slots.get();
         let ghost mut thread_id_g;
         proof {
             thread_id_g = thread_id as nat;
         }
-        // create the storage for storing the update operation
-        let (batch, batch_perms) = PCell::empty();
-        let batch = CachePadded(batch);
-        // create the atomic with the ghost context
-        let tracked context_ghost = ContextGhost {
-            batch_perms: None,
-            slots: slot.get(),
-            update: Option::None,
-        };
-        let atomic = CachePadded(
-            AtomicU64::new(
-                Ghost((flat_combiner_instance, unbounded_log_instance, batch, Ghost(thread_id_g))),
-                0,
-                Tracked(context_ghost),
-            ),
-        );
+        // create the storage for storing the update operations
+        let mut batch: Vec<PCell<PendingOperation<DT>>> = Vec::with_capacity(MAX_PENDING_OPS);
+        let tracked mut batch_perms: Map<nat, PointsTo<PendingOperation<DT>>> = Map::tracked_empty();
+        let mut idx = 0;
+        while idx < MAX_PENDING_OPS
+            invariant
+                0 <= idx <= MAX_PENDING_OPS,
+                batch.len() == idx,
+                forall|i: nat| #![trigger batch_perms[i]]
+                    i < idx ==> {
+                        &&& batch_perms.contains_key(i)
+                        &&& batch_perms[i]@.pcell == batch[i as int].id()
+                        &&& batch_perms[i]@.value.is_None()
+                    },
+        {
+            let (cell, cell_perms) = PCell::empty();
+            batch.push(cell);
+            proof {
+                batch_perms.tracked_insert(idx as nat, cell_perms.get());
+            }
+            idx = idx + 1;
+        }
+        // create the atomics with the ghost contexts
+        let mut atomic: Vec<AtomicU64<(Tracked<FlatCombiner::Instance>, Tracked<UnboundedLog::Instance<DT>>, Vec<PCell<PendingOperation<DT>>>, Ghost<nat>, int), ContextGhost<DT>, _>> = Vec::with_capacity(MAX_PENDING_OPS);
+        let mut idx = 0;
+        while idx < MAX_PENDING_OPS
+            invariant
+                0 <= idx <= MAX_PENDING_OPS,
+                atomic.len() == idx,
+                batch.len() == MAX_PENDING_OPS,
+                thread_id_g == thread_id as nat,
+                forall|i: nat| #![trigger slot_id(thread_id_g, i, MAX_PENDING_OPS as nat)]
+                    idx <= i < MAX_PENDING_OPS ==> {
+                        let s = slot_id(thread_id_g, i, MAX_PENDING_OPS as nat);
+                        &&& slots.contains_key(s)
+                        &&& slots[s]@.value.is_Empty()
+                        &&& slots[s]@.instance == flat_combiner_instance
+                        &&& slots[s]@.key == s
+                    },
+                forall|i: int| #![trigger atomic[i]]
+                    0 <= i < idx ==> {
+                        &&& atomic[i].well_formed()
+                        &&& atomic[i].constant() == (flat_combiner_instance, unbounded_log_instance, batch, Ghost(thread_id_g), i)
+                    },
+        {
+            let tracked slot;
+            proof {
+                slot = slots.tracked_remove(slot_id(thread_id_g, idx as nat, MAX_PENDING_OPS as nat));
+            }
+            let tracked context_ghost = ContextGhost {
+                batch_perms: None,
+                slots: slot,
+                update: Option::None,
+            };
+            let ghost i = idx as int;
+            atomic.push(
+                AtomicU64::new(
+                    Ghost((flat_combiner_instance, unbounded_log_instance, batch, Ghost(thread_id_g), i)),
+                    0,
+                    Tracked(context_ghost),
+                ),
+            );
+            idx = idx + 1;
+        }
         // Assemble the context, return with the permissions
         (
             Context {
@@ -224,7 +482,7 @@ impl<DT: Dispatch> Context<DT> {
                 flat_combiner_instance,
                 unbounded_log_instance,
             },
-            batch_perms,
+            Tracked(batch_perms),
         )
     }
 
@@ -232,24 +490,26 @@ impl<DT: Dispatch> Context<DT> {
     ///
     /// This is invoked by the thread that want's to execute an operation
     ///
-    /// Note, enqueue is a bit a misnomer. We just have one operation per thread
+    /// Note, enqueue is a bit a misnomer. The operation is placed in the given entry of the batch
     pub fn enqueue_op(
         &self,
+        idx: usize,
         op: DT::WriteOperation,
         context_ghost: Tracked<FCClientRequestResponseGhost<DT>>,
     ) -> (res: (bool, Tracked<FCClientRequestResponseGhost<DT>>))
         requires
+            idx < MAX_PENDING_OPS,
             context_ghost@.enqueue_op_pre(
-                self.thread_id_g@,
+                slot_id(self.thread_id_g@, idx as nat, MAX_PENDING_OPS as nat),
                 op,
-                self.batch.0.id(),
+                self.batch[idx as int].id(),
                 self.flat_combiner_instance@,
                 self.unbounded_log_instance@,
             ),
             self.wf(self.thread_id_g@),
         ensures
             res.1@.enqueue_op_post(context_ghost@),
-            res.1@.cell_id == self.batch.0.id(),
+            res.1@.cell_id == self.batch[idx as int].id(),
             self.wf(self.thread_id_g@),
     {
         let tracked FCClientRequestResponseGhost {
@@ -261,11 +521,11 @@ impl<DT: Dispatch> Context<DT> {
         let tracked mut batch_perms = batch_perms.tracked_unwrap();
         let tracked local_updates = local_updates.tracked_unwrap();
         // put the operation there, updates the permissions so we can store them in the GhostContext
-        self.batch.0.put(Tracked(&mut batch_perms), PendingOperation::new(op));
+        self.batch[idx].put(Tracked(&mut batch_perms), PendingOperation::new(op));
         let tracked send_request_result;
         let res =
             atomic_with_ghost!(
-            &self.atomic.0 => store(1);
+            &self.atomic[idx] => store(1);
             update prev->next;
             ghost g => {
                 let ghost tid = fc_clients.view().key;
@@ -279,7 +539,7 @@ impl<DT: Dispatch> Context<DT> {
                 g.batch_perms = Some(batch_perms);
                 g.update = Some(local_updates);
 
-                assert(g.inv(1, tid, self.batch.0, self.flat_combiner_instance.view(), self.unbounded_log_instance.view()))
+                assert(g.inv(1, tid, self.batch@[idx as int], self.flat_combiner_instance.view(), self.unbounded_log_instance.view()))
             }
         );
         let tracked new_context_ghost = FCClientRequestResponseGhost {
@@ -291,17 +551,24 @@ impl<DT: Dispatch> Context<DT> {
         (true, Tracked(new_context_ghost))
     }
 
-    /// Returns a single response if available. Otherwise, returns None.
+    /// Returns the response of the given entry of the batch if available. Otherwise, returns None.
+    /// The response is an error if the operation couldn't be appended to the log, the ticket of
+    /// the operation is returned to the thread then.
     ///
     /// this is invoked by the thread that has enqueued the operation before
     pub fn dequeue_response(
         &self,
+        idx: usize,
         context_ghost: Tracked<FCClientRequestResponseGhost<DT>>,
-    ) -> (res: (Option<DT::Response>, Tracked<FCClientRequestResponseGhost<DT>>))
+    ) -> (res: (
+        Option<Result<DT::Response, NrError>>,
+        Tracked<FCClientRequestResponseGhost<DT>>,
+    ))
         requires
+            idx < MAX_PENDING_OPS,
             context_ghost@.dequeue_resp_pre(
-                self.batch.0.id(),
-                self.thread_id_g@,
+                self.batch[idx as int].id(),
+                slot_id(self.thread_id_g@, idx as nat, MAX_PENDING_OPS as nat),
                 self.flat_combiner_instance@,
             ),
             self.wf(self.thread_id_g@),
@@ -318,7 +585,7 @@ impl<DT: Dispatch> Context<DT> {
         let tracked recv_response_result;
         let res =
             atomic_with_ghost!(
-            &self.atomic.0 => load();
+            &self.atomic[idx] => load();
             returning res;
             ghost g => {
                 if res == 0 {
@@ -339,8 +606,11 @@ impl<DT: Dispatch> Context<DT> {
         );
         if res == 0 {
             let tracked mut batch_perms = batch_perms.tracked_unwrap();
-            let op = self.batch.0.take(Tracked(&mut batch_perms));
-            let resp = op.resp.unwrap();
+            let op = self.batch[idx].take(Tracked(&mut batch_perms));
+            let resp = match op.resp {
+                Some(resp) => Ok(resp),
+                None => Err(NrError::LogExhausted),
+            };
             let tracked new_context_ghost = FCClientRequestResponseGhost {
                 batch_perms: Some(batch_perms),
                 cell_id,
@@ -394,11 +664,34 @@ impl<DT: Dispatch> Context<DT> {
     //     // }
     //     false
     // }
+    /// Returns whether an entry of the batch holds an operation that hasn't been executed yet.
+    pub(crate) fn has_pending_ops(&self) -> (res: bool)
+        requires
+            self.wf(self.thread_id_g@),
+    {
+        let mut idx: usize = 0;
+        while idx < MAX_PENDING_OPS
+            invariant
+                0 <= idx <= MAX_PENDING_OPS,
+                self.wf(self.thread_id_g@),
+        {
+            let num_ops =
+                atomic_with_ghost!(
+                &self.atomic[idx] => load();
+                ghost g => { }
+            );
+            if num_ops != 0 {
+                return true;
+            }
+            idx = idx + 1;
+        }
+        false
+    }
+
     /// Returns the maximum number of operations that will go pending on this context.
     #[inline(always)]
     pub(crate) fn batch_size() -> usize {
-        // MAX_PENDING_OPS
-        1
+        MAX_PENDING_OPS
     }  // /
     // Given a logical address, returns an index into the batch at which it falls.
     // #[inline(always)]
@@ -467,15 +760,20 @@ pub open spec fn inv(&self, v: u64, tid: nat, cell: PCell<PendingOperation<DT>>,
 
         &&& (self.slots@.value.is_Response() ==> {
             &&& self.update.is_Some()
-            &&& self.update.get_Some_0()@.value.is_Done()
             &&& self.update.get_Some_0()@.key == self.slots@.value.get_ReqId()
             &&& self.update.get_Some_0()@.instance == inst
 
             &&& self.batch_perms.is_Some()
             &&& self.batch_perms.get_Some_0()@.value.is_Some()
             &&& self.batch_perms.get_Some_0()@.pcell == cell.id()
-            &&& self.batch_perms.get_Some_0()@.value.get_Some_0().resp.is_Some()
-            &&& self.batch_perms.get_Some_0()@.value.get_Some_0().resp.get_Some_0() == self.update.get_Some_0()@.value.get_Done_ret()
+            // either the update has been executed, or it hasn't been appended to the log
+            &&& self.batch_perms.get_Some_0()@.value.get_Some_0().resp.is_Some() ==> {
+                &&& self.update.get_Some_0()@.value.is_Done()
+                &&& self.batch_perms.get_Some_0()@.value.get_Some_0().resp.get_Some_0() == self.update.get_Some_0()@.value.get_Done_ret()
+            }
+            &&& self.batch_perms.get_Some_0()@.value.get_Some_0().resp.is_None() ==> {
+                &&& self.update.get_Some_0()@.value.is_Init()
+            }
         })
     }
 }
@@ -542,7 +840,7 @@ impl<DT: Dispatch> FCClientRequestResponseGhost<DT> {
     pub open spec fn dequeue_resp_post(
         &self,
         pre: FCClientRequestResponseGhost<DT>,
-        ret: Option<DT::Response>,
+        ret: Option<Result<DT::Response, NrError>>,
         inst: UnboundedLog::Instance<DT>,
     ) -> bool {
         &&& ret.is_Some() ==> {
@@ -552,9 +850,16 @@ impl<DT: Dispatch> FCClientRequestResponseGhost<DT> {
             &&& self.batch_perms.get_Some_0()@.pcell == self.cell_id
             &&& self.local_updates.is_Some()
             &&& self.local_updates.get_Some_0()@.instance == inst
-            &&& self.local_updates.get_Some_0()@.value.is_Done()
             &&& self.local_updates.get_Some_0()@.key == pre.fc_clients@.value.get_Waiting_0()
-            &&& self.local_updates.get_Some_0()@.value.get_Done_ret() == ret.get_Some_0()
+            &&& ret.get_Some_0().is_Ok() ==> {
+                &&& self.local_updates.get_Some_0()@.value.is_Done()
+                &&& self.local_updates.get_Some_0()@.value.get_Done_ret() == ret.get_Some_0().get_Ok_0()
+            }
+            // the update hasn't been appended to the log, the thread has its ticket back
+            &&& ret.get_Some_0().is_Err() ==> {
+                &&& self.local_updates.get_Some_0()@.value.is_Init()
+                &&& ret.get_Some_0().get_Err_0() == NrError::LogExhausted
+            }
             &&& self.fc_clients@.instance == pre.fc_clients@.instance
             &&& self.fc_clients@.key == pre.fc_clients@.key
             &&& self.fc_clients@.value.is_Idle()
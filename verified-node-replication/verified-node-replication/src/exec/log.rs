@@ -15,32 +15,17 @@ use crate::spec::cyclicbuffer::{log_entry_alive_value, log_entry_idx, stored_typ
 use crate::spec::cyclicbuffer::{CyclicBuffer, LogicalLogIdx, StoredType};
 use crate::spec::types::{ConcreteLogEntry, LogIdx, NodeId, ReqId};
 use crate::spec::unbounded_log::UnboundedLog;
-use crate::Dispatch;
+use crate::{Dispatch, NrEvent, Observer};
 
 use crate::constants::{
-    GC_FROM_HEAD, LOG_SIZE, MAX_IDX, MAX_REPLICAS, MAX_REQUESTS, WARN_THRESHOLD,
+    valid_log_size, GC_FROM_HEAD, MAX_APPEND, MAX_IDX, MAX_REPLICAS, MAX_REQUESTS,
+    RETIRED_VERSION, WARN_THRESHOLD,
 };
-use crate::exec::replica::{ReplicaId, ReplicaToken};
+use crate::exec::replica::{Replica, ReplicaId, ReplicaToken};
 use crate::exec::CachePadded;
 
 verus! {
 
-////////////////////////////////////////////////////////////////////////////////////////////////////
-// Utils
-////////////////////////////////////////////////////////////////////////////////////////////////////
-#[verus::line_count::ignore]
-#[verus::trusted]
-#[verifier(external_body)]  /* vattr */
-pub fn print_starvation_warning(line: u32) {
-    eprintln!("WARNING({line}): has been looping for `WARN_THRESHOLD` iterations. Are we starving?");
-}
-
-#[verus::trusted]
-#[verifier(external_body)]  /* vattr */
-pub fn warn_with_tail_too_big() {
-    eprintln!("WARNING: Tail value exceeds the maximum value of u64.");
-}
-
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // Log Entries
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -137,12 +122,17 @@ pub struct NrLog<DT: Dispatch>
     ///  - Dafny: linear node_info: lseq<NodeInfo>, // NodeInfo is padded
     ///  - Rust:  pub(crate) ltails: [CachePadded<AtomicUsize>; MAX_REPLICAS_PER_LOG],
     ///
-    pub/*REVIEW: (crate)*/ local_versions: Vec<CachePadded<AtomicU64<_, (UnboundedLog::local_versions<DT>, CyclicBuffer::local_versions<DT>), _>>>,  // NodeInfo is padded
+    /// The local version of a retired replica is set to [`RETIRED_VERSION`].
+    ///
+    pub/*REVIEW: (crate)*/ local_versions: Vec<CachePadded<AtomicU64<_, (UnboundedLog::local_versions<DT>, CyclicBuffer::local_versions<DT>, CyclicBuffer::retired<DT>), _>>>,  // NodeInfo is padded
 
     // The upstream Rust version also contains:
     //  - pub(crate) next: CachePadded<AtomicUsize>, the identifier for the next replica
     //  - pub(crate) lmasks: [CachePadded<Cell<bool>>; MAX_REPLICAS_PER_LOG], tracking of alivebits
 
+    /// receives the progress and diagnostic events of the log
+    pub observer: Observer,
+
     pub num_replicas: Ghost<nat>,
     pub unbounded_log_instance: Tracked<UnboundedLog::Instance<DT>>,
     pub cyclic_buffer_instance: Tracked<CyclicBuffer::Instance<DT>>,
@@ -154,14 +144,14 @@ pub open spec fn wf(&self) -> bool {
 
         &&& self.local_versions.len() == self.num_replicas
 
-        &&& self.slog.len() == LOG_SIZE
+        &&& valid_log_size(self.slog.len() as nat)
         &&& self.slog.len() == self.cyclic_buffer_instance@.buffer_size()
         &&& self.slog.len() == self.cyclic_buffer_instance@.cell_ids().len()
         &&& (forall |i| #![trigger self.slog[i]] 0 <= i < self.slog.len() ==> {
             &&& self.slog[i].log_entry.id() == (#[trigger]self.cyclic_buffer_instance@.cell_ids()[i])
         })
 
-        &&& (forall |i: nat| i < LOG_SIZE ==> (#[trigger] self.slog[i as int]).wf(i, self.cyclic_buffer_instance@))
+        &&& (forall |i: nat| i < self.slog.len() ==> (#[trigger] self.slog[i as int]).wf(i, self.cyclic_buffer_instance@))
 
         &&& self.unbounded_log_instance@.num_replicas() == self.num_replicas
         &&& self.cyclic_buffer_instance@.num_replicas() == self.num_replicas
@@ -192,15 +182,19 @@ pub open spec fn wf(&self) -> bool {
         forall |i: int|
         where (0 <= i < self.local_versions@.len())
         specifically (self.local_versions@[i].0)
-        is (v: u64, g: (UnboundedLog::local_versions<DT>, CyclicBuffer::local_versions<DT>)) {
+        is (v: u64, g: (UnboundedLog::local_versions<DT>, CyclicBuffer::local_versions<DT>, CyclicBuffer::retired<DT>)) {
 
         &&& g.0@.instance == unbounded_log_instance@
         &&& g.0@.key == i
-        &&& g.0@.value == v
         &&& g.1@.instance == cyclic_buffer_instance@
         &&& g.1@.key == i
-        &&& g.1@.value == v
-        &&& 0 <= v <= MAX_IDX
+        &&& g.1@.value == g.0@.value
+        &&& g.2@.instance == cyclic_buffer_instance@
+        &&& g.2@.key == i
+        &&& 0 <= g.0@.value <= MAX_IDX
+        // retired replicas don't publish their local version
+        &&& !g.2@.value ==> g.0@.value == v
+        &&& g.2@.value ==> v == RETIRED_VERSION
     }
 }
 }  // struct_with_invariants!{
@@ -208,16 +202,22 @@ pub open spec fn wf(&self) -> bool {
 
 impl<DT: Dispatch> NrLog<DT> {
     /// initializes the NrLOg
-    pub fn new(num_replicas: usize, log_size: usize) -> (res: (
-        Self,
-        Vec<ReplicaToken>,
-        Tracked<NrLogTokens<DT>>,
-    ))
+    ///
+    /// The replicas start with the given state of the data structure, the updates of the log are
+    /// applied on top of it.
+    pub fn new(
+        num_replicas: usize,
+        log_size: usize,
+        observer: Observer,
+        init_state: Ghost<DT::View>,
+    ) -> (res: (Self, Vec<ReplicaToken>, Tracked<NrLogTokens<DT>>))
         requires
-            log_size == LOG_SIZE,
+            valid_log_size(log_size as nat),
             0 < num_replicas && num_replicas <= MAX_REPLICAS,
         ensures
             res.0.wf(),
+            res.2@.unbounded_log_instance.init_state() == init_state@,
+            res.0.local_versions.len() == num_replicas,
             res.0.unbounded_log_instance@ == res.2@.unbounded_log_instance,
             res.0.cyclic_buffer_instance@ == res.2@.cyclic_buffer_instance,
             res.1.len() == num_replicas,
@@ -245,7 +245,9 @@ impl<DT: Dispatch> NrLog<DT> {
                 _,  //Tracked(ul_local_reads0), //Tracked<Map<ReqId,local_reads>>,
                 _,  //Tracked(ul_local_updates0), //Tracked<Map<ReqId,local_updates>>,
                 Tracked(ul_combiner0),  //Tracked<Map<NodeId,combiner>>
-            ) = UnboundedLog::Instance::initialize(num_replicas as nat);
+                _,  //Tracked(ul_snapshots0), //Tracked<Map<LogIdx,snapshots>>
+                _,  //Tracked(ul_version_upper_bound_reached0), //Tracked<Map<LogIdx,version_upper_bound_reached>>
+            ) = UnboundedLog::Instance::initialize(num_replicas as nat, init_state@);
             unbounded_log_instance = unbounded_log_instance0;
             ul_log = ul_log0;
             ul_tail = ul_tail0;
@@ -270,7 +272,7 @@ impl<DT: Dispatch> NrLog<DT> {
         while log_idx < log_size
             invariant
                 0 <= log_idx <= log_size,
-                log_size == LOG_SIZE,
+                valid_log_size(log_size as nat),
                 logical_log_idx == log_idx - log_size,
                 -log_size <= logical_log_idx <= 0,
                 slog_entries.len() == log_idx,
@@ -318,6 +320,7 @@ impl<DT: Dispatch> NrLog<DT> {
         let tracked cb_head: CyclicBuffer::head<DT>;
         let tracked cb_tail: CyclicBuffer::tail<DT>;
         let tracked mut cb_local_versions: Map<NodeId, CyclicBuffer::local_versions<DT>>;
+        let tracked mut cb_retired: Map<NodeId, CyclicBuffer::retired<DT>>;
         let tracked mut cb_alive_bits: Map<LogIdx, CyclicBuffer::alive_bits<DT>>;
         let tracked cb_combiners: Map<NodeId, CyclicBuffer::combiner<DT>>;
         proof {
@@ -326,6 +329,7 @@ impl<DT: Dispatch> NrLog<DT> {
                 Tracked(cb_head0),  // CyclicBuffer::head>;
                 Tracked(cb_tail0),  // CyclicBuffer::tail>;
                 Tracked(cb_local_versions0),  // Map<NodeId, CyclicBuffer::local_versions>;
+                Tracked(cb_retired0),  // Map<NodeId, CyclicBuffer::retired>;
                 Tracked(cb_alive_bits0),  // Map<LogIdx, CyclicBuffer::alive_bits>;
                 Tracked(cb_combiner0),  // Map<NodeId, CyclicBuffer::combiner>;
             ) = CyclicBuffer::Instance::initialize(
@@ -340,6 +344,7 @@ impl<DT: Dispatch> NrLog<DT> {
             cb_head = cb_head0;
             cb_tail = cb_tail0;
             cb_local_versions = cb_local_versions0;
+            cb_retired = cb_retired0;
             cb_alive_bits = cb_alive_bits0;
             cb_combiners = cb_combiner0;
         }
@@ -413,7 +418,11 @@ impl<DT: Dispatch> NrLog<DT> {
             CachePadded<
                 AtomicU64<
                     (Tracked<UnboundedLog::Instance<DT>>, Tracked<CyclicBuffer::Instance<DT>>, int),
-                    (UnboundedLog::local_versions<DT>, CyclicBuffer::local_versions<DT>),
+                    (
+                        UnboundedLog::local_versions<DT>,
+                        CyclicBuffer::local_versions<DT>,
+                        CyclicBuffer::retired<DT>,
+                    ),
                     _,
                 >,
             >,
@@ -425,6 +434,14 @@ impl<DT: Dispatch> NrLog<DT> {
                 local_versions.len() == nid,
                 forall|i| nid <= i < num_replicas ==> ul_local_versions.contains_key(i),
                 forall|i| nid <= i < num_replicas ==> cb_local_versions.contains_key(i),
+                forall|i| nid <= i < num_replicas ==> cb_retired.contains_key(i),
+                forall|i|
+                    #![trigger cb_retired[i]]
+                    nid <= i < num_replicas ==> {
+                        &&& cb_retired[i]@.instance == cyclic_buffer_instance
+                        &&& cb_retired[i]@.key == i
+                        &&& cb_retired[i]@.value == false
+                    },
                 forall|i|
                     #![trigger cb_local_versions[i]]
                     nid <= i < num_replicas ==> {
@@ -451,10 +468,12 @@ impl<DT: Dispatch> NrLog<DT> {
             let ghost mut nid_ghost;
             let tracked ul_version;
             let tracked cb_version;
+            let tracked cb_retired_flag;
             proof {
                 nid_ghost = nid as int;
                 ul_version = ul_local_versions.tracked_remove(nid as nat);
                 cb_version = cb_local_versions.tracked_remove(nid as nat);
+                cb_retired_flag = cb_retired.tracked_remove(nid as nat);
             }
             let cb_inst = Tracked(cyclic_buffer_instance.clone());
             let ul_inst = Tracked(unbounded_log_instance.clone());
@@ -463,7 +482,7 @@ impl<DT: Dispatch> NrLog<DT> {
                     AtomicU64::new(
                         Ghost((ul_inst, cb_inst, nid_ghost)),
                         0,
-                        Tracked((ul_version, cb_version)),
+                        Tracked((ul_version, cb_version, cb_retired_flag)),
                     ),
                 ),
             );
@@ -504,6 +523,7 @@ impl<DT: Dispatch> NrLog<DT> {
             head,
             tail,
             local_versions,
+            observer,
             num_replicas: Ghost(num_replicas as nat),
             unbounded_log_instance: Tracked(unbounded_log_instance),
             cyclic_buffer_instance: Tracked(cyclic_buffer_instance),
@@ -515,7 +535,7 @@ impl<DT: Dispatch> NrLog<DT> {
     #[inline(always)]
     pub(crate) fn index(&self, logical: u64) -> (result: usize)
         requires
-            self.slog.len() == LOG_SIZE,
+            valid_log_size(self.slog.len() as nat),
         ensures
             result as nat == self.index_spec(logical as nat),
             result == log_entry_idx(logical as int, self.slog.len() as nat),
@@ -527,7 +547,7 @@ impl<DT: Dispatch> NrLog<DT> {
     pub  /*REVIEW: (crate)*/
      open spec fn index_spec(&self, logical: nat) -> nat
         recommends
-            self.slog.len() == LOG_SIZE,
+            valid_log_size(self.slog.len() as nat),
     {
         logical % (self.slog.len() as nat)
     }
@@ -535,20 +555,20 @@ impl<DT: Dispatch> NrLog<DT> {
     #[inline(always)]
     pub(crate) fn is_alive_value(&self, logical: u64) -> (result: bool)
         requires
-            self.slog.len() == LOG_SIZE,
+            valid_log_size(self.slog.len() as nat),
         ensures
             result == self.is_alive_value_spec(logical as int),
             result == log_entry_alive_value(logical as int, self.slog.len() as nat),
     {
-        ((logical as usize) / LOG_SIZE % 2) == 0
+        ((logical as usize) / self.slog.len() % 2) == 0
     }
 
     pub  /*REVIEW: (crate)*/
      open spec fn is_alive_value_spec(&self, logical: int) -> bool
         recommends
-            self.slog.len() == LOG_SIZE,
+            valid_log_size(self.slog.len() as nat),
     {
-        ((logical / (LOG_SIZE as int)) % 2) == 0
+        ((logical / (self.slog.len() as int)) % 2) == 0
     }
 
     /// This method returns the current version upper bound value for the log.
@@ -623,7 +643,8 @@ impl<DT: Dispatch> NrLog<DT> {
             local_version => load();
             returning res;
             ghost g => {
-                new_local_reads_g = if res >= version_upper_bound {
+                // a retired replica doesn't serve reads, it has no defined local version
+                new_local_reads_g = if res != RETIRED_VERSION && res >= version_upper_bound {
                     self.unbounded_log_instance
                         .borrow()
                         .readonly_ready_to_read(rid_g.view(), &g.0, local_reads.get())
@@ -632,7 +653,328 @@ impl<DT: Dispatch> NrLog<DT> {
                 };
             }
         );
-        (res >= version_upper_bound, Tracked(new_local_reads_g))
+        (res != RETIRED_VERSION && res >= version_upper_bound, Tracked(new_local_reads_g))
+    }
+
+    /// This method returns the current version upper bound value for the log, advancing all
+    /// read requests of a batch with the same version upper bound.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub(crate) fn get_version_upper_bound_multiple(
+        &self,
+        num_reads: Ghost<nat>,
+        local_reads: Tracked<Map<nat, UnboundedLog::local_reads<DT>>>,
+    ) -> (ret: (u64, Tracked<Map<nat, UnboundedLog::local_reads<DT>>>))
+        requires
+            self.wf(),
+            forall|i: nat|
+                i < num_reads@ ==> {
+                    &&& #[trigger] local_reads@.contains_key(i)
+                    &&& local_reads@[i]@.instance == self.unbounded_log_instance@
+                    &&& local_reads@[i]@.value.is_Init()
+                },
+        ensures
+            forall|i: nat|
+                i < num_reads@ ==> {
+                    &&& #[trigger] ret.1@.contains_key(i)
+                    &&& ret.1@[i]@.instance == self.unbounded_log_instance@
+                    &&& ret.1@[i]@.key == local_reads@[i]@.key
+                    &&& ret.1@[i]@.value.is_VersionUpperBound()
+                    &&& ret.1@[i]@.value.get_VersionUpperBound_version_upper_bound() == ret.0 as nat
+                    &&& ret.1@[i]@.value.get_VersionUpperBound_op()
+                        == local_reads@[i]@.value.get_Init_op()
+                    &&& ret.1@[i]@.value.get_VersionUpperBound_node_id()
+                        == local_reads@[i]@.value.get_Init_node_id()
+                },
+    {
+        let tracked local_reads = local_reads.get();
+        let tracked new_local_reads_g: Map<nat, UnboundedLog::local_reads<DT>>;
+        let res =
+            atomic_with_ghost!(
+            &self.version_upper_bound.0 => load();
+            returning res;
+            ghost g => {
+                new_local_reads_g = self.readonly_version_upper_bound_multiple(
+                    num_reads@,
+                    local_reads,
+                    &g,
+                );
+            }
+        );
+        (res, Tracked(new_local_reads_g))
+    }
+
+    /// checks whether the version of the local replica has advanced enough to perform the read
+    /// operations of a batch, they share the same version upper bound.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub(crate) fn is_replica_synced_for_reads_multiple(
+        &self,
+        node_id: ReplicaId,
+        version_upper_bound: u64,
+        num_reads: Ghost<nat>,
+        local_reads: Tracked<Map<nat, UnboundedLog::local_reads<DT>>>,
+    ) -> (result: (bool, Tracked<Map<nat, UnboundedLog::local_reads<DT>>>))
+        requires
+            self.wf(),
+            node_id < self.local_versions.len(),
+            forall|i: nat|
+                i < num_reads@ ==> {
+                    &&& #[trigger] local_reads@.contains_key(i)
+                    &&& local_reads@[i]@.instance == self.unbounded_log_instance@
+                    &&& local_reads@[i]@.value.is_VersionUpperBound()
+                    &&& local_reads@[i]@.value.get_VersionUpperBound_version_upper_bound()
+                        == version_upper_bound
+                    &&& local_reads@[i]@.value.get_VersionUpperBound_node_id() == node_id
+                },
+        ensures
+            !result.0 ==> result.1 == local_reads,
+            result.0 ==> forall|i: nat|
+                i < num_reads@ ==> {
+                    &&& #[trigger] result.1@.contains_key(i)
+                    &&& result.1@[i]@.instance == self.unbounded_log_instance@
+                    &&& result.1@[i]@.key == local_reads@[i]@.key
+                    &&& result.1@[i]@.value.is_ReadyToRead()
+                    &&& result.1@[i]@.value.get_ReadyToRead_node_id() == node_id
+                    &&& result.1@[i]@.value.get_ReadyToRead_op()
+                        == local_reads@[i]@.value.get_VersionUpperBound_op()
+                    &&& result.1@[i]@.value.get_ReadyToRead_version_upper_bound()
+                        == version_upper_bound
+                },
+    {
+        let tracked new_local_reads_g: Map<nat, UnboundedLog::local_reads<DT>>;
+        let local_version = &self.local_versions[node_id as usize].0;
+        let res =
+            atomic_with_ghost!(
+            local_version => load();
+            returning res;
+            ghost g => {
+                // a retired replica doesn't serve reads, it has no defined local version
+                new_local_reads_g = if res != RETIRED_VERSION && res >= version_upper_bound {
+                    self.readonly_ready_to_read_multiple(num_reads@, local_reads.get(), &g.0)
+                } else {
+                    local_reads.get()
+                };
+            }
+        );
+        (res != RETIRED_VERSION && res >= version_upper_bound, Tracked(new_local_reads_g))
+    }
+
+    /// proof function that advances the first `num_reads` read requests with the version upper bound
+    proof fn readonly_version_upper_bound_multiple(
+        tracked &self,
+        num_reads: nat,
+        tracked local_reads: Map<nat, UnboundedLog::local_reads<DT>>,
+        tracked version_upper_bound: &UnboundedLog::version_upper_bound<DT>,
+    ) -> (tracked res: Map<nat, UnboundedLog::local_reads<DT>>)
+        requires
+            self.wf(),
+            version_upper_bound@.instance == self.unbounded_log_instance@,
+            forall|i: nat|
+                i < num_reads ==> {
+                    &&& #[trigger] local_reads.contains_key(i)
+                    &&& local_reads[i]@.instance == self.unbounded_log_instance@
+                    &&& local_reads[i]@.value.is_Init()
+                },
+        ensures
+            forall|i: nat|
+                i < num_reads ==> {
+                    &&& #[trigger] res.contains_key(i)
+                    &&& res[i]@.instance == self.unbounded_log_instance@
+                    &&& res[i]@.key == local_reads[i]@.key
+                    &&& res[i]@.value.is_VersionUpperBound()
+                    &&& res[i]@.value.get_VersionUpperBound_version_upper_bound()
+                        == version_upper_bound@.value
+                    &&& res[i]@.value.get_VersionUpperBound_op() == local_reads[i]@.value.get_Init_op()
+                    &&& res[i]@.value.get_VersionUpperBound_node_id()
+                        == local_reads[i]@.value.get_Init_node_id()
+                },
+        decreases num_reads,
+    {
+        if num_reads == 0 {
+            return local_reads;
+        }
+        let tracked mut local_reads_new = local_reads;
+        let idx = (num_reads - 1) as nat;
+        let tracked local_read = local_reads_new.tracked_remove(idx);
+        local_reads_new =
+        self.readonly_version_upper_bound_multiple(idx, local_reads_new, version_upper_bound);
+        let tracked local_read = self.unbounded_log_instance.borrow().readonly_version_upper_bound(
+            local_read@.key,
+            version_upper_bound,
+            local_read,
+        );
+        local_reads_new.tracked_insert(idx, local_read);
+        return local_reads_new;
+    }
+
+    /// proof function that transitions the first `num_reads` read requests into ready to read
+    proof fn readonly_ready_to_read_multiple(
+        tracked &self,
+        num_reads: nat,
+        tracked local_reads: Map<nat, UnboundedLog::local_reads<DT>>,
+        tracked local_version: &UnboundedLog::local_versions<DT>,
+    ) -> (tracked res: Map<nat, UnboundedLog::local_reads<DT>>)
+        requires
+            self.wf(),
+            local_version@.instance == self.unbounded_log_instance@,
+            forall|i: nat|
+                i < num_reads ==> {
+                    &&& #[trigger] local_reads.contains_key(i)
+                    &&& local_reads[i]@.instance == self.unbounded_log_instance@
+                    &&& local_reads[i]@.value.is_VersionUpperBound()
+                    &&& local_reads[i]@.value.get_VersionUpperBound_version_upper_bound()
+                        <= local_version@.value
+                    &&& local_reads[i]@.value.get_VersionUpperBound_node_id() == local_version@.key
+                },
+        ensures
+            forall|i: nat|
+                i < num_reads ==> {
+                    &&& #[trigger] res.contains_key(i)
+                    &&& res[i]@.instance == self.unbounded_log_instance@
+                    &&& res[i]@.key == local_reads[i]@.key
+                    &&& res[i]@.value.is_ReadyToRead()
+                    &&& res[i]@.value.get_ReadyToRead_node_id()
+                        == local_reads[i]@.value.get_VersionUpperBound_node_id()
+                    &&& res[i]@.value.get_ReadyToRead_op()
+                        == local_reads[i]@.value.get_VersionUpperBound_op()
+                    &&& res[i]@.value.get_ReadyToRead_version_upper_bound()
+                        == local_reads[i]@.value.get_VersionUpperBound_version_upper_bound()
+                },
+        decreases num_reads,
+    {
+        if num_reads == 0 {
+            return local_reads;
+        }
+        let tracked mut local_reads_new = local_reads;
+        let idx = (num_reads - 1) as nat;
+        let tracked local_read = local_reads_new.tracked_remove(idx);
+        local_reads_new = self.readonly_ready_to_read_multiple(idx, local_reads_new, local_version);
+        let tracked local_read = self.unbounded_log_instance.borrow().readonly_ready_to_read(
+            local_read@.key,
+            local_version,
+            local_read,
+        );
+        local_reads_new.tracked_insert(idx, local_read);
+        return local_reads_new;
+    }
+
+    /// loads the current version upper bound without advancing a read request
+    ///
+    /// Used by relaxed reads that tolerate a bounded staleness and therefore are not linearized
+    /// at the version upper bound. The returned witness records that the version upper bound has
+    /// reached the loaded value.
+    pub(crate) fn get_version_upper_bound_relaxed(&self) -> (ret: (
+        u64,
+        Tracked<UnboundedLog::version_upper_bound_reached<DT>>,
+    ))
+        requires
+            self.wf(),
+        ensures
+            ret.1@@.instance == self.unbounded_log_instance@,
+            ret.1@@.key == ret.0,
+    {
+        let tracked witness: UnboundedLog::version_upper_bound_reached<DT>;
+        let res =
+            atomic_with_ghost!(
+            &self.version_upper_bound.0 => load();
+            returning res;
+            ghost g => {
+                witness = self.unbounded_log_instance.borrow().version_upper_bound_witness(&g);
+            }
+        );
+        (res, Tracked(witness))
+    }
+
+    /// loads the current version upper bound, which lies above the log index of a completed update
+    ///
+    /// Used to record the session version of a thread after its update has been applied.
+    pub(crate) fn get_version_upper_bound_after_update(
+        &self,
+        stub: Tracked<&UnboundedLog::local_updates<DT>>,
+    ) -> (ret: u64)
+        requires
+            self.wf(),
+            stub@@.instance == self.unbounded_log_instance@,
+            stub@@.value.is_Done(),
+        ensures
+            stub@@.value.get_Done_idx() < ret,
+    {
+        atomic_with_ghost!(
+            &self.version_upper_bound.0 => load();
+            returning res;
+            ghost g => {
+                self.unbounded_log_instance.borrow().update_done_below_version_upper_bound(
+                    stub@@.key, &g, stub.get());
+            }
+        )
+    }
+
+    /// the replicas that threads blocked on a full log may help, indexed by their id
+    pub(crate) open spec fn wf_peers(&self, peers: Seq<Box<Replica<DT>>>) -> bool {
+        &&& peers.len() == self.local_versions.len()
+        &&& forall|i|
+            0 <= i < peers.len() ==> {
+                &&& (#[trigger] peers[i]).wf()
+                &&& peers[i].spec_id() == i
+                &&& peers[i].replica_token@ == i
+                &&& peers[i].unbounded_log_instance@ == self.unbounded_log_instance@
+                &&& peers[i].cyclic_buffer_instance@ == self.cyclic_buffer_instance@
+            }
+    }
+
+    /// finds the replica with the smallest local version, ignoring retired replicas
+    ///
+    /// This doesn't start advancing the head, the result may be outdated by the time it is used.
+    pub(crate) fn lagging_replica(&self) -> (result: ReplicaId)
+        requires
+            self.wf(),
+        ensures
+            result < self.local_versions.len(),
+    {
+        let num_replicas = self.local_versions.len();
+        let mut min_local_version = RETIRED_VERSION;
+        let mut min_replica: ReplicaId = 0;
+        let mut idx: usize = 0;
+        while idx < num_replicas
+            invariant
+                self.wf(),
+                0 <= idx <= num_replicas,
+                min_replica < num_replicas,
+                num_replicas == self.local_versions.len(),
+        {
+            let cur_local_version =
+                atomic_with_ghost!(
+                &self.local_versions[idx].0 => load();
+                ghost g => { }
+            );
+            if cur_local_version != RETIRED_VERSION && cur_local_version < min_local_version {
+                min_local_version = cur_local_version;
+                min_replica = idx;
+            }
+            idx = idx + 1;
+        }
+        min_replica
+    }
+
+    /// executes the log on behalf of the replica that holds back the head of the log
+    ///
+    /// A replica without active threads doesn't combine, its local version never advances and
+    /// appenders would wait for the garbage collection forever.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    fn help_lagging_replica(&self, node_id: ReplicaId, peers: &Vec<Box<Replica<DT>>>)
+        requires
+            self.wf(),
+            self.wf_peers(peers@),
+    {
+        let lagging = self.lagging_replica();
+        if lagging != node_id {
+            peers[lagging].try_sync(self, node_id);
+        }
     }
 
     proof fn unbounded_log_append_entries(
@@ -695,9 +1037,14 @@ impl<DT: Dispatch> NrLog<DT> {
     }
 
     /// Inserts a slice of operations into the log.
+    ///
+    /// Returns the ghost state unchanged as an error if the operations would take the tail of the
+    /// log to `MAX_IDX`. None of the operations has been appended then, and as the tail never
+    /// goes backwards, all later appends of at least as many operations fail as well.
     #[inline(always)]
-    pub fn append(
+    pub(crate) fn append(
         &self,
+        peers: &Vec<Box<Replica<DT>>>,
         replica_token: &ReplicaToken,
         operations: &Vec<DT::WriteOperation>,
         // responses and actual replica are part of the closure
@@ -705,9 +1052,13 @@ impl<DT: Dispatch> NrLog<DT> {
         actual_replica: &mut DT,
         // here we also need to pass the mut replica
         ghost_data: Tracked<NrLogAppendExecDataGhost<DT>>,
-    ) -> (result: Tracked<NrLogAppendExecDataGhost<DT>>)
+    ) -> (result: Result<
+        Tracked<NrLogAppendExecDataGhost<DT>>,
+        Tracked<NrLogAppendExecDataGhost<DT>>,
+    >)
         requires
             self.wf(),
+            self.wf_peers(peers@),
             old(actual_replica).inv(),
             replica_token@ < self.local_versions.len(),
             old(responses).len() == 0,
@@ -718,10 +1069,10 @@ impl<DT: Dispatch> NrLog<DT> {
                 self.unbounded_log_instance@,
                 self.cyclic_buffer_instance@,
             ),
-            operations.len() <= MAX_REQUESTS,
+            operations.len() <= MAX_APPEND,
         ensures
             actual_replica.inv(),
-            result@.append_post(
+            result.is_Ok() ==> result.get_Ok_0()@.append_post(
                 ghost_data@,
                 replica_token@,
                 actual_replica.view(),
@@ -730,6 +1081,18 @@ impl<DT: Dispatch> NrLog<DT> {
                 self.unbounded_log_instance@,
                 self.cyclic_buffer_instance@,
             ),
+            result.is_Err() ==> {
+                &&& result.get_Err_0()@.append_pre(
+                    replica_token@,
+                    actual_replica.view(),
+                    operations@,
+                    self.unbounded_log_instance@,
+                    self.cyclic_buffer_instance@,
+                )
+                &&& result.get_Err_0()@.local_updates == ghost_data@.local_updates
+                &&& result.get_Err_0()@.request_ids == ghost_data@.request_ids
+                &&& responses@.len() == 0
+            },
     {
         let tracked mut ghost_data_new = ghost_data.get();
         let nid = replica_token.id() as usize;
@@ -739,6 +1102,7 @@ impl<DT: Dispatch> NrLog<DT> {
         loop
             invariant
                 self.wf(),
+                self.wf_peers(peers@),
                 actual_replica.inv(),
                 0 <= waitgc <= WARN_THRESHOLD,
                 0 <= iteration <= WARN_THRESHOLD,
@@ -746,9 +1110,10 @@ impl<DT: Dispatch> NrLog<DT> {
                 replica_token@ < self.local_versions.len(),
                 nid == replica_token@,
                 nops == operations.len(),
-                nops <= MAX_REQUESTS,
+                nops <= MAX_APPEND,
                 ghost_data_new.cb_combiner@@.value == ghost_data@.cb_combiner@@.value,
                 ghost_data_new.request_ids@ == ghost_data@.request_ids@,
+                ghost_data_new.local_updates == ghost_data@.local_updates,
                 ghost_data_new.append_pre(
                     replica_token@,
                     actual_replica.view(),
@@ -769,7 +1134,7 @@ impl<DT: Dispatch> NrLog<DT> {
             let tracked mut combiner = combiner.get();
             let tracked mut local_updates = local_updates.get();
             if iteration == WARN_THRESHOLD {
-                print_starvation_warning(line!());
+                self.observer.notify(NrEvent::Starvation { replica: nid, lagging_replica: None });
                 iteration = 0;
             }
             iteration = iteration + 1;
@@ -797,7 +1162,7 @@ impl<DT: Dispatch> NrLog<DT> {
             // if tail > head + self.slog.len() - GC_FROM_HEAD  {  }
             if tail > head + (self.slog.len() as u64 - GC_FROM_HEAD as u64) {
                 if waitgc == WARN_THRESHOLD {
-                    print_starvation_warning(line!());
+                    self.observer.notify(NrEvent::LogFull { replica: nid });
                     waitgc = 0;
                 }
                 waitgc = waitgc + 1;
@@ -835,19 +1200,28 @@ impl<DT: Dispatch> NrLog<DT> {
                 }
                 // upstream has an advance_head here, but dafny doesn't
                 // let ghost_data0 = self.advance_head(replica_token, responses, actual_replica, ghost_data0);
+                // the head may be held back by a replica that has no active threads
+                self.help_lagging_replica(nid, peers);
                 continue ;
             }
             let new_tail = tail + (nops as u64);
-            // capture the warning here
+            // The log has run out of indices, return without appending any of the operations.
             if new_tail >= MAX_IDX {
-                warn_with_tail_too_big();
-                ////////////////////////////////////////////////////////////////////////////////////
-                // !!! THIS IS A PANIC CASE! WE DO NOT RETURN FROM HERE !!!
-                ////////////////////////////////////////////////////////////////////////////////////
-                #[allow(while_true)]
-                while true {
-                }  // TODO: is that fine?
-
+                proof {
+                    cb_combiner =
+                    self.cyclic_buffer_instance.borrow().advance_tail_abort(
+                        nid as nat,
+                        cb_combiner,
+                    );
+                }
+                let tracked ghost_data0 = NrLogAppendExecDataGhost {
+                    local_updates: Tracked(local_updates),
+                    ghost_replica,
+                    combiner: Tracked(combiner),
+                    cb_combiner: Tracked(cb_combiner),
+                    request_ids,
+                };
+                return Err(Tracked(ghost_data0));
             }
             // If on adding in the above entries there would be fewer than `GC_FROM_HEAD`
             // entries left on the log, then we need to advance the head of the log.
@@ -936,7 +1310,7 @@ impl<DT: Dispatch> NrLog<DT> {
                     tail + nops == new_tail,
                     nops == operations.len(),
                     nops == request_ids@.len(),
-                    buffer_size == LOG_SIZE,
+                    buffer_size == self.slog.len(),
                     cell_ids == self.cyclic_buffer_instance@.cell_ids(),
                     cell_ids.len() == buffer_size,
                     cb_combiner@.key == nid,
@@ -967,7 +1341,7 @@ impl<DT: Dispatch> NrLog<DT> {
 
                         },
                     forall|i|
-                        (tail + idx) - LOG_SIZE <= i < new_tail - LOG_SIZE
+                        (tail + idx) - buffer_size <= i < new_tail - buffer_size
                             <==> cb_log_entries.contains_key(i),
                     forall|i|
                         cb_log_entries.contains_key(i) ==> stored_type_inv(
@@ -979,7 +1353,7 @@ impl<DT: Dispatch> NrLog<DT> {
             {
                 let tracked cb_log_entry;
                 proof {
-                    cb_log_entry = cb_log_entries.tracked_remove((tail + idx) - LOG_SIZE);
+                    cb_log_entry = cb_log_entries.tracked_remove((tail + idx) - buffer_size);
                 }
                 let tracked mut cb_log_entry_perms = cb_log_entry.cell_perms;
                 // the logical index into the log
@@ -1026,7 +1400,7 @@ impl<DT: Dispatch> NrLog<DT> {
                     &&& log_entries[i]@.value.op == operations[i as int]
                 });
             assert(forall|i|
-                (tail + idx) - LOG_SIZE <= i < new_tail - LOG_SIZE <==> cb_log_entries.contains_key(
+                (tail + idx) - buffer_size <= i < new_tail - buffer_size <==> cb_log_entries.contains_key(
                     i,
                 ));
             assert(forall|i|
@@ -1055,9 +1429,9 @@ impl<DT: Dispatch> NrLog<DT> {
                     actual_replica,
                     Tracked(ghost_data_new),
                 );
-                return ghost_data_new;
+                return Ok(ghost_data_new);
             } else {
-                return Tracked(ghost_data_new);
+                return Ok(Tracked(ghost_data_new));
             }
         }
     }
@@ -1108,6 +1482,7 @@ impl<DT: Dispatch> NrLog<DT> {
                 ghost_data_new@.combiner@@.value.is_Placed() ==> ghost_data_new@.pre_exec(
                     responses@,
                 ),
+                ghost_data@.placed_consecutively(),
                 ghost_data_new@.advance_head_post(
                     ghost_data@,
                     replica_token.id_spec(),
@@ -1141,7 +1516,7 @@ impl<DT: Dispatch> NrLog<DT> {
                 returning ret;
                 ghost g => { /* no-op */ }
             );
-            let (min_local_version, cb_combiner0) = self.find_min_local_version(
+            let (min_local_version, min_replica, cb_combiner0) = self.find_min_local_version(
                 Tracked(cb_combiner),
             );
             let tracked mut cb_combiner = cb_combiner0.get();
@@ -1157,7 +1532,12 @@ impl<DT: Dispatch> NrLog<DT> {
                     );
                 }
                 if iteration == WARN_THRESHOLD {
-                    print_starvation_warning(line!());
+                    self.observer.notify(
+                        NrEvent::Starvation {
+                            replica: replica_token.id(),
+                            lagging_replica: Some(min_replica),
+                        },
+                    );
                     // let tracked cb_combiner = Tracked(cb_combiner);
                     // let tracked ghost_data = NrLogAppendExecDataGhost { local_updates, ghost_replica, combiner, cb_combiner, request_ids };
                     // return Tracked(ghost_data);
@@ -1184,6 +1564,9 @@ impl<DT: Dispatch> NrLog<DT> {
                 ghost g => {
                     cb_combiner = self.cyclic_buffer_instance.borrow().advance_head_finish(replica_token.id_spec(), &mut g, cb_combiner);
             });
+            self.observer.notify(
+                NrEvent::GcAdvance { replica: replica_token.id(), head: min_local_version },
+            );
             if global_tail < min_local_version + self.slog.len() as u64 - GC_FROM_HEAD as u64 {
                 let cb_combiner = Tracked(cb_combiner);
                 let tracked ghost_data_new = NrLogAppendExecDataGhost {
@@ -1237,6 +1620,7 @@ impl<DT: Dispatch> NrLog<DT> {
                     &&& res[i]@.instance == self.unbounded_log_instance@
                     &&& res[i]@.value.is_Done()
                     &&& res[i]@.value.get_Done_ret() == local_updates[i]@.value.get_Applied_ret()
+                    &&& res[i]@.value.get_Done_idx() == local_updates[i]@.value.get_Applied_idx()
                 },
         decreases request_ids.len(),
     {
@@ -1310,6 +1694,9 @@ impl<DT: Dispatch> NrLog<DT> {
                 combiner =
                 self.unbounded_log_instance.borrow().exec_trivial_start(nid as nat, combiner);
                 request_ids_new = Seq::empty();
+            } else if combiner@.value.is_Helped() {
+                // the helper doesn't execute any requests of the replica
+                request_ids_new = Seq::empty();
             }
         }
         // let ltail = self.ltails[idx.0 - 1].load(Ordering::Relaxed);
@@ -1318,9 +1705,16 @@ impl<DT: Dispatch> NrLog<DT> {
             &self.local_versions[nid].0 => load();
             returning ret;
             ghost g => {
+                // the replica holds the combiner token, thus it is not retired
+                self.cyclic_buffer_instance.borrow().combiner_not_retired(nid as nat, &g.2, &cb_combiner);
                 // this kicks of the state transition in both the cyclic buffer and the unbounded log
-                combiner = self.unbounded_log_instance.borrow().exec_load_local_version(nid as nat, &g.0, combiner);
-                cb_combiner = self.cyclic_buffer_instance.borrow().reader_start(nid as nat, &g.1, cb_combiner);
+                if combiner@.value.is_Helped() {
+                    combiner = self.unbounded_log_instance.borrow().exec_help_load_local_version(nid as nat, &g.0, combiner);
+                    cb_combiner = self.cyclic_buffer_instance.borrow().help_reader_start(nid as nat, &g.1, cb_combiner);
+                } else {
+                    combiner = self.unbounded_log_instance.borrow().exec_load_local_version(nid as nat, &g.0, combiner);
+                    cb_combiner = self.cyclic_buffer_instance.borrow().reader_start(nid as nat, &g.1, cb_combiner);
+                }
             }
         );
         // Check if we have any work to do by comparing our local tail with the log's
@@ -1385,8 +1779,8 @@ impl<DT: Dispatch> NrLog<DT> {
                     &&& ghost_data.combiner@@.value.get_Placed_queued_ops() == request_ids
                     &&& combiner@.value.get_Loop_queued_ops() == request_ids
                 },
-                ghost_data.combiner@@.value.is_Ready() ==> combiner@.value.get_Loop_queued_ops()
-                    == Seq::<ReqId>::empty(),
+                ghost_data.combiner@@.value.is_Ready() || ghost_data.combiner@@.value.is_Helped()
+                    ==> combiner@.value.get_Loop_queued_ops() == Seq::<ReqId>::empty(),
                 combiner@.value.queued_ops() == request_ids_new,
                 0 <= local_version <= global_tail,
                 0 <= responses_idx as nat <= request_ids_new.len(),
@@ -1417,6 +1811,7 @@ impl<DT: Dispatch> NrLog<DT> {
                         &&& local_updates[i]@.key == request_ids_new[i as int]
                         &&& local_updates[i]@.value.is_Placed()
                         &&& local_updates[i]@.instance == self.unbounded_log_instance@
+                        &&& local_updates[i] == local_updates_old[i]
                     },
                 ghost_data.combiner@@.value.is_Placed() ==> forall|i|
                     #![trigger local_updates[i]]
@@ -1428,6 +1823,8 @@ impl<DT: Dispatch> NrLog<DT> {
                         &&& local_updates[i]@.value.get_Applied_ret() == responses[i as int]
                         &&& local_updates[i]@.value.get_Applied_idx()
                             < combiner@.value.get_Loop_tail()
+                        &&& local_updates[i]@.value.get_Applied_idx()
+                            == local_updates_old[i]@.value.get_Placed_idx()
                     },
         {
             // calculating the actual index and the
@@ -1462,7 +1859,7 @@ impl<DT: Dispatch> NrLog<DT> {
                     ),
             {
                 if iteration == WARN_THRESHOLD {
-                    print_starvation_warning(line!());
+                    self.observer.notify(NrEvent::Starvation { replica: nid, lagging_replica: None });
                     iteration = 0;
                 }
                 let alive_bit =
@@ -1509,6 +1906,13 @@ impl<DT: Dispatch> NrLog<DT> {
                         let tracked local_update = local_updates.tracked_remove(
                             responses_idx as nat,
                         );
+                        // the update is applied at the log index it has been placed at
+                        self.unbounded_log_instance.borrow().pre_exec_dispatch_local_placed(
+                            nid as nat,
+                            e,
+                            &local_update,
+                            &combiner,
+                        );
                         let tracked (
                             Tracked(ghost_replica0),
                             Tracked(local_update),
@@ -1578,6 +1982,7 @@ impl<DT: Dispatch> NrLog<DT> {
         atomic_with_ghost!(
             &self.local_versions[nid].0 => store(global_tail);
             ghost g => {
+                self.cyclic_buffer_instance.borrow().combiner_not_retired(nid as nat, &g.2, &cb_combiner);
                 let tracked (Tracked(ul_local_versions), Tracked(ul_combiner))
                     = self.unbounded_log_instance.borrow().exec_finish(nid as nat, g.0, combiner);
                 let tracked (Tracked(cb_local_versions), Tracked(cb_combiner0))
@@ -1586,7 +1991,7 @@ impl<DT: Dispatch> NrLog<DT> {
                 combiner = ul_combiner;
                 cb_combiner = cb_combiner0;
 
-                g = (ul_local_versions, cb_local_versions);
+                g = (ul_local_versions, cb_local_versions, g.2);
         });
         let tracked ghost_data_ret = NrLogAppendExecDataGhost {
             local_updates: Tracked(local_updates),  // Tracked::<Map<ReqId, UnboundedLog::local_updates>>,
@@ -1608,7 +2013,8 @@ impl<DT: Dispatch> NrLog<DT> {
         Tracked(ghost_data_ret)
     }
 
-    /// Loops over all `local_versions` and finds the replica with the lowest version.
+    /// Loops over all `local_versions` and finds the replica with the lowest version. Retired
+    /// replicas are skipped.
     ///
     /// # Returns
     /// The ID (in `LogToken`) of the replica with the lowest tail and the
@@ -1618,18 +2024,19 @@ impl<DT: Dispatch> NrLog<DT> {
     pub(crate) fn find_min_local_version(
         &self,
         cb_combiner: Tracked<CyclicBuffer::combiner<DT>>,
-    ) -> (result: (u64, Tracked<CyclicBuffer::combiner<DT>>))
+    ) -> (result: (u64, ReplicaId, Tracked<CyclicBuffer::combiner<DT>>))
         requires
             self.wf(),
             cb_combiner@@.instance == self.cyclic_buffer_instance@,
             cb_combiner@@.value.is_Idle(),
         ensures
             result.0 <= MAX_IDX,
-            result.1@@.key == cb_combiner@@.key,
-            result.1@@.value.is_AdvancingHead(),
-            result.1@@.instance == self.cyclic_buffer_instance@,
-            result.1@@.value.get_AdvancingHead_idx() == self.num_replicas,
-            result.1@@.value.get_AdvancingHead_min_local_version() == result.0,
+            result.1 < self.num_replicas,
+            result.2@@.key == cb_combiner@@.key,
+            result.2@@.value.is_AdvancingHead(),
+            result.2@@.instance == self.cyclic_buffer_instance@,
+            result.2@@.value.get_AdvancingHead_idx() == self.num_replicas,
+            result.2@@.value.get_AdvancingHead_min_local_version() == result.0,
     {
         // let r = self.next.load(Ordering::Relaxed);
         let num_replicas = self.local_versions.len();
@@ -1641,9 +2048,12 @@ impl<DT: Dispatch> NrLog<DT> {
             &self.local_versions[0].0 => load();
             returning ret;
             ghost g => {
+                self.cyclic_buffer_instance.borrow().first_replica_not_retired(&g.2);
                 g_cb_comb_new = self.cyclic_buffer_instance.borrow()
                                         .advance_head_start(g_node_id, &g.1, g_cb_comb_new);
             });
+        // the replica with the smallest local version, i.e., the one holding back the head
+        let mut min_replica: ReplicaId = 0;
         // Find the smallest local tail across all replicas.
         // for idx in 1..r {
         //    let cur_local_tail = self.ltails[idx - 1].load(Ordering::Relaxed);
@@ -1655,6 +2065,7 @@ impl<DT: Dispatch> NrLog<DT> {
                 self.wf(),
                 0 <= idx <= num_replicas,
                 min_local_version <= MAX_IDX,
+                min_replica < num_replicas,
                 g_cb_comb_new@.instance == self.cyclic_buffer_instance,
                 g_cb_comb_new@.value.is_AdvancingHead(),
                 g_cb_comb_new@.value.get_AdvancingHead_idx() == idx,
@@ -1669,14 +2080,311 @@ impl<DT: Dispatch> NrLog<DT> {
                 returning ret;
                 ghost g => {
                     g_cb_comb_new = self.cyclic_buffer_instance.borrow()
-                                            .advance_head_next(g_node_id, &g.1, g_cb_comb_new);
+                                            .advance_head_next(g_node_id, &g.1, &g.2, g_cb_comb_new);
                 });
-            if cur_local_tail < min_local_version {
+            if cur_local_tail != RETIRED_VERSION && cur_local_tail < min_local_version {
                 min_local_version = cur_local_tail;
+                min_replica = idx;
             }
             idx = idx + 1;
         }
-        (min_local_version, Tracked(g_cb_comb_new))
+        (min_local_version, min_replica, Tracked(g_cb_comb_new))
+    }
+
+    /// Retires the replica `node_id` such that its local version no longer holds back the head
+    /// of the log.
+    ///
+    /// The replica must not be ahead of the first replica, otherwise it can't be activated again
+    /// with the state of the first replica. In this case, the replica is not retired and the
+    /// combiner token is returned.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub(crate) fn retire_replica(
+        &self,
+        node_id: ReplicaId,
+        cb_combiner: Tracked<CyclicBuffer::combiner<DT>>,
+        first_cb_combiner: Tracked<CyclicBuffer::combiner<DT>>,
+    ) -> (result: (
+        bool,
+        Tracked<Option<CyclicBuffer::combiner<DT>>>,
+        Tracked<CyclicBuffer::combiner<DT>>,
+    ))
+        requires
+            self.wf(),
+            0 < node_id < self.local_versions.len(),
+            cb_combiner@@.instance == self.cyclic_buffer_instance@,
+            cb_combiner@@.key == node_id,
+            cb_combiner@@.value.is_Idle(),
+            first_cb_combiner@@.instance == self.cyclic_buffer_instance@,
+            first_cb_combiner@@.key == 0,
+            first_cb_combiner@@.value.is_Idle(),
+        ensures
+            result.0 ==> result.1@.is_None(),
+            !result.0 ==> result.1@ == Option::Some(cb_combiner@),
+            result.2@@ == first_cb_combiner@@,
+    {
+        let tracked mut cb_combiner: Option<CyclicBuffer::combiner<DT>> = Option::Some(
+            cb_combiner.get(),
+        );
+        let tracked mut first_cb_combiner = first_cb_combiner.get();
+        // pin the local version of the first replica
+        let first_local_version =
+            atomic_with_ghost!(
+            &self.local_versions[0].0 => load();
+            returning ret;
+            ghost g => {
+                self.cyclic_buffer_instance.borrow().first_replica_not_retired(&g.2);
+                first_cb_combiner = self.cyclic_buffer_instance.borrow().reader_start(0, &g.1, first_cb_combiner);
+            });
+        let local_version =
+            atomic_with_ghost!(
+            &self.local_versions[node_id].0 => load();
+            returning ret;
+            ghost g => {
+                self.cyclic_buffer_instance.borrow().combiner_not_retired(node_id as nat, &g.2, cb_combiner.tracked_borrow());
+            });
+        let retired = if local_version <= first_local_version {
+            // the local version doesn't change while we hold the combiner token
+            let res =
+                atomic_with_ghost!(
+                &self.local_versions[node_id].0 => compare_exchange(local_version, RETIRED_VERSION);
+                update prev -> next;
+                ghost g => {
+                    if prev == local_version {
+                        self.cyclic_buffer_instance.borrow().combiner_not_retired(node_id as nat, &g.2, cb_combiner.tracked_borrow());
+                        let tracked retired = self.cyclic_buffer_instance.borrow().retire_replica(
+                            node_id as nat, &g.1, g.2, &first_cb_combiner, cb_combiner.tracked_take());
+                        g = (g.0, g.1, retired);
+                    }
+                });
+            res.is_ok()
+        } else {
+            false
+        };
+        proof {
+            first_cb_combiner =
+            self.cyclic_buffer_instance.borrow().reader_abort(0, first_cb_combiner);
+        }
+        (retired, Tracked(cb_combiner), Tracked(first_cb_combiner))
+    }
+
+    /// Activates the retired replica `node_id` again, its local version is set to the one of the
+    /// first replica and its state becomes the state of the first replica.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub(crate) fn activate_replica(
+        &self,
+        node_id: ReplicaId,
+        ghost_data: Tracked<NrLogActivateGhost<DT>>,
+    ) -> (result: (bool, Tracked<NrLogActivateGhost<DT>>))
+        requires
+            self.wf(),
+            0 < node_id < self.local_versions.len(),
+            ghost_data@.activate_pre(
+                node_id as nat,
+                self.unbounded_log_instance@,
+                self.cyclic_buffer_instance@,
+            ),
+        ensures
+            result.1@.activate_post(
+                ghost_data@,
+                result.0,
+                node_id as nat,
+                self.unbounded_log_instance@,
+                self.cyclic_buffer_instance@,
+            ),
+    {
+        let tracked ghost_data = ghost_data.get();
+        let tracked mut replica = ghost_data.replica.get();
+        let tracked mut combiner = ghost_data.combiner.get();
+        let tracked mut cb_combiner: Option<CyclicBuffer::combiner<DT>> = Option::None;
+        let tracked first_replica = ghost_data.first_replica.get();
+        let tracked mut first_combiner = ghost_data.first_combiner.get();
+        let tracked mut first_cb_combiner = ghost_data.first_cb_combiner.get();
+        // pin the local version and the state of the first replica
+        let first_local_version =
+            atomic_with_ghost!(
+            &self.local_versions[0].0 => load();
+            returning ret;
+            ghost g => {
+                self.cyclic_buffer_instance.borrow().first_replica_not_retired(&g.2);
+                first_combiner = self.unbounded_log_instance.borrow().exec_trivial_start(0, first_combiner);
+                first_combiner = self.unbounded_log_instance.borrow().exec_load_local_version(0, &g.0, first_combiner);
+                first_cb_combiner = self.cyclic_buffer_instance.borrow().reader_start(0, &g.1, first_cb_combiner);
+            });
+        let res =
+            atomic_with_ghost!(
+            &self.local_versions[node_id].0 => compare_exchange(RETIRED_VERSION, first_local_version);
+            update prev -> next;
+            ghost g => {
+                if prev == RETIRED_VERSION {
+                    self.cyclic_buffer_instance.borrow().retired_version_bounded(node_id as nat, &g.1, &g.2, &first_cb_combiner);
+                    let tracked (Tracked(ul_replica), Tracked(ul_local_version), Tracked(ul_combiner))
+                        = self.unbounded_log_instance.borrow().replica_clone(
+                            node_id as nat, 0, &first_replica, replica, g.0, &first_combiner, combiner);
+                    let tracked (Tracked(cb_local_version), Tracked(cb_retired), Tracked(cb_combiner0))
+                        = self.cyclic_buffer_instance.borrow().activate_replica(
+                            node_id as nat, g.1, g.2, &first_cb_combiner);
+                    replica = ul_replica;
+                    combiner = ul_combiner;
+                    cb_combiner = Option::Some(cb_combiner0);
+                    g = (ul_local_version, cb_local_version, cb_retired);
+                }
+            });
+        proof {
+            first_combiner =
+            self.unbounded_log_instance.borrow().exec_load_local_version_abort(0, first_combiner);
+            first_cb_combiner =
+            self.cyclic_buffer_instance.borrow().reader_abort(0, first_cb_combiner);
+        }
+        let tracked ghost_data_ret = NrLogActivateGhost {
+            replica: Tracked(replica),
+            combiner: Tracked(combiner),
+            cb_combiner: Tracked(cb_combiner),
+            first_replica: Tracked(first_replica),
+            first_combiner: Tracked(first_combiner),
+            first_cb_combiner: Tracked(first_cb_combiner),
+        };
+        (res.is_ok(), Tracked(ghost_data_ret))
+    }
+
+    /// Loads the local version of a replica and records the state of the replica as a snapshot
+    /// at this version. The caller must prevent the replica from changing, i.e., hold the read
+    /// lock on its data structure.
+    ///
+    /// Returns no snapshot if the replica has been retired.
+    pub(crate) fn snapshot_version(
+        &self,
+        node_id: ReplicaId,
+        replica: Tracked<&UnboundedLog::replicas<DT>>,
+        combiner: Tracked<&UnboundedLog::combiner<DT>>,
+    ) -> (result: (u64, Tracked<Option<UnboundedLog::snapshots<DT>>>))
+        requires
+            self.wf(),
+            node_id < self.local_versions.len(),
+            replica@@.instance == self.unbounded_log_instance@,
+            replica@@.key == node_id,
+            combiner@@.instance == self.unbounded_log_instance@,
+            combiner@@.key == node_id,
+            combiner@@.value.is_Ready(),
+        ensures
+            result.0 != RETIRED_VERSION ==> {
+                &&& result.1@.is_Some()
+                &&& result.1@.get_Some_0()@.instance == self.unbounded_log_instance@
+                &&& result.1@.get_Some_0()@.key == result.0
+                &&& result.1@.get_Some_0()@.value == replica@@.value
+            },
+    {
+        let tracked mut snapshot: Option<UnboundedLog::snapshots<DT>> = Option::None;
+        let version =
+            atomic_with_ghost!(
+            &self.local_versions[node_id].0 => load();
+            returning ret;
+            ghost g => {
+                if !g.2@.value {
+                    snapshot = Option::Some(self.unbounded_log_instance.borrow().replica_snapshot(
+                        node_id as nat, replica.get(), &g.0, combiner.get()));
+                }
+            });
+        (version, Tracked(snapshot))
+    }
+
+    /// Activates a retired replica with the state of a snapshot.
+    ///
+    /// The first replica pins its local version and the tail of the log, the version of the
+    /// snapshot must be in between. Otherwise, the replica either would hold back the head of the
+    /// log, or it would skip entries that haven't been appended yet.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub(crate) fn activate_replica_from_snapshot(
+        &self,
+        node_id: ReplicaId,
+        version: u64,
+        snapshot: Tracked<&UnboundedLog::snapshots<DT>>,
+        ghost_data: Tracked<NrLogActivateGhost<DT>>,
+    ) -> (result: (bool, Tracked<NrLogActivateGhost<DT>>))
+        requires
+            self.wf(),
+            0 < node_id < self.local_versions.len(),
+            snapshot@@.instance == self.unbounded_log_instance@,
+            snapshot@@.key == version,
+            ghost_data@.activate_pre(
+                node_id as nat,
+                self.unbounded_log_instance@,
+                self.cyclic_buffer_instance@,
+            ),
+        ensures
+            result.1@.activate_snapshot_post(
+                ghost_data@,
+                result.0,
+                node_id as nat,
+                snapshot@@.value,
+                self.unbounded_log_instance@,
+                self.cyclic_buffer_instance@,
+            ),
+    {
+        let tracked ghost_data = ghost_data.get();
+        let tracked mut replica = ghost_data.replica.get();
+        let tracked mut combiner = ghost_data.combiner.get();
+        let tracked mut cb_combiner: Option<CyclicBuffer::combiner<DT>> = Option::None;
+        let tracked first_replica = ghost_data.first_replica.get();
+        let tracked first_combiner = ghost_data.first_combiner.get();
+        let tracked mut first_cb_combiner = ghost_data.first_cb_combiner.get();
+        // pin the local version of the first replica and the tail of the log
+        let first_local_version =
+            atomic_with_ghost!(
+            &self.local_versions[0].0 => load();
+            returning ret;
+            ghost g => {
+                self.cyclic_buffer_instance.borrow().first_replica_not_retired(&g.2);
+                first_cb_combiner = self.cyclic_buffer_instance.borrow().reader_start(0, &g.1, first_cb_combiner);
+            });
+        let tail =
+            atomic_with_ghost!(
+            &self.tail.0 => load();
+            returning ret;
+            ghost g => {
+                first_cb_combiner = self.cyclic_buffer_instance.borrow().reader_enter(0, &g.1, first_cb_combiner);
+            });
+        let mut activated = false;
+        if first_local_version <= version && version <= tail {
+            let res =
+                atomic_with_ghost!(
+                &self.local_versions[node_id].0 => compare_exchange(RETIRED_VERSION, version);
+                update prev -> next;
+                ghost g => {
+                    if prev == RETIRED_VERSION {
+                        self.cyclic_buffer_instance.borrow().retired_version_bounded_range(node_id as nat, &g.1, &g.2, &first_cb_combiner);
+                        let tracked (Tracked(ul_replica), Tracked(ul_local_version), Tracked(ul_combiner))
+                            = self.unbounded_log_instance.borrow().replica_restore(
+                                node_id as nat, version as nat, replica, g.0, combiner, snapshot.get());
+                        let tracked (Tracked(cb_local_version), Tracked(cb_retired), Tracked(cb_combiner0))
+                            = self.cyclic_buffer_instance.borrow().activate_replica_at(
+                                node_id as nat, version as nat, g.1, g.2, &first_cb_combiner);
+                        replica = ul_replica;
+                        combiner = ul_combiner;
+                        cb_combiner = Option::Some(cb_combiner0);
+                        g = (ul_local_version, cb_local_version, cb_retired);
+                    }
+                });
+            activated = res.is_ok();
+        }
+        proof {
+            first_cb_combiner =
+            self.cyclic_buffer_instance.borrow().reader_abort(0, first_cb_combiner);
+        }
+        let tracked ghost_data_ret = NrLogActivateGhost {
+            replica: Tracked(replica),
+            combiner: Tracked(combiner),
+            cb_combiner: Tracked(cb_combiner),
+            first_replica: Tracked(first_replica),
+            first_combiner: Tracked(first_combiner),
+            first_cb_combiner: Tracked(first_cb_combiner),
+        };
+        (activated, Tracked(ghost_data_ret))
     }
 }
 
@@ -1756,7 +2464,9 @@ impl<DT: Dispatch> NrLogAppendExecDataGhost<DT> {
         &&& self.common_pred(nid, data, inst, cb_inst)
         &&& self.combiner@@.value.is_Ready() || self.combiner@@.value.is_Placed()
         &&& self.combiner@@.value.is_Ready() ==> self.post_exec(pre.request_ids@, responses)
+        &&& self.combiner@@.value.is_Ready() ==> self.done_consecutively()
         &&& self.combiner@@.value.is_Placed() ==> self.pre_exec(responses)
+        &&& self.combiner@@.value.is_Placed() ==> self.placed_consecutively()
         &&& self.cb_combiner@@.value
             == pre.cb_combiner@@.value  // other fields in common_pred
 
@@ -1772,10 +2482,13 @@ impl<DT: Dispatch> NrLogAppendExecDataGhost<DT> {
         cb_inst: CyclicBuffer::Instance<DT>,
     ) -> bool {
         &&& self.common_pred(nid, data, inst, cb_inst)
-        &&& self.cb_combiner@@.value.is_Idle()
+        // a helper has taken over the combiners in both state machines, or neither of them
+        &&& self.combiner@@.value.is_Helped() <==> self.cb_combiner@@.value.is_Helped()
+        &&& !self.combiner@@.value.is_Helped() ==> self.cb_combiner@@.value.is_Idle()
         &&& self.combiner@@.value.is_Ready() || self.combiner@@.value.is_Placed()
+            || self.combiner@@.value.is_Helped()
         &&& self.combiner@@.value.is_Placed() ==> self.pre_exec(responses)
-        &&& self.combiner@@.value.is_Ready() ==> {
+        &&& self.combiner@@.value.is_Ready() || self.combiner@@.value.is_Helped() ==> {
             &&& self.request_ids@.len() == responses.len()
         }
     }
@@ -1791,16 +2504,29 @@ impl<DT: Dispatch> NrLogAppendExecDataGhost<DT> {
         cb_inst: CyclicBuffer::Instance<DT>,
     ) -> bool {
         &&& self.common_pred(nid, data, inst, cb_inst)
-        &&& self.cb_combiner@@.value == pre.cb_combiner@@.value
+        &&& !pre.combiner@@.value.is_Helped() ==> self.cb_combiner@@.value
+            == pre.cb_combiner@@.value
         &&& self.request_ids == pre.request_ids
         &&& pre.combiner@@.value.is_Placed() ==> {
             &&& self.post_exec(pre.request_ids@, responses)
+            // the updates are applied at the log indices they have been placed at
+            &&& forall|i|
+                #![trigger self.local_updates@[i]]
+                0 <= i < pre.request_ids@.len() ==> self.local_updates@[i]@.value.get_Done_idx()
+                    == pre.local_updates@[i]@.value.get_Placed_idx()
         }
         &&& pre.combiner@@.value.is_Ready() ==> {
             &&& self.combiner@@.value == pre.combiner@@.value
             &&& self.local_updates == pre.local_updates
             &&& responses == responses_old
         }
+        // the helper hands the combiners back in their idle states
+        &&& pre.combiner@@.value.is_Helped() ==> {
+            &&& self.combiner@@.value.is_Ready()
+            &&& self.cb_combiner@@.value.is_Idle()
+            &&& self.local_updates == pre.local_updates
+            &&& responses == responses_old
+        }
     }
 
     pub open spec fn advance_head_pre(
@@ -1813,6 +2539,7 @@ impl<DT: Dispatch> NrLogAppendExecDataGhost<DT> {
     ) -> bool {
         &&& self.common_pred(nid, data, inst, cb_inst)
         &&& self.pre_exec(responses)
+        &&& self.placed_consecutively()
         &&& self.cb_combiner@@.value.is_Idle()
         &&& self.combiner@@.value.is_Placed()
     }
@@ -1832,6 +2559,7 @@ impl<DT: Dispatch> NrLogAppendExecDataGhost<DT> {
         &&& self.combiner@@.value.is_Ready() || self.combiner@@.value.is_Placed()
         &&& self.combiner@@.value.is_Ready() ==> {
             &&& self.post_exec(self.request_ids@, responses)
+            &&& self.done_consecutively()
         }
         &&& self.combiner@@.value.is_Placed() ==> {
             &&& responses.len() == 0
@@ -1841,6 +2569,23 @@ impl<DT: Dispatch> NrLogAppendExecDataGhost<DT> {
         }
     }
 
+    /// the updates have been placed into consecutive entries of the log
+    pub open spec fn placed_consecutively(&self) -> bool {
+        forall|i|
+            #![trigger self.local_updates@[i]]
+            0 <= i < self.request_ids@.len() ==> self.local_updates@[i]@.value.get_Placed_idx()
+                == self.local_updates@[0]@.value.get_Placed_idx() + i
+    }
+
+    /// the updates have been applied at consecutive entries of the log, i.e., they linearize
+    /// one after the other without any other update in between
+    pub open spec fn done_consecutively(&self) -> bool {
+        forall|i|
+            #![trigger self.local_updates@[i]]
+            0 <= i < self.request_ids@.len() ==> self.local_updates@[i]@.value.get_Done_idx()
+                == self.local_updates@[0]@.value.get_Done_idx() + i
+    }
+
     // corresponds to Dafny's pre_exec() function
     pub open spec fn pre_exec(&self, responses: Seq<DT::Response>) -> bool {
         &&& responses.len() == 0
@@ -1874,6 +2619,103 @@ impl<DT: Dispatch> NrLogAppendExecDataGhost<DT> {
     }
 }
 
+/// Data structure that is passed to the log to activate a retired replica with the state of the
+/// first replica.
+pub tracked struct NrLogActivateGhost<DT: Dispatch> {
+    pub  /* REVIEW (crate) */
+     replica: Tracked<UnboundedLog::replicas<DT>>,
+    pub  /* REVIEW (crate) */
+     combiner: Tracked<UnboundedLog::combiner<DT>>,
+    /// the combiner token of the cyclic buffer, obtained when the replica got activated
+    pub  /* REVIEW (crate) */
+     cb_combiner: Tracked<Option<CyclicBuffer::combiner<DT>>>,
+    pub  /* REVIEW (crate) */
+     first_replica: Tracked<UnboundedLog::replicas<DT>>,
+    pub  /* REVIEW (crate) */
+     first_combiner: Tracked<UnboundedLog::combiner<DT>>,
+    pub  /* REVIEW (crate) */
+     first_cb_combiner: Tracked<CyclicBuffer::combiner<DT>>,
+}
+
+impl<DT: Dispatch> NrLogActivateGhost<DT> {
+    pub open spec fn activate_pre(
+        &self,
+        nid: NodeId,
+        inst: UnboundedLog::Instance<DT>,
+        cb_inst: CyclicBuffer::Instance<DT>,
+    ) -> bool {
+        &&& self.replica@@.instance == inst
+        &&& self.replica@@.key == nid
+        &&& self.combiner@@.instance == inst
+        &&& self.combiner@@.key == nid
+        &&& self.combiner@@.value.is_Ready()
+        &&& self.cb_combiner@.is_None()
+        &&& self.first_replica@@.instance == inst
+        &&& self.first_replica@@.key == 0
+        &&& self.first_combiner@@.instance == inst
+        &&& self.first_combiner@@.key == 0
+        &&& self.first_combiner@@.value.is_Ready()
+        &&& self.first_cb_combiner@@.instance == cb_inst
+        &&& self.first_cb_combiner@@.key == 0
+        &&& self.first_cb_combiner@@.value.is_Idle()
+    }
+
+    pub open spec fn activate_post(
+        &self,
+        pre: Self,
+        activated: bool,
+        nid: NodeId,
+        inst: UnboundedLog::Instance<DT>,
+        cb_inst: CyclicBuffer::Instance<DT>,
+    ) -> bool {
+        &&& self.combiner@@ == pre.combiner@@
+        &&& self.first_replica@@ == pre.first_replica@@
+        &&& self.first_combiner@@ == pre.first_combiner@@
+        &&& self.first_cb_combiner@@ == pre.first_cb_combiner@@
+        &&& activated ==> {
+            &&& self.replica@@.instance == inst
+            &&& self.replica@@.key == nid
+            &&& self.replica@@.value == pre.first_replica@@.value
+            &&& self.cb_combiner@.is_Some()
+            &&& self.cb_combiner@.get_Some_0()@.instance == cb_inst
+            &&& self.cb_combiner@.get_Some_0()@.key == nid
+            &&& self.cb_combiner@.get_Some_0()@.value.is_Idle()
+        }
+        &&& !activated ==> {
+            &&& self.replica@@ == pre.replica@@
+            &&& self.cb_combiner@.is_None()
+        }
+    }
+
+    pub open spec fn activate_snapshot_post(
+        &self,
+        pre: Self,
+        activated: bool,
+        nid: NodeId,
+        state: DT::View,
+        inst: UnboundedLog::Instance<DT>,
+        cb_inst: CyclicBuffer::Instance<DT>,
+    ) -> bool {
+        &&& self.combiner@@ == pre.combiner@@
+        &&& self.first_replica@@ == pre.first_replica@@
+        &&& self.first_combiner@@ == pre.first_combiner@@
+        &&& self.first_cb_combiner@@ == pre.first_cb_combiner@@
+        &&& activated ==> {
+            &&& self.replica@@.instance == inst
+            &&& self.replica@@.key == nid
+            &&& self.replica@@.value == state
+            &&& self.cb_combiner@.is_Some()
+            &&& self.cb_combiner@.get_Some_0()@.instance == cb_inst
+            &&& self.cb_combiner@.get_Some_0()@.key == nid
+            &&& self.cb_combiner@.get_Some_0()@.value.is_Idle()
+        }
+        &&& !activated ==> {
+            &&& self.replica@@ == pre.replica@@
+            &&& self.cb_combiner@.is_None()
+        }
+    }
+}
+
 struct_with_invariants!{
 /// keeps track of the recursive state when applying updates to the unbounded log
 tracked struct AppendEntriesGhostState<DT: Dispatch> {
@@ -1912,6 +2754,7 @@ pub open spec fn wf(&self, nid: nat, ridx: nat, inst: UnboundedLog::Instance<DT>
             &&& self.local_updates[i]@.key == self.request_ids[i as int]
             &&& self.local_updates[i]@.value.is_Placed()
             &&& self.local_updates[i]@.value.get_Placed_op() == self.operations[i as int]
+            &&& self.local_updates[i]@.value.get_Placed_idx() == self.old_tail + i
         })
 
         // unprocessed entries
@@ -1954,7 +2797,7 @@ pub open spec fn wf(&self, num_replicas: nat) -> bool {
             &&& #[trigger] self.replicas.contains_key(i)
             &&& self.replicas[i]@.instance == self.unbounded_log_instance
             &&& self.replicas[i]@.key == i
-            &&& self.replicas[i]@.value == DT::init_spec()
+            &&& self.replicas[i]@.value == self.unbounded_log_instance.init_state()
         })
 
         &&& (forall |i| #![trigger self.combiners[i]]0 <= i < self.num_replicas ==> {
@@ -5,23 +5,30 @@
 use builtin::*;
 use builtin_macros::*;
 
-use vstd::prelude::*;
+use vstd::{map::Map, prelude::*};
 
-use crate::Dispatch;
+use crate::{CloneState, Dispatch};
+#[cfg(verus_keep_ghost)]
+use crate::{is_update_stub, is_update_ticket};
 
 // spec imports
-use crate::spec::{cyclicbuffer::CyclicBuffer, unbounded_log::UnboundedLog};
+use crate::spec::{cyclicbuffer::CyclicBuffer, types::NodeId, unbounded_log::UnboundedLog};
 
 // exec imports
-use crate::exec::context::ThreadToken;
-use crate::exec::log::{NrLog, NrLogTokens};
-use crate::exec::replica::{Replica, ReplicaConfig, ReplicaId};
+use crate::exec::context::{PendingHandle, ThreadToken};
+use crate::exec::log::{NrLog, NrLogActivateGhost, NrLogTokens};
+use crate::exec::replica::{
+    Replica, ReplicaConfig, ReplicaId, ReplicaToken, ReplicatedDataStructure,
+};
 
-use crate::constants::{LOG_SIZE, MAX_REPLICAS, MAX_THREADS_PER_REPLICA};
-use crate::AffinityFn;
+use crate::constants::{MAX_REPLICAS, MAX_REQUESTS, MAX_THREADS_PER_REPLICA, RETIRED_VERSION};
+use crate::exec::config::NrConfig;
+use crate::{AffinityFn, NodeReplicatedT, NrError, Observer};
 
+pub mod config;
 pub mod context;
 pub mod log;
+pub mod multilog;
 pub mod replica;
 pub mod rwlock;
 pub mod utils;
@@ -54,75 +61,114 @@ pub struct NodeReplicated<DT: Dispatch> {
     // replicas: Vec<Box<Replica<DataStructureType, UpdateOp, ReturnType>>>,
     pub  /* REVIEW (crate) */
      replicas: Vec<Box<Replica<DT>>>,
+    /// whether the replica has been retired, retired replicas don't execute operations
+    pub  /* REVIEW (crate) */
+     retired: Vec<bool>,
+    /// the replica tokens of the retired replicas
+    pub  /* REVIEW (crate) */
+     retired_replicas: Tracked<Map<NodeId, UnboundedLog::replicas<DT>>>,
+    /// the combiner tokens of the retired replicas
+    pub  /* REVIEW (crate) */
+     retired_combiners: Tracked<Map<NodeId, UnboundedLog::combiner<DT>>>,
     // pub /* REVIEW: (crate) */ thread_tokens: Vec<Vec<ThreadToken<DT>>>,
     /// XXX: should that be here, or go into the NrLog / replicas?
     pub unbounded_log_instance: Tracked<UnboundedLog::Instance<DT>>,
     pub cyclic_buffer_instance: Tracked<CyclicBuffer::Instance<DT>>,
 }
 
-impl<DT: Dispatch> crate::ThreadTokenT<DT, Replica<DT>> for ThreadToken<DT> {
-    open spec fn wf(&self, replica: &Replica<DT>) -> bool {
-        ThreadToken::<DT>::wf(self, replica)
-    }
-
-    open spec fn replica_id_spec(&self) -> nat {
-        ThreadToken::<DT>::replica_id_spec(self)
-    }
-}
-
-impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
-    type Replica = Replica<DT>;
-
-    type ReplicaId = ReplicaId;
-
-    type TT = ThreadToken<DT>;
-
-    /// Wellformedness of the NodeReplicated data structure
-    open spec fn wf(&self) -> bool {
-        // the log shall be well-formed and the instances match
-        &&& self.log.wf()
-        &&& self.unbounded_log_instance@ == self.log.unbounded_log_instance@
-        &&& self.cyclic_buffer_instance@
-            == self.log.cyclic_buffer_instance@
-        // the number of replicas should be the as configured
-
-        &&& self.replicas.len()
-            <= MAX_REPLICAS
-        // the replicas should be well-formed and the instances match
-
-        &&& (forall|i|
-            0 <= i < self.replicas.len() ==> {
-                &&& (#[trigger] self.replicas[i]).wf()
-                &&& self.replicas[i].spec_id() == i
-                &&& self.replicas[i].replica_token@ == i
-                &&& self.replicas[i].unbounded_log_instance@ == self.unbounded_log_instance@
-                &&& self.replicas[i].cyclic_buffer_instance@ == self.cyclic_buffer_instance@
-            })
+impl<DT: Dispatch + Sync> NodeReplicated<DT> {
+    /// Creates a new, replicated data-structure with the given configuration of the log size,
+    /// the number of replicas and the number of threads per replica.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A (NodeReplicated::new with Log::new_with_bytes)
+    pub fn with_config(config: NrConfig, chg_mem_affinity: AffinityFn) -> (res: Self)
+        requires
+            config.wf(),
+        ensures
+            res.wf(),
+            res.replicas.len() == config.max_replicas,
+    {
+        Self::new_with_affinity(&config, &chg_mem_affinity, Observer::stderr())
     }
 
-    open spec fn replicas(&self) -> Vec<Box<Self::Replica>> {
-        self.replicas
+    /// Creates a new, replicated data-structure with the given configuration that reports its
+    /// progress and diagnostic events to the observer.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub fn with_observer(config: NrConfig, chg_mem_affinity: AffinityFn, observer: Observer) -> (res:
+        Self)
+        requires
+            config.wf(),
+        ensures
+            res.wf(),
+            res.replicas.len() == config.max_replicas,
+    {
+        Self::new_with_affinity(&config, &chg_mem_affinity, observer)
     }
 
-    open spec fn unbounded_log_instance(&self) -> UnboundedLog::Instance<DT> {
-        self.log.unbounded_log_instance@
+    /// Creates a new, replicated data-structure, borrowing the affinity function such that it
+    /// can be reused by the caller, e.g., to create several logs.
+    pub(crate) fn new_with_affinity(
+        nr_config: &NrConfig,
+        chg_mem_affinity: &AffinityFn,
+        observer: Observer,
+    ) -> (res: Self)
+        requires
+            nr_config.wf(),
+        ensures
+            res.wf(),
+            res.replicas.len() == nr_config.max_replicas,
+            res.unbounded_log_instance@.init_state() == DT::init_spec(),
+    {
+        let num_replicas = nr_config.max_replicas;
+        // create the initial data structures, the last one is used by the first replica
+        let mut states: Vec<DT> = Vec::with_capacity(num_replicas);
+        let mut idx = 0;
+        while idx < num_replicas
+            invariant
+                0 <= idx <= num_replicas,
+                states.len() == idx,
+                forall|i| #![trigger states[i]] 0 <= i < idx ==> states[i].inv() && states[i]@ == DT::init_spec(),
+        {
+            chg_mem_affinity.call(num_replicas - 1 - idx);
+            states.push(DT::init());
+            idx = idx + 1;
+        }
+        Self::with_states(nr_config, chg_mem_affinity, observer, states, Ghost(DT::init_spec()))
     }
 
-    /// Creates a new, replicated data-structure from a single-threaded
-    /// data-structure that implements [`Dispatch`]. It uses the [`Default`]
-    /// constructor to create a initial data-structure for `D` on all replicas.
-    ///
-    ///  - Dafny: n/a ?
-    ///  - Rust:  pub fn new(num_replicas: NonZeroUsize) -> Result<Self, NodeReplicatedError>
-    fn new(num_replicas: usize, chg_mem_affinity: AffinityFn) -> (res:
-        Self)
-    // requires
-    //     num_replicas <= MAX_REPLICAS
-    // ensures res.wf()
+    /// Creates a new, replicated data-structure where the replicas start with the given data
+    /// structures, which all have the same state. The replica `i` takes the data structure at
+    /// position `max_replicas - 1 - i`. The replicas beyond `num_replicas` are retired again.
+    pub(crate) fn with_states(
+        nr_config: &NrConfig,
+        chg_mem_affinity: &AffinityFn,
+        observer: Observer,
+        states: Vec<DT>,
+        init_state: Ghost<DT::View>,
+    ) -> (res: Self)
+        requires
+            nr_config.wf(),
+            states.len() == nr_config.max_replicas,
+            forall|i| #![trigger states[i]] 0 <= i < states.len() ==> states[i].inv() && states[i]@ == init_state@,
+        ensures
+            res.wf(),
+            res.replicas.len() == nr_config.max_replicas,
+            res.unbounded_log_instance@.init_state() == init_state@,
     {
+        let num_replicas = nr_config.max_replicas;
+        let threads_per_replica = nr_config.threads_per_replica;
+        let mut states = states;
         // switch affinity to the first replica
         chg_mem_affinity.call(0);
-        let (log, replica_tokens, nr_log_tokens) = NrLog::new(num_replicas, LOG_SIZE);
+        let (log, replica_tokens, nr_log_tokens) = NrLog::new(
+            num_replicas,
+            nr_config.log_size,
+            observer,
+            init_state,
+        );
         let tracked NrLogTokens {
             num_replicas: _,
             replicas: mut replicas,
@@ -137,10 +183,14 @@ impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
         while idx < num_replicas
             invariant
                 num_replicas <= MAX_REPLICAS,
+                0 < threads_per_replica <= MAX_THREADS_PER_REPLICA,
                 unbounded_log_instance.num_replicas() == num_replicas,
+                unbounded_log_instance.init_state() == init_state@,
                 cyclic_buffer_instance.num_replicas() == num_replicas,
                 cyclic_buffer_instance.unbounded_log_instance() == unbounded_log_instance,
                 0 <= idx <= num_replicas,
+                states.len() == num_replicas - idx,
+                forall|i| #![trigger states[i]] 0 <= i < states.len() ==> states[i].inv() && states[i]@ == init_state@,
                 replica_tokens.len() == num_replicas,
                 forall|i| 0 <= i < num_replicas ==> (#[trigger] replica_tokens[i]).id_spec() == i,
                 actual_replicas.len() == idx,
@@ -160,7 +210,7 @@ impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
                         &&& #[trigger] replicas.contains_key(i)
                         &&& replicas[i]@.instance == unbounded_log_instance
                         &&& replicas[i]@.key == i
-                        &&& replicas[i]@.value == DT::init_spec()
+                        &&& replicas[i]@.value == init_state@
                     }),
                 (forall|i|
                     #![trigger combiners[i]]
@@ -190,9 +240,10 @@ impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
                 unbounded_log_instance: unbounded_log_instance.clone(),
                 cyclic_buffer_instance: cyclic_buffer_instance.clone(),
             };
+            let data = states.pop().unwrap();
             // switch the affinity of the replica before we do the allocation
             chg_mem_affinity.call(replica_token.id());
-            let replica = Replica::new(replica_token, MAX_THREADS_PER_REPLICA, Tracked(config));
+            let replica = Replica::with_data(replica_token, threads_per_replica, data, Tracked(config));
             actual_replicas.push(Box::new(replica));
             idx = idx + 1;
         }
@@ -201,12 +252,726 @@ impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
         chg_mem_affinity.call(0);
         let unbounded_log_instance = Tracked(unbounded_log_instance);
         let cyclic_buffer_instance = Tracked(cyclic_buffer_instance);
-        NodeReplicated {
+        let mut retired: Vec<bool> = Vec::with_capacity(num_replicas);
+        let mut idx = 0;
+        while idx < num_replicas
+            invariant
+                0 <= idx <= num_replicas,
+                retired.len() == idx,
+                forall|i| 0 <= i < idx ==> !(#[trigger] retired[i]),
+        {
+            retired.push(false);
+            idx = idx + 1;
+        }
+        let mut nr = NodeReplicated {
             log,
             replicas: actual_replicas,
+            retired,
+            retired_replicas: Tracked(Map::tracked_empty()),
+            retired_combiners: Tracked(Map::tracked_empty()),
             unbounded_log_instance,
             cyclic_buffer_instance,
+        };
+        // the replicas beyond the initial ones are brought into service with add_replica
+        let mut replica_id = nr_config.num_replicas;
+        while replica_id < num_replicas
+            invariant
+                nr.wf(),
+                nr.replicas.len() == num_replicas,
+                nr.unbounded_log_instance@.init_state() == init_state@,
+                0 < replica_id <= num_replicas,
+        {
+            nr.retire_replica(replica_id);
+            replica_id = replica_id + 1;
+        }
+        nr
+    }
+
+    /// Retires the replica with the given id. The replica no longer executes operations and its
+    /// local version no longer holds back the garbage collection of the log. The first replica
+    /// can't be retired.
+    ///
+    /// The operations that are pending on the replica are executed before it is retired, their
+    /// responses are still obtained with [`NodeReplicated::poll`]. The data structure of a
+    /// retired replica is dropped, its write lock stays taken until the replica is replaced by
+    /// [`NodeReplicated::add_replica`].
+    ///
+    /// Returns false if the replica couldn't be retired. This is the case if operations are still
+    /// pending on the replica after combining, or if the replica is ahead of the first replica in
+    /// the log, as it can't be activated again with the state of the first replica. The first
+    /// replica is brought up to date with the log beforehand, such that the latter doesn't happen
+    /// when the updates went through other replicas.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub fn retire_replica(&mut self, replica_id: ReplicaId) -> (result: bool)
+        requires
+            old(self).wf(),
+        ensures
+            self.wf(),
+            self.replicas.len() == old(self).replicas.len(),
+            self.unbounded_log_instance@ == old(self).unbounded_log_instance@,
+            result ==> self.retired[replica_id as int],
+    {
+        if replica_id == 0 || replica_id >= self.replicas.len() || self.retired[replica_id] {
+            return false;
+        }
+        // drain the pending operations of the replica, we have exclusive access to the data
+        // structure, hence the combiner lock is not contended and no operations are added. A
+        // round of combining collects at most `MAX_APPEND` operations, but at least one, hence
+        // there are at most `MAX_REQUESTS` rounds.
+        let mut rounds: usize = 0;
+        while rounds < MAX_REQUESTS
+            && (&self.replicas[replica_id]).has_pending_ops()
+            invariant
+                self.wf(),
+                self.replicas.len() == old(self).replicas.len(),
+                self.unbounded_log_instance@ == old(self).unbounded_log_instance@,
+                0 < replica_id < self.replicas.len(),
+                !self.retired[replica_id as int],
+        {
+            (&self.replicas[replica_id]).try_combine(&self.log, &self.replicas);
+            rounds = rounds + 1;
+        }
+        if (&self.replicas[replica_id]).has_pending_ops() {
+            return false;
+        }
+        // bring the first replica up to date with the log, the replica must not be ahead of it
+        (&self.replicas[0]).try_sync(&self.log, replica_id);
+        // the write locks are not contended either
+        let (first, first_handle) = self.replicas[0].data.0.acquire_write();
+        let (retiring, retiring_handle) = self.replicas[replica_id].data.0.acquire_write();
+        let ReplicatedDataStructure {
+            data: first_data,
+            replica: first_replica,
+            combiner: first_combiner,
+            cb_combiner: first_cb_combiner,
+        } = first;
+        let ReplicatedDataStructure { data, replica, combiner, cb_combiner } = retiring;
+        let (retired, cb_combiner, first_cb_combiner) = self.log.retire_replica(
+            replica_id,
+            cb_combiner,
+            first_cb_combiner,
+        );
+        let first = ReplicatedDataStructure {
+            data: first_data,
+            replica: first_replica,
+            combiner: first_combiner,
+            cb_combiner: first_cb_combiner,
+        };
+        self.replicas[0].data.0.release_write(first, first_handle);
+        if retired {
+            // the write lock of the retired replica stays taken, we keep its tokens such that
+            // the replica can be activated again.
+            proof {
+                self.retired_replicas.borrow_mut().tracked_insert(replica_id as nat, replica.get());
+                self.retired_combiners.borrow_mut().tracked_insert(
+                    replica_id as nat,
+                    combiner.get(),
+                );
+            }
+            self.retired.set(replica_id, true);
+        } else {
+            let retiring = ReplicatedDataStructure {
+                data,
+                replica,
+                combiner,
+                cb_combiner: Tracked(cb_combiner.get().tracked_unwrap()),
+            };
+            self.replicas[replica_id].data.0.release_write(retiring, retiring_handle);
+        }
+        retired
+    }
+
+    /// Submits a mutable operation against the data-structure without waiting for its response.
+    /// The returned handle is passed to [`NodeReplicated::poll`] to obtain the response. A thread
+    /// can have up to `MAX_PENDING_OPS` operations pending at a time, they are applied in the
+    /// order of submission. The ticket is returned if the operation submitted `MAX_PENDING_OPS`
+    /// operations before is still pending, or if the replica has been retired.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub fn submit_mut(
+        &self,
+        op: DT::WriteOperation,
+        tkn: &mut ThreadToken<DT>,
+        ticket: Tracked<UnboundedLog::local_updates<DT>>,
+    ) -> (result: Result<PendingHandle<DT>, Tracked<UnboundedLog::local_updates<DT>>>)
+        requires
+            self.wf(),
+            old(tkn).wf_pending(&self.replicas[old(tkn).replica_id_spec() as int]),
+            is_update_ticket(ticket@, op, self.unbounded_log_instance@),
+        ensures
+            tkn.wf_pending(&self.replicas[tkn.replica_id_spec() as int]),
+            tkn.rid == old(tkn).rid,
+            tkn.tid == old(tkn).tid,
+            result.is_Ok() ==> {
+                &&& result.get_Ok_0().wf(&self.replicas[tkn.replica_id_spec() as int])
+                &&& result.get_Ok_0().req_id_spec() == ticket@@.key
+                &&& result.get_Ok_0().rid == tkn.rid
+                &&& result.get_Ok_0().tid == tkn.tid
+                &&& tkn.pending@ == old(tkn).pending@.update(result.get_Ok_0().idx as int, true)
+            },
+            result.is_Err() ==> result.get_Err_0() == ticket && *tkn == *old(tkn),
+    {
+        let replica_id = tkn.replica_id() as usize;
+        if replica_id < self.replicas.len() && !self.retired[replica_id] {
+            (&self.replicas[replica_id]).submit_mut(&self.log, &self.replicas, op, tkn, ticket)
+        } else {
+            Err(ticket)
+        }
+    }
+
+    /// Polls for the response of a submitted operation. Returns the handle again if the response
+    /// is not available yet. The response is an error together with the ticket of the operation
+    /// if it couldn't be appended to the log.
+    ///
+    /// The pending operations of a retired replica have been executed when it was retired, the
+    /// response is taken from the thread's context without combining.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub fn poll(&self, tkn: &mut ThreadToken<DT>, handle: PendingHandle<DT>) -> (result: Result<
+        (Result<DT::Response, NrError>, Tracked<UnboundedLog::local_updates<DT>>),
+        PendingHandle<DT>,
+    >)
+        requires
+            self.wf(),
+            old(tkn).wf_pending(&self.replicas[old(tkn).replica_id_spec() as int]),
+            handle.wf(&self.replicas[handle.replica_id_spec() as int]),
+            handle.rid == old(tkn).rid,
+            handle.tid == old(tkn).tid,
+            old(tkn).pending[handle.idx as int],
+        ensures
+            tkn.wf_pending(&self.replicas[tkn.replica_id_spec() as int]),
+            tkn.rid == old(tkn).rid,
+            tkn.tid == old(tkn).tid,
+            result.is_Ok() ==> {
+                let (resp, ticket) = result.get_Ok_0();
+                &&& resp.is_Ok() ==> is_update_stub(ticket@, handle.req_id_spec(), resp.get_Ok_0(), self.unbounded_log_instance@)
+                &&& resp.is_Err() ==> {
+                    &&& ticket@@.instance == self.unbounded_log_instance@
+                    &&& ticket@@.key == handle.req_id_spec()
+                    &&& ticket@@.value.is_Init()
+                    &&& resp.get_Err_0() == NrError::LogExhausted
+                }
+                &&& tkn.pending@ == old(tkn).pending@.update(handle.idx as int, false)
+            },
+            result.is_Err() ==> result.get_Err_0() == handle && *tkn == *old(tkn),
+    {
+        let replica_id = handle.replica_id() as usize;
+        if replica_id >= self.replicas.len() {
+            Err(handle)
+        } else if self.retired[replica_id] {
+            // a retired replica doesn't combine anymore
+            (&self.replicas[replica_id]).take_response(tkn, handle)
+        } else {
+            (&self.replicas[replica_id]).poll(&self.log, &self.replicas, tkn, handle)
+        }
+    }
+
+    /// returns whether the replica with the given id has been retired
+    pub fn is_retired(&self, replica_id: ReplicaId) -> (result: bool)
+        requires
+            self.wf(),
+            replica_id < self.replicas.len(),
+        ensures
+            result == self.retired[replica_id as int],
+    {
+        self.retired[replica_id]
+    }
+
+    /// checks whether the replica of the thread token can execute the thread's operations
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    fn check_token(&self, tkn: &ThreadToken<DT>) -> (result: Result<(), NrError>)
+        requires
+            self.wf(),
+        ensures
+            result.is_Ok() ==> {
+                &&& tkn.replica_id_spec() < self.replicas.len()
+                &&& !self.retired[tkn.replica_id_spec() as int]
+            },
+            result.is_Err() ==> result.get_Err_0() != NrError::LogExhausted,
+    {
+        let replica_id = tkn.replica_id() as usize;
+        if replica_id >= self.replicas.len() {
+            Err(NrError::InvalidReplica)
+        } else if self.retired[replica_id] {
+            Err(NrError::ReplicaRetired)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<DT: CloneState + Sync> NodeReplicated<DT> {
+    /// Adds a replica to the data structure by activating one of the retired replicas again.
+    /// The new replica starts with a copy of the state of the first replica, threads need to
+    /// register with it before executing operations.
+    ///
+    /// The log keeps a slot for every replica up to the capacity set with
+    /// [`NrConfig::max_replicas`], the data structure can't grow beyond it. The new replica
+    /// replaces the retired one, responses of operations that were pending on the retired
+    /// replica need to be polled before.
+    ///
+    /// Returns the id of the new replica, or None if there is no retired replica, i.e., the
+    /// capacity has been reached.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub fn add_replica(&mut self, chg_mem_affinity: &AffinityFn) -> (result: Option<ReplicaId>)
+        requires
+            old(self).wf(),
+        ensures
+            self.wf(),
+            self.replicas.len() == old(self).replicas.len(),
+            self.unbounded_log_instance@ == old(self).unbounded_log_instance@,
+            result.is_Some() ==> {
+                &&& result.get_Some_0() < self.replicas.len()
+                &&& old(self).retired[result.get_Some_0() as int]
+                &&& !self.retired[result.get_Some_0() as int]
+            },
+    {
+        let num_replicas = self.replicas.len();
+        let mut replica_id = 1;
+        while replica_id < num_replicas
+            invariant
+                self.wf(),
+                self.replicas.len() == num_replicas,
+                1 <= replica_id <= num_replicas,
+        {
+            if self.retired[replica_id] {
+                break ;
+            }
+            replica_id = replica_id + 1;
+        }
+        if replica_id == num_replicas {
+            return None;
+        }
+        // we have exclusive access to the data structure, the write lock is not contended
+        let (first, first_handle) = self.replicas[0].data.0.acquire_write();
+        let num_threads = self.replicas[0].contexts.len();
+        // copy the state of the first replica, allocated on the node of the new replica
+        chg_mem_affinity.call(replica_id);
+        let data = first.data.clone_state();
+        chg_mem_affinity.call(0);
+        let ReplicatedDataStructure {
+            data: first_data,
+            replica: first_replica,
+            combiner: first_combiner,
+            cb_combiner: first_cb_combiner,
+        } = first;
+        let tracked replica;
+        let tracked combiner;
+        proof {
+            replica = self.retired_replicas.borrow_mut().tracked_remove(replica_id as nat);
+            combiner = self.retired_combiners.borrow_mut().tracked_remove(replica_id as nat);
+        }
+        let tracked ghost_data = NrLogActivateGhost {
+            replica: Tracked(replica),
+            combiner: Tracked(combiner),
+            cb_combiner: Tracked(Option::None),
+            first_replica,
+            first_combiner,
+            first_cb_combiner,
+        };
+        let (activated, ghost_data) = self.log.activate_replica(replica_id, Tracked(ghost_data));
+        let tracked NrLogActivateGhost {
+            replica,
+            combiner,
+            cb_combiner,
+            first_replica,
+            first_combiner,
+            first_cb_combiner,
+        } = ghost_data.get();
+        let first = ReplicatedDataStructure {
+            data: first_data,
+            replica: first_replica,
+            combiner: first_combiner,
+            cb_combiner: first_cb_combiner,
+        };
+        self.replicas[0].data.0.release_write(first, first_handle);
+        if !activated {
+            proof {
+                self.retired_replicas.borrow_mut().tracked_insert(replica_id as nat, replica.get());
+                self.retired_combiners.borrow_mut().tracked_insert(
+                    replica_id as nat,
+                    combiner.get(),
+                );
+            }
+            return None;
+        }
+        let tracked config = ReplicaConfig {
+            replica: replica.get(),
+            combiner: combiner.get(),
+            cb_combiner: cb_combiner.get().tracked_unwrap(),
+            unbounded_log_instance: self.unbounded_log_instance.borrow().clone(),
+            cyclic_buffer_instance: self.cyclic_buffer_instance.borrow().clone(),
+        };
+        // switch the affinity of the replica before we do the allocation
+        chg_mem_affinity.call(replica_id);
+        let replica = Replica::with_data(
+            ReplicaToken::new(replica_id),
+            num_threads,
+            data,
+            Tracked(config),
+        );
+        chg_mem_affinity.call(0);
+        self.replicas.set(replica_id, Box::new(replica));
+        self.retired.set(replica_id, false);
+        Some(replica_id)
+    }
+
+    /// Takes a snapshot of the data structure of a replica together with its local version,
+    /// i.e., the position in the log the state corresponds to.
+    ///
+    /// The snapshot is taken under the read lock of the replica. The returned token records the
+    /// snapshot in the UnboundedLog, its state is the state of the replicas at this version of
+    /// the log. Returns None if the replica doesn't exist or has been retired.
+    ///
+    /// The token rebuilds retired replicas of this data structure, see
+    /// [`NodeReplicated::rebuild_from_snapshot`]. The state on its own bootstraps a new data
+    /// structure, e.g., in another process, see [`NodeReplicated::from_snapshot`].
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub fn snapshot(&self, replica_id: ReplicaId) -> (result: Option<
+        (DT, u64, Tracked<UnboundedLog::snapshots<DT>>),
+    >)
+        requires
+            self.wf(),
+        ensures
+            result.is_Some() ==> {
+                let (state, version, snapshot) = result.get_Some_0();
+                &&& state.inv()
+                &&& snapshot@@.instance == self.unbounded_log_instance@
+                &&& snapshot@@.key == version
+                &&& snapshot@@.value == state@
+            },
+    {
+        if replica_id >= self.replicas.len() || self.retired[replica_id] {
+            return None;
+        }
+        let replica = &self.replicas[replica_id];
+        let read_handle = replica.data.0.acquire_read(0);
+        let replicated_data_structure = replica.data.0.borrow(Tracked(&read_handle));
+        let state = replicated_data_structure.data.clone_state();
+        let (version, snapshot) = self.log.snapshot_version(
+            replica_id,
+            Tracked(replicated_data_structure.replica.borrow()),
+            Tracked(replicated_data_structure.combiner.borrow()),
+        );
+        replica.data.0.release_read(read_handle);
+        // the replica is not retired, it has a valid local version
+        if version == RETIRED_VERSION {
+            return None;
+        }
+        Some((state, version, Tracked(snapshot.get().tracked_unwrap())))
+    }
+
+    /// Creates a new, replicated data-structure whose replicas start with the state of a
+    /// snapshot taken with [`NodeReplicated::snapshot`], e.g., one that has been persisted or
+    /// transferred from another process.
+    ///
+    /// The new data structure has its own log, which starts out empty. It behaves as if its
+    /// data structure had been initialized with the state of the snapshot, the versions of the
+    /// log the snapshot has been taken from don't carry over.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub fn from_snapshot(config: NrConfig, chg_mem_affinity: AffinityFn, state: &DT) -> (res: Self)
+        requires
+            config.wf(),
+            state.inv(),
+        ensures
+            res.wf(),
+            res.replicas.len() == config.max_replicas,
+            res.unbounded_log_instance@.init_state() == state@,
+    {
+        let num_replicas = config.max_replicas;
+        // copy the state for each replica, the last copy is used by the first replica
+        let mut states: Vec<DT> = Vec::with_capacity(num_replicas);
+        let mut idx = 0;
+        while idx < num_replicas
+            invariant
+                0 <= idx <= num_replicas,
+                state.inv(),
+                states.len() == idx,
+                forall|i| #![trigger states[i]] 0 <= i < idx ==> states[i].inv() && states[i]@ == state@,
+        {
+            chg_mem_affinity.call(num_replicas - 1 - idx);
+            states.push(state.clone_state());
+            idx = idx + 1;
         }
+        Self::with_states(&config, &chg_mem_affinity, Observer::stderr(), states, Ghost(state@))
+    }
+
+    /// Rebuilds the retired replicas from a snapshot taken with [`NodeReplicated::snapshot`] of
+    /// this data structure.
+    ///
+    /// Every retired replica is activated again with a copy of the state of the snapshot and
+    /// starts executing the log from its version on. If the snapshot is behind the first
+    /// replica, the replica is activated with a copy of the state of the first replica instead,
+    /// as the log entries after the snapshot may already have been garbage collected.
+    ///
+    /// Returns the number of replicas that have been rebuilt.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub fn rebuild_from_snapshot(
+        &mut self,
+        state: &DT,
+        version: u64,
+        snapshot: Tracked<UnboundedLog::snapshots<DT>>,
+        chg_mem_affinity: &AffinityFn,
+    ) -> (result: usize)
+        requires
+            old(self).wf(),
+            state.inv(),
+            snapshot@@.instance == old(self).unbounded_log_instance@,
+            snapshot@@.key == version,
+            snapshot@@.value == state@,
+        ensures
+            self.wf(),
+            self.replicas.len() == old(self).replicas.len(),
+            self.unbounded_log_instance@ == old(self).unbounded_log_instance@,
+            result < self.replicas.len(),
+    {
+        let num_replicas = self.replicas.len();
+        let mut num_rebuilt = 0;
+        let mut replica_id = 1;
+        while replica_id < num_replicas
+            invariant
+                self.wf(),
+                self.replicas.len() == num_replicas,
+                self.unbounded_log_instance@ == old(self).unbounded_log_instance@,
+                state.inv(),
+                snapshot@@.instance == self.unbounded_log_instance@,
+                snapshot@@.key == version,
+                snapshot@@.value == state@,
+                1 <= replica_id <= num_replicas,
+                num_rebuilt < replica_id,
+        {
+            if self.retired[replica_id] && self.activate_from_snapshot(
+                replica_id,
+                state,
+                version,
+                Tracked(snapshot.borrow()),
+                chg_mem_affinity,
+            ) {
+                num_rebuilt = num_rebuilt + 1;
+            }
+            replica_id = replica_id + 1;
+        }
+        num_rebuilt
+    }
+
+    /// Activates the retired replica with the given id with the state of a snapshot, or with
+    /// the state of the first replica if the snapshot is behind it.
+    fn activate_from_snapshot(
+        &mut self,
+        replica_id: ReplicaId,
+        state: &DT,
+        version: u64,
+        snapshot: Tracked<&UnboundedLog::snapshots<DT>>,
+        chg_mem_affinity: &AffinityFn,
+    ) -> (result: bool)
+        requires
+            old(self).wf(),
+            0 < replica_id < old(self).replicas.len(),
+            old(self).retired[replica_id as int],
+            state.inv(),
+            snapshot@@.instance == old(self).unbounded_log_instance@,
+            snapshot@@.key == version,
+            snapshot@@.value == state@,
+        ensures
+            self.wf(),
+            self.replicas.len() == old(self).replicas.len(),
+            self.unbounded_log_instance@ == old(self).unbounded_log_instance@,
+    {
+        // we have exclusive access to the data structure, the write lock is not contended
+        let (first, first_handle) = self.replicas[0].data.0.acquire_write();
+        let num_threads = self.replicas[0].contexts.len();
+        let ReplicatedDataStructure {
+            data: first_data,
+            replica: first_replica,
+            combiner: first_combiner,
+            cb_combiner: first_cb_combiner,
+        } = first;
+        let tracked replica;
+        let tracked combiner;
+        proof {
+            replica = self.retired_replicas.borrow_mut().tracked_remove(replica_id as nat);
+            combiner = self.retired_combiners.borrow_mut().tracked_remove(replica_id as nat);
+        }
+        let tracked ghost_data = NrLogActivateGhost {
+            replica: Tracked(replica),
+            combiner: Tracked(combiner),
+            cb_combiner: Tracked(Option::None),
+            first_replica,
+            first_combiner,
+            first_cb_combiner,
+        };
+        let (activated, ghost_data) = self.log.activate_replica_from_snapshot(
+            replica_id,
+            version,
+            snapshot,
+            Tracked(ghost_data),
+        );
+        // the snapshot is behind the first replica, fall back to the state of the first replica
+        let from_first = !activated;
+        let (activated, ghost_data) = if from_first {
+            self.log.activate_replica(replica_id, ghost_data)
+        } else {
+            (activated, ghost_data)
+        };
+        let tracked NrLogActivateGhost {
+            replica,
+            combiner,
+            cb_combiner,
+            first_replica,
+            first_combiner,
+            first_cb_combiner,
+        } = ghost_data.get();
+        // copy the state while we still hold the first replica, allocated on the replica's node
+        let data = if !activated {
+            None
+        } else if from_first {
+            chg_mem_affinity.call(replica_id);
+            let data = first_data.clone_state();
+            chg_mem_affinity.call(0);
+            Some(data)
+        } else {
+            chg_mem_affinity.call(replica_id);
+            let data = state.clone_state();
+            chg_mem_affinity.call(0);
+            Some(data)
+        };
+        let first = ReplicatedDataStructure {
+            data: first_data,
+            replica: first_replica,
+            combiner: first_combiner,
+            cb_combiner: first_cb_combiner,
+        };
+        self.replicas[0].data.0.release_write(first, first_handle);
+        if !activated {
+            proof {
+                self.retired_replicas.borrow_mut().tracked_insert(replica_id as nat, replica.get());
+                self.retired_combiners.borrow_mut().tracked_insert(
+                    replica_id as nat,
+                    combiner.get(),
+                );
+            }
+            return false;
+        }
+        let data = data.unwrap();
+        let tracked config = ReplicaConfig {
+            replica: replica.get(),
+            combiner: combiner.get(),
+            cb_combiner: cb_combiner.get().tracked_unwrap(),
+            unbounded_log_instance: self.unbounded_log_instance.borrow().clone(),
+            cyclic_buffer_instance: self.cyclic_buffer_instance.borrow().clone(),
+        };
+        chg_mem_affinity.call(replica_id);
+        let replica = Replica::with_data(
+            ReplicaToken::new(replica_id),
+            num_threads,
+            data,
+            Tracked(config),
+        );
+        chg_mem_affinity.call(0);
+        self.replicas.set(replica_id, Box::new(replica));
+        self.retired.set(replica_id, false);
+        true
+    }
+}
+
+impl<DT: Dispatch> crate::ThreadTokenT<DT, Replica<DT>> for ThreadToken<DT> {
+    open spec fn wf(&self, replica: &Replica<DT>) -> bool {
+        ThreadToken::<DT>::wf(self, replica)
+    }
+
+    open spec fn replica_id_spec(&self) -> nat {
+        ThreadToken::<DT>::replica_id_spec(self)
+    }
+
+    open spec fn session_version_spec(&self) -> nat {
+        ThreadToken::<DT>::session_version_spec(self)
+    }
+}
+
+impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
+    type Replica = Replica<DT>;
+
+    type ReplicaId = ReplicaId;
+
+    type TT = ThreadToken<DT>;
+
+    /// Wellformedness of the NodeReplicated data structure
+    open spec fn wf(&self) -> bool {
+        // the log shall be well-formed and the instances match
+        &&& self.log.wf()
+        &&& self.unbounded_log_instance@ == self.log.unbounded_log_instance@
+        &&& self.cyclic_buffer_instance@
+            == self.log.cyclic_buffer_instance@
+        // the number of replicas should be the as configured
+
+        &&& self.replicas.len()
+            <= MAX_REPLICAS
+        &&& self.replicas.len() == self.log.local_versions.len()
+        // the replicas should be well-formed and the instances match
+
+        &&& (forall|i|
+            0 <= i < self.replicas.len() ==> {
+                &&& (#[trigger] self.replicas[i]).wf()
+                &&& self.replicas[i].spec_id() == i
+                &&& self.replicas[i].replica_token@ == i
+                &&& self.replicas[i].unbounded_log_instance@ == self.unbounded_log_instance@
+                &&& self.replicas[i].cyclic_buffer_instance@ == self.cyclic_buffer_instance@
+            })
+        // the first replica is never retired, the tokens of the retired replicas are kept here
+
+        &&& self.retired.len() == self.replicas.len()
+        &&& (forall|i|
+            0 <= i < self.retired.len() && #[trigger] self.retired[i] ==> {
+                &&& 0 < i
+                &&& self.retired_replicas@.contains_key(i as nat)
+                &&& self.retired_replicas@[i as nat]@.instance == self.unbounded_log_instance@
+                &&& self.retired_replicas@[i as nat]@.key == i
+                &&& self.retired_combiners@.contains_key(i as nat)
+                &&& self.retired_combiners@[i as nat]@.instance == self.unbounded_log_instance@
+                &&& self.retired_combiners@[i as nat]@.key == i
+                &&& self.retired_combiners@[i as nat]@.value.is_Ready()
+            })
+    }
+
+    open spec fn replicas(&self) -> Vec<Box<Self::Replica>> {
+        self.replicas
+    }
+
+    open spec fn unbounded_log_instance(&self) -> UnboundedLog::Instance<DT> {
+        self.log.unbounded_log_instance@
+    }
+
+    /// Creates a new, replicated data-structure from a single-threaded
+    /// data-structure that implements [`Dispatch`]. It uses the [`Default`]
+    /// constructor to create a initial data-structure for `D` on all replicas.
+    ///
+    ///  - Dafny: n/a ?
+    ///  - Rust:  pub fn new(num_replicas: NonZeroUsize) -> Result<Self, NodeReplicatedError>
+    fn new(num_replicas: usize, chg_mem_affinity: AffinityFn) -> (res:
+        Self)
+    // requires
+    //     num_replicas <= MAX_REPLICAS
+    // ensures res.wf()
+    {
+        Self::new_with_affinity(
+            &NrConfig::new(num_replicas),
+            &chg_mem_affinity,
+            Observer::stderr(),
+        )
     }
 
     /// Registers a thread with a given replica in the [`NodeReplicated`]
@@ -226,7 +991,7 @@ impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
     //     self.wf(),
     //     result.is_Some() ==> result.get_Some_0().WF(&self.replicas[replica_id as int])
     {
-        if (replica_id as usize) < self.replicas.len() {
+        if (replica_id as usize) < self.replicas.len() && !self.retired[replica_id] {
             let mut replica: Box<Replica<DT>> = self.replicas.remove(replica_id);
             let res: Option<ThreadToken<DT>> = (*replica).register();
             self.replicas.insert(replica_id, replica);
@@ -236,6 +1001,24 @@ impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
         }
     }
 
+    /// Deregisters a thread from its replica in the [`NodeReplicated`] data-structure. The
+    /// thread token is returned to the replica and can be handed out again by `register`.
+    ///
+    /// Tokens of retired replicas are dropped, the replica gets new thread tokens when it is
+    /// activated again.
+    ///
+    /// The thread tokens of a replica are protected by their own lock, a thread can deregister
+    /// while the other threads keep executing operations.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    fn deregister(&self, tkn: ThreadToken<DT>) {
+        let replica_id = tkn.replica_id() as usize;
+        if replica_id < self.replicas.len() && !self.retired[replica_id] {
+            self.replicas[replica_id].unregister(tkn);
+        }
+    }
+
     /// Executes a mutable operation against the data-structure.
     ///
     ///  - Dafny:
@@ -250,7 +1033,7 @@ impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
         ticket: Tracked<UnboundedLog::local_updates<DT>>,
     ) -> (result: Result<
         (DT::Response, ThreadToken<DT>, Tracked<UnboundedLog::local_updates<DT>>),
-        (ThreadToken<DT>, Tracked<UnboundedLog::local_updates<DT>>),
+        (ThreadToken<DT>, Tracked<UnboundedLog::local_updates<DT>>, NrError),
     >)
     // requires
     //     self.wf(), // wf global node
@@ -261,11 +1044,89 @@ impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
     //     result.is_Err() ==> result.get_Err_0().1 == ticket && result.get_Err_0().0 == tkn
     {
         let replica_id = tkn.replica_id() as usize;
-        if replica_id < self.replicas.len() {
+        match self.check_token(&tkn) {
             // get the replica/node, execute it with the log and provide the thread id.
-            Ok((&self.replicas[replica_id]).execute_mut(&self.log, op, tkn, ticket))
-        } else {
-            Err((tkn, ticket))
+            Ok(()) => (&self.replicas[replica_id]).execute_mut(
+                &self.log,
+                &self.replicas,
+                op, tkn, ticket,
+            ),
+            Err(err) => Err((tkn, ticket, err)),
+        }
+    }
+
+    /// Executes a immutable operation against the data-structure, tolerating bounded staleness.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    fn execute_relaxed(&self, op: DT::ReadOperation, tkn: ThreadToken<DT>, max_lag: u64) -> (result:
+        Result<
+            (
+                DT::Response,
+                ThreadToken<DT>,
+                Tracked<UnboundedLog::snapshots<DT>>,
+                Tracked<UnboundedLog::version_upper_bound_reached<DT>>,
+            ),
+            (ThreadToken<DT>, NrError),
+        >) {
+        let replica_id = tkn.replica_id() as usize;
+        match self.check_token(&tkn) {
+            Ok(()) => Ok(
+                (&self.replicas[replica_id]).execute_relaxed(
+                    &self.log,
+                    &self.replicas,
+                    op, tkn, max_lag,
+                ),
+            ),
+            Err(err) => Err((tkn, err)),
+        }
+    }
+
+    /// Executes a immutable operation against the data-structure with session consistency.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    fn execute_session(&self, op: DT::ReadOperation, tkn: ThreadToken<DT>) -> (result: Result<
+        (DT::Response, ThreadToken<DT>, Tracked<UnboundedLog::snapshots<DT>>),
+        (ThreadToken<DT>, NrError),
+    >) {
+        let replica_id = tkn.replica_id() as usize;
+        match self.check_token(&tkn) {
+            Ok(()) => Ok(
+                (&self.replicas[replica_id]).execute_session(
+                    &self.log,
+                    &self.replicas,
+                    op, tkn,
+                ),
+            ),
+            Err(err) => Err((tkn, err)),
+        }
+    }
+
+    /// Executes a batch of mutable operations against the data-structure.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    ///
+    /// The operations are appended to contiguous entries of the log and hence linearize one after
+    /// the other.
+    fn execute_mut_batch(
+        &self,
+        ops: Vec<DT::WriteOperation>,
+        tkn: ThreadToken<DT>,
+        tickets: Tracked<Map<nat, UnboundedLog::local_updates<DT>>>,
+    ) -> (result: Result<
+        (Vec<DT::Response>, ThreadToken<DT>, Tracked<Map<nat, UnboundedLog::local_updates<DT>>>),
+        (ThreadToken<DT>, Tracked<Map<nat, UnboundedLog::local_updates<DT>>>, NrError),
+    >) {
+        let replica_id = tkn.replica_id() as usize;
+        match self.check_token(&tkn) {
+            Ok(()) => (&self.replicas[replica_id]).execute_mut_batch(
+                &self.log,
+                &self.replicas,
+                ops, tkn, tickets,
+            ),
+            Err(err) => Err((tkn, tickets, err)),
         }
     }
 
@@ -283,7 +1144,7 @@ impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
         ticket: Tracked<UnboundedLog::local_reads<DT>>,
     ) -> (result: Result<
         (DT::Response, ThreadToken<DT>, Tracked<UnboundedLog::local_reads<DT>>),
-        (ThreadToken<DT>, Tracked<UnboundedLog::local_reads<DT>>),
+        (ThreadToken<DT>, Tracked<UnboundedLog::local_reads<DT>>, NrError),
     >)
     // requires
     //     self.wf(), // wf global node
@@ -294,11 +1155,47 @@ impl<DT: Dispatch + Sync> crate::NodeReplicatedT<DT> for NodeReplicated<DT> {
     //     result.is_Err() ==> result.get_Err_0().1 == ticket && result.get_Err_0().0 == tkn
     {
         let replica_id = tkn.replica_id() as usize;
-        if replica_id < self.replicas.len() {
+        match self.check_token(&tkn) {
             // get the replica/node, execute it with the log and provide the thread id.
-            Ok((&self.replicas[replica_id]).execute(&self.log, op, tkn, ticket))
-        } else {
-            Err((tkn, ticket))
+            Ok(()) => Ok(
+                (&self.replicas[replica_id]).execute(&self.log, &self.replicas, op, tkn, ticket),
+            ),
+            Err(err) => Err((tkn, ticket, err)),
+        }
+    }
+
+    /// Executes a batch of immutable operations against the data-structure.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    ///
+    /// The operations observe the same state of the replica, they are dispatched while holding
+    /// its read lock. The returned snapshot records the version of the log they are linearized
+    /// at.
+    fn execute_many(
+        &self,
+        ops: Vec<DT::ReadOperation>,
+        tkn: ThreadToken<DT>,
+        tickets: Tracked<Map<nat, UnboundedLog::local_reads<DT>>>,
+    ) -> (result: Result<
+        (
+            Vec<DT::Response>,
+            ThreadToken<DT>,
+            Tracked<Map<nat, UnboundedLog::local_reads<DT>>>,
+            Tracked<UnboundedLog::snapshots<DT>>,
+        ),
+        (ThreadToken<DT>, Tracked<Map<nat, UnboundedLog::local_reads<DT>>>, NrError),
+    >) {
+        let replica_id = tkn.replica_id() as usize;
+        match self.check_token(&tkn) {
+            Ok(()) => Ok(
+                (&self.replicas[replica_id]).execute_many(
+                    &self.log,
+                    &self.replicas,
+                    ops, tkn, tickets,
+                ),
+            ),
+            Err(err) => Err((tkn, tickets, err)),
         }
     }
 }
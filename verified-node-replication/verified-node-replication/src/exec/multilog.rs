@@ -0,0 +1,551 @@
+// Verified Node Replication Library
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+#[allow(unused_imports)]
+use builtin::*;
+use builtin_macros::*;
+
+use vstd::prelude::*;
+
+use std::sync::Arc;
+
+use crate::{ConcurrentDispatch, Dispatch, LogMapper, MultiLogNodeReplicatedT, NodeReplicatedT};
+
+// spec imports
+use crate::spec::unbounded_log::UnboundedLog;
+
+// exec imports
+use crate::exec::config::NrConfig;
+use crate::exec::context::{ThreadId, ThreadToken};
+use crate::exec::replica::{Replica, ReplicaId};
+use crate::exec::NodeReplicated;
+
+use crate::constants::MAX_REPLICAS;
+use crate::{AffinityFn, NrError, Observer};
+
+verus! {
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Log Partition
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The partition of a log in the copy of the data structure of a replica.
+///
+/// The replicas of all logs on a node share one copy of the data structure, the replica of a log
+/// accesses it through the permission of the partition of this log. The partition implements
+/// [`Dispatch`], hence every log is replicated by a [`NodeReplicated`] data structure.
+///
+///  - Dafny: N/A
+///  - Rust:  N/A (CNR: Replica holds `data: D` that is shared by the combiners of all logs)
+pub struct LogPartition<DT: ConcurrentDispatch> {
+    /// the copy of the data structure of the replica, shared by all logs
+    pub  /* REVIEW: (crate) */
+     ds: Arc<DT>,
+    /// the permission to access the partition of the log
+    pub  /* REVIEW: (crate) */
+     part: Tracked<DT::Partition>,
+}
+
+impl<DT: ConcurrentDispatch> Dispatch for LogPartition<DT> {
+    type ReadOperation = DT::ReadOperation;
+
+    type WriteOperation = DT::WriteOperation;
+
+    type Response = DT::Response;
+
+    type View = DT::View;
+
+    open spec fn view(&self) -> Self::View {
+        DT::partition_view(self.part@)
+    }
+
+    /// Initializes the partition of a copy of the data structure with a single partition.
+    fn init() -> (res: Self) {
+        let (ds, parts) = DT::init(1);
+        let tracked mut parts = parts.get();
+        let tracked part = parts.tracked_remove(0);
+        LogPartition { ds: Arc::new(ds), part: Tracked(part) }
+    }
+
+    fn clone_write_op(op: &Self::WriteOperation) -> (res: Self::WriteOperation) {
+        DT::clone_write_op(op)
+    }
+
+    fn clone_response(op: &Self::Response) -> (res: Self::Response) {
+        DT::clone_response(op)
+    }
+
+    fn dispatch(&self, op: Self::ReadOperation) -> (result: Self::Response) {
+        (*self.ds).dispatch(op, Tracked(self.part.borrow()))
+    }
+
+    fn dispatch_mut(&mut self, op: Self::WriteOperation) -> (result: Self::Response) {
+        (*self.ds).dispatch_mut(op, Tracked(self.part.borrow_mut()))
+    }
+
+    open spec fn init_spec() -> Self::View {
+        DT::init_spec(0)
+    }
+
+    open spec fn dispatch_spec(ds: Self::View, op: Self::ReadOperation) -> Self::Response {
+        DT::dispatch_spec(ds, op)
+    }
+
+    open spec fn dispatch_mut_spec(ds: Self::View, op: Self::WriteOperation) -> (
+        Self::View,
+        Self::Response,
+    ) {
+        DT::dispatch_mut_spec(ds, op)
+    }
+
+    open spec fn inv(&self) -> bool {
+        (*self.ds).partition_wf(self.part@)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Multi-Log Thread Token
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The thread token of a multi-log replicated data structure. It bundles the thread tokens
+/// of all logs, each registered with the replica of the same id.
+///
+///  - Dafny: N/A
+///  - Rust:  N/A (CNR: pub struct ThreadToken)
+pub struct MultiLogThreadToken<DT: ConcurrentDispatch> {
+    /// the replica id this thread uses in all logs
+    pub  /* REVIEW: (crate) */
+     rid: ReplicaId,
+    /// the thread tokens, one for every log
+    pub  /* REVIEW: (crate) */
+     tkns: Vec<ThreadToken<LogPartition<DT>>>,
+}
+
+impl<DT: ConcurrentDispatch> MultiLogThreadToken<DT> {
+    pub open spec fn replica_id_spec(&self) -> nat {
+        self.rid as nat
+    }
+
+    pub fn replica_id(&self) -> (result: ReplicaId)
+        ensures
+            result as nat == self.replica_id_spec(),
+    {
+        self.rid
+    }
+
+    /// the thread id of the thread in the replica of the first log
+    pub fn thread_id(&self) -> (result: ThreadId)
+        requires
+            self.tkns.len() > 0,
+        ensures
+            result as nat == self.tkns[0].thread_id_spec(),
+    {
+        self.tkns[0].thread_id()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Multi-Log Node Replicated
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A replicated data structure that is partitioned over several logs.
+///
+/// Every log replicates one partition of the data structure. A replica keeps a single copy of
+/// the data structure that is shared by all logs, the combiner of a log applies its operations
+/// to the partition of the log in this copy. The [`LogMapper`] selects the log of an operation,
+/// hence operations on different partitions don't contend on the same log tail and are applied
+/// to the copy concurrently.
+///
+///  - Dafny: N/A
+///  - Rust:  N/A (CNR: pub struct NodeReplicated<D: Dispatch + Sync>)
+#[verifier::reject_recursive_types(DT)]
+pub struct MultiLogNodeReplicated<DT: ConcurrentDispatch> {
+    /// the logs replicating the partitions of the data structure
+    pub  /* REVIEW: (crate) */
+     logs: Vec<NodeReplicated<LogPartition<DT>>>,
+    /// the number of replicas of every log
+    pub  /* REVIEW: (crate) */
+     num_replicas: usize,
+}
+
+impl<DT: LogMapper + Send + Sync> MultiLogNodeReplicated<DT> {
+    /// Creates a new data structure with `num_logs` logs, each of them set up with the given
+    /// configuration. Every replica gets one copy of the data structure with a partition per
+    /// log, which is allocated on the node of the replica.
+    ///
+    /// Replicas can't be added to the logs later, hence the capacity of the configuration must
+    /// be its number of replicas.
+    pub fn with_config(num_logs: usize, config: NrConfig, chg_mem_affinity: AffinityFn) -> (res:
+        Self)
+        requires
+            0 < num_logs,
+            config.wf(),
+            config.max_replicas == config.num_replicas,
+        ensures
+            res.wf(),
+            res.num_logs() == num_logs,
+            res.num_replicas() == config.num_replicas,
+    {
+        Self::with_observer(num_logs, config, chg_mem_affinity, Observer::stderr())
+    }
+
+    /// Creates a new data structure like [`MultiLogNodeReplicated::with_config`], all logs report
+    /// their progress and diagnostic events to the observer.
+    pub fn with_observer(
+        num_logs: usize,
+        config: NrConfig,
+        chg_mem_affinity: AffinityFn,
+        observer: Observer,
+    ) -> (res: Self)
+        requires
+            0 < num_logs,
+            config.wf(),
+            config.max_replicas == config.num_replicas,
+        ensures
+            res.wf(),
+            res.num_logs() == num_logs,
+            res.num_replicas() == config.num_replicas,
+    {
+        let num_replicas = config.num_replicas;
+        // create the copies of the data structure, keep the permissions of their partitions
+        let mut copies: Vec<Arc<DT>> = Vec::with_capacity(num_replicas);
+        let tracked mut parts: Map<nat, Map<nat, DT::Partition>> = Map::tracked_empty();
+        let mut replica_id = 0;
+        while replica_id < num_replicas
+            invariant
+                0 < num_logs,
+                config.wf(),
+                num_replicas == config.num_replicas,
+                0 <= replica_id <= num_replicas,
+                copies.len() == replica_id,
+                forall|r: nat, l: nat|
+                    #![trigger parts[r][l]]
+                    r < replica_id && l < num_logs ==> {
+                        &&& parts.contains_key(r)
+                        &&& parts[r].contains_key(l)
+                        &&& (*copies[r as int]).partition_wf(parts[r][l])
+                        &&& DT::partition_idx(parts[r][l]) == l
+                        &&& DT::partition_view(parts[r][l]) == DT::init_spec(l)
+                    },
+        {
+            chg_mem_affinity.call(replica_id);
+            let (ds, copy_parts) = DT::init(num_logs);
+            copies.push(Arc::new(ds));
+            proof {
+                parts.tracked_insert(replica_id as nat, copy_parts.get());
+            }
+            replica_id = replica_id + 1;
+        }
+        chg_mem_affinity.call(0);
+        // replicate every partition with its own log, the replicas share the copies
+        let mut logs: Vec<NodeReplicated<LogPartition<DT>>> = Vec::with_capacity(num_logs);
+        let mut log_idx = 0;
+        while log_idx < num_logs
+            invariant
+                0 < num_logs,
+                config.wf(),
+                config.max_replicas == config.num_replicas,
+                num_replicas == config.num_replicas,
+                copies.len() == num_replicas,
+                0 <= log_idx <= num_logs,
+                logs.len() == log_idx,
+                forall|i|
+                    0 <= i < log_idx ==> {
+                        &&& (#[trigger] logs[i]).wf()
+                        &&& logs[i].replicas.len() == num_replicas
+                    },
+                forall|r: nat, l: nat|
+                    #![trigger parts[r][l]]
+                    r < num_replicas && log_idx <= l < num_logs ==> {
+                        &&& parts.contains_key(r)
+                        &&& parts[r].contains_key(l)
+                        &&& (*copies[r as int]).partition_wf(parts[r][l])
+                        &&& DT::partition_idx(parts[r][l]) == l
+                        &&& DT::partition_view(parts[r][l]) == DT::init_spec(l)
+                    },
+        {
+            // the partitions of the log, the last one is used by the first replica
+            let mut states: Vec<LogPartition<DT>> = Vec::with_capacity(num_replicas);
+            let mut idx = 0;
+            while idx < num_replicas
+                invariant
+                    0 < num_logs,
+                    num_replicas == config.num_replicas,
+                    copies.len() == num_replicas,
+                    0 <= log_idx < num_logs,
+                    0 <= idx <= num_replicas,
+                    states.len() == idx,
+                    forall|i|
+                        #![trigger states[i]]
+                        0 <= i < idx ==> states[i].inv() && states[i]@ == DT::init_spec(
+                            log_idx as nat,
+                        ),
+                    forall|r: nat, l: nat|
+                        #![trigger parts[r][l]]
+                        r < num_replicas && log_idx <= l < num_logs && (l != log_idx || r
+                            < num_replicas - idx) ==> {
+                            &&& parts.contains_key(r)
+                            &&& parts[r].contains_key(l)
+                            &&& (*copies[r as int]).partition_wf(parts[r][l])
+                            &&& DT::partition_idx(parts[r][l]) == l
+                            &&& DT::partition_view(parts[r][l]) == DT::init_spec(l)
+                        },
+            {
+                let replica_id = num_replicas - 1 - idx;
+                let tracked part;
+                proof {
+                    let tracked mut copy_parts = parts.tracked_remove(replica_id as nat);
+                    part = copy_parts.tracked_remove(log_idx as nat);
+                    parts.tracked_insert(replica_id as nat, copy_parts);
+                }
+                let ds = Arc::clone(&copies[replica_id]);
+                states.push(LogPartition { ds, part: Tracked(part) });
+                idx = idx + 1;
+            }
+            let log = NodeReplicated::with_states(
+                &config,
+                &chg_mem_affinity,
+                observer.share(),
+                states,
+                Ghost(DT::init_spec(log_idx as nat)),
+            );
+            logs.push(log);
+            log_idx = log_idx + 1;
+        }
+        MultiLogNodeReplicated { logs, num_replicas }
+    }
+
+    /// Returns the thread tokens of a thread to the replicas of the first `tkns.len()` logs.
+    fn deregister_tokens(&self, rid: ReplicaId, tkns: Vec<ThreadToken<LogPartition<DT>>>)
+        requires
+            self.wf(),
+            rid < self.num_replicas,
+            tkns.len() <= self.logs.len(),
+            forall|i|
+                0 <= i < tkns.len() ==> {
+                    &&& (#[trigger] tkns[i]).replica_id_spec() == rid
+                    &&& tkns[i].wf(&self.logs[i].replicas[rid as int])
+                },
+    {
+        let mut tkns = tkns;
+        let mut idx = tkns.len();
+        while idx > 0
+            invariant
+                self.wf(),
+                rid < self.num_replicas,
+                0 <= idx <= self.logs.len(),
+                tkns.len() == idx,
+                forall|i|
+                    0 <= i < idx ==> {
+                        &&& (#[trigger] tkns[i]).replica_id_spec() == rid
+                        &&& tkns[i].wf(&self.logs[i].replicas[rid as int])
+                    },
+        {
+            idx = idx - 1;
+            let log_tkn = tkns.pop().unwrap();
+            self.logs[idx].deregister(log_tkn);
+        }
+    }
+}
+
+impl<DT: LogMapper + Send + Sync> crate::MultiLogNodeReplicatedT<DT> for MultiLogNodeReplicated<DT> {
+    type Replica = Replica<LogPartition<DT>>;
+
+    type TT = MultiLogThreadToken<DT>;
+
+    /// Wellformedness of the multi-log data structure
+    open spec fn wf(&self) -> bool {
+        &&& 0 < self.logs.len()
+        &&& 0 < self.num_replicas <= MAX_REPLICAS
+        // all logs are well-formed and have the same number of replicas
+        &&& (forall|i|
+            0 <= i < self.logs.len() ==> {
+                &&& (#[trigger] self.logs[i]).wf()
+                &&& self.logs[i].replicas.len() == self.num_replicas
+            })
+    }
+
+    open spec fn num_logs(&self) -> nat {
+        self.logs.len() as nat
+    }
+
+    open spec fn num_replicas(&self) -> nat {
+        self.num_replicas as nat
+    }
+
+    open spec fn replica(&self, log_idx: nat, replica_id: nat) -> Replica<LogPartition<DT>> {
+        *self.logs[log_idx as int].replicas[replica_id as int]
+    }
+
+    open spec fn unbounded_log_instance(&self, log_idx: nat) -> UnboundedLog::Instance<
+        LogPartition<DT>,
+    > {
+        self.logs[log_idx as int].log.unbounded_log_instance@
+    }
+
+    open spec fn tkn_wf(&self, tkn: &MultiLogThreadToken<DT>) -> bool {
+        &&& tkn.tkns.len() == self.logs.len()
+        &&& tkn.rid < self.num_replicas
+        &&& (forall|i|
+            0 <= i < self.logs.len() ==> {
+                &&& (#[trigger] tkn.tkns[i]).replica_id_spec() == tkn.rid
+                &&& tkn.tkns[i].wf(&self.logs[i].replicas[tkn.rid as int])
+            })
+    }
+
+    open spec fn tkn_replica_id(tkn: &MultiLogThreadToken<DT>) -> nat {
+        tkn.replica_id_spec()
+    }
+
+    /// Creates a new data structure with `num_logs` logs and `num_replicas` replicas per log.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A (CNR: pub fn new(num_replicas: NonZeroUsize, nlogs: ...) -> Result<Self, _>)
+    fn new(num_logs: usize, num_replicas: usize, chg_mem_affinity: AffinityFn) -> (res: Self) {
+        Self::with_config(num_logs, NrConfig::new(num_replicas), chg_mem_affinity)
+    }
+
+    /// Registers a thread with the replica of the given id in every log.
+    ///
+    /// If one of the logs runs out of thread tokens, the tokens obtained from the other logs
+    /// are returned to their replicas.
+    fn register(&mut self, replica_id: ReplicaId) -> (result: Option<MultiLogThreadToken<DT>>) {
+        if replica_id >= self.num_replicas {
+            return None;
+        }
+        let num_logs = self.logs.len();
+        let mut tkns: Vec<ThreadToken<LogPartition<DT>>> = Vec::new();
+        let mut idx = 0;
+        while idx < num_logs
+            invariant
+                self.wf(),
+                self.logs.len() == num_logs,
+                self.num_replicas == old(self).num_replicas,
+                replica_id < self.num_replicas,
+                forall|i|
+                    0 <= i < num_logs ==> #[trigger] self.logs[i].log.unbounded_log_instance@
+                        == old(self).logs[i].log.unbounded_log_instance@,
+                0 <= idx <= num_logs,
+                tkns.len() == idx,
+                forall|i|
+                    0 <= i < idx ==> {
+                        &&& (#[trigger] tkns[i]).replica_id_spec() == replica_id
+                        &&& tkns[i].wf(&self.logs[i].replicas[replica_id as int])
+                    },
+        {
+            let ghost prev_logs = self.logs@;
+            let mut log = self.logs.remove(idx);
+            let res = log.register(replica_id);
+            self.logs.insert(idx, log);
+            proof {
+                assert(forall|i| 0 <= i < num_logs && i != idx ==> self.logs@[i] == prev_logs[i]);
+            }
+            match res {
+                Some(tkn) => {
+                    tkns.push(tkn);
+                },
+                None => {
+                    self.deregister_tokens(replica_id, tkns);
+                    return None;
+                },
+            }
+            idx = idx + 1;
+        }
+        Some(MultiLogThreadToken { rid: replica_id, tkns })
+    }
+
+    /// Deregisters a thread from the replica of every log, returning the thread tokens to them.
+    fn deregister(&self, tkn: MultiLogThreadToken<DT>) {
+        let MultiLogThreadToken { rid, tkns } = tkn;
+        self.deregister_tokens(rid, tkns);
+    }
+
+    /// Executes a mutable operation against the log selected by [`LogMapper::write_op_log_idx`].
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A (CNR: pub fn execute_mut(&self, op, tkn: ThreadToken) -> D::Response)
+    fn execute_mut(
+        &self,
+        op: DT::WriteOperation,
+        tkn: MultiLogThreadToken<DT>,
+        ticket: Tracked<UnboundedLog::local_updates<LogPartition<DT>>>,
+    ) -> (result: Result<
+        (
+            DT::Response,
+            MultiLogThreadToken<DT>,
+            Tracked<UnboundedLog::local_updates<LogPartition<DT>>>,
+        ),
+        (MultiLogThreadToken<DT>, Tracked<UnboundedLog::local_updates<LogPartition<DT>>>, NrError),
+    >) {
+        let log_idx = DT::write_op_log_idx(&op, self.logs.len());
+        let MultiLogThreadToken { rid, mut tkns } = tkn;
+        let ghost prev_vec = tkns;
+        let ghost prev_tkns = tkns@;
+        let log_tkn = tkns.remove(log_idx);
+        match self.logs[log_idx].execute_mut(op, log_tkn, ticket) {
+            Ok((resp, log_tkn, stub)) => {
+                tkns.insert(log_idx, log_tkn);
+                proof {
+                    assert(forall|i|
+                        0 <= i < tkns.len() && i != log_idx ==> tkns@[i] == prev_tkns[i]);
+                }
+                Ok((resp, MultiLogThreadToken { rid, tkns }, stub))
+            },
+            Err((log_tkn, ticket, err)) => {
+                tkns.insert(log_idx, log_tkn);
+                proof {
+                    assert(forall|i|
+                        0 <= i < tkns.len() && i != log_idx ==> tkns@[i] == prev_tkns[i]);
+                    if err != NrError::LogExhausted {
+                        // the token of the log is returned unchanged
+                        assert(tkns@ =~= prev_tkns);
+                        assert(tkns =~= prev_vec);
+                    }
+                }
+                Err((MultiLogThreadToken { rid, tkns }, ticket, err))
+            },
+        }
+    }
+
+    /// Executes an immutable operation against the log selected by
+    /// [`LogMapper::read_op_log_idx`].
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A (CNR: pub fn execute(&self, op, tkn: ThreadToken) -> D::Response)
+    fn execute(
+        &self,
+        op: DT::ReadOperation,
+        tkn: MultiLogThreadToken<DT>,
+        ticket: Tracked<UnboundedLog::local_reads<LogPartition<DT>>>,
+    ) -> (result: Result<
+        (
+            DT::Response,
+            MultiLogThreadToken<DT>,
+            Tracked<UnboundedLog::local_reads<LogPartition<DT>>>,
+        ),
+        (MultiLogThreadToken<DT>, Tracked<UnboundedLog::local_reads<LogPartition<DT>>>, NrError),
+    >) {
+        let log_idx = DT::read_op_log_idx(&op, self.logs.len());
+        let MultiLogThreadToken { rid, mut tkns } = tkn;
+        let ghost prev_vec = tkns;
+        let ghost prev_tkns = tkns@;
+        let log_tkn = tkns.remove(log_idx);
+        match self.logs[log_idx].execute(op, log_tkn, ticket) {
+            Ok((resp, log_tkn, stub)) => {
+                tkns.insert(log_idx, log_tkn);
+                proof {
+                    assert(forall|i|
+                        0 <= i < tkns.len() && i != log_idx ==> tkns@[i] == prev_tkns[i]);
+                }
+                Ok((resp, MultiLogThreadToken { rid, tkns }, stub))
+            },
+            Err((log_tkn, ticket, err)) => {
+                tkns.insert(log_idx, log_tkn);
+                proof {
+                    assert(tkns@ =~= prev_tkns);
+                    assert(tkns =~= prev_vec);
+                }
+                Err((MultiLogThreadToken { rid, tkns }, ticket, err))
+            },
+        }
+    }
+}
+
+} // verus!
@@ -6,7 +6,7 @@ use builtin::*;
 use builtin_macros::*;
 
 use vstd::{
-    atomic_ghost::AtomicU64,
+    atomic_ghost::{AtomicBool, AtomicU64},
     atomic_with_ghost,
     cell::{CellId, PCell, PointsTo},
     map::Map,
@@ -14,39 +14,42 @@ use vstd::{
 };
 
 use crate::constants::{
-    MAX_REPLICAS, MAX_REQUESTS, MAX_THREADS_PER_REPLICA, RESPONSE_CHECK_INTERVAL,
+    MAX_APPEND, MAX_PENDING_OPS, MAX_REPLICAS, MAX_THREADS_PER_REPLICA, RESPONSE_CHECK_INTERVAL,
+    RETIRED_VERSION,
 };
 
-use crate::Dispatch;
+use crate::{Dispatch, NrError, NrEvent};
 
 // spec import
 use crate::spec::cyclicbuffer::CyclicBuffer;
 use crate::spec::flat_combiner::FlatCombiner;
+#[cfg(verus_keep_ghost)]
+use crate::spec::flat_combiner::{slot_id, slot_id_bound, slot_id_inverse, slot_id_next_thread};
 use crate::spec::types::{NodeId, ReqId};
 use crate::spec::unbounded_log::UnboundedLog;
 #[cfg(verus_keep_ghost)]
-use crate::{is_readonly_stub, is_readonly_ticket, is_update_stub, is_update_ticket};
+use crate::spec::unbounded_log::{is_update_batch_stubs, is_update_batch_tickets};
+#[cfg(verus_keep_ghost)]
+use crate::{
+    is_readonly_batch_stubs, is_readonly_batch_tickets, is_readonly_stub, is_readonly_ticket,
+    is_update_stub, is_update_ticket,
+};
 
 // exec imports
 use crate::exec::context::{
-    Context, FCClientRequestResponseGhost, PendingOperation, ThreadId, ThreadToken,
+    Context, FCClientRequestResponseGhost, PendingHandle, PendingOperation, ThreadId, ThreadToken,
 };
 use crate::exec::log::{NrLog, NrLogAppendExecDataGhost};
 use crate::exec::rwlock::RwLock;
 #[cfg(verus_keep_ghost)]
 use crate::exec::utils::{rids_match, rids_match_add_none, rids_match_add_rid, rids_match_pop};
+use crate::exec::utils::spin_loop_hint;
 use crate::exec::CachePadded;
 
 // use crate::exec::rwlock_unverified::RwLock as RwLockUnverified;
 
 verus! {
 
-#[verus::trusted]
-#[verifier(external_body)]  /* vattr */
-fn spin_loop_hint() {
-    core::hint::spin_loop();
-}
-
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // Replica Types
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -181,6 +184,12 @@ pub struct Replica<DT: Dispatch> {
     /// inflight: RefCell<[usize; MAX_THREADS_PER_REPLICA]>,
     pub collected_operations_per_thread: PCell<Vec<usize>>,
 
+    /// The entry of each thread's batch at which the combiner resumes collecting. The entries
+    /// are used round-robin, the ones before it have been collected by earlier rounds.
+    ///
+    /// Safety: Protected by the combiner lock.
+    pub collect_heads: PCell<Vec<usize>>,
+
     /// A buffer of results collected after flat combining. With the help of
     /// `inflight`, the combiner enqueues these results into the appropriate
     /// thread context.
@@ -204,8 +213,15 @@ pub struct Replica<DT: Dispatch> {
     // with the replica when calling [`Replica::register()`].
     pub num_threads: u64, //CachePadded<AtomicU64<_, Tracked<u64>, _>>,
 
+    /// Lock of the thread tokens, it is taken by the threads that register or deregister with
+    /// the replica. While the lock is not taken, its ghost state holds the permission to access
+    /// the thread tokens.
+    pub tokens_lock: CachePadded<AtomicBool<_, Option<PointsTo<Vec<ThreadToken<DT>>>>, _>>,
+
     /// thread token that is handed out to the threads that register
-    pub /* REVIEW: (crate) */ thread_tokens: Vec<ThreadToken<DT>>,
+    ///
+    /// Safety: Protected by the tokens lock.
+    pub /* REVIEW: (crate) */ thread_tokens: PCell<Vec<ThreadToken<DT>>>,
 
     pub unbounded_log_instance: Tracked<UnboundedLog::Instance<DT>>,
     pub cyclic_buffer_instance: Tracked<CyclicBuffer::Instance<DT>>,
@@ -221,23 +237,27 @@ pub open spec fn wf(&self) -> bool {
 
         &&& self.replica_token.rid < self.unbounded_log_instance@.num_replicas()
 
-        &&& self.contexts.len() == MAX_THREADS_PER_REPLICA
+        &&& 0 < self.contexts.len() <= MAX_THREADS_PER_REPLICA
         &&& self.data.0.max_threads() == self.contexts.len()
         &&& 0 <= self.spec_id() < MAX_REPLICAS
         &&& self.data.0.wf()
         &&& (forall |v: ReplicatedDataStructure<DT>| (#[trigger] self.data.0.inv(v)) == (v.wf(self.spec_id(), self.unbounded_log_instance@, self.cyclic_buffer_instance@) && v.data.inv()))
 
-        &&& self.flat_combiner_instance@.num_threads() == MAX_THREADS_PER_REPLICA
-        &&& (forall |i| #![trigger self.thread_tokens[i]] 0 <= i < self.thread_tokens.len() ==> {
-            self.thread_tokens[i].wf(self)
-        })
+        &&& self.flat_combiner_instance@.num_threads() == self.contexts.len()
+        &&& self.flat_combiner_instance@.batch_size() == MAX_PENDING_OPS
     }
 
-    invariant on combiner with (flat_combiner_instance, responses, collected_operations, collected_operations_per_thread) specifically (self.combiner.0) is (v: u64, g: Option<CombinerLockStateGhost<DT>>) {
+    invariant on combiner with (flat_combiner_instance, responses, collected_operations, collected_operations_per_thread, collect_heads) specifically (self.combiner.0) is (v: u64, g: Option<CombinerLockStateGhost<DT>>) {
         // v != 0 means lock is not taken, if it's not taken, the ghost state is Some
         &&& (v == 0) <==> g.is_some()
         //
-        &&& (g.is_some() ==> g.get_Some_0().inv(flat_combiner_instance@, responses.id(), collected_operations.id(), collected_operations_per_thread.id()))
+        &&& (g.is_some() ==> g.get_Some_0().inv(flat_combiner_instance@, responses.id(), collected_operations.id(), collected_operations_per_thread.id(), collect_heads.id()))
+    }
+
+    invariant on tokens_lock with (replica_token, contexts, flat_combiner_instance, thread_tokens) specifically (self.tokens_lock.0) is (v: bool, g: Option<PointsTo<Vec<ThreadToken<DT>>>>) {
+        // the lock is not taken iff the ghost state holds the permission to the thread tokens
+        &&& !v <==> g.is_some()
+        &&& (g.is_some() ==> thread_tokens_inv(g.get_Some_0(), thread_tokens.id(), replica_token.id_spec(), contexts@, flat_combiner_instance@))
     }
 
     // invariant on num_threads with (flat_combiner_instance) specifically (self.num_threads.0)  is (v: u64, g: Tracked<u64>) {
@@ -247,6 +267,24 @@ pub open spec fn wf(&self) -> bool {
 
 }  // struct_with_invariants!
 
+/// the permission accesses the thread tokens of the replica and all of them are well-formed for
+/// the replica, with none of the entries of their batches pending
+pub open spec fn thread_tokens_inv<DT: Dispatch>(
+    perm: PointsTo<Vec<ThreadToken<DT>>>,
+    cell: CellId,
+    replica_id: nat,
+    contexts: Seq<Context<DT>>,
+    fc_instance: FlatCombiner::Instance,
+) -> bool {
+    &&& perm@.pcell == cell
+    &&& perm@.value.is_some()
+    &&& forall|i|
+        #![trigger perm@.value.get_Some_0()[i]]
+        0 <= i < perm@.value.get_Some_0().len() ==> {
+            &&& perm@.value.get_Some_0()[i].wf_pending_in(replica_id, contexts, fc_instance)
+            &&& perm@.value.get_Some_0()[i].no_pending()
+        }
+}
 
 impl<DT: Dispatch> Replica<DT> {
     pub fn new(
@@ -255,7 +293,7 @@ impl<DT: Dispatch> Replica<DT> {
         config: Tracked<ReplicaConfig<DT>>,
     ) -> (res: Self)
         requires
-            num_threads == MAX_THREADS_PER_REPLICA,
+            0 < num_threads <= MAX_THREADS_PER_REPLICA,
             replica_token.id_spec() < MAX_REPLICAS,
             config@.wf(replica_token.id_spec()),
         ensures
@@ -263,6 +301,28 @@ impl<DT: Dispatch> Replica<DT> {
             res.spec_id() == replica_token.id_spec(),
             res.unbounded_log_instance@ == config@.unbounded_log_instance,
             res.cyclic_buffer_instance@ == config@.cyclic_buffer_instance,
+    {
+        Self::with_data(replica_token, num_threads, DT::init(), config)
+    }
+
+    /// Creates a new replica with the given data structure, e.g., a copy of the data structure of
+    /// another replica.
+    pub fn with_data(
+        replica_token: ReplicaToken,
+        num_threads: usize,
+        data: DT,
+        config: Tracked<ReplicaConfig<DT>>,
+    ) -> (res: Self)
+        requires
+            0 < num_threads <= MAX_THREADS_PER_REPLICA,
+            replica_token.id_spec() < MAX_REPLICAS,
+            data.inv(),
+            config@.wf_with_state(replica_token.id_spec(), data@),
+        ensures
+            res.wf(),
+            res.spec_id() == replica_token.id_spec(),
+            res.unbounded_log_instance@ == config@.unbounded_log_instance,
+            res.cyclic_buffer_instance@ == config@.cyclic_buffer_instance,
     {
         let tracked ReplicaConfig {
             replica: replica,
@@ -284,7 +344,7 @@ impl<DT: Dispatch> Replica<DT> {
                 Tracked(fc_clients0),  // Map<ThreadId, FlatCombiner::clients>,
                 Tracked(fc_slots0),  // Map<ThreadId, FlatCombiner::slots>,
                 Tracked(fc_combiner0),  // FlatCombiner::combiner
-            ) = FlatCombiner::Instance::initialize(num_threads as nat);
+            ) = FlatCombiner::Instance::initialize(num_threads as nat, MAX_PENDING_OPS as nat);
             fc_instance = fc_instance0;
             fc_clients = fc_clients0;
             fc_slots = fc_slots0;
@@ -293,18 +353,28 @@ impl<DT: Dispatch> Replica<DT> {
         //
         // create the memory cells for the buffers
         //
-        let (responses, responses_token) = PCell::new(Vec::with_capacity(num_threads));
+        let num_slots = num_threads * MAX_PENDING_OPS;
+        let (responses, responses_token) = PCell::new(Vec::with_capacity(num_slots));
         let (collected_operations, collected_operations_perm) = PCell::new(
-            Vec::with_capacity(num_threads),
+            Vec::with_capacity(num_slots),
         );
         let (collected_operations_per_thread, collected_operations_per_thread_perm) = PCell::new(
-            Vec::with_capacity(num_threads),
+            Vec::with_capacity(num_slots),
         );
+        // the combiner starts collecting at the first entry of the batch of every thread
+        let mut heads: Vec<usize> = Vec::with_capacity(num_threads);
+        while heads.len() < num_threads
+            invariant
+                heads.len() <= num_threads,
+        {
+            heads.push(0);
+        }
+        let (collect_heads, collect_heads_perm) = PCell::new(heads);
         //
         // create the data structure protected by the RW lock
         //
         let replicated_data_structure = ReplicatedDataStructure {
-            data: DT::init(),
+            data,
             replica: Tracked(replica),
             combiner: Tracked(combiner),
             cb_combiner: Tracked(cb_combiner),
@@ -319,7 +389,7 @@ impl<DT: Dispatch> Replica<DT> {
             { s.wf(replica_token.id_spec(), unbounded_log_instance, cyclic_buffer_instance) && s.data.inv() };
         let data = CachePadded(
             RwLock::new(
-                MAX_THREADS_PER_REPLICA,
+                num_threads,
                 replicated_data_structure,
                 Ghost(data_structure_inv),
             ),
@@ -335,32 +405,33 @@ impl<DT: Dispatch> Replica<DT> {
         // Create the thread contexts
         //
         let mut contexts: Vec<Context<DT>> = Vec::with_capacity(num_threads);
-        let mut thread_tokens: Vec<ThreadToken<DT>> = Vec::with_capacity(num_threads);
+        let mut tokens: Vec<ThreadToken<DT>> = Vec::with_capacity(num_threads);
         let mut idx = 0;
         while idx < num_threads
             invariant
                 num_threads <= MAX_THREADS_PER_REPLICA,
                 replica_token.id_spec() < unbounded_log_instance.num_replicas(),
                 contexts.len() == idx,
-                thread_tokens.len() == idx,
+                tokens.len() == idx,
                 0 <= idx <= num_threads,
-                forall|i: nat| idx <= i < num_threads ==> fc_slots.contains_key(i),
-                forall|i: nat|
-                    #![trigger fc_slots[i]]
-                    idx <= i < num_threads ==> {
-                        &&& fc_slots[i]@.value.is_Empty()
-                        &&& fc_slots[i]@.key == i
-                        &&& fc_slots[i]@.instance == fc_instance
+                forall|s: nat|
+                    #![trigger fc_slots[s]]
+                    slot_id(idx as nat, 0, MAX_PENDING_OPS as nat) <= s < num_threads
+                        * MAX_PENDING_OPS ==> {
+                        &&& fc_slots.contains_key(s)
+                        &&& fc_slots[s]@.value.is_Empty()
+                        &&& fc_slots[s]@.key == s
+                        &&& fc_slots[s]@.instance == fc_instance
                     },
-                forall|i: nat| idx <= i < num_threads ==> fc_clients.contains_key(i),
-                forall|i: nat|
-                    #![trigger fc_clients[i]]
-                    idx <= i < num_threads ==> {
-                        &&& fc_clients[i]@.instance == fc_instance
-                        &&& fc_clients[i]@.key == i
-                        &&& fc_clients[i]@.value.is_Idle()
+                forall|s: nat|
+                    #![trigger fc_clients[s]]
+                    slot_id(idx as nat, 0, MAX_PENDING_OPS as nat) <= s < num_threads
+                        * MAX_PENDING_OPS ==> {
+                        &&& fc_clients.contains_key(s)
+                        &&& fc_clients[s]@.instance == fc_instance
+                        &&& fc_clients[s]@.key == s
+                        &&& fc_clients[s]@.value.is_Idle()
                     },
-                forall|i: nat| idx <= i < num_threads ==> fc_slots.contains_key(i),
                 forall|i|
                     #![trigger contexts[i]]
                     0 <= i < contexts.len() ==> {
@@ -369,39 +440,111 @@ impl<DT: Dispatch> Replica<DT> {
                         &&& contexts[i].unbounded_log_instance == unbounded_log_instance
                     },
                 forall|i|
-                    #![trigger thread_tokens[i]]
-                    0 <= i < thread_tokens.len() ==> {
-                        &&& thread_tokens[i].wf2(unbounded_log_instance.num_replicas())
-                        &&& thread_tokens[i].thread_id_spec() == i
-                        &&& thread_tokens[i].rid@ == replica_token.id_spec()
-                        &&& thread_tokens[i].fc_client@@.instance == fc_instance
-                        &&& thread_tokens[i].batch_perm@@.pcell == contexts[i].batch.0.id()
+                    #![trigger tokens[i]]
+                    0 <= i < tokens.len() ==> {
+                        &&& tokens[i].wf2(unbounded_log_instance.num_replicas())
+                        &&& tokens[i].no_pending()
+                        &&& tokens[i].thread_id_spec() == i
+                        &&& tokens[i].rid@ == replica_token.id_spec()
+                        &&& forall|k: nat|
+                            #![trigger tokens[i].fc_clients@[k]]
+                            k < MAX_PENDING_OPS ==> {
+                                &&& tokens[i].fc_clients@[k]@.instance == fc_instance
+                                &&& tokens[i].batch_perms@[k]@.pcell
+                                    == contexts[i].batch[k as int].id()
+                            }
                     },
         {
-            let tracked slot;
-            let tracked client;
+            // take the flat combiner slots and clients of the thread's batch
+            let tracked mut slots: Map<nat, FlatCombiner::slots> = Map::tracked_empty();
+            let tracked mut clients: Map<nat, FlatCombiner::clients> = Map::tracked_empty();
+            let mut k = 0;
+            while k < MAX_PENDING_OPS
+                invariant
+                    0 <= idx < num_threads,
+                    0 <= k <= MAX_PENDING_OPS,
+                    forall|s: nat|
+                        #![trigger fc_slots[s]]
+                        slot_id(idx as nat, k as nat, MAX_PENDING_OPS as nat) <= s < num_threads
+                            * MAX_PENDING_OPS ==> {
+                            &&& fc_slots.contains_key(s)
+                            &&& fc_slots[s]@.value.is_Empty()
+                            &&& fc_slots[s]@.key == s
+                            &&& fc_slots[s]@.instance == fc_instance
+                        },
+                    forall|s: nat|
+                        #![trigger fc_clients[s]]
+                        slot_id(idx as nat, k as nat, MAX_PENDING_OPS as nat) <= s < num_threads
+                            * MAX_PENDING_OPS ==> {
+                            &&& fc_clients.contains_key(s)
+                            &&& fc_clients[s]@.instance == fc_instance
+                            &&& fc_clients[s]@.key == s
+                            &&& fc_clients[s]@.value.is_Idle()
+                        },
+                    forall|i: nat|
+                        #![trigger slot_id(idx as nat, i, MAX_PENDING_OPS as nat)]
+                        i < k ==> {
+                            let s = slot_id(idx as nat, i, MAX_PENDING_OPS as nat);
+                            &&& slots.contains_key(s)
+                            &&& slots[s]@.value.is_Empty()
+                            &&& slots[s]@.key == s
+                            &&& slots[s]@.instance == fc_instance
+                        },
+                    forall|i: nat|
+                        #![trigger clients[i]]
+                        i < k ==> {
+                            &&& clients.contains_key(i)
+                            &&& clients[i]@.instance == fc_instance
+                            &&& clients[i]@.key == slot_id(idx as nat, i, MAX_PENDING_OPS as nat)
+                            &&& clients[i]@.value.is_Idle()
+                        },
+            {
+                proof {
+                    let s = slot_id(idx as nat, k as nat, MAX_PENDING_OPS as nat);
+                    slot_id_bound(idx as nat, k as nat, num_threads as nat, MAX_PENDING_OPS as nat);
+                    slots.tracked_insert(s, fc_slots.tracked_remove(s));
+                    clients.tracked_insert(k as nat, fc_clients.tracked_remove(s));
+                }
+                k = k + 1;
+            }
             proof {
-                slot = fc_slots.tracked_remove(idx as nat);
-                client = fc_clients.tracked_remove(idx as nat);
+                slot_id_next_thread(idx as nat, MAX_PENDING_OPS as nat);
             }
             let fc_inst = Tracked(fc_instance.clone());
             let ul_inst = Tracked(unbounded_log_instance.clone());
-            let (context, batch_perm) = Context::new(idx, Tracked(slot), fc_inst, ul_inst);
-            let token = ThreadToken {
-                rid: replica_token.clone(),
-                tid: idx as u32,
-                fc_client: Tracked(client),
-                batch_perm,
-            };
+            let (context, batch_perms) = Context::new(idx, Tracked(slots), fc_inst, ul_inst);
+            let token = ThreadToken::new(
+                replica_token.clone(),
+                idx as u32,
+                Tracked(clients),
+                batch_perms,
+            );
             // assert(token.wf2(unbounded_log_instance.num_replicas()));
             contexts.push(context);
-            thread_tokens.push(token);
+            tokens.push(token);
             idx = idx + 1;
         }
+        let (thread_tokens, thread_tokens_perm) = PCell::new(tokens);
+        assert(thread_tokens_inv(
+            thread_tokens_perm@,
+            thread_tokens.id(),
+            replica_token.id_spec(),
+            contexts@,
+            fc_instance,
+        ));
+        let tracked fc_inst = fc_instance.clone();
+        let tokens_lock = CachePadded(
+            AtomicBool::new(
+                Ghost((replica_token, contexts, Tracked(fc_inst), thread_tokens)),
+                false,
+                Tracked(Option::Some(thread_tokens_perm.get())),
+            ),
+        );
         let tracked context_ghost = CombinerLockStateGhost {
             flat_combiner: Tracked(fc_combiner),
             collected_operations_perm,
             collected_operations_per_thread_perm,
+            collect_heads_perm,
             responses_token,
         };
         let tracked fc_inst = fc_instance.clone();
@@ -413,6 +556,7 @@ impl<DT: Dispatch> Replica<DT> {
                         responses,
                         collected_operations,
                         collected_operations_per_thread,
+                        collect_heads,
                     ),
                 ),
                 0,
@@ -429,9 +573,11 @@ impl<DT: Dispatch> Replica<DT> {
             contexts,
             collected_operations,
             collected_operations_per_thread,
+            collect_heads,
             responses,
             data,
             // _data,
+            tokens_lock,
             thread_tokens,
             num_threads,
             unbounded_log_instance: Tracked(unbounded_log_instance),
@@ -466,6 +612,7 @@ impl<DT: Dispatch> Replica<DT> {
                 self.responses.id(),
                 self.collected_operations.id(),
                 self.collected_operations_per_thread.id(),
+                self.collect_heads.id(),
             ),
     {
         // OPT: try to check whether the lock is already present
@@ -513,6 +660,40 @@ impl<DT: Dispatch> Replica<DT> {
         }
     }
 
+    /// Spins until the combiner lock has been acquired.
+    fn spin_combiner_lock(&self) -> (result: Tracked<CombinerLockStateGhost<DT>>)
+        requires
+            self.wf(),
+        ensures
+            result@.inv(
+                self.flat_combiner_instance@,
+                self.responses.id(),
+                self.collected_operations.id(),
+                self.collected_operations_per_thread.id(),
+                self.collect_heads.id(),
+            ),
+    {
+        let (mut acquired, mut combiner_lock) = self.acquire_combiner_lock();
+        while !acquired
+            invariant
+                self.wf(),
+                acquired ==> combiner_lock@.is_some(),
+                acquired ==> combiner_lock@.get_Some_0().inv(
+                    self.flat_combiner_instance@,
+                    self.responses.id(),
+                    self.collected_operations.id(),
+                    self.collected_operations_per_thread.id(),
+                    self.collect_heads.id(),
+                ),
+        {
+            spin_loop_hint();
+            let res = self.acquire_combiner_lock();
+            acquired = res.0;
+            combiner_lock = res.1;
+        }
+        Tracked(combiner_lock.get().tracked_unwrap())
+    }
+
     #[inline(always)]
     fn release_combiner_lock(&self, lock_state: Tracked<CombinerLockStateGhost<DT>>)
         requires
@@ -522,6 +703,7 @@ impl<DT: Dispatch> Replica<DT> {
                 self.responses.id(),
                 self.collected_operations.id(),
                 self.collected_operations_per_thread.id(),
+                self.collect_heads.id(),
             ),
     {
         atomic_with_ghost!(
@@ -535,10 +717,11 @@ impl<DT: Dispatch> Replica<DT> {
 
     /// Appends an operation to the log and attempts to perform flat combining.
     /// Accepts a thread `tid` as an argument. Required to acquire the combiner lock.
-    fn try_combine(&self, slog: &NrLog<DT>)
+    pub(crate) fn try_combine(&self, slog: &NrLog<DT>, peers: &Vec<Box<Replica<DT>>>)
         requires
             self.wf(),
             slog.wf(),
+            slog.wf_peers(peers@),
             self.unbounded_log_instance@ == slog.unbounded_log_instance@,
             slog.cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
     {
@@ -548,22 +731,132 @@ impl<DT: Dispatch> Replica<DT> {
         if acquired {
             assert(combiner_lock@.is_some());
             let combiner_lock = Tracked(combiner_lock.get().tracked_unwrap());
-            let combiner_lock = self.combine(slog, combiner_lock);
+            let combiner_lock = self.combine(slog, peers, combiner_lock);
             self.release_combiner_lock(combiner_lock);
         } else {
             // nothing to be done here.
         }
     }
 
+    /// Returns whether a thread of this replica has an operation enqueued that hasn't been
+    /// executed yet.
+    pub(crate) fn has_pending_ops(&self) -> (res: bool)
+        requires
+            self.wf(),
+    {
+        let mut thread_idx: usize = 0;
+        while thread_idx < self.contexts.len()
+            invariant
+                0 <= thread_idx <= self.contexts.len(),
+                self.wf(),
+        {
+            if self.contexts[thread_idx].has_pending_ops() {
+                return true;
+            }
+            thread_idx = thread_idx + 1;
+        }
+        false
+    }
+
+    /// Executes the outstanding log entries on behalf of this replica, unless one of its threads
+    /// is currently the combiner.
+    ///
+    /// This is called by threads of the `helper` replica that are blocked on a full log. The
+    /// combiner lock is only tried, the helping thread never waits for it. Holding the combiner
+    /// lock excludes all other writers of the data structure, hence the write lock is only
+    /// contended by readers.
+    pub(crate) fn try_sync(&self, slog: &NrLog<DT>, helper: ReplicaId)
+        requires
+            self.wf(),
+            slog.wf(),
+            helper != self.spec_id(),
+            self.unbounded_log_instance@ == slog.unbounded_log_instance@,
+            slog.cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
+    {
+        let (acquired, combiner_lock) = self.acquire_combiner_lock();
+        if acquired {
+            assert(combiner_lock@.is_some());
+            let combiner_lock = Tracked(combiner_lock.get().tracked_unwrap());
+            self.sync(slog, helper);
+            self.release_combiner_lock(combiner_lock);
+        }
+    }
+
+    /// Brings the data structure up to date with the log on behalf of this replica without
+    /// appending new operations.
+    ///
+    /// The combiners of the replica are handed over to the `helper` replica in the UnboundedLog
+    /// and the CyclicBuffer, the helper then executes the log without any requests of this
+    /// replica and hands the combiners back in their idle states.
+    fn sync(&self, slog: &NrLog<DT>, helper: ReplicaId)
+        requires
+            self.wf(),
+            slog.wf(),
+            helper != self.spec_id(),
+            self.unbounded_log_instance@ == slog.unbounded_log_instance@,
+            slog.cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
+    {
+        let (replicated_data_structure, write_handle) = self.data.0.acquire_write();
+        let mut data = replicated_data_structure.data;
+        let mut responses: Vec<DT::Response> = Vec::new();
+        let tracked mut combiner = replicated_data_structure.combiner.get();
+        let tracked mut cb_combiner = replicated_data_structure.cb_combiner.get();
+        proof {
+            combiner = self.unbounded_log_instance.borrow().exec_help_start(
+                self.spec_id(),
+                helper as nat,
+                combiner,
+            );
+            cb_combiner = self.cyclic_buffer_instance.borrow().help_start(
+                self.spec_id(),
+                helper as nat,
+                cb_combiner,
+            );
+        }
+        let tracked append_exec_ghost_data = NrLogAppendExecDataGhost {
+            local_updates: Tracked(Map::tracked_empty()),
+            ghost_replica: replicated_data_structure.replica,
+            combiner: Tracked(combiner),
+            cb_combiner: Tracked(cb_combiner),
+            request_ids: Ghost(Seq::empty()),
+        };
+        let append_exec_ghost_data = slog.execute(
+            &self.replica_token,
+            &mut responses,
+            &mut data,
+            Tracked(append_exec_ghost_data),
+        );
+        let Tracked(append_exec_ghost_data) = append_exec_ghost_data;
+        let tracked NrLogAppendExecDataGhost {
+            local_updates: _,
+            ghost_replica,
+            combiner,
+            cb_combiner,
+            request_ids: _,
+        } = append_exec_ghost_data;
+        let tracked ghost_replica = ghost_replica.get();
+        let tracked combiner = combiner.get();
+        let tracked cb_combiner = cb_combiner.get();
+        let replicated_data_structure = ReplicatedDataStructure {
+            data,
+            replica: Tracked(ghost_replica),
+            combiner: Tracked(combiner),
+            cb_combiner: Tracked(cb_combiner),
+        };
+        self.data.0.release_write(replicated_data_structure, write_handle);
+    }
+
     /// Performs one round of flat combining. Collects, appends and executes operations.
     fn combine(
         &self,
         slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
         combiner_lock: Tracked<CombinerLockStateGhost<DT>>,
     ) -> (result: Tracked<CombinerLockStateGhost<DT>>)
         requires
             self.wf(),
             slog.wf(),
+            slog.wf_peers(peers@),
             slog.unbounded_log_instance@ == self.unbounded_log_instance@,
             slog.cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
             combiner_lock@.inv(
@@ -571,6 +864,7 @@ impl<DT: Dispatch> Replica<DT> {
                 self.responses.id(),
                 self.collected_operations.id(),
                 self.collected_operations_per_thread.id(),
+                self.collect_heads.id(),
             ),
         ensures
             result@.inv(
@@ -578,6 +872,7 @@ impl<DT: Dispatch> Replica<DT> {
                 self.responses.id(),
                 self.collected_operations.id(),
                 self.collected_operations_per_thread.id(),
+                self.collect_heads.id(),
             ),
     {
         // disassemble the combiner lock
@@ -586,6 +881,7 @@ impl<DT: Dispatch> Replica<DT> {
         let tracked mut collected_operations_perm = combiner_lock.collected_operations_perm.get();
         let tracked mut collected_operations_per_thread_perm =
             combiner_lock.collected_operations_per_thread_perm.get();
+        let tracked mut collect_heads_perm = combiner_lock.collect_heads_perm.get();
         let tracked mut responses_token = combiner_lock.responses_token.get();
         // obtain access to the responses, operations and num_ops_per_thread buffers
         let mut responses = self.responses.take(Tracked(&mut responses_token));
@@ -596,15 +892,22 @@ impl<DT: Dispatch> Replica<DT> {
         let mut num_ops_per_thread = self.collected_operations_per_thread.take(
             Tracked(&mut collected_operations_per_thread_perm),
         );
+        let mut collect_heads = self.collect_heads.take(Tracked(&mut collect_heads_perm));
         // Step 1: collect the operations from the threads
         // self.collect_thread_ops(&mut buffer, operations.as_mut_slice());
         let Tracked(collect_res) = self.collect_thread_ops(
             &mut operations,
             &mut num_ops_per_thread,
+            &mut collect_heads,
             flat_combiner,
         );
         let tracked ThreadOpsData { flat_combiner, local_updates, request_ids, cell_permissions } =
             collect_res;
+        if operations.len() > 0 {
+            slog.observer.notify(
+                NrEvent::CombinerBatch { replica: self.id(), batch_size: operations.len() },
+            );
+        }
         // Step 2: Take the R/W lock on the data structure
         let (replicated_data_structure, write_handle) = self.data.0.acquire_write();
         let mut data = replicated_data_structure.data;
@@ -621,22 +924,30 @@ impl<DT: Dispatch> Replica<DT> {
             cb_combiner,
             request_ids,
         };
-        let append_exec_ghost_data = slog.append(
+        let (appended, append_exec_ghost_data) = match slog.append(
+            peers,
             &self.replica_token,
             &operations,
             &mut responses,
             &mut data,
             Tracked(append_exec_ghost_data),
-        );
-        // TODO: release lock here! upstream does release the lock here and the reacquire it!
-        // drop(replicated_data_structure);
-        // Step 3: Execute all operations
-        let append_exec_ghost_data = slog.execute(
-            &self.replica_token,
-            &mut responses,
-            &mut data,
-            append_exec_ghost_data,
-        );
+        ) {
+            Ok(append_exec_ghost_data) => {
+                // TODO: release lock here! upstream does release the lock here and the reacquire it!
+                // drop(replicated_data_structure);
+                // Step 3: Execute all operations
+                let append_exec_ghost_data = slog.execute(
+                    &self.replica_token,
+                    &mut responses,
+                    &mut data,
+                    append_exec_ghost_data,
+                );
+                (true, append_exec_ghost_data)
+            },
+            // the log has run out of indices, the threads obtain the tickets of their operations
+            // back instead of responses
+            Err(append_exec_ghost_data) => (false, append_exec_ghost_data),
+        };
         let Tracked(append_exec_ghost_data) = append_exec_ghost_data;
         let tracked NrLogAppendExecDataGhost {
             local_updates,
@@ -664,6 +975,7 @@ impl<DT: Dispatch> Replica<DT> {
             cell_permissions,
         };
         let distribute_thread_resps_result = self.distribute_thread_resps(
+            appended,
             &mut responses,
             &mut num_ops_per_thread,
             Tracked(thread_ops_data),
@@ -681,16 +993,27 @@ impl<DT: Dispatch> Replica<DT> {
             Tracked(&mut collected_operations_per_thread_perm),
             num_ops_per_thread,
         );
+        self.collect_heads.put(Tracked(&mut collect_heads_perm), collect_heads);
         // re-assemble the combiner lock
         let tracked combiner_lock = CombinerLockStateGhost {
             flat_combiner,
             collected_operations_perm: Tracked(collected_operations_perm),
             collected_operations_per_thread_perm: Tracked(collected_operations_per_thread_perm),
+            collect_heads_perm: Tracked(collect_heads_perm),
             responses_token: Tracked(responses_token),
         };
         Tracked(combiner_lock)
     }
 
+    /// Collects the operations of the threads registered with this replica.
+    ///
+    /// The threads use the entries of their batches round-robin. The combiner collects the
+    /// entries of a thread starting at its head up to the first entry without an operation, and
+    /// skips all other entries without loading them. The operations of a thread are therefore
+    /// collected in the order they have been submitted, and an idle thread costs a single load.
+    /// Collecting stops at the end of the batch, the entries at its beginning are collected by
+    /// the next round. A round collects at most `MAX_APPEND` operations, the threads that are
+    /// left over resume at their head in the next round.
     ///
     /// - Dafny: combine_collect()
     #[inline(always)]
@@ -698,17 +1021,20 @@ impl<DT: Dispatch> Replica<DT> {
         &self,
         operations: &mut Vec<DT::WriteOperation>,
         num_ops_per_thread: &mut Vec<usize>,
+        collect_heads: &mut Vec<usize>,
         flat_combiner: Tracked<FlatCombiner::combiner>,
     ) -> (response: Tracked<ThreadOpsData<DT>>)
         requires
             self.wf(),
             old(num_ops_per_thread).len() == 0,
             old(operations).len() == 0,
+            old(collect_heads).len() == self.contexts.len(),
             flat_combiner@@.instance == self.flat_combiner_instance@,
             flat_combiner@@.value.is_Collecting(),
             flat_combiner@@.value.get_Collecting_0().len() == 0,
         ensures
-            operations.len() <= MAX_REQUESTS,
+            operations.len() <= MAX_APPEND,
+            collect_heads.len() == self.contexts.len(),
             response@.collect_thread_ops_post(
                 self.flat_combiner_instance,
                 self.unbounded_log_instance@,
@@ -723,21 +1049,36 @@ impl<DT: Dispatch> Replica<DT> {
             Map::tracked_empty();
         let ghost mut request_ids = Seq::empty();
         // let num_registered_threads = self.next.load(Ordering::Relaxed);
-        let num_registered_threads = MAX_THREADS_PER_REPLICA;
-        // Collect operations from each thread registered with this replica.
+        let num_registered_threads = self.contexts.len();
+        // Collect operations from the entries of the batches of the threads registered with this
+        // replica, the slot index enumerates the entries of all threads.
         // for i in 1..num_registered_threads {
         let mut thread_idx = 0;
+        let mut op_idx = 0;
+        let ghost mut slot_idx: nat = 0;
+        // the entry of the current thread to start collecting at, whether collecting the thread
+        // has stopped, and the entry the next round resumes at
+        let mut head = collect_heads[0];
+        let mut stopped = false;
+        let mut resume = 0;
         while thread_idx < num_registered_threads
             invariant
                 0 <= thread_idx <= num_registered_threads,
+                0 <= op_idx < MAX_PENDING_OPS,
+                collect_heads.len() == num_registered_threads,
+                thread_idx == num_registered_threads ==> op_idx == 0,
+                slot_idx == slot_id(thread_idx as nat, op_idx as nat, MAX_PENDING_OPS as nat),
                 self.wf(),
-                operations.len() <= thread_idx,
+                operations.len() <= slot_idx,
+                operations.len() <= MAX_APPEND,
                 operations.len() == request_ids.len(),
-                num_ops_per_thread.len() == thread_idx,
+                num_ops_per_thread.len() == slot_idx,
                 self.contexts.len() == num_registered_threads,
+                self.contexts.len() <= MAX_THREADS_PER_REPLICA,
                 self.flat_combiner_instance@.num_threads() == num_registered_threads,
+                self.flat_combiner_instance@.batch_size() == MAX_PENDING_OPS,
                 flat_combiner@@.value.is_Collecting(),
-                flat_combiner@@.value.get_Collecting_0().len() == thread_idx,
+                flat_combiner@@.value.get_Collecting_0().len() == slot_idx,
                 flat_combiner@@.instance == self.flat_combiner_instance@,
                 forall|i: nat|
                     i < flat_combiner@@.value.get_Collecting_0().len() ==> (
@@ -747,7 +1088,9 @@ impl<DT: Dispatch> Replica<DT> {
                     i < flat_combiner@@.value.get_Collecting_0().len() && (
                     #[trigger] flat_combiner@@.value.get_Collecting_0()[i as int]).is_some() ==> {
                         &&& cell_permissions.contains_key(i)
-                        &&& cell_permissions[i]@.pcell === self.contexts@[i as int].batch.0.id()
+                        &&& cell_permissions[i]@.pcell === self.contexts@[(i
+                            / MAX_PENDING_OPS as nat) as int].batch@[(i
+                            % MAX_PENDING_OPS as nat) as int].id()
                         &&& cell_permissions[i]@.value.is_some()
                     },
                 forall|i| 0 <= i < request_ids.len() <==> updates.contains_key(i),
@@ -769,11 +1112,39 @@ impl<DT: Dispatch> Replica<DT> {
                     request_ids.len(),
                 ),
         {
+            proof {
+                slot_id_bound(
+                    thread_idx as nat,
+                    op_idx as nat,
+                    num_registered_threads as nat,
+                    MAX_PENDING_OPS as nat,
+                );
+                slot_id_inverse(thread_idx as nat, op_idx as nat, MAX_PENDING_OPS as nat);
+            }
             let tracked update_req: std::option::Option<UnboundedLog::local_updates<DT>>;
             let tracked batch_perms: std::option::Option<PointsTo<PendingOperation<DT>>>;
-            let num_ops =
+            if !stopped && op_idx >= head && operations.len() >= MAX_APPEND {
+                // the round is full, the next round resumes at this entry
+                stopped = true;
+                resume = op_idx;
+            }
+            let skip = stopped || op_idx < head;
+            let num_ops = if skip {
+                // the entry precedes the head, follows an entry without an operation or the
+                // round is full. It is collected by a later round
+                proof {
+                    rids_match_add_none(flat_combiner@@.value.get_Collecting_0(), request_ids,
+                        0, flat_combiner@@.value.get_Collecting_0().len(), 0, request_ids.len());
+                    self.flat_combiner_instance.borrow().combiner_collect_skip(
+                        flat_combiner.borrow_mut(),
+                    );
+                    update_req = None;
+                    batch_perms = None;
+                }
+                0
+            } else {
                 atomic_with_ghost!(
-                &self.contexts[thread_idx].atomic.0 => load();
+                &self.contexts[thread_idx].atomic[op_idx] => load();
                 returning num_ops;
                 ghost g // g : ContextGhost
             => {
@@ -797,16 +1168,23 @@ impl<DT: Dispatch> Replica<DT> {
                     update_req = None;
                     batch_perms = None;
                 }
-            });
+            })
+            };
+            if num_ops == 0 && !skip {
+                // the thread hasn't submitted to this entry yet, or it hasn't polled the response
+                // of the entry. It submits to the later entries only afterwards, stop here.
+                stopped = true;
+                resume = op_idx;
+            }
             if num_ops == 1 {
                 let tracked batch_token_value = batch_perms.tracked_unwrap();
                 let op = DT::clone_write_op(
-                    &self.contexts[thread_idx].batch.0.borrow(Tracked(&batch_token_value)).op,
+                    &self.contexts[thread_idx].batch[op_idx].borrow(Tracked(&batch_token_value)).op,
                 );
                 let tracked update_req = update_req.tracked_unwrap();
                 proof {
                     updates.tracked_insert(request_ids.len() as nat, update_req);
-                    cell_permissions.tracked_insert(thread_idx as nat, batch_token_value);
+                    cell_permissions.tracked_insert(slot_idx, batch_token_value);
                 }
                 proof {
                     request_ids = request_ids.push(update_req@.key);
@@ -816,7 +1194,25 @@ impl<DT: Dispatch> Replica<DT> {
             // set the number of active operations per thread
 
             num_ops_per_thread.push(num_ops as usize);
-            thread_idx = thread_idx + 1;
+            // advance to the next entry of the batch, or to the first entry of the next thread
+            if op_idx + 1 < MAX_PENDING_OPS {
+                op_idx = op_idx + 1;
+            } else {
+                proof {
+                    slot_id_next_thread(thread_idx as nat, MAX_PENDING_OPS as nat);
+                }
+                // the next round resumes at the entry we stopped at, or wraps around
+                collect_heads.set(thread_idx, if stopped { resume } else { 0 });
+                op_idx = 0;
+                thread_idx = thread_idx + 1;
+                stopped = false;
+                if thread_idx < num_registered_threads {
+                    head = collect_heads[thread_idx];
+                }
+            }
+            proof {
+                slot_idx = slot_idx + 1;
+            }
         }
         proof {
             self.flat_combiner_instance.borrow().combiner_responding_start(
@@ -832,9 +1228,13 @@ impl<DT: Dispatch> Replica<DT> {
         Tracked(thread_ops_data)
     }
 
+    /// Hands the responses to the threads. If the operations haven't been `appended` to the log,
+    /// the threads obtain the tickets of their operations back instead.
+    ///
     /// - Dafny: combine_respond
     fn distribute_thread_resps(
         &self,
+        appended: bool,
         responses: &mut Vec<DT::Response>,
         num_ops_per_thread: &mut Vec<usize>,
         thread_ops_data: Tracked<ThreadOpsData<DT>>,
@@ -844,6 +1244,7 @@ impl<DT: Dispatch> Replica<DT> {
             thread_ops_data@.distribute_thread_resps_pre(
                 self.flat_combiner_instance,
                 self.unbounded_log_instance@,
+                appended,
                 old(num_ops_per_thread)@,
                 old(responses)@,
                 self.contexts@,
@@ -869,34 +1270,43 @@ impl<DT: Dispatch> Replica<DT> {
         let tracked mut cell_permissions = cell_permissions.get();
         let tracked mut updates = local_updates.get();
         // let num_registered_threads = self.next.load(Ordering::Relaxed);
-        let num_registered_threads = MAX_THREADS_PER_REPLICA;
+        let num_registered_threads = self.contexts.len();
         // let (mut s, mut f) = (0, 0);
         // for i in 1..num_registered_threads {
         let mut thread_idx = 0;
+        let mut op_idx = 0;
+        let mut slot_idx: usize = 0;
         let mut resp_idx: usize = 0;
         while thread_idx < num_registered_threads
             invariant
                 0 <= thread_idx <= num_registered_threads,
-                0 <= resp_idx <= responses.len(),
-                resp_idx <= thread_idx,
-                num_ops_per_thread.len() == num_registered_threads,
-                request_ids@.len() == responses.len(),
-                num_registered_threads == MAX_THREADS_PER_REPLICA,
+                0 <= op_idx < MAX_PENDING_OPS,
+                thread_idx == num_registered_threads ==> op_idx == 0,
+                slot_idx == slot_id(thread_idx as nat, op_idx as nat, MAX_PENDING_OPS as nat),
+                0 <= resp_idx <= request_ids@.len(),
+                resp_idx <= slot_idx,
+                num_ops_per_thread.len() == num_registered_threads * MAX_PENDING_OPS,
+                appended ==> request_ids@.len() == responses.len(),
+                num_registered_threads == self.contexts.len(),
                 self.wf(),
                 self.flat_combiner_instance@.num_threads() == num_registered_threads,
+                self.flat_combiner_instance@.batch_size() == MAX_PENDING_OPS,
                 flat_combiner@.instance == self.flat_combiner_instance@,
                 flat_combiner@.value.is_Responding(),
-                flat_combiner@.value.get_Responding_1() == thread_idx,
-                flat_combiner@.value.get_Responding_0().len() == MAX_THREADS_PER_REPLICA,
+                flat_combiner@.value.get_Responding_1() == slot_idx,
+                flat_combiner@.value.get_Responding_0().len() == num_registered_threads
+                    * MAX_PENDING_OPS,
                 forall|i: nat|
                     i < flat_combiner@.value.get_Responding_0().len() ==> (
                     num_ops_per_thread[i as int] > 0) == (
                     #[trigger] flat_combiner@.value.get_Responding_0()[i as int]).is_some(),
                 forall|i: nat|
-                    thread_idx <= i < flat_combiner@.value.get_Responding_0().len() && (
+                    slot_idx <= i < flat_combiner@.value.get_Responding_0().len() && (
                     #[trigger] flat_combiner@.value.get_Responding_0()[i as int]).is_some() ==> {
                         &&& cell_permissions.contains_key(i)
-                        &&& cell_permissions[i]@.pcell === self.contexts@[i as int].batch.0.id()
+                        &&& cell_permissions[i]@.pcell === self.contexts@[(i
+                            / MAX_PENDING_OPS as nat) as int].batch@[(i
+                            % MAX_PENDING_OPS as nat) as int].id()
                         &&& cell_permissions[i]@.value.is_some()
                     },
                 forall|i: nat|
@@ -908,30 +1318,38 @@ impl<DT: Dispatch> Replica<DT> {
                     resp_idx <= i < request_ids@.len() ==> {
                         &&& updates.contains_key(i)
                         &&& updates[i]@.key == request_ids@[i as int]
-                        &&& updates[i]@.value.is_Done()
                         &&& updates[i]@.instance == self.unbounded_log_instance@
-                        &&& updates[i]@.value.get_Done_ret() == responses[i as int]
+                        &&& appended ==> updates[i]@.value.is_Done()
+                        &&& appended ==> updates[i]@.value.get_Done_ret() == responses[i as int]
+                        &&& !appended ==> updates[i]@.value.is_Init()
                     },
                 rids_match(
                     flat_combiner@.value.get_Responding_0(),
                     request_ids@,
-                    thread_idx as nat,
+                    slot_idx as nat,
                     flat_combiner@.value.get_Responding_0().len(),
                     resp_idx as nat,
                     request_ids@.len(),
                 ),
         {
             proof {
+                slot_id_bound(
+                    thread_idx as nat,
+                    op_idx as nat,
+                    num_registered_threads as nat,
+                    MAX_PENDING_OPS as nat,
+                );
+                slot_id_inverse(thread_idx as nat, op_idx as nat, MAX_PENDING_OPS as nat);
                 rids_match_pop(
                     flat_combiner@.value.get_Responding_0(),
                     request_ids@,
-                    thread_idx as nat,
+                    slot_idx as nat,
                     flat_combiner@.value.get_Responding_0().len(),
                     resp_idx as nat,
                     request_ids@.len(),
                 );
             }
-            let num_ops = num_ops_per_thread[thread_idx];
+            let num_ops = num_ops_per_thread[slot_idx];
             // assert(flat_combiner@.value.get_Responding_1() < num_registered_threads);
             if num_ops == 0 {
                 // if operations[i - 1] == 0 {
@@ -950,16 +1368,22 @@ impl<DT: Dispatch> Replica<DT> {
                 //     self.contexts[i - 1].enqueue_resps(&results[s..f]);
                 //     s += operations[i - 1];
                 // obtain the element from the operation batch
-                let tracked mut permission = cell_permissions.tracked_remove(thread_idx as nat);
-                let mut op_resp = self.contexts[thread_idx].batch.0.take(Tracked(&mut permission));
-                // update with the response
-                let resp: DT::Response = DT::clone_response(&responses[resp_idx]);
-                op_resp.resp = Some(resp);
+                let tracked mut permission = cell_permissions.tracked_remove(slot_idx as nat);
+                let mut op_resp = self.contexts[thread_idx].batch[op_idx].take(
+                    Tracked(&mut permission),
+                );
+                // update with the response, or with none if the operation hasn't been appended
+                if appended {
+                    let resp: DT::Response = DT::clone_response(&responses[resp_idx]);
+                    op_resp.resp = Some(resp);
+                } else {
+                    op_resp.resp = None;
+                }
                 // place the element back into the batch
-                self.contexts[thread_idx].batch.0.put(Tracked(&mut permission), op_resp);
+                self.contexts[thread_idx].batch[op_idx].put(Tracked(&mut permission), op_resp);
                 //     operations[i - 1] = 0;
                 atomic_with_ghost!(
-                    &self.contexts[thread_idx].atomic.0 => store(0);
+                    &self.contexts[thread_idx].atomic[op_idx] => store(0);
                     update prev -> next;
                     ghost g // g : ContextGhost
                     => {
@@ -971,7 +1395,17 @@ impl<DT: Dispatch> Replica<DT> {
                 );
                 resp_idx = resp_idx + 1;
             }
-            thread_idx = thread_idx + 1;
+            // advance to the next entry of the batch, or to the first entry of the next thread
+            if op_idx + 1 < MAX_PENDING_OPS {
+                op_idx = op_idx + 1;
+            } else {
+                proof {
+                    slot_id_next_thread(thread_idx as nat, MAX_PENDING_OPS as nat);
+                }
+                op_idx = 0;
+                thread_idx = thread_idx + 1;
+            }
+            slot_idx = slot_idx + 1;
         }
         proof {
             self.flat_combiner_instance.borrow().combiner_responding_done(&mut flat_combiner);
@@ -985,33 +1419,113 @@ impl<DT: Dispatch> Replica<DT> {
         Tracked(thread_ops_data)
     }
 
+    /// Spins until the lock of the thread tokens has been acquired.
+    fn acquire_tokens_lock(&self) -> (result: Tracked<PointsTo<Vec<ThreadToken<DT>>>>)
+        requires
+            self.wf(),
+        ensures
+            thread_tokens_inv(
+                result@,
+                self.thread_tokens.id(),
+                self.spec_id(),
+                self.contexts@,
+                self.flat_combiner_instance@,
+            ),
+    {
+        let tracked mut perm: Option<PointsTo<Vec<ThreadToken<DT>>>> = Option::None;
+        let mut acquired = false;
+        while !acquired
+            invariant
+                self.wf(),
+                acquired ==> perm.is_some() && thread_tokens_inv(
+                    perm.get_Some_0(),
+                    self.thread_tokens.id(),
+                    self.spec_id(),
+                    self.contexts@,
+                    self.flat_combiner_instance@,
+                ),
+        {
+            let res =
+                atomic_with_ghost!(
+                &self.tokens_lock.0 => compare_exchange(false, true);
+                update prev->next;
+                ghost g => {
+                    if !prev {
+                        perm = g;           // obtain the permission to the thread tokens
+                        g = Option::None;
+                    }
+                }
+            );
+            acquired = res.is_ok();
+            if !acquired {
+                spin_loop_hint();
+            }
+        }
+        Tracked(perm.tracked_unwrap())
+    }
+
+    #[inline(always)]
+    fn release_tokens_lock(&self, perm: Tracked<PointsTo<Vec<ThreadToken<DT>>>>)
+        requires
+            self.wf(),
+            thread_tokens_inv(
+                perm@,
+                self.thread_tokens.id(),
+                self.spec_id(),
+                self.contexts@,
+                self.flat_combiner_instance@,
+            ),
+    {
+        atomic_with_ghost!(
+            &self.tokens_lock.0 => store(false);
+            ghost g
+            => {
+                g = Option::Some(perm.get());
+            });
+    }
+
     /// Registers a thread with this replica. Returns a [`ReplicaToken`] if the
     /// registration was successfull. None if the registration failed.
-    pub fn register(&mut self) -> (res: Option<ThreadToken<DT>>)
+    pub fn register(&self) -> (res: Option<ThreadToken<DT>>)
         requires
-            old(self).wf(),
-        ensures
             self.wf(),
-            old(self).replica_token@ == self.replica_token@,
-            old(self).unbounded_log_instance@ == self.unbounded_log_instance@,
-            old(self).cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
+        ensures
             res.is_Some() ==> res.get_Some_0().wf(self),
     {
-        self.thread_tokens.pop()
+        let Tracked(mut perm) = self.acquire_tokens_lock();
+        let mut thread_tokens = self.thread_tokens.take(Tracked(&mut perm));
+        let res = thread_tokens.pop();
+        self.thread_tokens.put(Tracked(&mut perm), thread_tokens);
+        self.release_tokens_lock(Tracked(perm));
+        res
     }
 
-    #[verifier(external_body)]  /* vattr */
-    pub fn progress(line: u32) {
-        println!("Replica:: progress {line}");
+    /// Returns the thread token of a thread to this replica, such that it can be handed out again
+    /// by [`Replica::register`]. The well-formedness of the token ensures that the thread has no
+    /// pending operation in its context.
+    ///
+    /// The thread tokens are protected by their own lock, threads of the replica may deregister
+    /// while other threads are executing operations.
+    pub fn unregister(&self, tkn: ThreadToken<DT>)
+        requires
+            self.wf(),
+            tkn.wf(self),
+    {
+        let Tracked(mut perm) = self.acquire_tokens_lock();
+        let mut thread_tokens = self.thread_tokens.take(Tracked(&mut perm));
+        thread_tokens.push(tkn);
+        self.thread_tokens.put(Tracked(&mut perm), thread_tokens);
+        self.release_tokens_lock(Tracked(perm));
     }
 
     /// Executes an immutable operation against this replica and returns a
     /// response.
     ///
     /// In Dafny this refers to do_operation
-    pub fn execute(
+    pub(crate) fn execute(
         &self,
         slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
         op: DT::ReadOperation,
         tkn: ThreadToken<DT>,
         ticket: Tracked<UnboundedLog::local_reads<DT>>,
@@ -1019,16 +1533,14 @@ impl<DT: Dispatch> Replica<DT> {
         requires
             self.wf(),
             slog.wf(),
+            slog.wf_peers(peers@),
             tkn.wf(self),
-            tkn.batch_perm@@.pcell == self.contexts[tkn.thread_id_spec() as int].batch.0.id(),
             self.replica_token@ == tkn.replica_token()@,
             self.unbounded_log_instance@ == slog.unbounded_log_instance@,
             self.cyclic_buffer_instance@ == slog.cyclic_buffer_instance@,
             is_readonly_ticket(ticket@, op, self.spec_id(), slog.unbounded_log_instance@),
         ensures
             result.1.wf(&self),
-            result.1.batch_perm@@.pcell
-                == self.contexts[result.1.thread_id_spec() as int].batch.0.id(),
             is_readonly_stub(result.2@, ticket@@.key, result.0, slog.unbounded_log_instance@),
     {
         // let tracked local_reads : UnboundedLog::local_reads<DT>;
@@ -1060,6 +1572,7 @@ impl<DT: Dispatch> Replica<DT> {
             invariant
                 self.wf(),
                 slog.wf(),
+                slog.wf_peers(peers@),
                 !is_synced ==> ticket@@.value.is_VersionUpperBound(),
                 !is_synced ==> ticket@@.value.get_VersionUpperBound_version_upper_bound()
                     == version_upper_bound,
@@ -1073,7 +1586,7 @@ impl<DT: Dispatch> Replica<DT> {
                 slog.unbounded_log_instance@ == self.unbounded_log_instance@,
                 slog.cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
         {
-            self.try_combine(slog);
+            self.try_combine(slog, peers);
             spin_loop_hint();
             let res = slog.is_replica_synced_for_reads(self.id(), version_upper_bound, ticket);
             is_synced = res.0;
@@ -1106,50 +1619,435 @@ impl<DT: Dispatch> Replica<DT> {
         (result, tkn, Tracked(ticket))
     }
 
+    /// Executes a batch of immutable operations against this replica and returns the responses.
+    ///
+    /// All operations share one version upper bound and are dispatched while holding the read
+    /// lock once, hence the responses are computed against the same state of the replica. The
+    /// returned snapshot records this state together with the version of the log it corresponds
+    /// to, which is the linearization point of all operations.
+    ///
+    ///  - Dafny: N/A
+    ///  - Rust:  N/A
+    pub(crate) fn execute_many(
+        &self,
+        slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
+        ops: Vec<DT::ReadOperation>,
+        tkn: ThreadToken<DT>,
+        tickets: Tracked<Map<nat, UnboundedLog::local_reads<DT>>>,
+    ) -> (result: (
+        Vec<DT::Response>,
+        ThreadToken<DT>,
+        Tracked<Map<nat, UnboundedLog::local_reads<DT>>>,
+        Tracked<UnboundedLog::snapshots<DT>>,
+    ))
+        requires
+            self.wf(),
+            slog.wf(),
+            slog.wf_peers(peers@),
+            tkn.wf(self),
+            self.replica_token@ == tkn.replica_token()@,
+            self.unbounded_log_instance@ == slog.unbounded_log_instance@,
+            self.cyclic_buffer_instance@ == slog.cyclic_buffer_instance@,
+            is_readonly_batch_tickets(tickets@, ops@, self.spec_id(), slog.unbounded_log_instance@),
+        ensures
+            result.0.len() == ops.len(),
+            result.1 == tkn,
+            is_readonly_batch_stubs(
+                result.2@,
+                tickets@,
+                ops@,
+                result.0@,
+                result.3@,
+                slog.unbounded_log_instance@,
+            ),
+    {
+        let num_reads = ops.len();
+        let ghost ops0 = ops@;
+        let ghost tickets0 = tickets@;
+        // Step 1: Read the version upper bound once for all operations
+        let (version_upper_bound, tickets) = slog.get_version_upper_bound_multiple(
+            Ghost(num_reads as nat),
+            tickets,
+        );
+        let ghost tickets_vub = tickets@;
+        // Step 2: wait until the replica is synced for reads, try to combine in mean time
+        let (mut is_synced, mut tickets) = slog.is_replica_synced_for_reads_multiple(
+            self.id(),
+            version_upper_bound,
+            Ghost(num_reads as nat),
+            tickets,
+        );
+        while !is_synced
+            invariant
+                self.wf(),
+                slog.wf(),
+                slog.wf_peers(peers@),
+                slog.unbounded_log_instance@ == self.unbounded_log_instance@,
+                slog.cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
+                forall|i: nat|
+                    i < num_reads ==> {
+                        &&& #[trigger] tickets_vub.contains_key(i)
+                        &&& tickets_vub[i]@.instance == self.unbounded_log_instance@
+                        &&& tickets_vub[i]@.key == tickets0[i]@.key
+                        &&& tickets_vub[i]@.value.is_VersionUpperBound()
+                        &&& tickets_vub[i]@.value.get_VersionUpperBound_version_upper_bound()
+                            == version_upper_bound
+                        &&& tickets_vub[i]@.value.get_VersionUpperBound_node_id() == self.spec_id()
+                        &&& tickets_vub[i]@.value.get_VersionUpperBound_op() == ops0[i as int]
+                    },
+                !is_synced ==> tickets@ == tickets_vub,
+                is_synced ==> forall|i: nat|
+                    i < num_reads ==> {
+                        &&& #[trigger] tickets@.contains_key(i)
+                        &&& tickets@[i]@.instance == self.unbounded_log_instance@
+                        &&& tickets@[i]@.key == tickets0[i]@.key
+                        &&& tickets@[i]@.value.is_ReadyToRead()
+                        &&& tickets@[i]@.value.get_ReadyToRead_node_id() == self.spec_id()
+                        &&& tickets@[i]@.value.get_ReadyToRead_op() == ops0[i as int]
+                        &&& tickets@[i]@.value.get_ReadyToRead_version_upper_bound()
+                            == version_upper_bound
+                    },
+        {
+            self.try_combine(slog, peers);
+            spin_loop_hint();
+            let res = slog.is_replica_synced_for_reads_multiple(
+                self.id(),
+                version_upper_bound,
+                Ghost(num_reads as nat),
+                tickets,
+            );
+            is_synced = res.0;
+            tickets = res.1;
+        }
+        let tracked mut tickets = tickets.get();
+        assert(tkn.thread_id_spec() < self.data.0.max_threads());
+        loop
+            invariant
+                self.wf(),
+                slog.wf(),
+                slog.wf_peers(peers@),
+                tkn.wf(self),
+                slog.unbounded_log_instance@ == self.unbounded_log_instance@,
+                slog.cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
+                num_reads == ops0.len(),
+                ops@ == ops0,
+                forall|i: nat|
+                    i < num_reads ==> {
+                        &&& #[trigger] tickets.contains_key(i)
+                        &&& tickets[i]@.instance == self.unbounded_log_instance@
+                        &&& tickets[i]@.key == tickets0[i]@.key
+                        &&& tickets[i]@.value.is_ReadyToRead()
+                        &&& tickets[i]@.value.get_ReadyToRead_node_id() == self.spec_id()
+                        &&& tickets[i]@.value.get_ReadyToRead_op() == ops0[i as int]
+                        &&& tickets[i]@.value.get_ReadyToRead_version_upper_bound()
+                            == version_upper_bound
+                    },
+        {
+            // Step 3: Take the read-only lock once, and record the version of the replica. All
+            // reads are linearized at this version of the log.
+            let read_handle = self.data.0.acquire_read(tkn.thread_id() as usize);
+            let replica = self.data.0.borrow(Tracked(&read_handle));
+            let (version, snapshot) = slog.snapshot_version(
+                self.id(),
+                Tracked(replica.replica.borrow()),
+                Tracked(replica.combiner.borrow()),
+            );
+            if version != RETIRED_VERSION && version >= version_upper_bound {
+                let tracked snapshot = snapshot.get().tracked_unwrap();
+                let ghost state = replica.data@;
+                // Step 4: Dispatch the reads from the back of the vector, which is constant time
+                let mut ops = ops;
+                let mut reversed: Vec<DT::Response> = Vec::with_capacity(num_reads);
+                let tracked mut stubs: Map<nat, UnboundedLog::local_reads<DT>> =
+                    Map::tracked_empty();
+                while ops.len() > 0
+                    invariant
+                        self.wf(),
+                        self.data.0.wf_read_handle(&read_handle),
+                        replica.wf(
+                            self.spec_id(),
+                            self.unbounded_log_instance@,
+                            self.cyclic_buffer_instance@,
+                        ),
+                        replica.data.inv(),
+                        replica.data@ == state,
+                        num_reads == ops0.len(),
+                        ops@ == ops0.subrange(0, ops.len() as int),
+                        ops.len() + reversed.len() == num_reads,
+                        forall|i: nat|
+                            i < ops.len() ==> {
+                                &&& #[trigger] tickets.contains_key(i)
+                                &&& tickets[i]@.instance == self.unbounded_log_instance@
+                                &&& tickets[i]@.key == tickets0[i]@.key
+                                &&& tickets[i]@.value.is_ReadyToRead()
+                                &&& tickets[i]@.value.get_ReadyToRead_node_id() == self.spec_id()
+                                &&& tickets[i]@.value.get_ReadyToRead_op() == ops0[i as int]
+                                &&& tickets[i]@.value.get_ReadyToRead_version_upper_bound()
+                                    == version_upper_bound
+                            },
+                        forall|i: nat|
+                            ops.len() <= i < num_reads ==> {
+                                &&& #[trigger] stubs.contains_key(i)
+                                &&& is_readonly_stub(
+                                    stubs[i],
+                                    tickets0[i]@.key,
+                                    DT::dispatch_spec(state, ops0[i as int]),
+                                    self.unbounded_log_instance@,
+                                )
+                                &&& stubs[i]@.value.get_Done_version_upper_bound()
+                                    == version_upper_bound
+                            },
+                        forall|j: int|
+                            0 <= j < reversed.len() ==> #[trigger] reversed@[j]
+                                == DT::dispatch_spec(state, ops0[num_reads - 1 - j]),
+                    decreases ops.len(),
+                {
+                    let op = ops.pop().unwrap();
+                    let idx = ops.len();
+                    let response = replica.data.dispatch(op);
+                    proof {
+                        let tracked ticket = tickets.tracked_remove(idx as nat);
+                        let tracked stub = self.unbounded_log_instance.borrow().readonly_apply(
+                            ticket@.key,
+                            replica.replica.borrow(),
+                            ticket,
+                            replica.combiner.borrow(),
+                        );
+                        stubs.tracked_insert(idx as nat, stub);
+                    }
+                    reversed.push(response);
+                }
+                self.data.0.release_read(read_handle);
+                // Step 5: Restore the order of the operations
+                let mut responses: Vec<DT::Response> = Vec::with_capacity(num_reads);
+                while reversed.len() > 0
+                    invariant
+                        num_reads == ops0.len(),
+                        responses.len() + reversed.len() == num_reads,
+                        forall|j: int|
+                            0 <= j < reversed.len() ==> #[trigger] reversed@[j]
+                                == DT::dispatch_spec(state, ops0[num_reads - 1 - j]),
+                        forall|j: int|
+                            0 <= j < responses.len() ==> #[trigger] responses@[j]
+                                == DT::dispatch_spec(state, ops0[j]),
+                    decreases reversed.len(),
+                {
+                    responses.push(reversed.pop().unwrap());
+                }
+                assert(snapshot@.value == state);
+                return (responses, tkn, Tracked(stubs), Tracked(snapshot));
+            }
+            self.data.0.release_read(read_handle);
+            // the replica hasn't reached the version upper bound, try to combine in the mean time
+            self.try_combine(slog, peers);
+            spin_loop_hint();
+        }
+    }
+
+    /// Executes a read-only operation against this replica, tolerating bounded staleness.
+    ///
+    /// The read is served by the local replica as soon as its version is at most `max_lag`
+    /// entries behind the version upper bound, the replica only combines if it lags further
+    /// behind. The read is not linearizable and therefore does not use a read ticket. Instead,
+    /// the returned snapshot records the version of the log the response was computed from, and
+    /// the witness records the version upper bound the lag is measured against.
+    pub(crate) fn execute_relaxed(
+        &self,
+        slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
+        op: DT::ReadOperation,
+        tkn: ThreadToken<DT>,
+        max_lag: u64,
+    ) -> (result: (
+        DT::Response,
+        ThreadToken<DT>,
+        Tracked<UnboundedLog::snapshots<DT>>,
+        Tracked<UnboundedLog::version_upper_bound_reached<DT>>,
+    ))
+        requires
+            self.wf(),
+            slog.wf(),
+            slog.wf_peers(peers@),
+            tkn.wf(self),
+            self.replica_token@ == tkn.replica_token()@,
+            self.unbounded_log_instance@ == slog.unbounded_log_instance@,
+            self.cyclic_buffer_instance@ == slog.cyclic_buffer_instance@,
+        ensures
+            result.1 == tkn,
+            result.2@@.instance == slog.unbounded_log_instance@,
+            result.3@@.instance == slog.unbounded_log_instance@,
+            result.2@@.key + max_lag >= result.3@@.key,
+            result.0 == DT::dispatch_spec(result.2@@.value, op),
+    {
+        // Step 1: Read the version upper bound and compute the oldest acceptable version
+        let (version_upper_bound, witness) = slog.get_version_upper_bound_relaxed();
+        let min_version = if version_upper_bound > max_lag {
+            version_upper_bound - max_lag
+        } else {
+            0
+        };
+        // Step 2: Read the value once the replica has reached this version
+        let (result, snapshot) = self.read_at_version(slog, peers, op, &tkn, min_version);
+        (result, tkn, snapshot, witness)
+    }
+
+    /// Executes a read-only operation against this replica with session consistency.
+    ///
+    /// The thread token records a version of the log that includes the last update of the thread.
+    /// The read waits until the replica has reached this version, it therefore observes all
+    /// previous writes of the thread without reading the version upper bound.
+    pub(crate) fn execute_session(
+        &self,
+        slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
+        op: DT::ReadOperation,
+        tkn: ThreadToken<DT>,
+    ) -> (result: (DT::Response, ThreadToken<DT>, Tracked<UnboundedLog::snapshots<DT>>))
+        requires
+            self.wf(),
+            slog.wf(),
+            slog.wf_peers(peers@),
+            tkn.wf(self),
+            self.replica_token@ == tkn.replica_token()@,
+            self.unbounded_log_instance@ == slog.unbounded_log_instance@,
+            self.cyclic_buffer_instance@ == slog.cyclic_buffer_instance@,
+        ensures
+            result.1 == tkn,
+            result.2@@.instance == slog.unbounded_log_instance@,
+            result.2@@.key >= tkn.session_version_spec(),
+            result.0 == DT::dispatch_spec(result.2@@.value, op),
+    {
+        let session_version = tkn.session_version;
+        let (result, snapshot) = self.read_at_version(slog, peers, op, &tkn, session_version);
+        (result, tkn, snapshot)
+    }
+
+    /// Dispatches the read-only operation on the local replica once it has reached the given
+    /// version, holding the read lock.
+    ///
+    /// While the replica is behind, the thread tries to combine in the mean time. The returned
+    /// snapshot records the version and the state of the replica the read was executed on.
+    fn read_at_version(
+        &self,
+        slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
+        op: DT::ReadOperation,
+        tkn: &ThreadToken<DT>,
+        min_version: u64,
+    ) -> (result: (DT::Response, Tracked<UnboundedLog::snapshots<DT>>))
+        requires
+            self.wf(),
+            slog.wf(),
+            slog.wf_peers(peers@),
+            tkn.wf(self),
+            self.unbounded_log_instance@ == slog.unbounded_log_instance@,
+            self.cyclic_buffer_instance@ == slog.cyclic_buffer_instance@,
+        ensures
+            result.1@@.instance == slog.unbounded_log_instance@,
+            result.1@@.key >= min_version,
+            result.0 == DT::dispatch_spec(result.1@@.value, op),
+    {
+        assert(tkn.thread_id_spec() < self.data.0.max_threads());
+        loop
+            invariant
+                self.wf(),
+                slog.wf(),
+                slog.wf_peers(peers@),
+                tkn.wf(self),
+                slog.unbounded_log_instance@ == self.unbounded_log_instance@,
+                slog.cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
+        {
+            // Step 1: Take the read-only lock, and load the version of the replica
+            let read_handle = self.data.0.acquire_read(tkn.thread_id() as usize);
+            let replica = self.data.0.borrow(Tracked(&read_handle));
+            let (version, snapshot) = slog.snapshot_version(
+                self.id(),
+                Tracked(replica.replica.borrow()),
+                Tracked(replica.combiner.borrow()),
+            );
+            // Step 2: if the replica is recent enough, read the value
+            if version != RETIRED_VERSION && version >= min_version {
+                let result = replica.data.dispatch(op);
+                self.data.0.release_read(read_handle);
+                return (result, Tracked(snapshot.get().tracked_unwrap()));
+            }
+            self.data.0.release_read(read_handle);
+            // Step 3: the replica lags too far behind, try to combine in the mean time
+            self.try_combine(slog, peers);
+            spin_loop_hint();
+        }
+    }
+
     /// Executes a mutable operation against this replica and returns a
     /// response.
     ///
+    /// Returns the ticket of the operation with an error if it couldn't be appended to the log.
+    ///
     /// In Dafny this refers to do_operation
-    pub fn execute_mut(
+    pub(crate) fn execute_mut(
         &self,
         slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
         op: DT::WriteOperation,
         tkn: ThreadToken<DT>,
         ticket: Tracked<UnboundedLog::local_updates<DT>>,
-    ) -> (result: (DT::Response, ThreadToken<DT>, Tracked<UnboundedLog::local_updates<DT>>))
+    ) -> (result: Result<
+        (DT::Response, ThreadToken<DT>, Tracked<UnboundedLog::local_updates<DT>>),
+        (ThreadToken<DT>, Tracked<UnboundedLog::local_updates<DT>>, NrError),
+    >)
         requires
             slog.wf(),
+            slog.wf_peers(peers@),
             self.wf(),
             tkn.wf(self),
-            tkn.batch_perm@@.pcell == self.contexts[tkn.thread_id_spec() as int].batch.0.id(),
             self.replica_token == tkn.replica_token(),
             self.unbounded_log_instance@ == slog.unbounded_log_instance@,
             self.cyclic_buffer_instance@ == slog.cyclic_buffer_instance@,
             is_update_ticket(ticket@, op, slog.unbounded_log_instance@),
         ensures
-            result.1.wf(self),
-            result.1.batch_perm@@.pcell
-                == self.contexts[result.1.thread_id_spec() as int].batch.0.id(),
-            is_update_stub(result.2@, ticket@@.key, result.0, slog.unbounded_log_instance@),
+            result.is_Ok() ==> {
+                let (resp, tkn_out, stub) = result.get_Ok_0();
+                &&& tkn_out.wf(self)
+                &&& tkn_out.session_version_spec() > stub@@.value.get_Done_idx()
+                &&& is_update_stub(stub@, ticket@@.key, resp, slog.unbounded_log_instance@)
+            },
+            result.is_Err() ==> {
+                let (tkn_out, ticket_out, err) = result.get_Err_0();
+                &&& tkn_out.wf(self)
+                &&& tkn_out.replica_id_spec() == tkn.replica_id_spec()
+                &&& ticket_out@@.instance == ticket@@.instance
+                &&& ticket_out@@.key == ticket@@.key
+                &&& ticket_out@@.value.is_Init()
+                &&& err == NrError::LogExhausted
+            },
     {
         let tracked ticket = ticket.get();
         let ghost req_id: nat = ticket@.key;
-        let ThreadToken { rid, tid, fc_client, batch_perm } = tkn;
+        let mut tkn = tkn;
+        // there are no pending operations, the next entry of the batch is free
+        let idx: usize = tkn.next;
+        let tracked batch_perm;
+        let tracked fc_client;
+        proof {
+            batch_perm = tkn.batch_perms.borrow_mut().tracked_remove(idx as nat);
+            fc_client = tkn.fc_clients.borrow_mut().tracked_remove(idx as nat);
+        }
         // Step 1: Enqueue the operation onto the thread local batch
         // while !self.make_pending(op.clone(), idx.tid()) {}
         // Note: if we have the thread token, this will always succeed.
         let tracked context_ghost = FCClientRequestResponseGhost {
-            batch_perms: Some(batch_perm.get()),
-            cell_id: Ghost(self.contexts[tkn.thread_id_spec() as int].batch.0.id()),
+            batch_perms: Some(batch_perm),
+            cell_id: Ghost(self.contexts[tkn.thread_id_spec() as int].batch[idx as int].id()),
             local_updates: Some(ticket),
-            fc_clients: fc_client.get(),
+            fc_clients: fc_client,
         };
-        let mk_pending_res = self.make_pending(op, tid, Tracked(context_ghost));
+        let mk_pending_res = self.make_pending(op, tkn.tid, idx, Tracked(context_ghost));
         let context_ghost = mk_pending_res.1;
         // Step 2: Try to do flat combining to appy the update to the data structure
-        self.try_combine(slog);
+        self.try_combine(slog, peers);
         // Step 3: Obtain the result form the responses
-        let response = self.get_response(slog, tid, Ghost(req_id), context_ghost);
+        let response = self.get_response(slog, peers, tkn.tid, idx, Ghost(req_id), context_ghost);
         let context_ghost = response.1;
         let tracked FCClientRequestResponseGhost {
             batch_perms: batch_perms,
@@ -1159,34 +2057,357 @@ impl<DT: Dispatch> Replica<DT> {
         } = context_ghost.get();
         let tracked ticket = ticket.tracked_unwrap();
         let tracked batch_perm = batch_perms.tracked_unwrap();
-        (
-            response.0,
-            ThreadToken {
-                rid,
-                tid,
-                fc_client: Tracked(fc_clients),
-                batch_perm: Tracked(batch_perm),
+        proof {
+            tkn.batch_perms.borrow_mut().tracked_insert(idx as nat, batch_perm);
+            tkn.fc_clients.borrow_mut().tracked_insert(idx as nat, fc_clients);
+        }
+        tkn.advance();
+        match response.0 {
+            Ok(resp) => {
+                // Step 4: Record a version of the log that includes the update for session reads
+                tkn.session_version = slog.get_version_upper_bound_after_update(Tracked(&ticket));
+                Ok((resp, tkn, Tracked(ticket)))
             },
-            Tracked(ticket),
-        )
+            Err(err) => Err((tkn, Tracked(ticket), err)),
+        }
+    }
+
+    /// Executes a batch of mutable operations against this replica.
+    ///
+    /// The calling thread takes the combiner lock and the write lock on the data structure and
+    /// appends the batch to the log itself, bypassing the flat combiner. All operations of the
+    /// batch are therefore placed into contiguous log entries and are applied in the order of the
+    /// vector.
+    ///
+    /// Returns the tickets with an error if the batch couldn't be appended to the log, none of
+    /// the operations has been applied then.
+    pub(crate) fn execute_mut_batch(
+        &self,
+        slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
+        ops: Vec<DT::WriteOperation>,
+        tkn: ThreadToken<DT>,
+        tickets: Tracked<Map<nat, UnboundedLog::local_updates<DT>>>,
+    ) -> (result: Result<
+        (Vec<DT::Response>, ThreadToken<DT>, Tracked<Map<nat, UnboundedLog::local_updates<DT>>>),
+        (ThreadToken<DT>, Tracked<Map<nat, UnboundedLog::local_updates<DT>>>, NrError),
+    >)
+        requires
+            slog.wf(),
+            slog.wf_peers(peers@),
+            self.wf(),
+            tkn.wf(self),
+            self.replica_token == tkn.replica_token(),
+            self.unbounded_log_instance@ == slog.unbounded_log_instance@,
+            self.cyclic_buffer_instance@ == slog.cyclic_buffer_instance@,
+            ops.len() <= MAX_APPEND,
+            is_update_batch_tickets(tickets@, ops@, slog.unbounded_log_instance@),
+        ensures
+            result.is_Ok() ==> {
+                let (resps, tkn_out, stubs) = result.get_Ok_0();
+                &&& tkn_out.wf(self)
+                &&& tkn_out.replica_token() == tkn.replica_token()
+                &&& forall|i: nat|
+                    i < ops.len() ==> #[trigger] stubs@[i]@.value.get_Done_idx()
+                        < tkn_out.session_version_spec()
+                &&& resps.len() == ops.len()
+                &&& is_update_batch_stubs(stubs@, tickets@, resps@, slog.unbounded_log_instance@)
+            },
+            result.is_Err() ==> {
+                let (tkn_out, tickets_out, err) = result.get_Err_0();
+                &&& tkn_out == tkn
+                &&& tickets_out == tickets
+                &&& err == NrError::LogExhausted
+            },
+    {
+        let ghost request_ids = Seq::new(ops.len() as nat, |i: int| tickets@[i as nat]@.key);
+        // Step 0: Take the combiner lock, threads of other replicas only help this replica while
+        // holding it, and they must not wait for the write lock held by us
+        let combiner_lock = self.spin_combiner_lock();
+        // Step 1: Take the R/W lock on the data structure
+        let (replicated_data_structure, write_handle) = self.data.0.acquire_write();
+        let mut data = replicated_data_structure.data;
+        let ghost_replica = replicated_data_structure.replica;
+        let combiner = replicated_data_structure.combiner;
+        let cb_combiner = replicated_data_structure.cb_combiner;
+        // Step 2: Append the batch to the log
+        let mut responses: Vec<DT::Response> = Vec::new();
+        let tracked append_exec_ghost_data = NrLogAppendExecDataGhost {
+            local_updates: tickets,
+            ghost_replica,
+            combiner,
+            cb_combiner,
+            request_ids: Ghost(request_ids),
+        };
+        let append_exec_ghost_data = match slog.append(
+            peers,
+            &self.replica_token,
+            &ops,
+            &mut responses,
+            &mut data,
+            Tracked(append_exec_ghost_data),
+        ) {
+            Ok(append_exec_ghost_data) => append_exec_ghost_data,
+            Err(append_exec_ghost_data) => {
+                // the log has run out of indices, release the locks and return the tickets
+                let Tracked(append_exec_ghost_data) = append_exec_ghost_data;
+                let tracked NrLogAppendExecDataGhost {
+                    local_updates,
+                    ghost_replica,
+                    combiner,
+                    cb_combiner,
+                    request_ids: _,
+                } = append_exec_ghost_data;
+                let replicated_data_structure = ReplicatedDataStructure {
+                    data,
+                    replica: ghost_replica,
+                    combiner,
+                    cb_combiner,
+                };
+                self.data.0.release_write(replicated_data_structure, write_handle);
+                self.release_combiner_lock(combiner_lock);
+                return Err((tkn, local_updates, NrError::LogExhausted));
+            },
+        };
+        // Step 3: Execute the batch, this applies the updates in log order
+        let append_exec_ghost_data = slog.execute(
+            &self.replica_token,
+            &mut responses,
+            &mut data,
+            append_exec_ghost_data,
+        );
+        let Tracked(append_exec_ghost_data) = append_exec_ghost_data;
+        let tracked NrLogAppendExecDataGhost {
+            local_updates,
+            ghost_replica,
+            combiner,
+            cb_combiner,
+            request_ids: _,
+        } = append_exec_ghost_data;
+        let tracked ghost_replica = ghost_replica.get();
+        let tracked combiner = combiner.get();
+        let tracked cb_combiner = cb_combiner.get();
+        // Step 4: release the R/W lock on the data structure
+        let replicated_data_structure = ReplicatedDataStructure {
+            data,
+            replica: Tracked(ghost_replica),
+            combiner: Tracked(combiner),
+            cb_combiner: Tracked(cb_combiner),
+        };
+        self.data.0.release_write(replicated_data_structure, write_handle);
+        self.release_combiner_lock(combiner_lock);
+        // Step 5: Record a version of the log that includes the batch for session reads, the
+        // last update of the batch is appended at the highest log index
+        let mut tkn = tkn;
+        let n = ops.len();
+        if n > 0 {
+            assert(local_updates@.contains_key((n - 1) as nat));
+            tkn.session_version = slog.get_version_upper_bound_after_update(
+                Tracked(local_updates.borrow().tracked_borrow((n - 1) as nat)),
+            );
+            assert forall|i: nat| i < n implies #[trigger] local_updates@[i]@.value.get_Done_idx()
+                < tkn.session_version_spec() by {
+                assert(local_updates@[(n - 1) as nat]@.value.get_Done_idx()
+                    == local_updates@[0]@.value.get_Done_idx() + (n - 1));
+            }
+        }
+        Ok((responses, tkn, local_updates))
     }
 
-    /// Enqueues an operation inside a thread local context. Returns a boolean
+    /// Submits a mutable operation to this replica without waiting for its response.
+    ///
+    /// The operation is enqueued in the next entry of the thread's batch and the thread tries to
+    /// become the combiner once. The returned handle is used to [`Replica::poll`] for the
+    /// response. Returns the ticket again if the next entry is still pending, the entries are
+    /// used in order such that the operations of the thread are applied in submission order.
+    pub(crate) fn submit_mut(
+        &self,
+        slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
+        op: DT::WriteOperation,
+        tkn: &mut ThreadToken<DT>,
+        ticket: Tracked<UnboundedLog::local_updates<DT>>,
+    ) -> (result: Result<PendingHandle<DT>, Tracked<UnboundedLog::local_updates<DT>>>)
+        requires
+            slog.wf(),
+            slog.wf_peers(peers@),
+            self.wf(),
+            old(tkn).wf_pending(self),
+            self.replica_token == old(tkn).replica_token(),
+            self.unbounded_log_instance@ == slog.unbounded_log_instance@,
+            self.cyclic_buffer_instance@ == slog.cyclic_buffer_instance@,
+            is_update_ticket(ticket@, op, slog.unbounded_log_instance@),
+        ensures
+            tkn.wf_pending(self),
+            tkn.rid == old(tkn).rid,
+            tkn.tid == old(tkn).tid,
+            result.is_Ok() ==> {
+                &&& result.get_Ok_0().wf(self)
+                &&& result.get_Ok_0().req_id_spec() == ticket@@.key
+                &&& result.get_Ok_0().rid == tkn.rid
+                &&& result.get_Ok_0().tid == tkn.tid
+                &&& tkn.pending@ == old(tkn).pending@.update(result.get_Ok_0().idx as int, true)
+            },
+            result.is_Err() ==> result.get_Err_0() == ticket && *tkn == *old(tkn),
+    {
+        let idx = match tkn.next_entry() {
+            Some(idx) => idx,
+            None => return Err(ticket),
+        };
+        let tracked ticket = ticket.get();
+        let ghost req_id: nat = ticket@.key;
+        let tracked batch_perm;
+        let tracked fc_client;
+        proof {
+            batch_perm = tkn.batch_perms.borrow_mut().tracked_remove(idx as nat);
+            fc_client = tkn.fc_clients.borrow_mut().tracked_remove(idx as nat);
+        }
+        // Step 1: Enqueue the operation onto the thread local batch
+        let tracked context_ghost = FCClientRequestResponseGhost {
+            batch_perms: Some(batch_perm),
+            cell_id: Ghost(self.contexts[tkn.thread_id_spec() as int].batch[idx as int].id()),
+            local_updates: Some(ticket),
+            fc_clients: fc_client,
+        };
+        let mk_pending_res = self.make_pending(op, tkn.tid, idx, Tracked(context_ghost));
+        let context_ghost = mk_pending_res.1;
+        tkn.pending.set(idx, true);
+        tkn.advance();
+        // Step 2: Try to do flat combining to appy the update to the data structure
+        self.try_combine(slog, peers);
+        Ok(PendingHandle { rid: tkn.rid.clone(), tid: tkn.tid, idx, context_ghost, req_id: Ghost(req_id) })
+    }
+
+    /// Checks whether the response of a submitted operation is available. If not, the thread
+    /// tries to become the combiner once and the handle is returned again. Otherwise, the entry
+    /// of the thread's batch is no longer pending. The response is an error together with the
+    /// ticket of the operation if it couldn't be appended to the log.
+    pub(crate) fn poll(
+        &self,
+        slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
+        tkn: &mut ThreadToken<DT>,
+        handle: PendingHandle<DT>,
+    ) -> (result: Result<
+        (Result<DT::Response, NrError>, Tracked<UnboundedLog::local_updates<DT>>),
+        PendingHandle<DT>,
+    >)
+        requires
+            slog.wf(),
+            slog.wf_peers(peers@),
+            self.wf(),
+            old(tkn).wf_pending(self),
+            handle.wf(self),
+            handle.rid == old(tkn).rid,
+            handle.tid == old(tkn).tid,
+            old(tkn).pending[handle.idx as int],
+            self.unbounded_log_instance@ == slog.unbounded_log_instance@,
+            self.cyclic_buffer_instance@ == slog.cyclic_buffer_instance@,
+        ensures
+            tkn.wf_pending(self),
+            tkn.rid == old(tkn).rid,
+            tkn.tid == old(tkn).tid,
+            result.is_Ok() ==> {
+                let (resp, ticket) = result.get_Ok_0();
+                &&& tkn.pending@ == old(tkn).pending@.update(handle.idx as int, false)
+                &&& resp.is_Ok() ==> is_update_stub(ticket@, handle.req_id_spec(), resp.get_Ok_0(), slog.unbounded_log_instance@)
+                &&& resp.is_Err() ==> {
+                    &&& ticket@@.instance == slog.unbounded_log_instance@
+                    &&& ticket@@.key == handle.req_id_spec()
+                    &&& ticket@@.value.is_Init()
+                    &&& resp.get_Err_0() == NrError::LogExhausted
+                }
+            },
+            result.is_Err() ==> result.get_Err_0() == handle && *tkn == *old(tkn),
+    {
+        match self.take_response(tkn, handle) {
+            Ok(res) => Ok(res),
+            Err(handle) => {
+                self.try_combine(slog, peers);
+                self.take_response(tkn, handle)
+            },
+        }
+    }
+
+    /// Takes the response of a submitted operation from the thread's context if it is available,
+    /// the entry of the thread's batch is no longer pending then.
+    ///
+    /// Unlike [`Replica::poll`], the thread never becomes the combiner. Used for retired replicas,
+    /// whose pending operations have been executed before they were retired.
+    pub(crate) fn take_response(
+        &self,
+        tkn: &mut ThreadToken<DT>,
+        handle: PendingHandle<DT>,
+    ) -> (result: Result<
+        (Result<DT::Response, NrError>, Tracked<UnboundedLog::local_updates<DT>>),
+        PendingHandle<DT>,
+    >)
+        requires
+            self.wf(),
+            old(tkn).wf_pending(self),
+            handle.wf(self),
+            handle.rid == old(tkn).rid,
+            handle.tid == old(tkn).tid,
+            old(tkn).pending[handle.idx as int],
+        ensures
+            tkn.wf_pending(self),
+            tkn.rid == old(tkn).rid,
+            tkn.tid == old(tkn).tid,
+            result.is_Ok() ==> {
+                let (resp, ticket) = result.get_Ok_0();
+                &&& tkn.pending@ == old(tkn).pending@.update(handle.idx as int, false)
+                &&& resp.is_Ok() ==> is_update_stub(ticket@, handle.req_id_spec(), resp.get_Ok_0(), self.unbounded_log_instance@)
+                &&& resp.is_Err() ==> {
+                    &&& ticket@@.instance == self.unbounded_log_instance@
+                    &&& ticket@@.key == handle.req_id_spec()
+                    &&& ticket@@.value.is_Init()
+                    &&& resp.get_Err_0() == NrError::LogExhausted
+                }
+            },
+            result.is_Err() ==> result.get_Err_0() == handle && *tkn == *old(tkn),
+    {
+        let PendingHandle { rid, tid, idx, context_ghost, req_id } = handle;
+        let context = &self.contexts[tid as usize];
+        let (r, context_ghost) = context.dequeue_response(idx, context_ghost);
+        match r {
+            Some(resp) => {
+                let tracked FCClientRequestResponseGhost {
+                    batch_perms: batch_perms,
+                    cell_id,
+                    local_updates: ticket,
+                    fc_clients: fc_clients,
+                } = context_ghost.get();
+                let tracked ticket = ticket.tracked_unwrap();
+                let tracked batch_perm = batch_perms.tracked_unwrap();
+                proof {
+                    tkn.batch_perms.borrow_mut().tracked_insert(idx as nat, batch_perm);
+                    tkn.fc_clients.borrow_mut().tracked_insert(idx as nat, fc_clients);
+                }
+                tkn.pending.set(idx, false);
+                Ok((resp, Tracked(ticket)))
+            },
+            None => Err(PendingHandle { rid, tid, idx, context_ghost, req_id }),
+        }
+    }
+
+    /// Enqueues an operation inside an entry of a thread local context. Returns a boolean
     /// indicating whether the operation was enqueued (true) or not (false).
     #[inline(always)]
     fn make_pending(
         &self,
         op: DT::WriteOperation,
         tid: ThreadId,
+        idx: usize,
         context_ghost: Tracked<FCClientRequestResponseGhost<DT>>,
     ) -> (res: (bool, Tracked<FCClientRequestResponseGhost<DT>>))
         requires
             self.wf(),
             0 <= tid < self.contexts.len(),
+            idx < MAX_PENDING_OPS,
             context_ghost@.enqueue_op_pre(
-                tid as nat,
+                slot_id(tid as nat, idx as nat, MAX_PENDING_OPS as nat),
                 op,
-                self.contexts[tid as int].batch.0.id(),
+                self.contexts[tid as int].batch[idx as int].id(),
                 self.flat_combiner_instance@,
                 self.unbounded_log_instance@,
             ),
@@ -1194,26 +2415,31 @@ impl<DT: Dispatch> Replica<DT> {
             res.1@.enqueue_op_post(context_ghost@),
     {
         let context = &self.contexts[tid as usize];
-        context.enqueue_op(op, context_ghost)
+        context.enqueue_op(idx, op, context_ghost)
     }
 
-    /// Busy waits until a response is available within the thread's context.
+    /// Busy waits until a response is available within the thread's context. The response is an
+    /// error if the operation couldn't be appended to the log.
     fn get_response(
         &self,
         slog: &NrLog<DT>,
+        peers: &Vec<Box<Replica<DT>>>,
         tid: ThreadId,
+        idx: usize,
         req_id: Ghost<ReqId>,
         context_ghost: Tracked<FCClientRequestResponseGhost<DT>>,
-    ) -> (res: (DT::Response, Tracked<FCClientRequestResponseGhost<DT>>))
+    ) -> (res: (Result<DT::Response, NrError>, Tracked<FCClientRequestResponseGhost<DT>>))
         requires
             self.wf(),
             slog.wf(),
+            slog.wf_peers(peers@),
             slog.unbounded_log_instance@ == self.unbounded_log_instance@,
             slog.cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
             0 <= tid < self.contexts.len(),
+            idx < MAX_PENDING_OPS,
             context_ghost@.dequeue_resp_pre(
-                self.contexts[tid as int].batch.0.id(),
-                tid as nat,
+                self.contexts[tid as int].batch[idx as int].id(),
+                slot_id(tid as nat, idx as nat, MAX_PENDING_OPS as nat),
                 self.flat_combiner_instance@,
             ),
         ensures
@@ -1226,6 +2452,7 @@ impl<DT: Dispatch> Replica<DT> {
         while r.is_none()
             invariant
                 slog.wf(),
+                slog.wf_peers(peers@),
                 self.wf(),
                 slog.unbounded_log_instance@ == self.unbounded_log_instance@,
                 slog.cyclic_buffer_instance@ == self.cyclic_buffer_instance@,
@@ -1233,9 +2460,10 @@ impl<DT: Dispatch> Replica<DT> {
                 context.flat_combiner_instance@ == self.flat_combiner_instance@,
                 context.unbounded_log_instance@ == self.unbounded_log_instance@,
                 0 <= iter <= RESPONSE_CHECK_INTERVAL,
+                idx < MAX_PENDING_OPS,
                 r.is_None() ==> context_ghost_new@.dequeue_resp_pre(
-                    context.batch.0.id(),
-                    tid as nat,
+                    context.batch[idx as int].id(),
+                    slot_id(tid as nat, idx as nat, MAX_PENDING_OPS as nat),
                     self.flat_combiner_instance@,
                 ),
                 context_ghost_new@.dequeue_resp_post(
@@ -1245,10 +2473,10 @@ impl<DT: Dispatch> Replica<DT> {
                 ),
         {
             if iter == RESPONSE_CHECK_INTERVAL {
-                self.try_combine(slog);
+                self.try_combine(slog, peers);
                 iter = 0;
             }
-            let deq_resp_result = context.dequeue_response(context_ghost_new);
+            let deq_resp_result = context.dequeue_response(idx, context_ghost_new);
             r = deq_resp_result.0;
             context_ghost_new = deq_resp_result.1;
             iter = iter + 1;
@@ -1272,12 +2500,17 @@ pub tracked struct ReplicaConfig<DT: Dispatch> {
 
 impl<DT: Dispatch> ReplicaConfig<DT> {
     pub open spec fn wf(&self, nid: nat) -> bool {
+        self.wf_with_state(nid, DT::init_spec())
+    }
+
+    /// the tokens are well-formed for a replica with the given state
+    pub open spec fn wf_with_state(&self, nid: nat, state: DT::View) -> bool {
         &&& self.combiner@.instance == self.unbounded_log_instance
         &&& self.cb_combiner@.instance == self.cyclic_buffer_instance
         &&& self.cyclic_buffer_instance.unbounded_log_instance() == self.unbounded_log_instance
         &&& self.unbounded_log_instance.num_replicas() == self.cyclic_buffer_instance.num_replicas()
         &&& nid < self.unbounded_log_instance.num_replicas()
-        &&& self.replica@.value == DT::init_spec()
+        &&& self.replica@.value == state
         &&& self.replica@.key == nid
         &&& self.replica@.instance == self.unbounded_log_instance
         &&& self.combiner@.value.is_Ready()
@@ -1304,6 +2537,9 @@ pub tracked struct CombinerLockStateGhost<DT: Dispatch> {
     /// Stores the token to access the number of collected operations in the replica
     pub collected_operations_per_thread_perm: Tracked<PointsTo<Vec<usize>>>,
 
+    /// Stores the token to access the entries the combiner resumes collecting at
+    pub collect_heads_perm: Tracked<PointsTo<Vec<usize>>>,
+
     /// Stores the token to access the responses in teh Replica
     ///  - Dafny: glinear gresponses: LC.LCellContents<seq<nrifc.ReturnType>>,
     pub responses_token: Tracked<PointsTo<Vec<<DT as Dispatch>::Response>>>,
@@ -1314,7 +2550,7 @@ pub tracked struct CombinerLockStateGhost<DT: Dispatch> {
 //                                     responses: LC.LinearCell<seq<nrifc.ReturnType>>)
 //
 // Note: this predicate only holds when the lock is not taken.
-pub open spec fn inv(&self, combiner_instance: FlatCombiner::Instance, responses_id: CellId, op_buffer_id: CellId, thread_ops: CellId) -> bool {
+pub open spec fn inv(&self, combiner_instance: FlatCombiner::Instance, responses_id: CellId, op_buffer_id: CellId, thread_ops: CellId, heads: CellId) -> bool {
     predicate {
         &&& self.flat_combiner@@.value.is_Collecting()
         &&& self.flat_combiner@@.value.get_Collecting_0().len() == 0
@@ -1331,6 +2567,10 @@ pub open spec fn inv(&self, combiner_instance: FlatCombiner::Instance, responses
         &&& self.collected_operations_per_thread_perm@@.value.is_some()
         &&& self.collected_operations_per_thread_perm@@.pcell == thread_ops
         &&& self.collected_operations_per_thread_perm@@.value.get_Some_0().len() == 0
+
+        &&& self.collect_heads_perm@@.value.is_some()
+        &&& self.collect_heads_perm@@.pcell == heads
+        &&& self.collect_heads_perm@@.value.get_Some_0().len() == combiner_instance.num_threads()
     }
 }}  // struct_with_invariants!
 
@@ -1352,8 +2592,9 @@ impl<DT: Dispatch> ThreadOpsData<DT> {
         &&& self.flat_combiner@@.instance == flat_combiner_instance@
         &&& self.flat_combiner@@.value.is_Responding()
         &&& self.flat_combiner@@.value.get_Responding_0().len() as nat
-            == MAX_THREADS_PER_REPLICA as nat
-        &&& num_ops_per_thread.len() as nat == MAX_THREADS_PER_REPLICA as nat
+            == replica_contexts.len() as nat * MAX_PENDING_OPS as nat
+        &&& num_ops_per_thread.len() as nat == replica_contexts.len() as nat
+            * MAX_PENDING_OPS as nat
         &&& self.flat_combiner@@.value.get_Responding_1() == 0
         &&& (forall|i: nat|
             #![trigger num_ops_per_thread[i as int]]
@@ -1363,7 +2604,9 @@ impl<DT: Dispatch> ThreadOpsData<DT> {
                     == self.flat_combiner@@.value.get_Responding_0()[i as int].is_some()
                 &&& self.flat_combiner@@.value.get_Responding_0()[i as int].is_some() ==> {
                     &&& self.cell_permissions@.contains_key(i)
-                    &&& self.cell_permissions@[i]@.pcell === replica_contexts[i as int].batch.0.id()
+                    &&& self.cell_permissions@[i]@.pcell === replica_contexts[(i
+                        / MAX_PENDING_OPS as nat) as int].batch@[(i
+                        % MAX_PENDING_OPS as nat) as int].id()
                     &&& self.cell_permissions@[i]@.value.is_some()
                 }
             })
@@ -1373,12 +2616,13 @@ impl<DT: Dispatch> ThreadOpsData<DT> {
         &self,
         flat_combiner_instance: Tracked<FlatCombiner::Instance>,
         unbounded_log_instance: UnboundedLog::Instance<DT>,
+        appended: bool,
         num_ops_per_thread: Seq<usize>,
         responses: Seq<DT::Response>,
         replica_contexts: Seq<Context<DT>>,
     ) -> bool {
         &&& self.shared_inv(flat_combiner_instance, num_ops_per_thread, replica_contexts)
-        &&& self.request_ids@.len() == responses.len()
+        &&& appended ==> self.request_ids@.len() == responses.len()
         &&& (forall|i| 0 <= i < self.request_ids@.len() ==> self.local_updates@.contains_key(i))
         &&& (forall|i: nat|
             #![trigger self.local_updates@[i]]
@@ -1386,8 +2630,9 @@ impl<DT: Dispatch> ThreadOpsData<DT> {
                 &&& self.local_updates@.contains_key(i)
                 &&& self.local_updates@[i]@.instance == unbounded_log_instance
                 &&& self.local_updates@[i]@.key == self.request_ids@[i as int]
-                &&& self.local_updates@[i]@.value.is_Done()
-                &&& self.local_updates@[i]@.value.get_Done_ret() == responses[i as int]
+                &&& appended ==> self.local_updates@[i]@.value.is_Done()
+                &&& appended ==> self.local_updates@[i]@.value.get_Done_ret() == responses[i as int]
+                &&& !appended ==> self.local_updates@[i]@.value.is_Init()
             })
         &&& rids_match(
             self.flat_combiner@@.value.get_Responding_0(),
@@ -11,6 +11,7 @@ use vstd::{
     prelude::*,
 };
 
+use crate::exec::utils::spin_loop_hint;
 use crate::exec::CachePadded;
 use crate::spec::rwlock::RwLockSpec;
 
@@ -52,11 +53,9 @@ impl<T> RwLockReadGuard<T> {
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub const MAX_RC: u64 = 0xffff_ffff_ffff_fff0;
 
-#[verus::trusted]
-#[verifier(external_body)]  /* vattr */
-pub fn warn_with_ref_count_too_big() {
-    panic!("WARNING: Refcount value exceeds the maximum value of u64.");
-}
+/// value of a reference count that a pending writer has checked, readers wait until the writer
+/// has released the lock before they announce themselves again
+pub const RC_BLOCKED: u64 = 0xffff_ffff_ffff_ffff;
 
 struct_with_invariants!{
     #[verifier::reject_recursive_types(T)]
@@ -66,7 +65,15 @@ struct_with_invariants!{
         /// flag indicating whether the writer lock is held or being acquired
         exc_locked: CachePadded<AtomicBool<_, RwLockSpec::exc_locked<PointsTo<T>>, _>>,
         /// map of readers that want to acquire the reader lock
-        ref_counts: Vec<CachePadded<AtomicU64<_, RwLockSpec::ref_counts<PointsTo<T>>, _>>>,
+        ref_counts: Vec<
+            CachePadded<
+                AtomicU64<
+                    _,
+                    (RwLockSpec::ref_counts<PointsTo<T>>, RwLockSpec::blocked<PointsTo<T>>),
+                    _,
+                >,
+            >,
+        >,
         /// the spec instance
         inst: Tracked<RwLockSpec::Instance<PointsTo<T>>>,
         user_inv: Ghost<Set<T>>,
@@ -95,12 +102,15 @@ struct_with_invariants!{
 
         invariant on ref_counts with (inst)
             forall |i: int| where (0 <= i < self.ref_counts@.len()) specifically (self.ref_counts@[i].0)
-            is (v: u64, g: RwLockSpec::ref_counts<PointsTo<T>>)
+            is (v: u64, g: (RwLockSpec::ref_counts<PointsTo<T>>, RwLockSpec::blocked<PointsTo<T>>))
         {
-            &&& g@.instance == inst@
-            &&& g@.key == i
-            &&& g@.value == v as int
-            &&& g@.value <= MAX_RC
+            &&& g.0@.instance == inst@
+            &&& g.0@.key == i
+            &&& g.0@.value == (if v == RC_BLOCKED { 0 } else { v as int })
+            &&& g.0@.value <= MAX_RC
+            &&& g.1@.instance == inst@
+            &&& g.1@.key == i
+            &&& g.1@.value == (v == RC_BLOCKED)
         }
     }
 }
@@ -145,6 +155,7 @@ impl<T> RwLock<T> {
         let tracked inst;
         let tracked exc_locked_token;
         let tracked mut ref_counts_tokens;
+        let tracked mut blocked_tokens;
         // create the pcell object
         let (pcell_data, Tracked(mut pcell_token)) = PCell::new(t);
         // create the set of allowed data structures
@@ -166,6 +177,8 @@ impl<T> RwLock<T> {
                 Tracked(inst0),
                 Tracked(exc_locked_token0),
                 Tracked(ref_counts_tokens0),
+                Tracked(blocked_tokens0),
+                _,
                 _,
                 _,
                 _,
@@ -179,6 +192,7 @@ impl<T> RwLock<T> {
             inst = inst0;
             exc_locked_token = exc_locked_token0;
             ref_counts_tokens = ref_counts_tokens0;
+            blocked_tokens = blocked_tokens0;
         }
         let tracked_inst: Tracked<RwLockSpec::Instance<PointsTo<T>>> = Tracked(inst.clone());
         let exc_locked_atomic = AtomicBool::new(
@@ -190,7 +204,7 @@ impl<T> RwLock<T> {
             CachePadded<
                 AtomicU64<
                     (Tracked<RwLockSpec::Instance<PointsTo<T>>>, int),
-                    RwLockSpec::ref_counts<PointsTo<T>>,
+                    (RwLockSpec::ref_counts<PointsTo<T>>, RwLockSpec::blocked<PointsTo<T>>),
                     _,
                 >,
             >,
@@ -219,6 +233,16 @@ impl<T> RwLock<T> {
                 ref_counts_tokens.index(j)@.value,
                 0,
             )));
+        assert forall|j: int|
+            i <= j && j < rc_width implies #[trigger] blocked_tokens.dom().contains(j) && equal(
+            blocked_tokens.index(j)@.instance,
+            inst,
+        ) && equal(blocked_tokens.index(j)@.key, j) && equal(
+            blocked_tokens.index(j)@.value,
+            false,
+        ) by {
+            assert(blocked_tokens.dom().contains(j));
+        }
         while i < rc_width
             invariant
                 i <= rc_width,
@@ -240,13 +264,25 @@ impl<T> RwLock<T> {
                         ref_counts_tokens.index(j)@.value,
                         0,
                     )),
+                forall|j: int|
+                    #![trigger( blocked_tokens.dom().contains(j) )]
+                    #![trigger( blocked_tokens.index(j) )]
+                    i <= j && j < rc_width ==> (blocked_tokens.dom().contains(j) && equal(
+                        blocked_tokens.index(j)@.instance,
+                        inst,
+                    ) && equal(blocked_tokens.index(j)@.key, j) && equal(
+                        blocked_tokens.index(j)@.value,
+                        false,
+                    )),
         {
             assert(ref_counts_tokens.dom().contains(i as int));
+            assert(blocked_tokens.dom().contains(i as int));
             let tracked ref_count_token = ref_counts_tokens.tracked_remove(i as int);
+            let tracked blocked_token = blocked_tokens.tracked_remove(i as int);
             let rc_atomic = AtomicU64::new(
                 Ghost((tracked_inst, i as int)),
                 0,
-                Tracked(ref_count_token),
+                Tracked((ref_count_token, blocked_token)),
             );
             v.push(CachePadded(rc_atomic));
             i = i + 1;
@@ -261,6 +297,14 @@ impl<T> RwLock<T> {
                 assert(equal(ref_counts_tokens.index(j)@.key, j));
                 assert(equal(ref_counts_tokens.index(j)@.value, 0));
             }
+            assert forall|j: int|
+                i <= j && j < rc_width implies #[trigger] blocked_tokens.dom().contains(j)
+                && equal(blocked_tokens.index(j)@.instance, inst) && equal(
+                blocked_tokens.index(j)@.key,
+                j,
+            ) && equal(blocked_tokens.index(j)@.value, false) by {
+                assert(blocked_tokens.dom().contains(j));
+            }
         }
         let s = RwLock {
             user_inv: Ghost(set_inv),
@@ -301,10 +345,14 @@ impl<T> RwLock<T> {
                 }
             });
             acquired = result.is_ok();
+            if !acquired {
+                spin_loop_hint();
+            }
         }
         let tracked mut token = token.tracked_unwrap();
         // -----------------------------------------------------------------------------------------
-        // Next: wait until all readers have released their lock
+        // Next: wait until all readers have released their lock, and block their reference counts
+        // such that they can't announce themselves again until we have released the lock
         // -----------------------------------------------------------------------------------------
         let mut idx = 0;
         while idx < self.ref_counts.len()
@@ -314,7 +362,7 @@ impl<T> RwLock<T> {
                 token@.instance == self.inst,
                 token@.value == idx,
         {
-            // wait until the reader hasn't taken the reader lock yet
+            // wait until the reader has released the reader lock
             let mut taken = true;
             while taken
                 invariant
@@ -326,14 +374,22 @@ impl<T> RwLock<T> {
             {
                 let result =
                     atomic_with_ghost!(
-                    &self.ref_counts[idx].0 => load();
+                    &self.ref_counts[idx].0 => compare_exchange(0, RC_BLOCKED);
+                    update prev -> next;
                     returning res;
                     ghost g => {
-                        if res == 0 {
-                            token = self.inst.borrow().exc_check_count(&g, token);
+                        if res.is_Ok() {
+                            let tracked (rc_token, blocked_token) = g;
+                            let tracked (Tracked(blocked_token), Tracked(exc_pending)) =
+                                self.inst.borrow().exc_check_count(&rc_token, blocked_token, token);
+                            token = exc_pending;
+                            g = (rc_token, blocked_token);
                         }
                 });
-                taken = result != 0;
+                taken = result.is_err();
+                if taken {
+                    spin_loop_hint();
+                }
             }
             idx = idx + 1;
         }
@@ -363,15 +419,17 @@ impl<T> RwLock<T> {
                 returning rc;
                 ghost g => { }
             );
+            if rc == RC_BLOCKED {
+                // writer preference: a pending writer has checked our reference count, we can't
+                // announce ourselves again before it has released the lock
+                self.wait_for_writer();
+                continue ;
+            }
             if rc == MAX_RC {
-                warn_with_ref_count_too_big();
-                ////////////////////////////////////////////////////////////////////////////////////
-                // !!! THIS IS A PANIC CASE! WE DO NOT RETURN FROM HERE !!!
-                ////////////////////////////////////////////////////////////////////////////////////
-                #[allow(while_true)]
-                while true {
-                }  // TODO: is that fine?
-
+                // the reference count is saturated, wait until one of the read guards with this
+                // thread id is released
+                spin_loop_hint();
+                continue ;
             }
             // fetch add on the reader lock
             // let tracked mut ref_counts : Tracked<RwLockSpec::ref_counts<PointsTo<T>>>;
@@ -385,11 +443,13 @@ impl<T> RwLock<T> {
             {
                 if prev == rc {
                     assert(rc < MAX_RC);
+                    let tracked (rc_token, blocked_token) = g;
                     // Tracked<ref_counts<T>>,Tracked<shared_pending<T>>
-                    let tracked (_ref_counts, _shared_pending) = self.inst.borrow().shared_start(tid as int, g);
+                    let tracked (_ref_counts, _shared_pending) = self.inst.borrow()
+                        .shared_start(tid as int, rc_token, &blocked_token);
                     // ref_counts = _ref_counts;
                     shared_pending = Some(_shared_pending.get());
-                    g = _ref_counts.get();
+                    g = (_ref_counts.get(), blocked_token);
 
                     // assert(g@.value <= MAX_RC);
                 } else {
@@ -429,15 +489,25 @@ impl<T> RwLock<T> {
             });
             let perms = Ghost(perms);
             if is_exc_locked {
-                // writer lock still held, try again
+                // writer lock still held, back off and try again
                 let res =
                     atomic_with_ghost!(
                     &self.ref_counts[tid].0 => fetch_sub(1);
                     ghost g => {
                     let tracked shared_pending = shared_pending.tracked_unwrap();
-                    self.inst.borrow().rc_not_zero_guard(tid as int, &g, &shared_pending);
-                    g = self.inst.borrow().shared_abandon(tid as int, g, shared_pending);
+                    let tracked (rc_token, blocked_token) = g;
+                    self.inst.borrow()
+                        .rc_not_zero_guard(tid as int, &rc_token, &blocked_token, &shared_pending);
+                    let tracked rc_token =
+                        self.inst.borrow().shared_abandon(tid as int, rc_token, shared_pending);
+                    g = (rc_token, blocked_token);
                 });
+                // writer preference: wait for the writer before announcing the reader again.
+                // Otherwise a steady stream of readers keeps the reference counts non-zero and the
+                // writer (i.e., the combiner) starves in `exc_check_count`. Once the writer has
+                // checked the reference count of a reader, it stays blocked until the writer has
+                // released the lock, hence every reader delays a pending writer at most once.
+                self.wait_for_writer();
             } else {
                 // create the read guard lock
                 return RwLockReadGuard {
@@ -449,6 +519,26 @@ impl<T> RwLock<T> {
         }
     }
 
+    /// spins until no writer is pending or holds the lock
+    fn wait_for_writer(&self)
+        requires
+            self.wf(),
+    {
+        let mut is_exc_locked = true;
+        while is_exc_locked
+            invariant
+                self.wf(),
+        {
+            spin_loop_hint();
+            is_exc_locked =
+                atomic_with_ghost!(
+                &self.exc_locked.0 => load();
+                returning res;
+                ghost g => { }
+            );
+        }
+    }
+
     pub fn borrow<'a>(&'a self, read_handle: Tracked<&'a RwLockReadGuard<T>>) -> (res: &'a T)
         requires
             self.wf() && self.wf_read_handle(&read_handle@),
@@ -470,12 +560,37 @@ impl<T> RwLock<T> {
         let tracked RwLockWriteGuard { cell_perms, handle } = write_handle.get();
         let tracked mut cell_perms = cell_perms.get();
         self.data.put(Tracked(&mut cell_perms), val);
+        // hand back the data, the lock stays taken until the reference counts are unblocked
+        let tracked mut token: RwLockSpec::exc_releasing<PointsTo<T>>;
+        proof {
+            let tracked exc_guard = handle.get();
+            token = self.inst.borrow().exc_release(cell_perms, cell_perms, exc_guard);
+        }
+        let mut idx = 0;
+        while idx < self.ref_counts.len()
+            invariant
+                self.wf(),
+                idx <= self.ref_counts.len(),
+                token@.instance == self.inst,
+                token@.value == idx,
+        {
+            let res =
+                atomic_with_ghost!(
+                &self.ref_counts[idx].0 => store(0);
+                ghost g => {
+                    let tracked (rc_token, blocked_token) = g;
+                    let tracked (Tracked(blocked_token), Tracked(exc_releasing)) =
+                        self.inst.borrow().exc_unblock(blocked_token, token);
+                    token = exc_releasing;
+                    g = (rc_token, blocked_token);
+            });
+            idx = idx + 1;
+        }
         let res =
             atomic_with_ghost!(
             &self.exc_locked.0 => store(false);
             ghost g => {
-            let tracked exc_guard = handle.get();
-            self.inst.borrow().exc_release(cell_perms, cell_perms, &mut g, exc_guard);
+            self.inst.borrow().exc_release_finish(&mut g, token);
         });
     }
 
@@ -491,7 +606,10 @@ impl<T> RwLock<T> {
             &self.ref_counts[tid].0 => fetch_sub(1);
             ghost g => {
                 let val = (tid as int, perms@);
-                g = self.inst.borrow().shared_release(val, g, handle.get());
+                let tracked (rc_token, blocked_token) = g;
+                let tracked handle = handle.get();
+                self.inst.borrow().guard_not_blocked(val, &blocked_token, &handle);
+                g = (self.inst.borrow().shared_release(val, rc_token, handle), blocked_token);
         });
     }
 }
@@ -11,6 +11,12 @@ use crate::spec::types::ReqId;
 
 verus! {
 
+#[verus::trusted]
+#[verifier(external_body)]  /* vattr */
+pub fn spin_loop_hint() {
+    core::hint::spin_loop();
+}
+
 pub open spec fn rids_match(
     bools: Seq<Option<ReqId>>,
     rids: Seq<ReqId>,
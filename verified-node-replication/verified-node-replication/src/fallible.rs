@@ -0,0 +1,186 @@
+// Verified Node Replication Library
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+//! Data structures whose operations may fail.
+//!
+//! Defines the [`TryDispatch`] trait and the [`Fallible`] wrapper, which implements [`Dispatch`]
+//! on top of it. Unlike [`Dispatch`], neither of them is trusted: the wrapper is verified to
+//! satisfy the specification of [`Dispatch`] given the one of [`TryDispatch`].
+#[allow(unused_imports)]
+use builtin::*;
+use builtin_macros::*;
+
+use vstd::prelude::*;
+
+use crate::Dispatch;
+
+verus! {
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Try Dispatch Trait
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The try-dispatch trait defines a data structure whose operations may fail.
+///
+/// Operations return a `Result<Response, Error>` instead of encoding the failure in the
+/// response. The error is part of the specification, [`TryDispatch::try_dispatch_mut_spec`]
+/// defines when a write operation fails and how the failed operation affects the data structure.
+///
+/// A data structure implementing this trait is used with the node-replication library by
+/// wrapping it in a [`Fallible`], which implements [`Dispatch`] on top of it.
+///
+///  - Dafny: N/A
+///  - Rust:  N/A
+pub trait TryDispatch: Sized {
+    /// Type of a read-only operation. Operations of this type do not mutate the data structure.
+    type ReadOperation: Sized;
+
+    /// Type of a write operation. Operations of this type may mutate the data structure.
+    /// Write operations are sent between replicas.
+    type WriteOperation: Sized + Send;
+
+    /// Type of the response of a read or write operation that succeeded.
+    type Response: Sized;
+
+    /// Type of the error of a read or write operation that failed.
+    type Error: Sized;
+
+    /// Type of the view of the data structure for specs and proofs.
+    type View;
+
+    /// Constructs the view of the data structure.
+    spec fn view(&self) -> Self::View;
+
+    /// Initializes the data structure.
+    fn init() -> (res: Self)
+        ensures
+            res@ == Self::init_spec(),
+            res.inv(),
+    ;
+
+    /// Clones a write operation to be copied to and read from the shared log.
+    fn clone_write_op(op: &Self::WriteOperation) -> (res: Self::WriteOperation)
+        ensures
+            op == res,
+    ;
+
+    /// Clones a response value such that it can be returned to the waiting thread
+    fn clone_response(op: &Self::Response) -> (res: Self::Response)
+        ensures
+            op == res,
+    ;
+
+    /// Clones an error value such that it can be returned to the waiting thread
+    fn clone_error(err: &Self::Error) -> (res: Self::Error)
+        ensures
+            err == res,
+    ;
+
+    /// Executes a read-only operation against the data structure and returns the result.
+    fn try_dispatch(&self, op: Self::ReadOperation) -> (result: Result<Self::Response, Self::Error>)
+        requires
+            self.inv(),
+        ensures
+            Self::try_dispatch_spec(self@, op) == result,
+    ;
+
+    /// Executes a write operation against the data structure and returns the result.
+    fn try_dispatch_mut(&mut self, op: Self::WriteOperation) -> (result: Result<
+        Self::Response,
+        Self::Error,
+    >)
+        requires
+            old(self).inv(),
+        ensures
+            self.inv(),
+            Self::try_dispatch_mut_spec(old(self)@, op) == (self@, result),
+    ;
+
+    /// specification of the [`TryDispatch::init`] function.
+    spec fn init_spec() -> Self::View;
+
+    /// specification of the [`TryDispatch::try_dispatch`] function.
+    spec fn try_dispatch_spec(ds: Self::View, op: Self::ReadOperation) -> Result<
+        Self::Response,
+        Self::Error,
+    >;
+
+    /// specification of the [`TryDispatch::try_dispatch_mut`] function.
+    spec fn try_dispatch_mut_spec(ds: Self::View, op: Self::WriteOperation) -> (
+        Self::View,
+        Result<Self::Response, Self::Error>,
+    );
+
+    /// An invariant that is preserved by [`TryDispatch::try_dispatch_mut`]
+    spec fn inv(&self) -> bool;
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Fallible Data Structure
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Wraps a data structure implementing [`TryDispatch`] such that it can be replicated. The
+/// response of an operation is the `Result` returned by the data structure.
+pub struct Fallible<DT: TryDispatch>(pub DT);
+
+impl<DT: TryDispatch> Dispatch for Fallible<DT> {
+    type ReadOperation = DT::ReadOperation;
+
+    type WriteOperation = DT::WriteOperation;
+
+    type Response = Result<DT::Response, DT::Error>;
+
+    type View = DT::View;
+
+    open spec fn view(&self) -> Self::View {
+        self.0@
+    }
+
+    fn init() -> (res: Self) {
+        Fallible(DT::init())
+    }
+
+    fn clone_write_op(op: &Self::WriteOperation) -> (res: Self::WriteOperation) {
+        DT::clone_write_op(op)
+    }
+
+    fn clone_response(op: &Self::Response) -> (res: Self::Response) {
+        match op {
+            Ok(resp) => Ok(DT::clone_response(resp)),
+            Err(err) => Err(DT::clone_error(err)),
+        }
+    }
+
+    fn dispatch(&self, op: Self::ReadOperation) -> (result: Self::Response) {
+        self.0.try_dispatch(op)
+    }
+
+    fn dispatch_mut(&mut self, op: Self::WriteOperation) -> (result: Self::Response) {
+        self.0.try_dispatch_mut(op)
+    }
+
+    open spec fn init_spec() -> Self::View {
+        DT::init_spec()
+    }
+
+    open spec fn dispatch_spec(ds: Self::View, op: Self::ReadOperation) -> Self::Response {
+        DT::try_dispatch_spec(ds, op)
+    }
+
+    open spec fn dispatch_mut_spec(ds: Self::View, op: Self::WriteOperation) -> (
+        Self::View,
+        Self::Response,
+    ) {
+        DT::try_dispatch_mut_spec(ds, op)
+    }
+
+    open spec fn inv(&self) -> bool {
+        self.0.inv()
+    }
+}
+
+} // verus!
+
+impl<DT: TryDispatch + Default> Default for Fallible<DT> {
+    fn default() -> Self {
+        Fallible(DT::default())
+    }
+}
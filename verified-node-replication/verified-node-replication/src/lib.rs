@@ -18,14 +18,26 @@ mod spec;
 mod extra;
 
 mod counter;
+mod fallible;
+pub mod nrmap;
+pub mod nrqueue;
+pub mod nrstack;
 
 use crate::spec::simple_log::SimpleLog;
 use crate::spec::unbounded_log::UnboundedLog;
+#[cfg(verus_keep_ghost)]
+use crate::spec::unbounded_log::{is_update_batch_stubs, is_update_batch_tickets};
 
-pub use crate::exec::context::ThreadToken;
+pub use crate::exec::context::{PendingHandle, ThreadToken};
 pub use crate::exec::NodeReplicated;
+pub use crate::exec::config::NrConfig;
+pub use crate::fallible::{Fallible, TryDispatch};
+pub use crate::exec::multilog::{LogPartition, MultiLogNodeReplicated, MultiLogThreadToken};
+pub use crate::nrmap::{NrMap, NrMapKey};
+pub use crate::nrqueue::NrQueue;
+pub use crate::nrstack::NrStack;
 
-use crate::constants::MAX_REPLICAS;
+use crate::constants::{MAX_APPEND, MAX_REPLICAS};
 
 verus! {
 
@@ -98,6 +110,27 @@ proof fn theorem_3<DT: Dispatch + Sync>()
 {
 }
 
+/// Theorem 4: The multi-log Node Replication implementation refines one Unbounded Log per log.
+///
+/// Every log replicates one partition of the data structure, the replicas of all logs on a node
+/// share a copy of the data structure and access their partition through its permission, see
+/// [`LogPartition`]. Thus, every log is a Node Replicated data structure over its partition, and
+/// Theorem 2 and Theorem 3 apply to each of them. As linearizability is a local property, the
+/// partitioned data structure is linearizable as long as the [`LogMapper`] sends every operation
+/// on a partition to its log.
+proof fn theorem_4<DT: LogMapper + Send + Sync>()
+    ensures
+        implements_UnboundedLogRefinesSimpleLog::<
+            LogPartition<DT>,
+            crate::spec::unbounded_log_refines_simplelog::RefinementProof<LogPartition<DT>>,
+        >(),
+        implements_NodeReplicated::<LogPartition<DT>, NodeReplicated<LogPartition<DT>>>(),
+        implements_MultiLogNodeReplicated::<DT, MultiLogNodeReplicated<DT>>(),
+{
+    theorem_2::<LogPartition<DT>>();
+    theorem_3::<LogPartition<DT>>();
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // Thread Token
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -111,6 +144,9 @@ pub trait ThreadTokenT<DT: Dispatch, Replica> {
 
     /// obtains the replica identifier this thread is registered with
     spec fn replica_id_spec(&self) -> nat;
+
+    /// obtains a version of the log that includes the last update of the thread
+    spec fn session_version_spec(&self) -> nat;
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -205,6 +241,171 @@ pub trait Dispatch: Sized {
     spec fn inv(&self) -> bool;
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Clone State Trait
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A data structure whose state can be copied. This is required to bring a replica into service
+/// at runtime, its data structure is initialized with the state of an existing replica.
+///
+///  - Dafny: N/A
+///  - Rust:  N/A (Dispatch: Default + Clone)
+#[verus::trusted]
+pub trait CloneState: Dispatch {
+    /// creates a copy of the data structure with the same state
+    fn clone_state(&self) -> (res: Self)
+        requires
+            self.inv(),
+        ensures
+            res.inv(),
+            res@ == self@,
+    ;
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Concurrent Dispatch Trait
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The concurrent dispatch trait defines a data structure that supports concurrent operations on
+/// disjoint partitions of its state.
+///
+/// A replica keeps a single copy of the data structure that is shared by all logs. The state of
+/// the copy is split into partitions, one per log, and a log accesses its partition through a
+/// permission. The permission carries the view of the partition, an operation executed with a
+/// permission only observes and changes the partition of this permission. Hence, the combiners of
+/// different logs can apply their operations to the copy concurrently.
+///
+/// The concurrent dispatch trait interface is trusted by the verifier as it is the high-level
+/// interface that the data structure is verified against.
+///
+///  - Dafny: N/A
+///  - Rust:  N/A (CNR: pub trait Dispatch { fn dispatch_mut(&self, op) -> Self::Response; })
+#[verus::trusted]
+pub trait ConcurrentDispatch: Sized {
+    /// Type of a read-only operation. Operations of this type do not mutate the data structure.
+    type ReadOperation: Sized;
+
+    /// Type of a write operation. Operations of this type may mutate the data structure.
+    /// Write operations are sent between replicas.
+    type WriteOperation: Sized + Send;
+
+    /// Type of the response of either a read or write operation.
+    type Response: Sized;
+
+    /// Type of the view of a partition of the data structure for specs and proofs.
+    type View;
+
+    /// Type of the permission to access a partition of a copy of the data structure.
+    type Partition;
+
+    /// obtains the view of the partition the permission gives access to
+    spec fn partition_view(part: Self::Partition) -> Self::View;
+
+    /// obtains the index of the partition the permission gives access to
+    spec fn partition_idx(part: Self::Partition) -> nat;
+
+    /// returns true if the permission gives access to a partition of this copy
+    spec fn partition_wf(&self, part: Self::Partition) -> bool;
+
+    /// Initializes a copy of the data structure with the given number of partitions.
+    fn init(num_partitions: usize) -> (res: (Self, Tracked<Map<nat, Self::Partition>>))
+        requires
+            0 < num_partitions,
+        ensures
+            forall|i: nat|
+                i < num_partitions ==> {
+                    &&& #[trigger] res.1@.contains_key(i)
+                    &&& res.0.partition_wf(res.1@[i])
+                    &&& Self::partition_idx(res.1@[i]) == i
+                    &&& Self::partition_view(res.1@[i]) == Self::init_spec(i)
+                },
+    ;
+
+    /// Clones a write operation to be copied to and read from the shared log.
+    fn clone_write_op(op: &Self::WriteOperation) -> (res: Self::WriteOperation)
+        ensures
+            op == res,
+    ;
+
+    /// Clones a response value such that it can be returned to the waiting thread
+    fn clone_response(op: &Self::Response) -> (res: Self::Response)
+        ensures
+            op == res,
+    ;
+
+    /// Executes a read-only operation against a partition of the data structure.
+    fn dispatch(&self, op: Self::ReadOperation, part: Tracked<&Self::Partition>) -> (result:
+        Self::Response)
+        requires
+            self.partition_wf(part@),
+        ensures
+            Self::dispatch_spec(Self::partition_view(part@), op) == result,
+    ;
+
+    /// Executes a write operation against a partition of the data structure.
+    fn dispatch_mut(&self, op: Self::WriteOperation, part: Tracked<&mut Self::Partition>) -> (result:
+        Self::Response)
+        requires
+            self.partition_wf(old(part)@),
+        ensures
+            self.partition_wf(part@),
+            Self::partition_idx(part@) == Self::partition_idx(old(part)@),
+            Self::dispatch_mut_spec(Self::partition_view(old(part)@), op) == (
+                Self::partition_view(part@),
+                result,
+            ),
+    ;
+
+    /// specification of the initial state of the partition with the given index.
+    spec fn init_spec(idx: nat) -> Self::View;
+
+    /// specification of the [`ConcurrentDispatch::dispatch`] function.
+    spec fn dispatch_spec(ds: Self::View, op: Self::ReadOperation) -> Self::Response;
+
+    /// specification of the [`ConcurrentDispatch::dispatch_mut`] function.
+    spec fn dispatch_mut_spec(ds: Self::View, op: Self::WriteOperation) -> (
+        Self::View,
+        Self::Response,
+    );
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Log Mapper Trait
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The log mapper trait assigns operations of a data structure to one of several logs.
+///
+/// Each log replicates the partition of the data structure with the same index. All operations
+/// that touch the same partition must be mapped to the same log, operations on different
+/// partitions commute and can therefore be appended to different logs without synchronizing on a
+/// single log tail.
+///
+///  - Dafny: N/A
+///  - Rust:  pub trait LogMapper { fn hash(&self, nlogs: usize, logs: &mut Vec<usize>); }
+#[verus::trusted]
+pub trait LogMapper: ConcurrentDispatch {
+    /// specification of the [`LogMapper::write_op_log_idx`] function.
+    spec fn write_op_log_idx_spec(op: Self::WriteOperation, nlogs: nat) -> nat;
+
+    /// specification of the [`LogMapper::read_op_log_idx`] function.
+    spec fn read_op_log_idx_spec(op: Self::ReadOperation, nlogs: nat) -> nat;
+
+    /// obtains the index of the log the write operation is appended to.
+    fn write_op_log_idx(op: &Self::WriteOperation, nlogs: usize) -> (res: usize)
+        requires
+            0 < nlogs,
+        ensures
+            res < nlogs,
+            res as nat == Self::write_op_log_idx_spec(*op, nlogs as nat),
+    ;
+
+    /// obtains the index of the log whose replicas serve the read-only operation.
+    fn read_op_log_idx(op: &Self::ReadOperation, nlogs: usize) -> (res: usize)
+        requires
+            0 < nlogs,
+        ensures
+            res < nlogs,
+            res as nat == Self::read_op_log_idx_spec(*op, nlogs as nat),
+    ;
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // Node Replicated Trait
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -234,6 +435,121 @@ impl AffinityFn {
     }
 }
 
+/// Events reported to the [`NrObserver`] of the replicated data structure.
+#[verus::trusted]
+pub enum NrEvent {
+    /// the replica has been spinning for `WARN_THRESHOLD` iterations, waiting for the
+    /// lagging replica (if known) to make progress.
+    Starvation { replica: ReplicaId, lagging_replica: Option<ReplicaId> },
+    /// the replica couldn't append to the log as it is full, waiting for garbage collection.
+    LogFull { replica: ReplicaId },
+    /// the replica has advanced the head of the log to the new head.
+    GcAdvance { replica: ReplicaId, head: u64 },
+    /// the combiner of the replica has collected a non-empty batch of the given size.
+    CombinerBatch { replica: ReplicaId, batch_size: usize },
+}
+
+/// Observer Trait
+///
+/// Receives progress and diagnostic events of the replicated data structure, e.g., to export
+/// them as metrics. The observer is called from the hot paths, it should return quickly.
+#[verus::trusted]
+pub trait NrObserver: Send + Sync {
+    /// called with every event that occurs
+    fn on_event(&self, event: NrEvent);
+}
+
+/// Observer that prints the starvation warnings to stderr and discards all other events.
+#[verus::trusted]
+struct StderrObserver;
+
+#[verus::trusted]
+impl NrObserver for StderrObserver {
+    #[verifier(external_body)]  /* vattr */
+    fn on_event(&self, event: NrEvent) {
+        match event {
+            NrEvent::Starvation { replica, lagging_replica: Some(lagging) } => eprintln!(
+                "WARNING: replica {replica} has been looping for `WARN_THRESHOLD` iterations waiting for replica {lagging}. Are we starving?"
+            ),
+            NrEvent::Starvation { replica, lagging_replica: None } => eprintln!(
+                "WARNING: replica {replica} has been looping for `WARN_THRESHOLD` iterations. Are we starving?"
+            ),
+            NrEvent::LogFull { replica } => eprintln!(
+                "WARNING: replica {replica} has been waiting for `WARN_THRESHOLD` iterations on a full log. Are we starving?"
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Observer Handle
+///
+/// This structure is a wrapper around an [`NrObserver`] that is passed when creating the data
+/// structure, similar to the [`AffinityFn`]. Unless another observer is given, the data structure
+/// uses [`Observer::stderr`].
+///
+#[verifier(external_body)]  /* vattr */
+#[verus::trusted]
+pub struct Observer {
+    o: Option<std::sync::Arc<dyn NrObserver>>,
+}
+
+#[verus::trusted]
+impl Observer {
+    /// creates a new Observer object that forwards the events to the given observer.
+    #[verifier(external_body)]  /* vattr */
+    pub fn new(o: impl NrObserver + 'static) -> Self {
+        Self { o: Some(std::sync::Arc::new(o)) }
+    }
+
+    /// creates a new Observer object that prints the starvation warnings to stderr and discards
+    /// all other events.
+    #[verifier(external_body)]  /* vattr */
+    pub fn stderr() -> Self {
+        Self::new(StderrObserver)
+    }
+
+    /// creates a new Observer object that discards all events.
+    #[verifier(external_body)]  /* vattr */
+    pub fn none() -> Self {
+        Self { o: None }
+    }
+
+    /// creates a new Observer object that forwards the events to the same observer.
+    #[verifier(external_body)]  /* vattr */
+    pub fn share(&self) -> Self {
+        Self { o: self.o.clone() }
+    }
+
+    /// reports the event to the observer.
+    #[verifier(external_body)]  /* vattr */
+    pub fn notify(&self, event: NrEvent) {
+        if let Some(o) = &self.o {
+            o.on_event(event)
+        }
+    }
+}
+
+/// Errors of the replicated data structure itself.
+///
+/// These are failures of the node-replication library, not of the operation. The operation
+/// hasn't been executed, and the thread token and the ticket are returned to the caller.
+#[verus::trusted]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NrError {
+    /// the replica id of the thread token is out of range.
+    InvalidReplica,
+    /// the replica of the thread token has been retired, the thread needs to register with
+    /// another replica.
+    ReplicaRetired,
+    /// the log has run out of indices, no further update operations can be appended. The tail of
+    /// the log never goes backwards, hence all later update operations fail as well.
+    ///
+    /// The operation has been handed to the combiner before the error was detected, the returned
+    /// ticket and thread token are therefore equivalent to, but not the same as the ones passed.
+    LogExhausted,
+}
+
 /// Node Replicated Trait
 ///
 /// This is the top-level interface that users will interact with.
@@ -275,9 +591,18 @@ pub trait NodeReplicatedT<DT: Dispatch + Sync>: Sized {
             old(self).wf(),
         ensures
             self.wf(),
+            self.replicas().len() == old(self).replicas().len(),
+            self.unbounded_log_instance() == old(self).unbounded_log_instance(),
             result.is_Some() ==> result.get_Some_0().wf(&self.replicas()[replica_id as int]),
     ;
 
+    /// deregisters a thread, its thread token is returned to the replica it was registered with.
+    fn deregister(&self, tkn: Self::TT)
+        requires
+            self.wf(),
+            tkn.wf(&self.replicas()[tkn.replica_id_spec() as int]),
+    ;
+
     /// executes an update operation against the data structure.
     fn execute_mut(
         &self,
@@ -286,7 +611,7 @@ pub trait NodeReplicatedT<DT: Dispatch + Sync>: Sized {
         ticket: Tracked<UnboundedLog::local_updates<DT>>,
     ) -> (result: Result<
         (DT::Response, Self::TT, Tracked<UnboundedLog::local_updates<DT>>),
-        (Self::TT, Tracked<UnboundedLog::local_updates<DT>>),
+        (Self::TT, Tracked<UnboundedLog::local_updates<DT>>, NrError),
     >)
         requires
             self.wf(),  // wf global node
@@ -298,8 +623,107 @@ pub trait NodeReplicatedT<DT: Dispatch + Sync>: Sized {
                 ticket@@.key,
                 result.get_Ok_0().0,
                 self.unbounded_log_instance(),
-            ) && result.get_Ok_0().1.wf(&self.replicas().spec_index(tkn.replica_id_spec() as int)),
-            result.is_Err() ==> result.get_Err_0().1 == ticket && result.get_Err_0().0 == tkn,
+            ) && result.get_Ok_0().1.wf(&self.replicas().spec_index(tkn.replica_id_spec() as int))
+                && result.get_Ok_0().1.session_version_spec()
+                > result.get_Ok_0().2@@.value.get_Done_idx(),
+            result.is_Err() ==> {
+                let (tkn_out, ticket_out, err) = result.get_Err_0();
+                &&& err != NrError::LogExhausted ==> ticket_out == ticket && tkn_out == tkn
+                &&& err == NrError::LogExhausted ==> {
+                    &&& tkn_out.replica_id_spec() == tkn.replica_id_spec()
+                    &&& tkn_out.wf(&self.replicas().spec_index(tkn.replica_id_spec() as int))
+                    &&& ticket_out@@.instance == ticket@@.instance
+                    &&& ticket_out@@.key == ticket@@.key
+                    &&& ticket_out@@.value.is_Init()
+                }
+            },
+    ;
+
+    /// executes a read-only operation against the data structure, tolerating bounded staleness.
+    ///
+    /// The result is computed from the state of the data structure at the version of the
+    /// returned snapshot, which is at most `max_lag` log entries older than the version upper
+    /// bound recorded by the returned witness. The version upper bound is read at the time of the
+    /// call. The operation does not take a ticket, as it is not linearizable.
+    fn execute_relaxed(&self, op: DT::ReadOperation, tkn: Self::TT, max_lag: u64) -> (result:
+        Result<
+            (
+                DT::Response,
+                Self::TT,
+                Tracked<UnboundedLog::snapshots<DT>>,
+                Tracked<UnboundedLog::version_upper_bound_reached<DT>>,
+            ),
+            (Self::TT, NrError),
+        >)
+        requires
+            self.wf(),  // wf global node
+            tkn.wf(&self.replicas()[tkn.replica_id_spec() as int]),
+        ensures
+            result.is_Ok() ==> {
+                let (resp, tkn_out, snapshot, witness) = result.get_Ok_0();
+                &&& tkn_out.wf(&self.replicas()[tkn.replica_id_spec() as int])
+                &&& snapshot@@.instance == self.unbounded_log_instance()
+                &&& witness@@.instance == self.unbounded_log_instance()
+                &&& snapshot@@.key + max_lag >= witness@@.key
+                &&& resp == DT::dispatch_spec(snapshot@@.value, op)
+            },
+            result.is_Err() ==> result.get_Err_0().0 == tkn,
+    ;
+
+    /// executes a read-only operation against the data structure with session consistency.
+    ///
+    /// The result is computed from the state of the data structure at the version of the
+    /// returned snapshot, which includes all updates previously executed by the thread holding
+    /// the token, but not necessarily the updates of other threads.
+    fn execute_session(&self, op: DT::ReadOperation, tkn: Self::TT) -> (result: Result<
+        (DT::Response, Self::TT, Tracked<UnboundedLog::snapshots<DT>>),
+        (Self::TT, NrError),
+    >)
+        requires
+            self.wf(),  // wf global node
+            tkn.wf(&self.replicas()[tkn.replica_id_spec() as int]),
+        ensures
+            result.is_Ok() ==> {
+                let (resp, tkn_out, snapshot) = result.get_Ok_0();
+                &&& tkn_out.wf(&self.replicas()[tkn.replica_id_spec() as int])
+                &&& snapshot@@.instance == self.unbounded_log_instance()
+                &&& snapshot@@.key >= tkn.session_version_spec()
+                &&& resp == DT::dispatch_spec(snapshot@@.value, op)
+            },
+            result.is_Err() ==> result.get_Err_0().0 == tkn,
+    ;
+
+    /// executes a batch of update operations against the data structure.
+    ///
+    /// The operations are appended to contiguous entries of the log, they linearize one after
+    /// the other in the order of the vector without any other update in between. The batch holds
+    /// at most `MAX_APPEND` operations, the largest append to the log.
+    fn execute_mut_batch(
+        &self,
+        ops: Vec<DT::WriteOperation>,
+        tkn: Self::TT,
+        tickets: Tracked<Map<nat, UnboundedLog::local_updates<DT>>>,
+    ) -> (result: Result<
+        (Vec<DT::Response>, Self::TT, Tracked<Map<nat, UnboundedLog::local_updates<DT>>>),
+        (Self::TT, Tracked<Map<nat, UnboundedLog::local_updates<DT>>>, NrError),
+    >)
+        requires
+            self.wf(),  // wf global node
+            tkn.wf(&self.replicas().spec_index(tkn.replica_id_spec() as int)),
+            0 < ops.len() <= MAX_APPEND,
+            is_update_batch_tickets(tickets@, ops@, self.unbounded_log_instance()),
+        ensures
+            result.is_Ok() ==> is_update_batch_stubs(
+                result.get_Ok_0().2@,
+                tickets@,
+                result.get_Ok_0().0@,
+                self.unbounded_log_instance(),
+            ) && result.get_Ok_0().0.len() == ops.len() && result.get_Ok_0().1.wf(
+                &self.replicas().spec_index(tkn.replica_id_spec() as int),
+            ) && (forall|i: nat|
+                i < ops.len() ==> #[trigger] result.get_Ok_0().2@[i]@.value.get_Done_idx()
+                    < result.get_Ok_0().1.session_version_spec()),
+            result.is_Err() ==> result.get_Err_0().1 == tickets && result.get_Err_0().0 == tkn,
     ;
 
     /// executes a read-only operation against the data structure.
@@ -310,7 +734,7 @@ pub trait NodeReplicatedT<DT: Dispatch + Sync>: Sized {
         ticket: Tracked<UnboundedLog::local_reads<DT>>,
     ) -> (result: Result<
         (DT::Response, Self::TT, Tracked<UnboundedLog::local_reads<DT>>),
-        (Self::TT, Tracked<UnboundedLog::local_reads<DT>>),
+        (Self::TT, Tracked<UnboundedLog::local_reads<DT>>, NrError),
     >)
         requires
             self.wf(),  // wf global node
@@ -325,6 +749,47 @@ pub trait NodeReplicatedT<DT: Dispatch + Sync>: Sized {
             ) && result.get_Ok_0().1.wf(&self.replicas()[tkn.replica_id_spec() as int]),
             result.is_Err() ==> result.get_Err_0().1 == ticket && result.get_Err_0().0 == tkn,
     ;
+
+    /// executes a batch of read-only operations against the data structure.
+    ///
+    /// All operations are linearized at the version of the log of the returned snapshot and
+    /// observe its state of the data structure, e.g., to read several entries atomically.
+    fn execute_many(
+        &self,
+        ops: Vec<DT::ReadOperation>,
+        tkn: Self::TT,
+        tickets: Tracked<Map<nat, UnboundedLog::local_reads<DT>>>,
+    ) -> (result: Result<
+        (
+            Vec<DT::Response>,
+            Self::TT,
+            Tracked<Map<nat, UnboundedLog::local_reads<DT>>>,
+            Tracked<UnboundedLog::snapshots<DT>>,
+        ),
+        (Self::TT, Tracked<Map<nat, UnboundedLog::local_reads<DT>>>, NrError),
+    >)
+        requires
+            self.wf(),  // wf global node
+            tkn.wf(&self.replicas()[tkn.replica_id_spec() as int]),
+            is_readonly_batch_tickets(
+                tickets@,
+                ops@,
+                tkn.replica_id_spec(),
+                self.unbounded_log_instance(),
+            ),
+        ensures
+            result.is_Ok() ==> is_readonly_batch_stubs(
+                result.get_Ok_0().2@,
+                tickets@,
+                ops@,
+                result.get_Ok_0().0@,
+                result.get_Ok_0().3@,
+                self.unbounded_log_instance(),
+            ) && result.get_Ok_0().0.len() == ops.len() && result.get_Ok_0().1.wf(
+                &self.replicas()[tkn.replica_id_spec() as int],
+            ),
+            result.is_Err() ==> result.get_Err_0().1 == tickets && result.get_Err_0().0 == tkn,
+    ;
 }
 
 /// Spec function that checks whether the struct implements the trait properly.
@@ -333,6 +798,146 @@ spec fn implements_NodeReplicated<DT: Dispatch + Sync, N: NodeReplicatedT<DT>>()
     true
 }
 
+/// Multi-Log Node Replicated Trait
+///
+/// Partitions the data structure over several logs, each with its own Unbounded Log instance.
+/// A node keeps one copy of the data structure that is shared by the replicas of all logs on
+/// this node. Operations are routed to a log using the [`LogMapper`].
+///
+#[verus::trusted]
+pub trait MultiLogNodeReplicatedT<DT: LogMapper + Send + Sync>: Sized {
+    /// The type of a replica
+    type Replica;
+
+    /// the type of the thread token
+    type TT;
+
+    /// defines the well-formedness condition on the replicated data structure
+    spec fn wf(&self) -> bool;
+
+    /// obtains the number of logs
+    spec fn num_logs(&self) -> nat;
+
+    /// obtains the number of replicas per log
+    spec fn num_replicas(&self) -> nat;
+
+    /// obtains the replica with the given id of the log with the given index
+    spec fn replica(&self, log_idx: nat, replica_id: nat) -> Self::Replica;
+
+    /// obtains the instance to the unbounded log of the log with the given index
+    spec fn unbounded_log_instance(&self, log_idx: nat) -> UnboundedLog::Instance<LogPartition<DT>>;
+
+    /// returns true if the thread token is well-formed with respect to all logs
+    spec fn tkn_wf(&self, tkn: &Self::TT) -> bool;
+
+    /// obtains the replica identifier the thread token is registered with
+    spec fn tkn_replica_id(tkn: &Self::TT) -> nat;
+
+    /// creates a new instance with the given number of logs and replicas per log.
+    fn new(num_logs: usize, num_replicas: usize, chg_mem_affinity: AffinityFn) -> (res: Self)
+        requires
+            0 < num_logs,
+            0 < num_replicas && num_replicas <= MAX_REPLICAS,
+        ensures
+            res.wf(),
+            res.num_logs() == num_logs,
+            res.num_replicas() == num_replicas,
+    ;
+
+    /// registers a thread with the given replica id in every log.
+    fn register(&mut self, replica_id: ReplicaId) -> (result: Option<Self::TT>)
+        requires
+            old(self).wf(),
+        ensures
+            self.wf(),
+            self.num_logs() == old(self).num_logs(),
+            self.num_replicas() == old(self).num_replicas(),
+            forall|i| 0 <= i < self.num_logs() ==>
+                #[trigger] self.unbounded_log_instance(i) == old(self).unbounded_log_instance(i),
+            result.is_Some() ==> self.tkn_wf(&result.get_Some_0()),
+    ;
+
+    /// deregisters a thread, its thread tokens are returned to the replicas of all logs.
+    fn deregister(&self, tkn: Self::TT)
+        requires
+            self.wf(),
+            self.tkn_wf(&tkn),
+    ;
+
+    /// executes an update operation against the log selected by the [`LogMapper`].
+    fn execute_mut(
+        &self,
+        op: DT::WriteOperation,
+        tkn: Self::TT,
+        ticket: Tracked<UnboundedLog::local_updates<LogPartition<DT>>>,
+    ) -> (result: Result<
+        (DT::Response, Self::TT, Tracked<UnboundedLog::local_updates<LogPartition<DT>>>),
+        (Self::TT, Tracked<UnboundedLog::local_updates<LogPartition<DT>>>, NrError),
+    >)
+        requires
+            self.wf(),
+            self.tkn_wf(&tkn),
+            is_update_ticket(
+                ticket@,
+                op,
+                self.unbounded_log_instance(DT::write_op_log_idx_spec(op, self.num_logs())),
+            ),
+        ensures
+            result.is_Ok() ==> is_update_stub(
+                result.get_Ok_0().2@,
+                ticket@@.key,
+                result.get_Ok_0().0,
+                self.unbounded_log_instance(DT::write_op_log_idx_spec(op, self.num_logs())),
+            ) && self.tkn_wf(&result.get_Ok_0().1),
+            result.is_Err() ==> {
+                let (tkn_out, ticket_out, err) = result.get_Err_0();
+                &&& err != NrError::LogExhausted ==> ticket_out == ticket && tkn_out == tkn
+                &&& err == NrError::LogExhausted ==> {
+                    &&& Self::tkn_replica_id(&tkn_out) == Self::tkn_replica_id(&tkn)
+                    &&& self.tkn_wf(&tkn_out)
+                    &&& ticket_out@@.instance == ticket@@.instance
+                    &&& ticket_out@@.key == ticket@@.key
+                    &&& ticket_out@@.value.is_Init()
+                }
+            },
+    ;
+
+    /// executes a read-only operation against the log selected by the [`LogMapper`].
+    fn execute(
+        &self,
+        op: DT::ReadOperation,
+        tkn: Self::TT,
+        ticket: Tracked<UnboundedLog::local_reads<LogPartition<DT>>>,
+    ) -> (result: Result<
+        (DT::Response, Self::TT, Tracked<UnboundedLog::local_reads<LogPartition<DT>>>),
+        (Self::TT, Tracked<UnboundedLog::local_reads<LogPartition<DT>>>, NrError),
+    >)
+        requires
+            self.wf(),
+            self.tkn_wf(&tkn),
+            is_readonly_ticket(
+                ticket@,
+                op,
+                Self::tkn_replica_id(&tkn),
+                self.unbounded_log_instance(DT::read_op_log_idx_spec(op, self.num_logs())),
+            ),
+        ensures
+            result.is_Ok() ==> is_readonly_stub(
+                result.get_Ok_0().2@,
+                ticket@@.key,
+                result.get_Ok_0().0,
+                self.unbounded_log_instance(DT::read_op_log_idx_spec(op, self.num_logs())),
+            ) && self.tkn_wf(&result.get_Ok_0().1),
+            result.is_Err() ==> result.get_Err_0().1 == ticket && result.get_Err_0().0 == tkn,
+    ;
+}
+
+/// Spec function that checks whether the struct implements the trait properly.
+#[verus::trusted]
+spec fn implements_MultiLogNodeReplicated<DT: LogMapper + Send + Sync, N: MultiLogNodeReplicatedT<DT>>() -> bool {
+    true
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // Proof Functions for Node Replicated -> Unbounded Log Refinement Proof
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -369,6 +974,53 @@ pub open spec fn is_readonly_stub<DT: Dispatch>(
     &&& stub@.value.get_Done_ret() == result
 }
 
+#[verus::trusted]
+pub open spec fn is_readonly_batch_tickets<DT: Dispatch>(
+    tickets: Map<nat, UnboundedLog::local_reads<DT>>,
+    ops: Seq<DT::ReadOperation>,
+    node_id: NodeId,
+    log: UnboundedLog::Instance<DT>,
+) -> bool {
+    // the i-th ticket is a ticket for the i-th operation of the batch
+    forall|i: nat|
+        i < ops.len() ==> {
+            &&& #[trigger] tickets.contains_key(i)
+            &&& is_readonly_ticket(tickets[i], ops[i as int], node_id, log)
+        }
+}
+
+#[verus::trusted]
+pub open spec fn is_readonly_batch_stubs<DT: Dispatch>(
+    stubs: Map<nat, UnboundedLog::local_reads<DT>>,
+    tickets: Map<nat, UnboundedLog::local_reads<DT>>,
+    ops: Seq<DT::ReadOperation>,
+    results: Seq<DT::Response>,
+    snapshot: UnboundedLog::snapshots<DT>,
+    log: UnboundedLog::Instance<DT>,
+) -> bool {
+    // the i-th stub is the stub for the i-th ticket of the batch
+    &&& forall|i: nat|
+        i < results.len() ==> {
+            &&& #[trigger] stubs.contains_key(i)
+            &&& is_readonly_stub(stubs[i], tickets[i]@.key, results[i as int], log)
+        }
+    // the snapshot holds the state of the data structure at one version of the log
+    &&& snapshot@.instance == log
+    // the reads share their version upper bound, the version of the snapshot is not older
+    &&& forall|i: nat|
+        i < results.len() ==> {
+            &&& #[trigger] stubs[i]@.value.get_Done_version_upper_bound()
+                == stubs[0]@.value.get_Done_version_upper_bound()
+            &&& stubs[i]@.value.get_Done_version_upper_bound() <= snapshot@.key
+        }
+    // the reads observed the state of the snapshot
+    &&& forall|i: nat|
+        i < results.len() ==> #[trigger] results[i as int] == DT::dispatch_spec(
+            snapshot@.value,
+            ops[i as int],
+        )
+}
+
 #[verus::trusted]
 pub open spec fn is_update_ticket<DT: Dispatch>(
     ticket: UnboundedLog::local_updates<DT>,
@@ -572,8 +1224,8 @@ pub enum AsyncLabel<DT: Dispatch> {
 //    pub type Label<DT> = AsyncLabel<DT>;                        // $line_count$Trusted$
 //
 //    init!{                                                      // $line_count$Trusted$
-//        initialize() {                                          // $line_count$Trusted$
-//            init state = DT::init_spec();                       // $line_count$Trusted$
+//        initialize(init_state: DT::View) {                      // $line_count$Trusted$
+//            init state = init_state;                            // $line_count$Trusted$
 //            init reqs = Map::empty();                           // $line_count$Trusted$
 //            init resps = Map::empty();                          // $line_count$Trusted$
 //        }                                                       // $line_count$Trusted$
@@ -0,0 +1,235 @@
+// Verified Node Replication Library
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+//! A replicated map data structure.
+//!
+//! Implements [`Dispatch`] for a hash map such that it can be replicated with node-replication.
+//! The data structure is specified as a map from the views of the keys to the values.
+#[allow(unused_imports)]
+use builtin::*;
+use builtin_macros::*;
+
+use std::hash::Hash;
+
+use vstd::hash_map::HashMapWithView;
+use vstd::prelude::*;
+#[cfg(verus_keep_ghost)]
+use vstd::std_specs::hash::obeys_key_model;
+
+use crate::Dispatch;
+
+verus! {
+
+/// The types of the keys of the map.
+///
+/// The hash map requires that its keys obey the key model of vstd and that the view of a key
+/// determines the key. This holds for the primitive integer types, for which vstd provides the
+/// axioms of the key model.
+pub trait NrMapKey: View + Eq + Hash + Copy {
+    /// the keys obey the key model of the hash map and their views are injective
+    proof fn lemma_key_model()
+        ensures
+            obeys_key_model::<Self>(),
+            forall|k1: Self, k2: Self| k1@ == k2@ ==> k1 == k2,
+    ;
+}
+
+} // verus!
+
+macro_rules! nr_map_key_impl {
+    ($($t:ty)*) => {
+        $(
+            verus! {
+                impl NrMapKey for $t {
+                    proof fn lemma_key_model() {
+                        broadcast use vstd::std_specs::hash::group_hash_axioms;
+                    }
+                }
+            } // verus!
+        )*
+    };
+}
+
+nr_map_key_impl! { u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize }
+
+verus! {
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Operations
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The read-only operations on the map.
+pub enum NrMapReadOp<K> {
+    /// obtains the value of the key, if present
+    Get(K),
+    /// obtains the number of keys in the map
+    Len,
+}
+
+/// The update operations on the map.
+pub enum NrMapWriteOp<K, V> {
+    /// inserts the value for the key, replacing the previous value if present
+    Put(K, V),
+    /// removes the key and its value from the map
+    Remove(K),
+}
+
+/// The response of an operation on the map.
+pub enum NrMapResponse<V> {
+    /// the value of `Get`, or the previous value of `Put` and `Remove`
+    Value(Option<V>),
+    /// the number of keys of `Len`
+    Len(usize),
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Data Structure
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A map that can be replicated with node-replication.
+///
+///  - Dafny: N/A
+///  - Rust:  N/A (NrHashMap of the upstream benchmarks)
+pub struct NrMap<K: NrMapKey, V> {
+    /// the map of the keys to the values
+    map: HashMapWithView<K, V>,
+}
+
+impl<K: NrMapKey, V: Copy> NrMap<K, V> {
+    /// the map from the views of the keys to the values
+    pub closed spec fn view(&self) -> Map<K::V, V> {
+        self.map@
+    }
+
+    /// obtains the value of the key, if present
+    pub fn get(&self, key: &K) -> (res: Option<V>)
+        ensures
+            res == Self::get_spec(self@, *key),
+    {
+        match self.map.get(key) {
+            Some(v) => Some(*v),
+            None => None,
+        }
+    }
+
+    /// inserts the value for the key and returns the previous value, if present
+    pub fn put(&mut self, key: K, value: V) -> (res: Option<V>)
+        ensures
+            self@ == old(self)@.insert(key@, value),
+            res == Self::get_spec(old(self)@, key),
+    {
+        let res = self.get(&key);
+        self.map.insert(key, value);
+        res
+    }
+
+    /// removes the key and returns its value, if present
+    pub fn remove(&mut self, key: K) -> (res: Option<V>)
+        ensures
+            self@ == old(self)@.remove(key@),
+            res == Self::get_spec(old(self)@, key),
+    {
+        let res = self.get(&key);
+        self.map.remove(&key);
+        res
+    }
+
+    /// obtains the number of keys in the map
+    pub fn len(&self) -> (res: usize)
+        ensures
+            res == self@.len(),
+    {
+        self.map.len()
+    }
+
+    /// specification of the [`NrMap::get`] function.
+    pub open spec fn get_spec(ds: Map<K::V, V>, key: K) -> Option<V> {
+        if ds.contains_key(key@) {
+            Some(ds[key@])
+        } else {
+            None
+        }
+    }
+}
+
+/// implementation of Dispatch for the map
+impl<K: NrMapKey + Send, V: Copy + Send> Dispatch for NrMap<K, V> {
+    type ReadOperation = NrMapReadOp<K>;
+
+    type WriteOperation = NrMapWriteOp<K, V>;
+
+    type Response = NrMapResponse<V>;
+
+    type View = Map<K::V, V>;
+
+    open spec fn view(&self) -> Self::View {
+        NrMap::view(self)
+    }
+
+    open spec fn inv(&self) -> bool {
+        true
+    }
+
+    open spec fn init_spec() -> Self::View {
+        Map::empty()
+    }
+
+    open spec fn dispatch_spec(ds: Self::View, op: Self::ReadOperation) -> Self::Response {
+        match op {
+            NrMapReadOp::Get(key) => NrMapResponse::Value(Self::get_spec(ds, key)),
+            NrMapReadOp::Len => NrMapResponse::Len(ds.len() as usize),
+        }
+    }
+
+    open spec fn dispatch_mut_spec(ds: Self::View, op: Self::WriteOperation) -> (
+        Self::View,
+        Self::Response,
+    ) {
+        match op {
+            NrMapWriteOp::Put(key, value) => (
+                ds.insert(key@, value),
+                NrMapResponse::Value(Self::get_spec(ds, key)),
+            ),
+            NrMapWriteOp::Remove(key) => (
+                ds.remove(key@),
+                NrMapResponse::Value(Self::get_spec(ds, key)),
+            ),
+        }
+    }
+
+    fn init() -> Self {
+        proof {
+            K::lemma_key_model();
+        }
+        NrMap { map: HashMapWithView::new() }
+    }
+
+    fn clone_write_op(op: &Self::WriteOperation) -> Self::WriteOperation {
+        match op {
+            NrMapWriteOp::Put(key, value) => NrMapWriteOp::Put(*key, *value),
+            NrMapWriteOp::Remove(key) => NrMapWriteOp::Remove(*key),
+        }
+    }
+
+    fn clone_response(op: &Self::Response) -> Self::Response {
+        match op {
+            NrMapResponse::Value(Some(value)) => NrMapResponse::Value(Some(*value)),
+            NrMapResponse::Value(None) => NrMapResponse::Value(None),
+            NrMapResponse::Len(len) => NrMapResponse::Len(*len),
+        }
+    }
+
+    fn dispatch(&self, op: Self::ReadOperation) -> Self::Response {
+        match op {
+            NrMapReadOp::Get(key) => NrMapResponse::Value(self.get(&key)),
+            NrMapReadOp::Len => NrMapResponse::Len(self.len()),
+        }
+    }
+
+    fn dispatch_mut(&mut self, op: Self::WriteOperation) -> Self::Response {
+        match op {
+            NrMapWriteOp::Put(key, value) => NrMapResponse::Value(self.put(key, value)),
+            NrMapWriteOp::Remove(key) => NrMapResponse::Value(self.remove(key)),
+        }
+    }
+}
+
+} // verus!
@@ -0,0 +1,267 @@
+// Verified Node Replication Library
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+//! A replicated FIFO queue data structure.
+//!
+//! Implements [`Dispatch`] for a queue such that it can be replicated with node-replication.
+//! The data structure is specified as a sequence, elements are pushed to its end and popped
+//! from its front.
+#[allow(unused_imports)]
+use builtin::*;
+use builtin_macros::*;
+
+use vstd::prelude::*;
+
+use crate::Dispatch;
+
+verus! {
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Operations
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The read-only operations on the queue.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NrQueueReadOp {
+    /// obtains the element at the front of the queue, if any
+    Peek,
+    /// obtains the number of elements in the queue
+    Len,
+}
+
+/// The update operations on the queue.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NrQueueWriteOp<T> {
+    /// appends the element to the back of the queue
+    Push(T),
+    /// removes the element at the front of the queue, if any
+    Pop,
+}
+
+/// The response of an operation on the queue.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NrQueueResponse<T> {
+    /// the element of `Peek` and `Pop`
+    Value(Option<T>),
+    /// the number of elements of `Len`
+    Len(usize),
+    /// the `Push` succeeded
+    Ok,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Data Structure
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A FIFO queue that can be replicated with node-replication.
+///
+/// The elements are stored in a vector starting at the `head` index, popping an element
+/// advances the index. The popped elements are dropped from the vector once they make up at
+/// least half of it. This copies at most as many elements as have been popped since, popping
+/// therefore takes amortized constant time.
+///
+///  - Dafny: N/A
+///  - Rust:  N/A
+pub struct NrQueue<T> {
+    /// the elements of the queue, the front is at index `head`
+    pub  /* REVIEW: (crate) */
+     items: Vec<T>,
+    /// the index of the front of the queue
+    pub  /* REVIEW: (crate) */
+     head: usize,
+}
+
+impl<T: Copy> NrQueue<T> {
+    /// the head index is within the vector of elements
+    pub open spec fn wf(&self) -> bool {
+        self.head <= self.items@.len()
+    }
+
+    /// the elements of the queue, starting with the front
+    pub open spec fn elems(&self) -> Seq<T> {
+        self.items@.subrange(self.head as int, self.items@.len() as int)
+    }
+
+    /// appends the element to the back of the queue
+    pub fn push(&mut self, elem: T)
+        requires
+            old(self).wf(),
+        ensures
+            self.wf(),
+            self.elems() == old(self).elems().push(elem),
+    {
+        self.items.push(elem);
+        assert(self.elems() =~= old(self).elems().push(elem));
+    }
+
+    /// removes the element at the front of the queue and returns it, if any
+    pub fn pop(&mut self) -> (res: Option<T>)
+        requires
+            old(self).wf(),
+        ensures
+            self.wf(),
+            (self.elems(), res) == Self::pop_spec(old(self).elems()),
+    {
+        if self.head == self.items.len() {
+            None
+        } else {
+            let elem = self.items[self.head];
+            self.head = self.head + 1;
+            assert(self.elems() =~= old(self).elems().subrange(1, old(self).elems().len() as int));
+            if self.head >= self.items.len() - self.head {
+                self.compact();
+            }
+            Some(elem)
+        }
+    }
+
+    /// obtains the element at the front of the queue, if any
+    pub fn peek(&self) -> (res: Option<T>)
+        requires
+            self.wf(),
+        ensures
+            res == Self::peek_spec(self.elems()),
+    {
+        if self.head == self.items.len() {
+            None
+        } else {
+            Some(self.items[self.head])
+        }
+    }
+
+    /// obtains the number of elements in the queue
+    pub fn len(&self) -> (res: usize)
+        requires
+            self.wf(),
+        ensures
+            res == self.elems().len(),
+    {
+        self.items.len() - self.head
+    }
+
+    /// drops the popped elements from the vector
+    fn compact(&mut self)
+        requires
+            old(self).wf(),
+        ensures
+            self.wf(),
+            self.head == 0,
+            self.elems() == old(self).elems(),
+    {
+        let mut items = Vec::new();
+        let mut idx = self.head;
+        while idx < self.items.len()
+            invariant
+                self.wf(),
+                self.head <= idx <= self.items@.len(),
+                items@ == self.items@.subrange(self.head as int, idx as int),
+            decreases self.items@.len() - idx,
+        {
+            items.push(self.items[idx]);
+            idx = idx + 1;
+        }
+        self.items = items;
+        self.head = 0;
+        assert(self.elems() =~= old(self).elems());
+    }
+
+    /// specification of the [`NrQueue::pop`] function.
+    pub open spec fn pop_spec(ds: Seq<T>) -> (Seq<T>, Option<T>) {
+        if ds.len() == 0 {
+            (ds, None)
+        } else {
+            (ds.subrange(1, ds.len() as int), Some(ds[0]))
+        }
+    }
+
+    /// specification of the [`NrQueue::peek`] function.
+    pub open spec fn peek_spec(ds: Seq<T>) -> Option<T> {
+        if ds.len() == 0 {
+            None
+        } else {
+            Some(ds[0])
+        }
+    }
+}
+
+/// implementation of Dispatch for the queue
+impl<T: Copy + Send> Dispatch for NrQueue<T> {
+    type ReadOperation = NrQueueReadOp;
+
+    type WriteOperation = NrQueueWriteOp<T>;
+
+    type Response = NrQueueResponse<T>;
+
+    type View = Seq<T>;
+
+    open spec fn view(&self) -> Self::View {
+        self.elems()
+    }
+
+    open spec fn inv(&self) -> bool {
+        self.wf()
+    }
+
+    open spec fn init_spec() -> Self::View {
+        Seq::empty()
+    }
+
+    open spec fn dispatch_spec(ds: Self::View, op: Self::ReadOperation) -> Self::Response {
+        match op {
+            NrQueueReadOp::Peek => NrQueueResponse::Value(Self::peek_spec(ds)),
+            NrQueueReadOp::Len => NrQueueResponse::Len(ds.len() as usize),
+        }
+    }
+
+    open spec fn dispatch_mut_spec(ds: Self::View, op: Self::WriteOperation) -> (
+        Self::View,
+        Self::Response,
+    ) {
+        match op {
+            NrQueueWriteOp::Push(elem) => (ds.push(elem), NrQueueResponse::Ok),
+            NrQueueWriteOp::Pop => (Self::pop_spec(ds).0, NrQueueResponse::Value(Self::pop_spec(ds).1)),
+        }
+    }
+
+    fn init() -> Self {
+        NrQueue { items: Vec::new(), head: 0 }
+    }
+
+    fn clone_write_op(op: &Self::WriteOperation) -> Self::WriteOperation {
+        match op {
+            NrQueueWriteOp::Push(elem) => NrQueueWriteOp::Push(*elem),
+            NrQueueWriteOp::Pop => NrQueueWriteOp::Pop,
+        }
+    }
+
+    fn clone_response(op: &Self::Response) -> Self::Response {
+        match op {
+            NrQueueResponse::Value(elem) => NrQueueResponse::Value(*elem),
+            NrQueueResponse::Len(len) => NrQueueResponse::Len(*len),
+            NrQueueResponse::Ok => NrQueueResponse::Ok,
+        }
+    }
+
+    fn dispatch(&self, op: Self::ReadOperation) -> Self::Response {
+        match op {
+            NrQueueReadOp::Peek => NrQueueResponse::Value(self.peek()),
+            NrQueueReadOp::Len => NrQueueResponse::Len(self.len()),
+        }
+    }
+
+    fn dispatch_mut(&mut self, op: Self::WriteOperation) -> Self::Response {
+        match op {
+            NrQueueWriteOp::Push(elem) => {
+                self.push(elem);
+                NrQueueResponse::Ok
+            },
+            NrQueueWriteOp::Pop => NrQueueResponse::Value(self.pop()),
+        }
+    }
+}
+
+} // verus!
+
+impl<T> Default for NrQueue<T> {
+    fn default() -> Self {
+        NrQueue { items: Vec::new(), head: 0 }
+    }
+}
@@ -0,0 +1,205 @@
+// Verified Node Replication Library
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+//
+//! A replicated LIFO stack data structure.
+//!
+//! Implements [`Dispatch`] for a stack such that it can be replicated with node-replication.
+//! The data structure is specified as a sequence, elements are pushed to and popped from its end.
+#[allow(unused_imports)]
+use builtin::*;
+use builtin_macros::*;
+
+use vstd::prelude::*;
+
+use crate::Dispatch;
+
+verus! {
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Operations
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// The read-only operations on the stack.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NrStackReadOp {
+    /// obtains the element at the top of the stack, if any
+    Peek,
+    /// obtains the number of elements on the stack
+    Len,
+}
+
+/// The update operations on the stack.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NrStackWriteOp<T> {
+    /// pushes the element on top of the stack
+    Push(T),
+    /// removes the element at the top of the stack, if any
+    Pop,
+}
+
+/// The response of an operation on the stack.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NrStackResponse<T> {
+    /// the element of `Peek` and `Pop`
+    Value(Option<T>),
+    /// the number of elements of `Len`
+    Len(usize),
+    /// the `Push` succeeded
+    Ok,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Data Structure
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A LIFO stack that can be replicated with node-replication.
+///
+///  - Dafny: N/A
+///  - Rust:  N/A (Stack of the upstream benchmarks)
+pub struct NrStack<T> {
+    /// the elements of the stack, the top of the stack is the last element
+    pub  /* REVIEW: (crate) */
+     items: Vec<T>,
+}
+
+impl<T: Copy> NrStack<T> {
+    /// pushes the element on top of the stack
+    pub fn push(&mut self, elem: T)
+        ensures
+            self.items@ == old(self).items@.push(elem),
+    {
+        self.items.push(elem);
+    }
+
+    /// removes the element at the top of the stack and returns it, if any
+    pub fn pop(&mut self) -> (res: Option<T>)
+        ensures
+            (self.items@, res) == Self::pop_spec(old(self).items@),
+    {
+        if self.items.len() == 0 {
+            None
+        } else {
+            self.items.pop()
+        }
+    }
+
+    /// obtains the element at the top of the stack, if any
+    pub fn peek(&self) -> (res: Option<T>)
+        ensures
+            res == Self::peek_spec(self.items@),
+    {
+        if self.items.len() == 0 {
+            None
+        } else {
+            Some(self.items[self.items.len() - 1])
+        }
+    }
+
+    /// obtains the number of elements on the stack
+    pub fn len(&self) -> (res: usize)
+        ensures
+            res == self.items@.len(),
+    {
+        self.items.len()
+    }
+
+    /// specification of the [`NrStack::pop`] function.
+    pub open spec fn pop_spec(ds: Seq<T>) -> (Seq<T>, Option<T>) {
+        if ds.len() == 0 {
+            (ds, None)
+        } else {
+            (ds.drop_last(), Some(ds.last()))
+        }
+    }
+
+    /// specification of the [`NrStack::peek`] function.
+    pub open spec fn peek_spec(ds: Seq<T>) -> Option<T> {
+        if ds.len() == 0 {
+            None
+        } else {
+            Some(ds.last())
+        }
+    }
+}
+
+/// implementation of Dispatch for the stack
+impl<T: Copy + Send> Dispatch for NrStack<T> {
+    type ReadOperation = NrStackReadOp;
+
+    type WriteOperation = NrStackWriteOp<T>;
+
+    type Response = NrStackResponse<T>;
+
+    type View = Seq<T>;
+
+    open spec fn view(&self) -> Self::View {
+        self.items@
+    }
+
+    open spec fn inv(&self) -> bool {
+        true
+    }
+
+    open spec fn init_spec() -> Self::View {
+        Seq::empty()
+    }
+
+    open spec fn dispatch_spec(ds: Self::View, op: Self::ReadOperation) -> Self::Response {
+        match op {
+            NrStackReadOp::Peek => NrStackResponse::Value(Self::peek_spec(ds)),
+            NrStackReadOp::Len => NrStackResponse::Len(ds.len() as usize),
+        }
+    }
+
+    open spec fn dispatch_mut_spec(ds: Self::View, op: Self::WriteOperation) -> (
+        Self::View,
+        Self::Response,
+    ) {
+        match op {
+            NrStackWriteOp::Push(elem) => (ds.push(elem), NrStackResponse::Ok),
+            NrStackWriteOp::Pop => (Self::pop_spec(ds).0, NrStackResponse::Value(Self::pop_spec(ds).1)),
+        }
+    }
+
+    fn init() -> Self {
+        NrStack { items: Vec::new() }
+    }
+
+    fn clone_write_op(op: &Self::WriteOperation) -> Self::WriteOperation {
+        match op {
+            NrStackWriteOp::Push(elem) => NrStackWriteOp::Push(*elem),
+            NrStackWriteOp::Pop => NrStackWriteOp::Pop,
+        }
+    }
+
+    fn clone_response(op: &Self::Response) -> Self::Response {
+        match op {
+            NrStackResponse::Value(elem) => NrStackResponse::Value(*elem),
+            NrStackResponse::Len(len) => NrStackResponse::Len(*len),
+            NrStackResponse::Ok => NrStackResponse::Ok,
+        }
+    }
+
+    fn dispatch(&self, op: Self::ReadOperation) -> Self::Response {
+        match op {
+            NrStackReadOp::Peek => NrStackResponse::Value(self.peek()),
+            NrStackReadOp::Len => NrStackResponse::Len(self.len()),
+        }
+    }
+
+    fn dispatch_mut(&mut self, op: Self::WriteOperation) -> Self::Response {
+        match op {
+            NrStackWriteOp::Push(elem) => {
+                self.push(elem);
+                NrStackResponse::Ok
+            },
+            NrStackWriteOp::Pop => NrStackResponse::Value(self.pop()),
+        }
+    }
+}
+
+} // verus!
+
+impl<T> Default for NrStack<T> {
+    fn default() -> Self {
+        NrStack { items: Vec::new() }
+    }
+}
@@ -36,11 +36,11 @@ verus! {
 /// Logical Log Index
 ///
 /// In contrast to the unbounded log, the log entries can be "negative". This is used for the
-/// initialization where all entries are initialized with the range [-LOG_SIZE, 0)
+/// initialization where all entries are initialized with the range [-buffer_size, 0)
 pub type LogicalLogIdx = int;
 
-/// The size of the log. XXX: can we get rid of this?
-use crate::constants::LOG_SIZE;
+/// The bounds on the size of the log.
+use crate::constants::valid_log_size;
 
 /// An entry in the log
 ///
@@ -97,6 +97,8 @@ pub tracked enum ReaderState<DT: Dispatch> {
 #[is_variant]
 pub tracked enum CombinerState<DT: Dispatch> {
     Idle,
+    /// a thread of the `helper` replica is about to read the log on behalf of this replica
+    Helped { helper: NodeId },
     Reading(ReaderState<DT>),
     AdvancingHead { idx: LogIdx, min_local_version: LogIdx },
     AdvancingTail { observed_head: LogIdx },
@@ -165,13 +167,20 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
         #[sharding(map)]
         pub local_versions: Map<NodeId, LogIdx>,    // previously called local_tails
 
+        // Marks the replicas that have been retired. The local versions of retired replicas
+        // are not taken into account when advancing the head, hence they don't hold back
+        // the garbage collection of the log.
+
+        #[sharding(map)]
+        pub retired: Map<NodeId, bool>,
+
         /// the contents of the buffer/log.
         #[sharding(storage_map)]
         pub contents: Map<LogicalLogIdx, StoredType<DT>>,
 
         // The 'alive' bit flips back and forth. So sometimes 'true' means 'alive',
         // and sometimes 'false' means 'alive'.
-        // entry is an index into the buffer (0 <= entry < LOG_SIZE)
+        // entry is an index into the buffer (0 <= entry < buffer_size)
 
         #[sharding(map)]
         pub alive_bits: Map</* entry: */ LogIdx, /* bit: */ bool>,
@@ -188,7 +197,7 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
 
     #[invariant]
     pub spec fn log_size(&self) -> bool {
-        self.buffer_size == LOG_SIZE
+        valid_log_size(self.buffer_size)
     }
 
     #[invariant]
@@ -200,22 +209,33 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
     pub spec fn complete(&self) -> bool {
         &&& (forall |i| 0 <= i < self.num_replicas <==> self.local_versions.contains_key(i))
         &&& (forall |i| 0 <= i < self.buffer_size  <==> self.alive_bits.contains_key(i))
-        &&& (forall |i| 0 <= i < self.num_replicas <==> self.combiner.contains_key(i))
+        &&& (forall |i| 0 <= i < self.num_replicas <==> self.retired.contains_key(i))
+        &&& (forall |i| self.combiner.contains_key(i) <==> (0 <= i < self.num_replicas && !self.retired[i]))
         &&& (forall |i| self.contents.contains_key(i) ==> -self.buffer_size <= i < self.tail)
     }
 
+    /// the first replica is never retired, and it is ahead of all retired replicas. New
+    /// replicas are created from its state.
+    #[invariant]
+    pub spec fn first_replica_active(&self) -> bool {
+        &&& self.num_replicas > 0
+        &&& !self.retired[0]
+        &&& (forall |i| #[trigger] self.retired.contains_key(i) && self.retired[i] ==>
+                self.local_versions[i] <= self.local_versions[0])
+    }
+
     #[invariant]
     pub spec fn pointer_ordering(&self) -> bool {
         &&& self.head <= self.tail
-        &&& (forall |i| #[trigger] self.local_versions.contains_key(i) ==>
+        &&& (forall |i| #[trigger] self.local_versions.contains_key(i) && !self.retired[i] ==>
             self.head <= self.local_versions[i] <= self.tail)
-        &&& (forall |i| #[trigger] self.local_versions.contains_key(i) ==>
+        &&& (forall |i| #[trigger] self.local_versions.contains_key(i) && !self.retired[i] ==>
             self.tail <= self.local_versions[i] +  self.buffer_size)
     }
 
     #[invariant]
     pub spec fn pointer_differences(&self) -> bool {
-        forall |i| self.local_versions.contains_key(i) ==>
+        forall |i| self.local_versions.contains_key(i) && !self.retired[i] ==>
             self.local_versions[i] <= self.tail <= self.local_versions[i] + self.buffer_size
     }
 
@@ -228,7 +248,7 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
 
     #[invariant]
     pub spec fn upcoming_bits_are_not_alive(&self) -> bool {
-        let min_local_head = map_min_value(self.local_versions, (self.num_replicas - 1) as nat);
+        let min_local_head = self.min_active_version();
         forall |i|
             self.tail <= i < min_local_head + self.buffer_size
             ==> !log_entry_is_alive(self.alive_bits, i, self.buffer_size)
@@ -238,13 +258,23 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
     pub spec fn inv_buffer_contents(&self) -> bool {
         &&& (forall |i: int| self.tail - self.buffer_size <= i < self.tail ==> (
             (log_entry_is_alive(self.alive_bits, i, self.buffer_size) ||
-                i < map_min_value(self.local_versions, (self.num_replicas - 1) as nat))
+                i < self.min_active_version())
             <==>
             #[trigger] self.contents.contains_key(i)
         ))
         &&& (forall |i: int| self.tail <= i ==> ! #[trigger] self.contents.contains_key(i))
     }
 
+    /// the local versions of the replicas, where retired replicas are at the tail of the log
+    pub open spec fn active_versions(&self) -> Map<NodeId, LogIdx> {
+        active_versions(self.local_versions, self.retired, self.tail, self.num_replicas)
+    }
+
+    /// the smallest local version of all active replicas
+    pub open spec fn min_active_version(&self) -> nat {
+        map_min_value(self.active_versions(), (self.num_replicas - 1) as nat)
+    }
+
     #[invariant]
     pub spec fn contents_meet_inv(&self) -> bool {
         forall |i: int| #[trigger] self.contents.contains_key(i) ==>
@@ -307,16 +337,17 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
     pub closed spec fn combiner_valid(&self, node_id: NodeId, cs: CombinerState<DT>) -> bool {
         match cs {
             CombinerState::Idle => true,
+            CombinerState::Helped{helper} => helper != node_id,
             CombinerState::Reading(_) => true, // see reader_state_valid instead
             CombinerState::AdvancingHead{idx, min_local_version} => {
                 // the index is always within the defined replicas
-                &&& idx <= self.num_replicas as nat
+                &&& 1 <= idx <= self.num_replicas as nat
                 // forall replicas we'e seen, min_local_version is smaller than all localTails
-                &&& (forall |n| 0 <= n < idx ==> min_local_version <= self.local_versions[n])
+                &&& (forall |n| 0 <= n < idx ==> min_local_version <= #[trigger] self.active_versions()[n])
             }
             CombinerState::AdvancingTail{observed_head} => {
                 // the observed head is smaller than all local tails
-                &&& (forall |n| 0 <= n < self.num_replicas as nat ==> observed_head <= self.local_versions[n])
+                &&& (forall |n| 0 <= n < self.num_replicas as nat ==> observed_head <= #[trigger] self.active_versions()[n])
             }
             CombinerState::Appending{cur_idx, tail} => {
                 // the current index is between local tails and tail.
@@ -340,7 +371,7 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
     init!{
         initialize(buffer_size: nat, num_replicas: nat, contents: Map<int, StoredType<DT>>, cell_ids: Seq<CellId>, unbounded_log_instance: UnboundedLog::Instance<DT>, ) {
             require(num_replicas > 0);
-            require(buffer_size == LOG_SIZE);
+            require(valid_log_size(buffer_size));
             require(cell_ids.len() == buffer_size);
 
             init unbounded_log_instance = unbounded_log_instance;
@@ -350,6 +381,7 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
             init head = 0;
             init tail = 0;
             init local_versions = Map::new(|i: NodeId| 0 <= i < num_replicas, |i: NodeId| 0);
+            init retired = Map::new(|i: NodeId| 0 <= i < num_replicas, |i: NodeId| false);
 
             require(forall |i: int| (-buffer_size <= i < 0 <==> contents.contains_key(i)));
             require(forall |i: int| #[trigger] contents.contains_key(i) ==> stored_type_inv(contents[i], i, cell_ids[log_entry_idx(i, buffer_size) as int], unbounded_log_instance));
@@ -380,6 +412,29 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
         }
     }
 
+    /// a thread of the `helper` replica takes over the combiner of the provided node, the combiner
+    /// must be in idle state.
+    transition!{
+        help_start(node_id: NodeId, helper: NodeId) {
+            require(node_id != helper);
+
+            remove combiner -= [ node_id => CombinerState::Idle ];
+            add    combiner += [ node_id => CombinerState::Helped { helper } ];
+        }
+    }
+
+    /// start the reader on behalf of the provided node
+    transition!{
+        help_reader_start(node_id: NodeId) {
+            have   local_versions    >= [ node_id => let local_head ];
+
+            remove combiner -= [ node_id => let CombinerState::Helped { helper } ];
+            add    combiner += [
+                node_id => CombinerState::Reading(ReaderState::Starting { start: local_head })
+            ];
+        }
+    }
+
     /// enter the reading phase
     transition!{
         reader_enter(node_id: NodeId) {
@@ -480,15 +535,16 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
         }
     }
 
-    /// read the next local head
+    /// read the next local head, retired replicas are skipped
     transition!{
         advance_head_next(node_id: NodeId) {
             remove combiner -= [ node_id => let CombinerState::AdvancingHead { idx, min_local_version } ];
 
             have   local_versions    >= [ idx => let local_head_at_idx ];
+            have   retired           >= [ idx => let is_retired ];
             require(idx < pre.num_replicas);
 
-            let new_min = min(min_local_version, local_head_at_idx);
+            let new_min = if is_retired { min_local_version } else { min(min_local_version, local_head_at_idx) };
             add combiner += [ node_id => CombinerState::AdvancingHead { idx: idx + 1, min_local_version: new_min } ];
         }
     }
@@ -547,8 +603,8 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
 
             withdraw contents -= (withdrawn) by {
                 assert forall |i: int| pre.tail - pre.buffer_size <= i < new_tail - pre.buffer_size implies pre.contents.contains_key(i) by {
-                    let min_local_head = map_min_value(pre.local_versions, (pre.num_replicas - 1) as nat);
-                    map_min_value_smallest(pre.local_versions,  (pre.num_replicas - 1) as nat);
+                    let min_local_head = pre.min_active_version();
+                    map_min_value_smallest(pre.active_versions(),  (pre.num_replicas - 1) as nat);
                 }
             };
 
@@ -560,8 +616,8 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
                   |i: int| pre.tail - pre.buffer_size <= i < new_tail - pre.buffer_size
                     implies stored_type_inv(#[trigger] withdrawn[i], i, pre.cell_ids[log_entry_idx(i, pre.buffer_size) as int], pre.unbounded_log_instance) by {
                         assert(pre.contents.contains_key(i) && #[trigger] withdrawn.contains_key(i)) by {
-                            let min_local_head = map_min_value(pre.local_versions, (pre.num_replicas - 1) as nat);
-                            map_min_value_smallest(pre.local_versions,  (pre.num_replicas - 1) as nat);
+                            let min_local_head = pre.min_active_version();
+                            map_min_value_smallest(pre.active_versions(),  (pre.num_replicas - 1) as nat);
                         };
                     };
                 };
@@ -594,7 +650,7 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
             require(stored_type_inv(deposited, cur_idx as int, pre.cell_ids[log_entry_idx(cur_idx as int, pre.buffer_size) as int],  pre.unbounded_log_instance));
 
             deposit contents += [ cur_idx as int => deposited ] by {
-                map_min_value_smallest(pre.local_versions,  (pre.num_replicas - 1) as nat);
+                map_min_value_smallest(pre.active_versions(),  (pre.num_replicas - 1) as nat);
             };
         }
     }
@@ -609,6 +665,115 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
         }
     }
 
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    // Replica Membership Transitions
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    //
+    // Replicas can be retired and activated again at runtime. Retired replicas don't hold a
+    // combiner token and their local version is ignored when advancing the head.
+
+    /// a replica that holds a combiner token has not been retired
+    property!{
+        combiner_not_retired(node_id: NodeId) {
+            have combiner >= [ node_id => let _ ];
+            have retired  >= [ node_id => let is_retired ];
+            assert(!is_retired);
+        }
+    }
+
+    /// the first replica is never retired
+    property!{
+        first_replica_not_retired() {
+            have retired >= [ 0 => let is_retired ];
+            assert(!is_retired);
+        }
+    }
+
+    /// the local version of a retired replica is behind the one of the first replica
+    property!{
+        retired_version_bounded(node_id: NodeId) {
+            have local_versions >= [ node_id => let local_version ];
+            have retired        >= [ node_id => true ];
+            have combiner       >= [ 0 => let CombinerState::Reading(ReaderState::Starting { start }) ];
+            assert(local_version <= start);
+        }
+    }
+
+    /// retires a replica, its combiner must be idle.
+    ///
+    /// The first replica must have started reading, which pins its local version. The
+    /// retired replica must not be ahead of it, such that it can be activated again with
+    /// the state of the first replica.
+    transition!{
+        retire_replica(node_id: NodeId) {
+            require(node_id != 0);
+
+            have   combiner >= [ 0 => let CombinerState::Reading(ReaderState::Starting { start }) ];
+            have   local_versions >= [ node_id => let local_version ];
+            require(local_version <= start);
+
+            remove retired  -= [ node_id => false ];
+            add    retired  += [ node_id => true ];
+
+            remove combiner -= [ node_id => CombinerState::Idle ];
+        }
+    }
+
+    /// activates a retired replica with the local version of the first replica.
+    ///
+    /// The first replica must have started reading, which pins its local version. As the first
+    /// replica comes before all others, a concurrent advance of the head has either already
+    /// seen it, or will see the activated replica.
+    transition!{
+        activate_replica(node_id: NodeId) {
+            require(node_id != 0);
+
+            have   combiner       >= [ 0 => let CombinerState::Reading(ReaderState::Starting { start }) ];
+
+            remove retired        -= [ node_id => true ];
+            add    retired        += [ node_id => false ];
+
+            remove local_versions -= [ node_id => let _ ];
+            add    local_versions += [ node_id => start ];
+
+            add    combiner       += [ node_id => CombinerState::Idle ];
+        }
+    }
+
+    /// the local version of a retired replica is behind the range read by the first replica
+    property!{
+        retired_version_bounded_range(node_id: NodeId) {
+            have local_versions >= [ node_id => let local_version ];
+            have retired        >= [ node_id => true ];
+            have combiner       >= [ 0 => let CombinerState::Reading(ReaderState::Range { start, end, cur }) ];
+            assert(local_version <= start) by {
+                assert(pre.reader_state_valid(0, pre.combiner[0].get_Reading_0()));
+            };
+        }
+    }
+
+    /// activates a retired replica with a version within the range read by the first replica.
+    ///
+    /// The version is at least the local version of the first replica, hence the activated
+    /// replica doesn't hold back the head more than the first replica does. It is at most the
+    /// tail the first replica has read, hence all entries up to it have been appended.
+    transition!{
+        activate_replica_at(node_id: NodeId, version: LogIdx) {
+            require(node_id != 0);
+
+            have   combiner       >= [ 0 => let CombinerState::Reading(ReaderState::Range { start, end, cur }) ];
+            require(start <= version <= end);
+
+            remove retired        -= [ node_id => true ];
+            add    retired        += [ node_id => false ];
+
+            remove local_versions -= [ node_id => let _ ];
+            add    local_versions += [ node_id => version ];
+
+            add    combiner       += [ node_id => CombinerState::Idle ];
+        }
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // Proofs
     ////////////////////////////////////////////////////////////////////////////////////////////////
@@ -632,12 +797,19 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
     #[inductive(advance_head_finish)]
     fn advance_head_finish_inductive(pre: Self, post: Self, node_id: NodeId) {
         assert(post.local_versions.contains_key(node_id));
+        assert(post.local_versions.contains_key(0));
+        assert(post.active_versions()[0] == post.local_versions[0]);
+        assert forall |i| #[trigger] post.local_versions.contains_key(i) && !post.retired[i]
+            implies post.head <= post.local_versions[i] by {
+            assert(post.active_versions()[i] == post.local_versions[i]);
+        }
     }
 
     #[inductive(advance_tail_start)]
     fn advance_tail_start_inductive(pre: Self, post: Self, node_id: NodeId) {
-        assert forall |n| 0 <= n < post.num_replicas as nat implies post.head <= post.local_versions[n] by {
+        assert forall |n| 0 <= n < post.num_replicas as nat implies post.head <= #[trigger] post.active_versions()[n] by {
             assert(post.local_versions.contains_key(n));
+            assert(post.retired.contains_key(n));
         }
      }
 
@@ -652,9 +824,21 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
         let mytail = post.combiner[node_id].get_Appending_tail();
         assert(mycur_idx == pre.tail);
 
-        let min_local_versions = map_min_value(post.local_versions, (post.num_replicas - 1) as nat);
-        map_min_value_smallest(post.local_versions,  (post.num_replicas - 1) as nat);
+        // advancing the tail moves the retired replicas, but not the minimum
+        Self::min_active_version_tail_advance(pre, post);
+
+        let min_local_versions = post.min_active_version();
+        map_min_value_smallest(post.active_versions(),  (post.num_replicas - 1) as nat);
         assert(mycur_idx >= min_local_versions);
+
+        assert forall |nid| #[trigger] post.combiner.contains_key(nid) implies
+            post.combiner_valid(nid, post.combiner[nid]) by
+        {
+            assert forall |n| 0 <= n < post.num_replicas as nat implies
+                pre.active_versions()[n] <= #[trigger] post.active_versions()[n] by {
+                assert(pre.retired.contains_key(n));
+            }
+        };
     }
 
     #[inductive(append_flip_bit)]
@@ -664,8 +848,8 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
         let myidx = pre.combiner[node_id].get_Appending_cur_idx();
         let mytail = pre.combiner[node_id].get_Appending_tail();
 
-        let min_local_head = map_min_value(post.local_versions, (post.num_replicas - 1) as nat);
-        map_min_value_smallest(post.local_versions, (post.num_replicas - 1) as nat);
+        let min_local_head = post.min_active_version();
+        map_min_value_smallest(post.active_versions(), (post.num_replicas - 1) as nat);
 
         log_entry_idx_wrap_around(min_local_head, post.buffer_size, myidx);
 
@@ -694,6 +878,12 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
     #[inductive(reader_start)]
     fn reader_start_inductive(pre: Self, post: Self, node_id: NodeId) { }
 
+    #[inductive(help_start)]
+    fn help_start_inductive(pre: Self, post: Self, node_id: NodeId, helper: NodeId) { }
+
+    #[inductive(help_reader_start)]
+    fn help_reader_start_inductive(pre: Self, post: Self, node_id: NodeId) { }
+
     #[inductive(reader_enter)]
     fn reader_enter_inductive(pre: Self, post: Self, node_id: NodeId) { }
 
@@ -714,28 +904,159 @@ tokenized_state_machine! { CyclicBuffer<DT: Dispatch> {
 
     #[inductive(reader_finish)]
     fn reader_finish_inductive(pre: Self, post: Self, node_id: NodeId) {
+        Self::min_active_version_increases(pre, post, node_id);
+    }
 
-        let min_local_versions_pre = map_min_value(pre.local_versions, (pre.num_replicas - 1) as nat);
-        let min_local_versions_post = map_min_value(post.local_versions, (post.num_replicas - 1) as nat);
+    #[inductive(reader_abort)]
+    fn reader_abort_inductive(pre: Self, post: Self, node_id: NodeId) { }
 
-        // there was a change in the minimum of the local heads, meaning the minimum was updated by us.
-        if min_local_versions_pre != min_local_versions_post {
+    #[inductive(retire_replica)]
+    fn retire_replica_inductive(pre: Self, post: Self, node_id: NodeId) {
+        // retiring a replica moves its version to the tail, this can only increase the minimum
+        assert(post.local_versions[node_id] == pre.local_versions[node_id]);
+        assert(pre.local_versions[0] == pre.combiner[0].get_Reading_0().get_Starting_start());
+        Self::min_active_version_increases(pre, post, node_id);
+    }
 
-            map_min_value_smallest(pre.local_versions, (pre.num_replicas - 1) as nat);
-            map_min_value_smallest(post.local_versions, (post.num_replicas - 1) as nat);
+    #[inductive(activate_replica)]
+    fn activate_replica_inductive(pre: Self, post: Self, node_id: NodeId) {
+        let src = 0;
+        let start = pre.combiner[src].get_Reading_0().get_Starting_start();
+        assert(pre.local_versions[src] == start);
+        assert(!pre.retired[src]);
+        assert(post.active_versions()[node_id] == pre.active_versions()[src]);
 
-            assert(forall |i| #[trigger] pre.local_versions.contains_key(i) ==> min_local_versions_pre <= pre.local_versions[i]);
-            assert(forall |i| #[trigger] post.local_versions.contains_key(i) ==> min_local_versions_post <= post.local_versions[i]);
+        // the activated replica takes the version of the first replica, the minimum does not change
+        assert(pre.local_versions.contains_key(src));
+        map_min_value_update_larger(pre.active_versions(), post.active_versions(), (pre.num_replicas - 1) as nat, node_id, src);
+        assert(post.min_active_version() == pre.min_active_version());
 
+        assert forall |nid| #[trigger] post.combiner.contains_key(nid) implies
+            post.combiner_valid(nid, post.combiner[nid]) by
+        {
+            if nid != node_id {
+                assert(pre.combiner_valid(nid, pre.combiner[nid]));
+            }
+        };
+        assert forall |nid| #![trigger post.combiner[nid]] #[trigger] post.combiner.contains_key(nid) && post.combiner[nid].is_Reading() implies
+            post.reader_state_valid(nid, post.combiner[nid].get_Reading_0()) by
+        {
+            assert(nid != node_id);
+            assert(pre.reader_state_valid(nid, pre.combiner[nid].get_Reading_0()));
+        };
+    }
+
+    #[inductive(activate_replica_at)]
+    fn activate_replica_at_inductive(pre: Self, post: Self, node_id: NodeId, version: LogIdx) {
+        let src = 0;
+        let start = pre.combiner[src].get_Reading_0().get_Range_start();
+        assert(pre.reader_state_valid(src, pre.combiner[src].get_Reading_0()));
+        assert(pre.local_versions[src] == start);
+        assert(!pre.retired[src]);
+        assert(pre.active_versions()[src] <= post.active_versions()[node_id]);
+        assert(pre.active_versions()[node_id] == pre.tail);
+
+        // the activated replica is not behind the first replica, the minimum does not change
+        assert(pre.local_versions.contains_key(src));
+        map_min_value_update_larger(pre.active_versions(), post.active_versions(), (pre.num_replicas - 1) as nat, node_id, src);
+        assert(post.min_active_version() == pre.min_active_version());
+
+        assert forall |nid| #[trigger] post.combiner.contains_key(nid) implies
+            post.combiner_valid(nid, post.combiner[nid]) by
+        {
+            if nid != node_id {
+                assert(pre.combiner_valid(nid, pre.combiner[nid]));
+            }
+        };
+        assert forall |nid| #![trigger post.combiner[nid]] #[trigger] post.combiner.contains_key(nid) && post.combiner[nid].is_Reading() implies
+            post.reader_state_valid(nid, post.combiner[nid].get_Reading_0()) by
+        {
+            assert(nid != node_id);
+            assert(pre.reader_state_valid(nid, pre.combiner[nid].get_Reading_0()));
+        };
+    }
+
+    /// Moving the local version of a replica forward, or retiring it, can only increase the
+    /// minimum of the local versions. The entries in between are no longer alive.
+    proof fn min_active_version_increases(pre: Self, post: Self, node_id: NodeId)
+        requires
+            pre.invariant(),
+            post.num_replicas == pre.num_replicas,
+            post.buffer_size == pre.buffer_size,
+            post.tail == pre.tail,
+            post.alive_bits == pre.alive_bits,
+            forall |n| 0 <= n < pre.num_replicas ==>
+                pre.active_versions()[n] <= #[trigger] post.active_versions()[n] <= pre.tail,
+        ensures
+            pre.min_active_version() <= post.min_active_version(),
+            forall |i| post.tail <= i < post.min_active_version() + post.buffer_size
+                ==> !log_entry_is_alive(post.alive_bits, i, post.buffer_size),
+    {
+        let min_local_versions_pre = pre.min_active_version();
+        let min_local_versions_post = post.min_active_version();
+
+        map_min_value_smallest(pre.active_versions(), (pre.num_replicas - 1) as nat);
+        map_min_value_smallest(post.active_versions(), (post.num_replicas - 1) as nat);
+        map_min_value_monotonic(pre.active_versions(), post.active_versions(), (pre.num_replicas - 1) as nat);
+
+        // there was a change in the minimum of the local heads, meaning the minimum was updated by us.
+        if min_local_versions_pre != min_local_versions_post {
             log_entry_alive_wrap_around(post.alive_bits, post.buffer_size, min_local_versions_pre, min_local_versions_post);
             log_entry_alive_wrap_around_helper(post.alive_bits, post.buffer_size, min_local_versions_pre, min_local_versions_post );
         }
     }
 
-    #[inductive(reader_abort)]
-    fn reader_abort_inductive(pre: Self, post: Self, node_id: NodeId) { }
+    /// Advancing the tail does not change the minimum of the local versions: the first replica
+    /// is active and its local version is at most the old tail.
+    proof fn min_active_version_tail_advance(pre: Self, post: Self)
+        requires
+            pre.invariant(),
+            post.num_replicas == pre.num_replicas,
+            post.local_versions == pre.local_versions,
+            post.retired == pre.retired,
+            pre.tail <= post.tail,
+        ensures
+            post.min_active_version() == pre.min_active_version(),
+    {
+        let idx = (pre.num_replicas - 1) as nat;
+        map_min_value_smallest(pre.active_versions(), idx);
+        assert(pre.local_versions.contains_key(0));
+        assert(pre.active_versions()[0] == pre.local_versions[0]);
+        assert forall |n| 0 <= n <= idx implies
+            pre.min_active_version() <= #[trigger] post.active_versions()[n] by {
+            assert(pre.retired.contains_key(n));
+        }
+        map_min_value_lower_bound(post.active_versions(), idx, pre.min_active_version());
+        map_min_value_smallest_in_range(post.active_versions(), idx);
+        // the minimum is either attained by an active replica, or the first replica is at the tail
+        let k = map_min_value_attained(pre.active_versions(), idx);
+        assert(pre.retired.contains_key(k));
+        if pre.retired[k] {
+            assert(post.active_versions()[0] == pre.min_active_version());
+        } else {
+            assert(post.active_versions()[k] == pre.min_active_version());
+        }
+    }
 }}
 
+/// the local versions of all replicas, where retired replicas are placed at the tail.
+pub open spec fn active_versions(
+    local_versions: Map<NodeId, LogIdx>,
+    retired: Map<NodeId, bool>,
+    tail: LogIdx,
+    num_replicas: nat,
+) -> Map<NodeId, LogIdx> {
+    Map::new(
+        |i: NodeId| 0 <= i < num_replicas,
+        |i: NodeId|
+            if retired[i] {
+                tail
+            } else {
+                local_versions[i]
+            },
+    )
+}
+
 pub open spec fn min(x: nat, y: nat) -> nat {
     if x < y {
         x
@@ -772,10 +1093,107 @@ proof fn map_min_value_smallest(m: Map<NodeId, nat>, idx: nat)
     }
 }
 
+proof fn map_min_value_lower_bound(m: Map<NodeId, nat>, idx: nat, v: nat)
+    requires
+        forall|n| 0 <= n <= idx ==> v <= #[trigger] m.index(n),
+    ensures
+        v <= map_min_value(m, idx),
+    decreases idx,
+{
+    if idx != 0 {
+        map_min_value_lower_bound(m, (idx - 1) as nat, v);
+    }
+}
+
+proof fn map_min_value_monotonic(m1: Map<NodeId, nat>, m2: Map<NodeId, nat>, idx: nat)
+    requires
+        forall|n| 0 <= n <= idx ==> #[trigger] m1.index(n) <= m2.index(n),
+    ensures
+        map_min_value(m1, idx) <= map_min_value(m2, idx),
+    decreases idx,
+{
+    if idx != 0 {
+        map_min_value_monotonic(m1, m2, (idx - 1) as nat);
+    }
+}
+
+/// updating an entry that is not smaller than another entry to a value that is not smaller than
+/// this other entry does not change the minimum
+proof fn map_min_value_update_larger(
+    m1: Map<NodeId, nat>,
+    m2: Map<NodeId, nat>,
+    idx: nat,
+    upd: NodeId,
+    src: NodeId,
+)
+    requires
+        src <= idx,
+        upd <= idx,
+        src != upd,
+        m1.index(src) <= m1.index(upd),
+        m1.index(src) <= m2.index(upd),
+        forall|n| 0 <= n <= idx && n != upd ==> #[trigger] m2.index(n) == m1.index(n),
+    ensures
+        map_min_value(m1, idx) == map_min_value(m2, idx),
+{
+    let min1 = map_min_value(m1, idx);
+    map_min_value_smallest_in_range(m1, idx);
+    map_min_value_smallest_in_range(m2, idx);
+    assert forall|n| 0 <= n <= idx implies min1 <= #[trigger] m2.index(n) by {
+        if n == upd {
+            assert(min1 <= m1.index(src));
+        }
+    }
+    // the minimum of m2 is not below the one of m1
+    map_min_value_lower_bound(m2, idx, min1);
+    assert(min1 <= map_min_value(m2, idx));
+    // the minimum of m1 is still attained in m2, either by the entry itself or by src
+    let k = map_min_value_attained(m1, idx);
+    if k == upd {
+        // src is at least the minimum and at most the updated entry, which was the minimum
+        assert(min1 <= m1.index(src));
+        assert(m1.index(src) <= m1.index(upd));
+        assert(m1.index(src) == min1);
+        assert(m2.index(src) == min1);
+        assert(map_min_value(m2, idx) <= m2.index(src));
+    } else {
+        assert(m2.index(k) == min1);
+        assert(map_min_value(m2, idx) <= m2.index(k));
+    }
+}
+
+proof fn map_min_value_smallest_in_range(m: Map<NodeId, nat>, idx: nat)
+    ensures
+        forall|n| 0 <= n <= idx as nat ==> map_min_value(m, idx) <= #[trigger] m.index(n),
+    decreases idx,
+{
+    if idx != 0 {
+        map_min_value_smallest_in_range(m, (idx - 1) as nat);
+    }
+}
+
+proof fn map_min_value_attained(m: Map<NodeId, nat>, idx: nat) -> (k: nat)
+    ensures
+        k <= idx,
+        m.index(k) == map_min_value(m, idx),
+    decreases idx,
+{
+    if idx == 0 {
+        0
+    } else {
+        let k = map_min_value_attained(m, (idx - 1) as nat);
+        if m.index(idx) < map_min_value(m, (idx - 1) as nat) {
+            idx
+        } else {
+            k
+        }
+    }
+}
+
 /// converts the logical to the physical log index
 pub open spec fn log_entry_idx(logical: LogicalLogIdx, buffer_size: nat) -> LogIdx
     recommends
-        buffer_size == LOG_SIZE,
+        valid_log_size(buffer_size),
 {
     (logical % (buffer_size as int)) as nat
 }
@@ -783,7 +1201,7 @@ pub open spec fn log_entry_idx(logical: LogicalLogIdx, buffer_size: nat) -> LogI
 // a % b == 0 to a == b * (a / b)
 pub proof fn log_entry_idx_wrap_around(start: nat, buffer_size: nat, idx: nat)
     requires
-        buffer_size == LOG_SIZE,
+        valid_log_size(buffer_size),
         start <= idx < start + buffer_size,
     ensures
         forall|i|
@@ -799,7 +1217,7 @@ pub open spec fn log_entry_is_alive(
     buffer_size: nat,
 ) -> bool
     recommends
-        buffer_size == LOG_SIZE,
+        valid_log_size(buffer_size),
 {
     let phys_id = log_entry_idx(logical, buffer_size);
     alive_bits[phys_id as nat] == log_entry_alive_value(logical, buffer_size)
@@ -808,7 +1226,7 @@ pub open spec fn log_entry_is_alive(
 /// the value the alive but must have for the entry to be alive, this flips on wrap around
 pub open spec fn log_entry_alive_value(logical: LogicalLogIdx, buffer_size: nat) -> bool
     recommends
-        buffer_size == LOG_SIZE,
+        valid_log_size(buffer_size),
 {
     ((logical / buffer_size as int) % 2) == 0
 }
@@ -824,7 +1242,7 @@ proof fn log_entry_alive_wrap_around(
     high: nat,
 )
     requires
-        buffer_size == LOG_SIZE,
+        valid_log_size(buffer_size),
         forall|i: nat| i < buffer_size <==> alive_bits.contains_key(i),
         low <= high <= low + buffer_size,
     ensures
@@ -845,7 +1263,7 @@ proof fn log_entry_alive_wrap_around_helper(
     high: nat,
 )
     requires
-        buffer_size == LOG_SIZE,
+        valid_log_size(buffer_size),
         forall|i: nat| i < buffer_size <==> alive_bits.contains_key(i),
         low <= high <= low + buffer_size,
         forall|i: int|
@@ -14,6 +14,54 @@ use super::types::*;
 
 verus! {
 
+/// Identifies a slot of the flat combiner. Every thread has `batch_size` slots, one for each of
+/// its pending operations.
+pub type SlotId = nat;
+
+/// the slot of the `idx`-th pending operation of thread `tid`
+pub open spec fn slot_id(tid: ThreadId, idx: nat, batch_size: nat) -> SlotId {
+    tid * batch_size + idx
+}
+
+/// the slot ids of a thread's pending operations are unique and consecutive
+pub proof fn slot_id_inverse(tid: ThreadId, idx: nat, batch_size: nat)
+    requires
+        idx < batch_size,
+    ensures
+        slot_id(tid, idx, batch_size) / batch_size == tid,
+        slot_id(tid, idx, batch_size) % batch_size == idx,
+{
+    vstd::arithmetic::div_mod::lemma_fundamental_div_mod_converse(
+        slot_id(tid, idx, batch_size) as int,
+        batch_size as int,
+        tid as int,
+        idx as int,
+    );
+}
+
+/// the slot after the last pending operation of a thread is the first slot of the next thread
+pub proof fn slot_id_next_thread(tid: ThreadId, batch_size: nat)
+    ensures
+        slot_id(tid, batch_size, batch_size) == slot_id(tid + 1, 0, batch_size),
+{
+    assert(tid * batch_size + batch_size == (tid + 1) * batch_size) by (nonlinear_arith);
+}
+
+/// the slots of the threads' pending operations are within the total number of slots
+pub proof fn slot_id_bound(tid: ThreadId, idx: nat, num_threads: nat, batch_size: nat)
+    requires
+        tid < num_threads,
+        idx < batch_size,
+    ensures
+        slot_id(tid, idx, batch_size) < num_threads * batch_size,
+{
+    assert(tid * batch_size + idx < num_threads * batch_size) by (nonlinear_arith)
+        requires
+            tid < num_threads,
+            idx < batch_size,
+    ;
+}
+
 /// represents the state of a client thread
 #[is_variant]
 pub tracked enum ClientState {
@@ -85,12 +133,16 @@ FlatCombiner {
         #[sharding(constant)]
         pub num_threads: nat,
 
-        /// clients of the replica
+        /// the number of pending operations per thread
+        #[sharding(constant)]
+        pub batch_size: nat,
+
+        /// clients of the replica, one for every pending operation of a thread
         #[sharding(map)]
-        pub clients: Map<ThreadId, ClientState>,
+        pub clients: Map<SlotId, ClientState>,
 
         #[sharding(map)]
-        pub slots: Map<ThreadId, SlotState>,
+        pub slots: Map<SlotId, SlotState>,
 
         #[sharding(variable)]
         pub combiner: CombinerState,
@@ -100,12 +152,17 @@ FlatCombiner {
     // Invariant
     ////////////////////////////////////////////////////////////////////////////////////////////
 
+    /// the total number of slots, the combiner collects from all of them
+    pub open spec fn num_slots(&self) -> nat {
+        self.num_threads * self.batch_size
+    }
+
     #[invariant]
     pub fn inv_complete(&self) -> bool {
         // clients are complete
-        &&& (forall |i| self.clients.contains_key(i) <==> i < self.num_threads)
+        &&& (forall |i| self.clients.contains_key(i) <==> i < self.num_slots())
         // slots are complete
-        &&& (forall |i| self.slots.contains_key(i) <==> i < self.num_threads)
+        &&& (forall |i| self.slots.contains_key(i) <==> i < self.num_slots())
     }
 
 
@@ -126,10 +183,10 @@ FlatCombiner {
     pub fn inv_combiner_elements(&self) -> bool {
         match self.combiner {
             CombinerState::Collecting(elems) => {
-                elems.len() <= self.num_threads
+                elems.len() <= self.num_slots()
             },
             CombinerState::Responding(elems, idx) => {
-                &&& elems.len() == self.num_threads
+                &&& elems.len() == self.num_slots()
                 &&& idx <= elems.len()
             },
         }
@@ -149,7 +206,7 @@ FlatCombiner {
                 &&& (forall |i: nat| 0 <= i < elems.len() && elems[i as int].is_None()
                     ==> !(#[trigger] self.slots[i]).is_InProgress()) //Self::slot_in_progress(self.slots, i)))
                 // everything above is not in progress
-                &&& (forall |i: nat| elems.len() <= i < self.num_threads ==> !self.slots[i].is_InProgress())
+                &&& (forall |i: nat| elems.len() <= i < self.num_slots() ==> !self.slots[i].is_InProgress())
             },
             CombinerState::Responding(elems, idx) => {
                 &&& (forall |i: nat| 0 <= i < elems.len() && elems[i as int].is_None()
@@ -182,11 +239,12 @@ FlatCombiner {
 
 
     init!{
-        initialize(num_threads: nat) {
+        initialize(num_threads: nat, batch_size: nat) {
             init num_threads = num_threads;
+            init batch_size = batch_size;
 
-            init clients = Map::new(|i:ThreadId| i < num_threads, |i| ClientState::Idle);
-            init slots = Map::new(|i: ThreadId| i < num_threads, |i| SlotState::Empty);
+            init clients = Map::new(|i: SlotId| i < num_threads * batch_size, |i| ClientState::Idle);
+            init slots = Map::new(|i: SlotId| i < num_threads * batch_size, |i| SlotState::Empty);
 
             init combiner = CombinerState::Collecting(Seq::empty());
         }
@@ -227,12 +285,26 @@ FlatCombiner {
     }
 
 
+    /// the combiner skips the slot without looking at it
+    ///
+    /// The slot is not in progress, as the combiner hasn't collected it yet. If the slot holds a
+    /// request, the request remains there and is collected by a later round of combining.
+    transition!{
+        combiner_collect_skip() {
+            require(pre.combiner.is_Collecting());
+            require(pre.combiner.get_Collecting_0().len() < pre.num_slots());
+
+            update combiner = CombinerState::Collecting(pre.combiner.get_Collecting_0().push(Option::None));
+        }
+    }
+
+
     /// Safety Condition: the slot state is not in progress when collecting
     property!{
         pre_combiner_collect_request() {
             require(pre.combiner.is_Collecting());
             let idx = pre.combiner.get_Collecting_0().len();
-            require(idx < pre.num_threads);
+            require(idx < pre.num_slots());
             have slots >= [ idx => let slot_state ];
 
             assert(!slot_state.is_InProgress());
@@ -248,7 +320,7 @@ FlatCombiner {
     transition!{
         combiner_responding_start() {
             require(pre.combiner.is_Collecting());
-            require(pre.combiner.get_Collecting_0().len() == pre.num_threads);
+            require(pre.combiner.get_Collecting_0().len() == pre.num_slots());
 
             update combiner = CombinerState::Responding(pre.combiner.get_Collecting_0(), 0);
         }
@@ -260,7 +332,7 @@ FlatCombiner {
             require(pre.combiner.is_Responding());
             let tid = pre.combiner.get_Responding_1();
 
-            require(tid < pre.num_threads);
+            require(tid < pre.num_slots());
             require(pre.combiner.req_is_none(tid));
 
             update combiner = CombinerState::Responding(pre.combiner.get_Responding_0(), tid + 1);
@@ -274,7 +346,7 @@ FlatCombiner {
 
             let tid = pre.combiner.get_Responding_1();
 
-            require(tid < pre.num_threads);
+            require(tid < pre.num_slots());
             require(!pre.combiner.req_is_none(tid));
 
             update combiner = CombinerState::Responding(pre.combiner.get_Responding_0(), tid + 1);
@@ -289,7 +361,7 @@ FlatCombiner {
     transition!{
         combiner_responding_done() {
             require(pre.combiner.is_Responding());
-            require(pre.combiner.get_Responding_1() == pre.num_threads);
+            require(pre.combiner.get_Responding_1() == pre.num_slots());
 
             update combiner = CombinerState::Collecting(Seq::empty());
         }
@@ -303,7 +375,7 @@ FlatCombiner {
 
     /// Safety Condition: the slot state is not in progress when collecting
     property!{
-        pre_send_request(tid: ThreadId) {
+        pre_send_request(tid: SlotId) {
 
             have clients >= [ tid => let ClientState::Idle ];
             have slots   >= [ tid => let slot_state ];
@@ -313,7 +385,7 @@ FlatCombiner {
     }
 
     transition!{
-        send_request(tid: ThreadId, rid: ReqId) {
+        send_request(tid: SlotId, rid: ReqId) {
             remove clients -= [ tid => let ClientState::Idle ];
             add    clients += [ tid => ClientState::Waiting(rid) ];
 
@@ -324,7 +396,7 @@ FlatCombiner {
 
     /// Safety Condition: the slot state is not in progress when collecting
     property!{
-        pre_recv_response(tid: ThreadId) {
+        pre_recv_response(tid: SlotId) {
 
             have clients >= [ tid => let ClientState::Waiting(rid) ];
             have slots   >= [ tid => let slot_state ];
@@ -335,7 +407,7 @@ FlatCombiner {
     }
 
     transition!{
-        recv_response(tid: ThreadId, rid: ReqId) {
+        recv_response(tid: SlotId, rid: ReqId) {
             remove clients -= [ tid => ClientState::Waiting(rid) ];
             add    clients += [ tid => ClientState::Idle ];
 
@@ -351,11 +423,14 @@ FlatCombiner {
 
 
     #[inductive(initialize)]
-    fn initialize_inductive(post: Self, num_threads: nat) { }
+    fn initialize_inductive(post: Self, num_threads: nat, batch_size: nat) { }
 
     #[inductive(combiner_collect_empty)]
     fn combiner_collect_empty_inductive(pre: Self, post: Self) { }
 
+    #[inductive(combiner_collect_skip)]
+    fn combiner_collect_skip_inductive(pre: Self, post: Self) { }
+
     #[inductive(combiner_collect_request)]
     fn combiner_collect_request_inductive(pre: Self, post: Self) {
         match post.combiner {
@@ -388,17 +463,17 @@ FlatCombiner {
     fn combiner_responding_done_inductive(pre: Self, post: Self) { }
 
     #[inductive(send_request)]
-    fn send_request_inductive(pre: Self, post: Self, tid: ThreadId, rid: ReqId) {
+    fn send_request_inductive(pre: Self, post: Self, tid: SlotId, rid: ReqId) {
         assert(Self::slot_in_progress(post.slots, tid) == Self::slot_in_progress(pre.slots, tid));
-        assert(forall |i: nat| 0 <= i < post.num_threads
+        assert(forall |i: nat| 0 <= i < post.num_slots()
             ==> #[trigger] Self::slot_in_progress(post.slots, i) == Self::slot_in_progress(pre.slots, i));
 
     }
 
     #[inductive(recv_response)]
-    fn recv_response_inductive(pre: Self, post: Self, tid: ThreadId, rid: ReqId) {
+    fn recv_response_inductive(pre: Self, post: Self, tid: SlotId, rid: ReqId) {
         assert(Self::slot_in_progress(post.slots, tid) == Self::slot_in_progress(pre.slots, tid));
-        assert(forall |i: nat| 0 <= i < post.num_threads
+        assert(forall |i: nat| 0 <= i < post.num_slots()
             ==> #[trigger] Self::slot_in_progress(post.slots, i) == Self::slot_in_progress(pre.slots, i));
     }
 
@@ -162,7 +162,7 @@ proof fn exists_equiv_behavior_rec<DT: Dispatch>(
         },
         SimpleLogBehavior::Inited(sl_state) => {
             let st = AsynchronousSingleton::State {
-                state: DT::init_spec(),
+                state: sl_state.init_state,
                 reqs: Map::empty(),
                 resps: Map::empty(),
             };
@@ -173,7 +173,7 @@ proof fn exists_equiv_behavior_rec<DT: Dispatch>(
             reveal(AsynchronousSingleton::State::init_by);
             assert(AsynchronousSingleton::State::init_by(
                 st,
-                AsynchronousSingleton::Config::initialize(),
+                AsynchronousSingleton::Config::initialize(sl_state.init_state),
             ));
             res
         },
@@ -589,13 +589,13 @@ proof fn update_add_update_to_log_refines<DT: Dispatch>(
             aop,
         ),  //  one.Next(Is, Is', AI.InternalOp)
 {
-    state_at_version_preserves::<DT>(s.log, s2.log, s.update_reqs[rid], s.version);
+    state_at_version_preserves::<DT>(s.init_state, s.log, s2.log, s.update_reqs[rid], s.version);
     assert forall|r| #[trigger]
         s2.readonly_reqs.contains_key(r) && #[trigger] t.resps.contains_key(r)
             ==> readonly_response_is_valid(s2, t, r_points, r) by {
         if s2.readonly_reqs.contains_key(r) && #[trigger] t.resps.contains_key(r) {
             if r_points.contains_key(r) {
-                state_at_version_preserves::<DT>(s.log, s2.log, s.update_reqs[rid], r_points[r]);
+                state_at_version_preserves::<DT>(s.init_state, s.log, s2.log, s.update_reqs[rid], r_points[r]);
             }
         }
     }
@@ -604,6 +604,7 @@ proof fn update_add_update_to_log_refines<DT: Dispatch>(
             ==> update_response_is_valid(s2, t, r_points, r) by {
         if s2.update_resps.contains_key(r) && s2.update_resps[r].0 < s2.version {
             state_at_version_preserves::<DT>(
+                s.init_state,
                 s.log,
                 s2.log,
                 s.update_reqs[rid],
@@ -899,6 +900,7 @@ proof fn update_incr_version_1_read_reqs<DT: Dispatch>(
 // =================================================================================================
 /// Shows that adding an entry to the log doesn't change the state
 proof fn state_at_version_preserves<DT: Dispatch>(
+    init_state: DT::View,
     a: Seq<DT::WriteOperation>,
     b: Seq<DT::WriteOperation>,
     x: DT::WriteOperation,
@@ -909,11 +911,15 @@ proof fn state_at_version_preserves<DT: Dispatch>(
         i <= a.len(),
         i <= b.len(),
     ensures
-        compute_nrstate_at_version::<DT>(a, i) == compute_nrstate_at_version::<DT>(b, i),
+        compute_nrstate_at_version::<DT>(init_state, a, i) == compute_nrstate_at_version::<DT>(
+            init_state,
+            b,
+            i,
+        ),
     decreases i,
 {
     if i > 0 {
-        state_at_version_preserves::<DT>(a, b, x, (i - 1) as LogIdx);
+        state_at_version_preserves::<DT>(init_state, a, b, x, (i - 1) as LogIdx);
     }
 }
 
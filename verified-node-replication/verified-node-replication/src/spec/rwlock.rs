@@ -22,18 +22,29 @@ tokenized_state_machine!{
             #[sharding(storage_option)]
             pub storage: Option<T>,
 
+            /// set while a writer is pending, holds the lock, or releases it, new readers wait on
+            /// it to clear
             #[sharding(variable)]
             pub exc_locked: bool,
 
             #[sharding(map)]
             pub ref_counts: Map<int, int>,
 
+            /// the reference counts a pending writer has already checked, readers can't announce
+            /// themselves on them until the writer has released the lock
+            #[sharding(map)]
+            pub blocked: Map<int, bool>,
+
             #[sharding(option)]
             pub exc_pending: Option<int>,
 
             #[sharding(option)]
             pub exc_guard: Option<()>,
 
+            /// the writer has handed back the data and unblocks the reference counts in order
+            #[sharding(option)]
+            pub exc_releasing: Option<int>,
+
             #[sharding(multiset)]
             pub shared_pending: Multiset<int>,
 
@@ -53,8 +64,13 @@ tokenized_state_machine!{
                     |i| 0 <= i < rc_width,
                     |i| 0,
                 );
+                init blocked = Map::new(
+                    |i| 0 <= i < rc_width,
+                    |i| false,
+                );
                 init exc_pending = Option::None;
                 init exc_guard = Option::None;
+                init exc_releasing = Option::None;
                 init shared_pending = Multiset::empty();
                 init shared_guard = Multiset::empty();
             }
@@ -72,7 +88,9 @@ tokenized_state_machine!{
             exc_check_count() {
                 remove exc_pending -= Some(let r);
                 have ref_counts >= [r => 0];
+                remove blocked -= [r => false];
 
+                add blocked += [r => true];
                 add exc_pending += Some(r + 1);
             }
         }
@@ -92,15 +110,34 @@ tokenized_state_machine!{
         transition!{
             exc_release(t: T) {
                 require(pre.user_inv.contains(t));
-                update exc_locked = false;
                 remove exc_guard -= Some(());
                 deposit storage += Some(t);
+                add exc_releasing += Some(0);
+            }
+        }
+
+        transition!{
+            exc_unblock() {
+                remove exc_releasing -= Some(let r);
+                remove blocked -= [r => let b];
+                assert(b);
+
+                add blocked += [r => false];
+                add exc_releasing += Some(r + 1);
+            }
+        }
+
+        transition!{
+            exc_release_finish() {
+                remove exc_releasing -= Some(pre.rc_width);
+                update exc_locked = false;
             }
         }
 
         transition!{
             shared_start(r: int) {
                 remove ref_counts -= [r => let rc];
+                have blocked >= [r => false];
                 add ref_counts += [r => rc + 1];
                 add shared_pending += {r};
             }
@@ -157,7 +194,25 @@ tokenized_state_machine!{
             rc_not_zero_guard(r: int) {
                 have shared_pending >= {r};
                 have ref_counts >= [r => let rc];
+                have blocked >= [r => let b];
                 assert(rc > 0);
+                assert(!b) by {
+                    assert(pre.shared_pending.count(r) > 0);
+                };
+            }
+        }
+
+        property!{
+            guard_not_blocked(val: (int, T)) {
+                have shared_guard >= {val};
+                have blocked >= [val.0 => let b];
+                assert(!b) by {
+                    let r = val.0;
+                    assert(pre.shared_guard.count(val) > 0);
+                    assert(Self::filter_r(pre.shared_guard, r).count(val) > 0);
+                    assert(Self::filter_r(pre.shared_guard, r).len() > 0);
+                    assert(pre.ref_counts.index(r) > 0);
+                };
             }
         }
 
@@ -178,15 +233,46 @@ tokenized_state_machine!{
 
         #[invariant]
         pub fn exc_inv(&self) -> bool {
-            &&& self.exc_locked <==> (self.exc_pending.is_Some() || self.exc_guard.is_Some())
+            &&& self.exc_locked <==> (self.exc_pending.is_Some() || self.exc_guard.is_Some()
+                || self.exc_releasing.is_Some())
             &&& self.storage.is_Some() <==> self.exc_guard.is_None()
             &&& if let Option::Some(cur_r) = self.exc_pending {
                 &&& 0 <= cur_r <= self.rc_width
                 &&& self.exc_guard.is_None()
+                &&& self.exc_releasing.is_None()
                 &&& forall |x| self.shared_guard.count(x) > 0 ==> !(0 <= x.0 < cur_r)
             } else {
                 true
             }
+            &&& if let Option::Some(cur_r) = self.exc_releasing {
+                &&& 0 <= cur_r <= self.rc_width
+                &&& self.exc_guard.is_None()
+            } else {
+                true
+            }
+        }
+
+        /// whether the reference count `r` is blocked in the current state of the writer
+        pub open spec fn is_blocked(&self, r: int) -> bool {
+            if let Option::Some(cur_r) = self.exc_pending {
+                r < cur_r
+            } else if self.exc_guard.is_Some() {
+                true
+            } else if let Option::Some(cur_r) = self.exc_releasing {
+                r >= cur_r
+            } else {
+                false
+            }
+        }
+
+        #[invariant]
+        pub fn blocked_inv(&self) -> bool {
+            &&& forall |i: int| 0 <= i < self.rc_width <==> self.blocked.dom().contains(i)
+            &&& forall |i: int| 0 <= i < self.rc_width ==>
+                #[trigger] self.blocked.index(i) == self.is_blocked(i)
+            // no reader holds or waits for the lock on a blocked reference count
+            &&& forall |i: int| 0 <= i < self.rc_width && #[trigger] self.blocked.index(i) ==>
+                self.ref_counts.index(i) == 0
         }
 
         #[invariant]
@@ -234,7 +320,11 @@ tokenized_state_machine!{
 
         #[inductive(exc_start)]
         fn exc_start_inductive(pre: Self, post: Self) {
-
+            assert forall |i: int| 0 <= i < post.rc_width implies
+                #[trigger] post.blocked.index(i) == post.is_blocked(i)
+            by {
+                assert(!pre.is_blocked(i));
+            }
         }
 
         #[inductive(exc_check_count)]
@@ -245,22 +335,63 @@ tokenized_state_machine!{
             by {
                 assert(Self::filter_r(post.shared_guard, prev_r).count(x) > 0);
             }
+            assert forall |i: int| 0 <= i < post.rc_width implies
+                #[trigger] post.blocked.index(i) == post.is_blocked(i)
+            by {
+                if i != prev_r {
+                    assert(pre.blocked.index(i) == pre.is_blocked(i));
+                }
+            }
         }
 
         #[inductive(exc_finish)]
         fn exc_finish_inductive(pre: Self, post: Self) {
+            assert forall |i: int| 0 <= i < post.rc_width implies
+                #[trigger] post.blocked.index(i) == post.is_blocked(i)
+            by {
+                assert(pre.blocked.index(i) == pre.is_blocked(i));
+            }
         }
 
         #[inductive(exc_release)]
         fn exc_release_inductive(pre: Self, post: Self, t: T) {
+            assert forall |i: int| 0 <= i < post.rc_width implies
+                #[trigger] post.blocked.index(i) == post.is_blocked(i)
+            by {
+                assert(pre.blocked.index(i) == pre.is_blocked(i));
+            }
+        }
 
+        #[inductive(exc_unblock)]
+        fn exc_unblock_inductive(pre: Self, post: Self) {
+            let prev_r = pre.exc_releasing.get_Some_0();
+            assert(0 <= prev_r < pre.rc_width);
+            assert forall |i: int| 0 <= i < post.rc_width implies
+                #[trigger] post.blocked.index(i) == post.is_blocked(i)
+            by {
+                if i != prev_r {
+                    assert(pre.blocked.index(i) == pre.is_blocked(i));
+                }
+            }
+        }
+
+        #[inductive(exc_release_finish)]
+        fn exc_release_finish_inductive(pre: Self, post: Self) {
+            assert forall |i: int| 0 <= i < post.rc_width implies
+                #[trigger] post.blocked.index(i) == post.is_blocked(i)
+            by {
+                assert(pre.blocked.index(i) == pre.is_blocked(i));
+            }
         }
 
         #[inductive(shared_start)]
         fn shared_start_inductive(pre: Self, post: Self, r: int) { }
 
         #[inductive(shared_abandon)]
-        fn shared_abandon_inductive(pre: Self, post: Self, r: int) { }
+        fn shared_abandon_inductive(pre: Self, post: Self, r: int) {
+            // the reference count is not zero, hence it is not blocked
+            assert(!pre.blocked.index(r));
+        }
 
         #[inductive(shared_finish)]
         fn shared_finish_inductive(pre: Self, post: Self, r: int) {
@@ -289,6 +420,10 @@ tokenized_state_machine!{
         #[inductive(shared_release)]
         fn shared_release_inductive(pre: Self, post: Self, val: (int, T)) {
             let r = val.0;
+            // the reader holds the lock on the reference count, hence it is not blocked
+            assert(Self::filter_r(pre.shared_guard, r).count(val) > 0);
+            assert(Self::filter_r(pre.shared_guard, r).len() > 0);
+            assert(!pre.blocked.index(r));
             assert forall |r0| 0 <= r0 < post.rc_width implies
                 #[trigger] post.ref_counts.index(r0) ==
                     post.shared_pending.count(r0) as int +
@@ -64,6 +64,8 @@ pub ghost struct UpdateResp(pub LogIdx);
 state_machine! {
     SimpleLog<DT: Dispatch> {
     fields {
+        /// the state of the data structure before the first update operation of the log
+        pub init_state: DT::View,
         /// a sequence of update operations,
         pub log: Seq<DT::WriteOperation>,
         /// the number of replicas, TODO: can we make this a constant?
@@ -120,7 +122,8 @@ state_machine! {
 
 
     init!{
-        initialize(num_replicas: nat) {
+        initialize(num_replicas: nat, init_state: DT::View) {
+            init init_state = init_state;
             init num_replicas = num_replicas;
             init replica_versions = Map::new(|i:NodeId| i < num_replicas, |i| 0);
             init log = Seq::empty();
@@ -144,7 +147,7 @@ state_machine! {
     pub open spec fn nrstate_at_version(&self, version: LogIdx) -> DT::View
         recommends 0 <= version <= self.log.len()
     {
-        compute_nrstate_at_version::<DT>(self.log, version)
+        compute_nrstate_at_version::<DT>(self.init_state, self.log, version)
     }
 
     ////////////////////////////////////////////////////////////////////////////////////////////
@@ -329,7 +332,7 @@ state_machine! {
 
 
     #[inductive(initialize)]
-    fn initialize_inductive(post: Self, num_replicas: nat) { }
+    fn initialize_inductive(post: Self, num_replicas: nat, init_state: DT::View) { }
 
     #[inductive(readonly_start)]
     fn readonly_start_inductive(pre: Self, post: Self, label: Label<DT>, rid: ReqId, node_id: NodeId, op: DT::ReadOperation) { }
@@ -361,12 +364,13 @@ state_machine! {
 }}  // state_machine! SimpleLog
 /// constructs the state of the data structure at a specific version given the log
 ///
-/// This function recursively applies the update operations to the initial state of the
+/// This function recursively applies the update operations to the given initial state of the
 /// data structure and returns the state of the data structure at the given version. The
 /// version must be within the log's range.
 
 
 pub open spec fn compute_nrstate_at_version<DT: Dispatch>(
+    init_state: DT::View,
     log: Seq<DT::WriteOperation>,
     version: LogIdx,
 ) -> DT::View
@@ -375,10 +379,10 @@ pub open spec fn compute_nrstate_at_version<DT: Dispatch>(
     decreases version,
 {
     if version == 0 {
-        DT::init_spec()
+        init_state
     } else {
         DT::dispatch_mut_spec(
-            compute_nrstate_at_version::<DT>(log, (version - 1) as nat),
+            compute_nrstate_at_version::<DT>(init_state, log, (version - 1) as nat),
             log[version - 1],
         ).0
     }
@@ -13,6 +13,8 @@ use vstd::seq::Seq;
 use vstd::set::Set;
 
 use crate::Dispatch;
+#[cfg(verus_keep_ghost)]
+use crate::{is_update_stub, is_update_ticket};
 
 use super::types::*;
 use super::utils::*;
@@ -231,6 +233,9 @@ pub ghost enum UpdateState<DT: Dispatch> {
 #[is_variant]
 pub ghost enum CombinerState {
     Ready,
+    /// a thread of the `helper` replica executes the log on behalf of this replica, it doesn't
+    /// place any operations of its own
+    Helped { helper: NodeId },
     Placed { queued_ops: Seq<ReqId> },
     LoadedLocalVersion { queued_ops: Seq<ReqId>, lversion: LogIdx },
     Loop {
@@ -250,6 +255,7 @@ impl CombinerState {
     pub open spec fn queued_ops(self) -> Seq<ReqId> {
         match self {
             CombinerState::Ready => Seq::empty(),
+            CombinerState::Helped { .. } => Seq::empty(),
             CombinerState::Placed { queued_ops } => queued_ops,
             CombinerState::LoadedLocalVersion { queued_ops, .. } => queued_ops,
             CombinerState::Loop { queued_ops, .. } => queued_ops,
@@ -271,6 +277,10 @@ UnboundedLog<DT: Dispatch> {
         #[sharding(constant)]
         pub num_replicas: nat,
 
+        /// the state of the data structure before the first entry of the log
+        #[sharding(constant)]
+        pub init_state: DT::View,
+
         #[sharding(map)]
         pub log: Map<LogIdx, LogEntry<DT>>,
 
@@ -293,7 +303,15 @@ UnboundedLog<DT: Dispatch> {
         pub local_updates: Map<ReqId, UpdateState<DT>>,
 
         #[sharding(map)]
-        pub combiner: Map<NodeId, CombinerState>
+        pub combiner: Map<NodeId, CombinerState>,
+
+        /// the states of the replicas that have been snapshotted, by their version
+        #[sharding(persistent_map)]
+        pub snapshots: Map<LogIdx, DT::View>,
+
+        /// the values that the version upper bound has had
+        #[sharding(persistent_set)]
+        pub version_upper_bound_reached: Set<LogIdx>
     }
 
 
@@ -424,6 +442,11 @@ UnboundedLog<DT: Dispatch> {
                 // &&& self.local_versions[node_id] <= self.tail
                 &&& LogRangeNoNodeId(self.log, self.local_versions[node_id], self.tail, node_id)
             }
+            CombinerState::Helped { helper } => {
+                // the helper is another replica and there are no local entries to execute
+                &&& helper != node_id
+                &&& LogRangeNoNodeId(self.log, self.local_versions[node_id], self.tail, node_id)
+            }
             CombinerState::Placed { queued_ops } => {
                 // &&& self.local_versions.contains_key(node_id)
                 // &&& self.local_versions[node_id] <= self.tail
@@ -526,7 +549,7 @@ UnboundedLog<DT: Dispatch> {
             ReadonlyState::Done { ret, version_upper_bound, op, node_id } => {
                 exists |v: nat| (#[trigger] rangeincl(version_upper_bound, v, self.version_upper_bound))
                     && v <= self.current_local_version(node_id)
-                    && ret == DT::dispatch_spec(compute_nrstate_at_version(self.log, v), op)
+                    && ret == DT::dispatch_spec(compute_nrstate_at_version(self.init_state, self.log, v), op)
             },
             _ => true,
         }
@@ -544,10 +567,10 @@ UnboundedLog<DT: Dispatch> {
     pub open spec fn update_results_match(&self, update: UpdateState<DT>) -> bool {
         match update {
             UpdateState::Applied { ret, idx } => {
-                ret == DT::dispatch_mut_spec(compute_nrstate_at_version(self.log, idx), self.log[idx].op).1
+                ret == DT::dispatch_mut_spec(compute_nrstate_at_version(self.init_state, self.log, idx), self.log[idx].op).1
             },
             UpdateState::Done { ret, idx } => {
-                ret == DT::dispatch_mut_spec(compute_nrstate_at_version(self.log, idx), self.log[idx].op).1
+                ret == DT::dispatch_mut_spec(compute_nrstate_at_version(self.init_state, self.log, idx), self.log[idx].op).1
             },
             _ => true,
         }
@@ -571,7 +594,7 @@ UnboundedLog<DT: Dispatch> {
     #[invariant]
     pub open spec fn replica_state(&self) -> bool {
         forall |node_id| (#[trigger] self.replicas.contains_key(node_id)) ==>
-            self.replicas[node_id] == compute_nrstate_at_version(self.log, self.current_local_version(node_id))
+            self.replicas[node_id] == compute_nrstate_at_version(self.init_state, self.log, self.current_local_version(node_id))
     }
 
     #[invariant]
@@ -580,24 +603,43 @@ UnboundedLog<DT: Dispatch> {
             self.current_local_version(node_id) <= self.tail
     }
 
+    /// the snapshots match the state at their version of the log
+    #[invariant]
+    pub open spec fn inv_snapshots(&self) -> bool {
+        forall |version| (#[trigger] self.snapshots.contains_key(version)) ==> {
+            &&& version <= self.version_upper_bound
+            &&& self.snapshots[version] == compute_nrstate_at_version(self.init_state, self.log, version)
+        }
+    }
+
+    /// the version upper bound never goes backwards
+    #[invariant]
+    pub open spec fn inv_version_upper_bound_reached(&self) -> bool {
+        forall |version| #[trigger] self.version_upper_bound_reached.contains(version) ==>
+            version <= self.version_upper_bound
+    }
+
 
     ////////////////////////////////////////////////////////////////////////////////////////////
     // State Machine Initialization
     ////////////////////////////////////////////////////////////////////////////////////////////
 
     init!{
-        initialize(number_of_nodes: nat) {
+        initialize(number_of_nodes: nat, init_state: DT::View) {
             require(number_of_nodes > 0);
 
             init num_replicas = number_of_nodes;
+            init init_state = init_state;
             init log = Map::empty();
             init tail = 0;
-            init replicas = Map::new(|n: NodeId| n < number_of_nodes, |n| DT::init_spec());
+            init replicas = Map::new(|n: NodeId| n < number_of_nodes, |n| init_state);
             init local_versions = Map::new(|n: NodeId| n < number_of_nodes, |n| 0);
             init version_upper_bound = 0;
             init local_reads = Map::empty();
             init local_updates = Map::empty();
             init combiner = Map::new(|n: NodeId| n < number_of_nodes, |n| CombinerState::Ready);
+            init snapshots = Map::empty();
+            init version_upper_bound_reached = Set::empty();
         }
     }
 
@@ -658,6 +700,29 @@ UnboundedLog<DT: Dispatch> {
     }
 
 
+    ////////////////////////////////////////////////////////////////////////////////////////////
+    // Version Upper Bound Transitions
+    ////////////////////////////////////////////////////////////////////////////////////////////
+
+    /// Version Upper Bound: record the current value of the version upper bound
+    ///
+    /// Relaxed reads use this as the lower bound of the version they are allowed to read.
+    transition!{
+        version_upper_bound_witness() {
+            add version_upper_bound_reached (union) += set { pre.version_upper_bound };
+        }
+    }
+
+    /// Version Upper Bound: a completed update lies below the version upper bound
+    property!{
+        update_done_below_version_upper_bound(rid: ReqId) {
+            have local_updates >= [ rid => let UpdateState::Done { ret, idx } ];
+
+            assert(idx < pre.version_upper_bound);
+        }
+    }
+
+
     ////////////////////////////////////////////////////////////////////////////////////////////
     // Update Transitions
     ////////////////////////////////////////////////////////////////////////////////////////////
@@ -711,6 +776,28 @@ UnboundedLog<DT: Dispatch> {
         }
     }
 
+    /// Combiner: a thread of the `helper` replica starts to execute the log on behalf of the
+    /// replica, it doesn't place any operations
+    transition!{
+        exec_help_start(node_id: NodeId, helper: NodeId) {
+            require(node_id != helper);
+
+            remove combiner -= [ node_id => CombinerState::Ready ];
+            add    combiner += [ node_id => CombinerState::Helped { helper } ];
+        }
+    }
+
+    /// Combiner: the helper reads the version of the replica
+    transition!{
+        exec_help_load_local_version(node_id: NodeId) {
+            remove combiner       -= [ node_id => let CombinerState::Helped { helper } ];
+
+            have   local_versions >= [ node_id => let lversion ];
+
+            add    combiner       += [ node_id => CombinerState::LoadedLocalVersion { queued_ops: Seq::empty(), lversion } ];
+        }
+    }
+
     /// Combiner: read the version of the local replica
     transition!{
         exec_load_local_version(node_id: NodeId) {
@@ -748,6 +835,23 @@ UnboundedLog<DT: Dispatch> {
         }
     }
 
+    /// Combiner: Safety condition, the local update that is dispatched next has been placed at
+    /// the current version of the log
+    property!{
+        pre_exec_dispatch_local_placed(node_id: NodeId) {
+            have combiner >= [ node_id => let CombinerState::Loop{ queued_ops, lversion, tail, idx } ];
+            have log      >= [ lversion => let log_entry ];
+            let rid = queued_ops.index(idx as int);
+            have local_updates >= [ rid => let local_update ];
+
+            require(log_entry.node_id == node_id);
+            require(lversion < tail);
+            assert(local_update.is_Placed() && local_update.get_Placed_idx() == lversion) by {
+                assert(pre.wf_combiner_for_node_id(node_id));
+            };
+        }
+    }
+
     /// Combiner: dispatch a local update and apply it to the local replica and record the outcome of the update
     transition!{
         exec_dispatch_local(node_id: NodeId) {
@@ -848,6 +952,80 @@ UnboundedLog<DT: Dispatch> {
         }
     }
 
+    /// Combiner: abort after reading the local version, there were no operations placed in the log
+    transition!{
+        exec_load_local_version_abort(node_id: NodeId) {
+            remove combiner -= [ node_id => let CombinerState::LoadedLocalVersion { queued_ops, lversion } ];
+
+            require(queued_ops.len() == 0);
+
+            add    combiner += [ node_id => CombinerState::Ready];
+        }
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////////////////
+    // Replica Membership Transitions
+    ////////////////////////////////////////////////////////////////////////////////////////////
+
+    /// Replica: set the state of a replica to the state of the source replica
+    ///
+    /// The source replica must have loaded its local version, this pins its version and state.
+    /// The version of the replica must not go backwards.
+    transition!{
+        replica_clone(node_id: NodeId, src: NodeId) {
+            require(node_id != src);
+
+            have   combiner       >= [ src => let CombinerState::LoadedLocalVersion { queued_ops, lversion } ];
+            have   replicas       >= [ src => let state ];
+
+            remove combiner       -= [ node_id => CombinerState::Ready ];
+            add    combiner       += [ node_id => CombinerState::Ready ];
+
+            remove replicas       -= [ node_id => let _ ];
+            add    replicas       += [ node_id => state ];
+
+            remove local_versions -= [ node_id => let old_version ];
+            require(old_version <= lversion);
+            add    local_versions += [ node_id => lversion ];
+        }
+    }
+
+
+    /// Replica: record the state of an idle replica together with its local version
+    ///
+    /// The combiner of the replica must be ready, then its state is the one at its local version.
+    transition!{
+        replica_snapshot(node_id: NodeId) {
+            have   combiner       >= [ node_id => CombinerState::Ready ];
+            have   replicas       >= [ node_id => let state ];
+            have   local_versions >= [ node_id => let version ];
+
+            add    snapshots      (union) += [ version => state ] by {
+                assert(pre.replicas.contains_key(node_id));
+                assert(pre.current_local_version(node_id) == version);
+            };
+        }
+    }
+
+    /// Replica: set the state of a replica to a snapshot
+    ///
+    /// The version of the replica must not go backwards.
+    transition!{
+        replica_restore(node_id: NodeId, version: LogIdx) {
+            have   snapshots      >= [ version => let state ];
+
+            remove combiner       -= [ node_id => CombinerState::Ready ];
+            add    combiner       += [ node_id => CombinerState::Ready ];
+
+            remove replicas       -= [ node_id => let _ ];
+            add    replicas       += [ node_id => state ];
+
+            remove local_versions -= [ node_id => let old_version ];
+            require(old_version <= version);
+            add    local_versions += [ node_id => version ];
+        }
+    }
+
 
     ////////////////////////////////////////////////////////////////////////////////////////////
     // Inductiveness Proofs
@@ -855,7 +1033,7 @@ UnboundedLog<DT: Dispatch> {
 
 
     #[inductive(initialize)]
-    fn initialize_inductive(post: Self, number_of_nodes: nat) {
+    fn initialize_inductive(post: Self, number_of_nodes: nat, init_state: DT::View) {
 
         // XXX: is it really that hard to show finetness of map domain?
         let max_dom = (post.num_replicas - 1) as nat;
@@ -948,6 +1126,9 @@ UnboundedLog<DT: Dispatch> {
         }
     }
 
+    #[inductive(version_upper_bound_witness)]
+    fn version_upper_bound_witness_inductive(pre: Self, post: Self) { }
+
     #[inductive(update_done)]
     fn update_done_inductive(pre: Self, post: Self, rid: ReqId) {
         assert forall |node_id| #[trigger] post.combiner.contains_key(node_id) implies post.wf_combiner_for_node_id(node_id) by {
@@ -1043,6 +1224,10 @@ UnboundedLog<DT: Dispatch> {
                     LogRangeNoNodeId_append_other(pre.log, post.log,
                         post.local_versions[node_id1], pre.tail, node_id1, LogEntry{ op, node_id });
                 }
+                CombinerState::Helped{helper} => {
+                    LogRangeNoNodeId_append_other(pre.log, post.log,
+                        post.local_versions[node_id1], pre.tail, node_id1, LogEntry{ op, node_id });
+                }
                 CombinerState::Placed{queued_ops} => {
                     LogRangeMatchesQueue_append_other_augment(queued_ops, pre.log, post.log,
                         0, post.local_versions[node_id1], pre.tail, node_id1, pre.local_updates, post.local_updates, rid, LogEntry{ op, node_id });
@@ -1068,9 +1253,9 @@ UnboundedLog<DT: Dispatch> {
         assert (forall |nid| (#[trigger] pre.replicas.contains_key(nid)) ==> pre.local_versions.contains_key(nid));
 
         assert forall |nid| (#[trigger] post.replicas.contains_key(nid)) implies
-            post.replicas[nid] == compute_nrstate_at_version(post.log, post.current_local_version(nid)) by
+            post.replicas[nid] == compute_nrstate_at_version(post.init_state, post.log, post.current_local_version(nid)) by
         {
-            compute_nrstate_at_version_preserves(pre.log, post.log, post.current_local_version(nid));
+            compute_nrstate_at_version_preserves(pre.init_state, pre.log, post.log, post.current_local_version(nid));
         }
 
         assert forall |rid| (#[trigger] post.local_updates.contains_key(rid))
@@ -1078,10 +1263,10 @@ UnboundedLog<DT: Dispatch> {
         {
             match post.local_updates[rid] {
                 UpdateState::Applied { ret, idx } => {
-                    compute_nrstate_at_version_preserves(pre.log, post.log, idx);
+                    compute_nrstate_at_version_preserves(pre.init_state, pre.log, post.log, idx);
                 },
                 UpdateState::Done { ret, idx } => {
-                    compute_nrstate_at_version_preserves(pre.log, post.log, idx);
+                    compute_nrstate_at_version_preserves(pre.init_state, pre.log, post.log, idx);
                 },
                 _ => {},
             }
@@ -1094,15 +1279,39 @@ UnboundedLog<DT: Dispatch> {
                 ReadonlyState::Done { ret, version_upper_bound, op, node_id: node_id0 } => {
                     let ver = choose |ver| (#[trigger] rangeincl(version_upper_bound, ver, pre.version_upper_bound)
                         && ver <= pre.current_local_version(node_id0)
-                        && ret == DT::dispatch_spec(compute_nrstate_at_version(pre.log, ver), op));
-                    compute_nrstate_at_version_preserves(pre.log, post.log, ver);
+                        && ret == DT::dispatch_spec(compute_nrstate_at_version(pre.init_state, pre.log, ver), op));
+                    compute_nrstate_at_version_preserves(pre.init_state, pre.log, post.log, ver);
                 },
                 _ => {},
             }
         }
+
+        assert forall |version| (#[trigger] post.snapshots.contains_key(version)) implies
+            post.snapshots[version] == compute_nrstate_at_version(post.init_state, post.log, version) by
+        {
+            compute_nrstate_at_version_preserves(pre.init_state, pre.log, post.log, version);
+        }
     }
 
 
+    #[inductive(exec_help_start)]
+    fn exec_help_start_inductive(pre: Self, post: Self, node_id: NodeId, helper: NodeId) {
+        assert(post.wf_combiner_for_node_id(node_id));
+    }
+
+    #[inductive(exec_help_load_local_version)]
+    fn exec_help_load_local_version_inductive(pre: Self, post: Self, node_id: NodeId) {
+        concat_LogRangeNoNodeId_LogRangeMatchesQueue(
+            Seq::empty(), post.log, 0,
+            pre.local_versions[node_id],
+            pre.tail,
+            post.tail,
+            node_id,
+            post.local_updates);
+
+        assert(post.wf_combiner_for_node_id(node_id));
+    }
+
     #[inductive(exec_load_local_version)]
     fn exec_load_local_version_inductive(pre: Self, post: Self, node_id: NodeId) { }
 
@@ -1126,6 +1335,8 @@ UnboundedLog<DT: Dispatch> {
             match pre.combiner[node_id0] {
             CombinerState::Ready => {
             }
+            CombinerState::Helped{helper} => {
+            }
             CombinerState::Placed{queued_ops} => {
                 LogRangeMatchesQueue_update_change_2(
                 queued_ops, post.log, 0, post.local_versions[node_id0], post.tail, node_id0, pre.local_updates, post.local_updates);
@@ -1157,7 +1368,7 @@ UnboundedLog<DT: Dispatch> {
                 ReadonlyState::Done { ret, version_upper_bound, op, node_id: node_id0 } => {
                     let ver = choose |ver| (#[trigger] rangeincl(version_upper_bound, ver, pre.version_upper_bound)
                         && ver <= pre.current_local_version(node_id0)
-                        && ret == DT::dispatch_spec(compute_nrstate_at_version(post.log, ver), op));
+                        && ret == DT::dispatch_spec(compute_nrstate_at_version(post.init_state, post.log, ver), op));
                     assert(rangeincl(version_upper_bound, ver, post.version_upper_bound));
                 },
                 _ => {}
@@ -1171,6 +1382,65 @@ UnboundedLog<DT: Dispatch> {
     #[inductive(exec_finish_no_change)]
     fn exec_finish_no_change_inductive(pre: Self, post: Self, node_id: NodeId) { }
 
+    #[inductive(exec_load_local_version_abort)]
+    fn exec_load_local_version_abort_inductive(pre: Self, post: Self, node_id: NodeId) {
+        let lversion = pre.combiner[node_id].get_LoadedLocalVersion_lversion();
+        LogRangeMatchesQueue_empty_no_node_id(
+            pre.combiner[node_id].get_LoadedLocalVersion_queued_ops(),
+            post.log, lversion, post.tail, node_id, post.local_updates);
+        assert(post.wf_combiner_for_node_id(node_id));
+    }
+
+    #[inductive(replica_clone)]
+    fn replica_clone_inductive(pre: Self, post: Self, node_id: NodeId, src: NodeId) {
+        let lversion = pre.combiner[src].get_LoadedLocalVersion_lversion();
+        assert(pre.current_local_version(src) == lversion);
+        assert(post.current_local_version(node_id) == lversion);
+        LogRangeNoNodeId_suffix(post.log, pre.local_versions[node_id], lversion, post.tail, node_id);
+        assert(post.wf_combiner_for_node_id(node_id));
+
+        assert forall |nid| (#[trigger] post.combiner.contains_key(nid)) implies
+            post.wf_combiner_for_node_id(nid) by
+        {
+            if nid != node_id {
+                assert(pre.wf_combiner_for_node_id(nid));
+            }
+        }
+        assert forall |rid| (#[trigger] post.local_reads.contains_key(rid)) implies
+            post.wf_readstate(post.local_reads[rid]) by
+        {
+            assert(pre.wf_readstate(pre.local_reads[rid]));
+        }
+    }
+
+    #[inductive(replica_snapshot)]
+    fn replica_snapshot_inductive(pre: Self, post: Self, node_id: NodeId) {
+        let version = pre.local_versions[node_id];
+        assert(pre.replicas.contains_key(node_id));
+        assert(pre.current_local_version(node_id) == version);
+    }
+
+    #[inductive(replica_restore)]
+    fn replica_restore_inductive(pre: Self, post: Self, node_id: NodeId, version: LogIdx) {
+        assert(pre.snapshots.contains_key(version));
+        assert(post.current_local_version(node_id) == version);
+        LogRangeNoNodeId_suffix(post.log, pre.local_versions[node_id], version, post.tail, node_id);
+        assert(post.wf_combiner_for_node_id(node_id));
+
+        assert forall |nid| (#[trigger] post.combiner.contains_key(nid)) implies
+            post.wf_combiner_for_node_id(nid) by
+        {
+            if nid != node_id {
+                assert(pre.wf_combiner_for_node_id(nid));
+            }
+        }
+        assert forall |rid| (#[trigger] post.local_reads.contains_key(rid)) implies
+            post.wf_readstate(post.local_reads[rid]) by
+        {
+            assert(pre.wf_readstate(pre.local_reads[rid]));
+        }
+    }
+
     ////////////////////////////////////////////////////////////////////////////////////////////////
     // Helper Functions
     ////////////////////////////////////////////////////////////////////////////////////////////////
@@ -1181,6 +1451,7 @@ UnboundedLog<DT: Dispatch> {
     {
         match self.combiner[node_id] {
             CombinerState::Ready                              => self.local_versions[node_id],
+            CombinerState::Helped{ .. }                       => self.local_versions[node_id],
             CombinerState::Placed{ .. }                       => self.local_versions[node_id],
             CombinerState::LoadedLocalVersion{ lversion, .. } => lversion,
             CombinerState::Loop { lversion, .. }              => lversion,
@@ -1932,6 +2203,55 @@ proof fn LogRangeMatchesQueue_append_other_augment<DT: Dispatch>(
     }
 }
 
+/// a suffix of a range without entries of the node has no entries of the node either
+proof fn LogRangeNoNodeId_suffix<DT: Dispatch>(
+    log: Map<LogIdx, LogEntry<DT>>,
+    a: LogIdx,
+    b: LogIdx,
+    c: LogIdx,
+    node_id: NodeId,
+)
+    requires
+        a <= b <= c,
+        LogRangeNoNodeId(log, a, c, node_id),
+    ensures
+        LogRangeNoNodeId(log, b, c, node_id),
+    decreases b - a,
+{
+    if a != b {
+        LogRangeNoNodeId_suffix(log, a + 1, b, c, node_id);
+    }
+}
+
+/// if the queue is empty, then the range has no entries of the node
+proof fn LogRangeMatchesQueue_empty_no_node_id<DT: Dispatch>(
+    queue: Seq<ReqId>,
+    log: Map<LogIdx, LogEntry<DT>>,
+    logIndexLower: LogIdx,
+    logIndexUpper: LogIdx,
+    node_id: NodeId,
+    updates: Map<ReqId, UpdateState<DT>>,
+)
+    requires
+        queue.len() == 0,
+        logIndexLower <= logIndexUpper,
+        LogRangeMatchesQueue(queue, log, 0, logIndexLower, logIndexUpper, node_id, updates),
+    ensures
+        LogRangeNoNodeId(log, logIndexLower, logIndexUpper, node_id),
+    decreases logIndexUpper - logIndexLower,
+{
+    if logIndexLower < logIndexUpper {
+        LogRangeMatchesQueue_empty_no_node_id(
+            queue,
+            log,
+            logIndexLower + 1,
+            logIndexUpper,
+            node_id,
+            updates,
+        );
+    }
+}
+
 proof fn LogRangeNoNodeId_append_other<DT: Dispatch>(
     log: Map<nat, LogEntry<DT>>,
     new_log: Map<nat, LogEntry<DT>>,
@@ -2050,9 +2370,10 @@ proof fn concat_LogRangeNoNodeId_LogRangeMatchesQueue<DT: Dispatch>(
 
 /// constructs the state of the data structure at a specific version given the log
 ///
-/// This function recursively applies the update operations to the initial state of the
+/// This function recursively applies the update operations to the given initial state of the
 /// data structure and returns the state of the data structure at the given version.
 pub open spec fn compute_nrstate_at_version<DT: Dispatch>(
+    init_state: DT::View,
     log: Map<LogIdx, LogEntry<DT>>,
     version: LogIdx,
 ) -> DT::View
@@ -2061,14 +2382,15 @@ pub open spec fn compute_nrstate_at_version<DT: Dispatch>(
     decreases version,
 {
     if version == 0 {
-        DT::init_spec()
+        init_state
     } else {
         let ver = (version - 1) as nat;
-        DT::dispatch_mut_spec(compute_nrstate_at_version(log, ver), log[ver].op).0
+        DT::dispatch_mut_spec(compute_nrstate_at_version(init_state, log, ver), log[ver].op).0
     }
 }
 
 pub proof fn compute_nrstate_at_version_preserves<DT: Dispatch>(
+    init_state: DT::View,
     a: Map<LogIdx, LogEntry<DT>>,
     b: Map<LogIdx, LogEntry<DT>>,
     version: LogIdx,
@@ -2077,13 +2399,55 @@ pub proof fn compute_nrstate_at_version_preserves<DT: Dispatch>(
         forall|i| 0 <= i < version ==> a.contains_key(i),
         forall|i| 0 <= i < version ==> a[i] == b[i],
     ensures
-        compute_nrstate_at_version(a, version) == compute_nrstate_at_version(b, version),
+        compute_nrstate_at_version(init_state, a, version) == compute_nrstate_at_version(
+            init_state,
+            b,
+            version,
+        ),
     decreases version,
 {
     if version > 0 {
-        compute_nrstate_at_version_preserves(a, b, (version - 1) as nat);
+        compute_nrstate_at_version_preserves(init_state, a, b, (version - 1) as nat);
     }
 }
 
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Batches of Updates
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// the i-th ticket is a ticket for the i-th operation of the batch
+pub open spec fn is_update_batch_tickets<DT: Dispatch>(
+    tickets: Map<nat, UnboundedLog::local_updates<DT>>,
+    ops: Seq<DT::WriteOperation>,
+    log: UnboundedLog::Instance<DT>,
+) -> bool {
+    forall|i: nat|
+        i < ops.len() ==> {
+            &&& #[trigger] tickets.contains_key(i)
+            &&& is_update_ticket(tickets[i], ops[i as int], log)
+        }
+}
+
+/// the i-th stub is the stub for the i-th ticket of the batch, and the updates are done at
+/// consecutive indices of the log in the order of the batch.
+///
+/// The indices are the positions of the updates in the log of the SimpleLog, see
+/// `batch_updates_consecutive_refines`.
+pub open spec fn is_update_batch_stubs<DT: Dispatch>(
+    stubs: Map<nat, UnboundedLog::local_updates<DT>>,
+    tickets: Map<nat, UnboundedLog::local_updates<DT>>,
+    results: Seq<DT::Response>,
+    log: UnboundedLog::Instance<DT>,
+) -> bool {
+    &&& forall|i: nat|
+        i < results.len() ==> {
+            &&& #[trigger] stubs.contains_key(i)
+            &&& is_update_stub(stubs[i], tickets[i]@.key, results[i as int], log)
+        }
+    &&& forall|i: nat|
+        i < results.len() ==> #[trigger] stubs[i]@.value.get_Done_idx()
+            == stubs[0]@.value.get_Done_idx() + i
+}
+
 } // verus!
 // end verus!
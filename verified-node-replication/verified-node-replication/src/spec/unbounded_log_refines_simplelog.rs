@@ -134,15 +134,15 @@ impl<DT: Dispatch> crate::UnboundedLogRefinesSimpleLog<DT> for RefinementProof<D
                 assert(exists|version: nat| #[trigger]
                     rangeincl(version_upper_bound, version, pre.version_upper_bound)
                         && version <= pre.current_local_version(node_id)
-                        && result_match(pre.log, response, version, op));
+                        && result_match(pre.init_state, pre.log, response, version, op));
                 let version: nat = choose|version: nat|
                     {
                         version_upper_bound <= version <= pre.version_upper_bound
                             && version <= pre.current_local_version(node_id)
-                            && #[trigger] result_match(pre.log, response, version, op)
+                            && #[trigger] result_match(pre.init_state, pre.log, response, version, op)
                     };
                 assert(response == DT::dispatch_spec(interp(pre).nrstate_at_version(version), op)) by {
-                    state_at_version_refines(interp(pre).log, pre.log, pre.tail, version);
+                    state_at_version_refines(pre.init_state, interp(pre).log, pre.log, pre.tail, version);
                 }
                 assert(interp(post).update_resps =~= interp(pre).update_resps);
                 assert(interp(post).update_reqs =~= interp(pre).update_reqs);
@@ -166,7 +166,7 @@ impl<DT: Dispatch> crate::UnboundedLogRefinesSimpleLog<DT> for RefinementProof<D
                     interp(pre).nrstate_at_version(version),
                     interp(pre).log[version as int],
                 ).1) by {
-                    state_at_version_refines(interp(pre).log, pre.log, pre.tail, version);
+                    state_at_version_refines(pre.init_state, interp(pre).log, pre.log, pre.tail, version);
                 }
                 SimpleLog::show::update_finish(interp(pre), interp(post), aop, rid, response);
             },
@@ -243,6 +243,7 @@ spec fn interp_update_resps<DT: Dispatch>(local_updates: Map<nat, UpdateState<DT
 
 spec fn interp<DT: Dispatch>(s: UnboundedLog::State<DT>) -> SimpleLog::State<DT> {
     SimpleLog::State {
+        init_state: s.init_state,
         num_replicas: s.num_replicas,
         replica_versions:
             Map::new(|node_id: NodeId| node_id < s.num_replicas,
@@ -274,13 +275,13 @@ proof fn refinement_init<DT: Dispatch>(post: UnboundedLog::State<DT>)
         SimpleLog::State::init(interp(post)),
 {
     case_on_init!{ post, UnboundedLog::<DT> => {
-        initialize(number_of_nodes) => {
+        initialize(number_of_nodes, init_state) => {
             assert_maps_equal!(interp(post).replica_versions, Map::new(|n: NodeId| n < number_of_nodes, |n| 0));
             assert_maps_equal!(interp(post).readonly_reqs, Map::empty());
             assert_maps_equal!(interp(post).update_reqs, Map::empty());
             assert_maps_equal!(interp(post).update_resps, Map::empty());
             assert_seqs_equal!(interp(post).log, Seq::empty());
-            SimpleLog::show::initialize(interp(post), post.num_replicas);
+            SimpleLog::show::initialize(interp(post), post.num_replicas, post.init_state);
         }
     }}
 }
@@ -339,7 +340,7 @@ proof fn refinement_next<DT: Dispatch>(pre: UnboundedLog::State<DT>, post: Unbou
         readonly_view_oob(replica, res) => {
             assert(pre.replicas.contains_key(replica));
             lemma_interp_log_len(pre.log, pre.tail);
-            state_at_version_refines(interp(pre).log, pre.log, pre.tail, interp(pre).replica_versions[replica]);
+            state_at_version_refines(pre.init_state, interp(pre).log, pre.log, pre.tail, interp(pre).replica_versions[replica]);
             SimpleLog::show::readonly_view_oob(interp(pre), interp(post), aop, replica, res);
         }
 
@@ -373,11 +374,11 @@ proof fn refinement_next<DT: Dispatch>(pre: UnboundedLog::State<DT>, post: Unbou
             // assert(exists |version : nat | version_upper_bound <= version <= pre.version_upper_bound
             // ==> VersionInLog(pre.log, version) && result_match(s.log, output, version,  s.localReads[rid].op)) by
 
-            assert(exists |version: nat| #[trigger]rangeincl(version_upper_bound, version, pre.version_upper_bound) && result_match(pre.log, ret, version, op)) ;
+            assert(exists |version: nat| #[trigger]rangeincl(version_upper_bound, version, pre.version_upper_bound) && result_match(pre.init_state, pre.log, ret, version, op)) ;
 
             let version : nat = choose |version| {
                 version_upper_bound <= version <= pre.version_upper_bound
-                && #[trigger] result_match(pre.log, ret, version, op)
+                && #[trigger] result_match(pre.init_state, pre.log, ret, version, op)
             };
 
             assert(version_in_log(pre.log, version));
@@ -387,7 +388,7 @@ proof fn refinement_next<DT: Dispatch>(pre: UnboundedLog::State<DT>, post: Unbou
             assert(interp(pre).readonly_reqs.index(rid).get_Req_version() <= version <= interp(pre).log.len());
 
             assert(ret == DT::dispatch_spec(interp(pre).nrstate_at_version(version), op)) by {
-                state_at_version_refines(interp(pre).log, pre.log, pre.tail, version);
+                state_at_version_refines(pre.init_state, interp(pre).log, pre.log, pre.tail, version);
             }
 
             assert_maps_equal!(interp(pre).update_resps, interp(post).update_resps);
@@ -446,6 +447,16 @@ proof fn refinement_next<DT: Dispatch>(pre: UnboundedLog::State<DT>, post: Unbou
             SimpleLog::show::no_op(interp(pre), interp(post), aop);
         }
 
+        exec_help_start(node_id, helper) => {
+            assert(interp(pre).replica_versions =~= interp(post).replica_versions);
+            SimpleLog::show::no_op(interp(pre), interp(post), aop);
+        }
+
+        exec_help_load_local_version(node_id) => {
+            assert(interp(pre).replica_versions =~= interp(post).replica_versions);
+            SimpleLog::show::no_op(interp(pre), interp(post), aop);
+        }
+
         exec_load_local_version(node_id) => {
             assert(interp(pre).replica_versions =~= interp(post).replica_versions);
             SimpleLog::show::no_op(interp(pre), interp(post), aop);
@@ -495,6 +506,30 @@ proof fn refinement_next<DT: Dispatch>(pre: UnboundedLog::State<DT>, post: Unbou
             assert(interp(pre).replica_versions =~= interp(post).replica_versions);
             SimpleLog::show::no_op(interp(pre), interp(post), aop);
         }
+
+        version_upper_bound_witness() => {
+            assert(interp(pre).replica_versions =~= interp(post).replica_versions);
+            SimpleLog::show::no_op(interp(pre), interp(post), aop);
+        }
+
+        replica_snapshot(node_id) => {
+            assert(interp(pre).replica_versions =~= interp(post).replica_versions);
+            SimpleLog::show::no_op(interp(pre), interp(post), aop);
+        }
+
+        replica_restore(node_id, version) => {
+            assert_maps_equal!(interp(pre).update_reqs, interp(post).update_reqs);
+            assert_maps_equal!(interp(pre).update_resps, interp(post).update_resps);
+            if pre.local_versions[node_id] == version {
+                assert(interp(pre).replica_versions =~= interp(post).replica_versions);
+                SimpleLog::show::no_op(interp(pre), interp(post), aop);
+            } else {
+                lemma_interp_log_len(pre.log, pre.tail);
+                assert(interp(post).replica_versions =~=
+                    interp(pre).replica_versions.insert(node_id, version));
+                SimpleLog::show::update_replica_update(interp(pre), interp(post), aop, node_id, version);
+            }
+        }
       }
     }
 }
@@ -521,6 +556,7 @@ pub open spec fn version_in_log<DT: Dispatch>(
 }
 
 pub open spec fn result_match<DT: Dispatch>(
+    init_state: DT::View,
     log: Map<LogIdx, LogEntry<DT>>,
     output: DT::Response,
     version: LogIdx,
@@ -529,7 +565,42 @@ pub open spec fn result_match<DT: Dispatch>(
     recommends
         version_in_log(log, version),
 {
-    output == DT::dispatch_spec(i_nrstate_at_version(log, version), op)
+    output == DT::dispatch_spec(i_nrstate_at_version(init_state, log, version), op)
+}
+
+/// The updates of a batch that are done at consecutive indices of the UnboundedLog hold
+/// consecutive positions of the SimpleLog log, below its version. Thus, no other update is
+/// linearized in between the updates of the batch.
+pub proof fn batch_updates_consecutive_refines<DT: Dispatch>(
+    s: UnboundedLog::State<DT>,
+    rids: Seq<ReqId>,
+)
+    requires
+        s.invariant(),
+        rids.len() > 0,
+        forall|i|
+            0 <= i < rids.len() ==> #[trigger] s.local_updates.contains_key(rids[i])
+                && s.local_updates[rids[i]].is_Done(),
+        forall|i|
+            0 <= i < rids.len() ==> #[trigger] s.local_updates[rids[i]].get_Done_idx()
+                == s.local_updates[rids[0]].get_Done_idx() + i,
+    ensures
+        forall|i|
+            0 <= i < rids.len() ==> {
+                &&& #[trigger] interp(s).update_resps.contains_key(rids[i])
+                &&& interp(s).update_resps[rids[i]].0 == interp(s).update_resps[rids[0]].0 + i
+                &&& interp(s).update_resps[rids[i]].0 < interp(s).version
+            },
+{
+    assert(s.local_updates.contains_key(rids[0]));
+    assert forall|i| 0 <= i < rids.len() implies {
+        &&& #[trigger] interp(s).update_resps.contains_key(rids[i])
+        &&& interp(s).update_resps[rids[i]].0 == interp(s).update_resps[rids[0]].0 + i
+        &&& interp(s).update_resps[rids[i]].0 < interp(s).version
+    } by {
+        assert(s.local_updates.contains_key(rids[i]));
+        assert(s.inv_local_updates_wf(s.local_updates[rids[i]]));
+    }
 }
 
 proof fn lemma_interp_log_len<DT: Dispatch>(log: Map<LogIdx, LogEntry<DT>>, tail: LogIdx)
@@ -547,6 +618,7 @@ proof fn lemma_interp_log_len<DT: Dispatch>(log: Map<LogIdx, LogEntry<DT>>, tail
 }
 
 proof fn state_at_version_refines<DT: Dispatch>(
+    init_state: DT::View,
     s_log: Seq<DT::WriteOperation>,
     i_log: Map<LogIdx, LogEntry<DT>>,
     gtail: nat,
@@ -558,11 +630,15 @@ proof fn state_at_version_refines<DT: Dispatch>(
         idx <= gtail,
         s_log == interp_log(gtail, i_log),
     ensures
-        s_nrstate_at_version::<DT>(s_log, idx) == i_nrstate_at_version::<DT>(i_log, idx),
+        s_nrstate_at_version::<DT>(init_state, s_log, idx) == i_nrstate_at_version::<DT>(
+            init_state,
+            i_log,
+            idx,
+        ),
     decreases idx,
 {
     if idx > 0 {
-        state_at_version_refines(s_log, i_log, gtail, (idx - 1) as nat);
+        state_at_version_refines(init_state, s_log, i_log, gtail, (idx - 1) as nat);
     }
 }
 
@@ -0,0 +1,151 @@
+// Retiring Replicas with Verified NR
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// trustedness: ignore this file
+
+// the verus dependencies
+use builtin::Tracked;
+
+// the traits and types we need from the verified-node-replicaton crate
+use verified_node_replication::{AffinityFn, Dispatch, NodeReplicated, NodeReplicatedT, ThreadToken};
+
+/// the number of replicas we want to create
+const NUM_REPLICAS: usize = 3;
+
+/// number of update operations executed before retiring the replica
+const NUM_UPDATES: u64 = 100;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Data Structure Definition with the Operations
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// represents a update operation on the counter
+#[derive(Clone, Copy)]
+pub enum UpdateOp {
+    /// increment the counter
+    Inc,
+}
+
+/// represents a read-only operation on the counter
+pub enum ReadonlyOp {
+    /// get the current counter value
+    Get,
+}
+
+/// represents the result of the operation request
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum OpResult {
+    Value(u64),
+    Ok,
+}
+
+/// a simple counter data structure to be wrapped with node-replication
+pub struct Counter {
+    pub val: u64,
+}
+
+/// implementation of Disatch for the counter
+impl Dispatch for Counter {
+    type ReadOperation = ReadonlyOp;
+
+    type WriteOperation = UpdateOp;
+
+    type Response = OpResult;
+
+    type View = Counter;
+
+    fn init() -> Self {
+        Counter { val: 0 }
+    }
+
+    fn clone_write_op(op: &Self::WriteOperation) -> Self::WriteOperation {
+        op.clone()
+    }
+
+    fn clone_response(op: &Self::Response) -> Self::Response {
+        op.clone()
+    }
+
+    fn dispatch(&self, op: Self::ReadOperation) -> Self::Response {
+        match op {
+            ReadonlyOp::Get => OpResult::Value(self.val),
+        }
+    }
+
+    fn dispatch_mut(&mut self, op: Self::WriteOperation) -> Self::Response {
+        match op {
+            UpdateOp::Inc => self.val = self.val.wrapping_add(1),
+        }
+        OpResult::Ok
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Helpers
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+fn inc(nr: &NodeReplicated<Counter>, tkn: ThreadToken<Counter>) -> ThreadToken<Counter> {
+    match nr.execute_mut(UpdateOp::Inc, tkn, Tracked::assume_new()) {
+        Result::Ok((ret, tkn, _)) => {
+            assert!(ret == OpResult::Ok);
+            tkn
+        }
+        Result::Err(_) => panic!("update operation failed"),
+    }
+}
+
+fn get(nr: &NodeReplicated<Counter>, tkn: ThreadToken<Counter>) -> (u64, ThreadToken<Counter>) {
+    match nr.execute(ReadonlyOp::Get, tkn, Tracked::assume_new()) {
+        Result::Ok((OpResult::Value(v), tkn, _)) => (v, tkn),
+        Result::Ok(_) => panic!("unexpected response"),
+        Result::Err(_) => panic!("read-only operation failed"),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// the updates went through the replica being retired, it is ahead of the first replica
+#[test]
+fn retire_replica_after_own_updates() {
+    let mut nr = NodeReplicated::<Counter>::new(NUM_REPLICAS, AffinityFn::new(|_| {}));
+    let tkn0 = nr.register(0).expect("could not register with replica 0");
+    let mut tkn1 = nr.register(1).expect("could not register with replica 1");
+
+    for _ in 0..NUM_UPDATES {
+        tkn1 = inc(&nr, tkn1);
+    }
+    drop(tkn1);
+
+    assert!(nr.retire_replica(1));
+    assert!(nr.is_retired(1));
+
+    let (val, _) = get(&nr, tkn0);
+    assert_eq!(val, NUM_UPDATES);
+}
+
+/// the updates went through another replica than the first and the one being retired
+#[test]
+fn retire_replica_after_updates_of_other_replica() {
+    let mut nr = NodeReplicated::<Counter>::new(NUM_REPLICAS, AffinityFn::new(|_| {}));
+    let tkn0 = nr.register(0).expect("could not register with replica 0");
+    let tkn1 = nr.register(1).expect("could not register with replica 1");
+    let mut tkn2 = nr.register(2).expect("could not register with replica 2");
+
+    for _ in 0..NUM_UPDATES {
+        tkn2 = inc(&nr, tkn2);
+    }
+    // replica 1 catches up with the updates of replica 2, it is ahead of the first replica then
+    let (val, tkn1) = get(&nr, tkn1);
+    assert_eq!(val, NUM_UPDATES);
+    drop(tkn1);
+
+    assert!(nr.retire_replica(1));
+    assert!(nr.is_retired(1));
+
+    let (val, _) = get(&nr, tkn0);
+    assert_eq!(val, NUM_UPDATES);
+    let (val, _) = get(&nr, tkn2);
+    assert_eq!(val, NUM_UPDATES);
+}